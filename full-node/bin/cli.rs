@@ -108,6 +108,10 @@ pub struct CliOptionsRun {
     /// chain is not a parachain.
     #[arg(long, default_value = "256M", value_parser = parse_max_bytes)]
     pub relay_chain_database_cache_size: MaxBytes,
+    /// Only author blocks when requested through the `engine_createBlock` JSON-RPC function, and
+    /// allow finalizing blocks through `engine_finalizeBlock`. For testing purposes only.
+    #[arg(long)]
+    pub unsafe_manual_seal: bool,
 }
 
 #[derive(Debug, clap::Parser)]
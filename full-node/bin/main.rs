@@ -262,6 +262,7 @@ async fn run(cli_options: cli::CliOptionsRun) {
                     .as_ref()
                     .map(|path| path.join(parsed_relay_spec.id()).join("keys")),
                 json_rpc_listen: None,
+                manual_seal: false,
             };
 
             (Some(cfg), Some(relay_chain_name.to_owned()))
@@ -379,6 +380,7 @@ async fn run(cli_options: cli::CliOptionsRun) {
             } else {
                 None
             },
+            manual_seal: cli_options.unsafe_manual_seal,
         },
         relay_chain,
         libp2p_key,
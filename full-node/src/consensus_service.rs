@@ -40,7 +40,7 @@ use smoldot::{
     identity::keystore,
     informant::HashDisplay,
     libp2p,
-    network::{self, protocol::BlockData},
+    network::{self, codec::BlockData},
     sync::all,
     trie,
     verify::body_only::{self, StorageChanges, TrieEntryVersion},
@@ -48,8 +48,10 @@ use smoldot::{
 use std::{
     array,
     borrow::Cow,
+    collections::{BTreeMap, VecDeque},
     iter, mem,
     num::{NonZeroU64, NonZeroUsize},
+    ops,
     sync::Arc,
     time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
@@ -74,6 +76,14 @@ pub struct Config {
     /// >           to compare against a known genesis hash and print a warning.
     pub genesis_block_hash: [u8; 32],
 
+    /// List of hashes of blocks that must always be considered as invalid, as found in the
+    /// chain specification.
+    pub bad_blocks: Vec<[u8; 32]>,
+
+    /// List of block numbers and hashes of blocks that are known to be part of the canonical
+    /// chain, as found in the chain specification.
+    pub fork_blocks: Vec<(u64, [u8; 32])>,
+
     /// Stores of key to use for all block-production-related purposes.
     pub keystore: Arc<keystore::Keystore>,
 
@@ -108,6 +118,22 @@ pub struct Config {
     /// Note that this value doesn't determine the moment when creating the block has ended, but
     /// the moment when creating the block should start its final phase.
     pub slot_duration_author_ratio: u16,
+
+    /// If `true`, the changes to the storage performed by each non-finalized block are kept in
+    /// memory until the block is finalized or pruned, and are reported to new subscribers
+    /// through [`SubscribeAll::non_finalized_blocks_storage_changes`].
+    ///
+    /// The storage changes of blocks imported after a subscription has started are always
+    /// reported through [`Notification::Block`], no matter the value of this field.
+    pub keep_non_finalized_storage_changes: bool,
+
+    /// If `true`, blocks are never authored automatically. Instead, a block is authored only
+    /// when [`ConsensusService::create_block`] is called, and blocks can be finalized through
+    /// [`ConsensusService::finalize_block`].
+    ///
+    /// This is meant to be used when testing a chain where the local node is the only block
+    /// author, similar to the "manual seal" mode of Substrate.
+    pub manual_seal: bool,
 }
 
 /// Identifier for a blocks request to be performed.
@@ -131,6 +157,9 @@ pub struct ConsensusService {
 
     /// See [`Config::block_number_bytes`].
     block_number_bytes: usize,
+
+    /// See [`Config::manual_seal`].
+    manual_seal: bool,
 }
 
 enum ToBackground {
@@ -155,6 +184,19 @@ enum ToBackground {
     IsMajorSyncingHint {
         result_tx: oneshot::Sender<bool>,
     },
+    SubmitTransaction {
+        transaction: Vec<u8>,
+        result_tx: oneshot::Sender<Result<(), SubmitTransactionError>>,
+    },
+    CreateBlock {
+        create_empty: bool,
+        finalize: bool,
+        result_tx: oneshot::Sender<Result<[u8; 32], CreateBlockError>>,
+    },
+    FinalizeBlock {
+        block_hash: [u8; 32],
+        result_tx: oneshot::Sender<Result<(), FinalizeBlockError>>,
+    },
 }
 
 /// Potential error when calling [`ConsensusService::new`].
@@ -172,6 +214,41 @@ pub enum InitError {
     FinalizedRuntimeInit(executor::host::NewErr),
 }
 
+/// Error potentially returned by [`ConsensusService::submit_transaction`].
+#[derive(Debug, Clone, derive_more::Display)]
+pub enum SubmitTransactionError {
+    /// The queue of transactions waiting to be included in a block is full.
+    QueueFull,
+}
+
+/// Error potentially returned by [`ConsensusService::create_block`].
+#[derive(Debug, Clone, derive_more::Display)]
+pub enum CreateBlockError {
+    /// [`Config::manual_seal`] is `false`.
+    ManualSealDisabled,
+    /// No transaction is waiting to be included in a block, and creating an empty block wasn't
+    /// allowed.
+    NoTransactions,
+    /// The local node isn't capable of authoring a block, either because the consensus engine of
+    /// the chain isn't supported or because none of the keys of the keystore is allowed to
+    /// author blocks.
+    CannotAuthor,
+    /// Error while building the block. More information can be found in the logs.
+    AuthoringFailed,
+    /// The block has been built but has failed to be imported. More information can be found in
+    /// the logs.
+    ImportFailed,
+}
+
+/// Error potentially returned by [`ConsensusService::finalize_block`].
+#[derive(Debug, Clone, derive_more::Display)]
+pub enum FinalizeBlockError {
+    /// [`Config::manual_seal`] is `false`.
+    ManualSealDisabled,
+    /// The block isn't in the list of non-finalized blocks.
+    UnknownBlock,
+}
+
 impl ConsensusService {
     /// Initializes the [`ConsensusService`] with the given configuration.
     pub async fn new(config: Config) -> Result<Arc<Self>, InitError> {
@@ -295,6 +372,8 @@ impl ConsensusService {
             },
             max_disjoint_headers: 1024,
             max_requests_per_block: NonZeroU32::new(3).unwrap(),
+            // Matches the maximum number of blocks requested at once when starting requests.
+            max_ancestry_search_depth: NonZeroU64::new(64).unwrap(),
             download_ahead_blocks: {
                 // Assuming a verification speed of 1k blocks/sec and a 99th download time
                 // percentile of two second, the number of blocks to download ahead of time
@@ -305,6 +384,8 @@ impl ConsensusService {
             },
             full_mode: true,
             code_trie_node_hint: None,
+            bad_blocks: config.bad_blocks,
+            fork_blocks: config.fork_blocks,
         });
 
         let finalized_runtime = {
@@ -332,7 +413,11 @@ impl ConsensusService {
             block_author_sync_source,
             block_authoring: None,
             authored_block: None,
+            manual_seal: config.manual_seal,
+            manual_seal_requests: VecDeque::new(),
+            manual_seal_importing: None,
             slot_duration_author_ratio: config.slot_duration_author_ratio,
+            keep_non_finalized_storage_changes: config.keep_non_finalized_storage_changes,
             keystore: config.keystore,
             finalized_runtime: Arc::new(Mutex::new(Some(finalized_runtime))),
             network_service: config.network_service.0,
@@ -347,12 +432,16 @@ impl ConsensusService {
             block_requests_finished_tx,
             block_requests_finished_rx,
             jaeger_service: config.jaeger_service,
+            transactions_queue: Default::default(),
+            transactions_queue_order: BTreeMap::new(),
+            next_transaction_queue_index: 0,
         };
 
         background_sync.start();
 
         Ok(Arc::new(ConsensusService {
             block_number_bytes: config.block_number_bytes,
+            manual_seal: config.manual_seal,
             to_background_tx: Mutex::new(to_background_tx),
         }))
     }
@@ -362,6 +451,11 @@ impl ConsensusService {
         self.block_number_bytes
     }
 
+    /// Returns the value that was provided through [`Config::manual_seal`].
+    pub fn is_manual_seal_enabled(&self) -> bool {
+        self.manual_seal
+    }
+
     /// Returns a summary of the state of the service.
     ///
     /// > **Important**: This doesn't represent the content of the database.
@@ -454,8 +548,93 @@ impl ConsensusService {
             .await;
         result_rx.await.unwrap()
     }
+
+    /// Adds a transaction to the queue of transactions to include in the blocks authored locally.
+    ///
+    /// The transaction isn't validated. Invalid transactions are discarded when the runtime
+    /// refuses to include them in a block. Submitting a transaction that is already in the queue
+    /// has no effect and succeeds.
+    ///
+    /// Returns an error if the queue is full.
+    pub async fn submit_transaction(
+        &self,
+        transaction: Vec<u8>,
+    ) -> Result<(), SubmitTransactionError> {
+        let (result_tx, result_rx) = oneshot::channel();
+        let _ = self
+            .to_background_tx
+            .lock()
+            .await
+            .send(ToBackground::SubmitTransaction {
+                transaction,
+                result_tx,
+            })
+            .await;
+        result_rx.await.unwrap()
+    }
+
+    /// Authors a new block on top of the current best block, then imports it. Returns the hash
+    /// of the new block once it has been imported.
+    ///
+    /// If `create_empty` is `false` and no transaction is waiting to be included, no block is
+    /// created. If `finalize` is `true`, the new block is immediately finalized after having
+    /// been imported.
+    ///
+    /// Contrary to Substrate's manual seal, it isn't possible to choose the parent of the new
+    /// block. In order to build a block on top of a block that isn't the best block, this block
+    /// must first become the best block, for example by building more blocks on top of it.
+    ///
+    /// Only available if [`Config::manual_seal`] is `true`.
+    ///
+    /// > **Note**: The block must claim a slot according to the consensus engine of the chain.
+    /// >           Since a block can't be built in the same slot as its parent, calling this
+    /// >           function multiple times in a row might wait until the next slot.
+    pub async fn create_block(
+        &self,
+        create_empty: bool,
+        finalize: bool,
+    ) -> Result<[u8; 32], CreateBlockError> {
+        let (result_tx, result_rx) = oneshot::channel();
+        let _ = self
+            .to_background_tx
+            .lock()
+            .await
+            .send(ToBackground::CreateBlock {
+                create_empty,
+                finalize,
+                result_tx,
+            })
+            .await;
+        result_rx.await.unwrap()
+    }
+
+    /// Marks the given non-finalized block and all its ancestors as finalized, without any
+    /// finality proof.
+    ///
+    /// Only available if [`Config::manual_seal`] is `true`.
+    pub async fn finalize_block(&self, block_hash: [u8; 32]) -> Result<(), FinalizeBlockError> {
+        let (result_tx, result_rx) = oneshot::channel();
+        let _ = self
+            .to_background_tx
+            .lock()
+            .await
+            .send(ToBackground::FinalizeBlock {
+                block_hash,
+                result_tx,
+            })
+            .await;
+        result_rx.await.unwrap()
+    }
 }
 
+/// Maximum number of transactions in [`SyncBackground::transactions_queue`].
+// TODO: arbitrary constant
+const MAX_QUEUED_TRANSACTIONS: usize = 4096;
+
+/// Maximum number of transactions in a row that don't fit in a block being authored before the
+/// block is considered full. See [`BlockTransactionsSelection`].
+const MAX_SKIPPED_TRANSACTIONS: usize = 8;
+
 /// Return value of [`ConsensusService::subscribe_all`].
 pub struct SubscribeAll {
     /// Identifier of this subscription.
@@ -478,6 +657,12 @@ pub struct SubscribeAll {
     /// children.
     pub non_finalized_blocks_ancestry_order: Vec<BlockNotification>,
 
+    /// Changes to the storage that each block of
+    /// [`SubscribeAll::non_finalized_blocks_ancestry_order`] has performed, in the same order.
+    ///
+    /// `None` if [`Config::keep_non_finalized_storage_changes`] was `false`.
+    pub non_finalized_blocks_storage_changes: Option<Vec<Arc<StorageChanges>>>,
+
     /// Channel onto which new blocks are sent. The channel gets closed if it is full when a new
     /// block needs to be reported.
     pub new_blocks: async_channel::Receiver<Notification>,
@@ -606,11 +791,52 @@ struct SyncBackground {
     /// See [`Config::slot_duration_author_ratio`].
     slot_duration_author_ratio: u16,
 
+    /// See [`Config::keep_non_finalized_storage_changes`].
+    keep_non_finalized_storage_changes: bool,
+
+    /// Transactions submitted through [`ConsensusService::submit_transaction`] or received from
+    /// peers, and that are waiting to be included in a block authored locally. Indexed by the
+    /// hash of the transaction, and contains the index of the transaction within
+    /// [`SyncBackground::transactions_queue_order`].
+    ///
+    /// Transactions are removed from this queue when they are found in the body of a new best
+    /// block or when the runtime refuses to include them. Including a transaction in a
+    /// locally-authored block doesn't remove it, as the block might never be imported.
+    transactions_queue: hashbrown::HashMap<[u8; 32], (u64, Vec<u8>), fnv::FnvBuildHasher>,
+
+    /// Hashes of the transactions of [`SyncBackground::transactions_queue`], in order of
+    /// submission.
+    transactions_queue_order: BTreeMap<u64, [u8; 32]>,
+
+    /// Index to assign to the next transaction inserted in
+    /// [`SyncBackground::transactions_queue_order`].
+    next_transaction_queue_index: u64,
+
     /// After a block has been authored, it is inserted here while waiting for the `sync` to
     /// import it. Contains the block height, the block hash, the SCALE-encoded block header, and
     /// the list of SCALE-encoded extrinsics of the block.
     authored_block: Option<(u64, [u8; 32], Vec<u8>, Vec<Vec<u8>>)>,
 
+    /// See [`Config::manual_seal`].
+    manual_seal: bool,
+
+    /// Requests for a block to be authored, coming from [`ConsensusService::create_block`], in
+    /// order of arrival. Contains whether the block should be finalized after having been
+    /// imported, and the sender to report the outcome to.
+    ///
+    /// Always empty if [`SyncBackground::manual_seal`] is `false`.
+    manual_seal_requests: VecDeque<(bool, oneshot::Sender<Result<[u8; 32], CreateBlockError>>)>,
+
+    /// Block that has been authored in response to an element of
+    /// [`SyncBackground::manual_seal_requests`] and that is waiting to be imported. Contains the
+    /// hash of the block, whether it should be finalized after having been imported, and the
+    /// sender to report the outcome to.
+    manual_seal_importing: Option<(
+        [u8; 32],
+        bool,
+        oneshot::Sender<Result<[u8; 32], CreateBlockError>>,
+    )>,
+
     /// See [`Config::keystore`].
     keystore: Arc<keystore::Keystore>,
 
@@ -683,6 +909,10 @@ enum NonFinalizedBlock {
         ///
         /// The `Arc` is shared with [`SyncBackground::finalized_runtime`].
         runtime: Arc<Mutex<Option<executor::host::HostVmPrototype>>>,
+
+        /// Changes to the storage that the block has performed. `None` if
+        /// [`Config::keep_non_finalized_storage_changes`] is `false`.
+        storage_changes: Option<Arc<StorageChanges>>,
     },
 }
 
@@ -799,6 +1029,14 @@ impl SyncBackground {
                         };
 
                     match &block_authoring {
+                        // In manual seal mode, blocks are only authored on demand, and one at a
+                        // time.
+                        _ if self.manual_seal
+                            && (self.manual_seal_requests.is_empty()
+                                || self.manual_seal_importing.is_some()) =>
+                        {
+                            future::Either::Left(future::Either::Right(future::pending()))
+                        }
                         Some((author::build::Builder::Ready(_), _)) => future::Either::Left(
                             future::Either::Left(future::ready(Instant::now())),
                         ),
@@ -859,6 +1097,7 @@ impl SyncBackground {
                     match self.block_authoring {
                         Some((author::build::Builder::Ready(_), _)) => {
                             self.author_block().await;
+                            self.on_manual_seal_block_authored();
                         }
                         Some((author::build::Builder::WaitSlot(when), local_authorities)) => {
                             self.block_authoring = Some((
@@ -866,9 +1105,16 @@ impl SyncBackground {
                                 local_authorities,
                             ));
                             self.author_block().await;
+                            self.on_manual_seal_block_authored();
                         }
                         Some((author::build::Builder::Idle, _)) => {
                             self.block_authoring = None;
+
+                            // In manual seal mode, the builder can only be idle while a block
+                            // is requested if the local node isn't capable of authoring.
+                            if let Some((_, result_tx)) = self.manual_seal_requests.pop_front() {
+                                let _ = result_tx.send(Err(CreateBlockError::CannotAuthor));
+                            }
                         }
                         None => {
                             unreachable!()
@@ -899,6 +1145,13 @@ impl SyncBackground {
                         &finalized_block_scale_encoded_header,
                     );
 
+                    let mut non_finalized_blocks_storage_changes =
+                        if self.keep_non_finalized_storage_changes {
+                            Some(Vec::new())
+                        } else {
+                            None
+                        };
+
                     let non_finalized_blocks_ancestry_order = {
                         let best_hash = self.sync.best_block_hash();
                         let blocks_in = self
@@ -915,10 +1168,17 @@ impl SyncBackground {
                         let mut blocks_out = Vec::new();
                         for (number, scale_encoding, parent_hash) in blocks_in {
                             let hash = header::hash_from_scale_encoded_header(&scale_encoding);
-                            let runtime = match &self.sync[(number, &hash)] {
-                                NonFinalizedBlock::Verified { runtime } => runtime.clone(),
+                            let (runtime, storage_changes) = match &self.sync[(number, &hash)] {
+                                NonFinalizedBlock::Verified {
+                                    runtime,
+                                    storage_changes,
+                                } => (runtime.clone(), storage_changes.clone()),
                                 _ => unreachable!(),
                             };
+                            if let Some(list) = &mut non_finalized_blocks_storage_changes {
+                                // Always `Some` if `keep_non_finalized_storage_changes` is `true`.
+                                list.push(storage_changes.unwrap());
+                            }
                             let runtime_update = if Arc::ptr_eq(&self.finalized_runtime, &runtime) {
                                 None
                             } else {
@@ -946,6 +1206,7 @@ impl SyncBackground {
                             self.finalized_runtime.lock().await.clone().unwrap(),
                         ),
                         non_finalized_blocks_ancestry_order,
+                        non_finalized_blocks_storage_changes,
                         new_blocks,
                     });
                 }
@@ -974,6 +1235,47 @@ impl SyncBackground {
 
                     let _ = result_tx.send(result);
                 }
+                WhatHappened::FrontendEvent(ToBackground::SubmitTransaction {
+                    transaction,
+                    result_tx,
+                }) => {
+                    let _ = result_tx.send(self.queue_transaction(transaction));
+                }
+                WhatHappened::FrontendEvent(ToBackground::CreateBlock {
+                    create_empty,
+                    finalize,
+                    result_tx,
+                }) => {
+                    if !self.manual_seal {
+                        let _ = result_tx.send(Err(CreateBlockError::ManualSealDisabled));
+                    } else if !create_empty && self.transactions_queue.is_empty() {
+                        let _ = result_tx.send(Err(CreateBlockError::NoTransactions));
+                    } else if !matches!(
+                        self.sync.best_block_consensus(),
+                        chain_information::ChainInformationConsensusRef::Aura { .. }
+                    ) {
+                        // TODO: the block authoring doesn't support Babe at the moment
+                        let _ = result_tx.send(Err(CreateBlockError::CannotAuthor));
+                    } else {
+                        // Reset the block authoring, so that the slot to claim is determined
+                        // based on the current time rather than the time when the builder was
+                        // created.
+                        self.block_authoring = None;
+                        self.manual_seal_requests.push_back((finalize, result_tx));
+                    }
+                }
+                WhatHappened::FrontendEvent(ToBackground::FinalizeBlock {
+                    block_hash,
+                    result_tx,
+                }) => {
+                    let result = if self.manual_seal {
+                        self.force_finalize(&block_hash).await
+                    } else {
+                        Err(FinalizeBlockError::ManualSealDisabled)
+                    };
+                    let _ = result_tx.send(result);
+                    process_sync = true;
+                }
 
                 WhatHappened::NetworkEvent(network_service::Event::Connected {
                     peer_id,
@@ -1044,6 +1346,38 @@ impl SyncBackground {
                         all::BlockAnnounceOutcome::Discarded => {}
                         all::BlockAnnounceOutcome::StoredForLater {} => {}
                         all::BlockAnnounceOutcome::InvalidHeader(_) => unreachable!(),
+                        all::BlockAnnounceOutcome::BadBlock => {
+                            self.log_callback.log(
+                                LogLevel::Warn,
+                                format!(
+                                    "block-announce-bad-block; peer_id={}; is_best={:?}",
+                                    peer_id, is_best
+                                ),
+                            );
+                        }
+                    }
+                }
+                WhatHappened::NetworkEvent(network_service::Event::TransactionsReceived {
+                    chain_id,
+                    peer_id,
+                    transactions,
+                }) if chain_id == self.network_chain_id => {
+                    self.log_callback.log(
+                        LogLevel::Debug,
+                        format!(
+                            "transactions-received; peer_id={}; num_transactions={}",
+                            peer_id,
+                            transactions.len()
+                        ),
+                    );
+
+                    for transaction in transactions {
+                        if self.queue_transaction(transaction).is_err() {
+                            self.log_callback.log(
+                                LogLevel::Debug,
+                                "transaction-discarded; reason=queue-full".to_string(),
+                            );
+                        }
                     }
                 }
                 WhatHappened::NetworkEvent(_) => {
@@ -1078,6 +1412,10 @@ impl SyncBackground {
                         | all::ResponseOutcome::Queued
                         | all::ResponseOutcome::NotFinalizedChain { .. }
                         | all::ResponseOutcome::AllAlreadyInChain { .. } => {}
+                        all::ResponseOutcome::BadBlock => {
+                            self.log_callback
+                                .log(LogLevel::Warn, "blocks-response-bad-block".to_string());
+                        }
                     }
 
                     // If the source was actually disconnected and has no other request in
@@ -1146,7 +1484,6 @@ impl SyncBackground {
         // Most parts of the block authorship can't be accelerated, in particular the
         // initialization and the signing at the end. This end of authoring threshold is only
         // checked when deciding whether to continue including more transactions in the block.
-        // TODO: use this
         // TODO: Substrate nodes increase the time available for authoring if it detects that slots have been skipped, in order to account for the possibility that the initialization of a block or the inclusion of an extrinsic takes too long
         let authoring_end = {
             let start = authoring_start.slot_start_from_unix_epoch();
@@ -1166,6 +1503,7 @@ impl SyncBackground {
                 if self.sync.best_block_number() != self.sync.finalized_block_header().number {
                     let NonFinalizedBlock::Verified {
                         runtime: parent_runtime_arc,
+                        ..
                     } = &self.sync[(self.sync.best_block_number(), &self.sync.best_block_hash())]
                     else {
                         unreachable!()
@@ -1186,12 +1524,18 @@ impl SyncBackground {
                         .duration_since(SystemTime::UNIX_EPOCH)
                         .unwrap(),
                     parent_runtime,
-                    block_body_capacity: 0, // TODO: could be set to the size of the tx pool
+                    block_body_capacity: self.transactions_queue.len(),
                     max_log_level: 0,
                     calculate_trie_changes: true,
                 })
             };
 
+            // Hash of the transaction that has been passed to the runtime and whose inclusion
+            // result is still unknown.
+            let mut pending_transaction = None::<[u8; 32]>;
+            // Transactions are included in order of submission.
+            let mut transactions_selection = BlockTransactionsSelection::new();
+
             // The block authoring process jumps through various states, interrupted when it needs
             // access to the storage of the best block.
             loop {
@@ -1256,23 +1600,72 @@ impl SyncBackground {
                     // Part of the block production consists in adding transactions to the block.
                     // These transactions are extracted from the transactions pool.
                     author::build::BuilderAuthoring::ApplyExtrinsic(apply) => {
-                        // TODO: actually implement including transactions in the blocks
-                        block_authoring = apply.finish();
+                        // Transactions are included in order of submission, until too many
+                        // transactions in a row haven't fit in the block or until the moment
+                        // when the authoring should end.
+                        debug_assert!(pending_transaction.is_none());
+                        let next_transaction = if SystemTime::now() < authoring_end {
+                            transactions_selection.next(&self.transactions_queue_order)
+                        } else {
+                            None
+                        };
+
+                        if let Some(hash) = next_transaction {
+                            pending_transaction = Some(hash);
+                            block_authoring =
+                                apply.add_extrinsic(self.transactions_queue[&hash].1.clone());
+                        } else {
+                            block_authoring = apply.finish();
+                        }
                     }
                     author::build::BuilderAuthoring::ApplyExtrinsicResult { result, resume } => {
-                        if let Err(error) = result {
-                            // TODO: include transaction bytes or something?
-                            self.log_callback.log(
-                                LogLevel::Warn,
-                                format!(
-                                    "block-author-transaction-inclusion-error; error={}",
-                                    error
-                                ),
-                            );
+                        let transaction_hash = pending_transaction.take().unwrap();
+                        match result {
+                            Ok(_) => {
+                                // Note that a transaction whose dispatch has failed is still
+                                // included in the block. The transaction stays in the queue
+                                // until the block is imported as the new best block.
+                                transactions_selection.on_included();
+                                self.log_callback.log(
+                                    LogLevel::Debug,
+                                    format!(
+                                        "block-author-transaction-included; hash={}",
+                                        HashDisplay(&transaction_hash)
+                                    ),
+                                );
+                            }
+                            Err(author::runtime::TransactionValidityError::Invalid(
+                                author::runtime::InvalidTransaction::ExhaustsResources,
+                            )) => {
+                                // The transaction doesn't fit in the weight or size limits of
+                                // the block. It is skipped, and stays in the queue in order to
+                                // be included in a future block, unless it is the first
+                                // transaction of the block, in which case it will never fit.
+                                if transactions_selection.on_exhausts_resources() {
+                                    self.log_callback.log(
+                                        LogLevel::Debug,
+                                        format!(
+                                            "block-author-transaction-too-large; hash={}",
+                                            HashDisplay(&transaction_hash)
+                                        ),
+                                    );
+                                    self.remove_queued_transaction(&transaction_hash);
+                                }
+                            }
+                            Err(error) => {
+                                self.log_callback.log(
+                                    LogLevel::Warn,
+                                    format!(
+                                        "block-author-transaction-inclusion-error; hash={}; error={}",
+                                        HashDisplay(&transaction_hash),
+                                        error
+                                    ),
+                                );
+                                self.remove_queued_transaction(&transaction_hash);
+                            }
                         }
 
-                        // TODO: actually implement including transactions in the blocks
-                        block_authoring = resume.finish();
+                        block_authoring = author::build::BuilderAuthoring::ApplyExtrinsic(resume);
                     }
 
                     // Access to the best block storage.
@@ -1422,7 +1815,8 @@ impl SyncBackground {
             all::BlockAnnounceOutcome::TooOld { .. }
             | all::BlockAnnounceOutcome::AlreadyInChain
             | all::BlockAnnounceOutcome::NotFinalizedChain
-            | all::BlockAnnounceOutcome::InvalidHeader(_) => unreachable!(),
+            | all::BlockAnnounceOutcome::InvalidHeader(_)
+            | all::BlockAnnounceOutcome::BadBlock => unreachable!(),
         }
 
         debug_assert!(self.authored_block.is_none());
@@ -1434,6 +1828,163 @@ impl SyncBackground {
         ));
     }
 
+    /// Pushes a transaction at the back of [`SyncBackground::transactions_queue`], unless it is
+    /// already in the queue.
+    ///
+    /// Returns an error if the queue is full.
+    fn queue_transaction(&mut self, transaction: Vec<u8>) -> Result<(), SubmitTransactionError> {
+        let hash =
+            <[u8; 32]>::try_from(blake2_rfc::blake2b::blake2b(32, &[], &transaction).as_bytes())
+                .unwrap();
+
+        if self.transactions_queue.contains_key(&hash) {
+            return Ok(());
+        }
+
+        if self.transactions_queue.len() >= MAX_QUEUED_TRANSACTIONS {
+            return Err(SubmitTransactionError::QueueFull);
+        }
+
+        let index = self.next_transaction_queue_index;
+        self.next_transaction_queue_index += 1;
+        self.transactions_queue.insert(hash, (index, transaction));
+        self.transactions_queue_order.insert(index, hash);
+        Ok(())
+    }
+
+    /// Removes a transaction from [`SyncBackground::transactions_queue`]. Has no effect if the
+    /// transaction isn't in the queue.
+    fn remove_queued_transaction(&mut self, hash: &[u8; 32]) {
+        if let Some((index, _)) = self.transactions_queue.remove(hash) {
+            self.transactions_queue_order.remove(&index);
+        }
+    }
+
+    /// Must be called after [`SyncBackground::author_block`]. If a block has been requested
+    /// through [`ConsensusService::create_block`], either moves the request to
+    /// [`SyncBackground::manual_seal_importing`] or reports the failure to author.
+    fn on_manual_seal_block_authored(&mut self) {
+        let Some((finalize, result_tx)) = self.manual_seal_requests.pop_front() else {
+            return;
+        };
+
+        match &self.authored_block {
+            Some((_, block_hash, _, _)) => {
+                debug_assert!(self.manual_seal_importing.is_none());
+                self.manual_seal_importing = Some((*block_hash, finalize, result_tx));
+            }
+            None => {
+                let _ = result_tx.send(Err(CreateBlockError::AuthoringFailed));
+            }
+        }
+    }
+
+    /// Must be called when the verification of a block has failed. If this block was authored
+    /// as the result of a call to [`ConsensusService::create_block`], reports the failure.
+    fn on_block_verification_failed(&mut self, block_hash: &[u8; 32]) {
+        if self
+            .manual_seal_importing
+            .as_ref()
+            .map_or(false, |(hash, _, _)| hash == block_hash)
+        {
+            let (_, _, result_tx) = self.manual_seal_importing.take().unwrap();
+            let _ = result_tx.send(Err(CreateBlockError::ImportFailed));
+        }
+    }
+
+    /// Marks the given block and its ancestors as finalized without any finality proof. See
+    /// [`ConsensusService::finalize_block`].
+    async fn force_finalize(&mut self, block_hash: &[u8; 32]) -> Result<(), FinalizeBlockError> {
+        match self.sync.force_finalize(block_hash) {
+            Ok(all::FinalityProofVerifyOutcome::NewFinalized {
+                finalized_blocks_newest_to_oldest,
+                pruned_blocks,
+                updates_best_block,
+            }) => {
+                self.log_callback.log(
+                    LogLevel::Debug,
+                    format!(
+                        "forced-finalization; new-finalized={}",
+                        HashDisplay(block_hash)
+                    ),
+                );
+                self.on_blocks_finalized(
+                    finalized_blocks_newest_to_oldest,
+                    pruned_blocks,
+                    updates_best_block,
+                )
+                .await;
+                Ok(())
+            }
+            Ok(_) => unreachable!(),
+            Err(all::ForceFinalizeError::UnknownBlock) => Err(FinalizeBlockError::UnknownBlock),
+            Err(all::ForceFinalizeError::WarpSyncInProgress) => {
+                // Warp syncing is never used in "full" mode.
+                unreachable!()
+            }
+        }
+    }
+
+    /// Updates the state of the service after blocks have been finalized, and notifies the
+    /// subscribers.
+    async fn on_blocks_finalized(
+        &mut self,
+        finalized_blocks_newest_to_oldest: Vec<all::Block<NonFinalizedBlock>>,
+        pruned_blocks: Vec<[u8; 32]>,
+        updates_best_block: bool,
+    ) {
+        let new_finalized_hash = finalized_blocks_newest_to_oldest
+            .first()
+            .unwrap()
+            .header
+            .hash(self.sync.block_number_bytes());
+
+        if updates_best_block {
+            let fut = self.network_service.set_local_best_block(
+                self.network_chain_id,
+                self.sync.best_block_hash(),
+                self.sync.best_block_number(),
+            );
+            fut.await;
+
+            // Reset the block authoring, in order to potentially build a
+            // block on top of this new best.
+            self.block_authoring = None;
+        }
+
+        self.finalized_runtime = match &finalized_blocks_newest_to_oldest.first().unwrap().user_data
+        {
+            NonFinalizedBlock::Verified { runtime, .. } => runtime.clone(),
+            _ => unreachable!(),
+        };
+        // TODO: what if best block changed?
+        self.database
+            .with_database_detached(move |database| {
+                database.set_finalized(&new_finalized_hash).unwrap();
+            })
+            .await;
+        // Elements in `blocks_notifications` are removed one by one and inserted
+        // back if the channel is still open.
+        for index in (0..self.blocks_notifications.len()).rev() {
+            let subscription = self.blocks_notifications.swap_remove(index);
+            if subscription
+                .try_send(Notification::Finalized {
+                    finalized_blocks_newest_to_oldest: finalized_blocks_newest_to_oldest
+                        .iter()
+                        .map(|b| b.header.hash(self.sync.block_number_bytes()))
+                        .collect::<Vec<_>>(),
+                    pruned_blocks_hashes: pruned_blocks.clone(),
+                    best_block_hash: self.sync.best_block_hash(),
+                })
+                .is_err()
+            {
+                continue;
+            }
+
+            self.blocks_notifications.push(subscription);
+        }
+    }
+
     /// Starts all the new network requests that should be started.
     // TODO: handle obsolete requests
     async fn start_network_requests(&mut self) {
@@ -1538,24 +2089,22 @@ impl SyncBackground {
                     let request = self.network_service.clone().blocks_request(
                         peer_id,
                         self.network_chain_id,
-                        network::protocol::BlocksRequestConfig {
+                        network::codec::BlocksRequestConfig {
                             start: if let Some(first_block_hash) = first_block_hash {
-                                network::protocol::BlocksRequestConfigStart::Hash(first_block_hash)
+                                network::codec::BlocksRequestConfigStart::Hash(first_block_hash)
                             } else {
-                                network::protocol::BlocksRequestConfigStart::Number(
-                                    first_block_height,
-                                )
+                                network::codec::BlocksRequestConfigStart::Number(first_block_height)
                             },
                             desired_count: NonZeroU32::new(
                                 u32::try_from(num_blocks.get()).unwrap_or(u32::max_value()),
                             )
                             .unwrap(),
                             direction: if ascending {
-                                network::protocol::BlocksRequestDirection::Ascending
+                                network::codec::BlocksRequestDirection::Ascending
                             } else {
-                                network::protocol::BlocksRequestDirection::Descending
+                                network::codec::BlocksRequestDirection::Descending
                             },
-                            fields: network::protocol::BlocksRequestFields {
+                            fields: network::codec::BlocksRequestFields {
                                 header: request_headers,
                                 body: request_bodies,
                                 justifications: request_justification,
@@ -1635,13 +2184,14 @@ impl SyncBackground {
                                 ),
                             );
                             self.sync = sync;
+                            self.on_block_verification_failed(&hash_to_verify);
                             return (self, true);
                         }
                     };
 
                 let parent_hash = *header_verification_success.parent_hash();
                 let parent_info = header_verification_success.parent_user_data().map(|b| {
-                    let NonFinalizedBlock::Verified { runtime } = b else {
+                    let NonFinalizedBlock::Verified { runtime, .. } = b else {
                         unreachable!()
                     };
                     runtime.clone()
@@ -1696,6 +2246,7 @@ impl SyncBackground {
                             );
                             *parent_runtime_arc.try_lock().unwrap() = Some(parent_runtime);
                             self.sync = header_verification_success.reject_bad_block();
+                            self.on_block_verification_failed(&hash_to_verify);
                             return (self, true);
                         }
                         body_only::Verify::Finished(Ok(body_only::Success {
@@ -1823,6 +2374,27 @@ impl SyncBackground {
                                 self.blocks_notifications.push(subscription);
                             }
 
+                            // Transactions that are included in the new best block no longer
+                            // need to be included in a locally-authored block.
+                            if is_new_best && !self.transactions_queue.is_empty() {
+                                if let Some(body) =
+                                    header_verification_success.scale_encoded_extrinsics()
+                                {
+                                    for transaction in body {
+                                        let hash = blake2_rfc::blake2b::blake2b(
+                                            32,
+                                            &[],
+                                            transaction.as_ref(),
+                                        );
+                                        if let Some((index, _)) =
+                                            self.transactions_queue.remove(hash.as_bytes())
+                                        {
+                                            self.transactions_queue_order.remove(&index);
+                                        }
+                                    }
+                                }
+                            }
+
                             // Processing has made a step forward.
 
                             *parent_runtime_arc.try_lock().unwrap() = Some(parent_runtime);
@@ -1837,6 +2409,11 @@ impl SyncBackground {
                                 } else {
                                     parent_runtime_arc
                                 },
+                                storage_changes: if self.keep_non_finalized_storage_changes {
+                                    Some(storage_changes.clone())
+                                } else {
+                                    None
+                                },
                             };
 
                             if is_new_best {
@@ -1907,6 +2484,30 @@ impl SyncBackground {
                                 }
                             }
 
+                            // If the block has been authored in response to a call to
+                            // `create_block`, report the success.
+                            if self
+                                .manual_seal_importing
+                                .as_ref()
+                                .map_or(false, |(hash, _, _)| *hash == hash_to_verify)
+                            {
+                                let (_, finalize, result_tx) =
+                                    self.manual_seal_importing.take().unwrap();
+                                if finalize {
+                                    if let Err(error) = self.force_finalize(&hash_to_verify).await {
+                                        self.log_callback.log(
+                                            LogLevel::Warn,
+                                            format!(
+                                                "forced-finalization-failure; hash={}; error={}",
+                                                HashDisplay(&hash_to_verify),
+                                                error
+                                            ),
+                                        );
+                                    }
+                                }
+                                let _ = result_tx.send(Ok(hash_to_verify));
+                            }
+
                             return (self, true);
                         }
 
@@ -2025,112 +2626,230 @@ impl SyncBackground {
                 }
             }
 
-            all::ProcessOne::VerifyFinalityProof(verify) => {
-                match verify.perform(rand::random()) {
-                    (
-                        sync_out,
-                        all::FinalityProofVerifyOutcome::NewFinalized {
-                            finalized_blocks_newest_to_oldest,
-                            pruned_blocks,
-                            updates_best_block,
-                        },
-                    ) => {
-                        self.sync = sync_out;
-
-                        let new_finalized_hash = finalized_blocks_newest_to_oldest
-                            .first()
-                            .unwrap()
-                            .header
-                            .hash(self.sync.block_number_bytes());
-                        self.log_callback.log(
-                            LogLevel::Debug,
-                            format!(
-                                "finality-proof-verification; outcome=success; new-finalized={}",
-                                HashDisplay(&new_finalized_hash)
-                            ),
-                        );
+            all::ProcessOne::VerifyFinalityProof(verify) => match verify.perform(rand::random()) {
+                (
+                    sync_out,
+                    all::FinalityProofVerifyOutcome::NewFinalized {
+                        finalized_blocks_newest_to_oldest,
+                        pruned_blocks,
+                        updates_best_block,
+                    },
+                ) => {
+                    self.sync = sync_out;
+
+                    let new_finalized_hash = finalized_blocks_newest_to_oldest
+                        .first()
+                        .unwrap()
+                        .header
+                        .hash(self.sync.block_number_bytes());
+                    self.log_callback.log(
+                        LogLevel::Debug,
+                        format!(
+                            "finality-proof-verification; outcome=success; new-finalized={}",
+                            HashDisplay(&new_finalized_hash)
+                        ),
+                    );
 
-                        if updates_best_block {
-                            let fut = self.network_service.set_local_best_block(
-                                self.network_chain_id,
-                                self.sync.best_block_hash(),
-                                self.sync.best_block_number(),
-                            );
-                            fut.await;
+                    self.on_blocks_finalized(
+                        finalized_blocks_newest_to_oldest,
+                        pruned_blocks,
+                        updates_best_block,
+                    )
+                    .await;
+                    (self, true)
+                }
+                (sync_out, all::FinalityProofVerifyOutcome::GrandpaCommitPending) => {
+                    self.log_callback.log(
+                        LogLevel::Debug,
+                        "finality-proof-verification; outcome=pending".to_string(),
+                    );
+                    self.sync = sync_out;
+                    (self, true)
+                }
+                (sync_out, all::FinalityProofVerifyOutcome::AlreadyFinalized) => {
+                    self.log_callback.log(
+                        LogLevel::Debug,
+                        "finality-proof-verification; outcome=already-finalized".to_string(),
+                    );
+                    self.sync = sync_out;
+                    (self, true)
+                }
+                (sync_out, all::FinalityProofVerifyOutcome::GrandpaCommitError(error)) => {
+                    self.log_callback.log(
+                        LogLevel::Warn,
+                        format!("finality-proof-verification-failure; error={}", error),
+                    );
+                    self.sync = sync_out;
+                    (self, true)
+                }
+                (sync_out, all::FinalityProofVerifyOutcome::JustificationError(error)) => {
+                    self.log_callback.log(
+                        LogLevel::Warn,
+                        format!("finality-proof-verification-failure; error={}", error),
+                    );
+                    self.sync = sync_out;
+                    (self, true)
+                }
+                (sync_out, all::FinalityProofVerifyOutcome::FinalityEquivocation(equivocation)) => {
+                    self.log_callback.log(
+                        LogLevel::Error,
+                        format!(
+                            "finality-equivocation; block-number={}; finalized={}; conflicting={}",
+                            equivocation.block_number,
+                            HashDisplay(&equivocation.finalized_block_hash),
+                            HashDisplay(&equivocation.conflicting_block_hash)
+                        ),
+                    );
+                    self.sync = sync_out;
+                    (self, true)
+                }
+                (sync_out, all::FinalityProofVerifyOutcome::FinalityFrozen) => {
+                    self.log_callback.log(
+                        LogLevel::Debug,
+                        "finality-proof-verification; outcome=finality-frozen".to_string(),
+                    );
+                    self.sync = sync_out;
+                    (self, true)
+                }
+            },
+        }
+    }
+}
 
-                            // Reset the block authoring, in order to potentially build a
-                            // block on top of this new best.
-                            self.block_authoring = None;
-                        }
+/// Selection of the transactions of [`SyncBackground::transactions_queue`] to include in a block
+/// being authored.
+///
+/// Transactions are proposed in order of submission. A transaction that doesn't fit in the
+/// block is skipped, and the block is considered full once [`MAX_SKIPPED_TRANSACTIONS`]
+/// transactions in a row have been skipped.
+struct BlockTransactionsSelection {
+    /// Index within [`SyncBackground::transactions_queue_order`] of the last transaction
+    /// returned by [`BlockTransactionsSelection::next`].
+    last_index: Option<u64>,
+
+    /// Number of transactions that have been included in the block.
+    num_included: usize,
+
+    /// Number of transactions in a row that haven't fit in the block.
+    num_consecutive_skipped: usize,
+}
 
-                        self.finalized_runtime =
-                            match &finalized_blocks_newest_to_oldest.first().unwrap().user_data {
-                                NonFinalizedBlock::Verified { runtime } => runtime.clone(),
-                                _ => unreachable!(),
-                            };
-                        // TODO: what if best block changed?
-                        self.database
-                            .with_database_detached(move |database| {
-                                database.set_finalized(&new_finalized_hash).unwrap();
-                            })
-                            .await;
-                        // Elements in `blocks_notifications` are removed one by one and inserted
-                        // back if the channel is still open.
-                        for index in (0..self.blocks_notifications.len()).rev() {
-                            let subscription = self.blocks_notifications.swap_remove(index);
-                            if subscription
-                                .try_send(Notification::Finalized {
-                                    finalized_blocks_newest_to_oldest:
-                                        finalized_blocks_newest_to_oldest
-                                            .iter()
-                                            .map(|b| b.header.hash(self.sync.block_number_bytes()))
-                                            .collect::<Vec<_>>(),
-                                    pruned_blocks_hashes: pruned_blocks.clone(),
-                                    best_block_hash: self.sync.best_block_hash(),
-                                })
-                                .is_err()
-                            {
-                                continue;
-                            }
+impl BlockTransactionsSelection {
+    /// Starts the selection for a new block.
+    fn new() -> Self {
+        BlockTransactionsSelection {
+            last_index: None,
+            num_included: 0,
+            num_consecutive_skipped: 0,
+        }
+    }
 
-                            self.blocks_notifications.push(subscription);
-                        }
-                        (self, true)
-                    }
-                    (sync_out, all::FinalityProofVerifyOutcome::GrandpaCommitPending) => {
-                        self.log_callback.log(
-                            LogLevel::Debug,
-                            "finality-proof-verification; outcome=pending".to_string(),
-                        );
-                        self.sync = sync_out;
-                        (self, true)
-                    }
-                    (sync_out, all::FinalityProofVerifyOutcome::AlreadyFinalized) => {
-                        self.log_callback.log(
-                            LogLevel::Debug,
-                            "finality-proof-verification; outcome=already-finalized".to_string(),
-                        );
-                        self.sync = sync_out;
-                        (self, true)
-                    }
-                    (sync_out, all::FinalityProofVerifyOutcome::GrandpaCommitError(error)) => {
-                        self.log_callback.log(
-                            LogLevel::Warn,
-                            format!("finality-proof-verification-failure; error={}", error),
-                        );
-                        self.sync = sync_out;
-                        (self, true)
-                    }
-                    (sync_out, all::FinalityProofVerifyOutcome::JustificationError(error)) => {
-                        self.log_callback.log(
-                            LogLevel::Warn,
-                            format!("finality-proof-verification-failure; error={}", error),
-                        );
-                        self.sync = sync_out;
-                        (self, true)
-                    }
-                }
-            }
+    /// Returns the hash of the next transaction to try to include in the block, or `None` if
+    /// the block is full or if all the transactions have been tried.
+    ///
+    /// The outcome must be reported with [`BlockTransactionsSelection::on_included`] or
+    /// [`BlockTransactionsSelection::on_exhausts_resources`]. Transactions that are refused for
+    /// another reason don't need to be reported.
+    fn next(&mut self, transactions_queue_order: &BTreeMap<u64, [u8; 32]>) -> Option<[u8; 32]> {
+        if self.num_consecutive_skipped >= MAX_SKIPPED_TRANSACTIONS {
+            return None;
+        }
+
+        let range_start = match self.last_index {
+            Some(index) => ops::Bound::Excluded(index),
+            None => ops::Bound::Unbounded,
+        };
+        let (index, hash) = transactions_queue_order
+            .range((range_start, ops::Bound::Unbounded))
+            .next()?;
+        self.last_index = Some(*index);
+        Some(*hash)
+    }
+
+    /// Reports that the last transaction returned by [`BlockTransactionsSelection::next`] has
+    /// been included in the block.
+    fn on_included(&mut self) {
+        self.num_included += 1;
+        self.num_consecutive_skipped = 0;
+    }
+
+    /// Reports that the last transaction returned by [`BlockTransactionsSelection::next`]
+    /// exceeds the weight or size limits that remain in the block.
+    ///
+    /// Returns `true` if no transaction has been included in the block yet, in which case the
+    /// transaction doesn't fit in any block and should be removed from the queue.
+    fn on_exhausts_resources(&mut self) -> bool {
+        if self.num_included == 0 {
+            return true;
+        }
+
+        self.num_consecutive_skipped += 1;
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BlockTransactionsSelection, MAX_SKIPPED_TRANSACTIONS};
+    use std::collections::BTreeMap;
+
+    fn queue(num: u8) -> BTreeMap<u64, [u8; 32]> {
+        (0..num).map(|n| (u64::from(n) * 2, [n; 32])).collect()
+    }
+
+    #[test]
+    fn transactions_proposed_in_order() {
+        let queue = queue(3);
+        let mut selection = BlockTransactionsSelection::new();
+        for n in 0..3 {
+            assert_eq!(selection.next(&queue), Some([n; 32]));
+            selection.on_included();
         }
+        assert_eq!(selection.next(&queue), None);
+    }
+
+    #[test]
+    fn too_large_first_transaction_removed_and_skipped() {
+        let queue = queue(3);
+        let mut selection = BlockTransactionsSelection::new();
+
+        // The first transaction doesn't fit in an otherwise empty block, and thus never will.
+        assert_eq!(selection.next(&queue), Some([0; 32]));
+        assert!(selection.on_exhausts_resources());
+
+        // The following transactions are still proposed.
+        assert_eq!(selection.next(&queue), Some([1; 32]));
+        selection.on_included();
+
+        // A transaction that doesn't fit after another one has been included is kept.
+        assert_eq!(selection.next(&queue), Some([2; 32]));
+        assert!(!selection.on_exhausts_resources());
+        assert_eq!(selection.next(&queue), None);
+    }
+
+    #[test]
+    fn block_full_after_max_skipped() {
+        let num_transactions = u8::try_from(MAX_SKIPPED_TRANSACTIONS).unwrap() + 3;
+        let queue = queue(num_transactions);
+        let mut selection = BlockTransactionsSelection::new();
+
+        assert_eq!(selection.next(&queue), Some([0; 32]));
+        selection.on_included();
+
+        // Skipped transactions don't prevent the following ones from being proposed, as long as
+        // one of them fits before the limit is reached.
+        assert_eq!(selection.next(&queue), Some([1; 32]));
+        assert!(!selection.on_exhausts_resources());
+        assert_eq!(selection.next(&queue), Some([2; 32]));
+        selection.on_included();
+
+        for n in 0..MAX_SKIPPED_TRANSACTIONS {
+            let n = u8::try_from(n).unwrap() + 3;
+            assert_eq!(selection.next(&queue), Some([n; 32]));
+            assert!(!selection.on_exhausts_resources());
+        }
+
+        // The block is considered full, even though transactions remain.
+        assert_eq!(selection.next(&queue), None);
     }
 }
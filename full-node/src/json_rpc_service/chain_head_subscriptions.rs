@@ -22,11 +22,12 @@ use futures_lite::FutureExt as _;
 use smol::stream::StreamExt as _;
 use smoldot::{
     executor,
+    informant::HashDisplay,
     json_rpc::{methods, service},
 };
 use std::{future::Future, num::NonZeroUsize, pin::Pin, sync::Arc};
 
-use crate::{consensus_service, database_thread, LogCallback};
+use crate::{consensus_service, database_thread, LogCallback, LogLevel};
 
 pub struct Config {
     /// Function that can be used to spawn background tasks.
@@ -189,11 +190,24 @@ pub async fn spawn_chain_head_subscription_task(mut config: Config) -> String {
                         }
                         Ok(None) => {
                             // Should never happen given that blocks are pinned.
-                            // TODO: log the problem
+                            config.log_callback.log(
+                                LogLevel::Warn,
+                                format!(
+                                    "json-rpc; request=chainHead_unstable_header; block={}; error=pinned-block-missing",
+                                    HashDisplay(&hash.0)
+                                ),
+                            );
                             request.fail(service::ErrorResponse::InternalError);
                         }
-                        Err(_) => {
-                            // TODO: log the problem
+                        Err(error) => {
+                            config.log_callback.log(
+                                LogLevel::Warn,
+                                format!(
+                                    "json-rpc; request=chainHead_unstable_header; block={}; database_error={}",
+                                    HashDisplay(&hash.0),
+                                    error
+                                ),
+                            );
                             request.fail(service::ErrorResponse::InternalError);
                         }
                     }
@@ -21,6 +21,7 @@ use smoldot::{
     chain::fork_tree,
     executor::{host::HostVmPrototype, CoreVersion},
     trie,
+    verify::body_only::StorageChanges,
 };
 use std::{collections::BTreeSet, iter, mem, num::NonZeroUsize, ops, sync::Arc};
 
@@ -629,10 +630,11 @@ struct SubscribeStorageSubscription {
     /// (or all keys if subscribing to all keys) made in the pinned blocks found
     /// in [`SubscribeStorageSubscription::pinned_blocks`].
     ///
-    /// Because the storage changes of blocks that were already present at the time when the
-    /// subscription starts are unknown, they are also not in this list. This leads to corner
-    /// cases where some changes aren't provided, but we don't really care
-    /// as `state_subscribeStorage` is not properly defined anyway.
+    /// The storage changes of blocks that were already present at the time when the
+    /// subscription starts are only known if the consensus service keeps them (see
+    /// [`consensus_service::Config::keep_non_finalized_storage_changes`]). If it doesn't, they
+    /// are also not in this list. This leads to corner cases where some changes aren't provided,
+    /// but we don't really care as `state_subscribeStorage` is not properly defined anyway.
     pinned_blocks_storage_changes: BTreeSet<(fork_tree::NodeIndex, Vec<u8>)>,
     /// Hash of the current finalized block. Not found
     /// in [`SubscribeStorageSubscription::pinned_blocks`].
@@ -684,8 +686,13 @@ impl SubscribeStorage {
                         fork_tree::ForkTree::with_capacity(pinned_blocks_by_hash.capacity());
 
                     let mut current_best_block_index = None;
+                    let mut pinned_blocks_storage_changes = BTreeSet::new();
 
-                    for block in subscribe_all.non_finalized_blocks_ancestry_order {
+                    for (block_index, block) in subscribe_all
+                        .non_finalized_blocks_ancestry_order
+                        .into_iter()
+                        .enumerate()
+                    {
                         let node_index = pinned_blocks.insert(
                             if block.parent_hash != subscribe_all.finalized_block_hash {
                                 Some(*pinned_blocks_by_hash.get(&block.parent_hash).unwrap())
@@ -698,6 +705,16 @@ impl SubscribeStorage {
                         if block.is_new_best {
                             current_best_block_index = Some(node_index);
                         }
+                        if let Some(storage_changes) =
+                            &subscribe_all.non_finalized_blocks_storage_changes
+                        {
+                            insert_storage_changes(
+                                &mut pinned_blocks_storage_changes,
+                                &self.keys,
+                                node_index,
+                                &storage_changes[block_index],
+                            );
+                        }
                     }
 
                     subscription.insert(SubscribeStorageSubscription {
@@ -717,7 +734,7 @@ impl SubscribeStorage {
                         new_blocks: subscribe_all.new_blocks,
                         pinned_blocks,
                         pinned_blocks_by_hash,
-                        pinned_blocks_storage_changes: BTreeSet::new(),
+                        pinned_blocks_storage_changes,
                         blocks_to_unpin: Vec::with_capacity(8),
                         current_finalized_block_hash: subscribe_all.finalized_block_hash,
                         current_best_block_index,
@@ -823,23 +840,12 @@ impl SubscribeStorage {
                     .pinned_blocks_by_hash
                     .insert(block.block_hash, node_index);
 
-                if !self.keys.is_empty() {
-                    for key in &self.keys {
-                        if storage_changes.main_trie_diff_get(&key).is_some() {
-                            subscription
-                                .pinned_blocks_storage_changes
-                                .insert((node_index, key.clone()));
-                        }
-                    }
-                } else {
-                    for (changed_key, _) in
-                        storage_changes.main_trie_storage_changes_iter_unordered()
-                    {
-                        subscription
-                            .pinned_blocks_storage_changes
-                            .insert((node_index, changed_key.to_owned()));
-                    }
-                }
+                insert_storage_changes(
+                    &mut subscription.pinned_blocks_storage_changes,
+                    &self.keys,
+                    node_index,
+                    storage_changes,
+                );
             }
 
             // If the notification changes the best block, find the keys that have changed and
@@ -931,3 +937,26 @@ impl SubscribeStorage {
         }
     }
 }
+
+/// Inserts in `pinned_blocks_storage_changes` the keys of `keys` (or all keys if `keys` is empty)
+/// that have been modified by the block at `node_index`.
+///
+/// See [`SubscribeStorageSubscription::pinned_blocks_storage_changes`].
+fn insert_storage_changes(
+    pinned_blocks_storage_changes: &mut BTreeSet<(fork_tree::NodeIndex, Vec<u8>)>,
+    keys: &[Vec<u8>],
+    node_index: fork_tree::NodeIndex,
+    storage_changes: &StorageChanges,
+) {
+    if !keys.is_empty() {
+        for key in keys {
+            if storage_changes.main_trie_diff_get(key).is_some() {
+                pinned_blocks_storage_changes.insert((node_index, key.clone()));
+            }
+        }
+    } else {
+        for (changed_key, _) in storage_changes.main_trie_storage_changes_iter_unordered() {
+            pinned_blocks_storage_changes.insert((node_index, changed_key.to_owned()));
+        }
+    }
+}
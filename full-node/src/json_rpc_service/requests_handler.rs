@@ -18,6 +18,7 @@
 use futures_lite::future;
 use smol::stream::StreamExt as _;
 use smoldot::{
+    database::full_sqlite,
     executor,
     json_rpc::{methods, parse, service},
     trie,
@@ -74,6 +75,54 @@ pub struct Config {
     pub runtime_caches_service: Arc<runtime_caches_service::RuntimeCachesService>,
 }
 
+/// List of JSON-RPC methods that are implemented by the full node, either in this module or
+/// elsewhere in the JSON-RPC service. Reported by `rpc_methods`.
+///
+/// Must be kept in sync with the requests that are actually handled.
+const SUPPORTED_METHODS: &[&str] = &[
+    "archive_unstable_body",
+    "archive_unstable_finalizedHeight",
+    "archive_unstable_genesisHash",
+    "archive_unstable_hashByHeight",
+    "archive_unstable_header",
+    "archive_unstable_storage",
+    "author_submitExtrinsic",
+    "chainHead_unstable_follow",
+    "chainHead_unstable_header",
+    "chainHead_unstable_unfollow",
+    "chainHead_unstable_unpin",
+    "chainSpec_v1_chainName",
+    "chainSpec_v1_genesisHash",
+    "chainSpec_v1_properties",
+    "chain_getBlockHash",
+    "chain_getHeader",
+    "chain_subscribeAllHeads",
+    "chain_subscribeFinalizedHeads",
+    "chain_subscribeNewHeads",
+    "chain_unsubscribeAllHeads",
+    "chain_unsubscribeFinalizedHeads",
+    "chain_unsubscribeNewHeads",
+    "engine_createBlock",
+    "engine_finalizeBlock",
+    "rpc_methods",
+    "smoldot_unstable_checkRuntimeUpgrade",
+    "state_getKeysPaged",
+    "state_getMetadata",
+    "state_getRuntimeVersion",
+    "state_queryStorageAt",
+    "state_subscribeRuntimeVersion",
+    "state_subscribeStorage",
+    "state_unsubscribeRuntimeVersion",
+    "state_unsubscribeStorage",
+    "system_chain",
+    "system_chainType",
+    "system_health",
+    "system_localPeerId",
+    "system_name",
+    "system_properties",
+    "system_version",
+];
+
 pub enum Message {
     Request(service::RequestProcess),
     SubscriptionStart(service::SubscriptionStartProcess),
@@ -86,9 +135,14 @@ pub fn spawn_requests_handler(mut config: Config) {
             match config.receiver.next().await {
                 Some(Message::Request(request)) => match request.request() {
                     methods::MethodCall::rpc_methods {} => {
+                        let manual_seal = config.consensus_service.is_manual_seal_enabled();
                         request.respond(methods::Response::rpc_methods(methods::RpcMethods {
-                            methods: methods::MethodCall::method_names()
-                                .map(|n| n.into())
+                            methods: SUPPORTED_METHODS
+                                .iter()
+                                // The `engine` functions are treated as if they didn't exist if
+                                // manual seal is disabled.
+                                .filter(|n| manual_seal || !n.starts_with("engine_"))
+                                .map(|n| (*n).to_owned())
                                 .collect(),
                         }));
                     }
@@ -109,6 +163,138 @@ pub fn spawn_requests_handler(mut config: Config) {
                         ));
                     }
 
+                    methods::MethodCall::archive_unstable_body { hash } => {
+                        let hash = hash.0;
+                        let result = config
+                            .database
+                            .with_database(
+                                move |db| -> Result<_, database_thread::CorruptedError> {
+                                    if db.block_scale_encoded_header(&hash)?.is_none() {
+                                        return Ok(None);
+                                    }
+                                    Ok(db.block_extrinsics(&hash)?.map(|l| l.collect::<Vec<_>>()))
+                                },
+                            )
+                            .await;
+
+                        match result {
+                            Ok(Some(body)) => {
+                                request.respond(methods::Response::archive_unstable_body(Some(
+                                    body.into_iter().map(methods::HexString).collect(),
+                                )));
+                            }
+                            Ok(None) => {
+                                request.respond(methods::Response::archive_unstable_body(None));
+                            }
+                            Err(_) => {
+                                request.fail(service::ErrorResponse::InternalError);
+                            }
+                        }
+                    }
+                    methods::MethodCall::archive_unstable_finalizedHeight {} => {
+                        match config
+                            .database
+                            .with_database(|db| db.finalized_block_number())
+                            .await
+                        {
+                            Ok(number) => request.respond(
+                                methods::Response::archive_unstable_finalizedHeight(number),
+                            ),
+                            Err(_) => request.fail(service::ErrorResponse::InternalError),
+                        }
+                    }
+                    methods::MethodCall::archive_unstable_genesisHash {} => {
+                        request.respond(methods::Response::archive_unstable_genesisHash(
+                            methods::HashHexString(config.genesis_block_hash),
+                        ));
+                    }
+                    methods::MethodCall::archive_unstable_hashByHeight { height: 0 } => {
+                        // Similar to `chain_getBlockHash`, block 0 might be missing from the
+                        // database if it was populated through a warp sync.
+                        request.respond(methods::Response::archive_unstable_hashByHeight(vec![
+                            methods::HashHexString(config.genesis_block_hash),
+                        ]));
+                    }
+                    methods::MethodCall::archive_unstable_hashByHeight { height } => {
+                        let result = config
+                            .database
+                            .with_database(
+                                move |db| -> Result<_, database_thread::CorruptedError> {
+                                    // Blocks that are finalized or an ancestor of the finalized block
+                                    // are reported only if they are part of the canonical chain.
+                                    // Non-finalized blocks are all reported.
+                                    if height <= db.finalized_block_number()? {
+                                        Ok(db
+                                            .best_block_hash_by_number(height)?
+                                            .into_iter()
+                                            .collect::<Vec<_>>())
+                                    } else {
+                                        Ok(db.block_hash_by_number(height)?.collect::<Vec<_>>())
+                                    }
+                                },
+                            )
+                            .await;
+
+                        match result {
+                            Ok(hashes) => {
+                                request.respond(methods::Response::archive_unstable_hashByHeight(
+                                    hashes.into_iter().map(methods::HashHexString).collect(),
+                                ));
+                            }
+                            Err(_) => {
+                                request.fail(service::ErrorResponse::InternalError);
+                            }
+                        }
+                    }
+                    methods::MethodCall::archive_unstable_header { hash } => {
+                        let hash = hash.0;
+                        match config
+                            .database
+                            .with_database(move |db| db.block_scale_encoded_header(&hash))
+                            .await
+                        {
+                            Ok(header) => {
+                                request.respond(methods::Response::archive_unstable_header(
+                                    header.map(methods::HexString),
+                                ));
+                            }
+                            Err(_) => request.fail(service::ErrorResponse::InternalError),
+                        }
+                    }
+                    methods::MethodCall::archive_unstable_storage {
+                        hash,
+                        items,
+                        child_trie,
+                    } => {
+                        let hash = hash.0;
+                        let parent_paths = child_trie.map(|child_trie| {
+                            trie::bytes_to_nibbles(b":child_storage:default:".iter().copied())
+                                .chain(trie::bytes_to_nibbles(child_trie.0.iter().copied()))
+                                .map(u8::from)
+                                .collect::<Vec<_>>()
+                        });
+
+                        let result = config
+                            .database
+                            .with_database(move |db| {
+                                archive_storage(db, &hash, parent_paths, items)
+                            })
+                            .await;
+
+                        match result {
+                            Ok(result) => {
+                                request.respond(methods::Response::archive_unstable_storage(result))
+                            }
+                            Err(database_thread::StorageAccessError::UnknownBlock)
+                            | Err(database_thread::StorageAccessError::StoragePruned) => {
+                                request.fail(service::ErrorResponse::InvalidParams);
+                            }
+                            Err(database_thread::StorageAccessError::Corrupted(_)) => {
+                                request.fail(service::ErrorResponse::InternalError);
+                            }
+                        }
+                    }
+
                     methods::MethodCall::chain_getBlockHash { height: Some(0) } => {
                         // In the case where the database was populated through a warp sync, it
                         // might not store block 0 in it. However, the hash of block 0 is
@@ -507,6 +693,63 @@ pub fn spawn_requests_handler(mut config: Config) {
                             }
                         }
                     }
+                    methods::MethodCall::smoldot_unstable_checkRuntimeUpgrade { code, at } => {
+                        let at = match at {
+                            Some(h) => h.0,
+                            None => match config
+                                .database
+                                .with_database(|db| db.best_block_hash())
+                                .await
+                            {
+                                Ok(b) => b,
+                                Err(_) => {
+                                    request.fail(service::ErrorResponse::InternalError);
+                                    continue;
+                                }
+                            },
+                        };
+
+                        let current = match config.runtime_caches_service.get(at).await {
+                            Ok(runtime) => runtime,
+                            Err(runtime_caches_service::GetError::UnknownBlock)
+                            | Err(runtime_caches_service::GetError::Pruned) => {
+                                request.fail(service::ErrorResponse::InvalidParams);
+                                continue;
+                            }
+                            Err(runtime_caches_service::GetError::InvalidRuntime(_))
+                            | Err(runtime_caches_service::GetError::NoCode)
+                            | Err(runtime_caches_service::GetError::InvalidHeapPages)
+                            | Err(runtime_caches_service::GetError::CorruptedDatabase) => {
+                                request.fail(service::ErrorResponse::InternalError);
+                                continue;
+                            }
+                        };
+
+                        // The proposed runtime code is provided by the JSON-RPC client and is thus
+                        // untrusted. It is only instantiated in order to obtain its runtime
+                        // version, and the heap pages of the current runtime are used.
+                        match executor::runtime_upgrade::check(
+                            &current,
+                            executor::host::Config {
+                                module: &code.0,
+                                heap_pages: current.heap_pages(),
+                                exec_hint: executor::vm::ExecHint::Untrusted,
+                                allow_unresolved_imports: true,
+                            },
+                        ) {
+                            Ok(differences) => {
+                                request.respond(
+                                    methods::Response::smoldot_unstable_checkRuntimeUpgrade(
+                                        convert_runtime_upgrade_differences(differences),
+                                    ),
+                                );
+                            }
+                            Err(error) => request.fail(service::ErrorResponse::ServerError(
+                                -32000,
+                                &format!("Invalid runtime code: {error}"),
+                            )),
+                        }
+                    }
                     methods::MethodCall::state_queryStorageAt { keys, at } => {
                         // TODO: add a limit to the number of keys?
 
@@ -590,6 +833,68 @@ pub fn spawn_requests_handler(mut config: Config) {
                             }
                         }
                     }
+                    methods::MethodCall::author_submitExtrinsic { transaction } => {
+                        // In Substrate, `author_submitExtrinsic` returns the hash of the
+                        // transaction.
+                        let transaction_hash = <[u8; 32]>::try_from(
+                            blake2_rfc::blake2b::blake2b(32, &[], &transaction.0).as_bytes(),
+                        )
+                        .unwrap();
+                        match config
+                            .consensus_service
+                            .submit_transaction(transaction.0)
+                            .await
+                        {
+                            Ok(()) => request.respond(methods::Response::author_submitExtrinsic(
+                                methods::HashHexString(transaction_hash),
+                            )),
+                            // Error code used by Substrate when a transaction is dropped
+                            // because of the limits of the transactions pool.
+                            Err(error @ consensus_service::SubmitTransactionError::QueueFull) => {
+                                request.fail(service::ErrorResponse::ServerError(
+                                    1016,
+                                    &error.to_string(),
+                                ))
+                            }
+                        }
+                    }
+                    methods::MethodCall::engine_createBlock {
+                        create_empty,
+                        finalize,
+                    } => {
+                        match config
+                            .consensus_service
+                            .create_block(create_empty, finalize)
+                            .await
+                        {
+                            Ok(hash) => request.respond(methods::Response::engine_createBlock(
+                                methods::CreatedBlock {
+                                    hash: methods::HashHexString(hash),
+                                },
+                            )),
+                            Err(consensus_service::CreateBlockError::ManualSealDisabled) => {
+                                request.fail(service::ErrorResponse::MethodNotFound)
+                            }
+                            Err(error) => request.fail(service::ErrorResponse::ServerError(
+                                -32000,
+                                &error.to_string(),
+                            )),
+                        }
+                    }
+                    methods::MethodCall::engine_finalizeBlock { hash } => {
+                        match config.consensus_service.finalize_block(hash.0).await {
+                            Ok(()) => {
+                                request.respond(methods::Response::engine_finalizeBlock(true))
+                            }
+                            Err(consensus_service::FinalizeBlockError::ManualSealDisabled) => {
+                                request.fail(service::ErrorResponse::MethodNotFound)
+                            }
+                            Err(error) => request.fail(service::ErrorResponse::ServerError(
+                                -32000,
+                                &error.to_string(),
+                            )),
+                        }
+                    }
                     methods::MethodCall::system_chain {} => {
                         request
                             .respond(methods::Response::system_chain((&config.chain_name).into()));
@@ -838,3 +1143,230 @@ fn convert_runtime_version(runtime_spec: &executor::CoreVersion) -> methods::Run
             .collect(),
     }
 }
+
+fn convert_runtime_upgrade_differences(
+    differences: Vec<executor::runtime_upgrade::Difference>,
+) -> methods::RuntimeUpgradeCheck<'static> {
+    use executor::runtime_upgrade::Difference;
+
+    methods::RuntimeUpgradeCheck {
+        compatible: differences.iter().all(|d| !d.is_breaking()),
+        differences: differences
+            .into_iter()
+            .map(|difference| methods::RuntimeUpgradeDifference {
+                breaking: difference.is_breaking(),
+                kind: match difference {
+                    Difference::SpecNameChanged { current, proposed } => {
+                        methods::RuntimeUpgradeDifferenceKind::SpecNameChanged {
+                            current: current.into(),
+                            proposed: proposed.into(),
+                        }
+                    }
+                    Difference::SpecVersionNotIncreased { current, proposed } => {
+                        methods::RuntimeUpgradeDifferenceKind::SpecVersionNotIncreased {
+                            current,
+                            proposed,
+                        }
+                    }
+                    Difference::ApiRemoved { name_hash, version } => {
+                        methods::RuntimeUpgradeDifferenceKind::ApiRemoved {
+                            name_hash: methods::HexString(name_hash.to_vec()),
+                            version,
+                        }
+                    }
+                    Difference::ApiVersionChanged {
+                        name_hash,
+                        current_version,
+                        proposed_version,
+                    } => methods::RuntimeUpgradeDifferenceKind::ApiVersionChanged {
+                        name_hash: methods::HexString(name_hash.to_vec()),
+                        current_version,
+                        proposed_version,
+                    },
+                    Difference::ApiAdded { name_hash, version } => {
+                        methods::RuntimeUpgradeDifferenceKind::ApiAdded {
+                            name_hash: methods::HexString(name_hash.to_vec()),
+                            version,
+                        }
+                    }
+                    Difference::TransactionVersionChanged { current, proposed } => {
+                        methods::RuntimeUpgradeDifferenceKind::TransactionVersionChanged {
+                            current,
+                            proposed,
+                        }
+                    }
+                    Difference::StateVersionChanged { current, proposed } => {
+                        methods::RuntimeUpgradeDifferenceKind::StateVersionChanged {
+                            current: u8::from(current),
+                            proposed: u8::from(proposed),
+                        }
+                    }
+                },
+            })
+            .collect(),
+    }
+}
+
+/// Maximum number of items that a single `archive_unstable_storage` response can contain.
+///
+/// Descendants queries can return an arbitrary large number of items. Once this limit is
+/// reached, the remaining requested items are reported as discarded, and the JSON-RPC client is
+/// expected to send a new request, potentially using `paginationStartKey`.
+// TODO: arbitrary constant
+const MAX_ARCHIVE_STORAGE_RESULTS: usize = 1024;
+
+/// Performs the storage queries of an `archive_unstable_storage` JSON-RPC request.
+fn archive_storage(
+    db: &full_sqlite::SqliteFullDatabase,
+    block_hash: &[u8; 32],
+    parent_paths: Option<Vec<u8>>,
+    items: Vec<methods::ArchiveStorageRequestItem>,
+) -> Result<methods::ArchiveStorageResult, database_thread::StorageAccessError> {
+    let mut result = Vec::new();
+    let num_items = items.len();
+
+    for (item_index, item) in items.into_iter().enumerate() {
+        if result.len() >= MAX_ARCHIVE_STORAGE_RESULTS {
+            return Ok(methods::ArchiveStorageResult {
+                result,
+                discarded_items: num_items - item_index,
+            });
+        }
+
+        let key_nibbles = trie::bytes_to_nibbles(item.key.0.iter().copied())
+            .map(u8::from)
+            .collect::<Vec<_>>();
+
+        match item.ty {
+            methods::ChainHeadStorageType::Value | methods::ChainHeadStorageType::Hash => {
+                let Some((value, _)) = db.block_storage_get(
+                    block_hash,
+                    parent_paths.iter().map(|p| p.iter().copied()),
+                    key_nibbles.iter().copied(),
+                )?
+                else {
+                    continue;
+                };
+
+                let is_hash = matches!(item.ty, methods::ChainHeadStorageType::Hash);
+                result.push(archive_storage_value_item(item.key, value, is_hash));
+            }
+            methods::ChainHeadStorageType::ClosestDescendantMerkleValue => {
+                let Some(merkle_value) = db.block_storage_closest_descendant_merkle_value(
+                    block_hash,
+                    parent_paths.iter().map(|p| p.iter().copied()),
+                    key_nibbles.iter().copied(),
+                )?
+                else {
+                    continue;
+                };
+
+                result.push(methods::ChainHeadStorageResponseItem {
+                    key: item.key,
+                    value: None,
+                    hash: None,
+                    closest_descendant_merkle_value: Some(methods::HexString(merkle_value)),
+                });
+            }
+            methods::ChainHeadStorageType::DescendantsValues
+            | methods::ChainHeadStorageType::DescendantsHashes => {
+                let is_hash = matches!(item.ty, methods::ChainHeadStorageType::DescendantsHashes);
+
+                // If a pagination key is provided, the iteration resumes right after it.
+                let mut key_iter = match item.pagination_start_key {
+                    Some(start) => {
+                        let mut start = trie::bytes_to_nibbles(start.0.iter().copied())
+                            .map(u8::from)
+                            .collect::<Vec<_>>();
+                        start.push(0);
+                        if start < key_nibbles {
+                            key_nibbles.clone()
+                        } else {
+                            start
+                        }
+                    }
+                    None => key_nibbles.clone(),
+                };
+
+                loop {
+                    if result.len() >= MAX_ARCHIVE_STORAGE_RESULTS {
+                        // The current item is only partially processed and is thus reported as
+                        // discarded as well.
+                        return Ok(methods::ArchiveStorageResult {
+                            result,
+                            discarded_items: num_items - item_index,
+                        });
+                    }
+
+                    let Some(next_key_nibbles) = db.block_storage_next_key(
+                        block_hash,
+                        parent_paths.iter().map(|p| p.iter().copied()),
+                        key_iter.iter().copied(),
+                        key_nibbles.iter().copied(),
+                        false,
+                    )?
+                    else {
+                        break;
+                    };
+
+                    let value = db.block_storage_get(
+                        block_hash,
+                        parent_paths.iter().map(|p| p.iter().copied()),
+                        next_key_nibbles.iter().copied(),
+                    )?;
+
+                    if let Some((value, _)) = value {
+                        let key = methods::HexString(
+                            trie::nibbles_to_bytes_truncate(
+                                next_key_nibbles
+                                    .iter()
+                                    .copied()
+                                    .map(|n| trie::Nibble::try_from(n).unwrap()),
+                            )
+                            .collect::<Vec<_>>(),
+                        );
+                        result.push(archive_storage_value_item(key, value, is_hash));
+                    }
+
+                    // Push an extra nibble as otherwise `block_storage_next_key` will return the
+                    // same key again.
+                    key_iter = next_key_nibbles;
+                    key_iter.push(0);
+                }
+            }
+        }
+    }
+
+    Ok(methods::ArchiveStorageResult {
+        result,
+        discarded_items: 0,
+    })
+}
+
+/// Builds an item of the response of `archive_unstable_storage` containing either the storage
+/// value or its hash.
+fn archive_storage_value_item(
+    key: methods::HexString,
+    value: Vec<u8>,
+    is_hash: bool,
+) -> methods::ChainHeadStorageResponseItem {
+    let (value, hash) = if is_hash {
+        (
+            None,
+            Some(methods::HexString(
+                blake2_rfc::blake2b::blake2b(32, &[], &value)
+                    .as_bytes()
+                    .to_vec(),
+            )),
+        )
+    } else {
+        (Some(methods::HexString(value)), None)
+    };
+
+    methods::ChainHeadStorageResponseItem {
+        key,
+        value,
+        hash,
+        closest_descendant_merkle_value: None,
+    }
+}
@@ -15,12 +15,12 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <http://www.gnu.org/licenses/>.
 
-use crate::{database_thread, LogCallback};
+use crate::{database_thread, LogCallback, LogLevel};
 
 use futures_channel::oneshot;
 use futures_lite::{Future, StreamExt as _};
 use smol::lock::Mutex;
-use smoldot::{executor, trie};
+use smoldot::{executor, informant::HashDisplay, trie};
 use std::{iter, num::NonZeroUsize, pin::Pin, sync::Arc};
 
 /// Configuration of the service.
@@ -122,8 +122,16 @@ impl RuntimeCachesService {
                                 let _ = result_tx.send(Err(GetError::Pruned));
                                 continue;
                             }
-                            (Err(database_thread::StorageAccessError::Corrupted(_)), _)
-                            | (_, Err(database_thread::StorageAccessError::Corrupted(_))) => {
+                            (Err(database_thread::StorageAccessError::Corrupted(error)), _)
+                            | (_, Err(database_thread::StorageAccessError::Corrupted(error))) => {
+                                config.log_callback.log(
+                                    LogLevel::Warn,
+                                    format!(
+                                        "runtime-caches; block={}; database_error={}",
+                                        HashDisplay(&block_hash),
+                                        error
+                                    ),
+                                );
                                 // Note that we don't put the `CorruptedError` in the cache, in
                                 // case the database somehow recovers.
                                 let _ = result_tx.send(Err(GetError::CorruptedDatabase));
@@ -131,6 +139,17 @@ impl RuntimeCachesService {
                             }
                         };
 
+                        if let Err(error) = &runtime {
+                            config.log_callback.log(
+                                LogLevel::Debug,
+                                format!(
+                                    "runtime-caches; block={}; error={}",
+                                    HashDisplay(&block_hash),
+                                    error
+                                ),
+                            );
+                        }
+
                         let runtime = runtime.map(Arc::new);
                         cache.put(block_hash, runtime.clone());
                         let _ = result_tx.send(runtime);
@@ -116,6 +116,10 @@ pub struct ChainConfig<'a> {
     pub keystore_path: Option<PathBuf>,
     /// Configuration of the JSON-RPC server. If `None`, no TCP server is started.
     pub json_rpc_listen: Option<JsonRpcListenConfig>,
+    /// If `true`, blocks are only authored when requested through the `engine_createBlock`
+    /// JSON-RPC function, and can be finalized through the `engine_finalizeBlock` JSON-RPC
+    /// function. Meant to be used exclusively when testing a chain.
+    pub manual_seal: bool,
 }
 
 /// Running client. As long as this object is alive, the client reads/writes the database and has
@@ -406,6 +410,17 @@ pub async fn start(mut config: Config<'_>) -> Result<Client, StartError> {
     .await
     .map_err(StartError::JaegerInit)?;
 
+    // Loopback addresses discovered through the DHT are only useful if the local node is itself
+    // part of a local network, which is assumed to be the case if it listens on a loopback
+    // address.
+    let allow_loopback_addresses = config.listen_addresses.iter().any(|addr| {
+        addr.iter().any(|protocol| match protocol {
+            multiaddr::ProtocolRef::Ip4(ip) => ip[0] == 127,
+            multiaddr::ProtocolRef::Ip6(ip) => ip == std::net::Ipv6Addr::LOCALHOST.octets(),
+            _ => false,
+        })
+    });
+
     let (network_service, network_service_chain_ids, network_events_receivers) =
         network_service::NetworkService::new(network_service::Config {
             listen_addresses: config.listen_addresses,
@@ -559,6 +574,9 @@ pub async fn start(mut config: Config<'_>) -> Result<Client, StartError> {
             )
             .collect(),
             identify_agent_version: concat!(env!("CARGO_PKG_NAME"), " ", env!("CARGO_PKG_VERSION")).to_owned(),
+            address_sanitize_policy: multiaddr::SanitizePolicy {
+                allow_loopback: allow_loopback_addresses,
+            },
             noise_key,
             tasks_executor: {
                 let executor = config.tasks_executor.clone();
@@ -590,6 +608,8 @@ pub async fn start(mut config: Config<'_>) -> Result<Client, StartError> {
         },
         log_callback: config.log_callback.clone(),
         genesis_block_hash,
+        bad_blocks: chain_spec.bad_blocks_hashes().copied().collect(),
+        fork_blocks: chain_spec.fork_blocks().map(|(n, h)| (n, *h)).collect(),
         network_events_receiver: network_events_receivers.next().unwrap(),
         network_service: (network_service.clone(), network_service_chain_ids[0]),
         database: database.clone(),
@@ -597,6 +617,10 @@ pub async fn start(mut config: Config<'_>) -> Result<Client, StartError> {
         keystore,
         jaeger_service: jaeger_service.clone(),
         slot_duration_author_ratio: 43691_u16,
+        // The storage changes are used by the JSON-RPC service in order to report storage
+        // modifications to `state_subscribeStorage` subscribers.
+        keep_non_finalized_storage_changes: true,
+        manual_seal: config.chain.manual_seal,
     })
     .await
     .map_err(StartError::ConsensusServiceInit)?;
@@ -617,6 +641,18 @@ pub async fn start(mut config: Config<'_>) -> Result<Client, StartError> {
                     .hash(usize::from(
                         relay_chain_spec.as_ref().unwrap().block_number_bytes(),
                     )),
+                bad_blocks: relay_chain_spec
+                    .as_ref()
+                    .unwrap()
+                    .bad_blocks_hashes()
+                    .copied()
+                    .collect(),
+                fork_blocks: relay_chain_spec
+                    .as_ref()
+                    .unwrap()
+                    .fork_blocks()
+                    .map(|(n, h)| (n, *h))
+                    .collect(),
                 network_events_receiver: network_events_receivers.next().unwrap(),
                 network_service: (network_service.clone(), network_service_chain_ids[1]),
                 database: relay_chain_database.clone(),
@@ -640,6 +676,8 @@ pub async fn start(mut config: Config<'_>) -> Result<Client, StartError> {
                 }),
                 jaeger_service, // TODO: consider passing a different jaeger service with a different service name
                 slot_duration_author_ratio: 43691_u16,
+                keep_non_finalized_storage_changes: false,
+                manual_seal: config.relay_chain.as_ref().unwrap().manual_seal,
             })
             .await
             .map_err(StartError::RelayChainConsensusServiceInit)?,
@@ -669,7 +707,7 @@ pub async fn start(mut config: Config<'_>) -> Result<Client, StartError> {
             .map_or(0, |cfg| cfg.max_json_rpc_clients),
         chain_name: chain_spec.name().to_owned(),
         chain_type: chain_spec.chain_type().to_owned(),
-        chain_properties_json: chain_spec.properties().to_owned(),
+        chain_properties_json: chain_spec.validated_properties(),
         chain_is_live: chain_spec.has_live_network(),
         genesis_block_hash: genesis_chain_information
             .as_ref()
@@ -700,7 +738,7 @@ pub async fn start(mut config: Config<'_>) -> Result<Client, StartError> {
                     .map_or(0, |cfg| cfg.max_json_rpc_clients),
                 chain_name: relay_chain_spec.name().to_owned(),
                 chain_type: relay_chain_spec.chain_type().to_owned(),
-                chain_properties_json: relay_chain_spec.properties().to_owned(),
+                chain_properties_json: relay_chain_spec.validated_properties(),
                 chain_is_live: relay_chain_spec.has_live_network(),
                 genesis_block_hash: relay_genesis_chain_information
                     .as_ref()
@@ -29,7 +29,7 @@
 
 use crate::{database_thread, jaeger_service, LogCallback, LogLevel};
 
-use core::{cmp, future::Future, mem, pin::Pin, task::Poll, time::Duration};
+use core::{cmp, future::Future, mem, num::NonZeroU32, pin::Pin, task::Poll, time::Duration};
 use futures_channel::oneshot;
 use futures_lite::FutureExt as _;
 use hashbrown::HashMap;
@@ -48,13 +48,13 @@ use smoldot::{
         multiaddr::{self, Multiaddr, ProtocolRef},
         peer_id::{self, PeerId},
     },
-    network::{basic_peering_strategy, protocol, service},
+    network::{basic_peering_strategy, codec, service},
 };
 use std::{
     io,
     net::{IpAddr, SocketAddr},
     sync::Arc,
-    time::Instant,
+    time::{Instant, SystemTime, UNIX_EPOCH},
 };
 
 pub use smoldot::network::service::ChainId;
@@ -81,6 +81,10 @@ pub struct Config {
     /// Value sent back for the agent version when receiving an identification request.
     pub identify_agent_version: String,
 
+    /// Policy used to filter the addresses discovered through the Kademlia DHT before they are
+    /// stored.
+    pub address_sanitize_policy: multiaddr::SanitizePolicy,
+
     /// Key used for the encryption layer.
     /// This is a Noise static key, according to the Noise specification.
     /// Signed using the actual libp2p key.
@@ -140,6 +144,12 @@ pub enum Event {
         scale_encoded_header: Vec<u8>,
         is_best: bool,
     },
+    TransactionsReceived {
+        chain_id: ChainId,
+        peer_id: PeerId,
+        /// List of SCALE-encoded transactions. Not verified in any way.
+        transactions: Vec<Vec<u8>>,
+    },
 }
 
 pub struct NetworkService {
@@ -191,8 +201,8 @@ enum ToBackground {
     ForegroundBlocksRequest {
         target: PeerId,
         chain_id: ChainId,
-        config: protocol::BlocksRequestConfig,
-        result_tx: oneshot::Sender<Result<Vec<protocol::BlockData>, BlocksRequestError>>,
+        config: codec::BlocksRequestConfig,
+        result_tx: oneshot::Sender<Result<Vec<codec::BlockData>, BlocksRequestError>>,
     },
     ForegroundGetNumConnections {
         result_tx: oneshot::Sender<usize>,
@@ -210,6 +220,9 @@ struct Inner {
     /// Value provided through [`Config::identify_agent_version`].
     identify_agent_version: String,
 
+    /// Value provided through [`Config::address_sanitize_policy`].
+    address_sanitize_policy: multiaddr::SanitizePolicy,
+
     /// Sending events through the public API.
     ///
     /// Contains either senders, or a `Future` that is currently sending an event and will yield
@@ -263,7 +276,7 @@ struct Inner {
     /// List of all block requests that have been started but not finished yet.
     blocks_requests: HashMap<
         service::SubstreamId,
-        oneshot::Sender<Result<Vec<protocol::BlockData>, BlocksRequestError>>,
+        oneshot::Sender<Result<Vec<codec::BlockData>, BlocksRequestError>>,
         fnv::FnvBuildHasher,
     >,
 
@@ -278,8 +291,29 @@ struct Chain {
 
     /// How to access data to answer requests from the remotes.
     database: Arc<database_thread::DatabaseThread>,
+
+    /// Nodes that were stored in the database during a previous run and that haven't been
+    /// inserted in the peering strategy yet. The most recently seen nodes are at the end.
+    ///
+    /// These nodes are inserted in the peering strategy by batches when no other peer is
+    /// available, in order to not rely on the bootnodes being reachable.
+    persisted_peers: Vec<(PeerId, Multiaddr)>,
+
+    /// When the next batch of [`Chain::persisted_peers`] can be inserted in the peering
+    /// strategy.
+    next_persisted_peers_batch: Instant,
 }
 
+/// Maximum number of entries of known nodes stored in the database of each chain.
+const MAX_PERSISTED_PEERS: usize = 512;
+
+/// Number of entries of [`Chain::persisted_peers`] inserted at once in the peering strategy.
+const PERSISTED_PEERS_BATCH_SIZE: usize = 8;
+
+/// Minimum time between two batches of [`Chain::persisted_peers`] being inserted in the peering
+/// strategy.
+const PERSISTED_PEERS_BATCH_INTERVAL: Duration = Duration::from_secs(2);
+
 impl NetworkService {
     /// Initializes the network service with the given configuration.
     pub async fn new(
@@ -302,6 +336,18 @@ impl NetworkService {
             noise_key: config.noise_key,
             handshake_timeout: Duration::from_secs(8),
             randomness_seed: rand::random(),
+            deterministic_ordering: false,
+            // Re-opening gossip links is handled by the peering strategy.
+            gossip_open_retry: None,
+            reputation_ban: None,
+            refused_protocols_cooldown: Some(Duration::from_secs(60)),
+            ping_interval: Duration::from_secs(20),
+            ping_timeout: Duration::from_secs(10),
+            max_ping_failures: NonZeroU32::new(1).unwrap(),
+            max_connections_per_peer: None,
+            max_inbound_substreams: None,
+            max_queued_notifications_bytes: 1024 * 1024,
+            address_sanitize_policy: config.address_sanitize_policy.clone(),
         });
 
         let mut peering_strategy = basic_peering_strategy::BasicPeeringStrategy::new();
@@ -319,7 +365,11 @@ impl NetworkService {
                     best_hash: chain.best_block.1,
                     best_number: chain.best_block.0,
                     genesis_hash: chain.genesis_block_hash,
-                    role: protocol::Role::Full,
+                    role: codec::Role::Full,
+                    gossip_slots: None,
+                    notifications_open_timeout: Duration::from_secs(10),
+                    max_notifications_handshake_size: 1024 * 1024,
+                    max_notification_size: 1024 * 1024,
                     grandpa_protocol_config: if let Some(commit_finalized_height) =
                         chain.grandpa_protocol_finalized_block_height
                     {
@@ -333,6 +383,8 @@ impl NetworkService {
                         None
                     },
                     allow_inbound_block_requests: true,
+                    custom_request_response_protocols: Vec::new(),
+                    custom_notifications_protocols: Vec::new(),
                 })
                 .unwrap(); // TODO: don't unwrap?
 
@@ -341,6 +393,33 @@ impl NetworkService {
                 peering_strategy.insert_chain_peer(chain_id, peer_id);
             }
 
+            // Load the nodes that were seen during previous runs. Entries that fail to decode
+            // are simply ignored, as the definition of a valid multiaddress might change across
+            // versions.
+            let mut persisted_peers = chain
+                .database
+                .with_database(|database| database.known_peers(MAX_PERSISTED_PEERS))
+                .await
+                .unwrap_or_default()
+                .into_iter()
+                .filter_map(|peer| {
+                    Some((
+                        PeerId::from_bytes(peer.peer_id).ok()?,
+                        Multiaddr::try_from(peer.address).ok()?,
+                    ))
+                })
+                .collect::<Vec<_>>();
+            persisted_peers.reverse();
+
+            config.log_callback.log(
+                LogLevel::Debug,
+                format!(
+                    "persisted-peers-loaded; chain={}; num_entries={}",
+                    chain.log_name,
+                    persisted_peers.len()
+                ),
+            );
+
             chain_names.insert(chain_id, chain.log_name.clone());
 
             chains.insert(
@@ -348,6 +427,8 @@ impl NetworkService {
                 Chain {
                     log_name: chain.log_name,
                     database: chain.database,
+                    persisted_peers,
+                    next_persisted_peers_batch: Instant::now(),
                 },
             );
         }
@@ -363,6 +444,7 @@ impl NetworkService {
         let mut inner = Inner {
             local_peer_id: local_peer_id.clone(),
             identify_agent_version: config.identify_agent_version,
+            address_sanitize_policy: config.address_sanitize_policy,
             event_senders: either::Left(event_senders),
             chains,
             num_pending_out_attempts: 0,
@@ -422,6 +504,25 @@ impl NetworkService {
                 }
             };
 
+            // Report the address we are listening on to the remotes that send identify
+            // requests. The address is built from the actual socket in order to resolve the
+            // port if the configuration requested `0`. Unspecified IP addresses (such as
+            // `0.0.0.0`) aren't reachable and are thus not reported.
+            if let Ok(local_addr) = tcp_listener.local_addr() {
+                if !local_addr.ip().is_unspecified() {
+                    let multiaddr = [
+                        match local_addr.ip() {
+                            IpAddr::V4(ip) => ProtocolRef::Ip4(ip.octets()),
+                            IpAddr::V6(ip) => ProtocolRef::Ip6(ip.octets()),
+                        },
+                        ProtocolRef::Tcp(local_addr.port()),
+                    ]
+                    .into_iter()
+                    .collect::<Multiaddr>();
+                    inner.network.add_local_address(multiaddr.into_vec());
+                }
+            }
+
             // Spawn a background task dedicated to this listener.
             (inner.tasks_executor)(Box::pin({
                 let to_background_tx = to_background_tx.clone();
@@ -673,8 +774,8 @@ impl NetworkService {
         self: Arc<Self>,
         target: PeerId, // TODO: by value?
         chain_id: ChainId,
-        config: protocol::BlocksRequestConfig,
-    ) -> Result<Vec<protocol::BlockData>, BlocksRequestError> {
+        config: codec::BlocksRequestConfig,
+    ) -> Result<Vec<codec::BlockData>, BlocksRequestError> {
         let chain_name = self.chain_names[&chain_id].clone();
 
         self.log_callback.log(
@@ -684,13 +785,13 @@ impl NetworkService {
                 target,
                 chain_name,
                 match &config.start {
-                    protocol::BlocksRequestConfigStart::Hash(h) => either::Left(HashDisplay(h)),
-                    protocol::BlocksRequestConfigStart::Number(n) => either::Right(n),
+                    codec::BlocksRequestConfigStart::Hash(h) => either::Left(HashDisplay(h)),
+                    codec::BlocksRequestConfigStart::Number(n) => either::Right(n),
                 },
                 config.desired_count,
                 match config.direction {
-                    protocol::BlocksRequestDirection::Ascending => "ascending",
-                    protocol::BlocksRequestDirection::Descending => "descending",
+                    codec::BlocksRequestDirection::Ascending => "ascending",
+                    codec::BlocksRequestDirection::Descending => "descending",
                 },
             ),
         );
@@ -719,7 +820,7 @@ impl NetworkService {
             &self.local_peer_id,
             &target,
             config.desired_count.get(),
-            if let (1, protocol::BlocksRequestConfigStart::Hash(block_hash)) =
+            if let (1, codec::BlocksRequestConfigStart::Hash(block_hash)) =
                 (config.desired_count.get(), &config.start)
             {
                 Some(block_hash)
@@ -831,7 +932,7 @@ async fn background_task(mut inner: Inner) {
 
         if inner.process_network_service_events && matches!(inner.event_senders, either::Left(_)) {
             let event = loop {
-                let inner_event = match inner.network.next_event() {
+                let inner_event = match inner.network.next_event(&Instant::now()) {
                     Some(ev) => ev,
                     None => break None,
                 };
@@ -839,11 +940,13 @@ async fn background_task(mut inner: Inner) {
                 match inner_event {
                     service::Event::HandshakeFinished {
                         id,
+                        direction,
                         expected_peer_id,
                         peer_id,
-                        ..
                     } => {
-                        inner.num_pending_out_attempts -= 1;
+                        if direction == service::ConnectionDirection::Outbound {
+                            inner.num_pending_out_attempts -= 1;
+                        }
 
                         let remote_addr = Multiaddr::try_from(
                             inner.network.connection_remote_addr(id).to_owned(),
@@ -863,17 +966,57 @@ async fn background_task(mut inner: Inner) {
                                 .peering_strategy
                                 .insert_connected_address(&peer_id, remote_addr.clone().into_vec());
                         } else {
+                            inner.log_callback.log(
+                                LogLevel::Debug,
+                                format!(
+                                    "connected; peer_id={}; direction={:?}",
+                                    peer_id, direction
+                                ),
+                            );
+                        }
+                    }
+                    service::Event::SupernumeraryConnection {
+                        id,
+                        direction,
+                        expected_peer_id,
+                        peer_id,
+                    } => {
+                        if direction == service::ConnectionDirection::Outbound {
+                            inner.num_pending_out_attempts -= 1;
+                        }
+
+                        let remote_addr = Multiaddr::try_from(
+                            inner.network.connection_remote_addr(id).to_owned(),
+                        )
+                        .unwrap(); // TODO: review this unwrap
+                        if let Some(expected_peer_id) =
+                            expected_peer_id.as_ref().filter(|p| **p != peer_id)
+                        {
                             inner
-                                .log_callback
-                                .log(LogLevel::Debug, format!("connected; peer_id={}", peer_id));
+                                .peering_strategy
+                                .remove_address(expected_peer_id, remote_addr.as_ref());
+                            inner
+                                .peering_strategy
+                                .insert_connected_address(&peer_id, remote_addr.clone().into_vec());
                         }
+                        inner.log_callback.log(
+                            LogLevel::Debug,
+                            format!(
+                                "supernumerary-connection; peer_id={}; address={}; direction={:?}",
+                                peer_id, remote_addr, direction
+                            ),
+                        );
                     }
                     service::Event::PreHandshakeDisconnected {
                         address,
+                        direction,
                         expected_peer_id,
+                        reason,
                         ..
                     } => {
-                        inner.num_pending_out_attempts -= 1;
+                        if direction == service::ConnectionDirection::Outbound {
+                            inner.num_pending_out_attempts -= 1;
+                        }
                         if let Some(expected_peer_id) = expected_peer_id {
                             inner
                                 .peering_strategy
@@ -883,14 +1026,18 @@ async fn background_task(mut inner: Inner) {
                             inner.log_callback.log(
                                 LogLevel::Debug,
                                 format!(
-                                    "disconnected; handshake-finished=false; peer_id={}; address={}",
-                                    expected_peer_id, address
+                                    "disconnected; handshake-finished=false; peer_id={}; address={}; reason={}",
+                                    expected_peer_id, address, reason
                                 ),
                             );
                         }
                     }
                     service::Event::Disconnected {
-                        address, peer_id, ..
+                        address,
+                        direction,
+                        peer_id,
+                        reason,
+                        ..
                     } => {
                         inner
                             .peering_strategy
@@ -900,8 +1047,8 @@ async fn background_task(mut inner: Inner) {
                         inner.log_callback.log(
                             LogLevel::Debug,
                             format!(
-                                "disconnected; handshake-finished=true; peer_id={}; address={}",
-                                peer_id, address
+                                "disconnected; handshake-finished=true; peer_id={}; address={}; direction={:?}; reason={}",
+                                peer_id, address, direction, reason
                             ),
                         );
                     }
@@ -963,11 +1110,13 @@ async fn background_task(mut inner: Inner) {
                                         ),
                                     );
                                 }
+                                // An error is returned if the gossip link wasn't open, in which
+                                // case there is nothing to close.
                                 let _ = inner.network.gossip_close(
                                     chain_id,
                                     &peer_id,
                                     service::GossipKind::ConsensusTransactions,
-                                ); // TODO: what is the return value?
+                                );
 
                                 inner.process_network_service_events = true;
                             }
@@ -978,18 +1127,47 @@ async fn background_task(mut inner: Inner) {
                         chain_id,
                         best_number,
                         best_hash,
+                        connection_direction,
                         ..
                     } => {
                         inner.log_callback.log(
                             LogLevel::Debug,
                             format!(
-                            "chain-connected; peer_id={}; chain={}; best_number={}; best_hash={}",
+                            "chain-connected; peer_id={}; chain={}; best_number={}; best_hash={}; connection_direction={:?}",
                             peer_id,
                             inner.chains[&chain_id].log_name,
                             best_number,
                             HashDisplay(&best_hash),
+                            connection_direction,
                         ),
                         );
+
+                        // Remember the node in the database, so that it can be used to connect
+                        // to the peer-to-peer network after a restart.
+                        let addresses = inner
+                            .peering_strategy
+                            .peer_addresses(&peer_id)
+                            .map(|addr| addr.to_vec())
+                            .collect::<Vec<_>>();
+                        if !addresses.is_empty() {
+                            let peer_id_bytes = peer_id.as_bytes().to_vec();
+                            let last_seen = SystemTime::now()
+                                .duration_since(UNIX_EPOCH)
+                                .map_or(0, |d| d.as_secs());
+                            inner.chains[&chain_id]
+                                .database
+                                .with_database_detached(move |database| {
+                                    let _ = database.insert_known_peers(
+                                        addresses
+                                            .iter()
+                                            .map(|addr| (&peer_id_bytes[..], &addr[..])),
+                                        last_seen,
+                                        MAX_PERSISTED_PEERS,
+                                    );
+                                })
+                                .await;
+                        }
+
                         break Some(Event::Connected {
                             peer_id,
                             chain_id,
@@ -1105,10 +1283,11 @@ async fn background_task(mut inner: Inner) {
                         } else {
                             inner
                                 .network
-                                .gossip_close(
+                                .gossip_reject_in(
                                     chain_id,
                                     &peer_id,
                                     service::GossipKind::ConsensusTransactions,
+                                    service::GossipRejectReason::SlotsFull,
                                 )
                                 .unwrap();
                         }
@@ -1150,7 +1329,23 @@ async fn background_task(mut inner: Inner) {
                             let mut valid_addrs = Vec::with_capacity(addrs.len());
                             for addr in addrs {
                                 match Multiaddr::try_from(addr) {
-                                    Ok(a) => valid_addrs.push(a),
+                                    Ok(a) => {
+                                        if let Err(err) = multiaddr::sanitize(
+                                            &a,
+                                            &peer_id,
+                                            &inner.address_sanitize_policy,
+                                        ) {
+                                            inner.log_callback.log(
+                                                LogLevel::Debug,
+                                                format!(
+                                                    "discovery-refused-address; addr={}; error={}",
+                                                    a, err
+                                                ),
+                                            );
+                                            continue;
+                                        }
+                                        valid_addrs.push(a);
+                                    }
                                     Err(err) => {
                                         inner.log_callback.log(
                                             LogLevel::Debug,
@@ -1209,9 +1404,11 @@ async fn background_task(mut inner: Inner) {
                             LogLevel::Debug,
                             format!("identify-request; peer_id={}", peer_id),
                         );
-                        inner
-                            .network
-                            .respond_identify(substream_id, &inner.identify_agent_version);
+                        inner.network.respond_identify(
+                            &Instant::now(),
+                            substream_id,
+                            &inner.identify_agent_version,
+                        );
                     }
                     service::Event::BlocksRequestIn {
                         peer_id,
@@ -1230,7 +1427,7 @@ async fn background_task(mut inner: Inner) {
                             &inner.local_peer_id,
                             &peer_id,
                             config.desired_count.get(),
-                            if let (1, protocol::BlocksRequestConfigStart::Hash(block_hash)) =
+                            if let (1, codec::BlocksRequestConfigStart::Hash(block_hash)) =
                                 (config.desired_count.get(), &config.start)
                             {
                                 Some(block_hash)
@@ -1247,6 +1444,7 @@ async fn background_task(mut inner: Inner) {
                         )
                         .await;
                         inner.network.respond_blocks(
+                            &Instant::now(),
                             substream_id,
                             match response {
                                 Ok(b) => Some(b),
@@ -1260,6 +1458,39 @@ async fn background_task(mut inner: Inner) {
                             },
                         );
                     }
+                    service::Event::CustomRequestIn { .. }
+                    | service::Event::CustomRequestInStart { .. }
+                    | service::Event::CustomRequestInChunk { .. }
+                    | service::Event::CustomNotificationsOutResult { .. }
+                    | service::Event::CustomNotificationsOutClose { .. }
+                    | service::Event::CustomNotificationsOutQueueDrained { .. }
+                    | service::Event::CustomNotificationsInOpen { .. }
+                    | service::Event::CustomNotificationsInOpenCancel { .. }
+                    | service::Event::CustomNotificationIn { .. }
+                    | service::Event::CustomNotificationsInClose { .. } => {
+                        // No custom protocol is ever registered.
+                        unreachable!()
+                    }
+                    service::Event::TransactionsReceived {
+                        chain_id,
+                        peer_id,
+                        transactions,
+                    } => {
+                        inner.log_callback.log(
+                            LogLevel::Debug,
+                            format!(
+                                "transactions-received; peer_id={}; chain={}; num_transactions={}",
+                                peer_id,
+                                inner.chains[&chain_id].log_name,
+                                transactions.len()
+                            ),
+                        );
+                        break Some(Event::TransactionsReceived {
+                            chain_id,
+                            peer_id,
+                            transactions,
+                        });
+                    }
                     service::Event::GrandpaNeighborPacket {
                         chain_id,
                         peer_id,
@@ -1290,6 +1521,56 @@ async fn background_task(mut inner: Inner) {
                             ),
                         );
                     }
+                    service::Event::GrandpaVoteMessage { .. }
+                    | service::Event::GrandpaCatchUpRequest { .. }
+                    | service::Event::GrandpaCatchUp { .. } => {
+                        // The full node doesn't take part in GrandPa voting rounds yet.
+                        // TODO: implement a GrandPa voter
+                    }
+                    service::Event::NotificationsDropped {
+                        peer_id,
+                        chain_id,
+                        protocol,
+                        count,
+                    } => {
+                        inner.log_callback.log(
+                            LogLevel::Debug,
+                            format!(
+                                "notifications-dropped; peer_id={}; chain={}; protocol={:?}; count={}",
+                                peer_id, inner.chains[&chain_id].log_name, protocol, count
+                            ),
+                        );
+                    }
+                    service::Event::GossipOutQueueDrained {
+                        peer_id,
+                        chain_id,
+                        protocol,
+                    } => {
+                        // Notifications that can't be queued are simply discarded, and there is
+                        // consequently nothing to resend when a queue drains.
+                        inner.log_callback.log(
+                            LogLevel::Debug,
+                            format!(
+                                "notifications-out-queue-drained; peer_id={}; chain={}; protocol={:?}",
+                                peer_id, inner.chains[&chain_id].log_name, protocol
+                            ),
+                        );
+                    }
+                    service::Event::PingResult { peer_id, rtt } => {
+                        inner.log_callback.log(
+                            LogLevel::Debug,
+                            format!("ping-result; peer_id={}; rtt={:?}", peer_id, rtt),
+                        );
+                    }
+                    service::Event::InboundProtocolError { peer_id, error } => {
+                        inner.log_callback.log(
+                            LogLevel::Debug,
+                            format!(
+                                "inbound-protocol-error; peer_id={}; error={}",
+                                peer_id, error
+                            ),
+                        );
+                    }
                     service::Event::ProtocolError { peer_id, error } => {
                         inner.log_callback.log(
                             LogLevel::Warn,
@@ -1341,7 +1622,7 @@ async fn background_task(mut inner: Inner) {
             }
 
             // TODO: doc
-            for chain_id in inner.chains.keys() {
+            for (chain_id, chain) in inner.chains.iter_mut() {
                 loop {
                     // TODO: 25 is an arbitrary constant, make configurable
                     if inner
@@ -1352,19 +1633,56 @@ async fn background_task(mut inner: Inner) {
                         break;
                     }
 
-                    let peer_id = match inner.peering_strategy.assign_slot(chain_id, &Instant::now()) {
+                    let now = Instant::now();
+                    let peer_id = match inner.peering_strategy.assign_slot(chain_id, &now) {
                         basic_peering_strategy::AssignSlotOutcome::Assigned(peer_id) => {
                             peer_id.clone()
                         }
                         basic_peering_strategy::AssignSlotOutcome::AllPeersBanned { .. }  // TODO: handle `AllPeersBanned` by waking up when a ban expires
-                        | basic_peering_strategy::AssignSlotOutcome::NoPeer => break,
+                        | basic_peering_strategy::AssignSlotOutcome::NoPeer => {
+                            // All the known peers (such as the bootnodes) are either already
+                            // assigned a slot or banned, which typically happens when they are
+                            // unreachable. Fall back to the nodes seen during previous runs.
+                            // These nodes are added by small batches in order to not try to dial
+                            // all of them at once.
+                            // Note that there is no timer dedicated to waking up when the next
+                            // batch can be added, and we rely instead on other events (such as
+                            // failed dialing attempts or discoveries) happening in the meanwhile.
+                            if chain.persisted_peers.is_empty()
+                                || now < chain.next_persisted_peers_batch
+                            {
+                                break;
+                            }
+
+                            let batch = chain.persisted_peers.split_off(
+                                chain
+                                    .persisted_peers
+                                    .len()
+                                    .saturating_sub(PERSISTED_PEERS_BATCH_SIZE),
+                            );
+                            inner.log_callback.log(
+                                LogLevel::Debug,
+                                format!(
+                                    "persisted-peers-batch; chain={}; num_entries={}; remaining={}",
+                                    chain.log_name,
+                                    batch.len(),
+                                    chain.persisted_peers.len()
+                                ),
+                            );
+                            for (peer_id, addr) in batch {
+                                inner.peering_strategy.insert_address(&peer_id, addr.into_vec());
+                                inner.peering_strategy.insert_chain_peer(*chain_id, peer_id);
+                            }
+                            chain.next_persisted_peers_batch = now + PERSISTED_PEERS_BATCH_INTERVAL;
+                            continue;
+                        }
                     };
 
                     inner.log_callback.log(
                         LogLevel::Debug,
                         format!(
                             "slot-assigned; peer_id={}; chain={}",
-                            peer_id, inner.chains[chain_id].log_name
+                            peer_id, chain.log_name
                         ),
                     );
 
@@ -1596,6 +1914,11 @@ async fn background_task(mut inner: Inner) {
                         ) {
                             Ok(s) => s,
                             Err(service::StartRequestError::NoConnection) => unreachable!(),
+                            Err(service::StartRequestError::ProtocolNotAvailable) => {
+                                // The target has recently refused the Kademlia protocol.
+                                // Discovery is skipped for this chain until next time.
+                                continue;
+                            }
                         };
 
                         let _prev_value = inner
@@ -1659,6 +1982,13 @@ async fn background_task(mut inner: Inner) {
                     Err(service::StartRequestError::NoConnection) => {
                         let _ = result_tx.send(Err(BlocksRequestError::NoConnection));
                     }
+                    Err(service::StartRequestError::ProtocolNotAvailable) => {
+                        let _ = result_tx.send(Err(BlocksRequestError::Request(
+                            service::BlocksRequestError::Request(service::RequestError::Substream(
+                                connection::established::RequestError::ProtocolNotAvailable,
+                            )),
+                        )));
+                    }
                 }
             }
             ToBackground::ForegroundGetNumConnections { result_tx } => {
@@ -1704,8 +2034,8 @@ async fn background_task(mut inner: Inner) {
 async fn blocks_request_response(
     database: &database_thread::DatabaseThread,
     block_number_bytes: usize,
-    config: protocol::BlocksRequestConfig,
-) -> Result<Vec<protocol::BlockData>, full_sqlite::CorruptedError> {
+    config: codec::BlocksRequestConfig,
+) -> Result<Vec<codec::BlockData>, full_sqlite::CorruptedError> {
     database
         .with_database(move |database| {
             let num_blocks = cmp::min(
@@ -1722,8 +2052,8 @@ async fn blocks_request_response(
                 }
 
                 let hash = match next_block {
-                    protocol::BlocksRequestConfigStart::Hash(hash) => hash,
-                    protocol::BlocksRequestConfigStart::Number(number) => {
+                    codec::BlocksRequestConfigStart::Hash(hash) => hash,
+                    codec::BlocksRequestConfigStart::Number(number) => {
                         // TODO: naive block selection ; should choose the best chain instead
                         match database.block_hash_by_number(number)?.next() {
                             Some(h) => h,
@@ -1740,17 +2070,17 @@ async fn blocks_request_response(
                 next_block = {
                     let decoded = header::decode(&header, block_number_bytes).unwrap();
                     match config.direction {
-                        protocol::BlocksRequestDirection::Ascending => {
+                        codec::BlocksRequestDirection::Ascending => {
                             // TODO: right now, since we don't necessarily pick the best chain in `block_hash_by_number`, it is possible that the next block doesn't have the current block as parent
-                            protocol::BlocksRequestConfigStart::Number(decoded.number + 1)
+                            codec::BlocksRequestConfigStart::Number(decoded.number + 1)
                         }
-                        protocol::BlocksRequestDirection::Descending => {
-                            protocol::BlocksRequestConfigStart::Hash(*decoded.parent_hash)
+                        codec::BlocksRequestDirection::Descending => {
+                            codec::BlocksRequestConfigStart::Hash(*decoded.parent_hash)
                         }
                     }
                 };
 
-                output.push(protocol::BlockData {
+                output.push(codec::BlockData {
                     hash,
                     header: if config.fields.header {
                         Some(header)
@@ -33,6 +33,7 @@ fn basic_block_generated() {
                 sqlite_cache_size: 256 * 1024 * 1024,
                 keystore_path: None,
                 json_rpc_listen: None,
+                manual_seal: false,
             },
             relay_chain: None,
             libp2p_key: Box::new([0; 32]),
@@ -73,3 +74,58 @@ fn basic_block_generated() {
         }
     });
 }
+
+#[test]
+fn manual_seal_create_block() {
+    smol::block_on(async move {
+        let client = smoldot_full_node::start(smoldot_full_node::Config {
+            chain: smoldot_full_node::ChainConfig {
+                chain_spec: (&include_bytes!("./substrate-node-template.json")[..]).into(),
+                additional_bootnodes: Vec::new(),
+                keystore_memory: vec![smoldot::identity::seed_phrase::decode_sr25519_private_key(
+                    "//Alice",
+                )
+                .unwrap()],
+                sqlite_database_path: None,
+                sqlite_cache_size: 256 * 1024 * 1024,
+                keystore_path: None,
+                json_rpc_listen: None,
+                manual_seal: true,
+            },
+            relay_chain: None,
+            libp2p_key: Box::new([0; 32]),
+            listen_addresses: Vec::new(),
+            tasks_executor: Arc::new(|task| smol::spawn(task).detach()),
+            log_callback: Arc::new(move |_, _| {}),
+            jaeger_agent: None,
+        })
+        .await
+        .unwrap();
+
+        client.send_json_rpc_request(
+            r#"{"jsonrpc":"2.0","id":1,"method":"engine_createBlock","params":[true,false]}"#
+                .to_owned(),
+        );
+
+        let response = client.next_json_rpc_response().await;
+        let (_, created_block) = json_rpc::parse::parse_response(&response)
+            .unwrap()
+            .into_success()
+            .unwrap();
+        let hash = serde_json::from_str::<serde_json::Value>(created_block).unwrap()["hash"]
+            .as_str()
+            .unwrap()
+            .to_owned();
+
+        client.send_json_rpc_request(format!(
+            r#"{{"jsonrpc":"2.0","id":2,"method":"engine_finalizeBlock","params":["{hash}"]}}"#
+        ));
+
+        let response = client.next_json_rpc_response().await;
+        let (_, finalized) = json_rpc::parse::parse_response(&response)
+            .unwrap()
+            .into_success()
+            .unwrap();
+        assert_eq!(finalized, "true");
+    });
+}
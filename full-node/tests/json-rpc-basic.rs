@@ -30,6 +30,7 @@ fn send_request_errs_if_malformed() {
                 sqlite_cache_size: 256 * 1024 * 1024,
                 keystore_path: None,
                 json_rpc_listen: None,
+                manual_seal: false,
             },
             relay_chain: None,
             libp2p_key: Box::new([0; 32]),
@@ -62,6 +63,7 @@ fn send_request_works_if_unknown_request() {
                 sqlite_cache_size: 256 * 1024 * 1024,
                 keystore_path: None,
                 json_rpc_listen: None,
+                manual_seal: false,
             },
             relay_chain: None,
             libp2p_key: Box::new([0; 32]),
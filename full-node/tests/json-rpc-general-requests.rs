@@ -28,6 +28,7 @@ async fn start_client() -> smoldot_full_node::Client {
             sqlite_cache_size: 256 * 1024 * 1024,
             keystore_path: None,
             json_rpc_listen: None,
+            manual_seal: false,
         },
         relay_chain: None,
         libp2p_key: Box::new([0; 32]),
@@ -40,6 +41,90 @@ async fn start_client() -> smoldot_full_node::Client {
     .unwrap()
 }
 
+#[test]
+fn archive_unstable_genesis_hash_and_height() {
+    smol::block_on(async move {
+        let client = start_client().await;
+
+        client.send_json_rpc_request(
+            r#"{"jsonrpc":"2.0","id":1,"method":"archive_unstable_genesisHash","params":[]}"#
+                .to_owned(),
+        );
+        let response_raw = client.next_json_rpc_response().await;
+        let (_, result_json) = json_rpc::parse::parse_response(&response_raw)
+            .unwrap()
+            .into_success()
+            .unwrap();
+        assert_eq!(
+            serde_json::from_str::<String>(result_json).unwrap(),
+            "0x6bf30d04495c16ef053de4ac74eac35dfd6473e4907810f450bea1b976ac518f"
+        );
+
+        client.send_json_rpc_request(
+            r#"{"jsonrpc":"2.0","id":1,"method":"archive_unstable_finalizedHeight","params":[]}"#
+                .to_owned(),
+        );
+        let response_raw = client.next_json_rpc_response().await;
+        let (_, result_json) = json_rpc::parse::parse_response(&response_raw)
+            .unwrap()
+            .into_success()
+            .unwrap();
+        assert_eq!(serde_json::from_str::<u64>(result_json).unwrap(), 0);
+
+        client.send_json_rpc_request(
+            r#"{"jsonrpc":"2.0","id":1,"method":"archive_unstable_hashByHeight","params":[0]}"#
+                .to_owned(),
+        );
+        let response_raw = client.next_json_rpc_response().await;
+        let (_, result_json) = json_rpc::parse::parse_response(&response_raw)
+            .unwrap()
+            .into_success()
+            .unwrap();
+        assert_eq!(
+            serde_json::from_str::<Vec<String>>(result_json).unwrap(),
+            &["0x6bf30d04495c16ef053de4ac74eac35dfd6473e4907810f450bea1b976ac518f"]
+        );
+
+        client.send_json_rpc_request(
+            r#"{"jsonrpc":"2.0","id":1,"method":"archive_unstable_hashByHeight","params":[10000]}"#
+                .to_owned(),
+        );
+        let response_raw = client.next_json_rpc_response().await;
+        let (_, result_json) = json_rpc::parse::parse_response(&response_raw)
+            .unwrap()
+            .into_success()
+            .unwrap();
+        assert!(serde_json::from_str::<Vec<String>>(result_json)
+            .unwrap()
+            .is_empty());
+    });
+}
+
+#[test]
+fn archive_unstable_storage_values() {
+    smol::block_on(async move {
+        let client = start_client().await;
+
+        // Query `:code`, which must always be present, alongside with a key that doesn't exist.
+        client.send_json_rpc_request(
+            r#"{"jsonrpc":"2.0","id":1,"method":"archive_unstable_storage","params":["0x6bf30d04495c16ef053de4ac74eac35dfd6473e4907810f450bea1b976ac518f",[{"key":"0x3a636f6465","type":"hash"},{"key":"0xdeadbeef","type":"value"}]]}"#
+                .to_owned(),
+        );
+        let response_raw = client.next_json_rpc_response().await;
+        let (_, result_json) = json_rpc::parse::parse_response(&response_raw)
+            .unwrap()
+            .into_success()
+            .unwrap();
+        let decoded =
+            serde_json::from_str::<json_rpc::methods::ArchiveStorageResult>(result_json).unwrap();
+        assert_eq!(decoded.discarded_items, 0);
+        assert_eq!(decoded.result.len(), 1);
+        assert_eq!(decoded.result[0].key.0, b":code");
+        assert_eq!(decoded.result[0].hash.as_ref().unwrap().0.len(), 32);
+        assert!(decoded.result[0].value.is_none());
+    });
+}
+
 #[test]
 fn chain_spec_v1_chain_name() {
     smol::block_on(async move {
@@ -430,6 +515,35 @@ fn state_get_keys_paged_unknown_block() {
     });
 }
 
+#[test]
+fn rpc_methods() {
+    smol::block_on(async move {
+        let client = start_client().await;
+
+        client.send_json_rpc_request(
+            r#"{"jsonrpc":"2.0","id":1,"method":"rpc_methods","params":[]}"#.to_owned(),
+        );
+
+        let response_raw = client.next_json_rpc_response().await;
+        let (_, result_json) = json_rpc::parse::parse_response(&response_raw)
+            .unwrap()
+            .into_success()
+            .unwrap();
+        let methods = serde_json::from_str::<serde_json::Value>(result_json).unwrap()["methods"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|m| m.as_str().unwrap().to_owned())
+            .collect::<Vec<_>>();
+        assert!(methods.iter().any(|m| m == "rpc_methods"));
+        assert!(methods.iter().any(|m| m == "system_chain"));
+        // Manual seal is disabled.
+        assert!(!methods.iter().any(|m| m == "engine_createBlock"));
+        // Not implemented by the full node.
+        assert!(!methods.iter().any(|m| m == "state_getPairs"));
+    });
+}
+
 #[test]
 fn system_chain() {
     smol::block_on(async move {
@@ -38,6 +38,10 @@ libfuzzer_sys::fuzz_target!(|data: &[u8]| {
             // This timeout doesn't matter as we pass dummy time values.
             handshake_timeout: Duration::from_secs(5),
             ping_protocol: "ping".into(),
+            ping_interval: Duration::from_secs(20),
+            ping_timeout: Duration::from_secs(10),
+            max_queued_notifications_bytes: 1024 * 1024,
+            substreams_lifecycle_events: false,
         });
 
     // We use the first element of ̀`data` to determine whether we have opened the connection
@@ -18,5 +18,5 @@
 #![no_main]
 
 libfuzzer_sys::fuzz_target!(|params: (&[u8], u8)| {
-    let _ = smoldot::network::protocol::decode_block_announce(params.0, usize::from(params.1) + 1);
+    let _ = smoldot::network::codec::decode_block_announce(params.0, usize::from(params.1) + 1);
 });
@@ -18,8 +18,6 @@
 #![no_main]
 
 libfuzzer_sys::fuzz_target!(|params: (&[u8], u8)| {
-    let _ = smoldot::network::protocol::decode_grandpa_notification(
-        params.0,
-        usize::from(params.1) + 1,
-    );
+    let _ =
+        smoldot::network::codec::decode_grandpa_notification(params.0, usize::from(params.1) + 1);
 });
@@ -18,7 +18,7 @@
 #![no_main]
 
 libfuzzer_sys::fuzz_target!(|params: (&[u8], u8)| {
-    let _ = smoldot::network::protocol::decode_grandpa_warp_sync_response(
+    let _ = smoldot::network::codec::decode_grandpa_warp_sync_response(
         params.0,
         usize::from(params.1) + 1,
     );
@@ -18,5 +18,5 @@
 #![no_main]
 
 libfuzzer_sys::fuzz_target!(|data: &[u8]| {
-    let _ = smoldot::network::protocol::decode_identify_response(data);
+    let _ = smoldot::network::codec::decode_identify_response(data);
 });
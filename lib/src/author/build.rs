@@ -239,9 +239,18 @@ impl AuthoringStart {
             calculate_trie_changes: config.calculate_trie_changes,
         });
 
+        // The runtime verifies that the timestamp inherent corresponds to the slot found in the
+        // pre-runtime digest. As the authoring might start slightly before or after the slot,
+        // for example if the node is overloaded, the timestamp is bounded by the slot.
+        let timestamp = match self.consensus {
+            WaitSlotConsensus::Aura(claim) => config.now_from_unix_epoch.clamp(
+                claim.slot_start_from_unix_epoch,
+                claim.slot_end_from_unix_epoch - Duration::from_millis(1),
+            ),
+        };
+
         let inherent_data = inherents::InherentData {
-            timestamp: u64::try_from(config.now_from_unix_epoch.as_millis())
-                .unwrap_or(u64::max_value()),
+            timestamp: u64::try_from(timestamp.as_millis()).unwrap_or(u64::max_value()),
         };
 
         (Shared {
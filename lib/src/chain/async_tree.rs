@@ -67,6 +67,7 @@
 //!     finalized_async_user_data: "hello",
 //!     retry_after_failed: Duration::from_secs(5),
 //!     blocks_capacity: 32,
+//!     best_block_policy: async_tree::BestBlockPolicy::FollowInput,
 //! });
 //!
 //! // Insert a new best block, child of the finalized block.
@@ -157,6 +158,25 @@ pub struct Config<TAsync> {
     ///
     /// It is legal to pass 0, in which case no memory is pre-allocated.
     pub blocks_capacity: usize,
+
+    /// Strategy to use in order to determine the output best block.
+    pub best_block_policy: BestBlockPolicy,
+}
+
+/// Strategy used by the [`AsyncTree`] in order to determine which block is the output best block.
+///
+/// No matter the policy, the output best block is always either the output finalized block or
+/// a block whose asynchronous operation is finished and that has been reported in the output.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BestBlockPolicy {
+    /// The output best block is the block that has most recently been reported as best block by
+    /// the input, amongst the blocks that are part of the output.
+    FollowInput,
+
+    /// The output best block is the block with the highest height amongst the blocks that are
+    /// part of the output, no matter which block the input considers as best. In case of a tie,
+    /// the block that has most recently been reported as best block by the input is picked.
+    LongestOutputChain,
 }
 
 /// See [the module-level documentation](..).
@@ -188,11 +208,17 @@ pub struct AsyncTree<TNow, TBl, TAsync> {
     /// block.
     output_finalized_block_weight: u32,
 
+    /// Value that would be stored in [`Block::height`] for the output finalized block.
+    output_finalized_block_height: u64,
+
     /// Identifier to assign to the next asynchronous operation.
     next_async_op_id: AsyncOpId,
 
     /// See [`Config::retry_after_failed`].
     retry_after_failed: Duration,
+
+    /// See [`Config::best_block_policy`].
+    best_block_policy: BestBlockPolicy,
 }
 
 impl<TNow, TBl, TAsync> AsyncTree<TNow, TBl, TAsync>
@@ -209,8 +235,10 @@ where
             input_finalized_index: None,
             input_best_block_next_weight: 2,
             output_finalized_block_weight: 1, // `0` is reserved for blocks who are never best.
+            output_finalized_block_height: 0,
             next_async_op_id: AsyncOpId(0),
             retry_after_failed: config.retry_after_failed,
+            best_block_policy: config.best_block_policy,
         }
     }
 
@@ -256,13 +284,16 @@ where
                     },
                 },
                 input_best_block_weight: block.input_best_block_weight,
+                height: block.height,
                 user_data: block.user_data,
             }),
             input_finalized_index: self.input_finalized_index,
             input_best_block_next_weight: self.input_best_block_next_weight,
             output_finalized_block_weight: self.output_finalized_block_weight,
+            output_finalized_block_height: self.output_finalized_block_height,
             next_async_op_id: self.next_async_op_id,
             retry_after_failed: self.retry_after_failed,
+            best_block_policy: self.best_block_policy,
         }
     }
 
@@ -808,6 +839,10 @@ where
             },
         };
 
+        let height = 1 + parent_index.map_or(self.output_finalized_block_height, |idx| {
+            self.non_finalized_blocks.get(idx).unwrap().height
+        });
+
         // Insert the new block.
         self.non_finalized_blocks.insert(
             parent_index,
@@ -815,6 +850,7 @@ where
                 user_data: block,
                 async_op,
                 input_best_block_weight,
+                height,
             },
         )
     }
@@ -963,6 +999,7 @@ where
                     if pruned.index == new_finalized {
                         self.output_finalized_block_weight =
                             pruned.user_data.input_best_block_weight;
+                        self.output_finalized_block_height = pruned.user_data.height;
                         pruned_finalized = Some(pruned);
                         continue;
                     }
@@ -991,46 +1028,9 @@ where
                     pruned_blocks.push((pruned.index, pruned.user_data.user_data, async_op));
                 }
 
-                // Try to advance the output best block to the `Finished` block with the highest
-                // weight.
-                // Weight of the current output best block.
-                let mut current_runtime_service_best_block_weight =
-                    match self.output_best_block_index {
-                        None => self.output_finalized_block_weight,
-                        Some(idx) => {
-                            self.non_finalized_blocks
-                                .get(idx)
-                                .unwrap()
-                                .input_best_block_weight
-                        }
-                    };
-
-                for (node_index, block) in self.non_finalized_blocks.iter_unordered() {
-                    // Check uniqueness of weights.
-                    debug_assert!(
-                        block.input_best_block_weight != current_runtime_service_best_block_weight
-                            || block.input_best_block_weight == 0
-                            || self.output_best_block_index == Some(node_index)
-                    );
-
-                    if block.input_best_block_weight <= current_runtime_service_best_block_weight {
-                        continue;
-                    }
-
-                    if !matches!(
-                        block.async_op,
-                        AsyncOpState::Finished { reported: true, .. }
-                    ) {
-                        continue;
-                    }
-
-                    // Input best can be updated to the block being iterated.
-                    current_runtime_service_best_block_weight = block.input_best_block_weight;
-                    self.output_best_block_index = Some(node_index);
-
-                    // Continue looping, as there might be another block with an even
-                    // higher weight.
-                }
+                // Try to advance the output best block to the best `Finished` block according
+                // to the best block policy.
+                self.output_best_block_index = self.find_output_best_block();
 
                 let pruned_finalized = pruned_finalized.unwrap();
                 let former_finalized_async_op_user_data = match pruned_finalized.user_data.async_op
@@ -1084,19 +1084,8 @@ where
             }
 
             // Try to mark the best we're about to report as best block, if possible.
-            let is_new_best = self
-                .non_finalized_blocks
-                .get(node_index)
-                .unwrap()
-                .input_best_block_weight
-                > self
-                    .output_best_block_index
-                    .map_or(self.output_finalized_block_weight, |idx| {
-                        self.non_finalized_blocks
-                            .get(idx)
-                            .unwrap()
-                            .input_best_block_weight
-                    });
+            let is_new_best = self.best_block_key(Some(node_index))
+                > self.best_block_key(self.output_best_block_index);
             if is_new_best {
                 debug_assert_ne!(self.output_best_block_index, Some(node_index));
                 self.output_best_block_index = Some(node_index);
@@ -1121,58 +1110,73 @@ where
 
         // Try to advance the output best block.
         {
-            let mut best_block_updated = false;
-
-            // Try to advance the output best block to the `Finished` block with the highest
-            // weight.
-            // Weight of the current output best block.
-            let mut current_runtime_service_best_block_weight = match self.output_best_block_index {
-                None => self.output_finalized_block_weight,
-                Some(idx) => {
-                    self.non_finalized_blocks
-                        .get(idx)
-                        .unwrap()
-                        .input_best_block_weight
-                }
-            };
+            // Try to advance the output best block to the best `Finished` block according to
+            // the best block policy.
+            let new_best_block = self.find_output_best_block();
+            if new_best_block != self.output_best_block_index {
+                self.output_best_block_index = new_best_block;
+                return Some(OutputUpdate::BestBlockChanged {
+                    best_block_index: self.output_best_block_index,
+                });
+            }
+        }
 
-            for (node_index, block) in self.non_finalized_blocks.iter_unordered() {
-                // Check uniqueness of weights.
-                debug_assert!(
-                    block.input_best_block_weight != current_runtime_service_best_block_weight
-                        || block.input_best_block_weight == 0
-                        || self.output_best_block_index == Some(node_index)
-                );
+        // Nothing to do.
+        None
+    }
 
-                if block.input_best_block_weight <= current_runtime_service_best_block_weight {
-                    continue;
-                }
+    /// Returns the block, amongst the output finalized block and the blocks that have been
+    /// reported in the output, that should be the output best block according to
+    /// [`AsyncTree::best_block_policy`].
+    fn find_output_best_block(&self) -> Option<NodeIndex> {
+        let mut best_block = None;
+        let mut best_block_key = self.best_block_key(None);
+
+        for (node_index, block) in self.non_finalized_blocks.iter_unordered() {
+            if !matches!(
+                block.async_op,
+                AsyncOpState::Finished { reported: true, .. }
+            ) {
+                continue;
+            }
 
-                if !matches!(
-                    block.async_op,
-                    AsyncOpState::Finished { reported: true, .. }
-                ) {
-                    continue;
-                }
+            let key = self.best_block_key(Some(node_index));
 
-                // Input best can be updated to the block being iterated.
-                current_runtime_service_best_block_weight = block.input_best_block_weight;
-                self.output_best_block_index = Some(node_index);
-                best_block_updated = true;
+            // Check uniqueness of weights.
+            debug_assert!(key != best_block_key || block.input_best_block_weight == 0);
 
-                // Continue looping, as there might be another block with an even
-                // higher weight.
+            if key > best_block_key {
+                best_block = Some(node_index);
+                best_block_key = key;
             }
+        }
 
-            if best_block_updated {
-                return Some(OutputUpdate::BestBlockChanged {
-                    best_block_index: self.output_best_block_index,
-                });
+        best_block
+    }
+
+    /// Returns a value that can be compared in order to determine which of two blocks is the
+    /// better candidate for the output best block. Pass `None` for the output finalized block.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the [`NodeIndex`] is invalid.
+    ///
+    fn best_block_key(&self, node_index: Option<NodeIndex>) -> (u64, u32) {
+        let (height, weight) = match node_index {
+            Some(idx) => {
+                let block = self.non_finalized_blocks.get(idx).unwrap();
+                (block.height, block.input_best_block_weight)
             }
-        }
+            None => (
+                self.output_finalized_block_height,
+                self.output_finalized_block_weight,
+            ),
+        };
 
-        // Nothing to do.
-        None
+        match self.best_block_policy {
+            BestBlockPolicy::FollowInput => (0, weight),
+            BestBlockPolicy::LongestOutputChain => (height, weight),
+        }
     }
 }
 
@@ -1272,6 +1276,11 @@ struct Block<TNow, TBl, TAsync> {
     /// A block with a higher value here has been reported by the input as the best block
     /// more recently than a block with a lower value. `0` means never reported as best block.
     input_best_block_weight: u32,
+
+    /// Number of ancestors of this block, counting from the output finalized block at the time
+    /// when the [`AsyncTree`] was created. Only meaningful when compared with the height of
+    /// other blocks.
+    height: u64,
 }
 
 enum AsyncOpState<TNow, TAsync> {
@@ -1320,3 +1329,71 @@ enum NextNecessaryAsyncOpInternal<TNow> {
 }
 
 // TODO: needs tests
+
+#[cfg(test)]
+mod tests {
+    use super::{AsyncTree, BestBlockPolicy, Config};
+    use core::time::Duration;
+
+    fn new_tree(best_block_policy: BestBlockPolicy) -> AsyncTree<Duration, &'static str, ()> {
+        AsyncTree::new(Config {
+            finalized_async_user_data: (),
+            retry_after_failed: Duration::from_secs(5),
+            blocks_capacity: 16,
+            best_block_policy,
+        })
+    }
+
+    fn advance_output(tree: &mut AsyncTree<Duration, &'static str, ()>) {
+        while tree.try_advance_output().is_some() {}
+    }
+
+    #[test]
+    fn follow_input_ignores_longer_fork() {
+        let mut tree = new_tree(BestBlockPolicy::FollowInput);
+        let a1 = tree.input_insert_block("a1", None, true, false);
+        let _a2 = tree.input_insert_block("a2", Some(a1), true, false);
+        let b1 = tree.input_insert_block("b1", None, true, true);
+        advance_output(&mut tree);
+        assert_eq!(tree.output_best_block_index().map(|(idx, _)| idx), Some(b1));
+    }
+
+    #[test]
+    fn longest_output_chain_picks_longer_fork() {
+        let mut tree = new_tree(BestBlockPolicy::LongestOutputChain);
+        let a1 = tree.input_insert_block("a1", None, true, false);
+        let a2 = tree.input_insert_block("a2", Some(a1), true, false);
+        let _b1 = tree.input_insert_block("b1", None, true, true);
+        advance_output(&mut tree);
+        assert_eq!(tree.output_best_block_index().map(|(idx, _)| idx), Some(a2));
+    }
+
+    #[test]
+    fn longest_output_chain_tie_follows_input() {
+        let mut tree = new_tree(BestBlockPolicy::LongestOutputChain);
+        let a1 = tree.input_insert_block("a1", None, true, false);
+        let b1 = tree.input_insert_block("b1", None, true, true);
+        advance_output(&mut tree);
+        assert_eq!(tree.output_best_block_index().map(|(idx, _)| idx), Some(b1));
+
+        tree.input_set_best_block(Some(a1));
+        advance_output(&mut tree);
+        assert_eq!(tree.output_best_block_index().map(|(idx, _)| idx), Some(a1));
+    }
+
+    #[test]
+    fn longest_output_chain_after_finalization() {
+        let mut tree = new_tree(BestBlockPolicy::LongestOutputChain);
+        let a1 = tree.input_insert_block("a1", None, true, true);
+        let a2 = tree.input_insert_block("a2", Some(a1), true, false);
+        let b2 = tree.input_insert_block("b2", Some(a1), true, false);
+        let b3 = tree.input_insert_block("b3", Some(b2), true, false);
+        advance_output(&mut tree);
+        assert_eq!(tree.output_best_block_index().map(|(idx, _)| idx), Some(b3));
+
+        // After `a1` is finalized, heights remain comparable with the new finalized block.
+        tree.input_finalize(a1, a2);
+        advance_output(&mut tree);
+        assert_eq!(tree.output_best_block_index().map(|(idx, _)| idx), Some(b3));
+    }
+}
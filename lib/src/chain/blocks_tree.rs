@@ -126,6 +126,14 @@ pub struct NonFinalizedTree<T> {
     finalized_consensus: FinalizedConsensus,
     /// Best score of the finalized block.
     finalized_best_score: BestScore,
+    /// Grandpa authorities set ID and list of authorities that have finalized
+    /// [`NonFinalizedTree::finalized_block_header`]. Used in order to detect conflicting
+    /// justifications. `None` if unknown, which is the case for the finalized block passed at
+    /// initialization, or if the chain doesn't use Grandpa.
+    finalized_block_grandpa_voters: Option<(u64, Arc<[header::GrandpaAuthority]>)>,
+    /// If `Some`, a valid justification targeting a block that conflicts with the finalized
+    /// block has been verified. No block can be finalized anymore through finality proofs.
+    finality_equivocation: Option<FinalityEquivocation>,
 
     /// Container for non-finalized blocks.
     blocks: fork_tree::ForkTree<Block<T>>,
@@ -151,8 +159,17 @@ pub struct NonFinalizedTree<T> {
     block_number_bytes: usize,
     /// See [`Config::allow_unknown_consensus_engines`].
     allow_unknown_consensus_engines: bool,
+    /// Hashes of the headers whose seal signature has been verified by
+    /// [`NonFinalizedTree::verify_headers_batch`] but that haven't been inserted in the tree
+    /// yet, associated with the public key of the signer.
+    verified_seal_signatures: HashMap<[u8; 32], [u8; 32], fnv::FnvBuildHasher>,
 }
 
+/// Maximum number of entries in [`NonFinalizedTree::verified_seal_signatures`]. The container is
+/// cleared when this limit would be exceeded, in order to not accumulate the signatures of
+/// headers that are never inserted.
+const MAX_VERIFIED_SEAL_SIGNATURES: usize = 8192;
+
 impl<T> NonFinalizedTree<T> {
     /// Initializes a new queue.
     ///
@@ -213,6 +230,8 @@ impl<T> NonFinalizedTree<T> {
                 num_secondary_slots: 0,
                 insertion_counter: 0,
             },
+            finalized_block_grandpa_voters: None,
+            finality_equivocation: None,
             blocks: fork_tree::ForkTree::with_capacity(config.blocks_capacity),
             blocks_insertion_counter: 1,
             blocks_by_hash: hashbrown::HashMap::with_capacity_and_hasher(
@@ -223,6 +242,7 @@ impl<T> NonFinalizedTree<T> {
             blocks_trigger_gp_change: BTreeSet::new(),
             block_number_bytes: config.block_number_bytes,
             allow_unknown_consensus_engines: config.allow_unknown_consensus_engines,
+            verified_seal_signatures: HashMap::with_capacity_and_hasher(0, Default::default()),
         }
     }
 
@@ -232,6 +252,7 @@ impl<T> NonFinalizedTree<T> {
         self.blocks_by_hash.clear();
         self.blocks_by_best_score.clear();
         self.blocks_trigger_gp_change.clear();
+        self.verified_seal_signatures.clear();
     }
 
     /// Returns true if there isn't any non-finalized block in the chain.
@@ -57,6 +57,17 @@ impl<T> NonFinalizedTree<T> {
             })
     }
 
+    /// Returns the finality equivocation that has been detected, if any.
+    ///
+    /// If `Some`, a valid justification targeting a block that conflicts with the finalized
+    /// block has been verified. This means that the finality mechanism of the chain is broken,
+    /// and [`NonFinalizedTree::verify_justification`] and
+    /// [`NonFinalizedTree::verify_grandpa_commit_message`] will from now on always return
+    /// [`FinalityVerifyError::FinalityFrozen`].
+    pub fn finality_equivocation(&self) -> Option<&FinalityEquivocation> {
+        self.finality_equivocation.as_ref()
+    }
+
     /// Verifies the given justification.
     ///
     /// The verification is performed in the context of the chain. In particular, the
@@ -65,6 +76,11 @@ impl<T> NonFinalizedTree<T> {
     /// If the verification succeeds, a [`FinalityApply`] object will be returned which can
     /// be used to apply the finalization.
     ///
+    /// If the justification targets a block at the same height as the finalized block but with
+    /// a different hash, it is verified against the authorities that have finalized the
+    /// finalized block. If it is valid, [`JustificationVerifyError::ConflictingFinality`] is
+    /// returned and finality is frozen. See [`NonFinalizedTree::finality_equivocation`].
+    ///
     /// A randomness seed must be provided and will be used during the verification. Note that the
     /// verification is nonetheless deterministic.
     // TODO: expand the documentation about how blocks with authorities changes have to be finalized before any further block can be finalized
@@ -83,6 +99,42 @@ impl<T> NonFinalizedTree<T> {
                 )
                 .map_err(JustificationVerifyError::InvalidJustification)?;
 
+                // If the justification targets a block that conflicts with the finalized block,
+                // check whether the authorities that have finalized the finalized block have
+                // also signed it.
+                if self.finality_equivocation.is_none()
+                    && decoded.target_number == self.finalized_block_number
+                    && *decoded.target_hash != self.finalized_block_hash
+                {
+                    if let Some((authorities_set_id, authorities_list)) =
+                        &self.finalized_block_grandpa_voters
+                    {
+                        let conflicting_block_hash = *decoded.target_hash;
+                        justification::verify::verify(justification::verify::Config {
+                            justification: decoded,
+                            block_number_bytes: self.block_number_bytes,
+                            authorities_set_id: *authorities_set_id,
+                            authorities_list: authorities_list.iter().map(|a| &a.public_key[..]),
+                            randomness_seed,
+                        })
+                        .map_err(JustificationVerifyError::VerificationFailed)?;
+
+                        self.finality_equivocation = Some(FinalityEquivocation {
+                            block_number: self.finalized_block_number,
+                            finalized_block_hash: self.finalized_block_hash,
+                            conflicting_block_hash,
+                            proof: FinalityEquivocationProof::Justification(
+                                scale_encoded_justification.to_vec(),
+                            ),
+                        });
+
+                        return Err(JustificationVerifyError::ConflictingFinality {
+                            block_number: self.finalized_block_number,
+                            conflicting_block_hash,
+                        });
+                    }
+                }
+
                 // Delegate the first step to the other function.
                 let (block_index, authorities_set_id, authorities_list) = self
                     .verify_grandpa_finality_inner(decoded.target_hash, decoded.target_number)
@@ -116,6 +168,11 @@ impl<T> NonFinalizedTree<T> {
     /// If the verification succeeds, a [`FinalityApply`] object will be returned which can
     /// be used to apply the finalization.
     ///
+    /// If the commit targets a block at the same height as the finalized block but with a
+    /// different hash, it is verified against the authorities that have finalized the finalized
+    /// block. If it is valid, [`CommitVerifyError::ConflictingFinality`] is returned and finality
+    /// is frozen. See [`NonFinalizedTree::finality_equivocation`].
+    ///
     /// A randomness seed must be provided and will be used during the verification. Note that the
     /// verification is nonetheless deterministic.
     pub fn verify_grandpa_commit_message(
@@ -134,6 +191,62 @@ impl<T> NonFinalizedTree<T> {
         )
         .map_err(|_| CommitVerifyError::InvalidCommit)?;
 
+        // If the commit targets a block that conflicts with the finalized block, check whether
+        // the authorities that have finalized the finalized block have also signed it.
+        if self.finality_equivocation.is_none()
+            && decoded_commit.message.target_number == self.finalized_block_number
+            && *decoded_commit.message.target_hash != self.finalized_block_hash
+        {
+            if let Some((authorities_set_id, authorities_list)) =
+                &self.finalized_block_grandpa_voters
+            {
+                let mut verification =
+                    grandpa::commit::verify::verify(grandpa::commit::verify::Config {
+                        commit: scale_encoded_commit,
+                        block_number_bytes: self.block_number_bytes,
+                        expected_authorities_set_id: *authorities_set_id,
+                        num_authorities: u32::try_from(authorities_list.len()).unwrap(),
+                        randomness_seed,
+                    });
+
+                loop {
+                    match verification {
+                        grandpa::commit::verify::InProgress::Finished(Ok(())) => {
+                            let conflicting_block_hash = *decoded_commit.message.target_hash;
+                            self.finality_equivocation = Some(FinalityEquivocation {
+                                block_number: self.finalized_block_number,
+                                finalized_block_hash: self.finalized_block_hash,
+                                conflicting_block_hash,
+                                proof: FinalityEquivocationProof::GrandpaCommit(
+                                    scale_encoded_commit.to_vec(),
+                                ),
+                            });
+
+                            return Err(CommitVerifyError::ConflictingFinality {
+                                block_number: self.finalized_block_number,
+                                conflicting_block_hash,
+                            });
+                        }
+                        // The descendants of the conflicting block are never known, as they have
+                        // been pruned. A commit that can't be verified without them doesn't
+                        // prove anything and is handled below like any other commit.
+                        grandpa::commit::verify::InProgress::FinishedUnknown => break,
+                        grandpa::commit::verify::InProgress::Finished(Err(error)) => {
+                            return Err(CommitVerifyError::VerificationFailed(error))
+                        }
+                        grandpa::commit::verify::InProgress::IsAuthority(is_authority) => {
+                            let to_find = is_authority.authority_public_key();
+                            let result = authorities_list.iter().any(|a| a.public_key == *to_find);
+                            verification = is_authority.resume(result);
+                        }
+                        grandpa::commit::verify::InProgress::IsParent(is_parent) => {
+                            verification = is_parent.resume(None);
+                        }
+                    }
+                }
+            }
+        }
+
         // Delegate the first step to the other function.
         let (block_index, expected_authorities_set_id, authorities_list) = self
             .verify_grandpa_finality_inner(
@@ -244,6 +357,10 @@ impl<T> NonFinalizedTree<T> {
                 finalized_scheduled_change,
                 finalized_triggered_authorities,
             } => {
+                if self.finality_equivocation.is_some() {
+                    return Err(FinalityVerifyError::FinalityFrozen);
+                }
+
                 match target_number.cmp(&self.finalized_block_number) {
                     cmp::Ordering::Equal if *target_hash == self.finalized_block_hash => {
                         return Err(FinalityVerifyError::EqualToFinalized)
@@ -343,6 +460,18 @@ impl<T> NonFinalizedTree<T> {
                     .as_ref()
                     .map_or(true, |(n, _)| *n > new_finalized_block.number));
 
+                // Keep track of the authorities that are supposed to finalize the new finalized
+                // block, in order to later be able to detect conflicting justifications. This
+                // mirrors what `verify_grandpa_finality_inner` does.
+                self.finalized_block_grandpa_voters = Some((
+                    *after_finalized_block_authorities_set_id,
+                    finalized_scheduled_change
+                        .as_ref()
+                        .filter(|(trigger_height, _)| *trigger_height < new_finalized_block.number)
+                        .map_or(&*finalized_triggered_authorities, |(_, list)| list)
+                        .clone(),
+                ));
+
                 *after_finalized_block_authorities_set_id = *after_block_authorities_set_id;
                 *finalized_triggered_authorities = triggered_authorities.clone();
                 *finalized_scheduled_change = scheduled_change.clone();
@@ -493,6 +622,19 @@ pub enum JustificationVerifyError {
     /// Error while verifying the finality in the context of the chain.
     #[display(fmt = "{_0}")]
     FinalityVerify(FinalityVerifyError),
+    /// The justification is valid and targets a block at the same height as the finalized
+    /// block but with a different hash. Finality is now frozen.
+    ///
+    /// See [`NonFinalizedTree::finality_equivocation`].
+    #[display(
+        fmt = "Valid justification for block #{block_number} conflicts with the finalized block"
+    )]
+    ConflictingFinality {
+        /// Number of the finalized block and of the conflicting block.
+        block_number: u64,
+        /// Hash of the block targeted by the justification.
+        conflicting_block_hash: [u8; 32],
+    },
 }
 
 /// Error that can happen when verifying a Grandpa commit.
@@ -517,6 +659,17 @@ pub enum CommitVerifyError {
     /// The commit verification has failed. The commit is invalid and should be thrown away.
     #[display(fmt = "{_0}")]
     VerificationFailed(grandpa::commit::verify::Error),
+    /// The commit is valid and targets a block at the same height as the finalized block but
+    /// with a different hash. Finality is now frozen.
+    ///
+    /// See [`NonFinalizedTree::finality_equivocation`].
+    #[display(fmt = "Valid commit for block #{block_number} conflicts with the finalized block")]
+    ConflictingFinality {
+        /// Number of the finalized block and of the conflicting block.
+        block_number: u64,
+        /// Hash of the block targeted by the commit.
+        conflicting_block_hash: [u8; 32],
+    },
 }
 
 /// Error that can happen when verifying a proof of finality.
@@ -530,6 +683,10 @@ pub enum FinalityVerifyError {
     EqualFinalizedHeightButInequalHash,
     /// The target block height is strictly inferior to the finalized block height.
     BelowFinalized,
+    /// A finality equivocation has previously been detected, and no block can be finalized
+    /// anymore. See [`NonFinalizedTree::finality_equivocation`].
+    #[display(fmt = "Finality is frozen because of a finality equivocation")]
+    FinalityFrozen,
     /// Finality proof targets a block that isn't in the chain.
     #[display(fmt = "Justification targets a block (#{block_number}) that isn't in the chain.")]
     UnknownTargetBlock {
@@ -554,6 +711,33 @@ pub enum FinalityVerifyError {
     },
 }
 
+/// Two conflicting blocks have been finalized at the same height.
+///
+/// This can only happen if a large number of Grandpa authorities are malicious, and means that
+/// the finality of the chain can't be trusted anymore.
+///
+/// See [`NonFinalizedTree::finality_equivocation`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FinalityEquivocation {
+    /// Number of the two conflicting blocks.
+    pub block_number: u64,
+    /// Hash of the block that is finalized in the [`NonFinalizedTree`].
+    pub finalized_block_hash: [u8; 32],
+    /// Hash of the block targeted by the conflicting justification or commit.
+    pub conflicting_block_hash: [u8; 32],
+    /// Conflicting justification or commit. Can be used as a proof of the equivocation.
+    pub proof: FinalityEquivocationProof,
+}
+
+/// See [`FinalityEquivocation::proof`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FinalityEquivocationProof {
+    /// SCALE-encoded Grandpa justification.
+    Justification(Vec<u8>),
+    /// SCALE-encoded Grandpa commit message.
+    GrandpaCommit(Vec<u8>),
+}
+
 /// Iterator producing the newly-finalized blocks removed from the state when the finalized block
 /// is updated.
 pub struct SetFinalizedBlockIter<'a, T> {
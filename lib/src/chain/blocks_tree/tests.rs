@@ -19,14 +19,124 @@
 
 #![cfg(test)]
 
-use core::{num::NonZeroU64, time::Duration};
+use core::{iter, num::NonZeroU64, time::Duration};
 
-use super::{Config, HeaderVerifySuccess, NonFinalizedTree};
-use crate::{chain::chain_information, header};
+use super::{
+    CommitVerifyError, Config, FinalityEquivocationProof, FinalityVerifyError, HeaderVerifyError,
+    HeaderVerifySuccess, JustificationVerifyError, NonFinalizedTree,
+};
+use crate::{chain::chain_information, finality::justification, header, util, verify};
 
 #[test]
 fn polkadot_blocks_0_to_2() {
-    let mut tree = NonFinalizedTree::new(Config {
+    let mut tree = polkadot_genesis_tree();
+
+    let block1 = polkadot_block1();
+    let block2 = polkadot_block2();
+
+    let verified_header1 = match tree.verify_header(block1, Duration::new(0, 0)).unwrap() {
+        HeaderVerifySuccess::Verified {
+            verified_header, ..
+        } => verified_header,
+        _ => panic!(),
+    };
+
+    tree.insert_verified_header(verified_header1, ());
+
+    let verified_header2 = match tree.verify_header(block2, Duration::new(0, 0)).unwrap() {
+        HeaderVerifySuccess::Verified {
+            verified_header, ..
+        } => verified_header,
+        _ => panic!(),
+    };
+
+    tree.insert_verified_header(verified_header2, ());
+}
+
+#[test]
+fn polkadot_blocks_batch_valid() {
+    let mut tree = polkadot_genesis_tree();
+
+    let num_verified = tree.verify_headers_batch(
+        [polkadot_block1(), polkadot_block2()].into_iter(),
+        Duration::new(0, 0),
+    );
+    assert_eq!(num_verified, 2);
+
+    for block in [polkadot_block1(), polkadot_block2()] {
+        let verified_header = match tree.verify_header(block, Duration::new(0, 0)).unwrap() {
+            HeaderVerifySuccess::Verified {
+                verified_header, ..
+            } => verified_header,
+            _ => panic!(),
+        };
+
+        tree.insert_verified_header(verified_header, ());
+    }
+
+    assert_eq!(tree.best_block_header().number, 2);
+}
+
+#[test]
+fn polkadot_blocks_batch_invalid() {
+    let mut tree = polkadot_genesis_tree();
+
+    // Modify the seal signature of block 1. The last byte of the signature contains a marker
+    // bit, so the byte before is modified instead.
+    let mut block1 = polkadot_block1();
+    let signature_byte = block1.len() - 2;
+    block1[signature_byte] ^= 1;
+
+    let num_verified = tree.verify_headers_batch(iter::once(block1.clone()), Duration::new(0, 0));
+    assert_eq!(num_verified, 0);
+
+    assert!(matches!(
+        tree.verify_header(block1, Duration::new(0, 0)),
+        Err(HeaderVerifyError::VerificationFailed(
+            verify::header_only::Error::BabeVerification(verify::babe::VerifyError::BadSignature)
+        ))
+    ));
+}
+
+#[test]
+fn polkadot_blocks_batch_mixed() {
+    let mut tree = polkadot_genesis_tree();
+
+    // Modify the seal signature of block 2. See `polkadot_blocks_batch_invalid`.
+    let mut block2 = polkadot_block2();
+    let signature_byte = block2.len() - 2;
+    block2[signature_byte] ^= 1;
+
+    let num_verified = tree.verify_headers_batch(
+        [polkadot_block1(), block2.clone()].into_iter(),
+        Duration::new(0, 0),
+    );
+    assert_eq!(num_verified, 1);
+
+    let verified_header1 = match tree
+        .verify_header(polkadot_block1(), Duration::new(0, 0))
+        .unwrap()
+    {
+        HeaderVerifySuccess::Verified {
+            verified_header, ..
+        } => verified_header,
+        _ => panic!(),
+    };
+    tree.insert_verified_header(verified_header1, ());
+
+    assert!(matches!(
+        tree.verify_header(block2, Duration::new(0, 0)),
+        Err(HeaderVerifyError::VerificationFailed(
+            verify::header_only::Error::BabeVerification(verify::babe::VerifyError::BadSignature)
+        ))
+    ));
+    assert!(tree
+        .verify_header(polkadot_block2(), Duration::new(0, 0))
+        .is_ok());
+}
+
+fn polkadot_genesis_tree() -> NonFinalizedTree<()> {
+    NonFinalizedTree::new(Config {
         chain_information: chain_information::ChainInformation {
             finalized_block_header: Box::new(header::Header {
                 parent_hash: [
@@ -169,9 +279,11 @@ fn polkadot_blocks_0_to_2() {
         blocks_capacity: 8,
         block_number_bytes: 4,
         allow_unknown_consensus_engines: false,
-    });
+    })
+}
 
-    let block1 = vec![
+fn polkadot_block1() -> Vec<u8> {
+    vec![
         145, 177, 113, 187, 21, 142, 45, 56, 72, 250, 35, 169, 241, 194, 81, 130, 251, 142, 32, 49,
         59, 44, 30, 180, 146, 25, 218, 122, 112, 206, 144, 195, 4, 197, 111, 205, 110, 122, 117,
         121, 38, 172, 227, 225, 236, 255, 155, 64, 16, 252, 120, 185, 13, 69, 146, 2, 163, 57, 38,
@@ -199,9 +311,11 @@ fn polkadot_blocks_0_to_2() {
         77, 251, 220, 102, 151, 163, 91, 242, 180, 148, 189, 162, 197, 166, 150, 29, 77, 78, 172,
         251, 247, 69, 116, 55, 155, 160, 217, 123, 91, 182, 80, 194, 232, 103, 10, 99, 121, 26,
         114, 121, 67, 188, 182, 153, 220, 122, 34, 139, 219, 158, 10, 152, 201, 208, 137,
-    ];
+    ]
+}
 
-    let block2 = vec![
+fn polkadot_block2() -> Vec<u8> {
+    vec![
         192, 9, 99, 88, 83, 78, 200, 210, 29, 1, 211, 75, 131, 110, 237, 71, 106, 28, 52, 63, 135,
         36, 250, 33, 83, 220, 7, 37, 173, 121, 122, 144, 8, 85, 55, 218, 60, 103, 78, 2, 10, 176,
         179, 225, 183, 0, 154, 97, 43, 138, 222, 224, 104, 10, 126, 97, 3, 44, 219, 227, 236, 94,
@@ -216,25 +330,7 @@ fn polkadot_blocks_0_to_2() {
         26, 138, 9, 200, 2, 10, 46, 98, 24, 167, 215, 23, 117, 157, 130, 206, 250, 124, 193, 231,
         26, 77, 147, 33, 218, 103, 174, 2, 6, 143, 29, 1, 175, 29, 29, 124, 133, 17, 32, 124, 4,
         148, 131, 74, 156, 58, 185, 152, 11, 51, 226, 55, 115, 244, 139, 198, 207, 133,
-    ];
-
-    let verified_header1 = match tree.verify_header(block1, Duration::new(0, 0)).unwrap() {
-        HeaderVerifySuccess::Verified {
-            verified_header, ..
-        } => verified_header,
-        _ => panic!(),
-    };
-
-    tree.insert_verified_header(verified_header1, ());
-
-    let verified_header2 = match tree.verify_header(block2, Duration::new(0, 0)).unwrap() {
-        HeaderVerifySuccess::Verified {
-            verified_header, ..
-        } => verified_header,
-        _ => panic!(),
-    };
-
-    tree.insert_verified_header(verified_header2, ());
+    ]
 }
 
 #[test]
@@ -440,3 +536,277 @@ fn kusama_blocks_0_to_2() {
 
     tree.insert_verified_header(verified_header2, ());
 }
+
+#[test]
+fn conflicting_justification_freezes_finality() {
+    let mut chain = GrandpaTestChain::new();
+    let (block_a1, block_b1) = chain.finalize_fork();
+
+    assert!(matches!(
+        chain.tree.verify_justification(*b"FRNK", &chain.justification(&block_b1, 1), [0; 32]),
+        Err(JustificationVerifyError::ConflictingFinality {
+            block_number: 1,
+            conflicting_block_hash,
+        }) if conflicting_block_hash == block_b1
+    ));
+
+    let equivocation = chain.tree.finality_equivocation().unwrap();
+    assert_eq!(equivocation.block_number, 1);
+    assert_eq!(equivocation.finalized_block_hash, block_a1);
+    assert_eq!(equivocation.conflicting_block_hash, block_b1);
+    assert_eq!(
+        equivocation.proof,
+        FinalityEquivocationProof::Justification(chain.justification(&block_b1, 1))
+    );
+
+    // Later finality proofs are ignored, even valid ones.
+    let block_a2 = chain.insert_block(&block_a1, 2, 3, [1; 32]);
+    assert!(matches!(
+        chain
+            .tree
+            .verify_justification(*b"FRNK", &chain.justification(&block_a2, 2), [0; 32]),
+        Err(JustificationVerifyError::FinalityVerify(
+            FinalityVerifyError::FinalityFrozen
+        ))
+    ));
+    assert!(matches!(
+        chain
+            .tree
+            .verify_grandpa_commit_message(&chain.commit(&block_a2, 2), [0; 32]),
+        Err(CommitVerifyError::FinalityVerify(
+            FinalityVerifyError::FinalityFrozen
+        ))
+    ));
+}
+
+#[test]
+fn invalid_conflicting_justification_doesnt_freeze_finality() {
+    let mut chain = GrandpaTestChain::new();
+    let (block_a1, block_b1) = chain.finalize_fork();
+
+    // Corrupt the signature of the only precommit. The last byte of the justification is the
+    // number of votes ancestries, preceded by the public key of the authority then by the
+    // signature.
+    let mut justification = chain.justification(&block_b1, 1);
+    let signature_byte = justification.len() - 1 - 32 - 1;
+    justification[signature_byte] ^= 1;
+
+    assert!(matches!(
+        chain
+            .tree
+            .verify_justification(*b"FRNK", &justification, [0; 32]),
+        Err(JustificationVerifyError::VerificationFailed(
+            justification::verify::Error::BadSignature
+        ))
+    ));
+    assert!(chain.tree.finality_equivocation().is_none());
+
+    // Finality continues normally.
+    let block_a2 = chain.insert_block(&block_a1, 2, 3, [1; 32]);
+    chain
+        .tree
+        .verify_justification(*b"FRNK", &chain.justification(&block_a2, 2), [0; 32])
+        .unwrap()
+        .apply()
+        .for_each(drop);
+    assert_eq!(chain.tree.finalized_block_hash(), block_a2);
+}
+
+#[test]
+fn conflicting_commit_freezes_finality() {
+    let mut chain = GrandpaTestChain::new();
+    let (block_a1, block_b1) = chain.finalize_fork();
+
+    assert!(matches!(
+        chain.tree.verify_grandpa_commit_message(&chain.commit(&block_b1, 1), [0; 32]),
+        Err(CommitVerifyError::ConflictingFinality {
+            block_number: 1,
+            conflicting_block_hash,
+        }) if conflicting_block_hash == block_b1
+    ));
+
+    let equivocation = chain.tree.finality_equivocation().unwrap();
+    assert_eq!(equivocation.finalized_block_hash, block_a1);
+    assert_eq!(equivocation.conflicting_block_hash, block_b1);
+    assert_eq!(
+        equivocation.proof,
+        FinalityEquivocationProof::GrandpaCommit(chain.commit(&block_b1, 1))
+    );
+
+    let block_a2 = chain.insert_block(&block_a1, 2, 3, [1; 32]);
+    assert!(matches!(
+        chain
+            .tree
+            .verify_grandpa_commit_message(&chain.commit(&block_a2, 2), [0; 32]),
+        Err(CommitVerifyError::FinalityVerify(
+            FinalityVerifyError::FinalityFrozen
+        ))
+    ));
+}
+
+/// Chain using Aura and Grandpa, with a single authority for each.
+struct GrandpaTestChain {
+    tree: NonFinalizedTree<()>,
+    genesis_hash: [u8; 32],
+    aura_key: schnorrkel::Keypair,
+    grandpa_key: ed25519_zebra::SigningKey,
+}
+
+impl GrandpaTestChain {
+    fn new() -> Self {
+        let aura_key = schnorrkel::MiniSecretKey::from_bytes(&[1; 32])
+            .unwrap()
+            .expand_to_keypair(schnorrkel::ExpansionMode::Ed25519);
+        let grandpa_key = ed25519_zebra::SigningKey::from([2; 32]);
+
+        let genesis_header = header::Header {
+            parent_hash: [0; 32],
+            number: 0,
+            state_root: [0; 32],
+            extrinsics_root: [0; 32],
+            digest: header::Digest::from(header::DigestRef::empty()),
+        };
+        let genesis_hash = genesis_header.hash(4);
+
+        let tree = NonFinalizedTree::new(Config {
+            chain_information: chain_information::ChainInformation {
+                finalized_block_header: Box::new(genesis_header),
+                consensus: chain_information::ChainInformationConsensus::Aura {
+                    finalized_authorities_list: vec![header::AuraAuthority {
+                        public_key: aura_key.public.to_bytes(),
+                    }],
+                    slot_duration: NonZeroU64::new(6000).unwrap(),
+                },
+                finality: chain_information::ChainInformationFinality::Grandpa {
+                    after_finalized_block_authorities_set_id: 0,
+                    finalized_triggered_authorities: vec![header::GrandpaAuthority {
+                        public_key: ed25519_zebra::VerificationKey::from(&grandpa_key).into(),
+                        weight: NonZeroU64::new(1).unwrap(),
+                    }],
+                    finalized_scheduled_change: None,
+                },
+            }
+            .try_into()
+            .unwrap(),
+            blocks_capacity: 8,
+            block_number_bytes: 4,
+            allow_unknown_consensus_engines: false,
+        });
+
+        GrandpaTestChain {
+            tree,
+            genesis_hash,
+            aura_key,
+            grandpa_key,
+        }
+    }
+
+    /// Inserts two children of the genesis block, then finalizes the first one. Returns the
+    /// hashes of the finalized block and of the pruned block.
+    fn finalize_fork(&mut self) -> ([u8; 32], [u8; 32]) {
+        let genesis_hash = self.genesis_hash;
+        let block_a1 = self.insert_block(&genesis_hash, 1, 1, [1; 32]);
+        let block_b1 = self.insert_block(&genesis_hash, 1, 2, [2; 32]);
+
+        self.tree
+            .verify_justification(*b"FRNK", &self.justification(&block_a1, 1), [0; 32])
+            .unwrap()
+            .apply()
+            .for_each(drop);
+        assert_eq!(self.tree.finalized_block_hash(), block_a1);
+
+        (block_a1, block_b1)
+    }
+
+    /// Builds, signs, verifies, and inserts a block. Returns its hash.
+    fn insert_block(
+        &mut self,
+        parent_hash: &[u8; 32],
+        number: u64,
+        slot_number: u64,
+        extrinsics_root: [u8; 32],
+    ) -> [u8; 32] {
+        let digest_items = [header::DigestItem::AuraPreDigest(header::AuraPreDigest {
+            slot_number,
+        })];
+        let unsealed_header = header::Header {
+            parent_hash: *parent_hash,
+            number,
+            state_root: [0; 32],
+            extrinsics_root,
+            digest: header::Digest::from(header::DigestRef::from_slice(&digest_items).unwrap()),
+        };
+
+        let signature = self
+            .aura_key
+            .sign(schnorrkel::signing_context(b"substrate").bytes(&unsealed_header.hash(4)))
+            .to_bytes();
+        let scale_encoded_header = header::HeaderRef::from(&unsealed_header)
+            .scale_encoding_with_extra_digest_item(4, header::DigestItemRef::AuraSeal(&signature))
+            .fold(Vec::new(), |mut a, b| {
+                a.extend_from_slice(b.as_ref());
+                a
+            });
+        let hash = header::hash_from_scale_encoded_header(&scale_encoded_header);
+
+        let verified_header = match self
+            .tree
+            .verify_header(scale_encoded_header, Duration::from_secs(3600))
+            .unwrap()
+        {
+            HeaderVerifySuccess::Verified {
+                verified_header, ..
+            } => verified_header,
+            _ => panic!(),
+        };
+        self.tree.insert_verified_header(verified_header, ());
+
+        hash
+    }
+
+    /// Returns the signature of the authority of a precommit for the given block.
+    fn precommit_signature(&self, block_hash: &[u8; 32], block_number: u64) -> [u8; 64] {
+        let mut message = Vec::new();
+        message.push(1u8);
+        message.extend_from_slice(block_hash);
+        message.extend_from_slice(&u32::try_from(block_number).unwrap().to_le_bytes());
+        message.extend_from_slice(&0u64.to_le_bytes()); // Round number.
+        message.extend_from_slice(&0u64.to_le_bytes()); // Authorities set id.
+        self.grandpa_key.sign(&message).into()
+    }
+
+    /// Builds a justification signed by the authority that targets the given block.
+    fn justification(&self, block_hash: &[u8; 32], block_number: u64) -> Vec<u8> {
+        let mut justification = Vec::new();
+        justification.extend_from_slice(&0u64.to_le_bytes()); // Round number.
+        justification.extend_from_slice(block_hash);
+        justification.extend_from_slice(&u32::try_from(block_number).unwrap().to_le_bytes());
+        justification.extend_from_slice(util::encode_scale_compact_usize(1).as_ref());
+        justification.extend_from_slice(block_hash);
+        justification.extend_from_slice(&u32::try_from(block_number).unwrap().to_le_bytes());
+        justification.extend_from_slice(&self.precommit_signature(block_hash, block_number));
+        justification.extend_from_slice(&<[u8; 32]>::from(ed25519_zebra::VerificationKey::from(
+            &self.grandpa_key,
+        )));
+        justification.extend_from_slice(util::encode_scale_compact_usize(0).as_ref());
+        justification
+    }
+
+    /// Builds a commit message signed by the authority that targets the given block.
+    fn commit(&self, block_hash: &[u8; 32], block_number: u64) -> Vec<u8> {
+        let mut commit = Vec::new();
+        commit.extend_from_slice(&0u64.to_le_bytes()); // Round number.
+        commit.extend_from_slice(&0u64.to_le_bytes()); // Authorities set id.
+        commit.extend_from_slice(block_hash);
+        commit.extend_from_slice(&u32::try_from(block_number).unwrap().to_le_bytes());
+        commit.extend_from_slice(util::encode_scale_compact_usize(1).as_ref());
+        commit.extend_from_slice(block_hash);
+        commit.extend_from_slice(&u32::try_from(block_number).unwrap().to_le_bytes());
+        commit.extend_from_slice(util::encode_scale_compact_usize(1).as_ref());
+        commit.extend_from_slice(&self.precommit_signature(block_hash, block_number));
+        commit.extend_from_slice(&<[u8; 32]>::from(ed25519_zebra::VerificationKey::from(
+            &self.grandpa_key,
+        )));
+        commit
+    }
+}
@@ -19,10 +19,11 @@
 //! blocks.
 
 use crate::{chain::chain_information, header, verify};
+use alloc::boxed::Box;
 
 use super::{
     fmt, Arc, BestScore, Block, BlockConsensus, BlockFinality, Duration, Finality,
-    FinalizedConsensus, NonFinalizedTree, Vec,
+    FinalizedConsensus, NonFinalizedTree, Vec, MAX_VERIFIED_SEAL_SIGNATURES,
 };
 
 impl<T> NonFinalizedTree<T> {
@@ -42,6 +43,110 @@ impl<T> NonFinalizedTree<T> {
         scale_encoded_header: Vec<u8>,
         now_from_unix_epoch: Duration,
     ) -> Result<HeaderVerifySuccess, HeaderVerifyError> {
+        match self.verify_header_deferred_seal(scale_encoded_header, now_from_unix_epoch, None)? {
+            DeferredSealVerify::Duplicate => Ok(HeaderVerifySuccess::Duplicate),
+            DeferredSealVerify::Verified(verified) => {
+                let DeferredSealVerified {
+                    verified_header,
+                    is_new_best,
+                    seal_signature,
+                    bad_seal_signature_error,
+                } = *verified;
+
+                // Signatures that have already been verified by `verify_headers_batch` aren't
+                // verified again.
+                let already_verified = self.verified_seal_signatures.get(&verified_header.hash)
+                    == Some(&seal_signature.public_key());
+                if !already_verified && !seal_signature.verify() {
+                    return Err(HeaderVerifyError::VerificationFailed(
+                        bad_seal_signature_error,
+                    ));
+                }
+
+                Ok(HeaderVerifySuccess::Verified {
+                    verified_header,
+                    is_new_best,
+                })
+            }
+        }
+    }
+
+    /// Verifies the signatures found in the seals of the given headers all at once, which is
+    /// considerably faster than verifying them one by one. See
+    /// [the `seal_signatures` module](verify::seal_signatures).
+    ///
+    /// The headers must form a chain: the parent of each header must be either the previous
+    /// header in the list or a block in the tree. The headers are processed in order, and the
+    /// processing stops at the first header that doesn't satisfy this condition or that fails to
+    /// verify for a reason other than its seal signature.
+    ///
+    /// No block is inserted in the tree. Instead, the tree remembers which seal signatures are
+    /// valid, so that [`NonFinalizedTree::verify_header`] doesn't verify them again when these
+    /// headers are later verified. Headers whose seal signature is invalid, and their
+    /// descendants, are ignored. The error is reported by [`NonFinalizedTree::verify_header`].
+    ///
+    /// Returns the number of headers, starting from the first one, whose seal signature is now
+    /// known to be valid.
+    ///
+    /// Must be passed the current UNIX time in order to verify that the blocks don't pretend to
+    /// come from the future.
+    pub fn verify_headers_batch(
+        &mut self,
+        scale_encoded_headers: impl Iterator<Item = Vec<u8>>,
+        now_from_unix_epoch: Duration,
+    ) -> usize {
+        let mut signatures = verify::seal_signatures::SealSignatures::new();
+        let mut hashes = Vec::new();
+        let mut previous: Option<VerifiedHeader> = None;
+
+        for scale_encoded_header in scale_encoded_headers {
+            match self.verify_header_deferred_seal(
+                scale_encoded_header,
+                now_from_unix_epoch,
+                previous.as_ref(),
+            ) {
+                Ok(DeferredSealVerify::Verified(verified)) => {
+                    let DeferredSealVerified {
+                        verified_header,
+                        seal_signature,
+                        ..
+                    } = *verified;
+                    hashes.push((verified_header.hash, seal_signature.public_key()));
+                    signatures.push(seal_signature);
+                    previous = Some(verified_header);
+                }
+                Ok(DeferredSealVerify::Duplicate) => {
+                    // The children of the block are verified against the tree.
+                    previous = None;
+                }
+                Err(_) => break,
+            }
+        }
+
+        let num_valid = match signatures.verify() {
+            Ok(()) => hashes.len(),
+            Err(error) => error.index,
+        };
+
+        if self.verified_seal_signatures.len() + num_valid > MAX_VERIFIED_SEAL_SIGNATURES {
+            self.verified_seal_signatures.clear();
+        }
+        self.verified_seal_signatures
+            .extend(hashes.into_iter().take(num_valid));
+
+        num_valid
+    }
+
+    /// Verifies the given block header, except for the signature in its seal.
+    ///
+    /// If `batch_parent` is `Some` and is the parent of the header, it is used as the parent
+    /// instead of looking up the parent in the tree.
+    fn verify_header_deferred_seal(
+        &self,
+        scale_encoded_header: Vec<u8>,
+        now_from_unix_epoch: Duration,
+        batch_parent: Option<&VerifiedHeader>,
+    ) -> Result<DeferredSealVerify, HeaderVerifyError> {
         let decoded_header = match header::decode(&scale_encoded_header, self.block_number_bytes) {
             Ok(h) => h,
             Err(err) => return Err(HeaderVerifyError::InvalidHeader(err)),
@@ -51,14 +156,16 @@ impl<T> NonFinalizedTree<T> {
 
         // Check for duplicates.
         if self.blocks_by_hash.contains_key(&hash) {
-            return Ok(HeaderVerifySuccess::Duplicate);
+            return Ok(DeferredSealVerify::Duplicate);
         }
 
+        let batch_parent = batch_parent.filter(|p| p.hash == *decoded_header.parent_hash);
+
         // Try to find the parent block in the tree of known blocks.
         // `Some` with an index of the parent within the tree of unfinalized blocks.
-        // `None` means that the parent is the finalized block.
+        // `None` means that the parent is the finalized block or `batch_parent`.
         let parent_tree_index = {
-            if *decoded_header.parent_hash == self.finalized_block_hash {
+            if batch_parent.is_some() || *decoded_header.parent_hash == self.finalized_block_hash {
                 None
             } else {
                 match self.blocks_by_hash.get(decoded_header.parent_hash) {
@@ -73,58 +180,70 @@ impl<T> NonFinalizedTree<T> {
 
         // Some consensus-specific information must be fetched from the tree of ancestry. The
         // information is found either in the parent block, or in the finalized block.
-        let (parent_consensus, parent_best_score, parent_finality) =
-            if let Some(parent_tree_index) = parent_tree_index {
-                let parent = self.blocks.get(parent_tree_index).unwrap();
-                (
-                    Some(parent.consensus.clone()),
-                    parent.best_score,
-                    parent.finality.clone(),
-                )
-            } else {
-                let consensus = match &self.finalized_consensus {
-                    FinalizedConsensus::Unknown => None,
-                    FinalizedConsensus::Aura {
-                        authorities_list, ..
-                    } => Some(BlockConsensus::Aura {
-                        authorities_list: authorities_list.clone(),
-                    }),
-                    FinalizedConsensus::Babe {
-                        block_epoch_information,
-                        next_epoch_transition,
-                        ..
-                    } => Some(BlockConsensus::Babe {
-                        current_epoch: block_epoch_information.clone(),
-                        next_epoch: next_epoch_transition.clone(),
-                    }),
-                };
-
-                let finality = match self.finality {
-                    Finality::Outsourced => BlockFinality::Outsourced,
-                    Finality::Grandpa {
-                        after_finalized_block_authorities_set_id,
-                        ref finalized_scheduled_change,
-                        ref finalized_triggered_authorities,
-                    } => {
-                        debug_assert!(finalized_scheduled_change
-                            .as_ref()
-                            .map(|(n, _)| *n >= decoded_header.number)
-                            .unwrap_or(true));
-                        BlockFinality::Grandpa {
-                            prev_auth_change_trigger_number: None,
-                            triggers_change: false,
-                            scheduled_change: finalized_scheduled_change.clone(),
-                            after_block_authorities_set_id:
-                                after_finalized_block_authorities_set_id,
-                            triggered_authorities: finalized_triggered_authorities.clone(),
-                        }
-                    }
-                };
+        let (parent_consensus, parent_best_score, parent_finality) = if let Some(batch_parent) =
+            batch_parent
+        {
+            (
+                Some(batch_parent.consensus_update.clone()),
+                BestScore {
+                    num_primary_slots: batch_parent.best_score_num_primary_slots,
+                    num_secondary_slots: batch_parent.best_score_num_secondary_slots,
+                    insertion_counter: self.blocks_insertion_counter,
+                },
+                batch_parent.finality_update.clone(),
+            )
+        } else if let Some(parent_tree_index) = parent_tree_index {
+            let parent = self.blocks.get(parent_tree_index).unwrap();
+            (
+                Some(parent.consensus.clone()),
+                parent.best_score,
+                parent.finality.clone(),
+            )
+        } else {
+            let consensus = match &self.finalized_consensus {
+                FinalizedConsensus::Unknown => None,
+                FinalizedConsensus::Aura {
+                    authorities_list, ..
+                } => Some(BlockConsensus::Aura {
+                    authorities_list: authorities_list.clone(),
+                }),
+                FinalizedConsensus::Babe {
+                    block_epoch_information,
+                    next_epoch_transition,
+                    ..
+                } => Some(BlockConsensus::Babe {
+                    current_epoch: block_epoch_information.clone(),
+                    next_epoch: next_epoch_transition.clone(),
+                }),
+            };
 
-                (consensus, self.finalized_best_score, finality)
+            let finality = match self.finality {
+                Finality::Outsourced => BlockFinality::Outsourced,
+                Finality::Grandpa {
+                    after_finalized_block_authorities_set_id,
+                    ref finalized_scheduled_change,
+                    ref finalized_triggered_authorities,
+                } => {
+                    debug_assert!(finalized_scheduled_change
+                        .as_ref()
+                        .map(|(n, _)| *n >= decoded_header.number)
+                        .unwrap_or(true));
+                    BlockFinality::Grandpa {
+                        prev_auth_change_trigger_number: None,
+                        triggers_change: false,
+                        scheduled_change: finalized_scheduled_change.clone(),
+                        after_block_authorities_set_id: after_finalized_block_authorities_set_id,
+                        triggered_authorities: finalized_triggered_authorities.clone(),
+                    }
+                }
             };
 
-        let parent_block_header = if let Some(parent_tree_index) = parent_tree_index {
+            (consensus, self.finalized_best_score, finality)
+        };
+
+        let parent_block_header = if let Some(batch_parent) = batch_parent {
+            &batch_parent.scale_encoded_header
+        } else if let Some(parent_tree_index) = parent_tree_index {
             &self
                 .blocks
                 .get(parent_tree_index)
@@ -166,7 +285,7 @@ impl<T> NonFinalizedTree<T> {
                 }
             };
 
-            verify::header_only::verify(verify::header_only::Config {
+            verify::header_only::verify_deferred_seal(verify::header_only::Config {
                 consensus: consensus_config,
                 finality: match &parent_finality {
                     BlockFinality::Outsourced => verify::header_only::ConfigFinality::Outsourced,
@@ -184,6 +303,9 @@ impl<T> NonFinalizedTree<T> {
             })
         }
         .map_err(HeaderVerifyError::VerificationFailed)?;
+        let (header_verify_result, seal_signature) = header_verify_result;
+        let bad_seal_signature_error =
+            verify::header_only::bad_seal_signature_error(&header_verify_result);
 
         // Updated consensus information for the block being verified.
         let (best_score_num_primary_slots, best_score_num_secondary_slots, consensus_update) =
@@ -191,7 +313,11 @@ impl<T> NonFinalizedTree<T> {
                 header_verify_result,
                 &parent_consensus,
                 self.finalized_consensus.clone(),
-                parent_tree_index.map(|idx| self.blocks.get(idx).unwrap().consensus.clone()),
+                batch_parent
+                    .map(|p| p.consensus_update.clone())
+                    .or_else(|| {
+                        parent_tree_index.map(|idx| self.blocks.get(idx).unwrap().consensus.clone())
+                    }),
             ) {
                 // No Aura epoch transition. Just a regular block.
                 (
@@ -454,18 +580,22 @@ impl<T> NonFinalizedTree<T> {
             new_block_best_score > *current_best_score
         };
 
-        Ok(HeaderVerifySuccess::Verified {
-            verified_header: VerifiedHeader {
-                number: decoded_header.number,
-                scale_encoded_header,
-                consensus_update,
-                finality_update,
-                best_score_num_primary_slots,
-                best_score_num_secondary_slots,
-                hash,
+        Ok(DeferredSealVerify::Verified(Box::new(
+            DeferredSealVerified {
+                verified_header: VerifiedHeader {
+                    number: decoded_header.number,
+                    scale_encoded_header,
+                    consensus_update,
+                    finality_update,
+                    best_score_num_primary_slots,
+                    best_score_num_secondary_slots,
+                    hash,
+                },
+                is_new_best,
+                seal_signature,
+                bad_seal_signature_error,
             },
-            is_new_best,
-        })
+        )))
     }
 
     /// Insert a header that has already been verified to be valid.
@@ -524,6 +654,8 @@ impl<T> NonFinalizedTree<T> {
             },
         );
 
+        self.verified_seal_signatures.remove(&verified_header.hash);
+
         let _prev_value = self
             .blocks_by_hash
             .insert(verified_header.hash, new_node_index);
@@ -543,6 +675,24 @@ impl<T> NonFinalizedTree<T> {
     }
 }
 
+/// Outcome of [`NonFinalizedTree::verify_header_deferred_seal`].
+enum DeferredSealVerify {
+    /// Block is already known.
+    Duplicate,
+    /// Block wasn't known and has been successfully verified, except for its seal signature.
+    Verified(Box<DeferredSealVerified>),
+}
+
+/// See [`DeferredSealVerify::Verified`].
+struct DeferredSealVerified {
+    verified_header: VerifiedHeader,
+    is_new_best: bool,
+    /// Signature in the seal of the header, that remains to be verified.
+    seal_signature: verify::seal_signatures::SealSignature,
+    /// Error to return if [`DeferredSealVerified::seal_signature`] is invalid.
+    bad_seal_signature_error: verify::header_only::Error,
+}
+
 /// Successfully-verified block header that can be inserted into the chain.
 pub struct VerifiedHeader {
     scale_encoded_header: Vec<u8>,
@@ -43,8 +43,10 @@ use crate::{
 };
 
 use alloc::{
+    borrow::ToOwned as _,
     boxed::Box,
     string::{String, ToString as _},
+    vec,
     vec::Vec,
 };
 use core::{iter, num::NonZeroU64, ops::Bound};
@@ -245,6 +247,18 @@ impl ChainSpec {
             .map(|h| &h.0)
     }
 
+    /// Returns a list of block numbers and hashes of blocks that are known to be part of the
+    /// canonical chain. Blocks that have one of these numbers but a different hash should be
+    /// considered as invalid.
+    pub fn fork_blocks(&'_ self) -> impl Iterator<Item = (u64, &'_ [u8; 32])> + '_ {
+        self.client_spec
+            .fork_blocks
+            .as_ref()
+            .into_iter()
+            .flat_map(|l| l.iter())
+            .map(|(n, h)| (*n, &h.0))
+    }
+
     /// Returns the list of bootnode addresses found in the chain spec.
     ///
     /// Bootnode addresses that have failed to be parsed are returned as well in the form of
@@ -330,6 +344,116 @@ impl ChainSpec {
             .map_or("{}", |p| p.get())
     }
 
+    /// Returns the SS58 prefix of the addresses of the chain, as found in the `ss58Format`
+    /// property.
+    ///
+    /// Returns `Ok(None)` if the property is absent.
+    pub fn ss58_format(&self) -> Result<Option<u16>, InvalidPropertyError> {
+        let Some(value) = self.property("ss58Format") else {
+            return Ok(None);
+        };
+
+        // SS58 prefixes are encoded on 14 bits.
+        value
+            .as_u64()
+            .and_then(|n| u16::try_from(n).ok())
+            .filter(|n| *n < (1 << 14))
+            .map(Some)
+            .ok_or(InvalidPropertyError::Ss58Format)
+    }
+
+    /// Returns the number of decimals of the tokens of the chain, as found in the
+    /// `tokenDecimals` property.
+    ///
+    /// The property can be either a single number or an array of numbers, in which case each
+    /// entry corresponds to the entry at the same index in [`ChainSpec::token_symbol`]. Returns
+    /// an empty list if the property is absent.
+    pub fn token_decimals(&self) -> Result<Vec<u8>, InvalidPropertyError> {
+        fn parse(value: &serde_json::Value) -> Option<u8> {
+            value.as_u64().and_then(|n| u8::try_from(n).ok())
+        }
+
+        match self.property("tokenDecimals") {
+            None => Ok(Vec::new()),
+            Some(serde_json::Value::Array(list)) => list
+                .iter()
+                .map(|v| parse(v).ok_or(InvalidPropertyError::TokenDecimals))
+                .collect(),
+            Some(value) => parse(&value)
+                .map(|n| vec![n])
+                .ok_or(InvalidPropertyError::TokenDecimals),
+        }
+    }
+
+    /// Returns the symbols of the tokens of the chain, as found in the `tokenSymbol` property.
+    ///
+    /// The property can be either a single string or an array of strings, in which case each
+    /// entry corresponds to the entry at the same index in [`ChainSpec::token_decimals`]. Returns
+    /// an empty list if the property is absent.
+    pub fn token_symbol(&self) -> Result<Vec<String>, InvalidPropertyError> {
+        fn parse(value: serde_json::Value) -> Option<String> {
+            match value {
+                serde_json::Value::String(s) if !s.is_empty() => Some(s),
+                _ => None,
+            }
+        }
+
+        match self.property("tokenSymbol") {
+            None => Ok(Vec::new()),
+            Some(serde_json::Value::Array(list)) => list
+                .into_iter()
+                .map(|v| parse(v).ok_or(InvalidPropertyError::TokenSymbol))
+                .collect(),
+            Some(value) => parse(value)
+                .map(|s| vec![s])
+                .ok_or(InvalidPropertyError::TokenSymbol),
+        }
+    }
+
+    /// Returns the same value as [`ChainSpec::properties`], except that the `ss58Format`,
+    /// `tokenDecimals`, and `tokenSymbol` properties are removed if their value is invalid.
+    ///
+    /// This is the value that should be reported to JSON-RPC clients, as these clients typically
+    /// rely on the format of these properties.
+    pub fn validated_properties(&self) -> String {
+        // The properties are returned untouched if they are all valid, in order to preserve
+        // their formatting.
+        if self.ss58_format().is_ok()
+            && self.token_decimals().is_ok()
+            && self.token_symbol().is_ok()
+        {
+            return self.properties().to_owned();
+        }
+
+        let Ok(serde_json::Value::Object(mut properties)) =
+            serde_json::from_str::<serde_json::Value>(self.properties())
+        else {
+            return self.properties().to_owned();
+        };
+
+        if self.ss58_format().is_err() {
+            properties.remove("ss58Format");
+        }
+        if self.token_decimals().is_err() {
+            properties.remove("tokenDecimals");
+        }
+        if self.token_symbol().is_err() {
+            properties.remove("tokenSymbol");
+        }
+
+        serde_json::Value::Object(properties).to_string()
+    }
+
+    /// Returns the value of the given entry of [`ChainSpec::properties`], or `None` if it is
+    /// absent or if the properties aren't a JSON object.
+    fn property(&self, name: &str) -> Option<serde_json::Value> {
+        let properties = self.client_spec.properties.as_ref()?;
+        match serde_json::from_str::<serde_json::Value>(properties.get()) {
+            Ok(serde_json::Value::Object(mut properties)) => properties.remove(name),
+            _ => None,
+        }
+    }
+
     pub fn light_sync_state(&self) -> Option<LightSyncState> {
         self.client_spec
             .light_sync_state
@@ -542,6 +666,22 @@ enum ParseErrorInner {
     Other,
 }
 
+/// Error potentially returned by [`ChainSpec::ss58_format`], [`ChainSpec::token_decimals`], or
+/// [`ChainSpec::token_symbol`].
+#[derive(Debug, Clone, PartialEq, Eq, derive_more::Display)]
+pub enum InvalidPropertyError {
+    /// The `ss58Format` property isn't an integer between 0 and 16383.
+    #[display(fmt = "Invalid `ss58Format` property")]
+    Ss58Format,
+    /// The `tokenDecimals` property isn't an integer between 0 and 255 or an array of such
+    /// integers.
+    #[display(fmt = "Invalid `tokenDecimals` property")]
+    TokenDecimals,
+    /// The `tokenSymbol` property isn't a non-empty string or an array of non-empty strings.
+    #[display(fmt = "Invalid `tokenSymbol` property")]
+    TokenSymbol,
+}
+
 /// Error when building the chain information from the genesis storage.
 #[derive(Debug, derive_more::Display)]
 pub enum FromGenesisStorageError {
@@ -17,7 +17,7 @@
 
 #![cfg(test)]
 
-use super::{Bootnode, ChainSpec, CheckpointToChainInformationError};
+use super::{Bootnode, ChainSpec, CheckpointToChainInformationError, InvalidPropertyError};
 
 #[test]
 fn can_decode_polkadot_genesis() {
@@ -54,6 +54,39 @@ fn can_decode_polkadot_genesis() {
     );
 }
 
+#[test]
+fn bad_blocks_and_fork_blocks() {
+    let specs = ChainSpec::from_json_bytes(
+        r#"{
+            "name": "Test",
+            "id": "test",
+            "bootNodes": [],
+            "badBlocks": [
+              "0x15b1b925b0aa5cfe43c88cd024f74258cb5cfe3af424882c901014e8acd0d241"
+            ],
+            "forkBlocks": [
+              [12, "0x2563260209012232649ab9dc003f62e274c684037de499a23062f8e0e816c605"]
+            ],
+            "genesis": {
+              "raw": {
+                "top": {},
+                "childrenDefault": {}
+              }
+            }
+          }
+          "#,
+    )
+    .unwrap();
+
+    assert_eq!(specs.bad_blocks_hashes().count(), 1);
+    assert_eq!(specs.bad_blocks_hashes().next().unwrap()[..2], [0x15, 0xb1]);
+
+    let fork_blocks = specs.fork_blocks().collect::<Vec<_>>();
+    assert_eq!(fork_blocks.len(), 1);
+    assert_eq!(fork_blocks[0].0, 12);
+    assert_eq!(fork_blocks[0].1[..2], [0x25, 0x63]);
+}
+
 #[test]
 fn relay_chain_para_id_either_both_present_or_absent() {
     ChainSpec::from_json_bytes(
@@ -143,6 +176,55 @@ fn relay_chain_para_id_either_both_present_or_absent() {
     .is_err());
 }
 
+#[test]
+fn token_properties() {
+    let spec_with_properties = |properties: &str| {
+        ChainSpec::from_json_bytes(format!(
+            r#"{{
+                "name": "Test",
+                "id": "test",
+                "bootNodes": [],
+                "properties": {properties},
+                "genesis": {{
+                  "raw": {{
+                    "top": {{}},
+                    "childrenDefault": {{}}
+                  }}
+                }}
+              }}
+              "#
+        ))
+        .unwrap()
+    };
+
+    let specs =
+        spec_with_properties(r#"{"ss58Format": 0, "tokenDecimals": 10, "tokenSymbol": "DOT"}"#);
+    assert_eq!(specs.ss58_format(), Ok(Some(0)));
+    assert_eq!(specs.token_decimals(), Ok(vec![10]));
+    assert_eq!(specs.token_symbol(), Ok(vec!["DOT".to_owned()]));
+
+    let specs = spec_with_properties(
+        r#"{"foo": "bar", "tokenDecimals": [12, 18], "tokenSymbol": ["ACA", "AUSD"]}"#,
+    );
+    assert_eq!(specs.ss58_format(), Ok(None));
+    assert_eq!(specs.token_decimals(), Ok(vec![12, 18]));
+    assert_eq!(
+        specs.token_symbol(),
+        Ok(vec!["ACA".to_owned(), "AUSD".to_owned()])
+    );
+
+    let specs = spec_with_properties(
+        r#"{"foo": "bar", "ss58Format": 16384, "tokenDecimals": [12, 256], "tokenSymbol": ""}"#,
+    );
+    assert_eq!(specs.ss58_format(), Err(InvalidPropertyError::Ss58Format));
+    assert_eq!(
+        specs.token_decimals(),
+        Err(InvalidPropertyError::TokenDecimals)
+    );
+    assert_eq!(specs.token_symbol(), Err(InvalidPropertyError::TokenSymbol));
+    assert_eq!(specs.validated_properties(), r#"{"foo":"bar"}"#);
+}
+
 #[test]
 fn issue_598() {
     // Regression test for a panic.
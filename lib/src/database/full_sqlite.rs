@@ -35,10 +35,13 @@
 //! Any block that isn't an ancestor or descendant will be removed. Reverting finalization is
 //! not supported.
 //!
-//! In order to minimize disk usage, it is not possible to efficiently retrieve the storage items
-//! of blocks that are ancestors of the finalized block. When a block is finalized, the storage of
-//! its ancestors is lost, and the only way to reconstruct it is to execute all blocks starting
-//! from the genesis to the desired one.
+//! The database operates in *archive mode*: the headers, bodies, and storage of the finalized
+//! block and all of its ancestors are kept. Finalized blocks can be looked up by number using
+//! [`SqliteFullDatabase::best_block_hash_by_number`], and their storage can be queried using
+//! [`SqliteFullDatabase::block_storage_get`], [`SqliteFullDatabase::block_storage_next_key`], and
+//! [`SqliteFullDatabase::block_storage_closest_descendant_merkle_value`]. Only the blocks that
+//! aren't part of the finalized chain are removed, alongside with their storage, when
+//! [`SqliteFullDatabase::purge_finality_orphans`] is called.
 //!
 //! # About errors handling
 //!
@@ -124,6 +127,12 @@ impl SqliteFullDatabase {
         finalized_hash(&database)
     }
 
+    /// Returns the number of the finalized block in the database.
+    pub fn finalized_block_number(&self) -> Result<u64, CorruptedError> {
+        let database = self.database.lock();
+        finalized_num(&database)
+    }
+
     /// Returns the SCALE-encoded header of the given block, or `None` if the block is unknown.
     ///
     /// > **Note**: If this method is called twice times in a row with the same block hash, it
@@ -653,6 +662,86 @@ impl SqliteFullDatabase {
         Ok(())
     }
 
+    /// Returns the list of nodes of the peer-to-peer network that have been stored with
+    /// [`SqliteFullDatabase::insert_known_peers`], most recently seen first.
+    ///
+    /// A node with multiple addresses is yielded multiple times. At most `max_entries` items are
+    /// returned.
+    pub fn known_peers(&self, max_entries: usize) -> Result<Vec<KnownPeer>, CorruptedError> {
+        let connection = self.database.lock();
+
+        let peers = connection
+            .prepare_cached(r#"SELECT peer_id, address FROM peers ORDER BY last_seen DESC LIMIT ?"#)
+            .map_err(|err| CorruptedError::Internal(InternalError(err)))?
+            .query_map(
+                (i64::try_from(max_entries).unwrap_or(i64::max_value()),),
+                |row| {
+                    Ok(KnownPeer {
+                        peer_id: row.get::<_, Vec<u8>>(0)?,
+                        address: row.get::<_, Vec<u8>>(1)?,
+                    })
+                },
+            )
+            .map_err(|err| CorruptedError::Internal(InternalError(err)))?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|err| CorruptedError::Internal(InternalError(err)))?;
+
+        Ok(peers)
+    }
+
+    /// Inserts in the database the given nodes of the peer-to-peer network, or updates the time
+    /// when they were last seen if they were already in the database.
+    ///
+    /// Each item is the binary encoding of a `PeerId` and the binary encoding of one of its
+    /// multiaddresses. `last_seen` is a UNIX timestamp in seconds.
+    ///
+    /// After the insertion, the least recently seen entries are removed so that the database
+    /// contains at most `max_entries` entries.
+    pub fn insert_known_peers<'a>(
+        &self,
+        peers: impl Iterator<Item = (&'a [u8], &'a [u8])>,
+        last_seen: u64,
+        max_entries: usize,
+    ) -> Result<(), CorruptedError> {
+        let mut database = self.database.lock();
+
+        let transaction = database
+            .transaction()
+            .map_err(|err| CorruptedError::Internal(InternalError(err)))?;
+
+        {
+            let mut statement = transaction
+                .prepare_cached(
+                    "INSERT INTO peers(peer_id, address, last_seen) VALUES(?, ?, ?) \
+                        ON CONFLICT(peer_id, address) DO UPDATE SET last_seen = excluded.last_seen",
+                )
+                .map_err(|err| CorruptedError::Internal(InternalError(err)))?;
+            for (peer_id, address) in peers {
+                statement
+                    .execute((
+                        peer_id,
+                        address,
+                        i64::try_from(last_seen).unwrap_or(i64::max_value()),
+                    ))
+                    .map_err(|err| CorruptedError::Internal(InternalError(err)))?;
+            }
+        }
+
+        transaction
+            .prepare_cached(
+                r#"DELETE FROM peers WHERE ROWID NOT IN (SELECT ROWID FROM peers ORDER BY last_seen DESC LIMIT ?)"#,
+            )
+            .map_err(|err| CorruptedError::Internal(InternalError(err)))?
+            .execute((i64::try_from(max_entries).unwrap_or(i64::max_value()),))
+            .map_err(|err| CorruptedError::Internal(InternalError(err)))?;
+
+        transaction
+            .commit()
+            .map_err(|err| CorruptedError::Internal(InternalError(err)))?;
+
+        Ok(())
+    }
+
     /// Returns the value associated with a node of the trie of the given block.
     ///
     /// `parent_tries_paths_nibbles` is a list of keys to follow in order to find the root of the
@@ -1031,6 +1120,15 @@ impl Drop for SqliteFullDatabase {
     }
 }
 
+/// Node of the peer-to-peer network, as returned by [`SqliteFullDatabase::known_peers`].
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct KnownPeer {
+    /// Binary encoding of the `PeerId` of the node.
+    pub peer_id: Vec<u8>,
+    /// Binary encoding of one of the multiaddresses of the node.
+    pub address: Vec<u8>,
+}
+
 pub struct InsertTrieNode<'a> {
     pub merkle_value: Cow<'a, [u8]>,
     pub partial_key_nibbles: Cow<'a, [u8]>,
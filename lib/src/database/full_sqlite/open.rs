@@ -266,6 +266,32 @@ PRAGMA user_version = 1;
             .map_err(InternalError)?
     }
 
+    if user_version <= 1 {
+        database
+            .execute_batch(
+                r#"
+/*
+List of nodes of the peer-to-peer network that have recently been seen, and their addresses.
+Used in order to connect to the peer-to-peer network after a restart without having to rely on
+the bootnodes.
+`peer_id` and `address` are the binary encodings of the `PeerId` and multiaddress of the node.
+`last_seen` is the UNIX timestamp, in seconds, of the last time the node was seen.
+*/
+CREATE TABLE peers(
+    peer_id BLOB NOT NULL,
+    address BLOB NOT NULL,
+    last_seen INTEGER NOT NULL,
+    PRIMARY KEY(peer_id, address)
+);
+CREATE INDEX peers_by_last_seen ON peers(last_seen);
+
+PRAGMA user_version = 2;
+
+        "#,
+            )
+            .map_err(InternalError)?
+    }
+
     let is_empty = database
         .prepare_cached("SELECT COUNT(*) FROM meta WHERE key = ?")
         .map_err(InternalError)?
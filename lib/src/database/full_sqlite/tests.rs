@@ -17,7 +17,9 @@
 
 #![cfg(test)]
 
-use super::{open, Config, ConfigTy, DatabaseOpen, InsertTrieNode, InsertTrieNodeStorageValue};
+use super::{
+    open, Config, ConfigTy, DatabaseOpen, InsertTrieNode, InsertTrieNodeStorageValue, KnownPeer,
+};
 use crate::{chain::chain_information, header, trie};
 
 use alloc::borrow::Cow;
@@ -272,3 +274,81 @@ fn empty_database_fill_then_query() {
         }
     }
 }
+
+#[test]
+fn known_peers_most_recent_kept() {
+    let DatabaseOpen::Empty(empty_db) = open(Config {
+        block_number_bytes: 4,
+        cache_size: 2 * 1024 * 1024,
+        ty: ConfigTy::Memory,
+    })
+    .unwrap() else {
+        panic!()
+    };
+
+    let open_db = empty_db
+        .initialize(
+            chain_information::ChainInformationRef {
+                finalized_block_header: header::HeaderRef {
+                    number: 0,
+                    extrinsics_root: &[0; 32],
+                    parent_hash: &[0; 32],
+                    state_root: &[1; 32],
+                    digest: header::DigestRef::empty(),
+                },
+                consensus: chain_information::ChainInformationConsensusRef::Unknown,
+                finality: chain_information::ChainInformationFinalityRef::Outsourced,
+            },
+            iter::empty(),
+            None,
+            iter::once(InsertTrieNode {
+                merkle_value: Cow::Borrowed(&[1; 32]),
+                partial_key_nibbles: Cow::Borrowed(&[]),
+                children_merkle_values: array::from_fn(|_| None),
+                storage_value: InsertTrieNodeStorageValue::NoValue,
+            }),
+            0,
+        )
+        .unwrap();
+
+    assert!(open_db.known_peers(16).unwrap().is_empty());
+
+    open_db
+        .insert_known_peers(
+            [
+                (&b"peer1"[..], &b"addr1"[..]),
+                (&b"peer2"[..], &b"addr2"[..]),
+            ]
+            .into_iter(),
+            10,
+            2,
+        )
+        .unwrap();
+    open_db
+        .insert_known_peers(
+            [
+                (&b"peer1"[..], &b"addr1"[..]),
+                (&b"peer3"[..], &b"addr3"[..]),
+            ]
+            .into_iter(),
+            20,
+            2,
+        )
+        .unwrap();
+
+    let mut peers = open_db.known_peers(16).unwrap();
+    peers.sort();
+    assert_eq!(
+        peers,
+        vec![
+            KnownPeer {
+                peer_id: b"peer1".to_vec(),
+                address: b"addr1".to_vec()
+            },
+            KnownPeer {
+                peer_id: b"peer3".to_vec(),
+                address: b"addr3".to_vec()
+            }
+        ]
+    );
+}
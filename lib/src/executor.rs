@@ -31,6 +31,8 @@
 mod allocator; // TODO: make public after refactoring
 pub mod host;
 pub mod runtime_host;
+pub mod runtime_upgrade;
+pub mod scale_value;
 pub mod storage_diff;
 pub mod trie_root_calculator;
 pub mod vm;
@@ -279,6 +279,13 @@ struct VmCommon {
     /// Total number of pages of Wasm memory. This is equal to `heap_base / 64k` (rounded up) plus
     /// `heap_pages`.
     memory_total_pages: HeapPages,
+
+    /// See [`HostVmPrototype::set_max_memory_pages`].
+    max_memory_pages: Option<HeapPages>,
+
+    /// Size of the Wasm memory at the end of the latest call. See
+    /// [`HostVmPrototype::memory_size`].
+    memory_size: HeapPages,
 }
 
 impl HostVmPrototype {
@@ -382,6 +389,8 @@ impl HostVmPrototype {
                 registered_functions,
                 heap_pages: config.heap_pages,
                 memory_total_pages,
+                max_memory_pages: None,
+                memory_size: HeapPages::new(0),
             }),
         };
 
@@ -436,6 +445,31 @@ impl HostVmPrototype {
         self.common.heap_pages
     }
 
+    /// Returns the size of the Wasm memory, in pages of 64kiB, as it was at the end of the
+    /// latest call. Returns 0 if no call has been performed yet.
+    ///
+    /// > **Note**: The memory of the virtual machine is grown on demand, and this value is
+    /// >           therefore typically lower than the total number of pages that the runtime is
+    /// >           allowed to use.
+    pub fn memory_size(&self) -> HeapPages {
+        self.common.memory_size
+    }
+
+    /// Returns the value previously passed to [`HostVmPrototype::set_max_memory_pages`].
+    pub fn max_memory_pages(&self) -> Option<HeapPages> {
+        self.common.max_memory_pages
+    }
+
+    /// Sets the maximum size of the Wasm memory, in pages of 64kiB, for the calls started after
+    /// this function has been called. `None` means no limit other than the one imposed by the
+    /// heap pages and the Wasm module.
+    ///
+    /// If the memory of the virtual machine grows above this limit, execution stops with an
+    /// [`Error::MemoryLimitExceeded`].
+    pub fn set_max_memory_pages(&mut self, max: Option<HeapPages>) {
+        self.common.max_memory_pages = max;
+    }
+
     /// Returns the runtime version found in the module.
     pub fn runtime_version(&self) -> &CoreVersion {
         self.common.runtime_version.as_ref().unwrap()
@@ -686,6 +720,20 @@ impl ReadyToRun {
     }
 
     fn run_once(mut self) -> HostVm {
+        // The memory might have been grown by the host functions since the previous iteration.
+        if let Some(max_memory_pages) = self.inner.common.max_memory_pages {
+            let memory_size = self.inner.vm.memory_size();
+            if memory_size > max_memory_pages {
+                return HostVm::Error {
+                    prototype: self.inner.into_prototype(),
+                    error: Error::MemoryLimitExceeded {
+                        memory_pages: u32::from(memory_size),
+                        max_memory_pages: u32::from(max_memory_pages),
+                    },
+                };
+            }
+        }
+
         // `vm::ExecOutcome::Interrupted` is by far the variant that requires the most
         // handling code. As such, special-case all other variants before.
         let (id, params) = match self.inner.vm.run(self.resume_value) {
@@ -3798,7 +3846,8 @@ impl Inner {
     }
 
     /// Turns the virtual machine back into a prototype.
-    fn into_prototype(self) -> HostVmPrototype {
+    fn into_prototype(mut self) -> HostVmPrototype {
+        self.common.memory_size = self.vm.memory_size();
         HostVmPrototype {
             vm_proto: self.vm.into_prototype(),
             common: self.common,
@@ -3969,6 +4018,17 @@ pub enum Error {
         /// Name of the function being called.
         function: &'static str,
     },
+    /// The memory of the virtual machine has grown above the limit passed to
+    /// [`HostVmPrototype::set_max_memory_pages`].
+    #[display(
+        fmt = "Memory size ({memory_pages} pages) exceeds the limit of {max_memory_pages} pages"
+    )]
+    MemoryLimitExceeded {
+        /// Number of 64kiB pages of the memory of the virtual machine.
+        memory_pages: u32,
+        /// Maximum number of pages that was configured.
+        max_memory_pages: u32,
+    },
 }
 
 // Glue between the `allocator` module and the `vm` module.
@@ -685,4 +685,70 @@ fn unresolved_host_function_called() {
     }
 }
 
+#[test]
+fn memory_limit_exceeded() {
+    let module_bytes = with_core_version_custom_sections(
+        wat::parse_str(
+            r#"
+    (module
+        (type (;0;) (func (param i32 i32) (result i64)))
+        (func (;0;) (type 0) (param i32 i32) (result i64)
+          i64.const 0)
+        (memory (;0;) 16)
+        (global (;0;) i32 (i32.const 1048576))
+        (export "memory" (memory 0))
+        (export "test" (func 0))
+        (export "__heap_base" (global 0))
+    )
+    "#,
+        )
+        .unwrap(),
+    );
+
+    // Writing the input data requires growing the memory above 16 pages.
+    let input_data = vec![0; 200000];
+
+    for exec_hint in ExecHint::available_engines() {
+        let mut proto = HostVmPrototype::new(Config {
+            allow_unresolved_imports: false,
+            exec_hint,
+            heap_pages: HeapPages::new(1024),
+            module: &module_bytes,
+        })
+        .unwrap();
+
+        let mut vm = HostVm::from(proto.run("test", &input_data).unwrap());
+        proto = loop {
+            match vm {
+                HostVm::ReadyToRun(r) => vm = r.run(),
+                HostVm::Finished(v) => break v.into_prototype(),
+                _ => unreachable!(),
+            }
+        };
+
+        let memory_size = proto.memory_size();
+        assert!(memory_size > HeapPages::new(16));
+
+        proto.set_max_memory_pages(Some(HeapPages::new(16)));
+        let mut vm = HostVm::from(proto.run("test", &input_data).unwrap());
+        loop {
+            match vm {
+                HostVm::ReadyToRun(r) => vm = r.run(),
+                HostVm::Error {
+                    error:
+                        Error::MemoryLimitExceeded {
+                            memory_pages,
+                            max_memory_pages: 16,
+                        },
+                    ..
+                } => {
+                    assert_eq!(memory_pages, u32::from(memory_size));
+                    break;
+                }
+                _ => unreachable!(),
+            }
+        }
+    }
+}
+
 // TODO: consider more tests for the other errors here, or add them on a host-function case-by-case basis
@@ -28,7 +28,8 @@
 //! - Keeps track of the changes to the storage and off-chain storage made by the execution, and
 //!   provides them at the end. Any storage access takes into account the intermediary list of
 //!   changes.
-//! - Keeps track of the logs generated by the call and concatenates them into a [`String`].
+//! - Keeps track of the logs generated by the call and concatenates them into a [`String`] of
+//!   bounded size.
 //! - Automatically handles some externalities, such as calculating the Merkle root or storage
 //!   transactions.
 //!
@@ -121,6 +122,7 @@ pub fn run(
         offchain_storage_changes: BTreeMap::new(),
         root_calculation: None,
         logs: String::new(),
+        logs_truncated: false,
         max_log_level: config.max_log_level,
         calculate_trie_changes: config.calculate_trie_changes,
     }
@@ -139,7 +141,12 @@ pub struct Success {
     /// [`Success::storage_changes`] should store this version alongside with them.
     pub state_trie_version: TrieEntryVersion,
     /// Concatenation of all the log messages printed by the runtime.
+    ///
+    /// The size of this string is bounded. See [`Success::logs_truncated`].
     pub logs: String,
+    /// `true` if the runtime has generated more logs than can fit in [`Success::logs`], in which
+    /// case the log messages after the limit has been reached have been discarded.
+    pub logs_truncated: bool,
 }
 
 /// See [`Success::storage_changes`].
@@ -441,8 +448,6 @@ pub enum ErrorDetail {
         /// Concatenation of all the log messages printed by the runtime.
         logs: String,
     },
-    /// Size of the logs generated by the runtime exceeds the limit.
-    LogsTooLong,
 }
 
 /// Current state of the execution.
@@ -1230,6 +1235,9 @@ struct Inner {
     /// Concatenation of all the log messages generated by the runtime.
     logs: String,
 
+    /// `true` if [`Inner::logs`] has reached its maximum size. See [`Success::logs_truncated`].
+    logs_truncated: bool,
+
     /// Value provided by [`Config::max_log_level`].
     max_log_level: u32,
 
@@ -1543,6 +1551,7 @@ impl Inner {
                         },
                         state_trie_version: self.state_trie_version,
                         logs: self.logs,
+                        logs_truncated: self.logs_truncated,
                     }));
                 }
 
@@ -1752,37 +1761,7 @@ impl Inner {
                 }
 
                 host::HostVm::LogEmit(req) => {
-                    // We add a hardcoded limit to the logs generated by the runtime in order to
-                    // make sure that there is no memory leak. In practice, the runtime should
-                    // rarely log more than a few hundred bytes. This limit is hardcoded rather
-                    // than configurable because it is not expected to be reachable unless
-                    // something is very wrong.
-                    struct WriterWithMax<'a>(&'a mut String);
-                    impl<'a> fmt::Write for WriterWithMax<'a> {
-                        fn write_str(&mut self, s: &str) -> fmt::Result {
-                            if self.0.len().saturating_add(s.len()) >= 1024 * 1024 {
-                                return Err(fmt::Error);
-                            }
-                            self.0.push_str(s);
-                            Ok(())
-                        }
-                        fn write_char(&mut self, c: char) -> fmt::Result {
-                            if self.0.len().saturating_add(1) >= 1024 * 1024 {
-                                return Err(fmt::Error);
-                            }
-                            self.0.push(c);
-                            Ok(())
-                        }
-                    }
-                    match fmt::write(&mut WriterWithMax(&mut self.logs), format_args!("{req}")) {
-                        Ok(()) => {}
-                        Err(fmt::Error) => {
-                            return RuntimeHostVm::Finished(Err(Error {
-                                detail: ErrorDetail::LogsTooLong,
-                                prototype: host::HostVm::LogEmit(req).into_prototype(),
-                            }));
-                        }
-                    }
+                    append_log(&mut self.logs, &mut self.logs_truncated, &req);
                     self.vm = req.resume();
                 }
                 host::HostVm::OffchainTimestamp(req) => {
@@ -1851,3 +1830,47 @@ fn append_to_storage_value(value: &mut Vec<u8>, to_add: &[u8]) {
     value[..new_len_encoded_size].copy_from_slice(new_len_encoded.as_ref());
     value.extend_from_slice(to_add);
 }
+
+/// Maximum size, in bytes, of the logs generated by the runtime.
+///
+/// We add a hardcoded limit to the logs generated by the runtime in order to make sure that
+/// there is no memory leak. In practice, the runtime should rarely log more than a few hundred
+/// bytes. This limit is hardcoded rather than configurable because it is not expected to be
+/// reachable unless something is very wrong.
+const MAX_LOGS_LEN: usize = 1024 * 1024;
+
+/// Appends a log message generated by the runtime to `logs`.
+///
+/// If the message doesn't fit within [`MAX_LOGS_LEN`], it is discarded and `logs_truncated` is
+/// set to `true`. Once the limit has been reached, further log messages are discarded rather
+/// than making the call fail, as logs are merely informative. Log messages are never partially
+/// written.
+fn append_log(logs: &mut String, logs_truncated: &mut bool, message: impl fmt::Display) {
+    struct WriterWithMax<'a>(&'a mut String);
+    impl<'a> fmt::Write for WriterWithMax<'a> {
+        fn write_str(&mut self, s: &str) -> fmt::Result {
+            if self.0.len().saturating_add(s.len()) >= MAX_LOGS_LEN {
+                return Err(fmt::Error);
+            }
+            self.0.push_str(s);
+            Ok(())
+        }
+        fn write_char(&mut self, c: char) -> fmt::Result {
+            if self.0.len().saturating_add(c.len_utf8()) >= MAX_LOGS_LEN {
+                return Err(fmt::Error);
+            }
+            self.0.push(c);
+            Ok(())
+        }
+    }
+
+    if *logs_truncated {
+        return;
+    }
+
+    let logs_len_before = logs.len();
+    if fmt::write(&mut WriterWithMax(logs), format_args!("{message}")).is_err() {
+        logs.truncate(logs_len_before);
+        *logs_truncated = true;
+    }
+}
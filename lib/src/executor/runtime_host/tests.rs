@@ -17,7 +17,8 @@
 
 #![cfg(test)]
 
-//! The test in this module reads various JSON files containing test fixtures and executes them.
+//! The main test in this module reads various JSON files containing test fixtures and executes
+//! them.
 //!
 //! Each test fixture contains a block (header and body), plus the storage of its parent. The
 //! test consists in executing the block, to make sure that the state trie root matches the one
@@ -25,9 +26,9 @@
 
 use core::{iter, ops};
 
-use super::{run, Config, RuntimeHostVm};
+use super::{append_log, run, Config, RuntimeHostVm, MAX_LOGS_LEN};
 use crate::{executor::host, trie};
-use alloc::collections::BTreeMap;
+use alloc::{collections::BTreeMap, string::String};
 
 #[test]
 fn execute_blocks() {
@@ -234,3 +235,28 @@ impl<'a> serde::Deserialize<'a> for HexString {
         Ok(HexString(bytes))
     }
 }
+
+#[test]
+fn logs_truncated() {
+    let mut logs = String::new();
+    let mut logs_truncated = false;
+
+    append_log(&mut logs, &mut logs_truncated, "hello\n");
+    assert_eq!(logs, "hello\n");
+    assert!(!logs_truncated);
+
+    // A message that doesn't fit is entirely discarded rather than partially written.
+    let filler = "a".repeat(MAX_LOGS_LEN - logs.len() - 2);
+    append_log(&mut logs, &mut logs_truncated, &filler);
+    assert!(!logs_truncated);
+    let len_before = logs.len();
+    append_log(&mut logs, &mut logs_truncated, "world\n");
+    assert_eq!(logs.len(), len_before);
+    assert!(logs.ends_with('a'));
+    assert!(logs_truncated);
+
+    // Messages are ignored once the logs have been truncated, even if they would fit.
+    append_log(&mut logs, &mut logs_truncated, "!");
+    assert_eq!(logs.len(), len_before);
+    assert!(logs_truncated);
+}
@@ -0,0 +1,293 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Compatibility check between a runtime and a proposed replacement.
+//!
+//! Before a runtime upgrade is enacted on chain (typically by writing a new value to the `:code`
+//! storage item), it can be useful to verify that the proposed runtime code is compatible with
+//! the runtime currently in use. For example, a runtime upgrade that changes the `spec_name`
+//! of the chain, or that no longer provides an API that the current runtime provides, is almost
+//! always a mistake.
+//!
+//! The [`check`] function instantiates the proposed runtime code and compares its runtime
+//! version with the one of the current runtime. The [`compare`] function performs the same
+//! comparison but directly on runtime versions.
+
+use super::host::{
+    self,
+    runtime_version::{CoreVersionApisRefIter, CoreVersionRef},
+};
+use crate::trie::TrieEntryVersion;
+
+use alloc::{string::String, vec::Vec};
+
+/// Instantiates the proposed runtime code and compares its runtime version with the one of
+/// the current runtime.
+///
+/// Returns the list of differences between the two runtimes. See [`compare`].
+pub fn check(
+    current: &host::HostVmPrototype,
+    proposed: host::Config<impl AsRef<[u8]>>,
+) -> Result<Vec<Difference>, host::NewErr> {
+    let proposed = host::HostVmPrototype::new(proposed)?;
+    Ok(compare(
+        &current.runtime_version().decode(),
+        &proposed.runtime_version().decode(),
+    ))
+}
+
+/// Compares the runtime version of the current runtime with the one of a proposed runtime, and
+/// returns the list of differences between the two.
+///
+/// Use [`Difference::is_breaking`] to determine whether a difference is problematic.
+pub fn compare(current: &CoreVersionRef, proposed: &CoreVersionRef) -> Vec<Difference> {
+    let mut out = Vec::new();
+
+    if current.spec_name != proposed.spec_name {
+        out.push(Difference::SpecNameChanged {
+            current: current.spec_name.into(),
+            proposed: proposed.spec_name.into(),
+        });
+    }
+
+    if proposed.spec_version <= current.spec_version {
+        out.push(Difference::SpecVersionNotIncreased {
+            current: current.spec_version,
+            proposed: proposed.spec_version,
+        });
+    }
+
+    for api in current.apis.clone() {
+        // Ignore duplicate entries, in order to report each API only once.
+        if highest_api_version(&current.apis, &api.name_hash) != Some(api.version) {
+            continue;
+        }
+
+        match highest_api_version(&proposed.apis, &api.name_hash) {
+            None => out.push(Difference::ApiRemoved {
+                name_hash: api.name_hash,
+                version: api.version,
+            }),
+            Some(v) if v != api.version => out.push(Difference::ApiVersionChanged {
+                name_hash: api.name_hash,
+                current_version: api.version,
+                proposed_version: v,
+            }),
+            Some(_) => {}
+        }
+    }
+
+    for api in proposed.apis.clone() {
+        if highest_api_version(&proposed.apis, &api.name_hash) != Some(api.version) {
+            continue;
+        }
+
+        if highest_api_version(&current.apis, &api.name_hash).is_none() {
+            out.push(Difference::ApiAdded {
+                name_hash: api.name_hash,
+                version: api.version,
+            });
+        }
+    }
+
+    if current.transaction_version != proposed.transaction_version {
+        out.push(Difference::TransactionVersionChanged {
+            current: current.transaction_version,
+            proposed: proposed.transaction_version,
+        });
+    }
+
+    // As documented, a missing state version is equivalent to version 0.
+    let current_state_version = current.state_version.unwrap_or(TrieEntryVersion::V0);
+    let proposed_state_version = proposed.state_version.unwrap_or(TrieEntryVersion::V0);
+    if current_state_version != proposed_state_version {
+        out.push(Difference::StateVersionChanged {
+            current: current_state_version,
+            proposed: proposed_state_version,
+        });
+    }
+
+    out
+}
+
+/// Difference between the current runtime and a proposed runtime. See [`compare`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Difference {
+    /// The `spec_name` of the runtime is different. Nodes consider runtimes with different
+    /// names as belonging to different chains.
+    SpecNameChanged { current: String, proposed: String },
+    /// The `spec_version` of the proposed runtime isn't strictly superior to the current one.
+    /// Nodes rely on the `spec_version` being increased in order to detect runtime upgrades.
+    SpecVersionNotIncreased { current: u32, proposed: u32 },
+    /// An API provided by the current runtime is missing from the proposed runtime.
+    ApiRemoved {
+        /// See [`host::runtime_version::CoreVersionApi::name_hash`].
+        name_hash: [u8; 8],
+        /// Version of the API in the current runtime.
+        version: u32,
+    },
+    /// The version of an API is different between the two runtimes.
+    ApiVersionChanged {
+        /// See [`host::runtime_version::CoreVersionApi::name_hash`].
+        name_hash: [u8; 8],
+        /// Version of the API in the current runtime.
+        current_version: u32,
+        /// Version of the API in the proposed runtime.
+        proposed_version: u32,
+    },
+    /// An API is provided by the proposed runtime but not by the current runtime.
+    ApiAdded {
+        /// See [`host::runtime_version::CoreVersionApi::name_hash`].
+        name_hash: [u8; 8],
+        /// Version of the API in the proposed runtime.
+        version: u32,
+    },
+    /// The transaction version is different, meaning that the transactions generated for the
+    /// current runtime will be invalid once the proposed runtime is enacted.
+    TransactionVersionChanged {
+        current: Option<u32>,
+        proposed: Option<u32>,
+    },
+    /// The version of the state trie encoding is different.
+    StateVersionChanged {
+        current: TrieEntryVersion,
+        proposed: TrieEntryVersion,
+    },
+}
+
+impl Difference {
+    /// Returns `true` if this difference is likely to break the chain or the clients that
+    /// interact with it.
+    ///
+    /// Adding an API, increasing the version of an API, or changing the transaction or state
+    /// versions are normal parts of a runtime upgrade and aren't considered as breaking.
+    pub fn is_breaking(&self) -> bool {
+        match self {
+            Difference::SpecNameChanged { .. }
+            | Difference::SpecVersionNotIncreased { .. }
+            | Difference::ApiRemoved { .. } => true,
+            Difference::ApiVersionChanged {
+                current_version,
+                proposed_version,
+                ..
+            } => proposed_version < current_version,
+            Difference::ApiAdded { .. }
+            | Difference::TransactionVersionChanged { .. }
+            | Difference::StateVersionChanged { .. } => false,
+        }
+    }
+}
+
+/// Returns the highest version of the API with the given name hash, if any.
+fn highest_api_version(apis: &CoreVersionApisRefIter, name_hash: &[u8; 8]) -> Option<u32> {
+    apis.clone()
+        .filter(|api| api.name_hash == *name_hash)
+        .map(|api| api.version)
+        .max()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{compare, Difference};
+    use crate::executor::host::runtime_version::{
+        hash_api_name, CoreVersionApisRefIter, CoreVersionRef,
+    };
+
+    fn encode_apis(apis: &[(&str, u32)]) -> Vec<u8> {
+        let mut out = Vec::new();
+        for (name, version) in apis {
+            out.extend_from_slice(&hash_api_name(name));
+            out.extend_from_slice(&version.to_le_bytes());
+        }
+        out
+    }
+
+    fn version<'a>(spec_name: &'a str, spec_version: u32, apis: &'a [u8]) -> CoreVersionRef<'a> {
+        CoreVersionRef {
+            spec_name,
+            impl_name: "test",
+            authoring_version: 1,
+            spec_version,
+            impl_version: 0,
+            apis: CoreVersionApisRefIter::from_slice_no_length(apis).unwrap(),
+            transaction_version: Some(1),
+            state_version: None,
+        }
+    }
+
+    #[test]
+    fn compatible_upgrade() {
+        let current_apis = encode_apis(&[("Core", 4), ("Metadata", 1)]);
+        let proposed_apis = encode_apis(&[("Core", 4), ("Metadata", 2), ("BlockBuilder", 6)]);
+
+        let differences = compare(
+            &version("polkadot", 100, &current_apis),
+            &version("polkadot", 101, &proposed_apis),
+        );
+
+        assert_eq!(
+            differences,
+            vec![
+                Difference::ApiVersionChanged {
+                    name_hash: hash_api_name("Metadata"),
+                    current_version: 1,
+                    proposed_version: 2,
+                },
+                Difference::ApiAdded {
+                    name_hash: hash_api_name("BlockBuilder"),
+                    version: 6,
+                },
+            ]
+        );
+        assert!(differences.iter().all(|d| !d.is_breaking()));
+    }
+
+    #[test]
+    fn breaking_upgrade() {
+        let current_apis = encode_apis(&[("Core", 4), ("Metadata", 1)]);
+        let proposed_apis = encode_apis(&[("Core", 3)]);
+
+        let differences = compare(
+            &version("polkadot", 100, &current_apis),
+            &version("kusama", 100, &proposed_apis),
+        );
+
+        assert_eq!(
+            differences,
+            vec![
+                Difference::SpecNameChanged {
+                    current: "polkadot".into(),
+                    proposed: "kusama".into(),
+                },
+                Difference::SpecVersionNotIncreased {
+                    current: 100,
+                    proposed: 100,
+                },
+                Difference::ApiVersionChanged {
+                    name_hash: hash_api_name("Core"),
+                    current_version: 4,
+                    proposed_version: 3,
+                },
+                Difference::ApiRemoved {
+                    name_hash: hash_api_name("Metadata"),
+                    version: 1,
+                },
+            ]
+        );
+        assert!(differences.iter().all(|d| d.is_breaking()));
+    }
+}
@@ -0,0 +1,787 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Dynamic decoding of SCALE-encoded values.
+//!
+//! The SCALE encoding isn't self-describing: decoding a SCALE-encoded value requires knowing its
+//! type. The runtime metadata, as returned by the `Metadata_metadata` runtime function, contains
+//! a *types registry* describing all the types used by the runtime, such as the types of the
+//! events, of the calls, or of the storage items.
+//!
+//! This module decodes the types registry of the metadata, then uses it in order to decode
+//! arbitrary SCALE-encoded values into a [`Value`], which is a tree whose shape matches the type
+//! of the value. This is useful for example in order to display events or the outcome of a
+//! runtime call to a user.
+//!
+//! Only versions 14 and 15 of the metadata format are supported.
+//!
+//! # Usage
+//!
+//! Call [`decode_types_registry`] with the metadata, after its length prefix has been removed
+//! (see [`crate::json_rpc::methods::remove_metadata_length_prefix`]), then
+//! [`TypesRegistry::decode_value`] with the identifier of the type of the value to decode.
+
+use crate::util;
+
+use alloc::{string::String, vec, vec::Vec};
+use core::str;
+
+/// Decodes the types registry found in the given metadata.
+///
+/// The metadata must start with the `meta` magic number, in other words its length prefix must
+/// have been removed. Only the types registry is decoded, and the rest of the metadata is
+/// ignored.
+pub fn decode_types_registry(metadata: &[u8]) -> Result<TypesRegistry<'_>, RegistryDecodeError> {
+    let (after_header, version) = match nom::sequence::preceded(
+        nom::bytes::streaming::tag::<_, _, nom::error::Error<&[u8]>>(&b"meta"[..]),
+        nom::number::streaming::u8,
+    )(metadata)
+    {
+        Ok(v) => v,
+        Err(_) => return Err(RegistryDecodeError::InvalidMagicNumber),
+    };
+
+    if !matches!(version, 14 | 15) {
+        return Err(RegistryDecodeError::UnsupportedVersion(version));
+    }
+
+    let (_, types) = nom::multi::length_count(
+        util::nom_scale_compact_usize,
+        registry_type::<nom::error::Error<&[u8]>>,
+    )(after_header)
+    .map_err(|_| RegistryDecodeError::ParseError)?;
+
+    Ok(TypesRegistry { types })
+}
+
+/// Error potentially returned by [`decode_types_registry`].
+#[derive(Debug, Clone, derive_more::Display)]
+pub enum RegistryDecodeError {
+    /// Metadata doesn't start with the expected magic number.
+    InvalidMagicNumber,
+    /// Version of the metadata format isn't supported.
+    #[display(fmt = "Unsupported metadata version: {_0}")]
+    UnsupportedVersion(u8),
+    /// Failed to parse the types registry.
+    ParseError,
+}
+
+/// Types registry of a runtime. See [`decode_types_registry`].
+#[derive(Debug, Clone)]
+pub struct TypesRegistry<'a> {
+    /// List of types and their identifier. Normally ordered by identifier, but this isn't
+    /// guaranteed.
+    types: Vec<(u32, Type<'a>)>,
+}
+
+impl<'a> TypesRegistry<'a> {
+    /// Returns the path of the type with the given identifier, for example
+    /// `["sp_runtime", "DispatchError"]`. Returns `None` if the type is unknown.
+    ///
+    /// The path is empty for types that aren't defined by the runtime, such as tuples or
+    /// primitive types.
+    pub fn type_path(&self, type_id: u32) -> Option<&[&'a str]> {
+        self.get(type_id).map(|ty| &ty.path[..])
+    }
+
+    /// Decodes the given SCALE-encoded value, whose type is the type with the given identifier.
+    ///
+    /// Returns an error if the value doesn't match its type or if `scale_encoded` contains more
+    /// bytes than the value.
+    pub fn decode_value(
+        &self,
+        type_id: u32,
+        scale_encoded: &[u8],
+    ) -> Result<Value<'a>, DecodeValueError> {
+        match self.decode_value_partial(type_id, scale_encoded)? {
+            (value, []) => Ok(value),
+            (_, _) => Err(DecodeValueError::TrailingBytes),
+        }
+    }
+
+    /// Decodes the SCALE-encoded value found at the start of `scale_encoded`, whose type is the
+    /// type with the given identifier.
+    ///
+    /// Contrary to [`TypesRegistry::decode_value`], doesn't return an error if `scale_encoded`
+    /// is too long but returns the remainder.
+    pub fn decode_value_partial<'b>(
+        &self,
+        type_id: u32,
+        scale_encoded: &'b [u8],
+    ) -> Result<(Value<'a>, &'b [u8]), DecodeValueError> {
+        let (rest, value) = self.value(type_id, scale_encoded, 0)?;
+        Ok((value, rest))
+    }
+
+    fn get(&self, type_id: u32) -> Option<&Type<'a>> {
+        // Types are normally ordered by identifier, but this isn't guaranteed.
+        match self
+            .types
+            .get(usize::try_from(type_id).unwrap_or(usize::max_value()))
+        {
+            Some((id, ty)) if *id == type_id => Some(ty),
+            _ => self
+                .types
+                .iter()
+                .find(|(id, _)| *id == type_id)
+                .map(|(_, ty)| ty),
+        }
+    }
+
+    fn value<'b>(
+        &self,
+        type_id: u32,
+        bytes: &'b [u8],
+        depth: u32,
+    ) -> Result<(&'b [u8], Value<'a>), DecodeValueError> {
+        if depth >= MAX_VALUE_DEPTH {
+            return Err(DecodeValueError::TooDeep);
+        }
+
+        let ty = self
+            .get(type_id)
+            .ok_or(DecodeValueError::UnknownTypeId(type_id))?;
+
+        match &ty.def {
+            TypeDef::Composite(fields) => {
+                let (bytes, fields) = self.fields(fields, bytes, depth)?;
+                Ok((bytes, Value::Composite(fields)))
+            }
+            TypeDef::Variant(variants) => {
+                let (bytes, index) = take_array::<1>(bytes)?;
+                let variant = variants.iter().find(|v| v.index == index[0]).ok_or(
+                    DecodeValueError::UnknownVariant {
+                        type_id,
+                        index: index[0],
+                    },
+                )?;
+                let (bytes, fields) = self.fields(&variant.fields, bytes, depth)?;
+                Ok((
+                    bytes,
+                    Value::Variant {
+                        name: variant.name,
+                        index: variant.index,
+                        fields,
+                    },
+                ))
+            }
+            TypeDef::Sequence(item_type_id) => {
+                let (mut bytes, len) = compact_usize(bytes)?;
+                // The length is untrusted, and is thus not used to pre-allocate.
+                let mut items = Vec::new();
+                for _ in 0..len {
+                    let (rest, item) = self.value(*item_type_id, bytes, depth + 1)?;
+                    items.push(item);
+                    bytes = rest;
+                }
+                Ok((bytes, Value::Sequence(items)))
+            }
+            TypeDef::Array { len, item_type_id } => {
+                let mut bytes = bytes;
+                let mut items = Vec::new();
+                for _ in 0..*len {
+                    let (rest, item) = self.value(*item_type_id, bytes, depth + 1)?;
+                    items.push(item);
+                    bytes = rest;
+                }
+                Ok((bytes, Value::Sequence(items)))
+            }
+            TypeDef::Tuple(item_type_ids) => {
+                let mut bytes = bytes;
+                let mut items = Vec::with_capacity(item_type_ids.len());
+                for item_type_id in item_type_ids {
+                    let (rest, item) = self.value(*item_type_id, bytes, depth + 1)?;
+                    items.push((None, item));
+                    bytes = rest;
+                }
+                Ok((bytes, Value::Composite(items)))
+            }
+            TypeDef::Primitive(primitive) => primitive_value(*primitive, bytes),
+            TypeDef::Compact(inner_type_id) => {
+                let (bytes, number) = util::nom_scale_compact_u128::<nom::error::Error<&[u8]>>(
+                    bytes,
+                )
+                .map_err(|err| match err {
+                    nom::Err::Incomplete(_) => DecodeValueError::UnexpectedEnd,
+                    nom::Err::Error(_) | nom::Err::Failure(_) => DecodeValueError::InvalidCompact,
+                })?;
+                Ok((
+                    bytes,
+                    self.compact_value(*inner_type_id, number, depth + 1)?,
+                ))
+            }
+            TypeDef::BitSequence {
+                store_type_id,
+                order_type_id,
+            } => {
+                let store_bytes = match self.get(*store_type_id).map(|ty| &ty.def) {
+                    Some(TypeDef::Primitive(Primitive::U8)) => 1,
+                    Some(TypeDef::Primitive(Primitive::U16)) => 2,
+                    Some(TypeDef::Primitive(Primitive::U32)) => 4,
+                    Some(TypeDef::Primitive(Primitive::U64)) => 8,
+                    _ => return Err(DecodeValueError::UnsupportedBitSequence(type_id)),
+                };
+                let msb_first = match self
+                    .get(*order_type_id)
+                    .and_then(|ty| ty.path.last().copied())
+                {
+                    Some("Lsb0") => false,
+                    Some("Msb0") => true,
+                    _ => return Err(DecodeValueError::UnsupportedBitSequence(type_id)),
+                };
+
+                let (bytes, num_bits) = compact_usize(bytes)?;
+                let store_bits = store_bytes * 8;
+                let num_words = num_bits / store_bits + usize::from(num_bits % store_bits != 0);
+                let (bytes, data) = take(
+                    bytes,
+                    num_words
+                        .checked_mul(store_bytes)
+                        .ok_or(DecodeValueError::UnexpectedEnd)?,
+                )?;
+
+                let bits = data
+                    .chunks(store_bytes)
+                    .flat_map(|word| {
+                        let mut word_bytes = [0; 8];
+                        word_bytes[..store_bytes].copy_from_slice(word);
+                        let word = u64::from_le_bytes(word_bytes);
+                        (0..store_bits).map(move |bit| {
+                            let shift = if msb_first { store_bits - 1 - bit } else { bit };
+                            (word >> shift) & 1 == 1
+                        })
+                    })
+                    .take(num_bits)
+                    .collect();
+                Ok((bytes, Value::BitSequence(bits)))
+            }
+        }
+    }
+
+    fn fields<'b>(
+        &self,
+        fields: &[Field<'a>],
+        mut bytes: &'b [u8],
+        depth: u32,
+    ) -> Result<(&'b [u8], Fields<'a>), DecodeValueError> {
+        let mut out = Vec::with_capacity(fields.len());
+        for field in fields {
+            let (rest, value) = self.value(field.type_id, bytes, depth + 1)?;
+            out.push((field.name, value));
+            bytes = rest;
+        }
+        Ok((bytes, out))
+    }
+
+    /// Builds the value of a SCALE-compact-encoded number whose type is the given type. The type
+    /// is either an unsigned integer, or a structure or tuple wrapping a single unsigned integer.
+    fn compact_value(
+        &self,
+        type_id: u32,
+        number: u128,
+        depth: u32,
+    ) -> Result<Value<'a>, DecodeValueError> {
+        if depth >= MAX_VALUE_DEPTH {
+            return Err(DecodeValueError::TooDeep);
+        }
+
+        match self
+            .get(type_id)
+            .map(|ty| &ty.def)
+            .ok_or(DecodeValueError::UnknownTypeId(type_id))?
+        {
+            TypeDef::Primitive(
+                Primitive::U8 | Primitive::U16 | Primitive::U32 | Primitive::U64 | Primitive::U128,
+            ) => Ok(Value::Unsigned(number)),
+            TypeDef::Composite(fields) if fields.len() == 1 => Ok(Value::Composite(vec![(
+                fields[0].name,
+                self.compact_value(fields[0].type_id, number, depth + 1)?,
+            )])),
+            TypeDef::Tuple(item_type_ids) if item_type_ids.len() == 1 => {
+                Ok(Value::Composite(vec![(
+                    None,
+                    self.compact_value(item_type_ids[0], number, depth + 1)?,
+                )]))
+            }
+            _ => Err(DecodeValueError::InvalidCompactType(type_id)),
+        }
+    }
+}
+
+/// Maximum number of nested values. Values nested deeper than this can't be decoded.
+const MAX_VALUE_DEPTH: u32 = 128;
+
+/// Error potentially returned when decoding a value.
+#[derive(Debug, Clone, PartialEq, Eq, derive_more::Display)]
+pub enum DecodeValueError {
+    /// A type refers to a type that isn't in the types registry.
+    #[display(fmt = "Unknown type identifier: {_0}")]
+    UnknownTypeId(u32),
+    /// The SCALE-encoded value is shorter than expected.
+    UnexpectedEnd,
+    /// The SCALE-encoded value is longer than expected.
+    TrailingBytes,
+    /// The index of an enum variant doesn't correspond to any variant.
+    #[display(fmt = "Unknown variant index {index} for type {type_id}")]
+    UnknownVariant {
+        /// Identifier of the enum type.
+        type_id: u32,
+        /// Index found in the SCALE-encoded value.
+        index: u8,
+    },
+    /// A boolean is neither 0 nor 1.
+    InvalidBool,
+    /// A character isn't a valid Unicode scalar value.
+    InvalidChar,
+    /// A string isn't valid UTF-8.
+    InvalidUtf8,
+    /// A SCALE-compact-encoded number is invalid.
+    InvalidCompact,
+    /// A SCALE-compact-encoded value has a type that isn't an unsigned integer.
+    #[display(fmt = "Type {_0} can't be SCALE-compact-encoded")]
+    InvalidCompactType(u32),
+    /// The storage or order type of a bit sequence isn't supported.
+    #[display(fmt = "Unsupported bit sequence type: {_0}")]
+    UnsupportedBitSequence(u32),
+    /// Values are nested too deeply.
+    TooDeep,
+}
+
+/// Decoded SCALE-encoded value. See [`TypesRegistry::decode_value`].
+///
+/// Names of fields and variants are borrowed from the metadata.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Value<'a> {
+    /// Boolean.
+    Bool(bool),
+    /// Unicode character.
+    Char(char),
+    /// UTF-8 string.
+    Str(String),
+    /// Unsigned integer of at most 128 bits, including SCALE-compact-encoded integers.
+    Unsigned(u128),
+    /// Signed integer of at most 128 bits.
+    Signed(i128),
+    /// Unsigned 256 bits integer, in little endian.
+    U256([u8; 32]),
+    /// Signed 256 bits integer, in little endian.
+    I256([u8; 32]),
+    /// Structure or tuple. Contains the list of fields and their name. The names are `None` for
+    /// tuples and tuple structures.
+    Composite(Fields<'a>),
+    /// Variant of an enum.
+    Variant {
+        /// Name of the variant.
+        name: &'a str,
+        /// Index of the variant, as found in the SCALE encoding.
+        index: u8,
+        /// List of fields of the variant and their name. The names are `None` for tuple
+        /// variants.
+        fields: Fields<'a>,
+    },
+    /// Sequence or fixed-size array.
+    Sequence(Vec<Value<'a>>),
+    /// Sequence of bits.
+    BitSequence(Vec<bool>),
+}
+
+/// List of fields of a [`Value::Composite`] or [`Value::Variant`], and their name.
+pub type Fields<'a> = Vec<(Option<&'a str>, Value<'a>)>;
+
+/// Type of the types registry.
+#[derive(Debug, Clone)]
+struct Type<'a> {
+    /// Path of the type, for example `["sp_runtime", "DispatchError"]`.
+    path: Vec<&'a str>,
+    /// Definition of the type.
+    def: TypeDef<'a>,
+}
+
+#[derive(Debug, Clone)]
+enum TypeDef<'a> {
+    Composite(Vec<Field<'a>>),
+    Variant(Vec<Variant<'a>>),
+    Sequence(u32),
+    Array {
+        len: u32,
+        item_type_id: u32,
+    },
+    Tuple(Vec<u32>),
+    Primitive(Primitive),
+    Compact(u32),
+    BitSequence {
+        store_type_id: u32,
+        order_type_id: u32,
+    },
+}
+
+#[derive(Debug, Clone)]
+struct Field<'a> {
+    name: Option<&'a str>,
+    type_id: u32,
+}
+
+#[derive(Debug, Clone)]
+struct Variant<'a> {
+    name: &'a str,
+    index: u8,
+    fields: Vec<Field<'a>>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Primitive {
+    Bool,
+    Char,
+    Str,
+    U8,
+    U16,
+    U32,
+    U64,
+    U128,
+    U256,
+    I8,
+    I16,
+    I32,
+    I64,
+    I128,
+    I256,
+}
+
+fn primitive_value<'a>(
+    primitive: Primitive,
+    bytes: &[u8],
+) -> Result<(&[u8], Value<'a>), DecodeValueError> {
+    Ok(match primitive {
+        Primitive::Bool => {
+            let (bytes, [b]) = take_array::<1>(bytes)?;
+            match b {
+                0 => (bytes, Value::Bool(false)),
+                1 => (bytes, Value::Bool(true)),
+                _ => return Err(DecodeValueError::InvalidBool),
+            }
+        }
+        Primitive::Char => {
+            let (bytes, c) = take_array::<4>(bytes)?;
+            let c = char::from_u32(u32::from_le_bytes(c)).ok_or(DecodeValueError::InvalidChar)?;
+            (bytes, Value::Char(c))
+        }
+        Primitive::Str => {
+            let (bytes, len) = compact_usize(bytes)?;
+            let (bytes, s) = take(bytes, len)?;
+            let s = str::from_utf8(s).map_err(|_| DecodeValueError::InvalidUtf8)?;
+            (bytes, Value::Str(s.into()))
+        }
+        Primitive::U8 => {
+            let (bytes, n) = take_array::<1>(bytes)?;
+            (bytes, Value::Unsigned(u8::from_le_bytes(n).into()))
+        }
+        Primitive::U16 => {
+            let (bytes, n) = take_array::<2>(bytes)?;
+            (bytes, Value::Unsigned(u16::from_le_bytes(n).into()))
+        }
+        Primitive::U32 => {
+            let (bytes, n) = take_array::<4>(bytes)?;
+            (bytes, Value::Unsigned(u32::from_le_bytes(n).into()))
+        }
+        Primitive::U64 => {
+            let (bytes, n) = take_array::<8>(bytes)?;
+            (bytes, Value::Unsigned(u64::from_le_bytes(n).into()))
+        }
+        Primitive::U128 => {
+            let (bytes, n) = take_array::<16>(bytes)?;
+            (bytes, Value::Unsigned(u128::from_le_bytes(n)))
+        }
+        Primitive::U256 => {
+            let (bytes, n) = take_array::<32>(bytes)?;
+            (bytes, Value::U256(n))
+        }
+        Primitive::I8 => {
+            let (bytes, n) = take_array::<1>(bytes)?;
+            (bytes, Value::Signed(i8::from_le_bytes(n).into()))
+        }
+        Primitive::I16 => {
+            let (bytes, n) = take_array::<2>(bytes)?;
+            (bytes, Value::Signed(i16::from_le_bytes(n).into()))
+        }
+        Primitive::I32 => {
+            let (bytes, n) = take_array::<4>(bytes)?;
+            (bytes, Value::Signed(i32::from_le_bytes(n).into()))
+        }
+        Primitive::I64 => {
+            let (bytes, n) = take_array::<8>(bytes)?;
+            (bytes, Value::Signed(i64::from_le_bytes(n).into()))
+        }
+        Primitive::I128 => {
+            let (bytes, n) = take_array::<16>(bytes)?;
+            (bytes, Value::Signed(i128::from_le_bytes(n)))
+        }
+        Primitive::I256 => {
+            let (bytes, n) = take_array::<32>(bytes)?;
+            (bytes, Value::I256(n))
+        }
+    })
+}
+
+fn take(bytes: &[u8], len: usize) -> Result<(&[u8], &[u8]), DecodeValueError> {
+    if bytes.len() < len {
+        return Err(DecodeValueError::UnexpectedEnd);
+    }
+    let (taken, rest) = bytes.split_at(len);
+    Ok((rest, taken))
+}
+
+fn take_array<const N: usize>(bytes: &[u8]) -> Result<(&[u8], [u8; N]), DecodeValueError> {
+    let (rest, taken) = take(bytes, N)?;
+    Ok((rest, <[u8; N]>::try_from(taken).unwrap()))
+}
+
+fn compact_usize(bytes: &[u8]) -> Result<(&[u8], usize), DecodeValueError> {
+    util::nom_scale_compact_usize::<nom::error::Error<&[u8]>>(bytes).map_err(|err| match err {
+        nom::Err::Incomplete(_) => DecodeValueError::UnexpectedEnd,
+        nom::Err::Error(_) | nom::Err::Failure(_) => DecodeValueError::InvalidCompact,
+    })
+}
+
+fn registry_type<'a, E>(bytes: &'a [u8]) -> nom::IResult<&'a [u8], (u32, Type<'a>), E>
+where
+    E: nom::error::ParseError<&'a [u8]> + nom::error::FromExternalError<&'a [u8], str::Utf8Error>,
+{
+    let (bytes, id) = compact_u32(bytes)?;
+    let (bytes, path) =
+        nom::multi::length_count(util::nom_scale_compact_usize, util::nom_string_decode)(bytes)?;
+    // Generic parameters of the type.
+    let (bytes, _) = nom::multi::length_count(
+        util::nom_scale_compact_usize,
+        nom::sequence::tuple((
+            util::nom_string_decode,
+            util::nom_option_decode(compact_u32),
+        )),
+    )(bytes)?;
+
+    let (bytes, def) = match nom::number::streaming::u8(bytes)? {
+        (bytes, 0) => nom::combinator::map(
+            nom::multi::length_count(util::nom_scale_compact_usize, field),
+            TypeDef::Composite,
+        )(bytes)?,
+        (bytes, 1) => nom::combinator::map(
+            nom::multi::length_count(
+                util::nom_scale_compact_usize,
+                nom::combinator::map(
+                    nom::sequence::tuple((
+                        util::nom_string_decode,
+                        nom::multi::length_count(util::nom_scale_compact_usize, field),
+                        nom::number::streaming::u8,
+                        strings_list,
+                    )),
+                    |(name, fields, index, _)| Variant {
+                        name,
+                        index,
+                        fields,
+                    },
+                ),
+            ),
+            TypeDef::Variant,
+        )(bytes)?,
+        (bytes, 2) => nom::combinator::map(compact_u32, TypeDef::Sequence)(bytes)?,
+        (bytes, 3) => nom::combinator::map(
+            nom::sequence::tuple((nom::number::streaming::le_u32, compact_u32)),
+            |(len, item_type_id)| TypeDef::Array { len, item_type_id },
+        )(bytes)?,
+        (bytes, 4) => nom::combinator::map(
+            nom::multi::length_count(util::nom_scale_compact_usize, compact_u32),
+            TypeDef::Tuple,
+        )(bytes)?,
+        (bytes, 5) => nom::combinator::map_opt(nom::number::streaming::u8, |p| {
+            Some(TypeDef::Primitive(match p {
+                0 => Primitive::Bool,
+                1 => Primitive::Char,
+                2 => Primitive::Str,
+                3 => Primitive::U8,
+                4 => Primitive::U16,
+                5 => Primitive::U32,
+                6 => Primitive::U64,
+                7 => Primitive::U128,
+                8 => Primitive::U256,
+                9 => Primitive::I8,
+                10 => Primitive::I16,
+                11 => Primitive::I32,
+                12 => Primitive::I64,
+                13 => Primitive::I128,
+                14 => Primitive::I256,
+                _ => return None,
+            }))
+        })(bytes)?,
+        (bytes, 6) => nom::combinator::map(compact_u32, TypeDef::Compact)(bytes)?,
+        (bytes, 7) => nom::combinator::map(
+            nom::sequence::tuple((compact_u32, compact_u32)),
+            |(store_type_id, order_type_id)| TypeDef::BitSequence {
+                store_type_id,
+                order_type_id,
+            },
+        )(bytes)?,
+        (bytes, _) => {
+            return Err(nom::Err::Error(nom::error::make_error(
+                bytes,
+                nom::error::ErrorKind::Tag,
+            )))
+        }
+    };
+
+    // Documentation of the type.
+    let (bytes, _) = strings_list(bytes)?;
+
+    Ok((bytes, (id, Type { path, def })))
+}
+
+/// Decodes a field of a structure or enum variant.
+fn field<'a, E>(bytes: &'a [u8]) -> nom::IResult<&'a [u8], Field<'a>, E>
+where
+    E: nom::error::ParseError<&'a [u8]> + nom::error::FromExternalError<&'a [u8], str::Utf8Error>,
+{
+    nom::combinator::map(
+        nom::sequence::tuple((
+            util::nom_option_decode(util::nom_string_decode),
+            compact_u32,
+            util::nom_option_decode(util::nom_string_decode),
+            strings_list,
+        )),
+        |(name, type_id, _, _)| Field { name, type_id },
+    )(bytes)
+}
+
+/// Decodes and discards a SCALE-encoded list of strings.
+fn strings_list<'a, E>(bytes: &'a [u8]) -> nom::IResult<&'a [u8], (), E>
+where
+    E: nom::error::ParseError<&'a [u8]> + nom::error::FromExternalError<&'a [u8], str::Utf8Error>,
+{
+    let (mut bytes, num_strings) = util::nom_scale_compact_usize(bytes)?;
+    for _ in 0..num_strings {
+        bytes = util::nom_string_decode(bytes)?.0;
+    }
+    Ok((bytes, ()))
+}
+
+fn compact_u32<'a, E: nom::error::ParseError<&'a [u8]>>(
+    bytes: &'a [u8],
+) -> nom::IResult<&'a [u8], u32, E> {
+    nom::combinator::map_opt(util::nom_scale_compact_usize, |n| u32::try_from(n).ok())(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DecodeValueError, Value};
+
+    fn test_metadata() -> Vec<u8> {
+        let mut metadata = b"meta".to_vec();
+        metadata.push(14);
+
+        // Types registry.
+        metadata.push(7 << 2);
+        // Type 0: `u32`.
+        metadata.extend_from_slice(&[0, 0, 0, 5, 5, 0]);
+        // Type 1: `bool`.
+        metadata.extend_from_slice(&[1 << 2, 0, 0, 5, 0, 0]);
+        // Type 2: `Vec<bool>`.
+        metadata.extend_from_slice(&[2 << 2, 0, 0, 2, 1 << 2, 0]);
+        // Type 3: `struct { a: u32, b: Vec<bool> }`.
+        metadata.extend_from_slice(&[3 << 2, 0, 0, 0, 2 << 2]);
+        metadata.extend_from_slice(&[1, 1 << 2, b'a', 0, 0, 0]);
+        metadata.extend_from_slice(&[1, 1 << 2, b'b', 2 << 2, 0, 0]);
+        metadata.push(0);
+        // Type 4: `enum { Foo = 0, Bar(Compact<u32>) = 3 }`.
+        metadata.extend_from_slice(&[4 << 2, 0, 0, 1, 2 << 2]);
+        metadata.extend_from_slice(&[3 << 2, b'F', b'o', b'o', 0, 0, 0]);
+        metadata.extend_from_slice(&[3 << 2, b'B', b'a', b'r', 1 << 2, 0, 5 << 2, 0, 0, 3, 0]);
+        metadata.push(0);
+        // Type 5: `Compact<u32>`.
+        metadata.extend_from_slice(&[5 << 2, 0, 0, 6, 0, 0]);
+        // Type 6: `str`.
+        metadata.extend_from_slice(&[6 << 2, 0, 0, 5, 2, 0]);
+
+        // Rest of the metadata, ignored.
+        metadata.push(0);
+        metadata
+    }
+
+    #[test]
+    fn decode_composite() {
+        let metadata = test_metadata();
+        let registry = super::decode_types_registry(&metadata).unwrap();
+
+        assert_eq!(
+            registry
+                .decode_value(3, &[4, 3, 2, 1, 2 << 2, 1, 0])
+                .unwrap(),
+            Value::Composite(vec![
+                (Some("a"), Value::Unsigned(0x01020304)),
+                (
+                    Some("b"),
+                    Value::Sequence(vec![Value::Bool(true), Value::Bool(false)])
+                ),
+            ])
+        );
+    }
+
+    #[test]
+    fn decode_variant() {
+        let metadata = test_metadata();
+        let registry = super::decode_types_registry(&metadata).unwrap();
+
+        assert_eq!(
+            registry.decode_value(4, &[3, 42 << 2]).unwrap(),
+            Value::Variant {
+                name: "Bar",
+                index: 3,
+                fields: vec![(None, Value::Unsigned(42))],
+            }
+        );
+        assert_eq!(
+            registry.decode_value(4, &[1]),
+            Err(DecodeValueError::UnknownVariant {
+                type_id: 4,
+                index: 1
+            })
+        );
+    }
+
+    #[test]
+    fn decode_errors() {
+        let metadata = test_metadata();
+        let registry = super::decode_types_registry(&metadata).unwrap();
+
+        assert_eq!(
+            registry.decode_value(6, &[3 << 2, b'a', b'b', b'c']),
+            Ok(Value::Str("abc".into()))
+        );
+        assert_eq!(
+            registry.decode_value(0, &[1, 2, 3, 4, 5]),
+            Err(DecodeValueError::TrailingBytes)
+        );
+        assert_eq!(
+            registry.decode_value(0, &[1, 2, 3]),
+            Err(DecodeValueError::UnexpectedEnd)
+        );
+        assert_eq!(
+            registry.decode_value(1, &[2]),
+            Err(DecodeValueError::InvalidBool)
+        );
+        assert_eq!(
+            registry.decode_value(7, &[0]),
+            Err(DecodeValueError::UnknownTypeId(7))
+        );
+    }
+}
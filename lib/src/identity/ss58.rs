@@ -31,6 +31,22 @@ pub struct Decoded<P> {
     pub public_key: P,
 }
 
+impl<P> Decoded<P> {
+    /// Verifies that [`Decoded::chain_prefix`] is equal to `expected_prefix`.
+    ///
+    /// The value of `expected_prefix` is typically found in the `ss58Format` property of the
+    /// chain specification. See [`crate::chain_spec::ChainSpec::ss58_format`].
+    pub fn check_chain_prefix(&self, expected_prefix: ChainPrefix) -> Result<(), DecodeError> {
+        if self.chain_prefix != expected_prefix {
+            return Err(DecodeError::PrefixMismatch {
+                expected: expected_prefix.0,
+                actual: self.chain_prefix.0,
+            });
+        }
+        Ok(())
+    }
+}
+
 /// Identifier indicating which chain is concerned.
 ///
 /// The mapping between chains and this prefix can be found in this central registry:
@@ -145,6 +161,20 @@ pub fn decode(encoded: &'_ str) -> Result<Decoded<impl AsRef<[u8]>>, DecodeError
     })
 }
 
+/// Decodes an SS58 address from a string, and verifies that its prefix is equal to
+/// `expected_prefix`.
+///
+/// The value of `expected_prefix` is typically found in the `ss58Format` property of the chain
+/// specification. See [`crate::chain_spec::ChainSpec::ss58_format`].
+pub fn decode_with_prefix(
+    encoded: &'_ str,
+    expected_prefix: ChainPrefix,
+) -> Result<Decoded<impl AsRef<[u8]>>, DecodeError> {
+    let decoded = decode(encoded)?;
+    decoded.check_chain_prefix(expected_prefix)?;
+    Ok(decoded)
+}
+
 /// Error while decoding an SS58 address.
 #[derive(Debug, Clone, derive_more::Display)]
 pub enum DecodeError {
@@ -157,6 +187,15 @@ pub enum DecodeError {
     InvalidBs58(Bs58DecodeError),
     /// Calculated checksum doesn't match the one provided.
     InvalidChecksum,
+    /// Prefix of the address doesn't match the expected one. Only ever returned by
+    /// [`decode_with_prefix`] and [`Decoded::check_chain_prefix`].
+    #[display(fmt = "Address prefix {actual} doesn't match expected prefix {expected}")]
+    PrefixMismatch {
+        /// Prefix that was expected.
+        expected: u16,
+        /// Prefix found in the address.
+        actual: u16,
+    },
 }
 
 /// Error when decoding Base58 encoding.
@@ -207,4 +246,28 @@ mod tests {
 
         assert_eq!(super::encode(decoded), encoded);
     }
+
+    #[test]
+    fn prefix_mismatch() {
+        let encoded = "15oF4uVJwmo4TdGW7VfQxNLavjCXviqxT9S1MgbjMNHr6Sp5";
+
+        assert!(super::decode_with_prefix(encoded, super::ChainPrefix::from(0)).is_ok());
+        assert!(matches!(
+            super::decode_with_prefix(encoded, super::ChainPrefix::from(42)),
+            Err(super::DecodeError::PrefixMismatch {
+                expected: 42,
+                actual: 0
+            })
+        ));
+    }
+
+    #[test]
+    fn invalid_checksum() {
+        // Same as the Polkadot address of Alice, but with the last character modified.
+        let encoded = "15oF4uVJwmo4TdGW7VfQxNLavjCXviqxT9S1MgbjMNHr6Sp6";
+        assert!(matches!(
+            super::decode(encoded),
+            Err(super::DecodeError::InvalidChecksum)
+        ));
+    }
 }
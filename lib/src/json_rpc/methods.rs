@@ -421,7 +421,7 @@ define_methods! {
     state_getKeysPaged(prefix: Option<HexString>, count: u32, start_key: Option<HexString>, hash: Option<HashHexString>) -> Vec<HexString> [state_getKeysPagedAt],
     state_getMetadata(hash: Option<HashHexString>) -> HexString,
     state_getPairs() -> (), // TODO:
-    state_getReadProof() -> (), // TODO:
+    state_getReadProof(keys: Vec<HexString>, at: Option<HashHexString>) -> ReadProof,
     state_getRuntimeVersion(at: Option<HashHexString>) -> RuntimeVersion<'a> [chain_getRuntimeVersion],
     state_getStorage(key: HexString, hash: Option<HashHexString>) -> HexString [state_getStorageAt],
     state_getStorageHash() -> () [state_getStorageHashAt], // TODO:
@@ -436,7 +436,10 @@ define_methods! {
     system_addReservedPeer() -> (), // TODO:
     system_chain() -> Cow<'a, str>,
     system_chainType() -> Cow<'a, str>,
-    system_dryRun() -> () [system_dryRunAt], // TODO:
+    /// Applies the given extrinsic on top of the state of the given block, or of the best block
+    /// if `at` is `None`, and returns the SCALE-encoded outcome of its dispatch. Any storage
+    /// modification is discarded afterwards.
+    system_dryRun(extrinsic: HexString, at: Option<HashHexString>) -> HexString [system_dryRunAt],
     system_health() -> SystemHealth,
     system_localListenAddresses() -> Vec<String>,
     /// Returns the Base58 encoding of the network identity of the node on the peer-to-peer network.
@@ -452,15 +455,29 @@ define_methods! {
     system_version() -> Cow<'a, str>,
 
     // The functions below are experimental and are defined in the document https://github.com/paritytech/json-rpc-interface-spec/
+    archive_unstable_body(hash: HashHexString) -> Option<Vec<HexString>>,
+    archive_unstable_finalizedHeight() -> u64,
+    archive_unstable_genesisHash() -> HashHexString,
+    archive_unstable_hashByHeight(height: u64) -> Vec<HashHexString>,
+    archive_unstable_header(hash: HashHexString) -> Option<HexString>,
+    archive_unstable_storage(
+        hash: HashHexString,
+        items: Vec<ArchiveStorageRequestItem>,
+        #[rename = "childTrie"] child_trie: Option<HexString>
+    ) -> ArchiveStorageResult,
+
     chainHead_unstable_body(
         #[rename = "followSubscription"] follow_subscription: Cow<'a, str>,
         hash: HashHexString
     ) -> ChainHeadBodyCallReturn<'a>,
+    /// If `smoldotRuntimeLogs` is `true`, the logs printed by the runtime during the call are
+    /// reported in the `operationCallDone` event. This parameter is a smoldot-specific extension.
     chainHead_unstable_call(
         #[rename = "followSubscription"] follow_subscription: Cow<'a, str>,
         hash: HashHexString,
         function: Cow<'a, str>,
-        #[rename = "callParameters"] call_parameters: HexString
+        #[rename = "callParameters"] call_parameters: HexString,
+        #[rename = "smoldotRuntimeLogs"] smoldot_runtime_logs: Option<bool>
     ) -> ChainHeadBodyCallReturn<'a>,
     chainHead_unstable_follow(
         #[rename = "withRuntime"] with_runtime: bool
@@ -495,6 +512,14 @@ define_methods! {
     chainSpec_v1_genesisHash() -> HashHexString,
     chainSpec_v1_properties() -> Box<serde_json::value::RawValue>,
 
+    // These functions are a custom addition, mimicking the "manual seal" functions of Substrate.
+    // They are only meant to be used when testing a chain, and are only available on a full node
+    // configured to author blocks on demand.
+    // Contrary to Substrate, `engine_createBlock` doesn't accept a `parentHash` parameter, and
+    // new blocks are always built on top of the current best block.
+    engine_createBlock(#[rename = "createEmpty"] create_empty: bool, finalize: bool) -> CreatedBlock,
+    engine_finalizeBlock(hash: HashHexString) -> bool,
+
     sudo_unstable_p2pDiscover(multiaddr: Cow<'a, str>) -> (),
     sudo_unstable_version() -> Cow<'a, str>,
 
@@ -507,6 +532,10 @@ define_methods! {
     network_unstable_subscribeEvents() -> Cow<'a, str>,
     network_unstable_unsubscribeEvents(subscription: Cow<'a, str>) -> (),
     chainHead_unstable_finalizedDatabase(#[rename = "maxSizeBytes"] max_size_bytes: Option<u64>) -> Cow<'a, str>,
+    smoldot_unstable_reserveAccountNonce(account: AccountId) -> u64,
+    smoldot_unstable_releaseAccountNonce(account: AccountId, nonce: u64) -> (),
+    smoldot_unstable_submitTransaction(transaction: HexString, #[rename = "maxPeers"] max_peers: Option<u32>) -> HashHexString,
+    smoldot_unstable_checkRuntimeUpgrade(code: HexString, at: Option<HashHexString>) -> RuntimeUpgradeCheck<'a>,
 }
 
 define_methods! {
@@ -526,6 +555,10 @@ define_methods! {
     // This function is a custom addition in smoldot. As of the writing of this comment, there is
     // no plan to standardize it. See https://github.com/paritytech/smoldot/issues/2245.
     network_unstable_event(subscription: Cow<'a, str>, result: NetworkEvent<'a>) -> (),
+
+    // This function is a custom addition in smoldot. It is sent right after a subscription has
+    // been stopped by the server, and indicates the reason why it has been stopped.
+    smoldot_unstable_subscriptionStopped(subscription: Cow<'a, str>, result: SubscriptionStopReason) -> (),
 }
 
 #[derive(Clone, PartialEq, Eq, Hash)]
@@ -632,16 +665,45 @@ pub enum RemoveMetadataLengthPrefixError {
 
 /// Contains the public key of an account.
 ///
-/// The deserialization involves decoding an SS58 address into this public key.
+/// The serialization and deserialization involve encoding and decoding an SS58 address.
 #[derive(Debug, Clone)]
-pub struct AccountId(pub Vec<u8>);
+pub struct AccountId {
+    /// Prefix of the SS58 address. Indicates which chain the address is meant to be used on.
+    ///
+    /// When deserializing, this prefix isn't verified against the one of the chain. It is the
+    /// responsibility of the JSON-RPC server to do so, by calling
+    /// [`AccountId::check_chain_prefix`].
+    pub chain_prefix: ss58::ChainPrefix,
+
+    /// Public key of the account.
+    pub public_key: Vec<u8>,
+}
+
+impl AccountId {
+    /// Verifies that [`AccountId::chain_prefix`] is equal to `expected_prefix`.
+    ///
+    /// See [`ss58::Decoded::check_chain_prefix`].
+    pub fn check_chain_prefix(
+        &self,
+        expected_prefix: ss58::ChainPrefix,
+    ) -> Result<(), ss58::DecodeError> {
+        ss58::Decoded {
+            chain_prefix: self.chain_prefix,
+            public_key: &self.public_key,
+        }
+        .check_chain_prefix(expected_prefix)
+    }
+}
 
 impl serde::Serialize for AccountId {
-    fn serialize<S>(&self, _: S) -> Result<S::Ok, S::Error>
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
         S: serde::Serializer,
     {
-        todo!() // TODO: /!\
+        serializer.serialize_str(&ss58::encode(ss58::Decoded {
+            chain_prefix: self.chain_prefix,
+            public_key: &self.public_key,
+        }))
     }
 }
 
@@ -657,9 +719,10 @@ impl<'a> serde::Deserialize<'a> for AccountId {
             Err(err) => return Err(serde::de::Error::custom(err.to_string())),
         };
 
-        // TODO: check the prefix against the one of the current chain?
-
-        Ok(AccountId(decoded.public_key.as_ref().to_vec()))
+        Ok(AccountId {
+            chain_prefix: decoded.chain_prefix,
+            public_key: decoded.public_key.as_ref().to_vec(),
+        })
     }
 }
 
@@ -718,6 +781,14 @@ pub enum FollowEvent<'a> {
         #[serde(rename = "operationId")]
         operation_id: Cow<'a, str>,
         output: HexString,
+        /// Logs printed by the runtime during the call. Only present if it was requested
+        /// through the `smoldotRuntimeLogs` parameter of `chainHead_unstable_call`.
+        #[serde(
+            rename = "smoldotRuntimeLogs",
+            default,
+            skip_serializing_if = "Option::is_none"
+        )]
+        smoldot_runtime_logs: Option<RuntimeLogs<'a>>,
     },
     #[serde(rename = "operationInaccessible")]
     OperationInaccessible {
@@ -747,6 +818,18 @@ pub enum FollowEvent<'a> {
     Stop {},
 }
 
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "reason")]
+pub enum SubscriptionStopReason {
+    #[serde(rename = "tooManyPinnedBlocks")]
+    TooManyPinnedBlocks {
+        #[serde(rename = "maxPinnedBlocks")]
+        max_pinned_blocks: u32,
+    },
+    #[serde(rename = "finalityGap")]
+    FinalityGap {},
+}
+
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 #[serde(tag = "result")]
 pub enum ChainHeadBodyCallReturn<'a> {
@@ -780,6 +863,16 @@ pub struct ChainHeadStorageRequestItem {
     pub ty: ChainHeadStorageType,
 }
 
+/// See [`FollowEvent::OperationCallDone::smoldot_runtime_logs`].
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct RuntimeLogs<'a> {
+    /// Concatenation of all the log messages printed by the runtime.
+    pub logs: Cow<'a, str>,
+    /// `true` if the runtime has printed more logs than could be kept, in which case the log
+    /// messages after the limit has been reached are missing from [`RuntimeLogs::logs`].
+    pub truncated: bool,
+}
+
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct ChainHeadStorageResponseItem {
     pub key: HexString,
@@ -808,6 +901,26 @@ pub enum ChainHeadStorageType {
     DescendantsHashes,
 }
 
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct ArchiveStorageRequestItem {
+    pub key: HexString,
+    #[serde(rename = "type")]
+    pub ty: ChainHeadStorageType,
+    #[serde(
+        rename = "paginationStartKey",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub pagination_start_key: Option<HexString>,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct ArchiveStorageResult {
+    pub result: Vec<ChainHeadStorageResponseItem>,
+    #[serde(rename = "discardedItems")]
+    pub discarded_items: usize,
+}
+
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 #[serde(tag = "event")]
 pub enum TransactionWatchEvent<'a> {
@@ -1047,6 +1160,64 @@ pub struct RuntimeVersion<'a> {
     pub apis: Vec<(HexString, u32)>,
 }
 
+/// Result of a `smoldot_unstable_checkRuntimeUpgrade` call.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct RuntimeUpgradeCheck<'a> {
+    /// `true` if none of the differences is breaking.
+    pub compatible: bool,
+    pub differences: Vec<RuntimeUpgradeDifference<'a>>,
+}
+
+/// See [`RuntimeUpgradeCheck::differences`].
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct RuntimeUpgradeDifference<'a> {
+    /// `true` if this difference is likely to break the chain or its clients.
+    pub breaking: bool,
+    #[serde(flatten)]
+    pub kind: RuntimeUpgradeDifferenceKind<'a>,
+}
+
+/// See [`RuntimeUpgradeDifference::kind`].
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "type")]
+pub enum RuntimeUpgradeDifferenceKind<'a> {
+    #[serde(rename = "specNameChanged")]
+    SpecNameChanged {
+        current: Cow<'a, str>,
+        proposed: Cow<'a, str>,
+    },
+    #[serde(rename = "specVersionNotIncreased")]
+    SpecVersionNotIncreased { current: u32, proposed: u32 },
+    #[serde(rename = "apiRemoved")]
+    ApiRemoved {
+        #[serde(rename = "nameHash")]
+        name_hash: HexString,
+        version: u32,
+    },
+    #[serde(rename = "apiVersionChanged")]
+    ApiVersionChanged {
+        #[serde(rename = "nameHash")]
+        name_hash: HexString,
+        #[serde(rename = "currentVersion")]
+        current_version: u32,
+        #[serde(rename = "proposedVersion")]
+        proposed_version: u32,
+    },
+    #[serde(rename = "apiAdded")]
+    ApiAdded {
+        #[serde(rename = "nameHash")]
+        name_hash: HexString,
+        version: u32,
+    },
+    #[serde(rename = "transactionVersionChanged")]
+    TransactionVersionChanged {
+        current: Option<u32>,
+        proposed: Option<u32>,
+    },
+    #[serde(rename = "stateVersionChanged")]
+    StateVersionChanged { current: u8, proposed: u8 },
+}
+
 #[derive(Debug, Copy, Clone)]
 pub struct RuntimeDispatchInfo {
     pub weight: u64,
@@ -1067,6 +1238,17 @@ pub struct StorageChangeSet {
     pub changes: Vec<(HexString, Option<HexString>)>,
 }
 
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct ReadProof {
+    pub at: HashHexString,
+    pub proof: Vec<HexString>,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct CreatedBlock {
+    pub hash: HashHexString,
+}
+
 #[derive(Debug, Clone)]
 pub struct SystemHealth {
     pub is_syncing: bool,
@@ -1083,6 +1265,16 @@ pub struct SystemPeer {
     pub best_hash: HashHexString,
     #[serde(rename = "bestNumber")]
     pub best_number: u64,
+    #[serde(rename = "stateRetention", skip_serializing_if = "Option::is_none")]
+    pub state_retention: Option<SystemPeerStateRetention>,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub enum SystemPeerStateRetention {
+    #[serde(rename = "archive")]
+    Archive,
+    #[serde(rename = "pruned")]
+    Pruned,
 }
 
 #[derive(Debug, Clone, serde::Serialize)]
@@ -1314,4 +1506,82 @@ mod tests {
             })
         ));
     }
+
+    #[test]
+    fn subscription_stopped_roundtrip() {
+        let notification = super::ServerToClient::smoldot_unstable_subscriptionStopped {
+            subscription: "foo".into(),
+            result: super::SubscriptionStopReason::TooManyPinnedBlocks {
+                max_pinned_blocks: 256,
+            },
+        }
+        .to_json_request_object_parameters(None);
+
+        assert!(notification.contains(r#""reason":"tooManyPinnedBlocks""#));
+
+        match super::parse_notification(&notification).unwrap() {
+            super::ServerToClient::smoldot_unstable_subscriptionStopped {
+                subscription,
+                result,
+            } => {
+                assert_eq!(subscription, "foo");
+                assert_eq!(
+                    result,
+                    super::SubscriptionStopReason::TooManyPinnedBlocks {
+                        max_pinned_blocks: 256
+                    }
+                );
+            }
+            _ => panic!(),
+        }
+    }
+
+    #[test]
+    fn chain_head_call_runtime_logs_optional() {
+        let (_, call) = super::parse_jsonrpc_client_to_server(
+            r#"{"jsonrpc":"2.0","id":2,"method":"chainHead_unstable_call","params":["foo","0x0000000000000000000000000000000000000000000000000000000000000000","Core_version","0x"]}"#,
+        )
+        .unwrap();
+        assert!(matches!(
+            call,
+            super::MethodCall::chainHead_unstable_call {
+                smoldot_runtime_logs: None,
+                ..
+            }
+        ));
+
+        let (_, call) = super::parse_jsonrpc_client_to_server(
+            r#"{"jsonrpc":"2.0","id":2,"method":"chainHead_unstable_call","params":["foo","0x0000000000000000000000000000000000000000000000000000000000000000","Core_version","0x",true]}"#,
+        )
+        .unwrap();
+        assert!(matches!(
+            call,
+            super::MethodCall::chainHead_unstable_call {
+                smoldot_runtime_logs: Some(true),
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn operation_call_done_runtime_logs() {
+        let without_logs = serde_json::to_string(&super::FollowEvent::OperationCallDone {
+            operation_id: "1".into(),
+            output: super::HexString(vec![1, 2]),
+            smoldot_runtime_logs: None,
+        })
+        .unwrap();
+        assert!(!without_logs.contains("smoldotRuntimeLogs"));
+
+        let with_logs = serde_json::to_string(&super::FollowEvent::OperationCallDone {
+            operation_id: "1".into(),
+            output: super::HexString(vec![1, 2]),
+            smoldot_runtime_logs: Some(super::RuntimeLogs {
+                logs: "hello\n".into(),
+                truncated: true,
+            }),
+        })
+        .unwrap();
+        assert!(with_logs.contains(r#""smoldotRuntimeLogs":{"logs":"hello\n","truncated":true}"#));
+    }
 }
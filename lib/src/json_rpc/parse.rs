@@ -46,6 +46,9 @@ pub fn parse_request(request_json: &str) -> Result<Request, ParseError> {
 }
 
 /// Parses a JSON-encoded RPC response.
+///
+/// Successful responses that contain a `smoldot_unverified` field, as generated by
+/// [`build_unverified_success_response`], are accepted. The value of this field is ignored.
 pub fn parse_response(response_json: &str) -> Result<Response, ParseError> {
     let error = match serde_json::from_str::<SerdeSuccess>(response_json) {
         Err(err) => err,
@@ -53,6 +56,7 @@ pub fn parse_response(response_json: &str) -> Result<Response, ParseError> {
             jsonrpc: _,
             id,
             result,
+            smoldot_unverified: _,
         }) => {
             // Because of https://github.com/serde-rs/json/issues/742, we can't use ̀`&str`.
             #[derive(serde::Deserialize)]
@@ -240,6 +244,40 @@ pub fn build_success_response(id_json: &str, result_json: &str) -> String {
         jsonrpc: SerdeVersion::V2,
         id: serde_json::from_str(id_json).expect("invalid id_json"),
         result: serde_json::from_str(result_json).expect("invalid result_json"),
+        smoldot_unverified: false,
+    })
+    .unwrap()
+}
+
+/// Builds a JSON response whose result comes from a source that hasn't been verified.
+///
+/// Identical to [`build_success_response`], except that the response contains an additional
+/// `smoldot_unverified` field set to `true`. This is a non-standard extension to the JSON-RPC
+/// specification, and JSON-RPC clients that don't know about it will simply ignore it.
+///
+/// # Example
+///
+/// ```
+/// # use smoldot::json_rpc::parse;
+/// let result_json = parse::build_unverified_success_response("27", r#"[1, 2]"#);
+///
+/// // Note that the output is guaranteed to be stable.
+/// assert_eq!(
+///     result_json,
+///     r#"{"jsonrpc":"2.0","id":27,"result":[1, 2],"smoldot_unverified":true}"#
+/// );
+/// ```
+///
+/// # Panic
+///
+/// Panics if `id_json` or `result_json` aren't valid JSON.
+///
+pub fn build_unverified_success_response(id_json: &str, result_json: &str) -> String {
+    serde_json::to_string(&SerdeSuccess {
+        jsonrpc: SerdeVersion::V2,
+        id: serde_json::from_str(id_json).expect("invalid id_json"),
+        result: serde_json::from_str(result_json).expect("invalid result_json"),
+        smoldot_unverified: true,
     })
     .unwrap()
 }
@@ -411,6 +449,9 @@ struct SerdeSuccess<'a> {
     id: &'a serde_json::value::RawValue,
     #[serde(borrow)]
     result: &'a serde_json::value::RawValue,
+    /// See [`build_unverified_success_response`].
+    #[serde(default, skip_serializing_if = "core::ops::Not::not")]
+    smoldot_unverified: bool,
 }
 
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
@@ -637,6 +678,22 @@ mod tests {
         .is_err());
     }
 
+    #[test]
+    fn build_unverified_success_response() {
+        let response = super::build_unverified_success_response(r#""foo""#, r#"{"a": [1, 2]}"#);
+
+        // The marker doesn't prevent the response from being parsed as a regular response.
+        let (id, result) = super::parse_response(&response)
+            .unwrap()
+            .into_success()
+            .unwrap();
+        assert_eq!(id, r#""foo""#);
+        assert_eq!(result, r#"{"a": [1, 2]}"#);
+
+        let value = serde_json::from_str::<serde_json::Value>(&response).unwrap();
+        assert_eq!(value["smoldot_unverified"], serde_json::Value::Bool(true));
+    }
+
     #[test]
     fn build_request() {
         let request = super::Request {
@@ -439,9 +439,21 @@ impl ClientMainTask {
                 | methods::MethodCall::chainSpec_v1_chainName { .. }
                 | methods::MethodCall::chainSpec_v1_genesisHash { .. }
                 | methods::MethodCall::chainSpec_v1_properties { .. }
+                | methods::MethodCall::engine_createBlock { .. }
+                | methods::MethodCall::engine_finalizeBlock { .. }
                 | methods::MethodCall::rpc_methods { .. }
                 | methods::MethodCall::sudo_unstable_p2pDiscover { .. }
                 | methods::MethodCall::sudo_unstable_version { .. }
+                | methods::MethodCall::smoldot_unstable_reserveAccountNonce { .. }
+                | methods::MethodCall::smoldot_unstable_releaseAccountNonce { .. }
+                | methods::MethodCall::smoldot_unstable_submitTransaction { .. }
+                | methods::MethodCall::smoldot_unstable_checkRuntimeUpgrade { .. }
+                | methods::MethodCall::archive_unstable_body { .. }
+                | methods::MethodCall::archive_unstable_finalizedHeight { .. }
+                | methods::MethodCall::archive_unstable_genesisHash { .. }
+                | methods::MethodCall::archive_unstable_hashByHeight { .. }
+                | methods::MethodCall::archive_unstable_header { .. }
+                | methods::MethodCall::archive_unstable_storage { .. }
                 | methods::MethodCall::chainHead_unstable_body { .. }
                 | methods::MethodCall::chainHead_unstable_call { .. }
                 | methods::MethodCall::chainHead_unstable_continue { .. }
@@ -483,7 +495,7 @@ impl ClientMainTask {
                         let response = parse::build_error_response(
                             request_id,
                             ErrorResponse::ServerError(-32000, "Too many active subscriptions"),
-                            None,
+                            Some(r#"{"reason":"tooManySubscriptions"}"#),
                         );
                         let mut responses_queue =
                             self.inner.serialized_io.responses_queue.lock().await;
@@ -1033,6 +1045,32 @@ impl RequestProcess {
         self.has_sent_response = true;
     }
 
+    /// Indicate the response to the request to the [`ClientMainTask`], where `result_json` is
+    /// the JSON-formatted result and comes from a source that hasn't been verified. The
+    /// response is marked as such.
+    ///
+    /// See [`parse::build_unverified_success_response`].
+    ///
+    /// Has no effect if the [`ClientMainTask`] has been destroyed.
+    ///
+    /// # Panic
+    ///
+    /// Panics if `result_json` isn't valid JSON.
+    ///
+    pub fn respond_unverified(mut self, result_json: &str) {
+        let request_id = methods::parse_jsonrpc_client_to_server(&self.request)
+            .unwrap()
+            .0;
+        let serialized = parse::build_unverified_success_response(request_id, result_json);
+        self.responses_notifications_queue
+            .queue
+            .push(ToMainTask::RequestResponse(serialized));
+        self.responses_notifications_queue
+            .on_pushed
+            .notify(usize::MAX);
+        self.has_sent_response = true;
+    }
+
     /// Indicate to the [`ClientMainTask`] that the request should return an error.
     ///
     /// Has no effect if the [`ClientMainTask`] has been destroyed.
@@ -48,9 +48,11 @@
 //!
 
 use crate::libp2p::connection::noise;
+use crate::util::leb128;
 
 use super::connection::{established, single_stream_handshake};
 use alloc::{
+    borrow::ToOwned as _,
     collections::{BTreeMap, BTreeSet, VecDeque},
     string::String,
     sync::Arc,
@@ -70,13 +72,14 @@ use rand_chacha::{
 pub use super::peer_id::PeerId;
 pub use super::read_write::ReadWrite;
 pub use established::{InboundError, InboundTy, SubstreamFate};
-pub use single_stream_handshake::HandshakeError;
+pub use single_stream_handshake::{HandshakeError, HandshakeStage};
 
 pub use multi_stream::MultiStreamConnectionTask;
 pub use single_stream::SingleStreamConnectionTask;
 
 mod multi_stream;
 mod single_stream;
+mod tests;
 
 /// What kind of handshake to perform on the newly-added connection.
 pub enum SingleStreamHandshakeKind<'a> {
@@ -132,6 +135,24 @@ pub struct Config {
 
     /// Name of the ping protocol on the network.
     pub ping_protocol: String,
+
+    /// Interval between two consecutive outgoing ping requests on each connection.
+    pub ping_interval: Duration,
+
+    /// Amount of time after which an outgoing ping that hasn't been answered is considered as
+    /// failed.
+    pub ping_timeout: Duration,
+
+    /// Maximum number of bytes that can be queued in each outbound notifications substream.
+    /// See [`Network::queue_notification`].
+    pub max_queued_notifications_bytes: usize,
+
+    /// If `true`, [`Event::SubstreamOpened`] and [`Event::SubstreamClosed`] events are generated
+    /// whenever a substream starts or stops being tracked by the [`Network`].
+    ///
+    /// The number of substreams of each protocol is always tracked and can be obtained with
+    /// [`Network::substreams_counters`], no matter the value of this field.
+    pub substreams_lifecycle_events: bool,
 }
 
 /// Identifier of a connection spawned by the [`Network`].
@@ -207,6 +228,15 @@ pub struct Network<TConn, TNow> {
     // TODO: group with the other similar BTreeSets?
     outgoing_notification_substreams_by_connection: BTreeSet<(ConnectionId, SubstreamId)>,
 
+    /// State of the queue of notifications of each outgoing notification substream that is
+    /// fully open. Contains the same entries as [`Network::outgoing_notification_substreams`]
+    /// whose state is [`SubstreamState::Open`].
+    outgoing_notification_queues:
+        hashbrown::HashMap<SubstreamId, NotificationsOutQueue, fnv::FnvBuildHasher>,
+
+    /// See [`Config::max_queued_notifications_bytes`].
+    max_queued_notifications_bytes: usize,
+
     /// List of all requests that have been started locally.
     outgoing_requests: BTreeSet<(ConnectionId, SubstreamId)>,
 
@@ -265,6 +295,28 @@ pub struct Network<TConn, TNow> {
     /// See [`Config::ping_protocol`].
     ping_protocol: Arc<str>,
 
+    /// See [`Config::ping_interval`].
+    ping_interval: Duration,
+
+    /// See [`Config::ping_timeout`].
+    ping_timeout: Duration,
+
+    /// Connection, index within [`Connection::substreams_counters`], and direction of every
+    /// substream currently tracked by the [`Network`], no matter its kind.
+    substreams_accounting: hashbrown::HashMap<
+        SubstreamId,
+        (ConnectionId, usize, SubstreamDirection),
+        fnv::FnvBuildHasher,
+    >,
+
+    /// See [`Config::substreams_lifecycle_events`].
+    substreams_lifecycle_events: bool,
+
+    /// Queue of [`Event::SubstreamOpened`] and [`Event::SubstreamClosed`] events waiting to be
+    /// returned by [`Network::next_event`]. Always empty if
+    /// [`Network::substreams_lifecycle_events`] is `false`.
+    pending_lifecycle_events: VecDeque<Event<TConn>>,
+
     // Phantom data to keep the `TNow` type pinned.
     // TODO: considering removing
     now_pin: PhantomData<fn() -> TNow>,
@@ -273,6 +325,12 @@ pub struct Network<TConn, TNow> {
 struct Connection<TConn> {
     state: InnerConnectionState,
 
+    /// Counters of the substreams of this connection, by protocol name. Entries are never
+    /// removed, which makes it possible to refer to them by index and thus to not clone the
+    /// protocol name whenever a substream is opened or closed. A connection only ever uses a
+    /// handful of protocols, and a linear search is used to find a protocol name.
+    substreams_counters: Vec<(String, SubstreamsCounters)>,
+
     user_data: TConn,
 }
 
@@ -295,6 +353,19 @@ enum InnerConnectionState {
     },
 }
 
+/// See [`Network::outgoing_notification_queues`].
+#[derive(Debug, Default)]
+struct NotificationsOutQueue {
+    /// Number of bytes that have been queued and that the connection task hasn't reported as
+    /// sent yet.
+    queued_bytes: usize,
+
+    /// `true` if [`Network::queue_notification`] has returned
+    /// [`QueueNotificationError::QueueFull`] since the last time an
+    /// [`Event::NotificationsOutQueueDrained`] has been generated.
+    full: bool,
+}
+
 /// See [`Network::outgoing_notification_substreams`] and
 /// [`Network::ingoing_notification_substreams`].
 ///
@@ -336,6 +407,11 @@ where
                 Default::default(),
             ),
             outgoing_notification_substreams_by_connection: BTreeSet::new(),
+            outgoing_notification_queues: hashbrown::HashMap::with_capacity_and_hasher(
+                4 * config.capacity,
+                Default::default(),
+            ),
+            max_queued_notifications_bytes: config.max_queued_notifications_bytes,
             ingoing_notification_substreams: hashbrown::HashMap::with_capacity_and_hasher(
                 4 * config.capacity,
                 Default::default(),
@@ -349,10 +425,32 @@ where
             randomness_seeds: ChaCha20Rng::from_seed(config.randomness_seed),
             max_inbound_substreams: config.max_inbound_substreams,
             ping_protocol: config.ping_protocol.into(),
+            ping_interval: config.ping_interval,
+            ping_timeout: config.ping_timeout,
+            substreams_accounting: hashbrown::HashMap::with_capacity_and_hasher(
+                8 * config.capacity,
+                Default::default(),
+            ),
+            substreams_lifecycle_events: config.substreams_lifecycle_events,
+            pending_lifecycle_events: VecDeque::new(),
             now_pin: PhantomData,
         }
     }
 
+    /// Returns the value passed as [`Config::max_inbound_substreams`], or the value last
+    /// passed to [`Network::set_max_inbound_substreams`].
+    pub fn max_inbound_substreams(&self) -> usize {
+        self.max_inbound_substreams
+    }
+
+    /// Modifies the value of [`Config::max_inbound_substreams`].
+    ///
+    /// Only the connections inserted afterwards are affected. The limit of the existing
+    /// connections is left unchanged.
+    pub fn set_max_inbound_substreams(&mut self, max_inbound_substreams: usize) {
+        self.max_inbound_substreams = max_inbound_substreams;
+    }
+
     /// Adds a new single-stream connection to the collection.
     ///
     /// Must be passed the moment (as a `TNow`) when the connection process has been started, in
@@ -395,12 +493,15 @@ where
             substreams_capacity,
             max_protocol_name_len,
             ping_protocol: self.ping_protocol.clone(),
+            ping_interval: self.ping_interval,
+            ping_timeout: self.ping_timeout,
         });
 
         let _previous_value = self.connections.insert(
             connection_id,
             Connection {
                 state: InnerConnectionState::Handshaking,
+                substreams_counters: Vec::new(),
                 user_data,
             },
         );
@@ -470,24 +571,28 @@ where
             })
         };
 
-        let connection_task = MultiStreamConnectionTask::new(
-            {
+        let connection_task = MultiStreamConnectionTask::new(multi_stream::Config {
+            randomness_seed: {
                 let mut seed = [0; 32];
                 self.randomness_seeds.fill_bytes(&mut seed);
                 seed
             },
+            handshake_timeout: when_connection_start.clone() + self.handshake_timeout,
             when_connection_start,
             handshake,
-            self.max_inbound_substreams,
+            max_inbound_substreams: self.max_inbound_substreams,
             substreams_capacity,
             max_protocol_name_len,
-            self.ping_protocol.clone(),
-        );
+            ping_protocol: self.ping_protocol.clone(),
+            ping_interval: self.ping_interval,
+            ping_timeout: self.ping_timeout,
+        });
 
         let _previous_value = self.connections.insert(
             connection_id,
             Connection {
                 state: InnerConnectionState::Handshaking,
+                substreams_counters: Vec::new(),
                 user_data,
             },
         );
@@ -583,6 +688,51 @@ where
         }
     }
 
+    /// Returns the counters of the substreams of the given protocol on the given connection.
+    ///
+    /// All the counters are zero if no substream of this protocol has ever been opened on this
+    /// connection.
+    ///
+    /// The substreams that are counted are the ones that have been reported through
+    /// [`Event::InboundNegotiated`], [`Network::start_request`], and
+    /// [`Network::open_out_notifications`], until they are destroyed. The ping substreams that
+    /// are automatically opened by the connections aren't counted.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the identifier is invalid or corresponds to a connection that has already
+    /// entirely shut down.
+    ///
+    pub fn substreams_counters(
+        &self,
+        connection_id: ConnectionId,
+        protocol_name: &str,
+    ) -> SubstreamsCounters {
+        self.connections[&connection_id]
+            .substreams_counters
+            .iter()
+            .find(|(name, _)| *name == protocol_name)
+            .map_or_else(Default::default, |(_, counters)| *counters)
+    }
+
+    /// Returns the list of protocols for which at least one substream has been opened on the
+    /// given connection, alongside with their counters. See [`Network::substreams_counters`].
+    ///
+    /// # Panic
+    ///
+    /// Panics if the identifier is invalid or corresponds to a connection that has already
+    /// entirely shut down.
+    ///
+    pub fn substreams_counters_by_protocol(
+        &self,
+        connection_id: ConnectionId,
+    ) -> impl Iterator<Item = (&'_ str, SubstreamsCounters)> + '_ {
+        self.connections[&connection_id]
+            .substreams_counters
+            .iter()
+            .map(|(protocol_name, counters)| (&protocol_name[..], *counters))
+    }
+
     /// Call after an [`Event::InboundNegotiated`] has been emitted in order to accept the protocol
     /// name and indicate the type of the protocol.
     ///
@@ -627,6 +777,7 @@ where
             .remove(&(connection_id, inner_substream_id));
         debug_assert!(_was_in.is_some());
         assert!(!already_accepted);
+        self.substream_closed(substream_id);
 
         self.messages_to_connections.push_back((
             connection_id,
@@ -697,6 +848,12 @@ where
 
         let _was_inserted = self.outgoing_requests.insert((target, substream_id));
         debug_assert!(_was_inserted);
+        self.substream_opened(
+            target,
+            substream_id,
+            &protocol_name,
+            SubstreamDirection::Out,
+        );
 
         self.messages_to_connections.push_back((
             target,
@@ -712,6 +869,36 @@ where
         substream_id
     }
 
+    /// Cancels a request started with [`Network::start_request`].
+    ///
+    /// No [`Event::Response`] will be generated for this request. The substream used by the
+    /// request, if any, is reset.
+    ///
+    /// This function generates a message destined to the connection. Use
+    /// [`Network::pull_message_to_connection`] to process these messages after it has returned.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the [`SubstreamId`] doesn't correspond to a request in progress.
+    ///
+    #[track_caller]
+    pub fn cancel_request(&mut self, substream_id: SubstreamId) {
+        // TODO: O(n) complexity
+        let (connection_id, _) = *self
+            .outgoing_requests
+            .iter()
+            .find(|(_, s)| *s == substream_id)
+            .unwrap();
+        self.outgoing_requests
+            .remove(&(connection_id, substream_id));
+        self.substream_closed(substream_id);
+
+        self.messages_to_connections.push_back((
+            connection_id,
+            CoordinatorToConnectionInner::CancelRequest { substream_id },
+        ));
+    }
+
     /// Start opening a notifications substream.
     ///
     /// It is invalid to open a notifications substream on a connection before a
@@ -757,6 +944,12 @@ where
             .outgoing_notification_substreams_by_connection
             .insert((connection_id, substream_id));
         debug_assert!(_was_inserted);
+        self.substream_opened(
+            connection_id,
+            substream_id,
+            &protocol_name,
+            SubstreamDirection::Out,
+        );
 
         self.messages_to_connections.push_back((
             connection_id,
@@ -800,6 +993,8 @@ where
             .outgoing_notification_substreams_by_connection
             .remove(&(connection_id, substream_id));
         debug_assert!(_was_in);
+        self.outgoing_notification_queues.remove(&substream_id);
+        self.substream_closed(substream_id);
 
         self.messages_to_connections.push_back((
             connection_id,
@@ -832,6 +1027,13 @@ where
     /// Regardless of the success of this function, no guarantee exists about the successful
     /// delivery of notifications.
     ///
+    /// The queue is considered full if adding the notification would make the number of queued
+    /// bytes go above [`Config::max_queued_notifications_bytes`], unless the queue is empty.
+    /// Use [`Network::notification_queue_capacity`] to know ahead of time whether a notification
+    /// would be accepted. After this function has returned [`QueueNotificationError::QueueFull`],
+    /// an [`Event::NotificationsOutQueueDrained`] is generated once the queue has drained below
+    /// half of its capacity, which can be used to implement flow control.
+    ///
     /// This function generates a message destined to the connection. Use
     /// [`Network::pull_message_to_connection`] to process these messages after it has returned.
     ///
@@ -852,19 +1054,65 @@ where
         };
         assert!(matches!(state, SubstreamState::Open));
 
-        //  TODO: add some back-pressure system and return a `QueueNotificationError` if full
+        let notification = notification.into();
+        let queue = self
+            .outgoing_notification_queues
+            .get_mut(&substream_id)
+            .unwrap_or_else(|| unreachable!());
+
+        // Notifications are prefixed with their length when sent out. This prefix is included in
+        // the size of the queue, as the connection task reports the number of bytes sent out
+        // including this prefix.
+        let encoded_len = leb128::encode_usize(notification.len()).count() + notification.len();
+        if queue.queued_bytes != 0
+            && queue.queued_bytes.saturating_add(encoded_len) > self.max_queued_notifications_bytes
+        {
+            queue.full = true;
+            return Err(QueueNotificationError::QueueFull);
+        }
 
+        queue.queued_bytes += encoded_len;
         self.messages_to_connections.push_back((
             *connection_id,
             CoordinatorToConnectionInner::QueueNotification {
                 substream_id,
-                notification: notification.into(),
+                notification,
             },
         ));
 
         Ok(())
     }
 
+    /// Returns the number of bytes that can be queued on the given substream with
+    /// [`Network::queue_notification`] before the queue is full.
+    ///
+    /// This value is only an indication, as the length of each notification is also counted
+    /// (in addition to its content) when determining whether the queue is full.
+    ///
+    /// # Panics
+    ///
+    /// Panics if [`SubstreamId`] is not a fully open outbound notifications substream.
+    ///
+    #[track_caller]
+    pub fn notification_queue_capacity(&self, substream_id: SubstreamId) -> usize {
+        let (_, state) = self
+            .outgoing_notification_substreams
+            .get(&substream_id)
+            .unwrap_or_else(|| panic!("invalid outbound notifications substream"));
+        assert!(
+            matches!(state, SubstreamState::Open),
+            "outbound notifications substream isn't fully open"
+        );
+
+        let queue = self
+            .outgoing_notification_queues
+            .get(&substream_id)
+            .unwrap_or_else(|| unreachable!());
+
+        self.max_queued_notifications_bytes
+            .saturating_sub(queue.queued_bytes)
+    }
+
     /// Accepts a request for an inbound notifications substream reported by an
     /// [`Event::NotificationsInOpen`].
     ///
@@ -928,6 +1176,7 @@ where
                 .ingoing_notification_substreams_by_connection
                 .remove(&(connection_id, inner_substream_id));
             debug_assert_eq!(_was_in, Some(substream_id));
+            self.substream_closed(substream_id);
 
             self.messages_to_connections.push_back((
                 connection_id,
@@ -967,6 +1216,7 @@ where
 
         self.ingoing_requests_by_connection
             .remove(&(connection_id, substream_id));
+        self.substream_closed(substream_id);
 
         self.messages_to_connections.push_back((
             connection_id,
@@ -993,6 +1243,46 @@ where
             .map(|(id, inner)| (id, CoordinatorToConnection { inner }))
     }
 
+    /// Similar to [`Network::pull_message_to_connection`], but pulls at most `max` consecutive
+    /// messages destined to the same connection at once.
+    ///
+    /// The messages must be passed, in the order in which they are returned, to
+    /// [`SingleStreamConnectionTask::inject_coordinator_message`] or
+    /// [`MultiStreamConnectionTask::inject_coordinator_message`] in the appropriate connection.
+    ///
+    /// Pulling messages in batches makes it possible to wake up the task of a connection only
+    /// once for multiple messages.
+    ///
+    /// # Panic
+    ///
+    /// Panics if `max` is 0.
+    ///
+    pub fn pull_messages_to_connection(
+        &mut self,
+        max: usize,
+    ) -> Option<(ConnectionId, Vec<CoordinatorToConnection>)> {
+        assert_ne!(max, 0);
+
+        let (connection_id, first) = self.messages_to_connections.pop_front()?;
+
+        let num_messages = 1 + self
+            .messages_to_connections
+            .iter()
+            .take(max - 1)
+            .take_while(|(id, _)| *id == connection_id)
+            .count();
+
+        let mut messages = Vec::with_capacity(num_messages);
+        messages.push(CoordinatorToConnection { inner: first });
+        messages.extend(
+            self.messages_to_connections
+                .drain(..num_messages - 1)
+                .map(|(_, inner)| CoordinatorToConnection { inner }),
+        );
+
+        Some((connection_id, messages))
+    }
+
     /// Injects into the state machine a message generated by
     /// [`SingleStreamConnectionTask::pull_message_to_coordinator`] or
     /// [`MultiStreamConnectionTask::pull_message_to_coordinator`].
@@ -1024,6 +1314,10 @@ where
     /// [`Network::inject_connection_message`].
     pub fn next_event(&mut self) -> Option<Event<TConn>> {
         loop {
+            if let Some(event) = self.pending_lifecycle_events.pop_front() {
+                return Some(event);
+            }
+
             // When a connection starts its shutdown, its id is put in `shutting_down_connection`.
             // When that happens, we go through the local state and clean up all requests and
             // notification substreams that are in progress/open and return the cancellations
@@ -1048,6 +1342,8 @@ where
                         .outgoing_notification_substreams
                         .remove(&substream_id)
                         .unwrap();
+                    self.outgoing_notification_queues.remove(&substream_id);
+                    self.substream_closed(substream_id);
                     return Some(match state {
                         SubstreamState::Open => Event::NotificationsOutReset { substream_id },
                         SubstreamState::Pending => Event::NotificationsOutResult {
@@ -1079,6 +1375,7 @@ where
                     self.ingoing_notification_substreams_by_connection
                         .remove(&key)
                         .unwrap();
+                    self.substream_closed(substream_id);
 
                     return Some(Event::NotificationsInClose {
                         substream_id,
@@ -1098,6 +1395,7 @@ where
                     let substream_id = *substream_id;
                     self.outgoing_requests
                         .remove(&(shutting_down_connection, substream_id));
+                    self.substream_closed(substream_id);
 
                     return Some(Event::Response {
                         substream_id,
@@ -1122,6 +1420,7 @@ where
                         .ingoing_requests_by_connection
                         .remove(&(shutting_down_connection, substream_id));
                     debug_assert!(_was_in);
+                    self.substream_closed(substream_id);
 
                     return Some(Event::RequestInCancel { substream_id });
                 }
@@ -1151,6 +1450,7 @@ where
                         .ingoing_negotiated_substreams_by_connection
                         .remove(&key);
                     debug_assert!(_was_in.is_some());
+                    self.substream_closed(substream_id);
 
                     if was_accepted {
                         return Some(Event::InboundAcceptedCancel { substream_id });
@@ -1322,6 +1622,12 @@ where
                     );
                     self.ingoing_negotiated_substreams_by_connection
                         .insert((connection_id, connection_substream_id), substream_id);
+                    self.substream_opened(
+                        connection_id,
+                        substream_id,
+                        &protocol_name,
+                        SubstreamDirection::In,
+                    );
 
                     Event::InboundNegotiated {
                         id: connection_id,
@@ -1346,6 +1652,7 @@ where
                         .unwrap_or_else(|| unreachable!());
                     let _was_in = self.ingoing_negotiated_substreams.remove(&substream_id);
                     debug_assert!(_was_in.is_some());
+                    self.substream_closed(substream_id);
 
                     Event::InboundAcceptedCancel { substream_id }
                 }
@@ -1361,23 +1668,70 @@ where
                         continue;
                     }
 
-                    let substream_id = self
-                        .ingoing_negotiated_substreams_by_connection
-                        .remove(&(connection_id, connection_substream_id))
-                        .unwrap_or_else(|| unreachable!());
-                    let _was_in = self.ingoing_negotiated_substreams.remove(&substream_id);
-                    debug_assert!(_was_in.is_some());
-
-                    self.ingoing_requests
-                        .insert(substream_id, (connection_id, connection_substream_id));
-                    self.ingoing_requests_by_connection
-                        .insert((connection_id, substream_id));
+                    let substream_id =
+                        self.ingoing_request_received(connection_id, connection_substream_id);
 
                     Event::RequestIn {
                         substream_id,
                         request_payload: request,
                     }
                 }
+                ConnectionToCoordinatorInner::RequestInStart {
+                    id: connection_substream_id,
+                    request_size,
+                } => {
+                    // Ignore events if a shutdown has been initiated by the coordinator.
+                    if let InnerConnectionState::ShuttingDown { api_initiated, .. } =
+                        connection.state
+                    {
+                        debug_assert!(api_initiated);
+                        continue;
+                    }
+
+                    // If the request is empty, it has been fully received.
+                    let substream_id = if request_size == 0 {
+                        self.ingoing_request_received(connection_id, connection_substream_id)
+                    } else {
+                        *self
+                            .ingoing_negotiated_substreams_by_connection
+                            .get(&(connection_id, connection_substream_id))
+                            .unwrap_or_else(|| unreachable!())
+                    };
+
+                    Event::RequestInStart {
+                        substream_id,
+                        request_size,
+                    }
+                }
+                ConnectionToCoordinatorInner::RequestInChunk {
+                    id: connection_substream_id,
+                    chunk,
+                    is_last,
+                } => {
+                    // Ignore events if a shutdown has been initiated by the coordinator.
+                    if let InnerConnectionState::ShuttingDown { api_initiated, .. } =
+                        connection.state
+                    {
+                        debug_assert!(api_initiated);
+                        continue;
+                    }
+
+                    // After the last chunk, the request is now waiting for a response.
+                    let substream_id = if is_last {
+                        self.ingoing_request_received(connection_id, connection_substream_id)
+                    } else {
+                        *self
+                            .ingoing_negotiated_substreams_by_connection
+                            .get(&(connection_id, connection_substream_id))
+                            .unwrap_or_else(|| unreachable!())
+                    };
+
+                    Event::RequestInChunk {
+                        substream_id,
+                        chunk,
+                        is_last,
+                    }
+                }
                 ConnectionToCoordinatorInner::Response {
                     id: substream_id,
                     response,
@@ -1391,10 +1745,15 @@ where
                         continue;
                     }
 
-                    let _was_in = self
+                    // The request might have been cancelled by the API user, in which case
+                    // the response is ignored.
+                    if !self
                         .outgoing_requests
-                        .remove(&(connection_id, substream_id));
-                    debug_assert!(_was_in);
+                        .remove(&(connection_id, substream_id))
+                    {
+                        continue;
+                    }
+                    self.substream_closed(substream_id);
 
                     Event::Response {
                         substream_id,
@@ -1455,6 +1814,7 @@ where
                             .ingoing_notification_substreams
                             .remove(&substream_id)
                             .unwrap();
+                        self.substream_closed(substream_id);
                         match state {
                             SubstreamState::Open => Event::NotificationsInClose {
                                 substream_id,
@@ -1519,6 +1879,7 @@ where
                         .unwrap();
                     let _was_in = self.ingoing_notification_substreams.remove(&substream_id);
                     debug_assert!(_was_in.is_some());
+                    self.substream_closed(substream_id);
 
                     Event::NotificationsInClose {
                         substream_id,
@@ -1551,6 +1912,8 @@ where
 
                     if result.is_ok() {
                         entry.insert((connection_id, SubstreamState::Open));
+                        self.outgoing_notification_queues
+                            .insert(substream_id, NotificationsOutQueue::default());
                     } else {
                         entry.remove();
 
@@ -1558,6 +1921,7 @@ where
                             .outgoing_notification_substreams_by_connection
                             .remove(&(connection_id, substream_id));
                         debug_assert!(_was_removed);
+                        self.substream_closed(substream_id);
                     }
 
                     Event::NotificationsOutResult {
@@ -1616,10 +1980,39 @@ where
                         .outgoing_notification_substreams_by_connection
                         .remove(&(connection_id, substream_id));
                     debug_assert!(_was_removed);
+                    self.outgoing_notification_queues.remove(&substream_id);
+                    self.substream_closed(substream_id);
 
                     Event::NotificationsOutReset { substream_id }
                 }
-                ConnectionToCoordinatorInner::PingOutSuccess => {
+                ConnectionToCoordinatorInner::NotificationsOutQueueDrained {
+                    id: substream_id,
+                    num_bytes,
+                } => {
+                    // Ignore events if a shutdown has been initiated by the coordinator.
+                    if let InnerConnectionState::ShuttingDown { api_initiated, .. } =
+                        connection.state
+                    {
+                        debug_assert!(api_initiated);
+                        continue;
+                    }
+
+                    // The substream might already have been destroyed if the user closed the
+                    // substream while this message was pending in the queue.
+                    let Some(queue) = self.outgoing_notification_queues.get_mut(&substream_id)
+                    else {
+                        continue;
+                    };
+
+                    queue.queued_bytes = queue.queued_bytes.saturating_sub(num_bytes);
+                    if !queue.full || queue.queued_bytes > self.max_queued_notifications_bytes / 2 {
+                        continue;
+                    }
+
+                    queue.full = false;
+                    Event::NotificationsOutQueueDrained { substream_id }
+                }
+                ConnectionToCoordinatorInner::PingOutSuccess { ping_time } => {
                     // Ignore events if a shutdown has been initiated by the coordinator.
                     if let InnerConnectionState::ShuttingDown { api_initiated, .. } =
                         connection.state
@@ -1628,7 +2021,10 @@ where
                         continue;
                     }
 
-                    Event::PingOutSuccess { id: connection_id }
+                    Event::PingOutSuccess {
+                        id: connection_id,
+                        ping_time,
+                    }
                 }
                 ConnectionToCoordinatorInner::PingOutFailed => {
                     // Ignore events if a shutdown has been initiated by the coordinator.
@@ -1644,6 +2040,112 @@ where
             });
         }
     }
+
+    /// Moves an inbound substream from the list of negotiated substreams to the list of requests
+    /// waiting for a response, after the request has been fully received. Returns the
+    /// identifier of the substream.
+    fn ingoing_request_received(
+        &mut self,
+        connection_id: ConnectionId,
+        connection_substream_id: established::SubstreamId,
+    ) -> SubstreamId {
+        let substream_id = self
+            .ingoing_negotiated_substreams_by_connection
+            .remove(&(connection_id, connection_substream_id))
+            .unwrap_or_else(|| unreachable!());
+        let _was_in = self.ingoing_negotiated_substreams.remove(&substream_id);
+        debug_assert!(_was_in.is_some());
+
+        self.ingoing_requests
+            .insert(substream_id, (connection_id, connection_substream_id));
+        self.ingoing_requests_by_connection
+            .insert((connection_id, substream_id));
+
+        substream_id
+    }
+
+    /// Updates the substreams counters after a new substream has been opened, and queues an
+    /// [`Event::SubstreamOpened`] if necessary.
+    fn substream_opened(
+        &mut self,
+        connection_id: ConnectionId,
+        substream_id: SubstreamId,
+        protocol_name: &str,
+        direction: SubstreamDirection,
+    ) {
+        let substreams_counters = &mut self
+            .connections
+            .get_mut(&connection_id)
+            .unwrap_or_else(|| unreachable!())
+            .substreams_counters;
+        let counters_index = match substreams_counters
+            .iter()
+            .position(|(name, _)| name == protocol_name)
+        {
+            Some(index) => index,
+            None => {
+                substreams_counters.push((protocol_name.to_owned(), Default::default()));
+                substreams_counters.len() - 1
+            }
+        };
+
+        let counters = &mut substreams_counters[counters_index].1;
+        match direction {
+            SubstreamDirection::In => {
+                counters.num_inbound += 1;
+                counters.total_inbound += 1;
+            }
+            SubstreamDirection::Out => {
+                counters.num_outbound += 1;
+                counters.total_outbound += 1;
+            }
+        }
+
+        if self.substreams_lifecycle_events {
+            self.pending_lifecycle_events
+                .push_back(Event::SubstreamOpened {
+                    id: connection_id,
+                    substream_id,
+                    protocol_name: protocol_name.to_owned(),
+                    direction,
+                });
+        }
+
+        let _prev_value = self
+            .substreams_accounting
+            .insert(substream_id, (connection_id, counters_index, direction));
+        debug_assert!(_prev_value.is_none());
+    }
+
+    /// Updates the substreams counters after a substream has been destroyed, and queues an
+    /// [`Event::SubstreamClosed`] if necessary.
+    fn substream_closed(&mut self, substream_id: SubstreamId) {
+        let Some((connection_id, counters_index, direction)) =
+            self.substreams_accounting.remove(&substream_id)
+        else {
+            unreachable!()
+        };
+
+        let (protocol_name, counters) = &mut self
+            .connections
+            .get_mut(&connection_id)
+            .unwrap_or_else(|| unreachable!())
+            .substreams_counters[counters_index];
+        match direction {
+            SubstreamDirection::In => counters.num_inbound -= 1,
+            SubstreamDirection::Out => counters.num_outbound -= 1,
+        }
+
+        if self.substreams_lifecycle_events {
+            self.pending_lifecycle_events
+                .push_back(Event::SubstreamClosed {
+                    id: connection_id,
+                    substream_id,
+                    protocol_name: protocol_name.clone(),
+                    direction,
+                });
+        }
+    }
 }
 
 impl<TConn, TNow> ops::Index<ConnectionId> for Network<TConn, TNow> {
@@ -1669,6 +2171,30 @@ pub struct ConnectionState {
     pub shutting_down: bool,
 }
 
+/// See [`Network::substreams_counters`].
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct SubstreamsCounters {
+    /// Number of inbound substreams currently open.
+    pub num_inbound: usize,
+    /// Number of outbound substreams currently open.
+    pub num_outbound: usize,
+    /// Total number of inbound substreams that have been opened since the connection has been
+    /// added.
+    pub total_inbound: u64,
+    /// Total number of outbound substreams that have been opened since the connection has been
+    /// added.
+    pub total_outbound: u64,
+}
+
+/// Direction of a substream. See [`Event::SubstreamOpened`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SubstreamDirection {
+    /// Substream has been opened by the remote.
+    In,
+    /// Substream has been opened locally.
+    Out,
+}
+
 /// Message from a connection task destined to the coordinator.
 pub struct ConnectionToCoordinator {
     inner: ConnectionToCoordinatorInner,
@@ -1697,6 +2223,19 @@ enum ConnectionToCoordinatorInner {
         request: Vec<u8>,
     },
 
+    /// See the corresponding event in [`established::Event`].
+    RequestInStart {
+        id: established::SubstreamId,
+        request_size: usize,
+    },
+
+    /// See the corresponding event in [`established::Event`].
+    RequestInChunk {
+        id: established::SubstreamId,
+        chunk: Vec<u8>,
+        is_last: bool,
+    },
+
     /// See the corresponding event in [`established::Event`].
     Response {
         response: Result<Vec<u8>, established::RequestError>,
@@ -1755,8 +2294,18 @@ enum ConnectionToCoordinatorInner {
     NotificationsOutReset {
         id: SubstreamId,
     },
+    /// Some of the notifications queued on an outbound notifications substream have been sent
+    /// out.
+    NotificationsOutQueueDrained {
+        id: SubstreamId,
+        /// Number of bytes that have left the queue since the last message, including the
+        /// length prefix of each notification.
+        num_bytes: usize,
+    },
     /// See the corresponding event in [`established::Event`].
-    PingOutSuccess,
+    PingOutSuccess {
+        ping_time: Duration,
+    },
     /// See the corresponding event in [`established::Event`].
     PingOutFailed,
 
@@ -1810,6 +2359,11 @@ enum CoordinatorToConnectionInner {
         /// This is **not** the same as the actual substream used in the connection.
         substream_id: SubstreamId,
     },
+    CancelRequest {
+        /// Id of the substream assigned by the coordinator.
+        /// This is **not** the same as the actual substream used in the connection.
+        substream_id: SubstreamId,
+    },
     OpenOutNotifications {
         /// Id of the substream assigned by the coordinator.
         /// This is **not** the same as the actual substream used in the connection.
@@ -1957,6 +2511,36 @@ pub enum Event<TConn> {
         request_payload: Vec<u8>,
     },
 
+    /// Received the length of a request on a substream accepted with
+    /// [`InboundTy::RequestStreaming`].
+    ///
+    /// Followed with [`Event::RequestInChunk`] events whose total length is equal to
+    /// `request_size`. Once the request has been fully received, which is immediately if
+    /// `request_size` is 0, [`Network::respond_in_request`] must be called.
+    ///
+    /// An [`Event::InboundAcceptedCancel`] is generated if the substream is closed before the
+    /// request has been fully received.
+    RequestInStart {
+        /// Substream on which the request is being received. Must be passed back when providing
+        /// the response.
+        substream_id: SubstreamId,
+        /// Size in bytes of the request.
+        request_size: usize,
+    },
+
+    /// Received a chunk of a request on a substream accepted with
+    /// [`InboundTy::RequestStreaming`]. See [`Event::RequestInStart`].
+    RequestInChunk {
+        /// Substream on which the request is being received.
+        substream_id: SubstreamId,
+        /// Part of the payload that has been sent by the remote. Its interpretation is beyond
+        /// the scope of this module.
+        chunk: Vec<u8>,
+        /// `true` if this is the last chunk of the request, in which case the request has been
+        /// fully received.
+        is_last: bool,
+    },
+
     /// Request received earlier has been canceled by the remote.
     ///
     /// The [`SubstreamId`] is now invalid.
@@ -1988,6 +2572,13 @@ pub enum Event<TConn> {
     /// The substream no longer exists and the [`SubstreamId`] becomes invalid.
     NotificationsOutReset { substream_id: SubstreamId },
 
+    /// The queue of notifications of an outbound notifications substream, which was previously
+    /// full, has drained below half of [`Config::max_queued_notifications_bytes`].
+    ///
+    /// This event is only generated after [`Network::queue_notification`] has returned
+    /// [`QueueNotificationError::QueueFull`] for this substream.
+    NotificationsOutQueueDrained { substream_id: SubstreamId },
+
     /// The remote would like to open a notifications substream.
     ///
     /// The substream needs to be accepted or refused using [`Network::accept_in_notifications`]
@@ -2033,10 +2624,46 @@ pub enum Event<TConn> {
 
     /// An outgoing ping has succeeded. This event is generated automatically over time for each
     /// connection in the collection.
-    PingOutSuccess { id: ConnectionId },
+    PingOutSuccess {
+        id: ConnectionId,
+        /// Time it took for the remote to answer the ping.
+        ping_time: Duration,
+    },
     /// An outgoing ping has failed. This event is generated automatically over time for each
     /// connection in the collection.
     PingOutFailed { id: ConnectionId },
+
+    /// A substream has started being tracked by the [`Network`]. Only generated if
+    /// [`Config::substreams_lifecycle_events`] is `true`.
+    ///
+    /// For inbound substreams, this event is generated right after the corresponding
+    /// [`Event::InboundNegotiated`]. For outbound substreams, it is generated after
+    /// [`Network::start_request`] or [`Network::open_out_notifications`] has been called.
+    SubstreamOpened {
+        /// Connection the substream belongs to.
+        id: ConnectionId,
+        /// Identifier of the substream.
+        substream_id: SubstreamId,
+        /// Name of the protocol negotiated or requested on the substream.
+        protocol_name: String,
+        /// Whether the substream has been opened by the remote or locally.
+        direction: SubstreamDirection,
+    },
+
+    /// A substream previously reported with [`Event::SubstreamOpened`] is no longer tracked by
+    /// the [`Network`], either because it has been closed by the API user, by the remote, or
+    /// because of the connection shutting down. Only generated if
+    /// [`Config::substreams_lifecycle_events`] is `true`.
+    SubstreamClosed {
+        /// Connection the substream belongs to.
+        id: ConnectionId,
+        /// Identifier of the substream.
+        substream_id: SubstreamId,
+        /// Name of the protocol negotiated or requested on the substream.
+        protocol_name: String,
+        /// Whether the substream has been opened by the remote or locally.
+        direction: SubstreamDirection,
+    },
 }
 
 /// Reason why a connection is shutting down. See [`Event::StartShutdown`].
@@ -2051,7 +2678,25 @@ pub enum ShutdownCause {
     /// Error in the protocol of the handshake.
     HandshakeError(HandshakeError),
     /// Handshake phase took too long.
-    HandshakeTimeout,
+    #[display(fmt = "Handshake timeout during {_0}")]
+    HandshakeTimeout(HandshakeTimeoutStage),
+}
+
+/// Stage of the handshake during which a [`ShutdownCause::HandshakeTimeout`] has happened.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, derive_more::Display)]
+pub enum HandshakeTimeoutStage {
+    /// Handshake of a single-stream connection (TCP, WebSocket).
+    #[display(fmt = "{_0}")]
+    SingleStream(single_stream_handshake::HandshakeStage),
+    /// Noise handshake of a WebRTC connection.
+    ///
+    /// > **Note**: The DTLS handshake of WebRTC connections is performed before the connection
+    /// >           is inserted in the collection, and thus can't time out here.
+    #[display(fmt = "WebRTC Noise handshake message {message}")]
+    WebRtcNoise {
+        /// Number, between 1 and 3, of the Noise handshake message being sent or waited for.
+        message: u8,
+    },
 }
 
 #[derive(Debug, derive_more::Display, Clone)]
@@ -23,8 +23,8 @@ use super::{
         read_write::ReadWrite,
     },
     ConnectionToCoordinator, ConnectionToCoordinatorInner, CoordinatorToConnection,
-    CoordinatorToConnectionInner, NotificationsOutErr, PeerId, ShutdownCause, SubstreamFate,
-    SubstreamId,
+    CoordinatorToConnectionInner, HandshakeTimeoutStage, NotificationsOutErr, PeerId,
+    ShutdownCause, SubstreamFate, SubstreamId,
 };
 
 use alloc::{collections::VecDeque, string::ToString as _, sync::Arc, vec::Vec};
@@ -36,6 +36,19 @@ use core::{
     time::Duration,
 };
 
+pub(super) struct Config<TNow> {
+    pub(super) randomness_seed: [u8; 32],
+    pub(super) when_connection_start: TNow,
+    pub(super) handshake_timeout: TNow,
+    pub(super) handshake: noise::HandshakeInProgress,
+    pub(super) max_inbound_substreams: usize,
+    pub(super) substreams_capacity: usize,
+    pub(super) max_protocol_name_len: usize,
+    pub(super) ping_protocol: Arc<str>,
+    pub(super) ping_interval: Duration,
+    pub(super) ping_timeout: Duration,
+}
+
 /// State machine dedicated to a single multi-stream connection.
 pub struct MultiStreamConnectionTask<TNow, TSubId> {
     connection: MultiStreamConnectionTaskInner<TNow, TSubId>,
@@ -49,6 +62,9 @@ enum MultiStreamConnectionTaskInner<TNow, TSubId> {
         /// Noise handshake in progress. Always `Some`, except to be temporarily extracted.
         handshake: Option<noise::HandshakeInProgress>,
 
+        /// When the handshake phase times out.
+        timeout: TNow,
+
         /// All incoming data for the handshake substream is first transferred to this buffer.
         // TODO: this is very suboptimal code, instead the parsing should be done in a streaming way
         handshake_read_buffer: Vec<u8>,
@@ -87,6 +103,11 @@ enum MultiStreamConnectionTaskInner<TNow, TSubId> {
         outbound_substreams_map:
             hashbrown::HashMap<SubstreamId, established::SubstreamId, fnv::FnvBuildHasher>,
 
+        /// For each outbound notifications substream in which notifications have been queued,
+        /// the number of bytes queued the last time it was checked. Used in order to report to
+        /// the coordinator the number of bytes that have been sent out.
+        notifications_out_queued_bytes: hashbrown::HashMap<SubstreamId, usize, fnv::FnvBuildHasher>,
+
         /// After a [`ConnectionToCoordinatorInner::NotificationsInOpenCancel`] is emitted, an
         /// entry is added to this list. If the coordinator accepts or refuses a substream in this
         /// list, the acceptance/refusal is dismissed.
@@ -141,19 +162,24 @@ where
 {
     // Note that the parameters of this function are a bit rough and undocumented, as this is
     // a function only called from the parent module.
-    pub(super) fn new(
-        randomness_seed: [u8; 32],
-        when_connection_start: TNow,
-        handshake: noise::HandshakeInProgress,
-        max_inbound_substreams: usize,
-        substreams_capacity: usize,
-        max_protocol_name_len: usize,
-        ping_protocol: Arc<str>,
-    ) -> Self {
+    pub(super) fn new(config: Config<TNow>) -> Self {
+        let Config {
+            randomness_seed,
+            when_connection_start,
+            handshake_timeout,
+            handshake,
+            max_inbound_substreams,
+            substreams_capacity,
+            max_protocol_name_len,
+            ping_protocol,
+            ping_interval,
+            ping_timeout,
+        } = config;
+
         MultiStreamConnectionTask {
             connection: MultiStreamConnectionTaskInner::Handshake {
-                // TODO: the handshake doesn't have a timeout
                 handshake: Some(handshake),
+                timeout: handshake_timeout,
                 opened_substream: None,
                 handshake_read_buffer: Vec::new(),
                 extra_open_substreams: hashbrown::HashMap::with_capacity_and_hasher(
@@ -166,8 +192,8 @@ where
                     max_protocol_name_len,
                     randomness_seed,
                     ping_protocol: ping_protocol.to_string(), // TODO: cloning :-/
-                    ping_interval: Duration::from_secs(20),   // TODO: hardcoded
-                    ping_timeout: Duration::from_secs(10),    // TODO: hardcoded
+                    ping_interval,
+                    ping_timeout,
                     first_out_ping: when_connection_start, // TODO: only start the ping after the Noise handshake has ended
                 })),
             },
@@ -211,6 +237,7 @@ where
             MultiStreamConnectionTaskInner::Established {
                 established,
                 outbound_substreams_map,
+                notifications_out_queued_bytes,
                 handshake_finished_message_to_send,
                 notifications_in_open_cancel_acknowledgments,
                 inbound_negotiated_cancel_acknowledgments,
@@ -263,6 +290,12 @@ where
                     Some(established::Event::RequestIn { id, request, .. }) => {
                         Some(ConnectionToCoordinatorInner::RequestIn { id, request })
                     }
+                    Some(established::Event::RequestInStart { id, request_size }) => {
+                        Some(ConnectionToCoordinatorInner::RequestInStart { id, request_size })
+                    }
+                    Some(established::Event::RequestInChunk { id, chunk, is_last }) => {
+                        Some(ConnectionToCoordinatorInner::RequestInChunk { id, chunk, is_last })
+                    }
                     Some(established::Event::Response {
                         response,
                         user_data,
@@ -303,6 +336,7 @@ where
                                     panic!()
                                 };
                                 outbound_substreams_map.remove(&outer_substream_id);
+                                notifications_out_queued_bytes.remove(&outer_substream_id);
                                 (outer_substream_id, Err(NotificationsOutErr::Substream(err)))
                             }
                         };
@@ -327,17 +361,38 @@ where
                             panic!()
                         };
                         outbound_substreams_map.remove(&outer_substream_id);
+                        notifications_out_queued_bytes.remove(&outer_substream_id);
                         Some(ConnectionToCoordinatorInner::NotificationsOutReset {
                             id: outer_substream_id,
                         })
                     }
-                    Some(established::Event::PingOutSuccess) => {
-                        Some(ConnectionToCoordinatorInner::PingOutSuccess)
+                    Some(established::Event::PingOutSuccess { ping_time }) => {
+                        Some(ConnectionToCoordinatorInner::PingOutSuccess { ping_time })
                     }
                     Some(established::Event::PingOutFailed) => {
                         Some(ConnectionToCoordinatorInner::PingOutFailed)
                     }
-                    None => None,
+                    None => {
+                        // Report to the coordinator the notifications that have been sent out.
+                        // This is done only when the connection doesn't have any event to report,
+                        // as the substreams are guaranteed to all be alive at this point.
+                        notifications_out_queued_bytes.iter_mut().find_map(
+                            |(outer_substream_id, queued_bytes)| {
+                                let now_queued = established.notification_substream_queued_bytes(
+                                    *outbound_substreams_map.get(outer_substream_id)?,
+                                );
+                                let num_bytes = queued_bytes.checked_sub(now_queued)?;
+                                if num_bytes == 0 {
+                                    return None;
+                                }
+                                *queued_bytes = now_queued;
+                                Some(ConnectionToCoordinatorInner::NotificationsOutQueueDrained {
+                                    id: *outer_substream_id,
+                                    num_bytes,
+                                })
+                            },
+                        )
+                    }
                 };
 
                 (
@@ -436,6 +491,20 @@ where
                 let _prev_value = outbound_substreams_map.insert(substream_id, inner_substream_id);
                 debug_assert!(_prev_value.is_none());
             }
+            (
+                CoordinatorToConnectionInner::CancelRequest { substream_id },
+                MultiStreamConnectionTaskInner::Established {
+                    established,
+                    outbound_substreams_map,
+                    ..
+                },
+            ) => {
+                // The request might have finished in the meanwhile, in which case the
+                // cancellation is ignored.
+                if let Some(inner_substream_id) = outbound_substreams_map.remove(&substream_id) {
+                    established.cancel_request(inner_substream_id);
+                }
+            }
             (
                 CoordinatorToConnectionInner::OpenOutNotifications {
                     max_handshake_size,
@@ -467,9 +536,12 @@ where
                 MultiStreamConnectionTaskInner::Established {
                     established,
                     outbound_substreams_map,
+                    notifications_out_queued_bytes,
                     ..
                 },
             ) => {
+                notifications_out_queued_bytes.remove(&substream_id);
+
                 // It is possible that the remote has closed the outbound notification substream
                 // while the `CloseOutNotifications` message was being delivered, or that the API
                 // user close the substream before the message about the substream being closed
@@ -486,6 +558,7 @@ where
                 MultiStreamConnectionTaskInner::Established {
                     established,
                     outbound_substreams_map,
+                    notifications_out_queued_bytes,
                     ..
                 },
             ) => {
@@ -497,7 +570,14 @@ where
                 // notification to not be sent. This is consistent with the guarantees about
                 // notifications delivered that are documented in the public API.
                 if let Some(inner_substream_id) = outbound_substreams_map.get(&substream_id) {
+                    let before =
+                        established.notification_substream_queued_bytes(*inner_substream_id);
                     established.write_notification_unbounded(*inner_substream_id, notification);
+                    let after =
+                        established.notification_substream_queued_bytes(*inner_substream_id);
+                    *notifications_out_queued_bytes
+                        .entry(substream_id)
+                        .or_insert(0) += after - before;
                 }
             }
             (
@@ -572,6 +652,7 @@ where
                 | CoordinatorToConnectionInner::AcceptInNotifications { .. }
                 | CoordinatorToConnectionInner::RejectInNotifications { .. }
                 | CoordinatorToConnectionInner::StartRequest { .. }
+                | CoordinatorToConnectionInner::CancelRequest { .. }
                 | CoordinatorToConnectionInner::AnswerRequest { .. }
                 | CoordinatorToConnectionInner::OpenOutNotifications { .. }
                 | CoordinatorToConnectionInner::CloseOutNotifications { .. }
@@ -585,6 +666,7 @@ where
                 | CoordinatorToConnectionInner::AcceptInNotifications { .. }
                 | CoordinatorToConnectionInner::RejectInNotifications { .. }
                 | CoordinatorToConnectionInner::StartRequest { .. }
+                | CoordinatorToConnectionInner::CancelRequest { .. }
                 | CoordinatorToConnectionInner::AnswerRequest { .. }
                 | CoordinatorToConnectionInner::OpenOutNotifications { .. }
                 | CoordinatorToConnectionInner::CloseOutNotifications { .. }
@@ -839,6 +921,7 @@ where
         match &mut self.connection {
             MultiStreamConnectionTaskInner::Handshake {
                 handshake,
+                timeout,
                 opened_substream,
                 handshake_read_buffer,
                 established,
@@ -847,7 +930,24 @@ where
                 .as_ref()
                 .map_or(false, |s| s == substream_id) =>
             {
-                // TODO: check the handshake timeout
+                // Check that the handshake isn't taking too long. See the equivalent check in
+                // the single-stream connection for the reasoning behind checking this before
+                // looking into the incoming data.
+                // TODO: the timeout is only checked when the handshake substream is processed
+                if *timeout < read_write.now {
+                    let message = handshake.as_ref().unwrap().current_message_number();
+                    self.connection = MultiStreamConnectionTaskInner::ShutdownWaitingAck {
+                        start_shutdown_message_to_send: Some(Some(
+                            ShutdownCause::HandshakeTimeout(HandshakeTimeoutStage::WebRtcNoise {
+                                message,
+                            }),
+                        )),
+                        shutdown_finish_message_sent: false,
+                        initiator: ShutdownInitiator::Coordinator,
+                    };
+                    return SubstreamFate::Reset;
+                }
+                read_write.wake_up_after(timeout);
 
                 // The Noise data is not directly the data of the substream. Instead, everything
                 // is wrapped within a Protobuf frame. For this reason, we first transfer the data
@@ -989,6 +1089,8 @@ where
                                 0,
                                 Default::default(),
                             ),
+                            notifications_out_queued_bytes:
+                                hashbrown::HashMap::with_capacity_and_hasher(0, Default::default()),
                             notifications_in_open_cancel_acknowledgments: VecDeque::with_capacity(
                                 4,
                             ),
@@ -21,7 +21,8 @@ use super::{
         read_write::ReadWrite,
     },
     ConnectionToCoordinator, ConnectionToCoordinatorInner, CoordinatorToConnection,
-    CoordinatorToConnectionInner, NotificationsOutErr, ShutdownCause, SubstreamId,
+    CoordinatorToConnectionInner, HandshakeTimeoutStage, NotificationsOutErr, ShutdownCause,
+    SubstreamId,
 };
 
 use alloc::{collections::VecDeque, string::ToString as _, sync::Arc};
@@ -39,6 +40,8 @@ pub(super) struct Config<TNow> {
     pub(super) substreams_capacity: usize,
     pub(super) max_protocol_name_len: usize,
     pub(super) ping_protocol: Arc<str>,
+    pub(super) ping_interval: Duration,
+    pub(super) ping_timeout: Duration,
 }
 
 /// State machine dedicated to a single single-stream connection.
@@ -78,6 +81,12 @@ enum SingleStreamConnectionTaskInner<TNow> {
 
         /// See [`super::Config::ping_protocol`].
         ping_protocol: Arc<str>,
+
+        /// See [`super::Config::ping_interval`].
+        ping_interval: Duration,
+
+        /// See [`super::Config::ping_timeout`].
+        ping_timeout: Duration,
     },
 
     /// Connection has been fully established.
@@ -89,6 +98,11 @@ enum SingleStreamConnectionTaskInner<TNow> {
         outbound_substreams_map:
             hashbrown::HashMap<SubstreamId, established::SubstreamId, fnv::FnvBuildHasher>,
 
+        /// For each outbound notifications substream in which notifications have been queued,
+        /// the number of bytes queued the last time it was checked. Used in order to report to
+        /// the coordinator the number of bytes that have been sent out.
+        notifications_out_queued_bytes: hashbrown::HashMap<SubstreamId, usize, fnv::FnvBuildHasher>,
+
         /// After a [`ConnectionToCoordinatorInner::NotificationsInOpenCancel`] is emitted, an
         /// entry is added to this list. If the coordinator accepts or refuses a substream in this
         /// list, the acceptance/refusal is dismissed.
@@ -147,6 +161,8 @@ where
                 substreams_capacity: config.substreams_capacity,
                 max_protocol_name_len: config.max_protocol_name_len,
                 ping_protocol: config.ping_protocol,
+                ping_interval: config.ping_interval,
+                ping_timeout: config.ping_timeout,
             },
             pending_messages: VecDeque::with_capacity({
                 // We never buffer more than a few messages.
@@ -274,6 +290,20 @@ where
                 let _prev_value = outbound_substreams_map.insert(substream_id, inner_substream_id);
                 debug_assert!(_prev_value.is_none());
             }
+            (
+                CoordinatorToConnectionInner::CancelRequest { substream_id },
+                SingleStreamConnectionTaskInner::Established {
+                    established,
+                    outbound_substreams_map,
+                    ..
+                },
+            ) => {
+                // The request might have finished in the meanwhile, in which case the
+                // cancellation is ignored.
+                if let Some(inner_substream_id) = outbound_substreams_map.remove(&substream_id) {
+                    established.cancel_request(inner_substream_id);
+                }
+            }
             (
                 CoordinatorToConnectionInner::OpenOutNotifications {
                     protocol_name,
@@ -305,9 +335,12 @@ where
                 SingleStreamConnectionTaskInner::Established {
                     established,
                     outbound_substreams_map,
+                    notifications_out_queued_bytes,
                     ..
                 },
             ) => {
+                notifications_out_queued_bytes.remove(&substream_id);
+
                 // It is possible that the remote has closed the outbound notification substream
                 // while the `CloseOutNotifications` message was being delivered, or that the API
                 // user close the substream before the message about the substream being closed
@@ -324,6 +357,7 @@ where
                 SingleStreamConnectionTaskInner::Established {
                     established,
                     outbound_substreams_map,
+                    notifications_out_queued_bytes,
                     ..
                 },
             ) => {
@@ -335,7 +369,14 @@ where
                 // notification to not be sent. This is consistent with the guarantees about
                 // notifications delivered that are documented in the public API.
                 if let Some(inner_substream_id) = outbound_substreams_map.get(&substream_id) {
+                    let before =
+                        established.notification_substream_queued_bytes(*inner_substream_id);
                     established.write_notification_unbounded(*inner_substream_id, notification);
+                    let after =
+                        established.notification_substream_queued_bytes(*inner_substream_id);
+                    *notifications_out_queued_bytes
+                        .entry(substream_id)
+                        .or_insert(0) += after - before;
                 }
             }
             (
@@ -412,6 +453,7 @@ where
                 | CoordinatorToConnectionInner::AcceptInNotifications { .. }
                 | CoordinatorToConnectionInner::RejectInNotifications { .. }
                 | CoordinatorToConnectionInner::StartRequest { .. }
+                | CoordinatorToConnectionInner::CancelRequest { .. }
                 | CoordinatorToConnectionInner::AnswerRequest { .. }
                 | CoordinatorToConnectionInner::OpenOutNotifications { .. }
                 | CoordinatorToConnectionInner::CloseOutNotifications { .. }
@@ -425,6 +467,7 @@ where
                 | CoordinatorToConnectionInner::AcceptInNotifications { .. }
                 | CoordinatorToConnectionInner::RejectInNotifications { .. }
                 | CoordinatorToConnectionInner::StartRequest { .. }
+                | CoordinatorToConnectionInner::CancelRequest { .. }
                 | CoordinatorToConnectionInner::AnswerRequest { .. }
                 | CoordinatorToConnectionInner::OpenOutNotifications { .. }
                 | CoordinatorToConnectionInner::CloseOutNotifications { .. }
@@ -548,6 +591,7 @@ where
             SingleStreamConnectionTaskInner::Established {
                 established,
                 mut outbound_substreams_map,
+                mut notifications_out_queued_bytes,
                 mut notifications_in_open_cancel_acknowledgments,
                 mut inbound_negotiated_cancel_acknowledgments,
             } => match established.read_write(read_write) {
@@ -605,6 +649,16 @@ where
                             self.pending_messages
                                 .push_back(ConnectionToCoordinatorInner::RequestIn { id, request });
                         }
+                        Some(established::Event::RequestInStart { id, request_size }) => {
+                            self.pending_messages.push_back(
+                                ConnectionToCoordinatorInner::RequestInStart { id, request_size },
+                            );
+                        }
+                        Some(established::Event::RequestInChunk { id, chunk, is_last }) => {
+                            self.pending_messages.push_back(
+                                ConnectionToCoordinatorInner::RequestInChunk { id, chunk, is_last },
+                            );
+                        }
                         Some(established::Event::Response {
                             response,
                             user_data,
@@ -655,6 +709,7 @@ where
                                         panic!()
                                     };
                                     outbound_substreams_map.remove(&outer_substream_id);
+                                    notifications_out_queued_bytes.remove(&outer_substream_id);
                                     (outer_substream_id, Err(NotificationsOutErr::Substream(err)))
                                 }
                             };
@@ -681,26 +736,59 @@ where
                                 panic!()
                             };
                             outbound_substreams_map.remove(&outer_substream_id);
+                            notifications_out_queued_bytes.remove(&outer_substream_id);
                             self.pending_messages.push_back(
                                 ConnectionToCoordinatorInner::NotificationsOutReset {
                                     id: outer_substream_id,
                                 },
                             );
                         }
-                        Some(established::Event::PingOutSuccess) => {
-                            self.pending_messages
-                                .push_back(ConnectionToCoordinatorInner::PingOutSuccess);
+                        Some(established::Event::PingOutSuccess { ping_time }) => {
+                            self.pending_messages.push_back(
+                                ConnectionToCoordinatorInner::PingOutSuccess { ping_time },
+                            );
                         }
                         Some(established::Event::PingOutFailed) => {
                             self.pending_messages
                                 .push_back(ConnectionToCoordinatorInner::PingOutFailed);
                         }
-                        None => {}
+                        None => {
+                            // Report to the coordinator the notifications that have been sent
+                            // out. Only one substream is reported at a time, in order to not
+                            // buffer too many messages. The other substreams will be reported
+                            // during the next calls to `read_write`.
+                            // This is done only when the connection hasn't generated any event,
+                            // as the substreams are guaranteed to all be alive at this point.
+                            if let Some((outer_substream_id, num_bytes)) =
+                                notifications_out_queued_bytes.iter_mut().find_map(
+                                    |(outer_substream_id, queued_bytes)| {
+                                        let now_queued = connection
+                                            .notification_substream_queued_bytes(
+                                                *outbound_substreams_map.get(outer_substream_id)?,
+                                            );
+                                        let num_bytes = queued_bytes.checked_sub(now_queued)?;
+                                        if num_bytes == 0 {
+                                            return None;
+                                        }
+                                        *queued_bytes = now_queued;
+                                        Some((*outer_substream_id, num_bytes))
+                                    },
+                                )
+                            {
+                                self.pending_messages.push_back(
+                                    ConnectionToCoordinatorInner::NotificationsOutQueueDrained {
+                                        id: outer_substream_id,
+                                        num_bytes,
+                                    },
+                                );
+                            }
+                        }
                     }
 
                     self.connection = SingleStreamConnectionTaskInner::Established {
                         established: connection,
                         outbound_substreams_map,
+                        notifications_out_queued_bytes,
                         notifications_in_open_cancel_acknowledgments,
                         inbound_negotiated_cancel_acknowledgments,
                     };
@@ -726,6 +814,8 @@ where
                 substreams_capacity,
                 max_protocol_name_len,
                 ping_protocol,
+                ping_interval,
+                ping_timeout,
             } => {
                 // Check that the handshake isn't taking too long.
                 //
@@ -741,7 +831,9 @@ where
                 if timeout < read_write.now {
                     self.pending_messages
                         .push_back(ConnectionToCoordinatorInner::StartShutdown(Some(
-                            ShutdownCause::HandshakeTimeout,
+                            ShutdownCause::HandshakeTimeout(HandshakeTimeoutStage::SingleStream(
+                                handshake.stage(),
+                            )),
                         )));
                     self.pending_messages
                         .push_back(ConnectionToCoordinatorInner::ShutdownFinished);
@@ -788,6 +880,8 @@ where
                                 substreams_capacity,
                                 max_protocol_name_len,
                                 ping_protocol,
+                                ping_interval,
+                                ping_timeout,
                             };
                             break;
                         }
@@ -809,8 +903,8 @@ where
                                     max_protocol_name_len,
                                     randomness_seed,
                                     ping_protocol: ping_protocol.to_string(), // TODO: cloning :-/
-                                    ping_interval: Duration::from_secs(20),   // TODO: hardcoded
-                                    ping_timeout: Duration::from_secs(10),    // TODO: hardcoded
+                                    ping_interval,
+                                    ping_timeout,
                                     first_out_ping: read_write.now.clone() + Duration::from_secs(2), // TODO: hardcoded
                                 }),
                                 outbound_substreams_map:
@@ -818,6 +912,11 @@ where
                                         0,
                                         Default::default(),
                                     ), // TODO: capacity?
+                                notifications_out_queued_bytes:
+                                    hashbrown::HashMap::with_capacity_and_hasher(
+                                        0,
+                                        Default::default(),
+                                    ),
                                 notifications_in_open_cancel_acknowledgments:
                                     VecDeque::with_capacity(4),
                                 inbound_negotiated_cancel_acknowledgments:
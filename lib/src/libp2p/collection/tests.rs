@@ -0,0 +1,491 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+#![cfg(test)]
+
+use super::{
+    Config, ConnectionId, Event, InboundTy, Network, ReadWrite, SingleStreamConnectionTask,
+    SingleStreamHandshakeKind, SubstreamDirection, SubstreamId, SubstreamsCounters,
+};
+use crate::libp2p::connection::noise::NoiseKey;
+use core::{mem, time::Duration};
+
+const PING_PROTOCOL: &str = "/ipfs/ping/1.0.0";
+const NOTIFICATIONS_PROTOCOL: &str = "/test/notifications/1";
+
+/// Collection containing a single connection, connected to the connection of another [`Peer`].
+struct Peer {
+    network: Network<(), Duration>,
+    connection_id: ConnectionId,
+    /// `None` once the connection has entirely shut down.
+    task: Option<SingleStreamConnectionTask<Duration>>,
+    /// Data written out by [`Peer::task`] and not read by the other peer yet.
+    outgoing_buffer: Vec<u8>,
+    /// If `false`, inbound notifications substreams are rejected instead of accepted.
+    accept_in_notifications: bool,
+}
+
+impl Peer {
+    fn new(
+        is_initiator: bool,
+        max_queued_notifications_bytes: usize,
+        substreams_lifecycle_events: bool,
+    ) -> Self {
+        let mut network = Network::new(Config {
+            randomness_seed: rand::random(),
+            capacity: 1,
+            max_inbound_substreams: 16,
+            handshake_timeout: Duration::from_secs(5),
+            ping_protocol: PING_PROTOCOL.into(),
+            ping_interval: Duration::from_secs(20),
+            ping_timeout: Duration::from_secs(10),
+            max_queued_notifications_bytes,
+            substreams_lifecycle_events,
+        });
+
+        let noise_key = NoiseKey::new(&rand::random(), &rand::random());
+        let (connection_id, task) = network.insert_single_stream(
+            Duration::new(0, 0),
+            SingleStreamHandshakeKind::MultistreamSelectNoiseYamux {
+                is_initiator,
+                noise_key: &noise_key,
+            },
+            16,
+            64,
+            (),
+        );
+
+        Peer {
+            network,
+            connection_id,
+            task: Some(task),
+            outgoing_buffer: Vec::new(),
+            accept_in_notifications: true,
+        }
+    }
+
+    /// Processes the messages between the collection and its connection, reads the data found
+    /// in `incoming`, and writes out data. Inbound substreams are automatically accepted or
+    /// rejected, and the other events are pushed to `events`.
+    ///
+    /// Returns `true` if any progress has been made.
+    fn step(&mut self, incoming: &mut Vec<u8>, events: &mut Vec<Event<()>>) -> bool {
+        let now = Duration::new(0, 0);
+        let mut progress = false;
+
+        while let Some((_, message)) = self.network.pull_message_to_connection() {
+            if let Some(task) = self.task.as_mut() {
+                task.inject_coordinator_message(&now, message);
+            }
+            progress = true;
+        }
+
+        let Some(task) = self.task.as_mut() else {
+            return progress;
+        };
+
+        let mut read_write = ReadWrite {
+            now,
+            incoming_buffer: mem::take(incoming),
+            expected_incoming_bytes: Some(0),
+            read_bytes: 0,
+            write_buffers: Vec::new(),
+            write_bytes_queued: 0,
+            write_bytes_queueable: Some(4096),
+            wake_up_after: None,
+        };
+        task.read_write(&mut read_write);
+        *incoming = read_write.incoming_buffer;
+        progress |= read_write.read_bytes != 0;
+        // The connection asks to be polled again immediately if it has more work to do.
+        progress |= read_write.wake_up_after.map_or(false, |when| when <= now);
+        for buffer in read_write.write_buffers {
+            progress |= !buffer.is_empty();
+            self.outgoing_buffer.extend(buffer);
+        }
+
+        while let Some(task) = self.task.take() {
+            let (task, message) = task.pull_message_to_coordinator();
+            self.task = task;
+            let Some(message) = message else { break };
+            self.network
+                .inject_connection_message(self.connection_id, message);
+            progress = true;
+        }
+
+        while let Some(event) = self.network.next_event() {
+            progress = true;
+            match event {
+                Event::InboundNegotiated {
+                    substream_id,
+                    protocol_name,
+                    ..
+                } => {
+                    let ty = if protocol_name == PING_PROTOCOL {
+                        InboundTy::Ping
+                    } else {
+                        InboundTy::Notifications {
+                            max_handshake_size: 1024,
+                        }
+                    };
+                    self.network.accept_inbound(substream_id, ty);
+                }
+                Event::NotificationsInOpen { substream_id, .. } => {
+                    if self.accept_in_notifications {
+                        self.network
+                            .accept_in_notifications(substream_id, Vec::new(), 1024 * 1024);
+                    } else {
+                        self.network.reject_in_notifications(substream_id);
+                    }
+                }
+                ev => events.push(ev),
+            }
+        }
+
+        progress
+    }
+}
+
+/// Runs the two peers until no progress is made. Returns the events generated by each of them.
+fn run_until_idle(alice: &mut Peer, bob: &mut Peer) -> (Vec<Event<()>>, Vec<Event<()>>) {
+    let mut alice_events = Vec::new();
+    let mut bob_events = Vec::new();
+
+    for _ in 0..10000 {
+        let alice_progress = alice.step(&mut bob.outgoing_buffer, &mut alice_events);
+        let bob_progress = bob.step(&mut alice.outgoing_buffer, &mut bob_events);
+        if !alice_progress && !bob_progress {
+            return (alice_events, bob_events);
+        }
+    }
+
+    panic!("peers never became idle")
+}
+
+/// Connects two peers.
+fn connect(
+    max_queued_notifications_bytes: usize,
+    substreams_lifecycle_events: bool,
+) -> (Peer, Peer) {
+    let mut alice = Peer::new(
+        true,
+        max_queued_notifications_bytes,
+        substreams_lifecycle_events,
+    );
+    let mut bob = Peer::new(
+        false,
+        max_queued_notifications_bytes,
+        substreams_lifecycle_events,
+    );
+
+    let (alice_events, bob_events) = run_until_idle(&mut alice, &mut bob);
+    assert!(alice_events
+        .iter()
+        .any(|ev| matches!(ev, Event::HandshakeFinished { .. })));
+    assert!(bob_events
+        .iter()
+        .any(|ev| matches!(ev, Event::HandshakeFinished { .. })));
+
+    (alice, bob)
+}
+
+/// Opens a notifications substream from Alice to Bob and runs the two peers until idle. Returns
+/// the identifier of this substream within Alice and the events generated by each peer.
+fn open_out_notifications(
+    alice: &mut Peer,
+    bob: &mut Peer,
+) -> (SubstreamId, Vec<Event<()>>, Vec<Event<()>>) {
+    let substream_id = alice.network.open_out_notifications(
+        alice.connection_id,
+        NOTIFICATIONS_PROTOCOL.into(),
+        Duration::from_secs(5),
+        Vec::new(),
+        1024,
+    );
+
+    let (alice_events, bob_events) = run_until_idle(alice, bob);
+    (substream_id, alice_events, bob_events)
+}
+
+/// Connects two peers and opens a notifications substream from Alice to Bob. Returns the
+/// identifier of this substream within Alice.
+fn open_notifications_substream(
+    max_queued_notifications_bytes: usize,
+) -> (Peer, Peer, SubstreamId) {
+    let (mut alice, mut bob) = connect(max_queued_notifications_bytes, false);
+
+    let (substream_id, alice_events, _) = open_out_notifications(&mut alice, &mut bob);
+    assert!(alice_events.iter().any(|ev| matches!(
+        ev,
+        Event::NotificationsOutResult { substream_id: id, result: Ok(_) } if *id == substream_id
+    )));
+
+    (alice, bob, substream_id)
+}
+
+/// Returns the number of [`Event::SubstreamOpened`] and [`Event::SubstreamClosed`] events of
+/// the notifications protocol in the given direction found in `events`.
+fn lifecycle_events(events: &[Event<()>], direction: SubstreamDirection) -> (usize, usize) {
+    let opened = events
+        .iter()
+        .filter(|ev| {
+            matches!(ev, Event::SubstreamOpened { protocol_name, direction: d, .. }
+                if protocol_name == NOTIFICATIONS_PROTOCOL && *d == direction)
+        })
+        .count();
+    let closed = events
+        .iter()
+        .filter(|ev| {
+            matches!(ev, Event::SubstreamClosed { protocol_name, direction: d, .. }
+                if protocol_name == NOTIFICATIONS_PROTOCOL && *d == direction)
+        })
+        .count();
+    (opened, closed)
+}
+
+fn notifications_counters(peer: &Peer) -> SubstreamsCounters {
+    peer.network
+        .substreams_counters(peer.connection_id, NOTIFICATIONS_PROTOCOL)
+}
+
+#[test]
+fn notifications_queue_drained_after_full() {
+    let (mut alice, mut bob, substream_id) = open_notifications_substream(1024);
+    assert_eq!(
+        alice.network.notification_queue_capacity(substream_id),
+        1024
+    );
+
+    // Each notification occupies 101 bytes in the queue, including its length prefix.
+    let mut num_queued = 0;
+    while let Ok(()) = alice.network.queue_notification(substream_id, vec![0; 100]) {
+        num_queued += 1;
+    }
+    assert_eq!(num_queued, 10);
+    assert_eq!(alice.network.notification_queue_capacity(substream_id), 14);
+
+    let (alice_events, bob_events) = run_until_idle(&mut alice, &mut bob);
+    assert!(alice_events.iter().any(|ev| matches!(
+        ev,
+        Event::NotificationsOutQueueDrained { substream_id: id } if *id == substream_id
+    )));
+    assert_eq!(
+        bob_events
+            .iter()
+            .filter(|ev| matches!(ev, Event::NotificationsIn { .. }))
+            .count(),
+        num_queued
+    );
+    assert_eq!(
+        alice.network.notification_queue_capacity(substream_id),
+        1024
+    );
+}
+
+#[test]
+fn notifications_queue_drained_not_reported_if_never_full() {
+    let (mut alice, mut bob, substream_id) = open_notifications_substream(1024);
+
+    alice
+        .network
+        .queue_notification(substream_id, vec![0; 100])
+        .unwrap();
+    assert_eq!(alice.network.notification_queue_capacity(substream_id), 923);
+
+    let (alice_events, _) = run_until_idle(&mut alice, &mut bob);
+    assert!(!alice_events
+        .iter()
+        .any(|ev| matches!(ev, Event::NotificationsOutQueueDrained { .. })));
+    assert_eq!(
+        alice.network.notification_queue_capacity(substream_id),
+        1024
+    );
+}
+
+#[test]
+fn substreams_counters_open_and_close() {
+    let (mut alice, mut bob) = connect(1024, true);
+    assert_eq!(
+        notifications_counters(&alice),
+        SubstreamsCounters::default()
+    );
+    assert_eq!(notifications_counters(&bob), SubstreamsCounters::default());
+
+    let (substream_id, alice_events, bob_events) = open_out_notifications(&mut alice, &mut bob);
+    assert!(alice_events.iter().any(|ev| matches!(
+        ev,
+        Event::SubstreamOpened { substream_id: id, .. } if *id == substream_id
+    )));
+    assert_eq!(
+        lifecycle_events(&alice_events, SubstreamDirection::Out),
+        (1, 0)
+    );
+    assert_eq!(
+        lifecycle_events(&bob_events, SubstreamDirection::In),
+        (1, 0)
+    );
+    assert_eq!(
+        notifications_counters(&alice),
+        SubstreamsCounters {
+            num_inbound: 0,
+            num_outbound: 1,
+            total_inbound: 0,
+            total_outbound: 1,
+        }
+    );
+    assert_eq!(
+        notifications_counters(&bob),
+        SubstreamsCounters {
+            num_inbound: 1,
+            num_outbound: 0,
+            total_inbound: 1,
+            total_outbound: 0,
+        }
+    );
+
+    alice.network.close_out_notifications(substream_id);
+    let (alice_events, bob_events) = run_until_idle(&mut alice, &mut bob);
+    assert!(alice_events.iter().any(|ev| matches!(
+        ev,
+        Event::SubstreamClosed { substream_id: id, .. } if *id == substream_id
+    )));
+    assert_eq!(
+        lifecycle_events(&alice_events, SubstreamDirection::Out),
+        (0, 1)
+    );
+    assert_eq!(
+        lifecycle_events(&bob_events, SubstreamDirection::In),
+        (0, 1)
+    );
+    assert_eq!(
+        notifications_counters(&alice),
+        SubstreamsCounters {
+            num_inbound: 0,
+            num_outbound: 0,
+            total_inbound: 0,
+            total_outbound: 1,
+        }
+    );
+    assert_eq!(
+        notifications_counters(&bob),
+        SubstreamsCounters {
+            num_inbound: 0,
+            num_outbound: 0,
+            total_inbound: 1,
+            total_outbound: 0,
+        }
+    );
+}
+
+#[test]
+fn substreams_counters_reject() {
+    let (mut alice, mut bob) = connect(1024, true);
+    bob.accept_in_notifications = false;
+
+    let (substream_id, alice_events, bob_events) = open_out_notifications(&mut alice, &mut bob);
+    assert!(alice_events.iter().any(|ev| matches!(
+        ev,
+        Event::NotificationsOutResult { substream_id: id, result: Err(_) } if *id == substream_id
+    )));
+    assert_eq!(
+        lifecycle_events(&alice_events, SubstreamDirection::Out),
+        (1, 1)
+    );
+    assert_eq!(
+        lifecycle_events(&bob_events, SubstreamDirection::In),
+        (1, 1)
+    );
+    assert_eq!(
+        notifications_counters(&alice),
+        SubstreamsCounters {
+            num_inbound: 0,
+            num_outbound: 0,
+            total_inbound: 0,
+            total_outbound: 1,
+        }
+    );
+    assert_eq!(
+        notifications_counters(&bob),
+        SubstreamsCounters {
+            num_inbound: 0,
+            num_outbound: 0,
+            total_inbound: 1,
+            total_outbound: 0,
+        }
+    );
+}
+
+#[test]
+fn substreams_closed_on_connection_shutdown() {
+    let (mut alice, mut bob) = connect(1024, true);
+    let (_, alice_events, bob_events) = open_out_notifications(&mut alice, &mut bob);
+    assert_eq!(
+        lifecycle_events(&alice_events, SubstreamDirection::Out),
+        (1, 0)
+    );
+    assert_eq!(
+        lifecycle_events(&bob_events, SubstreamDirection::In),
+        (1, 0)
+    );
+    assert_eq!(notifications_counters(&alice).num_outbound, 1);
+    assert_eq!(notifications_counters(&bob).num_inbound, 1);
+
+    // Each peer shuts down its side of the connection. All the substreams are reported as
+    // closed before the connection is reported as shut down.
+    for (peer, direction) in [
+        (&mut alice, SubstreamDirection::Out),
+        (&mut bob, SubstreamDirection::In),
+    ] {
+        peer.network.start_shutdown(peer.connection_id);
+        let mut events = Vec::new();
+        while peer.step(&mut Vec::new(), &mut events) {}
+
+        let shutdown_position = events
+            .iter()
+            .position(|ev| matches!(ev, Event::Shutdown { .. }))
+            .unwrap();
+        assert_eq!(
+            lifecycle_events(&events[..shutdown_position], direction),
+            (0, 1)
+        );
+        assert!(peer.network.is_empty());
+    }
+}
+
+#[test]
+fn substreams_counters_without_lifecycle_events() {
+    let (mut alice, mut bob) = connect(1024, false);
+
+    let (substream_id, alice_events, bob_events) = open_out_notifications(&mut alice, &mut bob);
+    assert_eq!(notifications_counters(&alice).num_outbound, 1);
+    assert_eq!(notifications_counters(&bob).num_inbound, 1);
+
+    alice.network.close_out_notifications(substream_id);
+    let (alice_events2, bob_events2) = run_until_idle(&mut alice, &mut bob);
+    assert_eq!(notifications_counters(&alice).num_outbound, 0);
+    assert_eq!(notifications_counters(&bob).num_inbound, 0);
+
+    assert!(alice_events
+        .iter()
+        .chain(&bob_events)
+        .chain(&alice_events2)
+        .chain(&bob_events2)
+        .all(|ev| !matches!(
+            ev,
+            Event::SubstreamOpened { .. } | Event::SubstreamClosed { .. }
+        )));
+}
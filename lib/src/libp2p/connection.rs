@@ -76,6 +76,9 @@
 //! to obtain an [`established::SingleStream`]. Similar to the handshake, use
 //! [`established::SingleStream::read_write`] to update the state machine.
 //!
+//! If the remote is only reachable through the WebSocket protocol, the [`websocket`] module can
+//! be used in order to wrap the data exchanged over the TCP connection into WebSocket frames.
+//!
 
 pub use noise::{NoiseKey, UnsignedNoiseKey};
 
@@ -83,4 +86,5 @@ pub mod established;
 pub mod multistream_select;
 pub mod noise;
 pub mod single_stream_handshake;
+pub mod websocket;
 pub mod yamux;
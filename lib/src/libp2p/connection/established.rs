@@ -119,6 +119,30 @@ pub enum Event<TSubUd> {
         request: Vec<u8>,
     },
 
+    /// Received the length of a request on a substream accepted with
+    /// [`InboundTy::RequestStreaming`].
+    ///
+    /// Followed with [`Event::RequestInChunk`] events whose total length is equal to
+    /// `request_size`. If `request_size` is 0, the request has been fully received.
+    RequestInStart {
+        /// Identifier of the request. Needs to be provided back when answering the request.
+        id: SubstreamId,
+        /// Size in bytes of the request.
+        request_size: usize,
+    },
+
+    /// Received a chunk of a request on a substream accepted with
+    /// [`InboundTy::RequestStreaming`].
+    RequestInChunk {
+        /// Identifier of the request. Needs to be provided back when answering the request.
+        id: SubstreamId,
+        /// Bytes of the request. Its interpretation is out of scope of this module.
+        chunk: Vec<u8>,
+        /// `true` if this is the last chunk of the request, in which case the request has been
+        /// fully received.
+        is_last: bool,
+    },
+
     /// Received a response to a previously emitted request on a request-response protocol.
     Response {
         /// Bytes of the response. Its interpretation is out of scope of this module.
@@ -209,7 +233,10 @@ pub enum Event<TSubUd> {
     },
 
     /// An outgoing ping has succeeded. This event is generated automatically over time.
-    PingOutSuccess,
+    PingOutSuccess {
+        /// Time it took for the remote to answer the ping.
+        ping_time: Duration,
+    },
     /// An outgoing ping has failed. This event is generated automatically over time.
     PingOutFailed,
 }
@@ -295,11 +295,11 @@ where
             if read_write.now >= self.next_ping {
                 let mut payload = [0u8; 32];
                 self.ping_payload_randomness.fill_bytes(&mut payload);
-                substream
-                    .inner
-                    .as_mut()
-                    .unwrap()
-                    .queue_ping(&payload, read_write.now.clone() + self.ping_timeout);
+                substream.inner.as_mut().unwrap().queue_ping(
+                    &payload,
+                    read_write.now.clone(),
+                    read_write.now.clone() + self.ping_timeout,
+                );
                 self.next_ping = read_write.now.clone() + self.ping_interval;
             }
 
@@ -530,6 +530,15 @@ where
                 id: SubstreamId(SubstreamIdInner::MultiStream(substream_id)),
                 request,
             },
+            substream::Event::RequestInStart { request_size } => Event::RequestInStart {
+                id: SubstreamId(SubstreamIdInner::MultiStream(substream_id)),
+                request_size,
+            },
+            substream::Event::RequestInChunk { chunk, is_last } => Event::RequestInChunk {
+                id: SubstreamId(SubstreamIdInner::MultiStream(substream_id)),
+                chunk,
+                is_last,
+            },
             substream::Event::Response { response } => Event::Response {
                 id: SubstreamId(SubstreamIdInner::MultiStream(substream_id)),
                 response,
@@ -567,7 +576,7 @@ where
                 id: SubstreamId(SubstreamIdInner::MultiStream(substream_id)),
                 user_data: substream_user_data.take().unwrap(),
             },
-            substream::Event::PingOutSuccess => Event::PingOutSuccess,
+            substream::Event::PingOutSuccess { ping_time } => Event::PingOutSuccess { ping_time },
             substream::Event::PingOutError { .. } => {
                 // Because ping events are automatically generated by the external API without any
                 // guarantee, it is safe to merge multiple failed pings into one.
@@ -900,6 +909,35 @@ where
             .unwrap()
             .respond_in_request(response)
     }
+
+    /// Cancels an outgoing request started with [`MultiStream::add_request`]. No
+    /// [`Event::Response`] will be generated for this request.
+    ///
+    /// The substream is reset the next time it is processed through
+    /// [`MultiStream::substream_read_write`]. If the substream hasn't been opened yet, it is
+    /// reset as soon as it is opened. The value returned by
+    /// [`MultiStream::desired_outbound_substreams`] is intentionally not updated, as the API
+    /// user might already be in the process of opening the substream.
+    ///
+    /// Has no effect if the request has already finished.
+    pub fn cancel_request(&mut self, substream_id: SubstreamId) {
+        let substream_id = match substream_id.0 {
+            SubstreamIdInner::MultiStream(id) => id,
+            _ => return,
+        };
+
+        let substream = match self.out_in_substreams_map.get(&substream_id) {
+            Some(inner_substream_id) => self.in_substreams.get_mut(inner_substream_id),
+            None => self
+                .desired_out_substreams
+                .iter_mut()
+                .find(|s| s.id == substream_id),
+        };
+
+        if let Some(substream) = substream.and_then(|s| s.inner.as_mut()) {
+            substream.cancel_request();
+        }
+    }
 }
 
 impl<TNow, TSubId, TSubUd> Index<SubstreamId> for MultiStream<TNow, TSubId, TSubUd>
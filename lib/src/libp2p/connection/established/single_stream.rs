@@ -144,7 +144,11 @@ where
                     .as_mut()
                     .unwrap()
                     .0
-                    .queue_ping(&payload, read_write.now.clone() + self.inner.ping_timeout);
+                    .queue_ping(
+                        &payload,
+                        read_write.now.clone(),
+                        read_write.now.clone() + self.inner.ping_timeout,
+                    );
             } else {
                 return Ok((self, Some(Event::PingOutFailed)));
             }
@@ -359,6 +363,15 @@ where
                 id: SubstreamId(SubstreamIdInner::SingleStream(substream_id)),
                 request,
             },
+            substream::Event::RequestInStart { request_size } => Event::RequestInStart {
+                id: SubstreamId(SubstreamIdInner::SingleStream(substream_id)),
+                request_size,
+            },
+            substream::Event::RequestInChunk { chunk, is_last } => Event::RequestInChunk {
+                id: SubstreamId(SubstreamIdInner::SingleStream(substream_id)),
+                chunk,
+                is_last,
+            },
             substream::Event::Response { response } => Event::Response {
                 id: SubstreamId(SubstreamIdInner::SingleStream(substream_id)),
                 response,
@@ -396,7 +409,7 @@ where
                 id: SubstreamId(SubstreamIdInner::SingleStream(substream_id)),
                 user_data: substream_user_data.take().unwrap(),
             },
-            substream::Event::PingOutSuccess => Event::PingOutSuccess,
+            substream::Event::PingOutSuccess { ping_time } => Event::PingOutSuccess { ping_time },
             substream::Event::PingOutError { .. } => {
                 // Because ping events are automatically generated by the external API without any
                 // guarantee, it is safe to merge multiple failed pings into one.
@@ -722,6 +735,26 @@ where
         self.inner.yamux.mark_substream_write_ready(substream_id);
     }
 
+    /// Cancels an outgoing request started with [`SingleStream::add_request`]. The substream is
+    /// reset, and no [`Event::Response`] will be generated for this request.
+    ///
+    /// Has no effect if the request has already finished.
+    pub fn cancel_request(&mut self, substream_id: SubstreamId) {
+        let substream_id = match substream_id.0 {
+            SubstreamIdInner::SingleStream(id) => id,
+            _ => return,
+        };
+
+        if !self.inner.yamux.has_substream(substream_id) {
+            return;
+        }
+
+        if let Some((substream, _)) = self.inner.yamux.user_data_mut(substream_id).as_mut() {
+            substream.cancel_request();
+            self.inner.yamux.mark_substream_write_ready(substream_id);
+        }
+    }
+
     /// Responds to an incoming request. Must be called in response to a [`Event::RequestIn`].
     ///
     /// Passing an `Err` corresponds, on the other side, to a
@@ -836,7 +869,7 @@ impl ConnectionPrototype {
     /// Turns this prototype into an actual connection.
     pub fn into_connection<TNow, TSubUd>(self, config: Config<TNow>) -> SingleStream<TNow, TSubUd>
     where
-        TNow: Clone + Ord,
+        TNow: Clone + Add<Duration, Output = TNow> + Sub<TNow, Output = Duration> + Ord,
     {
         let mut randomness = rand_chacha::ChaCha20Rng::from_seed(config.randomness_seed);
 
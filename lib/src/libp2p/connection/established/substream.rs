@@ -28,7 +28,21 @@ use crate::util::leb128;
 
 use alloc::{borrow::ToOwned as _, collections::VecDeque, string::String, vec::Vec};
 use core::mem;
-use core::{fmt, num::NonZeroUsize};
+use core::{
+    cmp, fmt,
+    num::NonZeroUsize,
+    ops::{Add, Sub},
+    time::Duration,
+};
+
+/// Number of bytes that a substream accepted with [`InboundTy::RequestStreaming`] waits for
+/// before reporting a chunk of request, unless the request is smaller than that.
+const REQUEST_IN_STREAMING_CHUNK_SIZE: usize = 16 * 1024;
+
+/// Duration after the response to an inbound request has been fully queued during which the
+/// remote is expected to close its writing side. The substream is reset afterwards, in order
+/// to not count forever against the limit of inbound substreams.
+const REQUEST_IN_CLOSE_GRACE_PERIOD: Duration = Duration::from_secs(10);
 
 /// State machine containing the state of a single substream of an established connection.
 pub struct Substream<TNow> {
@@ -128,6 +142,9 @@ enum SubstreamInner<TNow> {
         /// Maximum allowed size of the response.
         response_max_size: usize,
     },
+    /// Outgoing request that has been cancelled by the API user. The substream is reset the next
+    /// time it is processed.
+    RequestOutCancelled,
 
     /// A request-response protocol has been negotiated on an inbound substream. A request is now
     /// expected.
@@ -141,12 +158,24 @@ enum SubstreamInner<TNow> {
     /// Similar to [`SubstreamInner::RequestInRecv`], but doesn't expect any request body.
     /// Immediately reports an event and switches to [`SubstreamInner::RequestInApiWait`].
     RequestInRecvEmpty,
+    /// Similar to [`SubstreamInner::RequestInRecv`], but the request is reported in chunks as
+    /// it is received rather than all at once.
+    RequestInRecvStreaming {
+        /// Number of bytes of the request that remain to be received, if known. If `Some`, we
+        /// have already extracted the length from the incoming buffer and reported it.
+        remaining: Option<usize>,
+        /// Maximum allowed size of the request.
+        request_max_size: usize,
+    },
     /// A request has been sent by the remote. API user must now send back the response.
     RequestInApiWait,
     /// A request has been sent by the remote. Sending back the response.
     RequestInRespond {
         /// Response being sent back.
         response: VecDeque<u8>,
+        /// When the substream is reset if the remote hasn't closed its writing side. `None` if
+        /// the response hasn't been fully queued yet.
+        close_deadline: Option<TNow>,
     },
 
     /// Inbound ping substream. Waiting for the ping payload to be received.
@@ -155,7 +184,7 @@ enum SubstreamInner<TNow> {
     /// Failed to negotiate a protocol for an outgoing ping substream.
     PingOutFailed {
         /// FIFO queue of pings that will immediately fail.
-        queued_pings: smallvec::SmallVec<[Option<TNow>; 1]>,
+        queued_pings: smallvec::SmallVec<[(TNow, Option<TNow>); 1]>,
     },
     /// Outbound ping substream.
     PingOut {
@@ -166,15 +195,16 @@ enum SubstreamInner<TNow> {
         /// Data waiting to be received from the remote. Any mismatch will cause an error.
         /// Contains even the data that is still queued in `outgoing_payload`.
         expected_payload: VecDeque<Vec<u8>>,
-        /// FIFO queue of pings waiting to be answered. For each ping, when the ping will time
-        /// out, or `None` if the timeout has already occurred.
-        queued_pings: smallvec::SmallVec<[Option<TNow>; 1]>,
+        /// FIFO queue of pings waiting to be answered. For each ping, when the ping has been
+        /// queued and when the ping will time out, or `None` if the timeout has already
+        /// occurred.
+        queued_pings: smallvec::SmallVec<[(TNow, Option<TNow>); 1]>,
     },
 }
 
 impl<TNow> Substream<TNow>
 where
-    TNow: Clone + Ord,
+    TNow: Clone + Add<Duration, Output = TNow> + Sub<TNow, Output = Duration> + Ord,
 {
     /// Initializes an new `ingoing` substream.
     ///
@@ -199,6 +229,11 @@ where
     /// which the API user must call [`Substream::respond_in_request`]. An [`Event::InboundError`]
     /// can happen at any point.
     ///
+    /// If [`InboundTy::RequestStreaming`] is passed, then a [`Event::RequestInStart`] will be
+    /// generated, followed with zero or more [`Event::RequestInChunk`], after which the API user
+    /// must call [`Substream::respond_in_request`]. An [`Event::InboundError`] can happen at any
+    /// point before the request has been fully received.
+    ///
     /// This flow is also true if you call [`Substream::reset`] at any point.
     pub fn ingoing(max_protocol_name_len: usize) -> Self {
         let negotiation =
@@ -417,6 +452,13 @@ where
                                 (Some(SubstreamInner::RequestInRecvEmpty), None)
                             }
                         }
+                        InboundTy::RequestStreaming { request_max_size } => (
+                            Some(SubstreamInner::RequestInRecvStreaming {
+                                remaining: None,
+                                request_max_size,
+                            }),
+                            None,
+                        ),
                     },
                     Ok(multistream_select::Negotiation::NotAvailable) => {
                         // Unreachable in listener mode.
@@ -647,6 +689,8 @@ where
                 )
             }
 
+            SubstreamInner::RequestOutCancelled => (None, None),
+
             SubstreamInner::RequestOut {
                 timeout,
                 mut negotiation,
@@ -820,14 +864,125 @@ where
                     request: Vec::new(),
                 }),
             ),
+            SubstreamInner::RequestInRecvStreaming {
+                remaining: None,
+                request_max_size,
+            } => match read_write.incoming_bytes_take_leb128(request_max_size) {
+                Ok(Some(request_size)) => (
+                    Some(if request_size == 0 {
+                        SubstreamInner::RequestInApiWait
+                    } else {
+                        SubstreamInner::RequestInRecvStreaming {
+                            remaining: Some(request_size),
+                            request_max_size,
+                        }
+                    }),
+                    Some(Event::RequestInStart { request_size }),
+                ),
+                Ok(None) => (
+                    Some(SubstreamInner::RequestInRecvStreaming {
+                        remaining: None,
+                        request_max_size,
+                    }),
+                    None,
+                ),
+                Err(error) => (
+                    None,
+                    Some(Event::InboundError {
+                        error: InboundError::RequestInLebError(error),
+                        was_accepted: true,
+                    }),
+                ),
+            },
+            SubstreamInner::RequestInRecvStreaming {
+                remaining: Some(remaining),
+                request_max_size,
+            } => {
+                // Wait for a reasonable amount of data to be available in order to not report
+                // a large number of tiny chunks, but take everything that is available.
+                let chunk_size = cmp::min(
+                    remaining,
+                    cmp::max(
+                        read_write.incoming_buffer_available(),
+                        REQUEST_IN_STREAMING_CHUNK_SIZE,
+                    ),
+                );
+
+                match read_write.incoming_bytes_take(chunk_size) {
+                    Ok(Some(chunk)) => {
+                        let remaining = remaining - chunk.len();
+                        if remaining == 0 {
+                            (
+                                Some(SubstreamInner::RequestInApiWait),
+                                Some(Event::RequestInChunk {
+                                    chunk,
+                                    is_last: true,
+                                }),
+                            )
+                        } else {
+                            (
+                                Some(SubstreamInner::RequestInRecvStreaming {
+                                    remaining: Some(remaining),
+                                    request_max_size,
+                                }),
+                                Some(Event::RequestInChunk {
+                                    chunk,
+                                    is_last: false,
+                                }),
+                            )
+                        }
+                    }
+                    Ok(None) => (
+                        Some(SubstreamInner::RequestInRecvStreaming {
+                            remaining: Some(remaining),
+                            request_max_size,
+                        }),
+                        None,
+                    ),
+                    Err(read_write::IncomingBytesTakeError::ReadClosed) => (
+                        None,
+                        Some(Event::InboundError {
+                            error: InboundError::SubstreamClosed,
+                            was_accepted: true,
+                        }),
+                    ),
+                }
+            }
             SubstreamInner::RequestInApiWait => (Some(SubstreamInner::RequestInApiWait), None),
-            SubstreamInner::RequestInRespond { mut response } => {
+            SubstreamInner::RequestInRespond {
+                mut response,
+                close_deadline,
+            } => {
                 if response.is_empty() {
+                    // Resetting the substream while the remote hasn't closed its writing side
+                    // would discard the part of the response that hasn't been sent out yet.
+                    // The remote is given a grace period to do so, after which the substream
+                    // is reset anyway.
+                    read_write.discard_all_incoming();
                     read_write.close_write();
-                    (None, None)
+                    let close_deadline = close_deadline
+                        .unwrap_or_else(|| read_write.now.clone() + REQUEST_IN_CLOSE_GRACE_PERIOD);
+                    if read_write.is_dead() || close_deadline <= read_write.now {
+                        (None, None)
+                    } else {
+                        read_write.wake_up_after(&close_deadline);
+                        (
+                            Some(SubstreamInner::RequestInRespond {
+                                response,
+                                close_deadline: Some(close_deadline),
+                            }),
+                            None,
+                        )
+                    }
                 } else {
                     read_write.write_from_vec_deque(&mut response);
-                    (Some(SubstreamInner::RequestInRespond { response }), None)
+                    (
+                        Some(SubstreamInner::RequestInRespond {
+                            response,
+                            close_deadline,
+                        }),
+                        None,
+                    )
                 }
             }
 
@@ -1036,7 +1191,7 @@ where
 
                 // We check the timeouts before checking the incoming data, as otherwise pings
                 // might succeed after their timeout.
-                for timeout in queued_pings.iter_mut() {
+                for (_, timeout) in queued_pings.iter_mut() {
                     if timeout.as_ref().map_or(false, |t| *t < read_write.now) {
                         *timeout = None;
                         read_write.wake_up_asap();
@@ -1066,7 +1221,9 @@ where
                         {
                             return (Some(SubstreamInner::PingOutFailed { queued_pings }), None);
                         }
-                        if queued_pings.remove(0).is_some() {
+                        let (queued_at, timeout) = queued_pings.remove(0);
+                        if timeout.is_some() {
+                            let ping_time = read_write.now.clone() - queued_at;
                             return (
                                 Some(SubstreamInner::PingOut {
                                     negotiation,
@@ -1074,7 +1231,7 @@ where
                                     outgoing_payload,
                                     queued_pings,
                                 }),
-                                Some(Event::PingOutSuccess),
+                                Some(Event::PingOutSuccess { ping_time }),
                             );
                         }
                     }
@@ -1102,6 +1259,7 @@ where
             SubstreamInner::RequestOut { .. } => Some(Event::Response {
                 response: Err(RequestError::SubstreamReset),
             }),
+            SubstreamInner::RequestOutCancelled => None,
             SubstreamInner::NotificationsInHandshake { .. } => None,
             SubstreamInner::NotificationsInWait { .. } => Some(Event::NotificationsInOpenCancel),
             SubstreamInner::NotificationsIn { .. } => Some(Event::NotificationsInClose {
@@ -1120,6 +1278,10 @@ where
             SubstreamInner::PingIn { .. } => None,
             SubstreamInner::RequestInRecv { .. } => None,
             SubstreamInner::RequestInRecvEmpty { .. } => None,
+            SubstreamInner::RequestInRecvStreaming { .. } => Some(Event::InboundError {
+                error: InboundError::SubstreamClosed,
+                was_accepted: true,
+            }),
             SubstreamInner::RequestInApiWait => None,
             SubstreamInner::RequestInRespond { .. } => None,
             SubstreamInner::PingOut { queued_pings, .. }
@@ -1239,17 +1401,17 @@ where
     }
 
     /// Queues a ping on the given substream. Must be passed a randomly-generated payload of 32
-    /// bytes, the time after which this ping is considered as failed.
+    /// bytes, the current time, and the time after which this ping is considered as failed.
     ///
     /// # Panic
     ///
     /// Panics if the substream isn't an outgoing ping substream.
     ///
-    pub fn queue_ping(&mut self, payload: &[u8; 32], timeout: TNow) {
+    pub fn queue_ping(&mut self, payload: &[u8; 32], now: TNow, timeout: TNow) {
         match &mut self.inner {
             SubstreamInner::PingOut { queued_pings, .. }
             | SubstreamInner::PingOutFailed { queued_pings, .. } => {
-                queued_pings.push(Some(timeout));
+                queued_pings.push((now, Some(timeout)));
             }
             _ => panic!(),
         }
@@ -1268,6 +1430,24 @@ where
         }
     }
 
+    /// Cancels an outgoing request. The substream is reset the next time it is processed, and
+    /// no [`Event::Response`] is generated.
+    ///
+    /// Has no effect if the request has already been cancelled.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the substream isn't an outgoing request substream.
+    ///
+    pub fn cancel_request(&mut self) {
+        match &self.inner {
+            SubstreamInner::RequestOut { .. } | SubstreamInner::RequestOutCancelled => {
+                self.inner = SubstreamInner::RequestOutCancelled;
+            }
+            _ => panic!(),
+        }
+    }
+
     /// Responds to an incoming request. Must be called in response to a [`Event::RequestIn`].
     ///
     /// Passing an `Err` corresponds, on the other side, to a [`RequestError::SubstreamClosed`].
@@ -1286,6 +1466,7 @@ where
                         // back the length of the response.
                         VecDeque::new()
                     },
+                    close_deadline: None,
                 };
 
                 Ok(())
@@ -1365,9 +1546,10 @@ impl<TNow> fmt::Debug for Substream<TNow> {
                 f.debug_tuple("notifications-in-closed").finish()
             }
             SubstreamInner::RequestOut { .. } => f.debug_tuple("request-out").finish(),
-            SubstreamInner::RequestInRecv { .. } | SubstreamInner::RequestInRecvEmpty { .. } => {
-                f.debug_tuple("request-in").finish()
-            }
+            SubstreamInner::RequestOutCancelled => f.debug_tuple("request-out-cancelled").finish(),
+            SubstreamInner::RequestInRecv { .. }
+            | SubstreamInner::RequestInRecvEmpty
+            | SubstreamInner::RequestInRecvStreaming { .. } => f.debug_tuple("request-in").finish(),
             SubstreamInner::RequestInRespond { .. } => f.debug_tuple("request-in-respond").finish(),
             SubstreamInner::RequestInApiWait => f.debug_tuple("request-in").finish(),
             SubstreamInner::PingIn { .. } => f.debug_tuple("ping-in").finish(),
@@ -1402,6 +1584,29 @@ pub enum Event {
         request: Vec<u8>,
     },
 
+    /// Received the length of a request on a substream accepted with
+    /// [`InboundTy::RequestStreaming`].
+    ///
+    /// Followed with [`Event::RequestInChunk`] events whose total length is equal to
+    /// `request_size`. If `request_size` is 0, the request has been fully received and
+    /// [`Substream::respond_in_request`] must now be called.
+    RequestInStart {
+        /// Size in bytes of the request.
+        request_size: usize,
+    },
+
+    /// Received a chunk of a request on a substream accepted with
+    /// [`InboundTy::RequestStreaming`].
+    ///
+    /// If `is_last` is `true`, the request has been fully received and
+    /// [`Substream::respond_in_request`] must now be called.
+    RequestInChunk {
+        /// Bytes of the request. Its interpretation is out of scope of this module.
+        chunk: Vec<u8>,
+        /// `true` if this is the last chunk of the request.
+        is_last: bool,
+    },
+
     /// Received a response to a previously emitted request on a request-response protocol.
     Response {
         /// Bytes of the response. Its interpretation is out of scope of this module.
@@ -1454,7 +1659,11 @@ pub enum Event {
     NotificationsOutReset,
 
     /// A ping has been successfully answered by the remote.
-    PingOutSuccess,
+    PingOutSuccess {
+        /// Time between the moment the ping has been queued and the moment it has been
+        /// answered.
+        ping_time: Duration,
+    },
     /// Remote has failed to answer one or more pings.
     PingOutError {
         /// Number of pings that the remote has failed to answer.
@@ -1472,6 +1681,13 @@ pub enum InboundTy {
         // TODO: use a proper enum
         request_max_size: Option<usize>,
     },
+    /// Similar to [`InboundTy::Request`], except that the request is reported through
+    /// [`Event::RequestInStart`] and [`Event::RequestInChunk`] as soon as its length is known,
+    /// rather than buffered entirely. Suitable for protocols whose requests can be large.
+    RequestStreaming {
+        /// Maximum allowed size of the request.
+        request_max_size: usize,
+    },
     Notifications {
         max_handshake_size: usize,
     },
@@ -18,6 +18,7 @@
 #![cfg(test)]
 
 use super::{
+    substream::{self, Substream},
     Config, Event, InboundError, InboundTy, NotificationsOutErr, RequestError, SingleStream,
 };
 use crate::libp2p::read_write::ReadWrite;
@@ -409,6 +410,83 @@ fn refused_request() {
     }
 }
 
+#[test]
+fn streaming_request() {
+    let config = Config {
+        first_out_ping: Duration::new(60, 0),
+        max_inbound_substreams: 64,
+        substreams_capacity: 16,
+        max_protocol_name_len: 128,
+        ping_interval: Duration::from_secs(20),
+        ping_protocol: "ping".to_owned(),
+        ping_timeout: Duration::from_secs(20),
+        randomness_seed: [0; 32],
+    };
+
+    let mut connections = perform_handshake(256, 256, config.clone(), config);
+
+    let request_payload = (0..200).map(|n| n as u8).collect::<Vec<_>>();
+
+    let substream_id = connections.alice.add_request(
+        "test-request-protocol".to_owned(),
+        Some(request_payload.clone()),
+        Duration::from_secs(5),
+        1024,
+        (),
+    );
+
+    let (connections_update, event) = connections.run_until_event();
+    connections = connections_update;
+    match event {
+        either::Right(Event::InboundNegotiated { id, protocol_name }) => {
+            assert_eq!(protocol_name, "test-request-protocol");
+            connections.bob.accept_inbound(
+                id,
+                InboundTy::RequestStreaming {
+                    request_max_size: 1024 * 1024,
+                },
+                (),
+            );
+        }
+        _ev => unreachable!("{:?}", _ev),
+    }
+
+    let (connections_update, event) = connections.run_until_event();
+    connections = connections_update;
+    match event {
+        either::Right(Event::RequestInStart { request_size, .. }) => {
+            assert_eq!(request_size, request_payload.len());
+        }
+        _ev => unreachable!("{:?}", _ev),
+    }
+
+    let mut received = Vec::new();
+    loop {
+        let (connections_update, event) = connections.run_until_event();
+        connections = connections_update;
+        match event {
+            either::Right(Event::RequestInChunk { id, chunk, is_last }) => {
+                received.extend_from_slice(&chunk);
+                if is_last {
+                    assert_eq!(received, request_payload);
+                    connections.bob.respond_in_request(id, Err(())).unwrap();
+                    break;
+                }
+            }
+            _ev => unreachable!("{:?}", _ev),
+        }
+    }
+
+    let (_, event) = connections.run_until_event();
+    match event {
+        either::Left(Event::Response { id, response, .. }) => {
+            assert_eq!(id, substream_id);
+            assert!(matches!(response, Err(RequestError::SubstreamClosed)));
+        }
+        _ev => unreachable!("{:?}", _ev),
+    }
+}
+
 #[test]
 fn request_protocol_not_supported() {
     let alice_config = Config {
@@ -518,6 +596,108 @@ fn request_timeout() {
     }
 }
 
+#[test]
+fn cancelled_request() {
+    let config = Config {
+        first_out_ping: Duration::new(60, 0),
+        max_inbound_substreams: 64,
+        substreams_capacity: 16,
+        max_protocol_name_len: 128,
+        ping_interval: Duration::from_secs(20),
+        ping_protocol: "ping".to_owned(),
+        ping_timeout: Duration::from_secs(20),
+        randomness_seed: [0; 32],
+    };
+
+    let mut connections = perform_handshake(256, 256, config.clone(), config);
+
+    let substream_id = connections.alice.add_request(
+        "test-request-protocol".to_owned(),
+        Some(b"request payload".to_vec()),
+        Duration::from_secs(5),
+        1024,
+        (),
+    );
+
+    let (connections_update, event) = connections.run_until_event();
+    connections = connections_update;
+    match event {
+        either::Right(Event::InboundNegotiated { id, protocol_name }) => {
+            assert_eq!(protocol_name, "test-request-protocol");
+            connections.bob.accept_inbound(
+                id,
+                InboundTy::Request {
+                    request_max_size: Some(1024 * 1024),
+                },
+                (),
+            );
+        }
+        _ev => unreachable!("{:?}", _ev),
+    }
+
+    let (connections_update, event) = connections.run_until_event();
+    connections = connections_update;
+    let bob_substream_id = match event {
+        either::Right(Event::RequestIn { id, request }) => {
+            assert_eq!(request, b"request payload");
+            id
+        }
+        _ev => unreachable!("{:?}", _ev),
+    };
+
+    connections.alice.cancel_request(substream_id);
+
+    // Start a second request. The `Response` event that Alice generates must be the one of
+    // this second request, and not the one of the cancelled request.
+    let substream_id2 = connections.alice.add_request(
+        "test-request-protocol".to_owned(),
+        Some(b"request payload 2".to_vec()),
+        Duration::from_secs(5),
+        1024,
+        (),
+    );
+
+    let (connections_update, event) = connections.run_until_event();
+    connections = connections_update;
+    match event {
+        either::Right(Event::InboundNegotiated { id, protocol_name }) => {
+            assert_eq!(protocol_name, "test-request-protocol");
+            connections.bob.accept_inbound(
+                id,
+                InboundTy::Request {
+                    request_max_size: Some(1024 * 1024),
+                },
+                (),
+            );
+        }
+        _ev => unreachable!("{:?}", _ev),
+    }
+
+    let (connections_update, event) = connections.run_until_event();
+    connections = connections_update;
+    match event {
+        either::Right(Event::RequestIn { id, request }) => {
+            assert_eq!(request, b"request payload 2");
+            // The substream of the cancelled request has been reset by Alice.
+            assert!(connections
+                .bob
+                .respond_in_request(bob_substream_id, Err(()))
+                .is_err());
+            connections.bob.respond_in_request(id, Err(())).unwrap();
+        }
+        _ev => unreachable!("{:?}", _ev),
+    }
+
+    let (_, event) = connections.run_until_event();
+    match event {
+        either::Left(Event::Response { id, response, .. }) => {
+            assert_eq!(id, substream_id2);
+            assert!(matches!(response, Err(RequestError::SubstreamClosed)));
+        }
+        _ev => unreachable!("{:?}", _ev),
+    }
+}
+
 #[test]
 fn outbound_substream_works() {
     let config = Config {
@@ -827,4 +1007,103 @@ fn outbound_substream_close_demanded() {
     }
 }
 
+#[test]
+fn request_in_reset_if_remote_never_closes() {
+    // Runs the given substream once. The data written by the substream is appended to
+    // `outgoing`. The remote never closes its writing side.
+    fn run(
+        substream: Substream<Duration>,
+        now: Duration,
+        incoming: &mut Vec<u8>,
+        outgoing: &mut Vec<u8>,
+    ) -> (
+        Option<Substream<Duration>>,
+        Option<substream::Event>,
+        Option<Duration>,
+    ) {
+        let mut read_write = ReadWrite {
+            now,
+            incoming_buffer: mem::take(incoming),
+            expected_incoming_bytes: Some(0),
+            read_bytes: 0,
+            write_bytes_queued: 0,
+            write_bytes_queueable: Some(4096),
+            write_buffers: Vec::new(),
+            wake_up_after: None,
+        };
+        let (substream, event) = substream.read_write(&mut read_write);
+        *incoming = read_write.incoming_buffer;
+        outgoing.extend(read_write.write_buffers.into_iter().flatten());
+        (substream, event, read_write.wake_up_after)
+    }
+
+    let now = Duration::new(0, 0);
+    let mut remote = Some(Substream::request_out(
+        "test-request-protocol".to_owned(),
+        Duration::from_secs(60),
+        Some(b"request payload".to_vec()),
+        1024,
+    ));
+    let mut local = Substream::ingoing(128);
+    let mut remote_to_local = Vec::new();
+    let mut local_to_remote = Vec::new();
+    let mut response = None;
+
+    for _ in 0..16 {
+        if let Some(substream) = remote.take() {
+            let (substream, event, _) =
+                run(substream, now, &mut local_to_remote, &mut remote_to_local);
+            match event {
+                Some(substream::Event::Response { response: r }) => response = Some(r),
+                None => remote = substream,
+                _ev => unreachable!("{:?}", _ev),
+            }
+        }
+
+        let (substream, event, _) = run(local, now, &mut remote_to_local, &mut local_to_remote);
+        local = substream.unwrap();
+        match event {
+            Some(substream::Event::InboundNegotiated(protocol_name)) => {
+                assert_eq!(protocol_name, "test-request-protocol");
+                local.accept_inbound(InboundTy::Request {
+                    request_max_size: Some(1024),
+                });
+            }
+            Some(substream::Event::RequestIn { request }) => {
+                assert_eq!(request, b"request payload");
+                local.respond_in_request(Ok(b"response".to_vec())).unwrap();
+            }
+            None => {}
+            _ev => unreachable!("{:?}", _ev),
+        }
+    }
+
+    assert!(matches!(response, Some(Ok(r)) if r == b"response"));
+
+    // The response has been sent, but the remote hasn't closed its writing side. The substream
+    // is kept alive until the end of the grace period, then reset.
+    let (substream, event, wake_up_after) =
+        run(local, now, &mut remote_to_local, &mut local_to_remote);
+    assert!(event.is_none());
+    let wake_up_after = wake_up_after.unwrap();
+    assert!(wake_up_after > now);
+
+    let (substream, event, _) = run(
+        substream.unwrap(),
+        wake_up_after - Duration::from_millis(1),
+        &mut remote_to_local,
+        &mut local_to_remote,
+    );
+    assert!(event.is_none());
+
+    let (substream, event, _) = run(
+        substream.unwrap(),
+        wake_up_after,
+        &mut remote_to_local,
+        &mut local_to_remote,
+    );
+    assert!(substream.is_none());
+    assert!(event.is_none());
+}
+
 // TODO: more tests
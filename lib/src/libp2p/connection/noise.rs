@@ -585,6 +585,15 @@ impl HandshakeInProgress {
         }))
     }
 
+    /// Returns the number, between 1 and 3, of the handshake message that is currently being
+    /// sent out or waited for.
+    ///
+    /// The Noise XX handshake consists in three messages. The first and third messages are sent
+    /// by the initiator, while the second message is sent by the responder.
+    pub fn current_message_number(&self) -> u8 {
+        cmp::min(self.0.num_buffered_or_transmitted_messages + 1, 3)
+    }
+
     /// Feeds data coming from a socket and outputs data to write to the socket.
     ///
     /// On success, returns the new state of the negotiation.
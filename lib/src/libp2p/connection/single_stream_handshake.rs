@@ -128,6 +128,20 @@ impl HealthyHandshake {
         }
     }
 
+    /// Returns the stage the handshake is currently at.
+    ///
+    /// This is mostly useful for diagnostic purposes, for example in order to report at which
+    /// point a handshake has timed out.
+    pub fn stage(&self) -> HandshakeStage {
+        match &self.state {
+            NegotiationState::EncryptionProtocol { .. } => HandshakeStage::EncryptionProtocol,
+            NegotiationState::Encryption { handshake } => HandshakeStage::Noise {
+                message: handshake.current_message_number(),
+            },
+            NegotiationState::Multiplexing { .. } => HandshakeStage::MultiplexingProtocol,
+        }
+    }
+
     /// Feeds data coming from a socket and writes back data to send up.
     ///
     /// On success, returns the new state of the negotiation.
@@ -295,6 +309,24 @@ impl fmt::Debug for HealthyHandshake {
     }
 }
 
+/// Stage of a connection handshake. See [`HealthyHandshake::stage`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, derive_more::Display)]
+pub enum HandshakeStage {
+    /// Negotiating the encryption protocol using multistream-select.
+    #[display(fmt = "multistream-select negotiation of the encryption protocol")]
+    EncryptionProtocol,
+    /// Performing the Noise handshake.
+    #[display(fmt = "Noise handshake message {message}")]
+    Noise {
+        /// Number, between 1 and 3, of the Noise handshake message being sent or waited for.
+        /// See [`noise::HandshakeInProgress::current_message_number`].
+        message: u8,
+    },
+    /// Negotiating the multiplexing protocol (i.e. Yamux) using multistream-select.
+    #[display(fmt = "multistream-select negotiation of the multiplexing protocol")]
+    MultiplexingProtocol,
+}
+
 /// Error during a connection handshake. The connection should be shut down.
 #[derive(Debug, derive_more::Display)]
 pub enum HandshakeError {
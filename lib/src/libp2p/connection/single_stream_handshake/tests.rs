@@ -19,7 +19,7 @@
 
 use core::{cmp, mem};
 
-use super::{super::super::read_write::ReadWrite, Handshake, NoiseKey};
+use super::{super::super::read_write::ReadWrite, Handshake, HandshakeStage, NoiseKey};
 
 #[test]
 fn handshake_basic_works() {
@@ -95,3 +95,73 @@ fn handshake_basic_works() {
     //test_with_buffer_sizes(1, 2048);
     //test_with_buffer_sizes(2048, 1);
 }
+
+#[test]
+fn stage_progresses() {
+    let key1 = NoiseKey::new(&rand::random(), &rand::random());
+    let key2 = NoiseKey::new(&rand::random(), &rand::random());
+
+    let mut handshakes = [
+        Some(Handshake::noise_yamux(&key1, &rand::random(), true)),
+        Some(Handshake::noise_yamux(&key2, &rand::random(), false)),
+    ];
+    let mut buffers = [Vec::new(), Vec::new()];
+    let mut stages = [Vec::new(), Vec::new()];
+
+    while !handshakes
+        .iter()
+        .all(|h| matches!(h, Some(Handshake::Success { .. })))
+    {
+        for local in 0..2 {
+            let remote = 1 - local;
+            let nego = match handshakes[local].take().unwrap() {
+                Handshake::Healthy(nego) => nego,
+                success @ Handshake::Success { .. } => {
+                    handshakes[local] = Some(success);
+                    continue;
+                }
+            };
+
+            if stages[local].last() != Some(&nego.stage()) {
+                stages[local].push(nego.stage());
+            }
+
+            let mut read_write = ReadWrite {
+                now: 0,
+                incoming_buffer: mem::take(&mut buffers[remote]),
+                expected_incoming_bytes: Some(0),
+                read_bytes: 0,
+                write_bytes_queued: buffers[local].len(),
+                write_bytes_queueable: Some(256 - buffers[local].len()),
+                write_buffers: vec![mem::take(&mut buffers[local])],
+                wake_up_after: None,
+            };
+            handshakes[local] = Some(nego.read_write(&mut read_write).unwrap());
+            buffers[remote] = read_write.incoming_buffer;
+            buffers[local].extend(
+                read_write
+                    .write_buffers
+                    .drain(..)
+                    .flat_map(|b| b.into_iter()),
+            );
+        }
+    }
+
+    // Stages must be reported in order, without ever going backwards.
+    for stages in stages {
+        assert_eq!(stages[0], HandshakeStage::EncryptionProtocol);
+        let ranks = stages
+            .iter()
+            .map(|stage| match stage {
+                HandshakeStage::EncryptionProtocol => 0,
+                HandshakeStage::Noise { message } => {
+                    assert!((1..=3).contains(message));
+                    *message
+                }
+                HandshakeStage::MultiplexingProtocol => 4,
+            })
+            .collect::<Vec<_>>();
+        assert!(ranks.windows(2).all(|w| w[0] < w[1]));
+        assert!(ranks.iter().any(|r| (1..=3).contains(r)));
+    }
+}
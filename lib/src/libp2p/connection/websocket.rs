@@ -0,0 +1,1001 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! WebSocket client framing layer.
+//!
+//! Some platforms only provide raw TCP sockets, but nodes are frequently only reachable through
+//! a `/ws` multiaddress. This module contains a state machine that performs the HTTP/1.1 upgrade
+//! request of the WebSocket protocol, then wraps the data exchanged with the remote into
+//! WebSocket binary frames. See [RFC 6455](https://datatracker.ietf.org/doc/html/rfc6455).
+//!
+//! Contrary to the `libp2p::websocket` module, which requires the `std` feature, this module
+//! is *no_std*-friendly and doesn't perform any I/O by itself.
+//!
+//! # Usage
+//!
+//! Once the TCP connection is established, create a [`WebSocket`] with [`WebSocket::new`].
+//! Then, every time the socket has received data or is ready to accept more data, call
+//! [`WebSocket::read_write`] with the [`ReadWrite`] representing the TCP socket. The returned
+//! [`InnerReadWrite`] represents the stream of data carried by the WebSocket frames, and can be
+//! passed for example to
+//! [`SingleStreamConnectionTask::read_write`](crate::libp2p::collection::SingleStreamConnectionTask::read_write).
+//! The data written to the [`InnerReadWrite`] is sent out when it is destroyed.
+//!
+//! As long as the HTTP handshake isn't finished, the [`InnerReadWrite`] doesn't contain any
+//! incoming data and doesn't accept any outgoing data.
+//!
+//! Ping frames sent by the server are automatically answered. When the writing side of the
+//! [`InnerReadWrite`] is closed, a close frame is sent to the server.
+//!
+//! # Messages
+//!
+//! [`WebSocket::read_write`] erases the boundaries between the messages sent by the server.
+//! Protocols that rely on these boundaries, such as JSON-RPC, should instead call
+//! [`WebSocket::read_write_messages`], then [`WebSocket::pull_message`] to obtain the messages
+//! that have been received, and [`WebSocket::queue_text_message`] to send messages. A
+//! [`WebSocket`] must use either [`WebSocket::read_write`] or [`WebSocket::read_write_messages`],
+//! but not both.
+
+use crate::libp2p::read_write::ReadWrite;
+
+use alloc::{collections::VecDeque, format, string::String, vec::Vec};
+use base64::Engine as _;
+use core::{cmp, fmt, mem, ops, str};
+use rand_chacha::{
+    rand_core::{RngCore as _, SeedableRng as _},
+    ChaCha20Rng,
+};
+use sha1::{Digest as _, Sha1};
+
+/// Configuration for a [`WebSocket`].
+#[derive(Debug)]
+pub struct Config<'a> {
+    /// Value to pass for the `Host` HTTP header. Example values include `example.com:1234` or
+    /// `127.0.0.1:3337`.
+    pub host: &'a str,
+
+    /// URL to pass to the server during the HTTP handshake. Typically `/`.
+    pub url: &'a str,
+
+    /// Seed used to generate the key of the HTTP handshake and the masking keys of the frames
+    /// sent to the server.
+    ///
+    /// The WebSocket protocol requires these values to be unpredictable. This seed should
+    /// therefore come from a cryptographically-secure source of entropy.
+    pub randomness_seed: [u8; 32],
+}
+
+/// Maximum size, in bytes, of the HTTP response to the upgrade request.
+const MAX_HANDSHAKE_RESPONSE_SIZE: usize = 8192;
+
+/// Maximum size, in bytes, of the payload of a data frame sent to the server.
+const MAX_OUT_FRAME_PAYLOAD: usize = 65535;
+
+/// Maximum size, in bytes, of the header of a frame sent to the server: two bytes, up to eight
+/// bytes of extended payload length, and four bytes of masking key.
+const MAX_OUT_FRAME_HEADER: usize = 14;
+
+/// Value appended to the key of the HTTP handshake in order to compute the expected value of the
+/// `Sec-WebSocket-Accept` header.
+const HANDSHAKE_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+const OPCODE_CONTINUATION: u8 = 0x0;
+const OPCODE_TEXT: u8 = 0x1;
+const OPCODE_BINARY: u8 = 0x2;
+const OPCODE_CLOSE: u8 = 0x8;
+const OPCODE_PING: u8 = 0x9;
+const OPCODE_PONG: u8 = 0xa;
+
+/// State of the WebSocket framing layer of a connection.
+pub struct WebSocket {
+    /// If `Some`, the HTTP handshake is still in progress, and contains the value that the
+    /// server must send back in the `Sec-WebSocket-Accept` header.
+    handshake_expected_accept: Option<String>,
+
+    /// Data that must be written out on the socket before any data frame. Contains the HTTP
+    /// request during the handshake, and control frames afterwards.
+    pending_out_data: Vec<u8>,
+
+    /// Source of randomness for the masking keys of the frames sent to the server.
+    randomness: ChaCha20Rng,
+
+    /// Frame currently being received, if its header has already been decoded.
+    incoming_frame: Option<IncomingFrame>,
+
+    /// Buffer of data extracted from data frames and not processed by the inner stream yet.
+    rx_buffer_decoded: Vec<u8>,
+
+    /// Value of [`ReadWrite::expected_incoming_bytes`] of the inner stream the last time that
+    /// [`WebSocket::read_write`] was called. Frames will be read until the length of
+    /// [`WebSocket::rx_buffer_decoded`] reaches the value in this field.
+    inner_stream_expected_incoming_bytes: usize,
+
+    /// `true` if the server has sent a close frame.
+    remote_closed: bool,
+
+    /// `true` if a close frame has been queued for sending. No frame can be sent afterwards.
+    local_closed: bool,
+
+    /// Messages fully received and not pulled with [`WebSocket::pull_message`] yet. Only used
+    /// by [`WebSocket::read_write_messages`].
+    rx_messages: VecDeque<Vec<u8>>,
+
+    /// Messages queued with [`WebSocket::queue_text_message`] and not encoded into frames yet.
+    tx_messages: VecDeque<String>,
+}
+
+/// See [`WebSocket::incoming_frame`].
+struct IncomingFrame {
+    /// Opcode found in the header of the frame.
+    opcode: u8,
+    /// `true` if this frame is the last frame of its message.
+    fin: bool,
+    /// Number of bytes of payload that haven't been received yet.
+    remaining_payload: u64,
+    /// Payload received so far, if the frame is a control frame. Control frames are processed
+    /// only once they have been fully received, while the payload of data frames is
+    /// immediately transferred to [`WebSocket::rx_buffer_decoded`].
+    control_payload: Vec<u8>,
+}
+
+impl WebSocket {
+    /// Initializes a new state machine. The HTTP request is sent to the server during the first
+    /// call to [`WebSocket::read_write`].
+    pub fn new(config: Config) -> Self {
+        let mut randomness = ChaCha20Rng::from_seed(config.randomness_seed);
+
+        let key = {
+            let mut key = [0; 16];
+            randomness.fill_bytes(&mut key);
+            base64::engine::general_purpose::STANDARD.encode(key)
+        };
+
+        let request = format!(
+            "GET {} HTTP/1.1\r\n\
+            Host: {}\r\n\
+            Upgrade: websocket\r\n\
+            Connection: Upgrade\r\n\
+            Sec-WebSocket-Key: {}\r\n\
+            Sec-WebSocket-Version: 13\r\n\r\n",
+            config.url, config.host, key
+        );
+
+        WebSocket {
+            handshake_expected_accept: Some(accept_key(&key)),
+            pending_out_data: request.into_bytes(),
+            randomness,
+            incoming_frame: None,
+            rx_buffer_decoded: Vec::new(),
+            inner_stream_expected_incoming_bytes: 0,
+            remote_closed: false,
+            local_closed: false,
+            rx_messages: VecDeque::new(),
+            tx_messages: VecDeque::new(),
+        }
+    }
+
+    /// Returns `true` if the HTTP handshake has successfully finished.
+    pub fn is_handshake_finished(&self) -> bool {
+        self.handshake_expected_accept.is_none()
+    }
+
+    /// Feeds data coming from a socket and outputs data to write to the socket.
+    ///
+    /// Returns an object that implements `Deref<Target = ReadWrite>`. This object represents the
+    /// stream of data carried by the WebSocket frames.
+    ///
+    /// An error is returned if the server refuses the upgrade or if the protocol is being
+    /// violated by the server. When that happens, the connection should be closed altogether.
+    pub fn read_write<'a, TNow: Clone>(
+        &'a mut self,
+        outer_read_write: &'a mut ReadWrite<TNow>,
+    ) -> Result<InnerReadWrite<'a, TNow>, Error> {
+        outer_read_write.write_from_vec(&mut self.pending_out_data);
+        self.read_handshake_response(outer_read_write)?;
+
+        if self.handshake_expected_accept.is_none() {
+            // Try to pull frames from `outer_read_write`.
+            while !self.remote_closed
+                && (self.rx_buffer_decoded.is_empty()
+                    || self.inner_stream_expected_incoming_bytes > self.rx_buffer_decoded.len())
+            {
+                if !self.read_incoming_frame(outer_read_write, None)? {
+                    break;
+                }
+            }
+
+            // Answers to ping frames or close frames might have been queued.
+            outer_read_write.write_from_vec(&mut self.pending_out_data);
+            if self.local_closed && self.pending_out_data.is_empty() {
+                outer_read_write.close_write();
+            }
+        }
+
+        let write_bytes_queueable = match outer_read_write.write_bytes_queueable {
+            _ if self.local_closed => None,
+            None => None,
+            Some(_)
+                if self.handshake_expected_accept.is_some()
+                    || !self.pending_out_data.is_empty() =>
+            {
+                Some(0)
+            }
+            Some(outer_writable) => Some(cmp::min(
+                outer_writable.saturating_sub(MAX_OUT_FRAME_HEADER),
+                MAX_OUT_FRAME_PAYLOAD,
+            )),
+        };
+
+        Ok(InnerReadWrite {
+            inner_read_write: ReadWrite {
+                now: outer_read_write.now.clone(),
+                incoming_buffer: mem::take(&mut self.rx_buffer_decoded),
+                read_bytes: 0,
+                expected_incoming_bytes: if !self.remote_closed
+                    && (outer_read_write.expected_incoming_bytes.is_some()
+                        || !outer_read_write.incoming_buffer.is_empty())
+                {
+                    Some(self.inner_stream_expected_incoming_bytes)
+                } else {
+                    None
+                },
+                write_buffers: Vec::new(),
+                write_bytes_queued: 0,
+                write_bytes_queueable,
+                wake_up_after: outer_read_write.wake_up_after.clone(),
+            },
+            inner_write_was_open: write_bytes_queueable.is_some(),
+            websocket: self,
+            outer_read_write,
+        })
+    }
+
+    /// Alternative to [`WebSocket::read_write`] that preserves the boundaries between messages.
+    /// See [the module-level documentation](..).
+    ///
+    /// The messages received from the server can then be obtained by calling
+    /// [`WebSocket::pull_message`]. Messages queued with [`WebSocket::queue_text_message`] are
+    /// written out once the HTTP handshake has finished.
+    ///
+    /// An error is returned if the server refuses the upgrade, if the protocol is being violated
+    /// by the server, or if the server sends a message larger than `max_message_size` bytes.
+    /// When that happens, the connection should be closed altogether.
+    pub fn read_write_messages<TNow: Clone>(
+        &mut self,
+        outer_read_write: &mut ReadWrite<TNow>,
+        max_message_size: usize,
+    ) -> Result<(), Error> {
+        outer_read_write.write_from_vec(&mut self.pending_out_data);
+        self.read_handshake_response(outer_read_write)?;
+
+        if self.handshake_expected_accept.is_some() {
+            return Ok(());
+        }
+
+        while !self.remote_closed
+            && self.read_incoming_frame(outer_read_write, Some(max_message_size))?
+        {}
+
+        if !self.local_closed {
+            for message in self.tx_messages.drain(..) {
+                encode_frame(
+                    &mut self.pending_out_data,
+                    OPCODE_TEXT,
+                    &[message.as_bytes()],
+                    &mut self.randomness,
+                );
+            }
+        }
+
+        outer_read_write.write_from_vec(&mut self.pending_out_data);
+        if self.local_closed && self.pending_out_data.is_empty() {
+            outer_read_write.close_write();
+        }
+
+        Ok(())
+    }
+
+    /// Returns the next message received from the server by [`WebSocket::read_write_messages`],
+    /// if any. Text and binary messages are returned the same way.
+    pub fn pull_message(&mut self) -> Option<Vec<u8>> {
+        self.rx_messages.pop_front()
+    }
+
+    /// Queues a text message for sending. The message is sent in a single frame during the next
+    /// call to [`WebSocket::read_write_messages`].
+    ///
+    /// Messages queued after a close frame has been sent are never sent.
+    pub fn queue_text_message(&mut self, message: String) {
+        self.tx_messages.push_back(message);
+    }
+
+    /// If the HTTP handshake is still in progress, tries to read the response of the server.
+    fn read_handshake_response<TNow>(
+        &mut self,
+        outer_read_write: &mut ReadWrite<TNow>,
+    ) -> Result<(), Error> {
+        if let Some(expected_accept) = &self.handshake_expected_accept {
+            if let Some(response_len) = outer_read_write
+                .incoming_buffer
+                .windows(4)
+                .position(|w| w == b"\r\n\r\n")
+                .map(|pos| pos + 4)
+            {
+                let response = outer_read_write
+                    .incoming_bytes_take(response_len)
+                    .unwrap_or_else(|_| unreachable!())
+                    .unwrap_or_else(|| unreachable!());
+                check_handshake_response(&response, expected_accept)?;
+                self.handshake_expected_accept = None;
+            } else if outer_read_write.incoming_buffer.len() >= MAX_HANDSHAKE_RESPONSE_SIZE {
+                return Err(Error::HandshakeResponseTooLarge);
+            } else {
+                let buffer_len = outer_read_write.incoming_buffer.len();
+                match outer_read_write.expected_incoming_bytes.as_mut() {
+                    Some(expected_incoming_bytes) => *expected_incoming_bytes = buffer_len + 1,
+                    None => return Err(Error::HandshakeInterrupted),
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Tries to make progress in receiving the frame currently being received, or decodes the
+    /// header of the next frame.
+    ///
+    /// If `max_message_size` is `Some`, the payload of data frames is accumulated in
+    /// [`WebSocket::rx_buffer_decoded`] until the end of the message, which is then moved to
+    /// [`WebSocket::rx_messages`].
+    ///
+    /// Returns `false` if more data from the socket is needed in order to make progress.
+    fn read_incoming_frame<TNow>(
+        &mut self,
+        outer_read_write: &mut ReadWrite<TNow>,
+        max_message_size: Option<usize>,
+    ) -> Result<bool, Error> {
+        let Some(frame) = &mut self.incoming_frame else {
+            let Some((frame, header_len)) = decode_frame_header(&outer_read_write.incoming_buffer)?
+            else {
+                let buffer_len = outer_read_write.incoming_buffer.len();
+                if let Some(expected_incoming_bytes) =
+                    outer_read_write.expected_incoming_bytes.as_mut()
+                {
+                    *expected_incoming_bytes = buffer_len + 1;
+                }
+                return Ok(false);
+            };
+
+            if let Some(max_message_size) = max_message_size {
+                let is_control = frame.opcode & 0x8 != 0;
+                let message_size = u64::try_from(self.rx_buffer_decoded.len())
+                    .unwrap()
+                    .saturating_add(frame.remaining_payload);
+                if !is_control && message_size > u64::try_from(max_message_size).unwrap_or(u64::MAX)
+                {
+                    return Err(Error::MessageTooLarge);
+                }
+            }
+
+            let _ = outer_read_write.incoming_bytes_take(header_len);
+            self.incoming_frame = Some(frame);
+            return Ok(true);
+        };
+
+        if frame.remaining_payload != 0 {
+            let is_control = frame.opcode & 0x8 != 0;
+            let remaining_payload = usize::try_from(frame.remaining_payload).unwrap_or(usize::MAX);
+
+            // The payload of control frames is read all at once, while data frames are read
+            // progressively.
+            let to_read = if is_control {
+                remaining_payload
+            } else {
+                cmp::max(
+                    1,
+                    cmp::min(remaining_payload, outer_read_write.incoming_buffer.len()),
+                )
+            };
+
+            let Ok(Some(payload)) = outer_read_write.incoming_bytes_take(to_read) else {
+                return Ok(false);
+            };
+
+            frame.remaining_payload -= u64::try_from(payload.len()).unwrap();
+            if is_control {
+                frame.control_payload.extend_from_slice(&payload);
+            } else if self.rx_buffer_decoded.is_empty() {
+                self.rx_buffer_decoded = payload;
+            } else {
+                self.rx_buffer_decoded.extend_from_slice(&payload);
+            }
+
+            if frame.remaining_payload != 0 {
+                return Ok(true);
+            }
+        }
+
+        let frame = self.incoming_frame.take().unwrap();
+        match frame.opcode {
+            OPCODE_PING if !self.local_closed => {
+                encode_frame(
+                    &mut self.pending_out_data,
+                    OPCODE_PONG,
+                    &[&frame.control_payload[..]],
+                    &mut self.randomness,
+                );
+            }
+            OPCODE_CLOSE => {
+                self.remote_closed = true;
+
+                // The close frame is echoed back, as required by the protocol.
+                if !self.local_closed {
+                    let status_code = frame.control_payload.get(..2).unwrap_or(&[]);
+                    encode_frame(
+                        &mut self.pending_out_data,
+                        OPCODE_CLOSE,
+                        &[status_code],
+                        &mut self.randomness,
+                    );
+                    self.local_closed = true;
+                }
+            }
+            OPCODE_CONTINUATION | OPCODE_TEXT | OPCODE_BINARY
+                if frame.fin && max_message_size.is_some() =>
+            {
+                self.rx_messages
+                    .push_back(mem::take(&mut self.rx_buffer_decoded));
+            }
+            _ => {}
+        }
+
+        Ok(true)
+    }
+}
+
+impl fmt::Debug for WebSocket {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("WebSocket").finish()
+    }
+}
+
+/// Stream of data carried by the WebSocket frames. See [`WebSocket::read_write`].
+pub struct InnerReadWrite<'a, TNow: Clone> {
+    websocket: &'a mut WebSocket,
+    outer_read_write: &'a mut ReadWrite<TNow>,
+    inner_read_write: ReadWrite<TNow>,
+    /// `true` if [`InnerReadWrite::inner_read_write`] was initially writable.
+    inner_write_was_open: bool,
+}
+
+impl<'a, TNow: Clone> ops::Deref for InnerReadWrite<'a, TNow> {
+    type Target = ReadWrite<TNow>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.inner_read_write
+    }
+}
+
+impl<'a, TNow: Clone> ops::DerefMut for InnerReadWrite<'a, TNow> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.inner_read_write
+    }
+}
+
+impl<'a, TNow: Clone> Drop for InnerReadWrite<'a, TNow> {
+    fn drop(&mut self) {
+        self.outer_read_write.wake_up_after = self.inner_read_write.wake_up_after.clone();
+        self.websocket.rx_buffer_decoded = mem::take(&mut self.inner_read_write.incoming_buffer);
+        self.websocket.inner_stream_expected_incoming_bytes =
+            self.inner_read_write.expected_incoming_bytes.unwrap_or(0);
+
+        // If the inner stream has processed some bytes of `rx_buffer_decoded`, it might expect
+        // to be called again while no byte was pulled from the outer `ReadWrite`. In order to
+        // avoid a stall, we make sure that the outer `ReadWrite` wakes up as soon as possible.
+        if self.inner_read_write.read_bytes != 0 {
+            self.outer_read_write.wake_up_asap();
+        }
+
+        // Wrap the data written by the inner stream in a binary frame.
+        if self
+            .inner_read_write
+            .write_buffers
+            .iter()
+            .any(|b| !b.is_empty())
+        {
+            let mut frame =
+                Vec::with_capacity(MAX_OUT_FRAME_HEADER + self.inner_read_write.write_bytes_queued);
+            encode_frame(
+                &mut frame,
+                OPCODE_BINARY,
+                &self.inner_read_write.write_buffers,
+                &mut self.websocket.randomness,
+            );
+            self.outer_read_write.write_out(frame);
+        }
+
+        // Closing the writing side of the inner stream translates into a close frame.
+        if self.inner_write_was_open && self.inner_read_write.write_bytes_queueable.is_none() {
+            if self.websocket.handshake_expected_accept.is_none() {
+                encode_frame(
+                    &mut self.websocket.pending_out_data,
+                    OPCODE_CLOSE,
+                    &[&1000u16.to_be_bytes()[..]],
+                    &mut self.websocket.randomness,
+                );
+                self.websocket.local_closed = true;
+                self.outer_read_write
+                    .write_from_vec(&mut self.websocket.pending_out_data);
+                if self.websocket.pending_out_data.is_empty() {
+                    self.outer_read_write.close_write();
+                }
+            } else {
+                // There is no point in finishing the handshake.
+                self.websocket.local_closed = true;
+                self.outer_read_write.close_write();
+            }
+        }
+    }
+}
+
+/// Error potentially returned by [`WebSocket::read_write`] or [`WebSocket::read_write_messages`].
+#[derive(Debug, Clone, derive_more::Display)]
+pub enum Error {
+    /// Reading side of the socket has been closed before the end of the HTTP handshake.
+    HandshakeInterrupted,
+    /// HTTP response to the upgrade request is too large.
+    HandshakeResponseTooLarge,
+    /// HTTP response to the upgrade request couldn't be parsed.
+    InvalidHandshakeResponse,
+    /// Server has refused the upgrade.
+    #[display(fmt = "Server has refused the upgrade with status code {_0}")]
+    UpgradeRefused(u16),
+    /// Server has accepted the upgrade but is missing the `Upgrade` or `Connection` header.
+    MissingUpgradeHeaders,
+    /// Value of the `Sec-WebSocket-Accept` header sent by the server doesn't match the key of
+    /// the request.
+    InvalidAcceptKey,
+    /// Server has sent a masked frame, which is forbidden.
+    MaskedFrame,
+    /// Server has sent a frame with reserved bits set, while no extension has been negotiated.
+    ReservedBitsSet,
+    /// Server has sent a frame with an unknown opcode.
+    #[display(fmt = "Unknown frame opcode: {_0}")]
+    UnknownOpcode(u8),
+    /// Server has sent a fragmented control frame or a control frame with a payload too large.
+    InvalidControlFrame,
+    /// Server has sent a message larger than the limit passed to
+    /// [`WebSocket::read_write_messages`].
+    MessageTooLarge,
+}
+
+/// Returns the value that the server must send back in the `Sec-WebSocket-Accept` header given
+/// the value of the `Sec-WebSocket-Key` header of the request.
+fn accept_key(key: &str) -> String {
+    let mut hasher = Sha1::new();
+    hasher.update(key.as_bytes());
+    hasher.update(HANDSHAKE_GUID.as_bytes());
+    base64::engine::general_purpose::STANDARD.encode(hasher.finalize())
+}
+
+/// Verifies the HTTP response to the upgrade request. `response` must contain the status line
+/// and all the headers.
+fn check_handshake_response(response: &[u8], expected_accept: &str) -> Result<(), Error> {
+    let response = str::from_utf8(response).map_err(|_| Error::InvalidHandshakeResponse)?;
+    let mut lines = response.split("\r\n");
+
+    let status_code = {
+        let status_line = lines.next().ok_or(Error::InvalidHandshakeResponse)?;
+        let mut parts = status_line.splitn(3, ' ');
+        if !parts.next().is_some_and(|v| v.starts_with("HTTP/1.")) {
+            return Err(Error::InvalidHandshakeResponse);
+        }
+        parts
+            .next()
+            .and_then(|code| code.parse::<u16>().ok())
+            .ok_or(Error::InvalidHandshakeResponse)?
+    };
+
+    if status_code != 101 {
+        return Err(Error::UpgradeRefused(status_code));
+    }
+
+    let mut upgrade_ok = false;
+    let mut connection_ok = false;
+    let mut accept_ok = false;
+
+    for line in lines.filter(|l| !l.is_empty()) {
+        let (name, value) = line
+            .split_once(':')
+            .ok_or(Error::InvalidHandshakeResponse)?;
+        let value = value.trim();
+        if name.eq_ignore_ascii_case("upgrade") {
+            upgrade_ok = value.eq_ignore_ascii_case("websocket");
+        } else if name.eq_ignore_ascii_case("connection") {
+            connection_ok = value
+                .split(',')
+                .any(|token| token.trim().eq_ignore_ascii_case("upgrade"));
+        } else if name.eq_ignore_ascii_case("sec-websocket-accept") {
+            accept_ok = value == expected_accept;
+        }
+    }
+
+    if !upgrade_ok || !connection_ok {
+        return Err(Error::MissingUpgradeHeaders);
+    }
+    if !accept_ok {
+        return Err(Error::InvalidAcceptKey);
+    }
+
+    Ok(())
+}
+
+/// Decodes the header of a frame sent by the server found at the start of `buffer`.
+///
+/// Returns `None` if `buffer` doesn't contain the entire header yet. On success, also returns
+/// the size in bytes of the header.
+fn decode_frame_header(buffer: &[u8]) -> Result<Option<(IncomingFrame, usize)>, Error> {
+    if buffer.len() < 2 {
+        return Ok(None);
+    }
+
+    let fin = buffer[0] & 0x80 != 0;
+    if buffer[0] & 0x70 != 0 {
+        return Err(Error::ReservedBitsSet);
+    }
+    let opcode = buffer[0] & 0xf;
+    if buffer[1] & 0x80 != 0 {
+        return Err(Error::MaskedFrame);
+    }
+
+    let (payload_len, header_len) = match buffer[1] & 0x7f {
+        126 => {
+            let Some(len) = buffer.get(2..4) else {
+                return Ok(None);
+            };
+            (
+                u64::from(u16::from_be_bytes(<[u8; 2]>::try_from(len).unwrap())),
+                4,
+            )
+        }
+        127 => {
+            let Some(len) = buffer.get(2..10) else {
+                return Ok(None);
+            };
+            (u64::from_be_bytes(<[u8; 8]>::try_from(len).unwrap()), 10)
+        }
+        len => (u64::from(len), 2),
+    };
+
+    match opcode {
+        OPCODE_CONTINUATION | OPCODE_TEXT | OPCODE_BINARY => {}
+        OPCODE_CLOSE | OPCODE_PING | OPCODE_PONG => {
+            if !fin || payload_len > 125 {
+                return Err(Error::InvalidControlFrame);
+            }
+        }
+        _ => return Err(Error::UnknownOpcode(opcode)),
+    }
+
+    Ok(Some((
+        IncomingFrame {
+            opcode,
+            fin,
+            remaining_payload: payload_len,
+            control_payload: Vec::new(),
+        },
+        header_len,
+    )))
+}
+
+/// Appends to `out` a final frame with the given opcode, whose payload is the concatenation of
+/// `payload`. The payload is masked with a key generated from `randomness`, as required for
+/// frames sent by a client.
+fn encode_frame(
+    out: &mut Vec<u8>,
+    opcode: u8,
+    payload: &[impl AsRef<[u8]>],
+    randomness: &mut ChaCha20Rng,
+) {
+    let payload_len = payload.iter().map(|b| b.as_ref().len()).sum::<usize>();
+
+    out.push(0x80 | opcode);
+    if payload_len < 126 {
+        out.push(0x80 | u8::try_from(payload_len).unwrap());
+    } else if let Ok(payload_len) = u16::try_from(payload_len) {
+        out.push(0x80 | 126);
+        out.extend_from_slice(&payload_len.to_be_bytes());
+    } else {
+        out.push(0x80 | 127);
+        out.extend_from_slice(&u64::try_from(payload_len).unwrap().to_be_bytes());
+    }
+
+    let mut mask = [0; 4];
+    randomness.fill_bytes(&mut mask);
+    out.extend_from_slice(&mask);
+
+    out.extend(
+        payload
+            .iter()
+            .flat_map(|b| b.as_ref().iter())
+            .zip(mask.iter().cycle())
+            .map(|(byte, mask)| byte ^ mask),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{accept_key, Config, Error, ReadWrite, WebSocket};
+
+    fn read_write(incoming: Vec<u8>) -> ReadWrite<u32> {
+        ReadWrite {
+            now: 0,
+            incoming_buffer: incoming,
+            expected_incoming_bytes: Some(0),
+            read_bytes: 0,
+            write_bytes_queued: 0,
+            write_bytes_queueable: Some(1024 * 1024),
+            write_buffers: Vec::new(),
+            wake_up_after: None,
+        }
+    }
+
+    /// Decodes the masked frames sent by the client. Returns a list of opcodes and payloads.
+    fn decode_client_frames(mut data: &[u8]) -> Vec<(u8, Vec<u8>)> {
+        let mut frames = Vec::new();
+        while !data.is_empty() {
+            assert_eq!(data[0] & 0xf0, 0x80);
+            assert_eq!(data[1] & 0x80, 0x80);
+            let (len, header_len) = match data[1] & 0x7f {
+                126 => (usize::from(u16::from_be_bytes([data[2], data[3]])), 4),
+                127 => (
+                    usize::try_from(u64::from_be_bytes(
+                        <[u8; 8]>::try_from(&data[2..10]).unwrap(),
+                    ))
+                    .unwrap(),
+                    10,
+                ),
+                len => (usize::from(len), 2),
+            };
+            let mask = &data[header_len..header_len + 4];
+            let payload = data[header_len + 4..header_len + 4 + len]
+                .iter()
+                .zip(mask.iter().cycle())
+                .map(|(b, m)| b ^ m)
+                .collect();
+            frames.push((data[0] & 0xf, payload));
+            data = &data[header_len + 4 + len..];
+        }
+        frames
+    }
+
+    /// Sends the HTTP request and returns the value of its `Sec-WebSocket-Key` header.
+    fn start_handshake(websocket: &mut WebSocket) -> String {
+        let mut outer = read_write(Vec::new());
+        let inner = websocket.read_write(&mut outer).unwrap();
+        assert_eq!(inner.write_bytes_queueable, Some(0));
+        drop(inner);
+
+        let request = String::from_utf8(outer.write_buffers.concat()).unwrap();
+        assert!(request.starts_with("GET /path HTTP/1.1\r\n"));
+        assert!(request.ends_with("\r\n\r\n"));
+        request
+            .split("\r\n")
+            .find_map(|l| l.strip_prefix("Sec-WebSocket-Key: "))
+            .unwrap()
+            .to_owned()
+    }
+
+    #[test]
+    fn handshake_and_frames() {
+        let mut websocket = WebSocket::new(Config {
+            host: "example.com:30333",
+            url: "/path",
+            randomness_seed: [0; 32],
+        });
+        let key = start_handshake(&mut websocket);
+
+        let mut incoming = handshake_response(&key);
+        // Binary frame, ping frame, then the continuation of a fragmented binary frame.
+        incoming.extend_from_slice(&[0x02, 3, b'f', b'o', b'o']);
+        incoming.extend_from_slice(&[0x89, 2, 1, 2]);
+        incoming.extend_from_slice(&[0x80, 3, b'b', b'a', b'r']);
+
+        // Frames are decoded only as long as the inner stream needs more data.
+        let mut outer = read_write(incoming);
+        let mut received = Vec::new();
+        while !outer.incoming_buffer.is_empty() {
+            let mut inner = websocket.read_write(&mut outer).unwrap();
+            received.extend_from_slice(&inner.incoming_buffer);
+            inner.discard_all_incoming();
+        }
+        assert!(websocket.is_handshake_finished());
+        assert_eq!(received, b"foobar");
+
+        let mut inner = websocket.read_write(&mut outer).unwrap();
+        inner.write_out(b"hello".to_vec());
+        inner.close_write();
+        drop(inner);
+
+        assert!(outer.write_bytes_queueable.is_none());
+        assert_eq!(
+            decode_client_frames(&outer.write_buffers.concat()),
+            vec![
+                (0xa, vec![1, 2]),
+                (0x2, b"hello".to_vec()),
+                (0x8, 1000u16.to_be_bytes().to_vec())
+            ]
+        );
+    }
+
+    /// Returns the HTTP response that accepts the upgrade request with the given key.
+    fn handshake_response(key: &str) -> Vec<u8> {
+        format!(
+            "HTTP/1.1 101 Switching Protocols\r\n\
+            Upgrade: websocket\r\n\
+            Connection: Upgrade\r\n\
+            Sec-WebSocket-Accept: {}\r\n\r\n",
+            accept_key(key)
+        )
+        .into_bytes()
+    }
+
+    #[test]
+    fn messages() {
+        let mut websocket = WebSocket::new(Config {
+            host: "example.com:9944",
+            url: "/path",
+            randomness_seed: [0; 32],
+        });
+        websocket.queue_text_message("hello".to_owned());
+
+        // The message isn't sent as long as the handshake isn't finished.
+        let mut outer = read_write(Vec::new());
+        websocket.read_write_messages(&mut outer, 1024).unwrap();
+        let request = String::from_utf8(outer.write_buffers.concat()).unwrap();
+        assert!(request.ends_with("\r\n\r\n"));
+        let key = request
+            .split("\r\n")
+            .find_map(|l| l.strip_prefix("Sec-WebSocket-Key: "))
+            .unwrap();
+
+        let mut incoming = handshake_response(key);
+        // Text message fragmented in two frames with a ping frame in between, then a binary
+        // message, then the first half of a text message.
+        incoming.extend_from_slice(&[0x01, 3, b'f', b'o', b'o']);
+        incoming.extend_from_slice(&[0x89, 2, 1, 2]);
+        incoming.extend_from_slice(&[0x80, 3, b'b', b'a', b'r']);
+        incoming.extend_from_slice(&[0x82, 3, b'b', b'a', b'z']);
+        incoming.extend_from_slice(&[0x81, 4, b'a', b'b']);
+
+        let mut outer = read_write(incoming);
+        websocket.read_write_messages(&mut outer, 1024).unwrap();
+        assert!(websocket.is_handshake_finished());
+        assert_eq!(websocket.pull_message().unwrap(), b"foobar");
+        assert_eq!(websocket.pull_message().unwrap(), b"baz");
+        assert!(websocket.pull_message().is_none());
+        assert_eq!(
+            decode_client_frames(&outer.write_buffers.concat()),
+            vec![(0xa, vec![1, 2]), (0x1, b"hello".to_vec())]
+        );
+
+        let mut outer = read_write(b"cd".to_vec());
+        websocket.read_write_messages(&mut outer, 1024).unwrap();
+        assert_eq!(websocket.pull_message().unwrap(), b"abcd");
+        assert!(websocket.pull_message().is_none());
+    }
+
+    #[test]
+    fn frames_with_64bits_length() {
+        let mut websocket = WebSocket::new(Config {
+            host: "example.com:9944",
+            url: "/path",
+            randomness_seed: [0; 32],
+        });
+        let key = start_handshake(&mut websocket);
+
+        // Payloads over 65535 bytes require a 64 bits length in the frame header.
+        let message = (0..70000)
+            .map(|n| b'a' + (n % 26) as u8)
+            .collect::<Vec<_>>();
+        websocket.queue_text_message(String::from_utf8(message.clone()).unwrap());
+
+        let mut incoming = handshake_response(&key);
+        incoming.extend_from_slice(&[0x82, 127]);
+        incoming.extend_from_slice(&u64::try_from(message.len()).unwrap().to_be_bytes());
+        incoming.extend_from_slice(&message);
+
+        let mut outer = read_write(incoming);
+        websocket
+            .read_write_messages(&mut outer, 1024 * 1024)
+            .unwrap();
+        assert_eq!(websocket.pull_message().unwrap(), message);
+        assert!(websocket.pull_message().is_none());
+        assert_eq!(
+            decode_client_frames(&outer.write_buffers.concat()),
+            vec![(0x1, message)]
+        );
+    }
+
+    #[test]
+    fn message_too_large() {
+        let mut websocket = WebSocket::new(Config {
+            host: "example.com:9944",
+            url: "/path",
+            randomness_seed: [0; 32],
+        });
+        let key = start_handshake(&mut websocket);
+
+        // The limit applies to the total size of the fragments of a message.
+        let mut incoming = handshake_response(&key);
+        incoming.extend_from_slice(&[0x01, 3, b'f', b'o', b'o']);
+        incoming.extend_from_slice(&[0x80, 3, b'b', b'a', b'r']);
+
+        let mut outer = read_write(incoming);
+        assert!(matches!(
+            websocket.read_write_messages(&mut outer, 5),
+            Err(Error::MessageTooLarge)
+        ));
+    }
+
+    #[test]
+    fn invalid_accept_key() {
+        let mut websocket = WebSocket::new(Config {
+            host: "example.com:30333",
+            url: "/path",
+            randomness_seed: [0; 32],
+        });
+        let _ = start_handshake(&mut websocket);
+
+        let mut outer = read_write(
+            b"HTTP/1.1 101 Switching Protocols\r\n\
+            Upgrade: websocket\r\n\
+            Connection: Upgrade\r\n\
+            Sec-WebSocket-Accept: dGhlIHNhbXBsZSBub25jZQ==\r\n\r\n"
+                .to_vec(),
+        );
+        assert!(matches!(
+            websocket.read_write(&mut outer),
+            Err(Error::InvalidAcceptKey)
+        ));
+    }
+
+    #[test]
+    fn upgrade_refused() {
+        let mut websocket = WebSocket::new(Config {
+            host: "example.com:30333",
+            url: "/path",
+            randomness_seed: [0; 32],
+        });
+        let _ = start_handshake(&mut websocket);
+
+        let mut outer = read_write(b"HTTP/1.1 404 Not Found\r\n\r\n".to_vec());
+        assert!(matches!(
+            websocket.read_write(&mut outer),
+            Err(Error::UpgradeRefused(404))
+        ));
+    }
+
+    #[test]
+    fn rfc_accept_key() {
+        // Example found in RFC 6455.
+        assert_eq!(
+            accept_key("dGhlIHNhbXBsZSBub25jZQ=="),
+            "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+        );
+    }
+}
@@ -24,7 +24,7 @@ use core::{
     str::{self, FromStr},
 };
 
-use super::multihash;
+use super::{multihash, peer_id::PeerId};
 
 #[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct Multiaddr {
@@ -473,6 +473,61 @@ impl<'a> fmt::Display for DomainNameRef<'a> {
     }
 }
 
+/// Policy applied by [`sanitize`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SanitizePolicy {
+    /// If `true`, addresses containing a loopback IP address (`127.0.0.0/8` or `::1`) are
+    /// accepted. This should be `true` only if the local node is itself reachable through a
+    /// loopback address, for example when running a local test network.
+    pub allow_loopback: bool,
+}
+
+/// Reason why an address has been refused by [`sanitize`].
+#[derive(Debug, derive_more::Display, Clone, PartialEq, Eq)]
+pub enum SanitizeError {
+    /// Address contains an unspecified IP address (`0.0.0.0` or `::`).
+    #[display(fmt = "Unspecified IP address")]
+    UnspecifiedIp,
+    /// Address contains a loopback IP address, which the policy forbids.
+    #[display(fmt = "Loopback IP address")]
+    LoopbackIp,
+    /// Address contains a `/p2p` component that doesn't match the peer the address belongs to.
+    #[display(fmt = "PeerId in address doesn't match the expected one")]
+    PeerIdMismatch,
+}
+
+/// Checks whether an address of the given peer, learned from a remote (for example through the
+/// identify protocol or through a Kademlia query), is worth storing or advertising.
+///
+/// Addresses that can't possibly be used to reach the given peer, such as addresses containing
+/// an unspecified IP address or a `/p2p` component of a different peer, are refused.
+pub fn sanitize(
+    address: &Multiaddr,
+    peer_id: &PeerId,
+    policy: &SanitizePolicy,
+) -> Result<(), SanitizeError> {
+    for protocol in address.iter() {
+        match protocol {
+            ProtocolRef::Ip4(ip) if ip == [0; 4] => return Err(SanitizeError::UnspecifiedIp),
+            ProtocolRef::Ip6(ip) if ip == [0; 16] => return Err(SanitizeError::UnspecifiedIp),
+            ProtocolRef::Ip4([127, ..]) if !policy.allow_loopback => {
+                return Err(SanitizeError::LoopbackIp)
+            }
+            ProtocolRef::Ip6(ip)
+                if !policy.allow_loopback && ip == no_std_net::Ipv6Addr::LOCALHOST.octets() =>
+            {
+                return Err(SanitizeError::LoopbackIp)
+            }
+            ProtocolRef::P2p(hash) if *hash != *peer_id.as_bytes() => {
+                return Err(SanitizeError::PeerIdMismatch)
+            }
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
 /// Parses a single protocol from its bytes.
 fn protocol<'a, E: nom::error::ParseError<&'a [u8]>>(
     bytes: &'a [u8],
@@ -562,7 +617,8 @@ fn protocol<'a, E: nom::error::ParseError<&'a [u8]>>(
 
 #[cfg(test)]
 mod tests {
-    use super::Multiaddr;
+    use super::{sanitize, Multiaddr, SanitizeError, SanitizePolicy};
+    use crate::libp2p::PeerId;
 
     #[test]
     fn basic() {
@@ -610,4 +666,53 @@ mod tests {
         check_invalid("/certhash");
         check_invalid("/certhash/12D3KooWDpJ7As7BWAwRMfu1VU2WCqNjvq387JEYKDBj4kx6nXTN");
     }
+
+    #[test]
+    fn sanitize_addresses() {
+        let peer_id = "12D3KooWDpJ7As7BWAwRMfu1VU2WCqNjvq387JEYKDBj4kx6nXTN"
+            .parse::<PeerId>()
+            .unwrap();
+        let check = |addr: &str, allow_loopback: bool| {
+            sanitize(
+                &addr.parse::<Multiaddr>().unwrap(),
+                &peer_id,
+                &SanitizePolicy { allow_loopback },
+            )
+        };
+
+        assert_eq!(check("/ip4/1.2.3.4/tcp/30333", false), Ok(()));
+        assert_eq!(check("/dns/example.com/tcp/30333/ws", false), Ok(()));
+        assert_eq!(
+            check(
+                "/ip4/1.2.3.4/tcp/30333/p2p/12D3KooWDpJ7As7BWAwRMfu1VU2WCqNjvq387JEYKDBj4kx6nXTN",
+                false
+            ),
+            Ok(())
+        );
+        assert_eq!(
+            check(
+                "/ip4/1.2.3.4/tcp/30333/p2p/12D3KooWEyoppNCUx8Yx66oV9fJnriXwCcXwDDUA2kj6vnc6iDEp",
+                false
+            ),
+            Err(SanitizeError::PeerIdMismatch)
+        );
+        assert_eq!(
+            check("/ip4/0.0.0.0/tcp/30333", true),
+            Err(SanitizeError::UnspecifiedIp)
+        );
+        assert_eq!(
+            check("/ip6/::/tcp/30333", true),
+            Err(SanitizeError::UnspecifiedIp)
+        );
+        assert_eq!(
+            check("/ip4/127.0.0.1/tcp/30333", false),
+            Err(SanitizeError::LoopbackIp)
+        );
+        assert_eq!(
+            check("/ip6/::1/tcp/30333", false),
+            Err(SanitizeError::LoopbackIp)
+        );
+        assert_eq!(check("/ip4/127.0.0.1/tcp/30333", true), Ok(()));
+        assert_eq!(check("/ip6/::1/tcp/30333", true), Ok(()));
+    }
 }
@@ -21,6 +21,10 @@
 *********************************************************/
 
 pub mod basic_peering_strategy;
+pub mod codec;
 pub mod kademlia;
-pub mod protocol;
 pub mod service;
+
+/// Former name of the [`codec`] module, kept so that code referring to `network::protocol`
+/// continues to compile.
+pub use codec as protocol;
@@ -22,6 +22,14 @@
 //!
 //! Each network identity is associated with zero or more addresses. Each address is either
 //! "connected" or "disconnected".
+//!
+//! Additionally, the [`BasicPeeringStrategy`] can remember which network identity was found
+//! behind each address (see [`BasicPeeringStrategy::pin_address_identity`]), in order to detect
+//! addresses that suddenly present a different identity. Such an address might indicate an
+//! attempt at a man-in-the-middle attack, or simply that the address has been reused by a
+//! different node. Addresses can be put in quarantine (see
+//! [`BasicPeeringStrategy::quarantine_address`]), in which case they are no longer chosen by
+//! [`BasicPeeringStrategy::addr_to_connected`].
 
 use alloc::{
     borrow::ToOwned as _,
@@ -40,6 +48,15 @@ pub struct BasicPeeringStrategy<TChainId, TInstant> {
     peers_chains: BTreeMap<(PeerId, TChainId), PeerChainState<TInstant>>,
 
     peers_chains_by_state: BTreeSet<(TChainId, PeerChainState<TInstant>, PeerId)>,
+
+    /// See [`BasicPeeringStrategy::pin_address_identity`].
+    ///
+    /// Entries are never removed except through
+    /// [`BasicPeeringStrategy::unpin_address_identity`], even if the address is removed.
+    identity_pins: BTreeMap<Vec<u8>, PeerId>,
+
+    /// See [`BasicPeeringStrategy::quarantine_address`].
+    quarantined_addresses: BTreeSet<Vec<u8>>,
 }
 
 #[derive(Debug)]
@@ -66,6 +83,8 @@ where
             addresses: BTreeMap::new(),
             peers_chains: BTreeMap::new(),
             peers_chains_by_state: BTreeSet::new(),
+            identity_pins: BTreeMap::new(),
+            quarantined_addresses: BTreeSet::new(),
         }
     }
 
@@ -191,6 +210,43 @@ where
         }
     }
 
+    /// Assigns a slot to the given peer on the given chain, if the peer is known to belong to
+    /// this chain, isn't banned, and doesn't have a slot assigned to it yet.
+    ///
+    /// Returns `true` if a slot has been assigned.
+    ///
+    /// A `TInstant` must be provided in order to determine whether past bans have expired.
+    pub fn assign_slot_to_peer(
+        &mut self,
+        chain: &TChainId,
+        peer_id: &PeerId,
+        now: &TInstant,
+    ) -> bool {
+        let Some(state) = self.peers_chains.get_mut(&(peer_id.clone(), chain.clone())) else {
+            return false;
+        };
+
+        if !(matches!(*state, PeerChainState::Assignable)
+            || matches!(&*state, PeerChainState::Banned { expires } if *expires <= *now))
+        {
+            return false;
+        }
+
+        let _was_in =
+            self.peers_chains_by_state
+                .remove(&(chain.clone(), state.clone(), peer_id.clone()));
+        debug_assert!(_was_in);
+
+        *state = PeerChainState::Slot;
+
+        let _was_inserted =
+            self.peers_chains_by_state
+                .insert((chain.clone(), state.clone(), peer_id.clone()));
+        debug_assert!(_was_inserted);
+
+        true
+    }
+
     /// Unassign the slot that has been assigned to the given peer and bans the peer, preventing
     /// it from being assigned a slot on this chain for a certain amount of time.
     pub fn unassign_slot_and_ban(
@@ -252,11 +308,23 @@ where
 
     /// Picks an address from the list whose state is "not connected", and switches it to
     /// "connected". Returns `None` if no such address is available.
+    ///
+    /// Addresses that are in quarantine are never picked.
     pub fn addr_to_connected(&mut self, peer_id: &PeerId) -> Option<&[u8]> {
+        self.addr_to_connected_filtered(peer_id, |_| true)
+    }
+
+    /// Similar to [`BasicPeeringStrategy::addr_to_connected`], but only picks an address for
+    /// which `filter` returns `true`.
+    pub fn addr_to_connected_filtered(
+        &mut self,
+        peer_id: &PeerId,
+        mut filter: impl FnMut(&[u8]) -> bool,
+    ) -> Option<&[u8]> {
         // TODO: optimize
-        if let Some(((_, address), state)) =
-            self.addresses.iter_mut().find(|((p, _), _)| p == peer_id)
-        {
+        if let Some(((_, address), state)) = self.addresses.iter_mut().find(|((p, a), _)| {
+            p == peer_id && !self.quarantined_addresses.contains(a) && filter(a)
+        }) {
             *state = AddressState::Connected;
             Some(&address)
         } else {
@@ -287,6 +355,78 @@ where
 
         Ok(())
     }
+
+    /// Records that the given address has been found to be reachable under the given
+    /// [`PeerId`], and compares it with the [`PeerId`] previously recorded for this address.
+    ///
+    /// The first [`PeerId`] recorded for each address is kept. If a different [`PeerId`] is
+    /// later recorded for the same address, [`PinAddressIdentityOutcome::Changed`] is returned
+    /// and the recorded [`PeerId`] isn't modified. Use
+    /// [`BasicPeeringStrategy::unpin_address_identity`] in order to accept the new identity.
+    ///
+    /// The recorded identities are kept even when the address is removed with
+    /// [`BasicPeeringStrategy::remove_address`].
+    pub fn pin_address_identity(
+        &'_ mut self,
+        address: &[u8],
+        peer_id: &PeerId,
+    ) -> PinAddressIdentityOutcome<'_> {
+        match self.identity_pins.entry(address.to_owned()) {
+            btree_map::Entry::Vacant(entry) => {
+                entry.insert(peer_id.clone());
+                PinAddressIdentityOutcome::New
+            }
+            btree_map::Entry::Occupied(entry) if entry.get() == peer_id => {
+                PinAddressIdentityOutcome::Unchanged
+            }
+            btree_map::Entry::Occupied(entry) => PinAddressIdentityOutcome::Changed {
+                pinned: entry.into_mut(),
+            },
+        }
+    }
+
+    /// Forgets the [`PeerId`] recorded for the given address with
+    /// [`BasicPeeringStrategy::pin_address_identity`], and returns it.
+    pub fn unpin_address_identity(&mut self, address: &[u8]) -> Option<PeerId> {
+        self.identity_pins.remove(address)
+    }
+
+    /// Puts the given address in quarantine. It will no longer be picked by
+    /// [`BasicPeeringStrategy::addr_to_connected`], no matter which peer it belongs to, until
+    /// [`BasicPeeringStrategy::unquarantine_address`] is called.
+    ///
+    /// The address isn't removed from the peers it belongs to, and its state is left untouched.
+    ///
+    /// Returns `false` if the address was already in quarantine.
+    pub fn quarantine_address(&mut self, address: &[u8]) -> bool {
+        self.quarantined_addresses.insert(address.to_owned())
+    }
+
+    /// Removes the given address from the quarantine.
+    ///
+    /// Returns `false` if the address wasn't in quarantine.
+    pub fn unquarantine_address(&mut self, address: &[u8]) -> bool {
+        self.quarantined_addresses.remove(address)
+    }
+
+    /// Returns `true` if the given address is in quarantine.
+    pub fn is_address_quarantined(&self, address: &[u8]) -> bool {
+        self.quarantined_addresses.contains(address)
+    }
+}
+
+/// Outcome of [`BasicPeeringStrategy::pin_address_identity`].
+#[derive(Debug)]
+pub enum PinAddressIdentityOutcome<'a> {
+    /// No [`PeerId`] was recorded for this address. The given [`PeerId`] is now recorded.
+    New,
+    /// The given [`PeerId`] is the same as the one recorded for this address.
+    Unchanged,
+    /// The given [`PeerId`] is different from the one recorded for this address.
+    Changed {
+        /// [`PeerId`] that was recorded for this address, and that is still recorded.
+        pinned: &'a PeerId,
+    },
 }
 
 #[derive(Debug, derive_more::Display)]
@@ -0,0 +1,639 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Substrate/Polkadot-specific protocols on top of libp2p.
+//!
+//! Libp2p provides a framework on top of which higher-level, domain-specific protocols can be
+//! used. This module contains the domain-specific protocols specific to Substrate and Polkadot.
+//!
+//! This module only provides the tools to encode/decode messages.
+
+// TODO: expand docs
+
+use alloc::{
+    borrow::Cow,
+    string::{String, ToString as _},
+    vec::Vec,
+};
+use core::{fmt, iter};
+
+// Implementation note: each protocol goes into a different sub-module whose content is
+// re-exported here.
+
+mod block_announces;
+mod block_request;
+mod block_request_planner;
+mod grandpa;
+mod grandpa_warp_sync;
+mod identify;
+mod kademlia;
+mod state_request;
+mod storage_call_proof;
+mod transactions;
+
+pub use self::block_announces::*;
+pub use self::block_request::*;
+pub use self::block_request_planner::*;
+pub use self::grandpa::*;
+pub use self::grandpa_warp_sync::*;
+pub use self::identify::*;
+pub use self::kademlia::*;
+pub use self::state_request::*;
+pub use self::storage_call_proof::*;
+pub use self::transactions::*;
+
+/// Name of a protocol that is part of the Substrate/Polkadot networking.
+///
+/// The chain-specific protocols don't include any version. The version that is used when
+/// encoding a [`ProtocolName`] is determined by looking up [`PROTOCOL_SCHEMAS`].
+#[derive(Copy, Clone, PartialEq, Eq, Hash)]
+pub enum ProtocolName<'a> {
+    Identify,
+    Ping,
+    BlockAnnounces {
+        genesis_hash: [u8; 32],
+        fork_id: Option<&'a str>,
+    },
+    Transactions {
+        genesis_hash: [u8; 32],
+        fork_id: Option<&'a str>,
+    },
+    Grandpa {
+        genesis_hash: [u8; 32],
+        fork_id: Option<&'a str>,
+    },
+    Sync {
+        genesis_hash: [u8; 32],
+        fork_id: Option<&'a str>,
+    },
+    Light {
+        genesis_hash: [u8; 32],
+        fork_id: Option<&'a str>,
+    },
+    Kad {
+        genesis_hash: [u8; 32],
+        fork_id: Option<&'a str>,
+    },
+    SyncWarp {
+        genesis_hash: [u8; 32],
+        fork_id: Option<&'a str>,
+    },
+    State {
+        genesis_hash: [u8; 32],
+        fork_id: Option<&'a str>,
+    },
+}
+
+impl<'a> ProtocolName<'a> {
+    /// Returns the kind of the protocol, or `None` if the protocol isn't chain-specific.
+    pub fn kind(&self) -> Option<ProtocolKind> {
+        match self {
+            ProtocolName::Identify | ProtocolName::Ping => None,
+            ProtocolName::BlockAnnounces { .. } => Some(ProtocolKind::BlockAnnounces),
+            ProtocolName::Transactions { .. } => Some(ProtocolKind::Transactions),
+            ProtocolName::Grandpa { .. } => Some(ProtocolKind::Grandpa),
+            ProtocolName::Sync { .. } => Some(ProtocolKind::Sync),
+            ProtocolName::Light { .. } => Some(ProtocolKind::Light),
+            ProtocolName::Kad { .. } => Some(ProtocolKind::Kad),
+            ProtocolName::SyncWarp { .. } => Some(ProtocolKind::SyncWarp),
+            ProtocolName::State { .. } => Some(ProtocolKind::State),
+        }
+    }
+
+    fn from_kind(kind: ProtocolKind, genesis_hash: [u8; 32], fork_id: Option<&'a str>) -> Self {
+        match kind {
+            ProtocolKind::BlockAnnounces => ProtocolName::BlockAnnounces {
+                genesis_hash,
+                fork_id,
+            },
+            ProtocolKind::Transactions => ProtocolName::Transactions {
+                genesis_hash,
+                fork_id,
+            },
+            ProtocolKind::Grandpa => ProtocolName::Grandpa {
+                genesis_hash,
+                fork_id,
+            },
+            ProtocolKind::Sync => ProtocolName::Sync {
+                genesis_hash,
+                fork_id,
+            },
+            ProtocolKind::Light => ProtocolName::Light {
+                genesis_hash,
+                fork_id,
+            },
+            ProtocolKind::Kad => ProtocolName::Kad {
+                genesis_hash,
+                fork_id,
+            },
+            ProtocolKind::SyncWarp => ProtocolName::SyncWarp {
+                genesis_hash,
+                fork_id,
+            },
+            ProtocolKind::State => ProtocolName::State {
+                genesis_hash,
+                fork_id,
+            },
+        }
+    }
+}
+
+impl<'a> fmt::Debug for ProtocolName<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+impl<'a> fmt::Display for ProtocolName<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for chunk in encode_protocol_name(*self) {
+            f.write_str(chunk.as_ref())?;
+        }
+        Ok(())
+    }
+}
+
+/// Kind of a chain-specific protocol, independently of its version.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum ProtocolKind {
+    BlockAnnounces,
+    Transactions,
+    Grandpa,
+    Sync,
+    Light,
+    Kad,
+    SyncWarp,
+    State,
+}
+
+/// Entry of [`PROTOCOL_SCHEMAS`].
+#[derive(Debug, Copy, Clone)]
+pub struct ProtocolSchema {
+    /// Kind of the protocol.
+    pub kind: ProtocolKind,
+    /// Name of the protocol, after the genesis hash and fork id and without the version.
+    pub family: &'static str,
+    /// Version of the protocol, appended to [`ProtocolSchema::family`] after a `/`. `None` for
+    /// the protocols whose name doesn't contain any version.
+    pub version: Option<u32>,
+    /// Functions that decode the messages of this version of the protocol.
+    pub codec: ProtocolCodec,
+}
+
+/// Functions that decode the messages of a specific version of a protocol.
+///
+/// The networking service decodes messages through the [`ProtocolCodec`] of the entry of
+/// [`PROTOCOL_SCHEMAS`] that has been negotiated, rather than by calling the decoding functions
+/// directly. Supporting a new version of a protocol consists in adding an entry to
+/// [`PROTOCOL_SCHEMAS`] that points to the decoding functions of this new version.
+#[derive(Debug, Copy, Clone)]
+#[allow(clippy::type_complexity)]
+pub enum ProtocolCodec {
+    /// Notifications protocols. Their messages are decoded by the networking service directly.
+    Notifications,
+    /// See [`ProtocolKind::Sync`].
+    Sync {
+        /// Decodes a blocks request. The first parameter is the number of bytes used to encode
+        /// block numbers.
+        decode_request: fn(usize, &[u8]) -> Result<BlocksRequestConfig, DecodeBlockRequestError>,
+        /// Decodes the response to a blocks request.
+        decode_response: fn(&[u8]) -> Result<Vec<BlockData>, DecodeBlockResponseError>,
+    },
+    /// See [`ProtocolKind::Light`].
+    Light {
+        /// Decodes the response to a storage or call proof request.
+        decode_response: fn(
+            StorageOrCallProof,
+            &[u8],
+        ) -> Result<Option<&[u8]>, DecodeStorageCallProofResponseError>,
+    },
+    /// See [`ProtocolKind::Kad`].
+    Kad {
+        /// Decodes the response to a Kademlia `FIND_NODE` request.
+        decode_response:
+            fn(
+                &[u8],
+            )
+                -> Result<Vec<(crate::libp2p::PeerId, Vec<Vec<u8>>)>, DecodeFindNodeResponseError>,
+    },
+    /// See [`ProtocolKind::SyncWarp`].
+    SyncWarp {
+        /// Decodes the response to a GrandPa warp sync request. The second parameter is the
+        /// number of bytes used to encode block numbers.
+        decode_response:
+            fn(&[u8], usize) -> Result<GrandpaWarpSyncResponse, DecodeGrandpaWarpSyncResponseError>,
+    },
+    /// See [`ProtocolKind::State`].
+    State {
+        /// Decodes the response to a state request.
+        decode_response: fn(&[u8]) -> Result<&[u8], DecodeStateResponseError>,
+    },
+}
+
+/// Registry of all the versions of the chain-specific protocols that are supported.
+///
+/// If multiple versions of the same [`ProtocolKind`] are present, the one that appears first in
+/// the list is the preferred one and is the one used when encoding a [`ProtocolName`]. The other
+/// versions are still accepted when decoding a protocol name, in order to be able to talk to
+/// peers that don't support the preferred version.
+///
+/// Each [`ProtocolKind`] must appear at least once in this list.
+pub const PROTOCOL_SCHEMAS: &[ProtocolSchema] = &[
+    ProtocolSchema {
+        kind: ProtocolKind::BlockAnnounces,
+        family: "block-announces",
+        version: Some(1),
+        codec: ProtocolCodec::Notifications,
+    },
+    ProtocolSchema {
+        kind: ProtocolKind::Transactions,
+        family: "transactions",
+        version: Some(1),
+        codec: ProtocolCodec::Notifications,
+    },
+    ProtocolSchema {
+        kind: ProtocolKind::Grandpa,
+        family: "grandpa",
+        version: Some(1),
+        codec: ProtocolCodec::Notifications,
+    },
+    ProtocolSchema {
+        kind: ProtocolKind::Sync,
+        family: "sync",
+        version: Some(2),
+        codec: ProtocolCodec::Sync {
+            decode_request: decode_block_request,
+            decode_response: decode_block_response,
+        },
+    },
+    ProtocolSchema {
+        kind: ProtocolKind::Light,
+        family: "light",
+        version: Some(2),
+        codec: ProtocolCodec::Light {
+            decode_response: decode_storage_or_call_proof_response,
+        },
+    },
+    ProtocolSchema {
+        kind: ProtocolKind::Kad,
+        family: "kad",
+        version: None,
+        codec: ProtocolCodec::Kad {
+            decode_response: decode_find_node_response,
+        },
+    },
+    ProtocolSchema {
+        kind: ProtocolKind::SyncWarp,
+        family: "sync/warp",
+        version: None,
+        codec: ProtocolCodec::SyncWarp {
+            decode_response: decode_grandpa_warp_sync_response,
+        },
+    },
+    ProtocolSchema {
+        kind: ProtocolKind::State,
+        family: "state",
+        version: Some(2),
+        codec: ProtocolCodec::State {
+            decode_response: decode_state_response,
+        },
+    },
+];
+
+/// Returns the preferred entry of [`PROTOCOL_SCHEMAS`] for the given kind of protocol.
+pub fn preferred_protocol_schema(kind: ProtocolKind) -> &'static ProtocolSchema {
+    PROTOCOL_SCHEMAS
+        .iter()
+        .find(|schema| schema.kind == kind)
+        .unwrap_or_else(|| unreachable!())
+}
+
+/// Turns a [`ProtocolName`] into its string version. Returns a list of objects that, when
+/// concatenated together, forms the string version of the [`ProtocolName`].
+///
+/// The version of the protocol is the one of [`preferred_protocol_schema`].
+pub fn encode_protocol_name(
+    protocol: ProtocolName<'_>,
+) -> impl Iterator<Item = impl AsRef<str> + '_> + '_ {
+    let (genesis_hash, fork_id) = match protocol {
+        ProtocolName::Identify => return either::Left(iter::once(Cow::Borrowed("/ipfs/id/1.0.0"))),
+        ProtocolName::Ping => return either::Left(iter::once(Cow::Borrowed("/ipfs/ping/1.0.0"))),
+        ProtocolName::BlockAnnounces {
+            genesis_hash,
+            fork_id,
+        }
+        | ProtocolName::Transactions {
+            genesis_hash,
+            fork_id,
+        }
+        | ProtocolName::Grandpa {
+            genesis_hash,
+            fork_id,
+        }
+        | ProtocolName::Sync {
+            genesis_hash,
+            fork_id,
+        }
+        | ProtocolName::Light {
+            genesis_hash,
+            fork_id,
+        }
+        | ProtocolName::Kad {
+            genesis_hash,
+            fork_id,
+        }
+        | ProtocolName::SyncWarp {
+            genesis_hash,
+            fork_id,
+        }
+        | ProtocolName::State {
+            genesis_hash,
+            fork_id,
+        } => (genesis_hash, fork_id),
+    };
+
+    let schema = preferred_protocol_schema(protocol.kind().unwrap_or_else(|| unreachable!()));
+
+    either::Right(
+        [
+            Some(Cow::Borrowed("/")),
+            Some(Cow::Owned(hex::encode(genesis_hash))),
+            fork_id.map(|_| Cow::Borrowed("/")),
+            fork_id.map(Cow::Borrowed),
+            Some(Cow::Borrowed("/")),
+            Some(Cow::Borrowed(schema.family)),
+            schema.version.map(|_| Cow::Borrowed("/")),
+            schema.version.map(|v| Cow::Owned(v.to_string())),
+        ]
+        .into_iter()
+        .flatten(),
+    )
+}
+
+/// Turns a [`ProtocolName`] into a string.
+pub fn encode_protocol_name_string(protocol: ProtocolName<'_>) -> String {
+    encode_protocol_name(protocol).fold(String::with_capacity(128), |mut a, b| {
+        a.push_str(b.as_ref());
+        a
+    })
+}
+
+/// Decodes a protocol name into its components.
+///
+/// Returns an error if the protocol name isn't recognized.
+pub fn decode_protocol_name(name: &str) -> Result<ProtocolName<'_>, DecodeProtocolNameError> {
+    decode_protocol_schema(name).map(|(protocol, _)| protocol)
+}
+
+/// Decodes a protocol name into its components, and returns the entry of [`PROTOCOL_SCHEMAS`]
+/// that corresponds to it.
+///
+/// The returned [`ProtocolSchema`] is `None` for the protocols that aren't chain-specific.
+///
+/// Returns an error if the protocol name isn't recognized. If the protocol name corresponds to
+/// a version of a chain-specific protocol that isn't in [`PROTOCOL_SCHEMAS`], for example a
+/// version more recent than the ones supported, a
+/// [`DecodeProtocolNameError::UnsupportedVersion`] is returned.
+pub fn decode_protocol_schema(
+    name: &str,
+) -> Result<(ProtocolName<'_>, Option<&'static ProtocolSchema>), DecodeProtocolNameError> {
+    match name {
+        "/ipfs/id/1.0.0" => return Ok((ProtocolName::Identify, None)),
+        "/ipfs/ping/1.0.0" => return Ok((ProtocolName::Ping, None)),
+        _ => {}
+    }
+
+    // Chain-specific protocols have the format `/<genesis_hash>/<fork_id>/<protocol>` or
+    // `/<genesis_hash>/<protocol>`, where `<protocol>` can itself contain slashes.
+    let Some(rest) = name.strip_prefix('/') else {
+        return Err(DecodeProtocolNameError::Unknown);
+    };
+    let Some((genesis_hash, rest)) = rest.split_once('/') else {
+        return Err(DecodeProtocolNameError::Unknown);
+    };
+    let genesis_hash = match <[u8; 32]>::try_from(
+        hex::decode(genesis_hash).map_err(|_| DecodeProtocolNameError::Unknown)?,
+    ) {
+        Ok(hash) => hash,
+        Err(_) => return Err(DecodeProtocolNameError::Unknown),
+    };
+
+    // Try first without a fork id, then with a fork id. The error corresponding to the attempt
+    // without a fork id is returned only if it is more precise.
+    let without_fork_id = match lookup_protocol_schema(rest) {
+        Ok(schema) => {
+            return Ok((
+                ProtocolName::from_kind(schema.kind, genesis_hash, None),
+                Some(schema),
+            ))
+        }
+        Err(err) => err,
+    };
+
+    let with_fork_id = match rest.split_once('/') {
+        Some((fork_id, rest)) => match lookup_protocol_schema(rest) {
+            Ok(schema) => {
+                return Ok((
+                    ProtocolName::from_kind(schema.kind, genesis_hash, Some(fork_id)),
+                    Some(schema),
+                ))
+            }
+            Err(err) => err,
+        },
+        None => DecodeProtocolNameError::Unknown,
+    };
+
+    match (without_fork_id, with_fork_id) {
+        (err @ DecodeProtocolNameError::UnsupportedVersion { .. }, _) => Err(err),
+        (_, err) => Err(err),
+    }
+}
+
+/// Finds the entry of [`PROTOCOL_SCHEMAS`] corresponding to the part of a protocol name that
+/// follows the genesis hash and fork id.
+fn lookup_protocol_schema(name: &str) -> Result<&'static ProtocolSchema, DecodeProtocolNameError> {
+    if let Some(schema) = PROTOCOL_SCHEMAS
+        .iter()
+        .find(|schema| schema.version.is_none() && schema.family == name)
+    {
+        return Ok(schema);
+    }
+
+    let Some((family, version)) = name.rsplit_once('/') else {
+        return Err(DecodeProtocolNameError::Unknown);
+    };
+    // Note that `u32::from_str` accepts a leading `+`, which we don't want.
+    if !version.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(DecodeProtocolNameError::Unknown);
+    }
+    let Ok(version) = version.parse::<u32>() else {
+        return Err(DecodeProtocolNameError::Unknown);
+    };
+
+    let mut kind = None;
+    for schema in PROTOCOL_SCHEMAS.iter().filter(|s| s.family == family) {
+        if schema.version == Some(version) {
+            return Ok(schema);
+        }
+        kind = Some(schema.kind);
+    }
+
+    match kind {
+        Some(kind) => Err(DecodeProtocolNameError::UnsupportedVersion { kind, version }),
+        None => Err(DecodeProtocolNameError::Unknown),
+    }
+}
+
+/// Error potentially returned by [`decode_protocol_name`] and [`decode_protocol_schema`].
+#[derive(Debug, Clone, PartialEq, Eq, derive_more::Display)]
+pub enum DecodeProtocolNameError {
+    /// Protocol name doesn't correspond to any known protocol.
+    #[display(fmt = "Unknown protocol name")]
+    Unknown,
+    /// Protocol name corresponds to a known protocol, but its version isn't supported.
+    #[display(fmt = "Unsupported version {version} of {kind:?} protocol")]
+    UnsupportedVersion {
+        /// Kind of the protocol.
+        kind: ProtocolKind,
+        /// Version found in the protocol name.
+        version: u32,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        decode_protocol_name, decode_protocol_schema, encode_protocol_name_string,
+        DecodeProtocolNameError, ProtocolCodec, ProtocolKind, ProtocolName, PROTOCOL_SCHEMAS,
+    };
+
+    const GENESIS_HASH: [u8; 32] = [0xaa; 32];
+
+    #[test]
+    fn every_kind_registered() {
+        for kind in [
+            ProtocolKind::BlockAnnounces,
+            ProtocolKind::Transactions,
+            ProtocolKind::Grandpa,
+            ProtocolKind::Sync,
+            ProtocolKind::Light,
+            ProtocolKind::Kad,
+            ProtocolKind::SyncWarp,
+            ProtocolKind::State,
+        ] {
+            assert!(PROTOCOL_SCHEMAS.iter().any(|s| s.kind == kind));
+        }
+    }
+
+    #[test]
+    fn codec_matches_kind() {
+        for schema in PROTOCOL_SCHEMAS {
+            assert!(matches!(
+                (schema.kind, schema.codec),
+                (
+                    ProtocolKind::BlockAnnounces
+                        | ProtocolKind::Transactions
+                        | ProtocolKind::Grandpa,
+                    ProtocolCodec::Notifications
+                ) | (ProtocolKind::Sync, ProtocolCodec::Sync { .. })
+                    | (ProtocolKind::Light, ProtocolCodec::Light { .. })
+                    | (ProtocolKind::Kad, ProtocolCodec::Kad { .. })
+                    | (ProtocolKind::SyncWarp, ProtocolCodec::SyncWarp { .. })
+                    | (ProtocolKind::State, ProtocolCodec::State { .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn encode_decode_roundtrip() {
+        for fork_id in [None, Some("fork")] {
+            for kind in PROTOCOL_SCHEMAS.iter().map(|s| s.kind) {
+                let protocol = ProtocolName::from_kind(kind, GENESIS_HASH, fork_id);
+                let encoded = encode_protocol_name_string(protocol);
+                assert_eq!(decode_protocol_name(&encoded).unwrap(), protocol);
+            }
+        }
+
+        for protocol in [ProtocolName::Identify, ProtocolName::Ping] {
+            let encoded = encode_protocol_name_string(protocol);
+            assert_eq!(decode_protocol_name(&encoded).unwrap(), protocol);
+        }
+    }
+
+    #[test]
+    fn known_names() {
+        let hash = hex::encode(GENESIS_HASH);
+        assert_eq!(
+            encode_protocol_name_string(ProtocolName::Sync {
+                genesis_hash: GENESIS_HASH,
+                fork_id: None,
+            }),
+            format!("/{hash}/sync/2")
+        );
+        assert_eq!(
+            encode_protocol_name_string(ProtocolName::SyncWarp {
+                genesis_hash: GENESIS_HASH,
+                fork_id: Some("fork"),
+            }),
+            format!("/{hash}/fork/sync/warp")
+        );
+
+        let name = format!("/{hash}/fork/state/2");
+        let (protocol, schema) = decode_protocol_schema(&name).unwrap();
+        assert_eq!(
+            protocol,
+            ProtocolName::State {
+                genesis_hash: GENESIS_HASH,
+                fork_id: Some("fork"),
+            }
+        );
+        assert_eq!(schema.unwrap().version, Some(2));
+    }
+
+    #[test]
+    fn unsupported_version() {
+        let hash = hex::encode(GENESIS_HASH);
+        for name in [format!("/{hash}/sync/3"), format!("/{hash}/fork/sync/3")] {
+            assert_eq!(
+                decode_protocol_name(&name),
+                Err(DecodeProtocolNameError::UnsupportedVersion {
+                    kind: ProtocolKind::Sync,
+                    version: 3
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn unknown_names() {
+        let hash = hex::encode(GENESIS_HASH);
+        for name in [
+            format!("/{hash}/foo/1"),
+            format!("/{hash}/sync/+2"),
+            format!("/{hash}/sync"),
+            format!("/{hash}"),
+            "/ipfs/id/2.0.0".to_owned(),
+            "/1234/sync/2".to_owned(),
+            "".to_owned(),
+        ] {
+            assert_eq!(
+                decode_protocol_name(&name),
+                Err(DecodeProtocolNameError::Unknown)
+            );
+        }
+    }
+}
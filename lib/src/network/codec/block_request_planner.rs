@@ -0,0 +1,329 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use super::{
+    BlocksRequestConfig, BlocksRequestConfigStart, BlocksRequestDirection, BlocksRequestFields,
+};
+
+use core::num::{NonZeroU32, NonZeroU64};
+
+/// Configuration for a [`BlocksRequestPlanner`].
+#[derive(Debug, Clone)]
+pub struct BlocksRequestPlannerConfig {
+    /// Number of the first block of the range. In the case of
+    /// [`BlocksRequestDirection::Descending`], this is the block with the highest number.
+    pub first_block_number: u64,
+
+    /// Hash of the first block of the range, if known. Requesting a block by hash is preferable
+    /// to requesting it by number, as the number is ambiguous in case of forks.
+    pub first_block_hash: Option<[u8; 32]>,
+
+    /// Number of blocks in the range, including the first block.
+    ///
+    /// In the case of [`BlocksRequestDirection::Descending`], the range is capped so that it
+    /// doesn't go further than the genesis block.
+    pub num_blocks: NonZeroU64,
+
+    /// Direction in which the range is iterated.
+    pub direction: BlocksRequestDirection,
+
+    /// Which fields should be present in the responses.
+    pub fields: BlocksRequestFields,
+
+    /// Maximum number of blocks to request at once. Substrate-based nodes refuse to return more
+    /// than 128 blocks in a single response, and this value should therefore normally not be
+    /// higher.
+    pub max_blocks_per_request: NonZeroU32,
+}
+
+/// Splits a range of blocks into a series of block requests.
+///
+/// Call [`BlocksRequestPlanner::next_request`] in order to obtain the request to send, then
+/// [`BlocksRequestPlanner::inject_response`] once the response has been received, and repeat
+/// until [`BlocksRequestPlanner::next_request`] returns `None`.
+///
+/// Remotes are free to return fewer blocks than requested, for example because the response
+/// would otherwise be too large. When that happens, the number of blocks requested at once is
+/// lowered to the number of blocks that have been returned, in order to not repeatedly ask for
+/// more blocks than the remote is willing to send.
+#[derive(Debug, Clone)]
+pub struct BlocksRequestPlanner {
+    /// See [`BlocksRequestPlannerConfig::direction`].
+    direction: BlocksRequestDirection,
+
+    /// See [`BlocksRequestPlannerConfig::fields`].
+    fields: BlocksRequestFields,
+
+    /// Number of the next block to request.
+    next_block_number: u64,
+
+    /// Hash of the next block to request, if known.
+    next_block_hash: Option<[u8; 32]>,
+
+    /// Number of blocks that remain to be received. `0` if the planner is finished.
+    remaining_blocks: u64,
+
+    /// Number of blocks to request at once. Lowered when a response is truncated.
+    max_blocks: NonZeroU32,
+}
+
+impl BlocksRequestPlanner {
+    /// Initializes a new planner.
+    pub fn new(config: BlocksRequestPlannerConfig) -> Self {
+        let remaining_blocks = match config.direction {
+            BlocksRequestDirection::Ascending => config.num_blocks.get(),
+            BlocksRequestDirection::Descending => config
+                .num_blocks
+                .get()
+                .min(config.first_block_number.saturating_add(1)),
+        };
+
+        BlocksRequestPlanner {
+            direction: config.direction,
+            fields: config.fields,
+            next_block_number: config.first_block_number,
+            next_block_hash: config.first_block_hash,
+            remaining_blocks,
+            max_blocks: config.max_blocks_per_request,
+        }
+    }
+
+    /// Returns the request to send next, or `None` if all the blocks of the range have been
+    /// received.
+    pub fn next_request(&self) -> Option<BlocksRequestConfig> {
+        let desired_count = NonZeroU32::new(
+            u32::try_from(self.remaining_blocks)
+                .unwrap_or(u32::MAX)
+                .min(self.max_blocks.get()),
+        )?;
+
+        Some(BlocksRequestConfig {
+            start: match self.next_block_hash {
+                Some(hash) => BlocksRequestConfigStart::Hash(hash),
+                None => BlocksRequestConfigStart::Number(self.next_block_number),
+            },
+            desired_count,
+            direction: self.direction.clone(),
+            fields: self.fields.clone(),
+        })
+    }
+
+    /// Updates the planner with the response to the request returned by
+    /// [`BlocksRequestPlanner::next_request`].
+    ///
+    /// `num_blocks` is the number of blocks in the response, which must have been verified to
+    /// form a chain starting at the requested block.
+    ///
+    /// `next_block_hash` is the hash of the block the next request should start with, if known.
+    /// In the case of [`BlocksRequestDirection::Descending`], this is the parent hash of the last
+    /// block of the response. In the case of [`BlocksRequestDirection::Ascending`], this is
+    /// normally not known and the next request uses a block number instead.
+    ///
+    /// Returns an error if the response is empty, in which case the state of the planner isn't
+    /// modified and the same request should be sent to a different peer.
+    ///
+    /// # Panic
+    ///
+    /// Panics if [`BlocksRequestPlanner::next_request`] would return `None`.
+    ///
+    pub fn inject_response(
+        &mut self,
+        num_blocks: u32,
+        next_block_hash: Option<[u8; 32]>,
+    ) -> Result<(), EmptyResponseError> {
+        let requested = self.next_request().unwrap().desired_count;
+
+        let Some(num_blocks) = NonZeroU32::new(num_blocks.min(requested.get())) else {
+            return Err(EmptyResponseError);
+        };
+
+        // The remote has sent fewer blocks than requested. Assume that this is the maximum it
+        // is willing to send.
+        if num_blocks < requested {
+            self.max_blocks = num_blocks;
+        }
+
+        self.remaining_blocks -= u64::from(num_blocks.get());
+        self.next_block_number = match self.direction {
+            BlocksRequestDirection::Ascending => self
+                .next_block_number
+                .saturating_add(u64::from(num_blocks.get())),
+            BlocksRequestDirection::Descending => self
+                .next_block_number
+                .saturating_sub(u64::from(num_blocks.get())),
+        };
+        self.next_block_hash = next_block_hash;
+
+        Ok(())
+    }
+
+    /// Returns the number of blocks that remain to be received.
+    pub fn remaining_blocks(&self) -> u64 {
+        self.remaining_blocks
+    }
+
+    /// Returns the number of blocks currently requested at once. Starts at
+    /// [`BlocksRequestPlannerConfig::max_blocks_per_request`] and is lowered every time a
+    /// response is truncated.
+    pub fn max_blocks_per_request(&self) -> NonZeroU32 {
+        self.max_blocks
+    }
+}
+
+/// Error returned by [`BlocksRequestPlanner::inject_response`].
+#[derive(Debug, Clone, derive_more::Display)]
+#[display(fmt = "Response doesn't contain any block")]
+pub struct EmptyResponseError;
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        BlocksRequestConfigStart, BlocksRequestDirection, BlocksRequestFields,
+        BlocksRequestPlanner, BlocksRequestPlannerConfig,
+    };
+    use core::num::{NonZeroU32, NonZeroU64};
+
+    fn fields() -> BlocksRequestFields {
+        BlocksRequestFields {
+            header: true,
+            body: false,
+            justifications: false,
+        }
+    }
+
+    #[test]
+    fn ascending_with_truncated_responses() {
+        let mut planner = BlocksRequestPlanner::new(BlocksRequestPlannerConfig {
+            first_block_number: 10,
+            first_block_hash: None,
+            num_blocks: NonZeroU64::new(300).unwrap(),
+            direction: BlocksRequestDirection::Ascending,
+            fields: fields(),
+            max_blocks_per_request: NonZeroU32::new(128).unwrap(),
+        });
+
+        let request = planner.next_request().unwrap();
+        assert_eq!(request.start, BlocksRequestConfigStart::Number(10));
+        assert_eq!(request.desired_count.get(), 128);
+
+        // Truncated response.
+        planner.inject_response(100, None).unwrap();
+        assert_eq!(planner.max_blocks_per_request().get(), 100);
+
+        let request = planner.next_request().unwrap();
+        assert_eq!(request.start, BlocksRequestConfigStart::Number(110));
+        assert_eq!(request.desired_count.get(), 100);
+        planner.inject_response(100, None).unwrap();
+
+        // Empty responses don't modify the state.
+        assert!(planner.inject_response(0, None).is_err());
+
+        let request = planner.next_request().unwrap();
+        assert_eq!(request.start, BlocksRequestConfigStart::Number(210));
+        assert_eq!(request.desired_count.get(), 100);
+        planner.inject_response(100, None).unwrap();
+
+        assert!(planner.next_request().is_none());
+        assert_eq!(planner.remaining_blocks(), 0);
+    }
+
+    #[test]
+    fn descending_stops_at_genesis() {
+        let mut planner = BlocksRequestPlanner::new(BlocksRequestPlannerConfig {
+            first_block_number: 150,
+            first_block_hash: Some([1; 32]),
+            num_blocks: NonZeroU64::new(1000).unwrap(),
+            direction: BlocksRequestDirection::Descending,
+            fields: fields(),
+            max_blocks_per_request: NonZeroU32::new(128).unwrap(),
+        });
+        assert_eq!(planner.remaining_blocks(), 151);
+
+        let request = planner.next_request().unwrap();
+        assert_eq!(request.start, BlocksRequestConfigStart::Hash([1; 32]));
+        assert_eq!(request.desired_count.get(), 128);
+        planner.inject_response(128, Some([2; 32])).unwrap();
+
+        let request = planner.next_request().unwrap();
+        assert_eq!(request.start, BlocksRequestConfigStart::Hash([2; 32]));
+        assert_eq!(request.desired_count.get(), 23);
+
+        // Without hash, the block number is used.
+        planner.inject_response(20, None).unwrap();
+        let request = planner.next_request().unwrap();
+        assert_eq!(request.start, BlocksRequestConfigStart::Number(2));
+        assert_eq!(request.desired_count.get(), 3);
+        planner.inject_response(3, None).unwrap();
+
+        assert!(planner.next_request().is_none());
+    }
+
+    #[test]
+    fn descending_with_truncated_responses() {
+        let mut planner = BlocksRequestPlanner::new(BlocksRequestPlannerConfig {
+            first_block_number: 1000,
+            first_block_hash: Some([1; 32]),
+            num_blocks: NonZeroU64::new(200).unwrap(),
+            direction: BlocksRequestDirection::Descending,
+            fields: fields(),
+            max_blocks_per_request: NonZeroU32::new(64).unwrap(),
+        });
+
+        // Truncated response.
+        planner.inject_response(50, Some([2; 32])).unwrap();
+        assert_eq!(planner.max_blocks_per_request().get(), 50);
+        let request = planner.next_request().unwrap();
+        assert_eq!(request.start, BlocksRequestConfigStart::Hash([2; 32]));
+        assert_eq!(request.desired_count.get(), 50);
+
+        // Truncated again.
+        planner.inject_response(30, Some([3; 32])).unwrap();
+        assert_eq!(planner.max_blocks_per_request().get(), 30);
+        assert_eq!(planner.remaining_blocks(), 120);
+
+        // A full response doesn't raise the limit back.
+        planner.inject_response(30, Some([4; 32])).unwrap();
+        assert_eq!(planner.max_blocks_per_request().get(), 30);
+        assert_eq!(planner.next_request().unwrap().desired_count.get(), 30);
+    }
+
+    #[test]
+    fn oversized_response_is_clamped() {
+        let mut planner = BlocksRequestPlanner::new(BlocksRequestPlannerConfig {
+            first_block_number: 0,
+            first_block_hash: None,
+            num_blocks: NonZeroU64::new(100).unwrap(),
+            direction: BlocksRequestDirection::Ascending,
+            fields: fields(),
+            max_blocks_per_request: NonZeroU32::new(64).unwrap(),
+        });
+
+        // The remote sends more blocks than requested. Only the requested ones are counted.
+        planner.inject_response(80, None).unwrap();
+        assert_eq!(planner.max_blocks_per_request().get(), 64);
+        assert_eq!(planner.remaining_blocks(), 36);
+
+        let request = planner.next_request().unwrap();
+        assert_eq!(request.start, BlocksRequestConfigStart::Number(64));
+        assert_eq!(request.desired_count.get(), 36);
+
+        // A response that covers the rest of the range isn't considered as truncated.
+        planner.inject_response(36, None).unwrap();
+        assert_eq!(planner.max_blocks_per_request().get(), 64);
+        assert!(planner.next_request().is_none());
+    }
+}
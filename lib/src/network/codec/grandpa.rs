@@ -41,14 +41,111 @@ impl<'a> GrandpaNotificationRef<'a> {
         &self,
         block_number_bytes: usize,
     ) -> impl Iterator<Item = impl AsRef<[u8]> + Clone> + Clone {
-        match self {
-            GrandpaNotificationRef::Neighbor(n) => iter::once(either::Left(&[2u8]))
-                .chain(n.scale_encoding(block_number_bytes).map(either::Right)),
-            _ => todo!(),
-        }
+        // Neighbor packets have a dedicated encoding function. All the other messages are
+        // encoded into a single buffer.
+        let out = match self {
+            GrandpaNotificationRef::Neighbor(n) => {
+                return either::Left(
+                    iter::once(either::Left(&[2u8]))
+                        .chain(n.scale_encoding(block_number_bytes).map(either::Right))
+                        .map(either::Left),
+                );
+            }
+            GrandpaNotificationRef::Vote(vote) => {
+                let mut out = Vec::with_capacity(1 + 8 + 8 + 1 + 32 + block_number_bytes + 96);
+                out.push(0);
+                out.extend_from_slice(&vote.round_number.to_le_bytes());
+                out.extend_from_slice(&vote.set_id.to_le_bytes());
+                let (message_ty, target_hash, target_number) = match &vote.message {
+                    MessageRef::Prevote(m) => (0, m.target_hash, m.target_number),
+                    MessageRef::Precommit(m) => (1, m.target_hash, m.target_number),
+                    MessageRef::PrimaryPropose(m) => (2, m.target_hash, m.target_number),
+                };
+                out.push(message_ty);
+                out.extend_from_slice(target_hash);
+                encode_block_number(&mut out, target_number, block_number_bytes);
+                out.extend_from_slice(vote.signature);
+                out.extend_from_slice(vote.authority_public_key);
+                out
+            }
+            GrandpaNotificationRef::Commit(commit) => {
+                let mut out = Vec::new();
+                out.push(1);
+                out.extend_from_slice(&commit.round_number.to_le_bytes());
+                out.extend_from_slice(&commit.set_id.to_le_bytes());
+                out.extend_from_slice(commit.message.target_hash);
+                encode_block_number(&mut out, commit.message.target_number, block_number_bytes);
+                out.extend_from_slice(
+                    crate::util::encode_scale_compact_usize(commit.message.precommits.len())
+                        .as_ref(),
+                );
+                for precommit in &commit.message.precommits {
+                    out.extend_from_slice(precommit.target_hash);
+                    encode_block_number(&mut out, precommit.target_number, block_number_bytes);
+                }
+                out.extend_from_slice(
+                    crate::util::encode_scale_compact_usize(commit.message.auth_data.len())
+                        .as_ref(),
+                );
+                for (signature, public_key) in &commit.message.auth_data {
+                    out.extend_from_slice(*signature);
+                    out.extend_from_slice(*public_key);
+                }
+                out
+            }
+            GrandpaNotificationRef::CatchUpRequest(request) => {
+                let mut out = Vec::with_capacity(1 + 8 + 8);
+                out.push(3);
+                out.extend_from_slice(&request.round_number.to_le_bytes());
+                out.extend_from_slice(&request.set_id.to_le_bytes());
+                out
+            }
+            GrandpaNotificationRef::CatchUp(catch_up) => {
+                let mut out = Vec::new();
+                out.push(4);
+                out.extend_from_slice(&catch_up.set_id.to_le_bytes());
+                out.extend_from_slice(&catch_up.round_number.to_le_bytes());
+                out.extend_from_slice(
+                    crate::util::encode_scale_compact_usize(catch_up.prevotes.len()).as_ref(),
+                );
+                for prevote in &catch_up.prevotes {
+                    out.extend_from_slice(prevote.target_hash);
+                    encode_block_number(&mut out, prevote.target_number, block_number_bytes);
+                    out.extend_from_slice(prevote.signature);
+                    out.extend_from_slice(prevote.authority_public_key);
+                }
+                out.extend_from_slice(
+                    crate::util::encode_scale_compact_usize(catch_up.precommits.len()).as_ref(),
+                );
+                for precommit in &catch_up.precommits {
+                    out.extend_from_slice(precommit.target_hash);
+                    encode_block_number(&mut out, precommit.target_number, block_number_bytes);
+                    out.extend_from_slice(precommit.signature);
+                    out.extend_from_slice(precommit.authority_public_key);
+                }
+                out.extend_from_slice(catch_up.base_hash);
+                encode_block_number(&mut out, catch_up.base_number, block_number_bytes);
+                out
+            }
+        };
+
+        either::Right(iter::once(either::Right(out)))
     }
 }
 
+/// Appends to `out` the given block number encoded with `block_number_bytes` bytes.
+fn encode_block_number(out: &mut Vec<u8>, block_number: u64, block_number_bytes: usize) {
+    let mut encoded = Vec::with_capacity(cmp::max(
+        block_number_bytes,
+        mem::size_of_val(&block_number),
+    ));
+    encoded.extend(block_number.to_le_bytes());
+    // TODO: unclear what to do if the block number doesn't fit in `block_number_bytes`
+    debug_assert!(!encoded.iter().skip(block_number_bytes).any(|b| *b != 0));
+    encoded.resize(block_number_bytes, 0);
+    out.extend_from_slice(&encoded);
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct VoteMessageRef<'a> {
     pub round_number: u64,
@@ -446,4 +543,70 @@ mod tests {
 
         assert_eq!(actual, expected);
     }
+
+    #[test]
+    fn encode_decode_vote() {
+        let vote = super::GrandpaNotificationRef::Vote(super::VoteMessageRef {
+            round_number: 12,
+            set_id: 3,
+            message: super::MessageRef::Precommit(super::UnsignedPrecommitRef {
+                target_hash: &[5; 32],
+                target_number: 1234,
+            }),
+            signature: &[6; 64],
+            authority_public_key: &[7; 32],
+        });
+
+        let encoded = vote.scale_encoding(4).fold(Vec::new(), |mut a, b| {
+            a.extend_from_slice(b.as_ref());
+            a
+        });
+        assert_eq!(
+            super::decode_grandpa_notification(&encoded, 4).unwrap(),
+            vote
+        );
+    }
+
+    #[test]
+    fn encode_decode_catch_up() {
+        let request = super::GrandpaNotificationRef::CatchUpRequest(super::CatchUpRequest {
+            round_number: 12,
+            set_id: 3,
+        });
+        let encoded = request.scale_encoding(4).fold(Vec::new(), |mut a, b| {
+            a.extend_from_slice(b.as_ref());
+            a
+        });
+        assert_eq!(
+            super::decode_grandpa_notification(&encoded, 4).unwrap(),
+            request
+        );
+
+        let catch_up = super::GrandpaNotificationRef::CatchUp(super::CatchUpRef {
+            set_id: 3,
+            round_number: 12,
+            prevotes: vec![super::PrevoteRef {
+                target_hash: &[1; 32],
+                target_number: 100,
+                signature: &[2; 64],
+                authority_public_key: &[3; 32],
+            }],
+            precommits: vec![crate::finality::justification::decode::PrecommitRef {
+                target_hash: &[1; 32],
+                target_number: 100,
+                signature: &[4; 64],
+                authority_public_key: &[3; 32],
+            }],
+            base_hash: &[8; 32],
+            base_number: 99,
+        });
+        let encoded = catch_up.scale_encoding(4).fold(Vec::new(), |mut a, b| {
+            a.extend_from_slice(b.as_ref());
+            a
+        });
+        assert_eq!(
+            super::decode_grandpa_notification(&encoded, 4).unwrap(),
+            catch_up
+        );
+    }
 }
@@ -0,0 +1,368 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! The identify protocol is a request-response protocol.
+//!
+//! The request's body is empty. Contrary to other request-response protocols, it doesn't even
+//! contain a message length. As soon as the protocol has been negotiated, the other side should
+//! send back the response.
+//!
+//! The response's body consists in various useful general-purpose information about the node.
+//! See [`IdentifyResponse`] for details.
+//!
+//! The two most important fields are [`IdentifyResponse::listen_addrs`] and
+//! [`IdentifyResponse::observed_addr`]. They are necessary in order for nodes to discover their
+//! public address, and in order to insert peers in the Kademlia k-buckets.
+//!
+//! See also [the official specification](https://github.com/libp2p/specs/tree/69e57d59dc5d59d3979d79842b577ec2c483f7fa/identify).
+
+use crate::{
+    libp2p::{
+        multiaddr::Multiaddr,
+        peer_id::{FromProtobufEncodingError, PublicKey},
+    },
+    util::protobuf,
+};
+
+use core::str;
+
+/// Description of a response to an identify request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IdentifyResponse<'a, TLaIter, TProtoIter> {
+    /// Name of the set of protocols supposed by the node.
+    pub protocol_version: &'a str,
+
+    /// Name and version of the software that responds. Similar to `User-Agent` in the HTTP
+    /// protocol. Used for debugging purposes.
+    pub agent_version: &'a str,
+
+    /// Ed25519 public key of the local node.
+    pub ed25519_public_key: [u8; 32],
+
+    /// List of multiaddresses the local node is listening on. This should include first and
+    /// foremost addresses that are publicly-reachable.
+    ///
+    /// > **Note**: Each item should be decoded into a multiaddr, but keep in mind that it might
+    /// >           not be valid.
+    pub listen_addrs: TLaIter,
+
+    /// Multiaddress of the sender of the identify request, as seen from the receiver.
+    ///
+    /// > **Note**: This should be decoded into a multiaddr, but keep in mind that it might not
+    /// >           be valid.
+    pub observed_addr: &'a [u8],
+
+    /// Names of the protocols supported by the local node.
+    pub protocols: TProtoIter,
+}
+
+// See https://github.com/libp2p/specs/tree/master/identify#the-identify-message for the protobuf
+// message format.
+
+/// Builds the bytes corresponding to a block request.
+pub fn build_identify_response<'a>(
+    config: IdentifyResponse<
+        'a,
+        impl Iterator<Item = &'a [u8]> + 'a,
+        impl Iterator<Item = &'a str> + 'a,
+    >,
+) -> impl Iterator<Item = impl AsRef<[u8]> + 'a> + 'a {
+    protobuf::string_tag_encode(5, config.protocol_version)
+        .map(either::Left)
+        .map(either::Left)
+        .map(either::Left)
+        .chain(
+            protobuf::string_tag_encode(6, config.agent_version)
+                .map(either::Right)
+                .map(either::Left)
+                .map(either::Left),
+        )
+        .chain(
+            protobuf::bytes_tag_encode(
+                1,
+                PublicKey::Ed25519(config.ed25519_public_key).to_protobuf_encoding(),
+            )
+            .map(either::Left)
+            .map(either::Right)
+            .map(either::Left),
+        )
+        .chain(
+            config
+                .listen_addrs
+                .flat_map(|addr| protobuf::bytes_tag_encode(2, addr))
+                .map(either::Right)
+                .map(either::Right)
+                .map(either::Left),
+        )
+        .chain(
+            protobuf::bytes_tag_encode(4, config.observed_addr)
+                .map(either::Left)
+                .map(either::Right),
+        )
+        .chain(
+            config
+                .protocols
+                .flat_map(|p| protobuf::string_tag_encode(3, p))
+                .map(either::Right)
+                .map(either::Right),
+        )
+}
+
+/// Decodes a response to an identify request.
+///
+/// The decoding doesn't perform any heap allocation. The list of listen addresses and of
+/// protocols are iterators that decode the items lazily from `response_bytes`. The entire
+/// message is verified ahead of time, meaning that iterating over these lists never fails.
+pub fn decode_identify_response(
+    response_bytes: &'_ [u8],
+) -> Result<IdentifyResponse<'_, ListenAddrsIter<'_>, ProtocolsIter<'_>>, DecodeIdentifyResponseError>
+{
+    // The repeated fields are deliberately not decoded here, as this would require allocating
+    // a `Vec`. They are instead verified below.
+    let mut parser = nom::combinator::all_consuming::<_, _, nom::error::Error<&[u8]>, _>(
+        nom::combinator::complete(protobuf::message_decode! {
+            #[optional] protocol_version = 5 => protobuf::string_tag_decode,
+            #[optional] agent_version = 6 => protobuf::string_tag_decode,
+            #[optional] ed25519_public_key = 1 => protobuf::bytes_tag_decode,
+            #[optional] observed_addr = 4 => protobuf::bytes_tag_decode,
+        }),
+    );
+
+    let decoded = match nom::Finish::finish(parser(response_bytes)) {
+        Ok((_, out)) => out,
+        Err(_) => return Err(DecodeIdentifyResponseError::ProtobufDecode),
+    };
+
+    let listen_addrs = ListenAddrsIter {
+        inner: RepeatedFieldIter {
+            remaining: response_bytes,
+            field_num: 2,
+        },
+    };
+    let protocols = ProtocolsIter {
+        inner: RepeatedFieldIter {
+            remaining: response_bytes,
+            field_num: 3,
+        },
+    };
+
+    // Make sure that the repeated fields have the proper wire type, that protocols are valid
+    // UTF-8, and that the number of items is bounded.
+    if !listen_addrs.inner.is_valid(MAX_REPEATED_ITEMS, |_| true)
+        || !protocols
+            .inner
+            .is_valid(MAX_REPEATED_ITEMS, |p| str::from_utf8(p).is_ok())
+    {
+        return Err(DecodeIdentifyResponseError::ProtobufDecode);
+    }
+
+    Ok(IdentifyResponse {
+        agent_version: decoded.agent_version.unwrap_or_default(),
+        protocol_version: decoded.protocol_version.unwrap_or_default(),
+        ed25519_public_key: match PublicKey::from_protobuf_encoding(
+            decoded.ed25519_public_key.unwrap_or_default(),
+        )
+        .map_err(DecodeIdentifyResponseError::InvalidPublicKey)?
+        {
+            PublicKey::Ed25519(key) => key,
+        },
+        listen_addrs,
+        observed_addr: decoded.observed_addr.unwrap_or_default(),
+        protocols,
+    })
+}
+
+impl<'a, TLaIter, TProtoIter> IdentifyResponse<'a, TLaIter, TProtoIter>
+where
+    TLaIter: Iterator<Item = &'a [u8]> + Clone,
+    TProtoIter: Iterator<Item = &'a str> + Clone,
+{
+    /// Returns `true` if the given protocol name is found in [`IdentifyResponse::protocols`].
+    ///
+    /// Can be used in order to avoid sending requests that the remote would refuse.
+    pub fn supports_protocol(&self, protocol_name: &str) -> bool {
+        self.protocols.clone().any(|p| p == protocol_name)
+    }
+
+    /// Returns the list of [`IdentifyResponse::listen_addrs`] decoded as multiaddresses. Items
+    /// that can't be decoded are silently skipped.
+    pub fn decoded_listen_addrs(&self) -> impl Iterator<Item = Multiaddr> + 'a
+    where
+        TLaIter: 'a,
+    {
+        self.listen_addrs
+            .clone()
+            .filter_map(|addr| Multiaddr::try_from(addr.to_vec()).ok())
+    }
+}
+
+/// Maximum number of items in each repeated field of an identify response.
+const MAX_REPEATED_ITEMS: usize = 1024;
+
+/// Iterator to the listen addresses of an identify response.
+///
+/// See [`decode_identify_response`] and [`IdentifyResponse::listen_addrs`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ListenAddrsIter<'a> {
+    inner: RepeatedFieldIter<'a>,
+}
+
+impl<'a> Iterator for ListenAddrsIter<'a> {
+    type Item = &'a [u8];
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next()
+    }
+}
+
+/// Iterator to the protocols of an identify response.
+///
+/// See [`decode_identify_response`] and [`IdentifyResponse::protocols`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProtocolsIter<'a> {
+    inner: RepeatedFieldIter<'a>,
+}
+
+impl<'a> Iterator for ProtocolsIter<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // The UTF-8-ness has been verified when decoding the response.
+        self.inner.next().map(|p| str::from_utf8(p).unwrap())
+    }
+}
+
+/// Iterator to the values of a Protobuf field of wire type "delimited" that is found multiple
+/// times in a message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct RepeatedFieldIter<'a> {
+    /// Encoded message that remains to be iterated over.
+    remaining: &'a [u8],
+    /// Number of the field whose values to yield.
+    field_num: u64,
+}
+
+impl<'a> RepeatedFieldIter<'a> {
+    /// Iterates over the entire message and returns `true` if all the fields have been
+    /// successfully decoded, if there's no more than `max_items` items, and if `validate`
+    /// returned `true` for all of them.
+    fn is_valid(&self, max_items: usize, validate: impl Fn(&[u8]) -> bool) -> bool {
+        let mut iter = self.clone();
+        let mut num_items = 0;
+        while !iter.remaining.is_empty() {
+            match iter.try_next() {
+                Ok(Some(item)) => {
+                    num_items += 1;
+                    if num_items > max_items || !validate(item) {
+                        return false;
+                    }
+                }
+                Ok(None) => {}
+                Err(()) => return false,
+            }
+        }
+        true
+    }
+
+    /// Decodes the next field of the message. Returns `Ok(None)` if it isn't a value of the
+    /// field of interest.
+    fn try_next(&mut self) -> Result<Option<&'a [u8]>, ()> {
+        let (_, (field_num, _)) =
+            protobuf::tag_decode::<nom::error::Error<&[u8]>>(self.remaining).map_err(|_| ())?;
+
+        if field_num == self.field_num {
+            let (rest, value) =
+                protobuf::bytes_tag_decode::<nom::error::Error<&[u8]>>(self.remaining)
+                    .map_err(|_| ())?;
+            self.remaining = rest;
+            Ok(Some(value))
+        } else {
+            let (rest, ()) =
+                protobuf::tag_value_skip_decode::<nom::error::Error<&[u8]>>(self.remaining)
+                    .map_err(|_| ())?;
+            self.remaining = rest;
+            Ok(None)
+        }
+    }
+}
+
+impl<'a> Iterator for RepeatedFieldIter<'a> {
+    type Item = &'a [u8];
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.remaining.is_empty() {
+            // The message has been verified when decoding the response.
+            if let Some(value) = self.try_next().unwrap() {
+                return Some(value);
+            }
+        }
+        None
+    }
+}
+
+/// Error potentially returned by [`decode_identify_response`].
+#[derive(Debug, derive_more::Display)]
+pub enum DecodeIdentifyResponseError {
+    /// Error while decoding the Protobuf encoding.
+    ProtobufDecode,
+    /// Couldn't decode the public key of the remote.
+    #[display(fmt = "Failed to decode remote public key: {_0}")]
+    InvalidPublicKey(FromProtobufEncodingError),
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn encode_decode_roundtrip() {
+        let listen_addr = "/ip4/1.2.3.4/tcp/30333"
+            .parse::<crate::libp2p::Multiaddr>()
+            .unwrap();
+
+        let encoded = super::build_identify_response(super::IdentifyResponse {
+            protocol_version: "/substrate/1.0",
+            agent_version: "smoldot",
+            ed25519_public_key: [5; 32],
+            listen_addrs: [listen_addr.as_ref(), &[0xff, 0xff][..]].into_iter(),
+            observed_addr: &[],
+            protocols: ["/ipfs/id/1.0.0", "/ipfs/ping/1.0.0"].into_iter(),
+        })
+        .fold(Vec::new(), |mut a, b| {
+            a.extend_from_slice(b.as_ref());
+            a
+        });
+
+        let decoded = super::decode_identify_response(&encoded).unwrap();
+        assert_eq!(decoded.agent_version, "smoldot");
+        assert_eq!(decoded.ed25519_public_key, [5; 32]);
+        assert_eq!(decoded.listen_addrs.clone().count(), 2);
+        assert_eq!(
+            decoded.decoded_listen_addrs().collect::<Vec<_>>(),
+            vec![listen_addr]
+        );
+        assert!(decoded.supports_protocol("/ipfs/ping/1.0.0"));
+        assert!(!decoded.supports_protocol("/ipfs/kad/1.0.0"));
+    }
+
+    #[test]
+    fn invalid_utf8_protocol() {
+        // Field 3 (protocols) containing an invalid UTF-8 string.
+        assert!(matches!(
+            super::decode_identify_response(&[0x1a, 0x01, 0xff]),
+            Err(super::DecodeIdentifyResponseError::ProtobufDecode)
+        ));
+    }
+}
@@ -0,0 +1,238 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::{
+    libp2p::{multiaddr, peer_id},
+    util::protobuf,
+};
+
+use alloc::vec::Vec;
+
+// See https://github.com/libp2p/specs/tree/master/kad-dht#rpc-messages for the protobuf format.
+
+/// Builds a wire message to send on the Kademlia request-response protocol to ask the target to
+/// return the nodes closest to the parameter.
+// TODO: parameter type?
+pub fn build_find_node_request(peer_id: &[u8]) -> Vec<u8> {
+    // The capacity is arbitrary but large enough to avoid Vec reallocations.
+    let mut out = Vec::with_capacity(64 + peer_id.len());
+    for slice in protobuf::enum_tag_encode(1, 4) {
+        out.extend_from_slice(slice.as_ref());
+    }
+    for slice in protobuf::bytes_tag_encode(2, peer_id) {
+        out.extend_from_slice(slice.as_ref());
+    }
+    out
+}
+
+/// Decodes a request built using [`build_find_node_request`].
+///
+/// Returns the key whose closest peers are requested. This is normally a [`peer_id::PeerId`]
+/// encoded as bytes, but the Kademlia protocol allows arbitrary keys.
+pub fn decode_find_node_request(request_bytes: &[u8]) -> Result<&[u8], DecodeFindNodeRequestError> {
+    let mut parser = nom::combinator::all_consuming::<_, _, nom::error::Error<&[u8]>, _>(
+        nom::combinator::complete(protobuf::message_decode! {
+            #[optional] request_ty = 1 => protobuf::enum_tag_decode,
+            #[required] key = 2 => protobuf::bytes_tag_decode,
+        }),
+    );
+
+    match nom::Finish::finish(parser(request_bytes)) {
+        Ok((_, out)) if out.request_ty.unwrap_or(0) == 4 => Ok(out.key),
+        Ok((_, _)) => Err(DecodeFindNodeRequestError::BadRequestTy),
+        Err(_) => Err(DecodeFindNodeRequestError::ProtobufDecode(
+            ProtobufDecodeError,
+        )),
+    }
+}
+
+/// Builds a wire message to send back as a response to a request decoded with
+/// [`decode_find_node_request`].
+///
+/// Each item of `closer_peers` must contain the [`peer_id::PeerId`] encoded as bytes, the
+/// multiaddresses of the peer, and optionally the signed envelope containing the peer record
+/// of this peer.
+///
+/// The signed envelope is encoded in field number 4 of the `Peer` message. This field isn't
+/// part of the original Kademlia `Peer` message, and is thus ignored by implementations that
+/// don't support it.
+pub fn build_find_node_response<'a>(
+    closer_peers: impl Iterator<
+            Item = (
+                &'a [u8],
+                impl Iterator<Item = &'a [u8]> + 'a,
+                Option<&'a [u8]>,
+            ),
+        > + 'a,
+) -> Vec<u8> {
+    // The capacity is arbitrary but large enough to avoid Vec reallocations in most situations.
+    let mut out = Vec::with_capacity(1024);
+    for slice in protobuf::enum_tag_encode(1, 4) {
+        out.extend_from_slice(slice.as_ref());
+    }
+    for (peer_id, addrs, signed_envelope) in closer_peers {
+        let peer = protobuf::bytes_tag_encode(1, peer_id)
+            .map(either::Left)
+            .chain(addrs.flat_map(|addr| protobuf::bytes_tag_encode(2, addr).map(either::Right)))
+            .chain(
+                signed_envelope
+                    .into_iter()
+                    .flat_map(|envelope| protobuf::bytes_tag_encode(4, envelope))
+                    .map(either::Left),
+            );
+        for slice in protobuf::message_tag_encode(8, peer) {
+            out.extend_from_slice(slice.as_ref());
+        }
+    }
+    out
+}
+
+/// Decodes a response to a request built using [`build_find_node_request`].
+// TODO: return a borrow of the response bytes ; we're limited by protobuf library
+pub fn decode_find_node_response(
+    response_bytes: &[u8],
+) -> Result<Vec<(peer_id::PeerId, Vec<Vec<u8>>)>, DecodeFindNodeResponseError> {
+    let mut parser = nom::combinator::all_consuming::<_, _, nom::error::Error<&[u8]>, _>(
+        nom::combinator::complete(protobuf::message_decode! {
+            #[optional] response_ty = 1 => protobuf::enum_tag_decode,
+            #[repeated(max = 1024)] peers = 8 => protobuf::message_tag_decode(protobuf::message_decode!{
+                #[required] peer_id = 1 => protobuf::bytes_tag_decode,
+                #[repeated(max = 1024)] addrs = 2 => protobuf::bytes_tag_decode,
+            }),
+        }),
+    );
+
+    let closer_peers = match nom::Finish::finish(parser(response_bytes)) {
+        Ok((_, out)) if out.response_ty.unwrap_or(0) == 4 => out.peers,
+        Ok((_, _)) => return Err(DecodeFindNodeResponseError::BadResponseTy),
+        Err(_) => {
+            return Err(DecodeFindNodeResponseError::ProtobufDecode(
+                ProtobufDecodeError,
+            ))
+        }
+    };
+
+    let mut result = Vec::with_capacity(closer_peers.len());
+    for peer in closer_peers {
+        let peer_id = peer_id::PeerId::from_bytes(peer.peer_id.to_vec())
+            .map_err(|(err, _)| DecodeFindNodeResponseError::BadPeerId(err))?;
+
+        let mut multiaddrs = Vec::with_capacity(peer.addrs.len());
+        for addr in peer.addrs {
+            multiaddrs.push(addr.to_vec());
+        }
+
+        result.push((peer_id, multiaddrs));
+    }
+
+    Ok(result)
+}
+
+/// Error potentially returned by [`decode_find_node_response`].
+#[derive(Debug, derive_more::Display)]
+pub enum DecodeFindNodeResponseError {
+    /// Error while decoding the Protobuf encoding.
+    #[display(fmt = "Error decoding the response: {_0}")]
+    ProtobufDecode(ProtobufDecodeError),
+    /// Response isn't a response to a find node request.
+    BadResponseTy,
+    /// Error while parsing a [`peer_id::PeerId`] in the response.
+    #[display(fmt = "Invalid PeerId: {_0}")]
+    BadPeerId(peer_id::FromBytesError),
+    /// Error while parsing a [`multiaddr::Multiaddr`] in the response.
+    #[display(fmt = "Invalid multiaddress: {_0}")]
+    BadMultiaddr(multiaddr::FromVecError),
+}
+
+/// Error potentially returned by [`decode_find_node_request`].
+#[derive(Debug, derive_more::Display)]
+pub enum DecodeFindNodeRequestError {
+    /// Error while decoding the Protobuf encoding.
+    #[display(fmt = "Error decoding the request: {_0}")]
+    ProtobufDecode(ProtobufDecodeError),
+    /// Request isn't a find node request.
+    BadRequestTy,
+}
+
+/// Error while decoding the Protobuf encoding.
+#[derive(Debug, derive_more::Display)]
+pub struct ProtobufDecodeError;
+
+#[cfg(test)]
+mod tests {
+    use crate::libp2p::{
+        multiaddr::Multiaddr,
+        peer_id::{PeerId, PublicKey},
+    };
+
+    #[test]
+    fn find_node_request_and_response_round_trip() {
+        let target = PeerId::from_public_key(&PublicKey::Ed25519([1; 32]));
+        let request = super::build_find_node_request(target.as_bytes());
+        assert_eq!(
+            super::decode_find_node_request(&request).unwrap(),
+            target.as_bytes()
+        );
+
+        let peer = PeerId::from_public_key(&PublicKey::Ed25519([2; 32]));
+        let addrs = ["/dns/example.com/tcp/30333", "/ip4/1.2.3.4/tcp/30333"]
+            .into_iter()
+            .map(|addr| addr.parse::<Multiaddr>().unwrap().to_vec())
+            .collect::<Vec<_>>();
+        let response = super::build_find_node_response(
+            [(peer.as_bytes(), addrs.iter().map(|a| &a[..]), None)].into_iter(),
+        );
+        let decoded = super::decode_find_node_response(&response).unwrap();
+        assert_eq!(decoded.len(), 1);
+        assert_eq!(decoded[0].0, peer);
+        assert_eq!(decoded[0].1, addrs);
+    }
+
+    #[test]
+    fn find_node_response_signed_envelope() {
+        let peer = PeerId::from_public_key(&PublicKey::Ed25519([2; 32]));
+        let addrs = ["/ip4/1.2.3.4/tcp/30333"
+            .parse::<Multiaddr>()
+            .unwrap()
+            .to_vec()];
+        let envelope = [0xde, 0xad, 0xbe, 0xef];
+
+        let without_envelope = super::build_find_node_response(
+            [(peer.as_bytes(), addrs.iter().map(|a| &a[..]), None)].into_iter(),
+        );
+        let with_envelope = super::build_find_node_response(
+            [(
+                peer.as_bytes(),
+                addrs.iter().map(|a| &a[..]),
+                Some(&envelope[..]),
+            )]
+            .into_iter(),
+        );
+
+        // The envelope is encoded as field 4 of the `Peer` message.
+        assert!(with_envelope
+            .windows(6)
+            .any(|w| w == [0x22, 4, 0xde, 0xad, 0xbe, 0xef]));
+        assert!(!without_envelope.windows(4).any(|w| w == envelope));
+
+        // Decoders that don't know about the envelope ignore it.
+        let decoded = super::decode_find_node_response(&with_envelope).unwrap();
+        assert_eq!(decoded.len(), 1);
+        assert_eq!(decoded[0].0, peer);
+        assert_eq!(decoded[0].1, addrs);
+    }
+}
@@ -0,0 +1,76 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use alloc::vec::Vec;
+use nom::Finish as _;
+
+/// Decodes a notification received on the transactions protocol.
+///
+/// A transactions notification consists of a SCALE-encoded list of SCALE-encoded transactions.
+/// Each item of the returned list is a SCALE-encoded transaction, including its length prefix.
+pub fn decode_transactions_notification(
+    scale_encoded: &[u8],
+) -> Result<Vec<&[u8]>, DecodeTransactionsNotificationError> {
+    let result: Result<_, nom::error::Error<&[u8]>> =
+        nom::combinator::all_consuming(nom::combinator::complete(nom::combinator::flat_map(
+            crate::util::nom_scale_compact_usize,
+            |num_elems| {
+                nom::multi::many_m_n(
+                    num_elems,
+                    num_elems,
+                    nom::combinator::recognize(crate::util::nom_bytes_decode),
+                )
+            },
+        )))(scale_encoded)
+        .finish();
+
+    match result {
+        Ok((_, transactions)) => Ok(transactions),
+        Err(err) => Err(DecodeTransactionsNotificationError(err.code)),
+    }
+}
+
+/// Error potentially returned by [`decode_transactions_notification`].
+#[derive(Debug, derive_more::Display)]
+#[display(fmt = "Failed to decode a transactions notification")]
+pub struct DecodeTransactionsNotificationError(nom::error::ErrorKind);
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn decode_transactions() {
+        let decoded = super::decode_transactions_notification(&[8, 8, 1, 2, 4, 3]).unwrap();
+        assert_eq!(decoded, vec![&[8, 1, 2][..], &[4, 3][..]]);
+    }
+
+    #[test]
+    fn decode_empty() {
+        assert!(super::decode_transactions_notification(&[0])
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn decode_trailing_data() {
+        assert!(super::decode_transactions_notification(&[4, 4, 1, 0]).is_err());
+    }
+
+    #[test]
+    fn decode_truncated() {
+        assert!(super::decode_transactions_notification(&[8, 4, 1]).is_err());
+    }
+}
@@ -76,15 +76,23 @@
 
 // TODO: expand explanations once the API is finalized
 
+use crate::header;
 use crate::libp2p::collection;
-use crate::network::protocol;
+use crate::libp2p::connection::established;
+use crate::network::codec;
 use crate::util::{self, SipHasherBuild};
 
-use alloc::{borrow::ToOwned as _, collections::BTreeSet, string::String, vec::Vec};
+use alloc::{
+    borrow::ToOwned as _,
+    collections::{BTreeMap, BTreeSet},
+    string::String,
+    vec::Vec,
+};
 use core::{
-    fmt,
+    cmp, fmt,
     hash::Hash,
-    iter, mem,
+    mem,
+    num::{NonZeroU32, NonZeroUsize},
     ops::{Add, Sub},
     time::Duration,
 };
@@ -92,16 +100,18 @@ use rand_chacha::rand_core::{RngCore as _, SeedableRng as _};
 
 pub use crate::libp2p::{
     collection::{
-        ConnectionId, ConnectionToCoordinator, CoordinatorToConnection, InboundError,
-        MultiStreamConnectionTask, NotificationsOutErr, ReadWrite, RequestError,
-        SingleStreamConnectionTask, SubstreamId,
+        ConnectionId, ConnectionToCoordinator, CoordinatorToConnection, HandshakeError,
+        HandshakeTimeoutStage, InboundError, MultiStreamConnectionTask, NotificationsOutErr,
+        ReadWrite, RequestError, SingleStreamConnectionTask, SubstreamId,
     },
     connection::noise::{self, NoiseKey},
     multiaddr::{self, Multiaddr},
     peer_id::{self, PeerId},
 };
 
-pub use crate::network::protocol::{BlockAnnouncesHandshakeDecodeError, Role};
+pub use crate::network::codec::{BlockAnnouncesHandshakeDecodeError, Role};
+
+mod tests;
 
 /// Configuration for a [`ChainNetwork`].
 pub struct Config {
@@ -117,8 +127,22 @@ pub struct Config {
     /// notably isn't used when generating the ephemeral key used for the Diffie-Hellman
     /// handshake.
     /// This is a defensive measure against users passing a dummy seed instead of actual entropy.
+    ///
+    /// The order in which the [`ChainNetwork`] iterates over its internal containers, and thus
+    /// the order in which events are generated and peers are returned, is entirely determined by
+    /// this seed and by the sequence of calls made on the [`ChainNetwork`]. See also
+    /// [`Config::deterministic_ordering`].
     pub randomness_seed: [u8; 32],
 
+    /// If `true`, the order in which the [`ChainNetwork`] iterates over its internal containers
+    /// no longer depends on [`Config::randomness_seed`], but only on the sequence of calls made
+    /// on the [`ChainNetwork`]. This makes it possible for test suites to assert exact sequences
+    /// of events while still using a random seed.
+    ///
+    /// This flag must be `false` outside of tests, as the internal containers would otherwise be
+    /// vulnerable to HashDoS attacks.
+    pub deterministic_ordering: bool,
+
     /// Key used for the encryption layer.
     /// This is a Noise static key, according to the Noise specification.
     /// Signed using the actual libp2p key.
@@ -127,6 +151,96 @@ pub struct Config {
     /// Amount of time after which a connection hathat ndshake is considered to have taken too long
     /// and must be aborted.
     pub handshake_timeout: Duration,
+
+    /// If `Some`, gossip links whose opening fails are automatically re-opened after a delay.
+    /// See [`ChainNetwork::gossip_open_retry`].
+    ///
+    /// If `None`, every failure is reported with a [`Event::GossipOpenFailed`] and it is the
+    /// responsibility of the API user to call [`ChainNetwork::gossip_open`] again.
+    pub gossip_open_retry: Option<GossipOpenRetryConfig>,
+
+    /// If `Some`, peers whose reputation falls to or below a threshold are automatically banned.
+    /// See [`ChainNetwork::peer_reputation`].
+    ///
+    /// If `None`, the reputation of peers is still tracked, but peers are only banned when
+    /// [`ChainNetwork::ban_peer`] is called.
+    pub reputation_ban: Option<ReputationBanConfig>,
+
+    /// If `Some`, request-response protocols that a remote refuses to negotiate aren't
+    /// attempted again on the same connection for the given duration. Starting a request on such
+    /// a protocol instead immediately fails with [`StartRequestError::ProtocolNotAvailable`],
+    /// unless another connection with the same peer can be used.
+    ///
+    /// This avoids wasting round trips when talking to nodes that don't support some protocols,
+    /// such as the light client or warp sync protocols.
+    ///
+    /// If `None`, protocols are always attempted.
+    pub refused_protocols_cooldown: Option<Duration>,
+
+    /// Interval between two consecutive outgoing pings on each connection.
+    pub ping_interval: Duration,
+
+    /// Amount of time after which an outgoing ping that hasn't been answered is considered as
+    /// failed.
+    pub ping_timeout: Duration,
+
+    /// Number of consecutive ping failures after which a connection is shut down.
+    pub max_ping_failures: NonZeroU32,
+
+    /// If `Some`, maximum number of healthy connections that can exist with the same peer.
+    ///
+    /// Connections whose handshake finishes while this limit is already reached are
+    /// automatically shut down and reported with a [`Event::SupernumeraryConnection`] instead
+    /// of a [`Event::HandshakeFinished`]. As an exception, and so that both sides of a
+    /// simultaneous dial keep the same connection, a connection dialed by the peer with the
+    /// lowest [`PeerId`] instead replaces an existing connection dialed by the other peer.
+    ///
+    /// If `None`, the number of connections per peer isn't limited.
+    pub max_connections_per_peer: Option<NonZeroUsize>,
+
+    /// Maximum number of substreams that each remote can have simultaneously opened on a
+    /// connection.
+    ///
+    /// If `None`, the limit is automatically computed from the number of chains and of custom
+    /// protocols, and adjusted whenever a chain is added. See
+    /// [`ChainNetwork::max_inbound_substreams`].
+    ///
+    /// > **Note**: This limit is necessary in order to avoid DoS attacks where a remote opens too
+    /// >           many substreams.
+    pub max_inbound_substreams: Option<usize>,
+
+    /// Maximum number of bytes of notifications that can be queued in each outbound
+    /// notifications substream before [`QueueNotificationError::QueueFull`] is returned.
+    pub max_queued_notifications_bytes: usize,
+
+    /// Policy applied to the listen addresses that remotes report in their identify responses.
+    /// Addresses refused by [`multiaddr::sanitize`] are removed from
+    /// [`IdentifyInfo::listen_addrs`].
+    pub address_sanitize_policy: multiaddr::SanitizePolicy,
+}
+
+/// Configuration for the automatic re-opening of gossip links. See [`Config::gossip_open_retry`].
+#[derive(Debug, Clone)]
+pub struct GossipOpenRetryConfig {
+    /// Delay before the first re-opening attempt. Doubles after each consecutive failure.
+    pub initial_backoff: Duration,
+
+    /// Maximum delay between two re-opening attempts.
+    pub max_backoff: Duration,
+
+    /// Number of consecutive failures, including the initial opening, after which the
+    /// [`ChainNetwork`] gives up and generates a [`Event::GossipOpenFailed`].
+    pub max_attempts: NonZeroU32,
+}
+
+/// Configuration for the automatic banning of peers. See [`Config::reputation_ban`].
+#[derive(Debug, Clone)]
+pub struct ReputationBanConfig {
+    /// Reputation at or below which a peer is banned. Must be negative.
+    pub threshold: i32,
+
+    /// Duration of the bans. Once a ban expires, the reputation of the peer is reset to 0.
+    pub duration: Duration,
 }
 
 /// Configuration for a specific overlay network.
@@ -152,6 +266,25 @@ pub struct ChainConfig {
     /// `true` if incoming block requests are allowed.
     pub allow_inbound_block_requests: bool,
 
+    /// Additional request-response protocols to support on this chain, on top of the standard
+    /// ones.
+    ///
+    /// Requests can be sent with [`ChainNetwork::start_custom_request`], and are reported with
+    /// [`Event::CustomRequestIn`]. Protocols are designated by their index within this list.
+    ///
+    /// More protocols can be added later with
+    /// [`ChainNetwork::add_custom_request_response_protocol`].
+    pub custom_request_response_protocols: Vec<CustomRequestResponseProtocolConfig>,
+
+    /// Additional notifications protocols to support on this chain, on top of the standard
+    /// ones. Unlike the standard notifications protocols, the substreams of these protocols
+    /// aren't tied to the gossip links.
+    ///
+    /// Substreams can be opened with [`ChainNetwork::custom_notifications_open`], and are
+    /// reported with [`Event::CustomNotificationsInOpen`]. Protocols are designated by their
+    /// index within this list.
+    pub custom_notifications_protocols: Vec<CustomNotificationsProtocolConfig>,
+
     /// Hash of the best block according to the local node.
     pub best_hash: [u8; 32],
     /// Height of the best block according to the local node.
@@ -160,6 +293,103 @@ pub struct ChainConfig {
     /// Role of the local node. Sent to the remote nodes and used as a hint. Has no incidence
     /// on the behavior of any function.
     pub role: Role,
+
+    /// If `Some`, the gossip links of this chain are automatically managed according to a
+    /// number of slots. See [`GossipSlotsConfig`].
+    ///
+    /// If `None`, every inbound gossip link is reported with a [`Event::GossipInDesired`] and
+    /// it is the responsibility of the API user to decide which peers to gossip with.
+    pub gossip_slots: Option<GossipSlotsConfig>,
+
+    /// Amount of time after which the opening of an outbound block announces, transactions or
+    /// GrandPa substream is considered as failed if the remote hasn't answered.
+    pub notifications_open_timeout: Duration,
+
+    /// Maximum size, in bytes, of the handshake that remotes can send on the block announces
+    /// substreams, and in response to the opening of a transactions or GrandPa substream.
+    pub max_notifications_handshake_size: usize,
+
+    /// Maximum size, in bytes, of a notification that remotes can send on the inbound
+    /// transactions and GrandPa substreams.
+    pub max_notification_size: usize,
+}
+
+/// Configuration for the automatic management of the gossip links of a chain. See
+/// [`ChainConfig::gossip_slots`].
+///
+/// Inbound gossip links are automatically accepted or rejected, and no [`Event::GossipInDesired`]
+/// is generated for this chain. A [`Event::GossipConnected`] is generated for the links that
+/// have been accepted.
+///
+/// Outbound gossip links are assigned with [`ChainNetwork::gossip_assign_out_slot`].
+#[derive(Debug, Clone)]
+pub struct GossipSlotsConfig {
+    /// Maximum number of gossip links opened by remotes that aren't desired by the local node.
+    /// Reserved peers don't count towards this limit.
+    pub in_slots: usize,
+
+    /// Maximum number of peers that can be assigned an outbound slot with
+    /// [`ChainNetwork::gossip_assign_out_slot`]. Reserved peers don't count towards this limit.
+    pub out_slots: usize,
+
+    /// Peers that are always marked as desired, and whose inbound gossip links are always
+    /// accepted. See also [`ChainNetwork::gossip_add_reserved`].
+    pub reserved_peers: Vec<PeerId>,
+
+    /// If `true`, only reserved peers are allowed to gossip with the local node. See also
+    /// [`ChainNetwork::gossip_set_reserved_only`].
+    pub reserved_only: bool,
+}
+
+/// Configuration for a request-response protocol. See
+/// [`ChainConfig::custom_request_response_protocols`].
+#[derive(Debug, Clone)]
+pub struct CustomRequestResponseProtocolConfig {
+    /// Name of the protocol, without the chain-specific prefix. Similarly to the standard
+    /// protocols, the full name of the protocol is `/<genesis_hash>/<name>`, or
+    /// `/<genesis_hash>/<fork_id>/<name>` if the chain has a fork ID, where `<genesis_hash>` is
+    /// the hexadecimal genesis hash of the chain.
+    ///
+    /// For example, `das/1`.
+    pub name: String,
+
+    /// Maximum size, in bytes, of the requests, both inbound and outbound.
+    pub max_request_size: usize,
+
+    /// Maximum size, in bytes, of the responses to outbound requests. Responses that exceed
+    /// this size are treated as a failure of the request.
+    pub max_response_size: usize,
+
+    /// `true` if incoming requests are allowed. If `false`, substreams opened by remotes on
+    /// this protocol are refused.
+    pub allow_inbound_requests: bool,
+
+    /// If `true`, incoming requests are reported with [`Event::CustomRequestInStart`] as soon
+    /// as their length is known, followed with their payload in [`Event::CustomRequestInChunk`]s,
+    /// rather than buffered entirely and reported with an [`Event::CustomRequestIn`]. This is
+    /// suitable for protocols whose [`CustomRequestResponseProtocolConfig::max_request_size`] is
+    /// large.
+    pub stream_inbound_requests: bool,
+}
+
+/// Configuration for a notifications protocol. See
+/// [`ChainConfig::custom_notifications_protocols`].
+#[derive(Debug, Clone)]
+pub struct CustomNotificationsProtocolConfig {
+    /// Name of the protocol, without the chain-specific prefix. See
+    /// [`CustomRequestResponseProtocolConfig::name`].
+    pub name: String,
+
+    /// Maximum size, in bytes, of the handshake sent by remotes, both on inbound and outbound
+    /// substreams.
+    pub max_handshake_size: usize,
+
+    /// Maximum size, in bytes, of the notifications received on inbound substreams.
+    pub max_notification_size: usize,
+
+    /// `true` if remotes are allowed to open substreams. If `false`, substreams opened by
+    /// remotes on this protocol are refused.
+    pub allow_inbound_substreams: bool,
 }
 
 /// Identifier of a chain added through [`ChainNetwork::add_chain`].
@@ -184,20 +414,22 @@ pub struct ChainNetwork<TNow> {
     /// Connections indexed by the value in [`ConnectionInfo::peer_id`].
     connections_by_peer_id: BTreeSet<(PeerId, collection::ConnectionId)>,
 
-    /// All the outbound notification substreams, indexed by protocol, `PeerId`, and state.
-    // TODO: unclear whether PeerId should come before or after the state, same for direction/state
-    notification_substreams_by_peer_id: BTreeSet<(
-        NotificationsProtocol,
-        PeerId,
-        SubstreamDirection,
-        NotificationsSubstreamState,
-        collection::SubstreamId,
-    )>,
+    /// For each outbound notification substream, number of notifications that couldn't be
+    /// queued because the queue of the substream was full and that haven't been reported yet
+    /// through an [`Event::NotificationsDropped`]. Entries are removed when the substream is
+    /// closed.
+    notifications_dropped:
+        hashbrown::HashMap<collection::SubstreamId, DroppedNotifications, fnv::FnvBuildHasher>,
 
-    /// See [`Config::noise_key`].
-    // TODO: make rotatable, see <https://github.com/smol-dot/smoldot/issues/44>
+    /// See [`Config::noise_key`]. Can be modified with [`ChainNetwork::set_noise_key`].
     noise_key: NoiseKey,
 
+    /// List of addresses that the local node is reachable on, as multiaddresses. Reported to
+    /// remotes in identify responses.
+    ///
+    /// See [`ChainNetwork::add_local_address`] and [`ChainNetwork::remove_local_address`].
+    local_addresses: Vec<Vec<u8>>,
+
     /// Chains indexed by genesis hash and fork ID.
     ///
     /// Contains the same number of entries as [`ChainNetwork::chains`]. The values are `usize`s
@@ -206,6 +438,11 @@ pub struct ChainNetwork<TNow> {
     chains_by_protocol_info:
         hashbrown::HashMap<([u8; 32], Option<String>), usize, fnv::FnvBuildHasher>,
 
+    /// Full names of all the protocols of [`ChainConfig::custom_request_response_protocols`] and
+    /// [`ChainConfig::custom_notifications_protocols`], with the corresponding
+    /// [`Protocol::Custom`] or [`Protocol::CustomNotifications`].
+    custom_protocols_by_name: hashbrown::HashMap<String, Protocol, fnv::FnvBuildHasher>,
+
     /// List of peers that have been marked as desired. Can include peers not connected to the
     /// local node yet.
     gossip_desired_peers_by_chain: BTreeSet<(usize, GossipKind, PeerId)>,
@@ -229,8 +466,123 @@ pub struct ChainNetwork<TNow> {
     // TODO: shrink to fit from time to time
     opened_gossip_undesired:
         hashbrown::HashSet<(ChainId, PeerId, GossipKind), util::SipHasherBuild>,
+
+    /// State of the gossip links, indexed by chain index and peer.
+    ///
+    /// An entry is inserted when [`ChainNetwork::gossip_open`] is called or when a block
+    /// announces, transactions, or GrandPa substream is opened, and removed once the peer is no
+    /// longer desired and no such substream exists anymore, in either direction. Consequently,
+    /// an entry always exists for peers with a gossip substream. See also
+    /// [`ChainNetwork::debug_check_gossip_link`].
+    // TODO: shrink to fit from time to time
+    gossip_links: hashbrown::HashMap<(usize, PeerId), GossipLink, util::SipHasherBuild>,
+
+    /// See [`Config::gossip_open_retry`].
+    gossip_open_retry: Option<GossipOpenRetryConfig>,
+
+    /// Gossip links, indexed by chain index and peer, whose opening has failed and that must
+    /// be automatically re-opened. Contains the moment when to re-open them, or `None` if
+    /// [`ChainNetwork::gossip_open_retry`] hasn't been called yet since the failure.
+    ///
+    /// Always empty if [`ChainNetwork::gossip_open_retry`] is `None`.
+    gossip_open_retries: BTreeMap<(usize, PeerId), Option<TNow>>,
+
+    /// Inbound requests that have been reported to the API user through an event and that
+    /// haven't been answered or cancelled yet, with the moment when they have been reported.
+    /// Used in order to measure the time the API user takes to answer.
+    // TODO: shrink to fit from time to time
+    inbound_requests_received: hashbrown::HashMap<SubstreamId, TNow, fnv::FnvBuildHasher>,
+
+    /// Statistics about the inbound identify requests.
+    inbound_identify_requests_stats: InboundRequestsStats,
+
+    /// Moment when the current period of [`INBOUND_PROTOCOL_ERRORS_REPORTS_PERIOD`] has started,
+    /// and number of [`Event::InboundProtocolError`] generated during this period. `None` if no
+    /// such event has been generated yet.
+    inbound_protocol_errors_reports: Option<(TNow, u32)>,
+
+    /// Number of connections whose handshake has finished. See [`ChainNetwork::metrics`].
+    connections_opened: u64,
+
+    /// Number of connections that have been closed after their handshake had finished. See
+    /// [`ChainNetwork::metrics`].
+    connections_closed: u64,
+
+    /// Counters about the activity of each protocol. See [`ChainNetwork::metrics`].
+    protocols_metrics: hashbrown::HashMap<Protocol, ProtocolMetrics, fnv::FnvBuildHasher>,
+
+    /// Reputation of the peers whose reputation isn't 0 or that are banned.
+    /// See [`ChainNetwork::peer_reputation`].
+    // TODO: shrink to fit from time to time
+    peers_reputation: hashbrown::HashMap<PeerId, PeerReputation<TNow>, util::SipHasherBuild>,
+
+    /// Same entries as the ones in [`ChainNetwork::peers_reputation`] whose
+    /// [`PeerReputation::banned_until`] is `Some`, indexed by the moment when the ban expires.
+    peers_bans_expiration: BTreeSet<(TNow, PeerId)>,
+
+    /// See [`Config::reputation_ban`].
+    reputation_ban: Option<ReputationBanConfig>,
+
+    /// For each connection, names of the request-response protocols that the remote has refused
+    /// to negotiate, with the moment when they can be attempted again.
+    /// See [`Config::refused_protocols_cooldown`].
+    refused_protocols: BTreeMap<(collection::ConnectionId, String), TNow>,
+
+    /// Same entries as the ones in [`ChainNetwork::refused_protocols`], indexed by the moment
+    /// when the protocol can be attempted again.
+    refused_protocols_expiration: BTreeSet<(TNow, collection::ConnectionId, String)>,
+
+    /// See [`Config::refused_protocols_cooldown`].
+    refused_protocols_cooldown: Option<Duration>,
+
+    /// See [`Config::max_ping_failures`].
+    max_ping_failures: NonZeroU32,
+
+    /// See [`Config::max_connections_per_peer`].
+    max_connections_per_peer: Option<NonZeroUsize>,
+
+    /// See [`Config::address_sanitize_policy`].
+    address_sanitize_policy: multiaddr::SanitizePolicy,
+
+    /// `true` if [`Config::max_inbound_substreams`] was `None`, in which case the limit is
+    /// adjusted whenever a chain is added.
+    max_inbound_substreams_automatic: bool,
+}
+
+/// See [`ChainNetwork::peers_reputation`].
+struct PeerReputation<TNow> {
+    /// See [`ChainNetwork::peer_reputation`].
+    score: i32,
+    /// If `Some`, the peer is banned until the given moment.
+    banned_until: Option<TNow>,
 }
 
+/// Reputation change applied to a peer when it violates a networking protocol, for example by
+/// sending an invalid request or notification.
+const PROTOCOL_ERROR_REPUTATION_CHANGE: i32 = -25;
+
+/// Reputation change applied to a peer when a request sent to it times out.
+const REQUEST_TIMEOUT_REPUTATION_CHANGE: i32 = -5;
+
+/// Maximum number of [`Event::InboundProtocolError`] generated during each period of
+/// [`INBOUND_PROTOCOL_ERRORS_REPORTS_PERIOD`]. Errors beyond this limit are silently discarded.
+const MAX_INBOUND_PROTOCOL_ERRORS_REPORTS: u32 = 32;
+
+/// See [`MAX_INBOUND_PROTOCOL_ERRORS_REPORTS`].
+const INBOUND_PROTOCOL_ERRORS_REPORTS_PERIOD: Duration = Duration::from_secs(10);
+
+/// Number of inbound substreams per connection allowed independently of the chains, in
+/// addition to the ones of [`INBOUND_SUBSTREAMS_PER_CHAIN`] and
+/// [`INBOUND_SUBSTREAMS_PER_CUSTOM_PROTOCOL`]. Used if [`Config::max_inbound_substreams`] is
+/// `None`.
+const INBOUND_SUBSTREAMS_BASE: usize = 96;
+
+/// See [`INBOUND_SUBSTREAMS_BASE`].
+const INBOUND_SUBSTREAMS_PER_CHAIN: usize = 32;
+
+/// See [`INBOUND_SUBSTREAMS_BASE`].
+const INBOUND_SUBSTREAMS_PER_CUSTOM_PROTOCOL: usize = 4;
+
 struct Chain {
     /// See [`ChainConfig::block_number_bytes`].
     block_number_bytes: usize,
@@ -253,17 +605,96 @@ struct Chain {
 
     /// See [`ChainConfig::allow_inbound_block_requests`].
     allow_inbound_block_requests: bool,
+
+    /// Statistics about the inbound blocks requests concerning this chain.
+    inbound_blocks_requests_stats: InboundRequestsStats,
+
+    /// Statistics about the inbound gossip links rejected with
+    /// [`ChainNetwork::gossip_reject_in`].
+    gossip_in_rejects_stats: GossipInRejectsStats,
+
+    /// See [`ChainConfig::gossip_slots`].
+    gossip_slots: Option<GossipSlots>,
+
+    /// See [`ChainConfig::notifications_open_timeout`].
+    notifications_open_timeout: Duration,
+    /// See [`ChainConfig::max_notifications_handshake_size`].
+    max_notifications_handshake_size: usize,
+    /// See [`ChainConfig::max_notification_size`].
+    max_notification_size: usize,
+
+    /// See [`ChainConfig::custom_request_response_protocols`].
+    custom_protocols: Vec<CustomProtocol>,
+
+    /// See [`ChainConfig::custom_notifications_protocols`].
+    custom_notifications_protocols: Vec<CustomNotificationsProtocol>,
+}
+
+/// See [`Chain::gossip_slots`].
+struct GossipSlots {
+    /// See [`GossipSlotsConfig::in_slots`].
+    in_slots: usize,
+    /// See [`GossipSlotsConfig::out_slots`].
+    out_slots: usize,
+    /// Peers that have been assigned an outbound slot with
+    /// [`ChainNetwork::gossip_assign_out_slot`] and that are still desired. Can contain reserved
+    /// peers, in which case they keep occupying their slot.
+    out_slots_assigned: BTreeSet<PeerId>,
+    /// See [`GossipSlotsConfig::reserved_peers`].
+    reserved_peers: BTreeSet<PeerId>,
+    /// See [`GossipSlotsConfig::reserved_only`].
+    reserved_only: bool,
+}
+
+/// See [`Chain::custom_protocols`].
+struct CustomProtocol {
+    /// Full name of the protocol, including the chain-specific prefix.
+    name: String,
+
+    /// Configuration passed by the API user.
+    config: CustomRequestResponseProtocolConfig,
+
+    /// Statistics about the inbound requests on this protocol.
+    inbound_requests_stats: InboundRequestsStats,
+}
+
+/// See [`Chain::custom_notifications_protocols`].
+struct CustomNotificationsProtocol {
+    /// Full name of the protocol, including the chain-specific prefix.
+    name: String,
+
+    /// Configuration passed by the API user.
+    config: CustomNotificationsProtocolConfig,
 }
 
 /// See [`ChainNetwork::inner`].
 struct ConnectionInfo {
     address: Vec<u8>,
 
+    /// Whether the connection has been initiated locally or by the remote.
+    direction: ConnectionDirection,
+
     /// Identity of the remote. Can be either the expected or the actual identity.
     ///
     /// `None` if unknown, which can only be the case if the connection is still in its handshake
     /// phase.
     peer_id: Option<PeerId>,
+
+    /// Rolling estimate of the round-trip time of pings on this connection. `None` if no ping
+    /// has succeeded yet. See [`ChainNetwork::connection_ping_rtt`].
+    ping_rtt_estimate: Option<Duration>,
+
+    /// Number of outgoing pings that have failed in a row. Reset to 0 whenever a ping succeeds.
+    consecutive_ping_failures: u32,
+
+    /// Reason why the connection is shutting down. `None` if the connection isn't shutting down.
+    shutdown_reason: Option<DisconnectReason>,
+
+    /// Ed25519 public key of the Noise key that was in use when the connection was added, in
+    /// other words the identity of the local node as known by the remote of this connection.
+    /// Might be different from the current key if [`ChainNetwork::set_noise_key`] has been
+    /// called afterwards.
+    local_ed25519_public_key: [u8; 32],
 }
 
 /// See [`ChainNetwork::substreams`].
@@ -272,22 +703,54 @@ struct SubstreamInfo {
     // TODO: substream <-> connection mapping should be provided by collection.rs instead
     connection_id: collection::ConnectionId,
     protocol: Protocol,
+    /// Entry of [`codec::PROTOCOL_SCHEMAS`] negotiated on this substream. Always `None` for
+    /// outbound substreams, as they use the entry returned by
+    /// [`codec::preferred_protocol_schema`].
+    schema: Option<&'static codec::ProtocolSchema>,
 }
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 enum Protocol {
     Identify,
     Ping,
-    BlockAnnounces { chain_index: usize },
-    Transactions { chain_index: usize },
-    Grandpa { chain_index: usize },
-    Sync { chain_index: usize },
-    LightUnknown { chain_index: usize },
-    LightStorage { chain_index: usize },
-    LightCall { chain_index: usize },
-    Kad { chain_index: usize },
-    SyncWarp { chain_index: usize },
-    State { chain_index: usize },
+    BlockAnnounces {
+        chain_index: usize,
+    },
+    Transactions {
+        chain_index: usize,
+    },
+    Grandpa {
+        chain_index: usize,
+    },
+    Sync {
+        chain_index: usize,
+    },
+    LightUnknown {
+        chain_index: usize,
+    },
+    LightStorage {
+        chain_index: usize,
+    },
+    LightCall {
+        chain_index: usize,
+    },
+    Kad {
+        chain_index: usize,
+    },
+    SyncWarp {
+        chain_index: usize,
+    },
+    State {
+        chain_index: usize,
+    },
+    Custom {
+        chain_index: usize,
+        protocol_index: usize,
+    },
+    CustomNotifications {
+        chain_index: usize,
+        protocol_index: usize,
+    },
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
@@ -297,6 +760,418 @@ enum NotificationsProtocol {
     Grandpa { chain_index: usize },
 }
 
+/// Health metrics of a gossip link. See [`ChainNetwork::gossip_link_info`].
+#[derive(Debug, Clone, Default)]
+pub struct GossipLinkInfo {
+    /// Number of valid block announces received from the peer.
+    pub announces_received: u64,
+    /// Total size, in bytes, of the notifications received from the peer on the block announces,
+    /// transactions, and GrandPa substreams.
+    pub bytes_received: u64,
+    /// Number of times [`ChainNetwork::gossip_open`] has successfully started opening the gossip
+    /// link.
+    pub open_attempts: u32,
+    /// Number of opening attempts that have failed since the gossip link was last open.
+    pub consecutive_open_failures: u32,
+    /// Direction of the connection the gossip link is open on. `None` if the gossip link isn't
+    /// currently open.
+    pub connection_direction: Option<ConnectionDirection>,
+}
+
+/// Block designated either by its hash or by its number. See [`ChainNetwork::peers_with_block`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BlockHashOrNumber {
+    /// BLAKE2 hash of the header of the block.
+    Hash([u8; 32]),
+    /// Height of the block.
+    Number(u64),
+}
+
+/// State of a gossip link with a peer on a chain. See [`ChainNetwork::gossip_links`].
+///
+/// A gossip link consists of at most one block announces, one transactions, and one GrandPa
+/// substream in each direction. The invariants between these substreams are verified by
+/// [`ChainNetwork::debug_check_gossip_link`].
+#[derive(Debug, Default)]
+struct GossipLink {
+    /// Health metrics of the gossip link. See [`ChainNetwork::gossip_link_info`].
+    info: GossipLinkInfo,
+
+    /// Best block number and hash of the peer. Initialized from the block announces handshake
+    /// and updated when a block announce indicating a new best block is received.
+    ///
+    /// `Some` if and only if the outbound block announces substream is open.
+    best_block: Option<(u64, [u8; 32])>,
+
+    /// Substreams opened by the local node.
+    ///
+    /// The transactions and GrandPa substreams are only opened once the block announces
+    /// substream is open, and are closed alongside with it.
+    out_substreams: GossipLinkSubstreams,
+
+    /// Substreams opened by the remote.
+    ///
+    /// The transactions and GrandPa substreams can only be pending while the block announces
+    /// substream is pending, in which case they are accepted or rejected alongside with it.
+    in_substreams: GossipLinkSubstreams,
+}
+
+impl GossipLink {
+    /// Returns the substreams of the gossip link in the given direction.
+    fn substreams(&self, direction: SubstreamDirection) -> &GossipLinkSubstreams {
+        match direction {
+            SubstreamDirection::In => &self.in_substreams,
+            SubstreamDirection::Out => &self.out_substreams,
+        }
+    }
+
+    /// Returns the substreams of the gossip link in the given direction.
+    fn substreams_mut(&mut self, direction: SubstreamDirection) -> &mut GossipLinkSubstreams {
+        match direction {
+            SubstreamDirection::In => &mut self.in_substreams,
+            SubstreamDirection::Out => &mut self.out_substreams,
+        }
+    }
+}
+
+/// Notifications substreams of a [`GossipLink`] in one direction.
+#[derive(Debug, Default)]
+struct GossipLinkSubstreams {
+    block_announces: Option<(NotificationsSubstreamState, SubstreamId)>,
+    transactions: Option<(NotificationsSubstreamState, SubstreamId)>,
+    grandpa: Option<(NotificationsSubstreamState, SubstreamId)>,
+}
+
+impl GossipLinkSubstreams {
+    /// Returns the state and identifier of the substream of the given protocol, if any. The
+    /// chain of the protocol is ignored.
+    fn get(
+        &self,
+        protocol: NotificationsProtocol,
+    ) -> Option<(NotificationsSubstreamState, SubstreamId)> {
+        match protocol {
+            NotificationsProtocol::BlockAnnounces { .. } => self.block_announces,
+            NotificationsProtocol::Transactions { .. } => self.transactions,
+            NotificationsProtocol::Grandpa { .. } => self.grandpa,
+        }
+    }
+
+    /// Returns the slot of the substream of the given protocol. The chain of the protocol is
+    /// ignored.
+    fn get_mut(
+        &mut self,
+        protocol: NotificationsProtocol,
+    ) -> &mut Option<(NotificationsSubstreamState, SubstreamId)> {
+        match protocol {
+            NotificationsProtocol::BlockAnnounces { .. } => &mut self.block_announces,
+            NotificationsProtocol::Transactions { .. } => &mut self.transactions,
+            NotificationsProtocol::Grandpa { .. } => &mut self.grandpa,
+        }
+    }
+
+    /// Returns `true` if there isn't any substream.
+    fn is_empty(&self) -> bool {
+        self.block_announces.is_none() && self.transactions.is_none() && self.grandpa.is_none()
+    }
+}
+
+/// Borrowed equivalent of the keys of [`ChainNetwork::gossip_links`], making it possible to
+/// look up an entry without cloning the [`PeerId`].
+#[derive(Hash)]
+struct GossipLinkKey<'a>(usize, &'a PeerId);
+
+impl<'a> hashbrown::Equivalent<(usize, PeerId)> for GossipLinkKey<'a> {
+    fn equivalent(&self, key: &(usize, PeerId)) -> bool {
+        self.0 == key.0 && *self.1 == key.1
+    }
+}
+
+/// Summary of the state of the gossip-desired peers of a chain. See
+/// [`ChainNetwork::gossip_desired_status`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GossipDesiredStatus {
+    /// Number of desired peers for which no healthy connection exists. Includes the peers a
+    /// connection is currently being established with.
+    pub unconnected: usize,
+    /// Number of desired peers for which a healthy connection exists, but for which no gossip
+    /// link opening attempt is in progress.
+    pub connected_unopened: usize,
+    /// Number of desired peers whose gossip link is currently being opened.
+    pub opening: usize,
+    /// Number of desired peers whose gossip link is open.
+    pub open: usize,
+}
+
+/// Protocol of inbound requests. See [`ChainNetwork::inbound_requests_stats`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum InboundRequestsProtocol {
+    /// Identify protocol. Not specific to any chain.
+    Identify,
+    /// Blocks requests concerning the given chain.
+    Blocks {
+        /// Chain concerned by the requests.
+        chain_id: ChainId,
+    },
+    /// Requests on one of the [`ChainConfig::custom_request_response_protocols`] of the given
+    /// chain.
+    Custom {
+        /// Chain concerned by the requests.
+        chain_id: ChainId,
+        /// Index of the protocol within [`ChainConfig::custom_request_response_protocols`].
+        protocol_index: usize,
+    },
+}
+
+/// Statistics about the inbound requests of a protocol. See
+/// [`ChainNetwork::inbound_requests_stats`].
+#[derive(Debug, Clone, Default)]
+pub struct InboundRequestsStats {
+    /// Number of requests that have been reported to the API user through an event.
+    pub received: u64,
+    /// Number of requests that have been answered with a response.
+    pub served: u64,
+    /// Number of requests that the API user has refused to answer, for example by passing
+    /// `None` to [`ChainNetwork::respond_blocks`].
+    pub denied: u64,
+    /// Number of substreams that have been refused during the protocol negotiation because the
+    /// protocol isn't enabled, for example because [`ChainConfig::allow_inbound_block_requests`]
+    /// is `false`. These substreams are never reported to the API user.
+    pub rejected: u64,
+    /// Number of requests that couldn't be decoded. These requests are never reported to the
+    /// API user.
+    pub invalid: u64,
+    /// Number of requests that the remote has cancelled before an answer was sent.
+    pub cancelled: u64,
+    /// Sum of the time between the moment when requests have been reported to the API user and
+    /// the moment when they have been answered, for both served and denied requests.
+    pub total_response_time: Duration,
+    /// Highest time between the moment when a request has been reported to the API user and the
+    /// moment when it has been answered.
+    pub max_response_time: Duration,
+}
+
+impl InboundRequestsStats {
+    /// Returns the average time the API user has taken to answer requests, or `None` if no
+    /// request has been answered yet.
+    pub fn average_response_time(&self) -> Option<Duration> {
+        let answered = self.served + self.denied;
+        if answered == 0 {
+            return None;
+        }
+        Some(Duration::from_nanos(
+            u64::try_from(self.total_response_time.as_nanos() / u128::from(answered))
+                .unwrap_or(u64::MAX),
+        ))
+    }
+
+    /// Updates the statistics after a request has been answered.
+    fn record_answer(&mut self, served: bool, response_time: Option<Duration>) {
+        if served {
+            self.served += 1;
+        } else {
+            self.denied += 1;
+        }
+        if let Some(response_time) = response_time {
+            self.total_response_time = self.total_response_time.saturating_add(response_time);
+            self.max_response_time = cmp::max(self.max_response_time, response_time);
+        }
+    }
+}
+
+/// Statistics about the inbound gossip links that have been rejected. See
+/// [`ChainNetwork::gossip_in_rejects_stats`].
+#[derive(Debug, Clone, Default)]
+pub struct GossipInRejectsStats {
+    /// Number of rejections with [`GossipRejectReason::SlotsFull`].
+    pub slots_full: u64,
+    /// Number of rejections with [`GossipRejectReason::Banned`].
+    pub banned: u64,
+    /// Number of rejections with [`GossipRejectReason::NotReserved`].
+    pub not_reserved: u64,
+    /// Number of rejections with [`GossipRejectReason::Other`].
+    pub other: u64,
+}
+
+impl GossipInRejectsStats {
+    /// Returns the total number of rejections, no matter the reason.
+    pub fn total(&self) -> u64 {
+        self.slots_full + self.banned + self.not_reserved + self.other
+    }
+
+    /// Updates the statistics after an inbound gossip link has been rejected.
+    fn record(&mut self, reason: GossipRejectReason) {
+        match reason {
+            GossipRejectReason::SlotsFull => self.slots_full += 1,
+            GossipRejectReason::Banned => self.banned += 1,
+            GossipRejectReason::NotReserved => self.not_reserved += 1,
+            GossipRejectReason::Other => self.other += 1,
+        }
+    }
+}
+
+/// Metrics about the activity of a [`ChainNetwork`]. See [`ChainNetwork::metrics`].
+#[derive(Debug, Clone, Default)]
+pub struct NetworkMetrics {
+    /// Number of connections whose handshake has finished.
+    pub connections_opened: u64,
+    /// Number of connections that have been closed after their handshake had finished.
+    pub connections_closed: u64,
+    /// Metrics of the protocols that aren't specific to any chain, such as the identify
+    /// protocol, indexed by protocol name.
+    pub protocols: BTreeMap<String, ProtocolMetrics>,
+    /// Metrics of the protocols of each chain, indexed by chain and by protocol name.
+    pub chains: BTreeMap<ChainId, BTreeMap<String, ProtocolMetrics>>,
+}
+
+/// Counters about the activity of a protocol. See [`NetworkMetrics`].
+///
+/// The number of bytes only include the payloads of the requests, responses, and notifications,
+/// and not the handshakes or the framing of the underlying protocols.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProtocolMetrics {
+    /// Number of bytes of requests, responses, and notifications sent to remotes.
+    pub bytes_sent: u64,
+    /// Number of bytes of requests, responses, and notifications received from remotes.
+    pub bytes_received: u64,
+    /// Number of notifications that have been queued for sending.
+    pub notifications_queued: u64,
+    /// Number of notifications that have been dropped because the queue of the substream was
+    /// full.
+    pub notifications_dropped: u64,
+    /// Number of notifications received from remotes.
+    pub notifications_received: u64,
+    /// Number of outbound requests that have been started.
+    pub requests_started: u64,
+    /// Number of outbound requests for which a response has been received, no matter whether
+    /// this response could later be decoded.
+    pub requests_succeeded: u64,
+    /// Number of outbound requests that have failed, for example because of a timeout or
+    /// because the remote has refused the protocol. Requests cancelled with
+    /// [`ChainNetwork::cancel_request`] are neither counted as succeeded nor failed.
+    pub requests_failed: u64,
+}
+
+impl ProtocolMetrics {
+    /// Updates the metrics after a payload of the given size has been sent.
+    fn record_sent(&mut self, num_bytes: usize) {
+        self.bytes_sent = self
+            .bytes_sent
+            .saturating_add(u64::try_from(num_bytes).unwrap_or(u64::MAX));
+    }
+
+    /// Updates the metrics after a payload of the given size has been received.
+    fn record_received(&mut self, num_bytes: usize) {
+        self.bytes_received = self
+            .bytes_received
+            .saturating_add(u64::try_from(num_bytes).unwrap_or(u64::MAX));
+    }
+
+    /// Updates the metrics after an attempt at queuing a notification of the given size.
+    fn record_notification_out(&mut self, num_bytes: usize, queued: bool) {
+        if queued {
+            self.notifications_queued += 1;
+            self.record_sent(num_bytes);
+        } else {
+            self.notifications_dropped += 1;
+        }
+    }
+
+    /// Adds the counters of `other` to the counters of `self`.
+    fn merge(&mut self, other: &ProtocolMetrics) {
+        self.bytes_sent = self.bytes_sent.saturating_add(other.bytes_sent);
+        self.bytes_received = self.bytes_received.saturating_add(other.bytes_received);
+        self.notifications_queued += other.notifications_queued;
+        self.notifications_dropped += other.notifications_dropped;
+        self.notifications_received += other.notifications_received;
+        self.requests_started += other.requests_started;
+        self.requests_succeeded += other.requests_succeeded;
+        self.requests_failed += other.requests_failed;
+    }
+}
+
+/// See [`ChainNetwork::notifications_dropped`].
+struct DroppedNotifications {
+    /// Peer the substream is connected to.
+    peer_id: PeerId,
+    /// Protocol of the substream.
+    protocol: NotificationsProtocol,
+    /// Number of notifications dropped since the last report.
+    count: u32,
+}
+
+/// Increments the number of notifications dropped on the given substream.
+fn record_dropped_notification(
+    notifications_dropped: &mut hashbrown::HashMap<
+        collection::SubstreamId,
+        DroppedNotifications,
+        fnv::FnvBuildHasher,
+    >,
+    substream_id: collection::SubstreamId,
+    peer_id: &PeerId,
+    protocol: NotificationsProtocol,
+) {
+    let entry = notifications_dropped
+        .entry(substream_id)
+        .or_insert_with(|| DroppedNotifications {
+            peer_id: peer_id.clone(),
+            protocol,
+            count: 0,
+        });
+    entry.count = entry.count.saturating_add(1);
+}
+
+/// Returns the delay before automatically re-opening a gossip link after the given number of
+/// consecutive failures.
+fn gossip_open_backoff(config: &GossipOpenRetryConfig, consecutive_failures: u32) -> Duration {
+    let factor = 1u32
+        .checked_shl(consecutive_failures.saturating_sub(1))
+        .unwrap_or(u32::MAX);
+    cmp::min(
+        config.initial_backoff.saturating_mul(factor),
+        config.max_backoff,
+    )
+}
+
+/// Builds the full name of a custom protocol, including the chain-specific prefix. See
+/// [`CustomRequestResponseProtocolConfig::name`].
+fn custom_protocol_full_name(genesis_hash: &[u8; 32], fork_id: Option<&str>, name: &str) -> String {
+    let mut full_name = String::with_capacity(128);
+    full_name.push('/');
+    full_name.push_str(&hex::encode(genesis_hash));
+    if let Some(fork_id) = fork_id {
+        full_name.push('/');
+        full_name.push_str(fork_id);
+    }
+    full_name.push('/');
+    full_name.push_str(name);
+    full_name
+}
+
+impl NotificationsProtocol {
+    /// Returns the index of the chain the protocol belongs to.
+    fn chain_index(&self) -> usize {
+        match *self {
+            NotificationsProtocol::BlockAnnounces { chain_index }
+            | NotificationsProtocol::Transactions { chain_index }
+            | NotificationsProtocol::Grandpa { chain_index } => chain_index,
+        }
+    }
+}
+
+impl From<NotificationsProtocol> for Protocol {
+    fn from(value: NotificationsProtocol) -> Self {
+        match value {
+            NotificationsProtocol::BlockAnnounces { chain_index } => {
+                Protocol::BlockAnnounces { chain_index }
+            }
+            NotificationsProtocol::Transactions { chain_index } => {
+                Protocol::Transactions { chain_index }
+            }
+            NotificationsProtocol::Grandpa { chain_index } => Protocol::Grandpa { chain_index },
+        }
+    }
+}
+
 impl TryFrom<Protocol> for NotificationsProtocol {
     type Error = ();
 
@@ -318,32 +1193,24 @@ impl TryFrom<Protocol> for NotificationsProtocol {
             Protocol::Kad { .. } => Err(()),
             Protocol::SyncWarp { .. } => Err(()),
             Protocol::State { .. } => Err(()),
+            Protocol::Custom { .. } => Err(()),
+            Protocol::CustomNotifications { .. } => Err(()),
         }
     }
 }
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 enum SubstreamDirection {
     In,
     Out,
 }
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 enum NotificationsSubstreamState {
     Pending,
     Open,
 }
 
-impl NotificationsSubstreamState {
-    fn min_value() -> Self {
-        NotificationsSubstreamState::Pending
-    }
-
-    fn max_value() -> Self {
-        NotificationsSubstreamState::Open
-    }
-}
-
 impl<TNow> ChainNetwork<TNow>
 where
     TNow: Clone + Add<Duration, Output = TNow> + Sub<TNow, Output = Duration> + Ord,
@@ -352,64 +1219,162 @@ where
     pub fn new(config: Config) -> Self {
         let mut randomness = rand_chacha::ChaCha20Rng::from_seed(config.randomness_seed);
 
+        // Seeds of the hashers whose iteration order influences the public API.
+        // Note that the randomness is consumed even if `deterministic_ordering` is `true`, in
+        // order for the rest of the state machine to behave the same way in both cases.
+        let deterministic_ordering = config.deterministic_ordering;
+        let hasher_seed = move |randomness: &mut rand_chacha::ChaCha20Rng| {
+            let mut seed = [0; 16];
+            randomness.fill_bytes(&mut seed);
+            if deterministic_ordering {
+                [0; 16]
+            } else {
+                seed
+            }
+        };
+
         ChainNetwork {
             inner: collection::Network::new(collection::Config {
                 capacity: config.connections_capacity,
-                max_inbound_substreams: 128, // TODO: arbitrary value ; this value should be dynamically adjusted based on the number of chains that have been added
+                max_inbound_substreams: config
+                    .max_inbound_substreams
+                    .unwrap_or(INBOUND_SUBSTREAMS_BASE),
                 randomness_seed: {
                     let mut seed = [0; 32];
                     randomness.fill_bytes(&mut seed);
                     seed
                 },
                 ping_protocol: "/ipfs/ping/1.0.0".into(),
+                ping_interval: config.ping_interval,
+                ping_timeout: config.ping_timeout,
+                max_queued_notifications_bytes: config.max_queued_notifications_bytes,
                 handshake_timeout: config.handshake_timeout,
+                substreams_lifecycle_events: false,
             }),
             substreams: hashbrown::HashMap::with_capacity_and_hasher(
                 config.connections_capacity * 20, // TODO: capacity?
                 fnv::FnvBuildHasher::default(),
             ),
             connections_by_peer_id: BTreeSet::new(),
-            notification_substreams_by_peer_id: BTreeSet::new(),
+            notifications_dropped: hashbrown::HashMap::with_capacity_and_hasher(
+                0,
+                fnv::FnvBuildHasher::default(),
+            ),
             gossip_desired_peers_by_chain: BTreeSet::new(),
             gossip_desired_peers: BTreeSet::new(),
             unconnected_desired: hashbrown::HashSet::with_capacity_and_hasher(
                 config.connections_capacity,
-                SipHasherBuild::new({
-                    let mut seed = [0; 16];
-                    randomness.fill_bytes(&mut seed);
-                    seed
-                }),
+                SipHasherBuild::new(hasher_seed(&mut randomness)),
             ),
             connected_unopened_gossip_desired: hashbrown::HashSet::with_capacity_and_hasher(
                 config.connections_capacity,
-                SipHasherBuild::new({
-                    let mut seed = [0; 16];
-                    randomness.fill_bytes(&mut seed);
-                    seed
-                }),
+                SipHasherBuild::new(hasher_seed(&mut randomness)),
             ),
             opened_gossip_undesired: hashbrown::HashSet::with_capacity_and_hasher(
                 config.connections_capacity,
-                SipHasherBuild::new({
-                    let mut seed = [0; 16];
-                    randomness.fill_bytes(&mut seed);
-                    seed
-                }),
+                SipHasherBuild::new(hasher_seed(&mut randomness)),
             ),
-            chains: slab::Slab::with_capacity(config.chains_capacity),
+            gossip_links: hashbrown::HashMap::with_capacity_and_hasher(
+                config.connections_capacity,
+                SipHasherBuild::new(hasher_seed(&mut randomness)),
+            ),
+            gossip_open_retry: config.gossip_open_retry,
+            gossip_open_retries: BTreeMap::new(),
+            inbound_requests_received: hashbrown::HashMap::with_capacity_and_hasher(
+                0,
+                fnv::FnvBuildHasher::default(),
+            ),
+            inbound_identify_requests_stats: InboundRequestsStats::default(),
+            inbound_protocol_errors_reports: None,
+            connections_opened: 0,
+            connections_closed: 0,
+            protocols_metrics: hashbrown::HashMap::with_capacity_and_hasher(
+                0,
+                fnv::FnvBuildHasher::default(),
+            ),
+            peers_reputation: hashbrown::HashMap::with_capacity_and_hasher(
+                0,
+                SipHasherBuild::new(hasher_seed(&mut randomness)),
+            ),
+            peers_bans_expiration: BTreeSet::new(),
+            reputation_ban: config.reputation_ban,
+            refused_protocols: BTreeMap::new(),
+            refused_protocols_expiration: BTreeSet::new(),
+            refused_protocols_cooldown: config.refused_protocols_cooldown,
+            max_inbound_substreams_automatic: config.max_inbound_substreams.is_none(),
+            max_ping_failures: config.max_ping_failures,
+            max_connections_per_peer: config.max_connections_per_peer,
+            address_sanitize_policy: config.address_sanitize_policy,
+            chains: slab::Slab::with_capacity(config.chains_capacity),
             chains_by_protocol_info: hashbrown::HashMap::with_capacity_and_hasher(
                 config.chains_capacity,
                 Default::default(),
             ),
+            custom_protocols_by_name: hashbrown::HashMap::with_capacity_and_hasher(
+                0,
+                Default::default(),
+            ),
             noise_key: config.noise_key,
+            local_addresses: Vec::new(),
         }
     }
 
-    /// Returns the Noise key originally passed as [`Config::noise_key`].
+    /// Returns the Noise key used for new connections.
+    ///
+    /// This is the key originally passed as [`Config::noise_key`], unless it has been replaced
+    /// with [`ChainNetwork::set_noise_key`].
     pub fn noise_key(&self) -> &NoiseKey {
         &self.noise_key
     }
 
+    /// Replaces the Noise key, and thus the identity of the local node.
+    ///
+    /// The new key is used for all the connections added afterwards, both incoming and outgoing,
+    /// and in order to answer Kademlia requests. Connections that have already been added,
+    /// including the ones whose handshake is still in progress, continue to use the previous key
+    /// and are unaffected. In particular, identify requests received on these connections are
+    /// answered with the previous public key.
+    ///
+    /// Note that remotes that are connected through an existing connection know the local node
+    /// under its previous [`PeerId`] until that connection is closed.
+    pub fn set_noise_key(&mut self, noise_key: NoiseKey) {
+        self.noise_key = noise_key;
+    }
+
+    /// Adds an address to the list of addresses the local node is reachable on. These addresses
+    /// are reported to remotes when they send an identify request.
+    ///
+    /// The address is expected to be an encoded multiaddress, but isn't verified. It should
+    /// ideally be publicly-reachable, for example an address the local node is listening on or
+    /// an external address.
+    ///
+    /// Has no effect if the address was already in the list. Returns `true` if the address has
+    /// been added.
+    pub fn add_local_address(&mut self, address: Vec<u8>) -> bool {
+        if self.local_addresses.contains(&address) {
+            return false;
+        }
+
+        self.local_addresses.push(address);
+        true
+    }
+
+    /// Removes an address from the list of addresses the local node is reachable on. Returns
+    /// `true` if the address was in the list.
+    ///
+    /// See [`ChainNetwork::add_local_address`].
+    pub fn remove_local_address(&mut self, address: &[u8]) -> bool {
+        let len_before = self.local_addresses.len();
+        self.local_addresses.retain(|a| *a != address);
+        self.local_addresses.len() != len_before
+    }
+
+    /// Returns the list of addresses that have been added with
+    /// [`ChainNetwork::add_local_address`].
+    pub fn local_addresses(&self) -> impl ExactSizeIterator<Item = &[u8]> {
+        self.local_addresses.iter().map(|a| &a[..])
+    }
+
     /// Adds a chain to the list of chains that is handled by the [`ChainNetwork`].
     ///
     /// It is not possible to add a chain if its protocol names would conflict with an existing
@@ -418,6 +1383,57 @@ where
         let chain_entry = self.chains.vacant_entry();
         let chain_id = chain_entry.key();
 
+        // Build the full names of the custom protocols.
+        let custom_protocol_name = |name: &str| {
+            custom_protocol_full_name(&config.genesis_hash, config.fork_id.as_deref(), name)
+        };
+        let custom_protocols = config
+            .custom_request_response_protocols
+            .into_iter()
+            .map(|protocol_config| CustomProtocol {
+                name: custom_protocol_name(&protocol_config.name),
+                config: protocol_config,
+                inbound_requests_stats: InboundRequestsStats::default(),
+            })
+            .collect::<Vec<_>>();
+        let custom_notifications_protocols = config
+            .custom_notifications_protocols
+            .into_iter()
+            .map(|protocol_config| CustomNotificationsProtocol {
+                name: custom_protocol_name(&protocol_config.name),
+                config: protocol_config,
+            })
+            .collect::<Vec<_>>();
+
+        // Make sure that the names of the custom protocols don't conflict with any other
+        // protocol, including each other.
+        {
+            let mut new_names = Vec::<String>::with_capacity(
+                custom_protocols.len() + custom_notifications_protocols.len(),
+            );
+            let mut is_conflict = |name: &str| {
+                let conflict = codec::decode_protocol_name(name).is_ok()
+                    || self.custom_protocols_by_name.contains_key(name)
+                    || new_names.iter().any(|n| n == name);
+                new_names.push(name.to_owned());
+                conflict
+            };
+            for (protocol_index, custom_protocol) in custom_protocols.iter().enumerate() {
+                if is_conflict(&custom_protocol.name) {
+                    return Err(AddChainError::CustomProtocolConflict { protocol_index });
+                }
+            }
+            for (protocol_index, custom_protocol) in
+                custom_notifications_protocols.iter().enumerate()
+            {
+                if is_conflict(&custom_protocol.name) {
+                    return Err(AddChainError::CustomNotificationsProtocolConflict {
+                        protocol_index,
+                    });
+                }
+            }
+        }
+
         match self
             .chains_by_protocol_info
             .entry((config.genesis_hash, config.fork_id.clone()))
@@ -432,6 +1448,25 @@ where
             }
         }
 
+        for (protocol_index, custom_protocol) in custom_protocols.iter().enumerate() {
+            self.custom_protocols_by_name.insert(
+                custom_protocol.name.clone(),
+                Protocol::Custom {
+                    chain_index: chain_id,
+                    protocol_index,
+                },
+            );
+        }
+        for (protocol_index, custom_protocol) in custom_notifications_protocols.iter().enumerate() {
+            self.custom_protocols_by_name.insert(
+                custom_protocol.name.clone(),
+                Protocol::CustomNotifications {
+                    chain_index: chain_id,
+                    protocol_index,
+                },
+            );
+        }
+
         chain_entry.insert(Chain {
             block_number_bytes: config.block_number_bytes,
             genesis_hash: config.genesis_hash,
@@ -441,8 +1476,51 @@ where
             best_number: config.best_number,
             allow_inbound_block_requests: config.allow_inbound_block_requests,
             grandpa_protocol_config: config.grandpa_protocol_config,
+            inbound_blocks_requests_stats: InboundRequestsStats::default(),
+            gossip_in_rejects_stats: GossipInRejectsStats::default(),
+            gossip_slots: config.gossip_slots.as_ref().map(|config| GossipSlots {
+                in_slots: config.in_slots,
+                out_slots: config.out_slots,
+                out_slots_assigned: BTreeSet::new(),
+                reserved_peers: config.reserved_peers.iter().cloned().collect(),
+                reserved_only: config.reserved_only,
+            }),
+            notifications_open_timeout: config.notifications_open_timeout,
+            max_notifications_handshake_size: config.max_notifications_handshake_size,
+            max_notification_size: config.max_notification_size,
+            custom_protocols,
+            custom_notifications_protocols,
         });
 
+        // Reserved peers are always desired.
+        for peer_id in config
+            .gossip_slots
+            .into_iter()
+            .flat_map(|config| config.reserved_peers)
+        {
+            self.gossip_insert_desired(
+                ChainId(chain_id),
+                peer_id,
+                GossipKind::ConsensusTransactions,
+            );
+        }
+
+        if self.max_inbound_substreams_automatic {
+            let max_inbound_substreams = INBOUND_SUBSTREAMS_BASE
+                + self
+                    .chains
+                    .iter()
+                    .map(|(_, chain)| {
+                        INBOUND_SUBSTREAMS_PER_CHAIN
+                            + INBOUND_SUBSTREAMS_PER_CUSTOM_PROTOCOL
+                                * (chain.custom_protocols.len()
+                                    + chain.custom_notifications_protocols.len())
+                    })
+                    .sum::<usize>();
+            self.inner
+                .set_max_inbound_substreams(max_inbound_substreams);
+        }
+
         Ok(ChainId(chain_id))
     }
 
@@ -451,7 +1529,9 @@ where
     /// Modifies the best block of the local node for the given chain. See
     /// [`ChainConfig::best_hash`] and [`ChainConfig::best_number`].
     ///
-    /// This information is sent to remotes whenever a block announces substream is opened.
+    /// This information is sent to remotes whenever a block announces substream is opened. See
+    /// also [`ChainNetwork::set_chain_local_best_block_and_announce`] in order to inform the
+    /// peers that are already connected.
     ///
     /// # Panic
     ///
@@ -468,6 +1548,70 @@ where
         chain.best_number = best_number;
     }
 
+    /// Modifies the best block of the local node for the given chain, similar to
+    /// [`ChainNetwork::set_chain_local_best_block`], then immediately announces this new best
+    /// block on all the block announces substreams of this chain that are currently open.
+    ///
+    /// Contrary to [`ChainNetwork::set_chain_local_best_block`], peers are informed of the new
+    /// best block without having to wait for the next block announces substream to be opened.
+    ///
+    /// Peers whose queue of notifications is full are silently skipped. Returns the number of
+    /// peers the block announce has been queued to, or an error if the header couldn't be
+    /// decoded, in which case the best block isn't modified.
+    ///
+    /// This function might generate messages destined to connections. Use
+    /// [`ChainNetwork::pull_message_to_connection`] to process messages after it has returned.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the [`ChainId`] is out of range.
+    ///
+    pub fn set_chain_local_best_block_and_announce(
+        &mut self,
+        chain_id: ChainId,
+        scale_encoded_header: &[u8],
+    ) -> Result<usize, header::Error> {
+        let best_number = header::decode(
+            scale_encoded_header,
+            self.chains[chain_id.0].block_number_bytes,
+        )?
+        .number;
+        let best_hash = header::hash_from_scale_encoded_header(scale_encoded_header);
+        self.set_chain_local_best_block(chain_id, best_hash, best_number);
+
+        // A link has a best block if and only if its outbound block announces substream is open.
+        let targets = self
+            .gossip_links
+            .iter()
+            .filter(|((chain_index, _), link)| {
+                *chain_index == chain_id.0 && link.best_block.is_some()
+            })
+            .map(|((_, peer_id), _)| peer_id.clone())
+            .collect::<Vec<_>>();
+
+        let mut num_announced = 0;
+        for target in &targets {
+            if self
+                .gossip_send_block_announce(target, chain_id, scale_encoded_header, true)
+                .is_ok()
+            {
+                num_announced += 1;
+            }
+        }
+
+        Ok(num_announced)
+    }
+
+    /// Returns the maximum number of substreams that each remote can have simultaneously opened
+    /// on the connections added from now on.
+    ///
+    /// This is either the value passed as [`Config::max_inbound_substreams`], or, if `None` was
+    /// passed, a value that depends on the chains that have been added. Connections that already
+    /// exist keep the limit that was in place when they have been added.
+    pub fn max_inbound_substreams(&self) -> usize {
+        self.inner.max_inbound_substreams()
+    }
+
     /// Returns the list of all the chains that have been added.
     pub fn chains(&'_ self) -> impl Iterator<Item = ChainId> + '_ {
         self.chains.iter().map(|(idx, _)| ChainId(idx))
@@ -536,28 +1680,13 @@ where
             })
         {
             if self
-                .notification_substreams_by_peer_id
-                .range(
-                    (
-                        NotificationsProtocol::BlockAnnounces {
-                            chain_index: chain_id.0,
-                        },
-                        peer_id.clone(),
-                        SubstreamDirection::Out,
-                        NotificationsSubstreamState::min_value(),
-                        SubstreamId::min_value(),
-                    )
-                        ..=(
-                            NotificationsProtocol::BlockAnnounces {
-                                chain_index: chain_id.0,
-                            },
-                            peer_id.clone(),
-                            SubstreamDirection::Out,
-                            NotificationsSubstreamState::max_value(),
-                            SubstreamId::max_value(),
-                        ),
+                .gossip_link_substream(
+                    NotificationsProtocol::BlockAnnounces {
+                        chain_index: chain_id.0,
+                    },
+                    &peer_id,
+                    SubstreamDirection::Out,
                 )
-                .next()
                 .is_none()
             {
                 let _was_inserted = self.connected_unopened_gossip_desired.insert((
@@ -609,6 +1738,12 @@ where
         self.connected_unopened_gossip_desired
             .remove(&(peer_id.clone(), chain_id, kind)); // TODO: cloning
 
+        if kind == GossipKind::ConsensusTransactions {
+            if let Some(slots) = self.chains[chain_id.0].gossip_slots.as_mut() {
+                slots.out_slots_assigned.remove(peer_id);
+            }
+        }
+
         if self
             .gossip_desired_peers
             .range(
@@ -622,28 +1757,13 @@ where
         }
 
         if self
-            .notification_substreams_by_peer_id
-            .range(
-                (
-                    NotificationsProtocol::BlockAnnounces {
-                        chain_index: chain_id.0,
-                    },
-                    peer_id.clone(),
-                    SubstreamDirection::Out,
-                    NotificationsSubstreamState::min_value(),
-                    SubstreamId::min_value(),
-                )
-                    ..=(
-                        NotificationsProtocol::BlockAnnounces {
-                            chain_index: chain_id.0,
-                        },
-                        peer_id.clone(),
-                        SubstreamDirection::Out,
-                        NotificationsSubstreamState::max_value(),
-                        SubstreamId::max_value(),
-                    ),
+            .gossip_link_substream(
+                NotificationsProtocol::BlockAnnounces {
+                    chain_index: chain_id.0,
+                },
+                peer_id,
+                SubstreamDirection::Out,
             )
-            .next()
             .is_some()
         {
             let _was_inserted =
@@ -652,6 +1772,8 @@ where
             debug_assert!(_was_inserted);
         }
 
+        self.gossip_link_cleanup(chain_id.0, peer_id);
+
         true
     }
 
@@ -682,6 +1804,12 @@ where
                 ChainId(chain_index),
                 kind,
             ));
+            if kind == GossipKind::ConsensusTransactions {
+                if let Some(slots) = self.chains[chain_index].gossip_slots.as_mut() {
+                    slots.out_slots_assigned.remove(peer_id);
+                }
+            }
+            self.gossip_link_cleanup(chain_index, peer_id);
         }
 
         self.unconnected_desired.remove(peer_id);
@@ -701,13 +1829,67 @@ where
             .count()
     }
 
+    /// Returns a summary of the state of the gossip links with the gossip-desired peers of the
+    /// given chain.
+    ///
+    /// This can be used in order to determine whether more peers should be discovered.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the given [`ChainId`] is invalid.
+    ///
+    pub fn gossip_desired_status(
+        &self,
+        chain_id: ChainId,
+        kind: GossipKind,
+    ) -> GossipDesiredStatus {
+        assert!(self.chains.contains(chain_id.0));
+
+        let mut status = GossipDesiredStatus::default();
+
+        // TODO: O(n), optimize
+        for (_, _, peer_id) in self
+            .gossip_desired_peers_by_chain
+            .iter()
+            .filter(|(c, k, _)| *c == chain_id.0 && *k == kind)
+        {
+            let substream = self.gossip_link_substream(
+                NotificationsProtocol::BlockAnnounces {
+                    chain_index: chain_id.0,
+                },
+                peer_id,
+                SubstreamDirection::Out,
+            );
+
+            if let Some((NotificationsSubstreamState::Open, _)) = substream {
+                status.open += 1;
+            } else if substream.is_some() {
+                status.opening += 1;
+            } else if self.connected_unopened_gossip_desired.contains(&(
+                peer_id.clone(),
+                chain_id,
+                kind,
+            )) {
+                status.connected_unopened += 1;
+            } else {
+                status.unconnected += 1;
+            }
+        }
+
+        status
+    }
+
     /// Returns the list of [`PeerId`]s that are desired (for any chain) but for which no
     /// connection exists.
     ///
+    /// Peers that are banned (see [`ChainNetwork::ban_peer`]) are not returned.
+    ///
     /// > **Note**: Connections that are currently in the process of shutting down are also
     /// >           ignored for the purpose of this function.
-    pub fn unconnected_desired(&'_ self) -> impl ExactSizeIterator<Item = &'_ PeerId> + Clone + '_ {
-        self.unconnected_desired.iter()
+    pub fn unconnected_desired(&'_ self) -> impl Iterator<Item = &'_ PeerId> + Clone + '_ {
+        self.unconnected_desired
+            .iter()
+            .filter(move |peer_id| !self.is_banned(peer_id))
     }
 
     /// Returns the list of [`PeerId`]s that are marked as desired, and for which a healthy
@@ -774,6 +1956,11 @@ where
         // TODO: do the max protocol name length better ; knowing that it can later change if a chain with a long forkId is added
         let max_protocol_name_len = 256;
         let substreams_capacity = 16; // TODO: ?
+        let direction = match handshake_kind {
+            SingleStreamHandshakeKind::MultistreamSelectNoiseYamux { is_initiator } => {
+                ConnectionDirection::from_is_initiator(is_initiator)
+            }
+        };
         let (id, task) = self.inner.insert_single_stream(
             when_connection_start,
             match handshake_kind {
@@ -788,7 +1975,12 @@ where
             max_protocol_name_len,
             ConnectionInfo {
                 address: remote_addr,
+                direction,
                 peer_id: expected_peer_id.clone(),
+                ping_rtt_estimate: None,
+                consecutive_ping_failures: 0,
+                shutdown_reason: None,
+                local_ed25519_public_key: *self.noise_key.libp2p_public_ed25519_key(),
             },
         );
         if let Some(expected_peer_id) = expected_peer_id {
@@ -827,6 +2019,11 @@ where
         // TODO: do the max protocol name length better ; knowing that it can later change if a chain with a long forkId is added
         let max_protocol_name_len = 256;
         let substreams_capacity = 16; // TODO: ?
+        let direction = match handshake_kind {
+            MultiStreamHandshakeKind::WebRtc { is_initiator, .. } => {
+                ConnectionDirection::from_is_initiator(is_initiator)
+            }
+        };
         let (id, task) = self.inner.insert_multi_stream(
             when_connection_start,
             match handshake_kind {
@@ -845,7 +2042,12 @@ where
             max_protocol_name_len,
             ConnectionInfo {
                 address: remote_addr,
+                direction,
                 peer_id: expected_peer_id.clone(),
+                ping_rtt_estimate: None,
+                consecutive_ping_failures: 0,
+                shutdown_reason: None,
+                local_ed25519_public_key: *self.noise_key.libp2p_public_ed25519_key(),
             },
         );
         if let Some(expected_peer_id) = expected_peer_id {
@@ -855,6 +2057,121 @@ where
         (id, task)
     }
 
+    /// Starts the shutdown of the given connection.
+    ///
+    /// The substreams of this connection are closed, and the corresponding events (such as
+    /// [`Event::GossipDisconnected`] or failed requests) are later returned by
+    /// [`ChainNetwork::next_event`], followed with [`Event::Disconnected`] or
+    /// [`Event::PreHandshakeDisconnected`] once the shutdown is complete.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the [`ConnectionId`] is invalid or if the connection is already shutting down.
+    ///
+    #[track_caller]
+    pub fn start_shutdown(&mut self, id: ConnectionId) {
+        self.inner.start_shutdown(id);
+        self.inner[id].shutdown_reason = Some(DisconnectReason::LocallyRequested);
+        if let Some(peer_id) = self.inner[id].peer_id.clone() {
+            self.update_unconnected_desired_after_shutdown(&peer_id);
+        }
+    }
+
+    /// Starts the shutdown of all the connections to the given peer, including the connections
+    /// that are still handshaking and that are expected to reach this peer.
+    ///
+    /// Connections that are already shutting down are ignored. See
+    /// [`ChainNetwork::start_shutdown`] for the events that are later generated.
+    ///
+    /// The peer is not removed from the list of desired peers, and can thus be returned again by
+    /// [`ChainNetwork::unconnected_desired`]. Use [`ChainNetwork::gossip_remove_desired_all`] or
+    /// [`ChainNetwork::ban_peer`] if this isn't desired.
+    ///
+    /// Returns the number of connections whose shutdown has been started.
+    pub fn disconnect_peer(&mut self, peer_id: &PeerId) -> usize {
+        let to_shut_down = self
+            .connections_by_peer_id
+            .range(
+                (peer_id.clone(), ConnectionId::min_value())
+                    ..=(peer_id.clone(), ConnectionId::max_value()),
+            )
+            .map(|(_, connection_id)| *connection_id)
+            .filter(|connection_id| !self.inner.connection_state(*connection_id).shutting_down)
+            .collect::<Vec<_>>();
+
+        for connection_id in &to_shut_down {
+            self.inner.start_shutdown(*connection_id);
+            self.inner[*connection_id].shutdown_reason = Some(DisconnectReason::LocallyRequested);
+        }
+
+        if !to_shut_down.is_empty() {
+            self.update_unconnected_desired_after_shutdown(peer_id);
+        }
+
+        to_shut_down.len()
+    }
+
+    /// Must be called after one of the connections of the given peer has started shutting down.
+    ///
+    /// If the peer is desired and we have no connection or only shutting down connections to it,
+    /// adds the peer to `unconnected_desired` and removes it from
+    /// `connected_unopened_gossip_desired`.
+    fn update_unconnected_desired_after_shutdown(&mut self, peer_id: &PeerId) {
+        if self
+            .gossip_desired_peers
+            .range(
+                (
+                    peer_id.clone(),
+                    GossipKind::ConsensusTransactions,
+                    usize::min_value(),
+                )
+                    ..=(
+                        peer_id.clone(),
+                        GossipKind::ConsensusTransactions,
+                        usize::max_value(),
+                    ),
+            )
+            .next()
+            .is_none()
+        {
+            return;
+        }
+
+        if self
+            .connections_by_peer_id
+            .range(
+                (peer_id.clone(), ConnectionId::min_value())
+                    ..=(peer_id.clone(), ConnectionId::max_value()),
+            )
+            .any(|(_, connection_id)| {
+                let state = self.inner.connection_state(*connection_id);
+                !state.shutting_down
+            })
+        {
+            return;
+        }
+
+        self.unconnected_desired.insert(peer_id.clone());
+        for (_, _, chain_index) in self.gossip_desired_peers.range(
+            (
+                peer_id.clone(),
+                GossipKind::ConsensusTransactions,
+                usize::min_value(),
+            )
+                ..=(
+                    peer_id.clone(),
+                    GossipKind::ConsensusTransactions,
+                    usize::max_value(),
+                ),
+        ) {
+            self.connected_unopened_gossip_desired.remove(&(
+                peer_id.clone(),
+                ChainId(*chain_index),
+                GossipKind::ConsensusTransactions,
+            ));
+        }
+    }
+
     /// Returns the number of connections, both handshaking or established.
     pub fn num_connections(&self) -> usize {
         self.inner.len()
@@ -874,6 +2191,43 @@ where
         &self.inner[id].address
     }
 
+    /// Returns whether the given connection has been initiated locally or by the remote, as
+    /// indicated by the handshake kind passed to [`ChainNetwork::add_single_stream_connection`]
+    /// or [`ChainNetwork::add_multi_stream_connection`].
+    ///
+    /// # Panic
+    ///
+    /// Panics if the [`ConnectionId`] is invalid.
+    ///
+    pub fn connection_direction(&self, id: ConnectionId) -> ConnectionDirection {
+        self.inner[id].direction
+    }
+
+    /// Returns `true` if the given connection is in the process of shutting down, for example
+    /// following a call to [`ChainNetwork::start_shutdown`].
+    ///
+    /// # Panic
+    ///
+    /// Panics if the [`ConnectionId`] is invalid.
+    ///
+    pub fn is_connection_shutting_down(&self, id: ConnectionId) -> bool {
+        self.inner.connection_state(id).shutting_down
+    }
+
+    /// Returns the estimated round-trip time of the given connection, as measured by the pings
+    /// that are automatically sent on it.
+    ///
+    /// The estimate is a moving average, where each successful ping accounts for one eighth of
+    /// the value. Returns `None` if no ping has succeeded yet on this connection.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the [`ConnectionId`] is invalid.
+    ///
+    pub fn connection_ping_rtt(&self, id: ConnectionId) -> Option<Duration> {
+        self.inner[id].ping_rtt_estimate
+    }
+
     /// Pulls a message that must be sent to a connection.
     ///
     /// The message must be passed to [`SingleStreamConnectionTask::inject_coordinator_message`]
@@ -888,6 +2242,24 @@ where
         self.inner.pull_message_to_connection()
     }
 
+    /// Similar to [`ChainNetwork::pull_message_to_connection`], but pulls at most `max`
+    /// consecutive messages destined to the same connection at once.
+    ///
+    /// The messages must be passed, in the order in which they are returned, to
+    /// [`SingleStreamConnectionTask::inject_coordinator_message`] or
+    /// [`MultiStreamConnectionTask::inject_coordinator_message`] in the appropriate connection.
+    ///
+    /// # Panic
+    ///
+    /// Panics if `max` is 0.
+    ///
+    pub fn pull_messages_to_connection(
+        &mut self,
+        max: usize,
+    ) -> Option<(ConnectionId, Vec<CoordinatorToConnection>)> {
+        self.inner.pull_messages_to_connection(max)
+    }
+
     /// Injects into the state machine a message generated by
     /// [`SingleStreamConnectionTask::pull_message_to_coordinator`] or
     /// [`MultiStreamConnectionTask::pull_message_to_coordinator`].
@@ -900,7 +2272,38 @@ where
     }
 
     /// Returns the next event produced by the service.
-    pub fn next_event(&mut self) -> Option<Event> {
+    ///
+    /// `now` is used in order to measure the time it takes to answer inbound requests (see
+    /// [`ChainNetwork::inbound_requests_stats`]) and in order to lift the bans that have expired
+    /// (see [`ChainNetwork::ban_peer`]).
+    pub fn next_event(&mut self, now: &TNow) -> Option<Event> {
+        self.lift_expired_bans(now);
+        self.lift_expired_protocol_refusals(now);
+
+        // Dropped notifications are reported before any other event, so that a continuous flow
+        // of other events can't delay them indefinitely. The drops that happen between two calls
+        // to this function are grouped together.
+        if let Some(&substream_id) = self.notifications_dropped.keys().next() {
+            let dropped = self.notifications_dropped.remove(&substream_id).unwrap();
+            let (chain_index, protocol) = match dropped.protocol {
+                NotificationsProtocol::BlockAnnounces { chain_index } => {
+                    (chain_index, GossipNotificationsProtocol::BlockAnnounces)
+                }
+                NotificationsProtocol::Transactions { chain_index } => {
+                    (chain_index, GossipNotificationsProtocol::Transactions)
+                }
+                NotificationsProtocol::Grandpa { chain_index } => {
+                    (chain_index, GossipNotificationsProtocol::Grandpa)
+                }
+            };
+            return Some(Event::NotificationsDropped {
+                peer_id: dropped.peer_id,
+                chain_id: ChainId(chain_index),
+                protocol,
+                count: dropped.count,
+            });
+        }
+
         loop {
             let inner_event = self.inner.next_event()?;
             match inner_event {
@@ -916,7 +2319,11 @@ where
                         peer_id_refmut @ None => {
                             self.unconnected_desired.remove(&actual_peer_id);
                             *peer_id_refmut = Some(actual_peer_id.clone());
-                        }
+                            let _was_inserted = self
+                                .connections_by_peer_id
+                                .insert((actual_peer_id.clone(), id));
+                            debug_assert!(_was_inserted);
+                        }
                         Some(peer_id_refmut) => {
                             // The actual PeerId doesn't match the expected PeerId.
                             let expected_peer_id =
@@ -972,7 +2379,69 @@ where
 
                     debug_assert!(!self.unconnected_desired.contains(&actual_peer_id));
 
-                    // TODO: limit the number of connections per peer?
+                    self.connections_opened += 1;
+
+                    // If the limit to the number of connections per peer is reached, one
+                    // connection must be shut down. In order for both sides of a simultaneous
+                    // dial to agree on which connection to keep, the connections that have been
+                    // dialed by the peer with the lowest `PeerId` are preferred. Between two
+                    // connections in the same direction, the one whose handshake has finished
+                    // first is kept in order to not interrupt the substreams that are open on it.
+                    // The local `PeerId` used for this comparison is the one the remote knows,
+                    // which is the one of the connection that has just finished its handshake.
+                    if let Some(max_connections_per_peer) = self.max_connections_per_peer {
+                        let local_peer_id = PeerId::from_public_key(&peer_id::PublicKey::Ed25519(
+                            self.inner[id].local_ed25519_public_key,
+                        ));
+                        let preferred_direction = if local_peer_id < actual_peer_id {
+                            ConnectionDirection::Outbound
+                        } else {
+                            ConnectionDirection::Inbound
+                        };
+
+                        let mut num_healthy_connections = 0;
+                        let mut non_preferred_connection = None;
+                        for (_, connection_id) in self.connections_by_peer_id.range(
+                            (actual_peer_id.clone(), ConnectionId::min_value())
+                                ..=(actual_peer_id.clone(), ConnectionId::max_value()),
+                        ) {
+                            let state = self.inner.connection_state(*connection_id);
+                            if *connection_id == id || !state.established || state.shutting_down {
+                                continue;
+                            }
+
+                            num_healthy_connections += 1;
+                            if self.inner[*connection_id].direction != preferred_direction {
+                                non_preferred_connection = Some(*connection_id);
+                            }
+                        }
+
+                        if num_healthy_connections >= max_connections_per_peer.get() {
+                            match non_preferred_connection {
+                                Some(to_shut_down)
+                                    if self.inner[id].direction == preferred_direction =>
+                                {
+                                    // The connection that has just finished its handshake
+                                    // replaces an existing one. A `Disconnected` event will
+                                    // later be generated for the replaced connection.
+                                    self.inner.start_shutdown(to_shut_down);
+                                    self.inner[to_shut_down].shutdown_reason =
+                                        Some(DisconnectReason::LocallyRequested);
+                                }
+                                _ => {
+                                    self.inner.start_shutdown(id);
+                                    self.inner[id].shutdown_reason =
+                                        Some(DisconnectReason::LocallyRequested);
+                                    return Some(Event::SupernumeraryConnection {
+                                        id,
+                                        direction: self.inner[id].direction,
+                                        expected_peer_id,
+                                        peer_id: actual_peer_id,
+                                    });
+                                }
+                            }
+                        }
+                    }
 
                     for (_, _, chain_id) in self.gossip_desired_peers.range(
                         (
@@ -987,28 +2456,13 @@ where
                             ),
                     ) {
                         if self
-                            .notification_substreams_by_peer_id
-                            .range(
-                                (
-                                    NotificationsProtocol::BlockAnnounces {
-                                        chain_index: *chain_id,
-                                    },
-                                    actual_peer_id.clone(),
-                                    SubstreamDirection::Out,
-                                    NotificationsSubstreamState::min_value(),
-                                    SubstreamId::min_value(),
-                                )
-                                    ..=(
-                                        NotificationsProtocol::BlockAnnounces {
-                                            chain_index: *chain_id,
-                                        },
-                                        actual_peer_id.clone(),
-                                        SubstreamDirection::Out,
-                                        NotificationsSubstreamState::max_value(),
-                                        SubstreamId::max_value(),
-                                    ),
+                            .gossip_link_substream(
+                                NotificationsProtocol::BlockAnnounces {
+                                    chain_index: *chain_id,
+                                },
+                                &actual_peer_id,
+                                SubstreamDirection::Out,
                             )
-                            .next()
                             .is_none()
                         {
                             self.connected_unopened_gossip_desired.insert((
@@ -1021,6 +2475,7 @@ where
 
                     return Some(Event::HandshakeFinished {
                         id,
+                        direction: self.inner[id].direction,
                         expected_peer_id,
                         peer_id: actual_peer_id,
                     });
@@ -1028,67 +2483,42 @@ where
 
                 collection::Event::PingOutFailed { id }
                 | collection::Event::StartShutdown { id, .. } => {
-                    if let collection::Event::PingOutFailed { .. } = inner_event {
-                        self.inner.start_shutdown(id);
-                    }
-
-                    // TODO: IMPORTANT this event should be turned into `NewOutboundSubstreamsForbidden` and the `reason` removed; see <https://github.com/smol-dot/smoldot/pull/391>
-
-                    let connection_info = &self.inner[id];
-
-                    // If peer is desired, and we have no connection or only shutting down
-                    // connections, add peer to `unconnected_desired` and remove it from
-                    // `connected_unopened_gossip_desired`.
-                    if let Some(peer_id) = &connection_info.peer_id {
-                        if self
-                            .gossip_desired_peers
-                            .range(
-                                (
-                                    peer_id.clone(),
-                                    GossipKind::ConsensusTransactions,
-                                    usize::min_value(),
-                                )
-                                    ..=(
-                                        peer_id.clone(),
-                                        GossipKind::ConsensusTransactions,
-                                        usize::max_value(),
-                                    ),
-                            )
-                            .count()
-                            != 0
-                        {
-                            if !self
-                                .connections_by_peer_id
-                                .range(
-                                    (peer_id.clone(), ConnectionId::min_value())
-                                        ..=(peer_id.clone(), ConnectionId::max_value()),
-                                )
-                                .any(|(_, connection_id)| {
-                                    let state = self.inner.connection_state(*connection_id);
-                                    !state.shutting_down
-                                })
+                    let shutdown_reason = match inner_event {
+                        collection::Event::PingOutFailed { .. } => {
+                            let connection_info = &mut self.inner[id];
+                            connection_info.consecutive_ping_failures += 1;
+                            if connection_info.consecutive_ping_failures
+                                < self.max_ping_failures.get()
                             {
-                                self.unconnected_desired.insert(peer_id.clone());
-                                for (_, _, chain_index) in self.gossip_desired_peers.range(
-                                    (
-                                        peer_id.clone(),
-                                        GossipKind::ConsensusTransactions,
-                                        usize::min_value(),
-                                    )
-                                        ..=(
-                                            peer_id.clone(),
-                                            GossipKind::ConsensusTransactions,
-                                            usize::max_value(),
-                                        ),
-                                ) {
-                                    self.connected_unopened_gossip_desired.remove(&(
-                                        peer_id.clone(),
-                                        ChainId(*chain_index),
-                                        GossipKind::ConsensusTransactions,
-                                    ));
-                                }
+                                continue;
                             }
+
+                            self.inner.start_shutdown(id);
+                            DisconnectReason::PingFailures
                         }
+                        collection::Event::StartShutdown { reason, .. } => match reason {
+                            collection::ShutdownCause::CleanShutdown => {
+                                DisconnectReason::CleanShutdown
+                            }
+                            collection::ShutdownCause::RemoteReset => DisconnectReason::RemoteReset,
+                            collection::ShutdownCause::ProtocolError(error) => {
+                                DisconnectReason::ProtocolError(error)
+                            }
+                            collection::ShutdownCause::HandshakeError(error) => {
+                                DisconnectReason::HandshakeError(error)
+                            }
+                            collection::ShutdownCause::HandshakeTimeout(stage) => {
+                                DisconnectReason::HandshakeTimeout(stage)
+                            }
+                        },
+                        _ => unreachable!(),
+                    };
+                    self.inner[id].shutdown_reason = Some(shutdown_reason);
+
+                    // TODO: IMPORTANT this event should be turned into `NewOutboundSubstreamsForbidden` and the `reason` removed; see <https://github.com/smol-dot/smoldot/pull/391>
+
+                    if let Some(peer_id) = self.inner[id].peer_id.clone() {
+                        self.update_unconnected_desired_after_shutdown(&peer_id);
                     }
                 }
 
@@ -1103,32 +2533,63 @@ where
 
                     debug_assert!(connection_info.peer_id.is_some() || !was_established);
 
+                    if was_established {
+                        self.connections_closed += 1;
+                    }
+
                     if let Some(peer_id) = &connection_info.peer_id {
                         let _was_removed =
                             self.connections_by_peer_id.remove(&(peer_id.clone(), id));
                         debug_assert!(_was_removed);
                     }
 
-                    // TODO: IMPORTANT this event should indicate a clean shutdown, a pre-handshake interruption, a protocol error, a reset, etc. and should get a `reason`; see <https://github.com/smol-dot/smoldot/pull/391>
+                    self.remove_protocol_refusals(id);
+
+                    // All the code paths that start the shutdown of a connection store the reason
+                    // of the shutdown.
+                    let reason = connection_info
+                        .shutdown_reason
+                        .unwrap_or_else(|| unreachable!());
 
                     if was_established {
                         return Some(Event::Disconnected {
                             id,
                             address: connection_info.address,
+                            direction: connection_info.direction,
                             peer_id: connection_info.peer_id.unwrap(),
+                            reason,
                         });
                     } else {
                         return Some(Event::PreHandshakeDisconnected {
                             id,
                             address: connection_info.address,
+                            direction: connection_info.direction,
                             expected_peer_id: connection_info.peer_id,
+                            reason,
                         });
                     }
                 }
 
-                collection::Event::InboundError { .. } => {
-                    // TODO: report the error for diagnostic purposes, but revisit the concept of "InboundError"
-                    continue;
+                collection::Event::InboundError { id, error } => {
+                    // In order to not flood the API user, the number of errors reported is
+                    // limited.
+                    match &mut self.inbound_protocol_errors_reports {
+                        Some((period_start, num_reports))
+                            if *now
+                                < period_start.clone() + INBOUND_PROTOCOL_ERRORS_REPORTS_PERIOD =>
+                        {
+                            if *num_reports >= MAX_INBOUND_PROTOCOL_ERRORS_REPORTS {
+                                continue;
+                            }
+                            *num_reports += 1;
+                        }
+                        reports => *reports = Some((now.clone(), 1)),
+                    }
+
+                    // Inbound substreams can only be opened on established connections, whose
+                    // peer is always known.
+                    let peer_id = self.inner[id].peer_id.clone().unwrap();
+                    return Some(Event::InboundProtocolError { peer_id, error });
                 }
 
                 collection::Event::InboundNegotiated {
@@ -1141,15 +2602,16 @@ where
                     // If accepted, we must also save the protocol somewhere in `self` in order to
                     // load it later once things happen on this substream.
                     match self.recognize_protocol(&protocol_name) {
-                        Ok(protocol) => {
+                        Ok((protocol, schema)) => {
                             let inbound_type = match protocol {
                                 Protocol::Identify => collection::InboundTy::Request {
                                     request_max_size: None,
                                 },
                                 Protocol::Ping => collection::InboundTy::Ping,
-                                Protocol::BlockAnnounces { .. } => {
+                                Protocol::BlockAnnounces { chain_index } => {
                                     collection::InboundTy::Notifications {
-                                        max_handshake_size: 1024 * 1024, // TODO: arbitrary
+                                        max_handshake_size: self.chains[chain_index]
+                                            .max_notifications_handshake_size,
                                     }
                                 }
                                 Protocol::Transactions { .. } => {
@@ -1177,10 +2639,53 @@ where
                                         request_max_size: Some(1024),
                                     }
                                 }
-                                Protocol::Sync { .. } => {
+                                Protocol::Sync { chain_index } => {
+                                    self.chains[chain_index]
+                                        .inbound_blocks_requests_stats
+                                        .rejected += 1;
                                     self.inner.reject_inbound(substream_id);
                                     continue;
                                 }
+                                Protocol::Custom {
+                                    chain_index,
+                                    protocol_index,
+                                } => {
+                                    let custom_protocol = &mut self.chains[chain_index]
+                                        .custom_protocols[protocol_index];
+                                    if !custom_protocol.config.allow_inbound_requests {
+                                        custom_protocol.inbound_requests_stats.rejected += 1;
+                                        self.inner.reject_inbound(substream_id);
+                                        continue;
+                                    }
+                                    if custom_protocol.config.stream_inbound_requests {
+                                        collection::InboundTy::RequestStreaming {
+                                            request_max_size: custom_protocol
+                                                .config
+                                                .max_request_size,
+                                        }
+                                    } else {
+                                        collection::InboundTy::Request {
+                                            request_max_size: Some(
+                                                custom_protocol.config.max_request_size,
+                                            ),
+                                        }
+                                    }
+                                }
+                                Protocol::CustomNotifications {
+                                    chain_index,
+                                    protocol_index,
+                                } => {
+                                    let config = &self.chains[chain_index]
+                                        .custom_notifications_protocols[protocol_index]
+                                        .config;
+                                    if !config.allow_inbound_substreams {
+                                        self.inner.reject_inbound(substream_id);
+                                        continue;
+                                    }
+                                    collection::InboundTy::Notifications {
+                                        max_handshake_size: config.max_handshake_size,
+                                    }
+                                }
 
                                 // TODO: protocols that are not supported
                                 Protocol::LightUnknown { .. }
@@ -1203,6 +2708,7 @@ where
                                 SubstreamInfo {
                                     connection_id: id,
                                     protocol,
+                                    schema,
                                 },
                             );
                             debug_assert!(_prev_value.is_none());
@@ -1239,100 +2745,198 @@ where
                         .remove(&substream_id)
                         .unwrap_or_else(|| unreachable!());
 
-                    // Decode/verify the response.
-                    let response = match substream_info.protocol {
-                        Protocol::Identify => todo!(), // TODO: we don't send identify requests yet, so it's fine to leave this unimplemented
-                        Protocol::Sync { .. } => RequestResult::Blocks(
-                            response
-                                .map_err(BlocksRequestError::Request)
-                                .and_then(|response| {
-                                    protocol::decode_block_response(&response)
-                                        .map_err(BlocksRequestError::Decode)
-                                }),
-                        ),
-                        Protocol::LightUnknown { .. } => unreachable!(),
-                        Protocol::LightStorage { .. } => RequestResult::StorageProof(
-                            response
-                                .map_err(StorageProofRequestError::Request)
-                                .and_then(|payload| {
-                                    match protocol::decode_storage_or_call_proof_response(
-                                        protocol::StorageOrCallProof::StorageProof,
-                                        &payload,
-                                    ) {
-                                        Err(err) => Err(StorageProofRequestError::Decode(err)),
-                                        Ok(None) => {
-                                            Err(StorageProofRequestError::RemoteCouldntAnswer)
+                    let protocol_metrics = self
+                        .protocols_metrics
+                        .entry(substream_info.protocol)
+                        .or_default();
+                    if let Ok(payload) = &response {
+                        protocol_metrics.requests_succeeded += 1;
+                        protocol_metrics.record_received(payload.len());
+                    } else {
+                        protocol_metrics.requests_failed += 1;
+                    }
+
+                    // Requests that time out or that fail because of a misbehavior of the remote
+                    // lower the reputation of the remote.
+                    if let Err(error) = &response {
+                        let reputation_change = if matches!(
+                            error,
+                            RequestError::Substream(established::RequestError::Timeout)
+                        ) {
+                            Some(REQUEST_TIMEOUT_REPUTATION_CHANGE)
+                        } else if error.is_protocol_error() {
+                            Some(PROTOCOL_ERROR_REPUTATION_CHANGE)
+                        } else {
+                            None
+                        };
+                        if let (Some(reputation_change), Some(peer_id)) = (
+                            reputation_change,
+                            self.inner[substream_info.connection_id].peer_id.clone(),
+                        ) {
+                            self.report_peer(now, &peer_id, reputation_change);
+                        }
+                    }
+
+                    // Protocols that the remote refuses to negotiate aren't attempted again on
+                    // the same connection for a while.
+                    if let (
+                        Err(RequestError::Substream(
+                            established::RequestError::ProtocolNotAvailable,
+                        )),
+                        Some(cooldown),
+                    ) = (&response, self.refused_protocols_cooldown)
+                    {
+                        let protocol_name = self.protocol_name(substream_info.protocol);
+                        self.insert_protocol_refusal(
+                            substream_info.connection_id,
+                            protocol_name,
+                            now.clone() + cooldown,
+                        );
+                    }
+
+                    // Decode/verify the response. Outbound requests always use the preferred
+                    // version of the protocol.
+                    let codec::ProtocolCodec::Sync {
+                        decode_response: sync_decode_response,
+                        ..
+                    } = codec::preferred_protocol_schema(codec::ProtocolKind::Sync).codec
+                    else {
+                        unreachable!()
+                    };
+                    let codec::ProtocolCodec::Light {
+                        decode_response: light_decode_response,
+                    } = codec::preferred_protocol_schema(codec::ProtocolKind::Light).codec
+                    else {
+                        unreachable!()
+                    };
+                    let codec::ProtocolCodec::Kad {
+                        decode_response: kad_decode_response,
+                    } = codec::preferred_protocol_schema(codec::ProtocolKind::Kad).codec
+                    else {
+                        unreachable!()
+                    };
+                    let codec::ProtocolCodec::SyncWarp {
+                        decode_response: sync_warp_decode_response,
+                    } = codec::preferred_protocol_schema(codec::ProtocolKind::SyncWarp).codec
+                    else {
+                        unreachable!()
+                    };
+                    let codec::ProtocolCodec::State {
+                        decode_response: state_decode_response,
+                    } = codec::preferred_protocol_schema(codec::ProtocolKind::State).codec
+                    else {
+                        unreachable!()
+                    };
+                    let response =
+                        match substream_info.protocol {
+                            Protocol::Identify => {
+                                // Requests can only happen on connections after their handshake
+                                // phase is finished, therefore their `PeerId` is known.
+                                let peer_id = self.inner[substream_info.connection_id]
+                                    .peer_id
+                                    .as_ref()
+                                    .unwrap_or_else(|| unreachable!());
+                                RequestResult::Identify(
+                                    response.map_err(IdentifyRequestError::Request).and_then(
+                                        |payload| {
+                                            decode_identify_info(
+                                                peer_id,
+                                                &payload,
+                                                &self.address_sanitize_policy,
+                                            )
+                                        },
+                                    ),
+                                )
+                            }
+                            Protocol::Sync { .. } => RequestResult::Blocks(
+                                response.map_err(BlocksRequestError::Request).and_then(
+                                    |response| {
+                                        sync_decode_response(&response)
+                                            .map_err(BlocksRequestError::Decode)
+                                    },
+                                ),
+                            ),
+                            Protocol::LightUnknown { .. } => unreachable!(),
+                            Protocol::LightStorage { .. } => RequestResult::StorageProof(
+                                response
+                                    .map_err(StorageProofRequestError::Request)
+                                    .and_then(|payload| {
+                                        match light_decode_response(
+                                            codec::StorageOrCallProof::StorageProof,
+                                            &payload,
+                                        ) {
+                                            Err(err) => Err(StorageProofRequestError::Decode(err)),
+                                            Ok(None) => {
+                                                Err(StorageProofRequestError::RemoteCouldntAnswer)
+                                            }
+                                            Ok(Some(_)) => Ok(EncodedMerkleProof(
+                                                payload,
+                                                codec::StorageOrCallProof::StorageProof,
+                                            )),
                                         }
-                                        Ok(Some(_)) => Ok(EncodedMerkleProof(
-                                            payload,
-                                            protocol::StorageOrCallProof::StorageProof,
-                                        )),
-                                    }
-                                }),
-                        ),
-                        Protocol::LightCall { .. } => {
-                            RequestResult::CallProof(
+                                    }),
+                            ),
+                            Protocol::LightCall { .. } => RequestResult::CallProof(
                                 response.map_err(CallProofRequestError::Request).and_then(
-                                    |payload| match protocol::decode_storage_or_call_proof_response(
-                                        protocol::StorageOrCallProof::CallProof,
+                                    |payload| match light_decode_response(
+                                        codec::StorageOrCallProof::CallProof,
                                         &payload,
                                     ) {
                                         Err(err) => Err(CallProofRequestError::Decode(err)),
                                         Ok(None) => Err(CallProofRequestError::RemoteCouldntAnswer),
                                         Ok(Some(_)) => Ok(EncodedMerkleProof(
                                             payload,
-                                            protocol::StorageOrCallProof::CallProof,
+                                            codec::StorageOrCallProof::CallProof,
                                         )),
                                     },
                                 ),
-                            )
-                        }
-                        Protocol::Kad { .. } => RequestResult::KademliaFindNode(
-                            response
-                                .map_err(KademliaFindNodeError::RequestFailed)
-                                .and_then(|payload| {
-                                    match protocol::decode_find_node_response(&payload) {
+                            ),
+                            Protocol::Kad { .. } => RequestResult::KademliaFindNode(
+                                response
+                                    .map_err(KademliaFindNodeError::RequestFailed)
+                                    .and_then(|payload| match kad_decode_response(&payload) {
                                         Err(err) => Err(KademliaFindNodeError::DecodeError(err)),
                                         Ok(nodes) => Ok(nodes),
-                                    }
-                                }),
-                        ),
-                        Protocol::SyncWarp { chain_index } => RequestResult::GrandpaWarpSync(
-                            response
-                                .map_err(GrandpaWarpSyncRequestError::Request)
-                                .and_then(|message| {
-                                    if let Err(err) = protocol::decode_grandpa_warp_sync_response(
-                                        &message,
-                                        self.chains[chain_index].block_number_bytes,
-                                    ) {
-                                        Err(GrandpaWarpSyncRequestError::Decode(err))
-                                    } else {
-                                        Ok(EncodedGrandpaWarpSyncResponse {
-                                            message,
-                                            block_number_bytes: self.chains[chain_index]
-                                                .block_number_bytes,
-                                        })
-                                    }
-                                }),
-                        ),
-                        Protocol::State { .. } => RequestResult::State(
-                            response
-                                .map_err(StateRequestError::Request)
-                                .and_then(|payload| {
-                                    if let Err(err) = protocol::decode_state_response(&payload) {
-                                        Err(StateRequestError::Decode(err))
-                                    } else {
-                                        Ok(EncodedStateResponse(payload))
-                                    }
-                                }),
-                        ),
-
-                        // The protocols below aren't request-response protocols.
-                        Protocol::Ping
-                        | Protocol::BlockAnnounces { .. }
-                        | Protocol::Transactions { .. }
-                        | Protocol::Grandpa { .. } => unreachable!(),
-                    };
+                                    }),
+                            ),
+                            Protocol::SyncWarp { chain_index } => RequestResult::GrandpaWarpSync(
+                                response
+                                    .map_err(GrandpaWarpSyncRequestError::Request)
+                                    .and_then(|message| {
+                                        if let Err(err) = sync_warp_decode_response(
+                                            &message,
+                                            self.chains[chain_index].block_number_bytes,
+                                        ) {
+                                            Err(GrandpaWarpSyncRequestError::Decode(err))
+                                        } else {
+                                            Ok(EncodedGrandpaWarpSyncResponse {
+                                                message,
+                                                block_number_bytes: self.chains[chain_index]
+                                                    .block_number_bytes,
+                                            })
+                                        }
+                                    }),
+                            ),
+                            Protocol::State { .. } => RequestResult::State(
+                                response
+                                    .map_err(StateRequestError::Request)
+                                    .and_then(|payload| {
+                                        if let Err(err) = state_decode_response(&payload) {
+                                            Err(StateRequestError::Decode(err))
+                                        } else {
+                                            Ok(EncodedStateResponse(payload))
+                                        }
+                                    }),
+                            ),
+                            Protocol::Custom { .. } => RequestResult::Custom(response),
+
+                            // The protocols below aren't request-response protocols.
+                            Protocol::Ping
+                            | Protocol::BlockAnnounces { .. }
+                            | Protocol::Transactions { .. }
+                            | Protocol::Grandpa { .. }
+                            | Protocol::CustomNotifications { .. } => unreachable!(),
+                        };
 
                     return Some(Event::RequestResult {
                         substream_id,
@@ -1340,6 +2944,84 @@ where
                     });
                 }
 
+                collection::Event::RequestInStart {
+                    substream_id,
+                    request_size,
+                } => {
+                    // Only custom protocols accept inbound substreams with
+                    // `collection::InboundTy::RequestStreaming`.
+                    let substream_info = self
+                        .substreams
+                        .get(&substream_id)
+                        .unwrap_or_else(|| unreachable!());
+                    let Protocol::Custom {
+                        chain_index,
+                        protocol_index,
+                    } = substream_info.protocol
+                    else {
+                        unreachable!()
+                    };
+                    // Requests can only happen on connections after their handshake phase is
+                    // finished, therefore their `PeerId` is known.
+                    let peer_id = self.inner[substream_info.connection_id]
+                        .peer_id
+                        .clone()
+                        .unwrap_or_else(|| unreachable!());
+
+                    // An empty request has been fully received.
+                    if request_size == 0 {
+                        self.chains[chain_index].custom_protocols[protocol_index]
+                            .inbound_requests_stats
+                            .received += 1;
+                        self.inbound_requests_received
+                            .insert(substream_id, now.clone());
+                    }
+
+                    return Some(Event::CustomRequestInStart {
+                        peer_id,
+                        chain_id: ChainId(chain_index),
+                        protocol_index,
+                        request_size,
+                        substream_id,
+                    });
+                }
+
+                collection::Event::RequestInChunk {
+                    substream_id,
+                    chunk,
+                    is_last,
+                } => {
+                    let substream_info = self
+                        .substreams
+                        .get(&substream_id)
+                        .unwrap_or_else(|| unreachable!());
+                    self.protocols_metrics
+                        .entry(substream_info.protocol)
+                        .or_default()
+                        .record_received(chunk.len());
+
+                    if is_last {
+                        let Protocol::Custom {
+                            chain_index,
+                            protocol_index,
+                        } = substream_info.protocol
+                        else {
+                            unreachable!()
+                        };
+                        self.chains[chain_index].custom_protocols[protocol_index]
+                            .inbound_requests_stats
+                            .received += 1;
+                        self.inbound_requests_received
+                            .insert(substream_id, now.clone());
+                    }
+
+                    return Some(Event::CustomRequestInChunk {
+                        substream_id,
+                        chunk,
+                        is_last,
+                    });
+                }
+
                 collection::Event::RequestIn {
                     substream_id,
                     request_payload,
@@ -1349,6 +3031,10 @@ where
                         .substreams
                         .get(&substream_id)
                         .unwrap_or_else(|| unreachable!());
+                    self.protocols_metrics
+                        .entry(substream_info.protocol)
+                        .or_default()
+                        .record_received(request_payload.len());
                     let connection_info = &self.inner[substream_info.connection_id];
                     // Requests can only happen on connections after their handshake phase is
                     // finished, therefore their `PeerId` is known.
@@ -1361,14 +3047,19 @@ where
                     match substream_info.protocol {
                         Protocol::Identify => {
                             if request_payload.is_empty() {
+                                self.inbound_identify_requests_stats.received += 1;
+                                self.inbound_requests_received
+                                    .insert(substream_id, now.clone());
                                 return Some(Event::IdentifyRequestIn {
                                     peer_id,
                                     substream_id,
                                 });
                             } else {
                                 // TODO: can this actually be reached? isn't the inner code going to refuse a bad request anyway due to no length prefix?
+                                self.inbound_identify_requests_stats.invalid += 1;
                                 let _ = self.substreams.remove(&substream_id);
                                 self.inner.respond_in_request(substream_id, Err(()));
+                                self.report_peer(now, &peer_id, PROTOCOL_ERROR_REPUTATION_CHANGE);
                                 return Some(Event::ProtocolError {
                                     peer_id,
                                     error: ProtocolError::BadIdentifyRequest,
@@ -1376,21 +3067,41 @@ where
                             }
                         }
                         Protocol::Sync { chain_index } => {
-                            match protocol::decode_block_request(
+                            let Some(codec::ProtocolSchema {
+                                codec: codec::ProtocolCodec::Sync { decode_request, .. },
+                                ..
+                            }) = substream_info.schema
+                            else {
+                                unreachable!()
+                            };
+                            match decode_request(
                                 self.chains[chain_index].block_number_bytes,
                                 &request_payload,
                             ) {
                                 Ok(config) => {
+                                    self.chains[chain_index]
+                                        .inbound_blocks_requests_stats
+                                        .received += 1;
+                                    self.inbound_requests_received
+                                        .insert(substream_id, now.clone());
                                     return Some(Event::BlocksRequestIn {
                                         peer_id,
                                         chain_id: ChainId(chain_index),
                                         config,
                                         substream_id,
-                                    })
+                                    });
                                 }
                                 Err(error) => {
+                                    self.chains[chain_index]
+                                        .inbound_blocks_requests_stats
+                                        .invalid += 1;
                                     let _ = self.substreams.remove(&substream_id);
                                     self.inner.respond_in_request(substream_id, Err(()));
+                                    self.report_peer(
+                                        now,
+                                        &peer_id,
+                                        PROTOCOL_ERROR_REPUTATION_CHANGE,
+                                    );
                                     return Some(Event::ProtocolError {
                                         peer_id,
                                         error: ProtocolError::BadBlocksRequest(error),
@@ -1398,14 +3109,44 @@ where
                                 }
                             }
                         }
+                        Protocol::Custom {
+                            chain_index,
+                            protocol_index,
+                        } => {
+                            self.chains[chain_index].custom_protocols[protocol_index]
+                                .inbound_requests_stats
+                                .received += 1;
+                            self.inbound_requests_received
+                                .insert(substream_id, now.clone());
+                            return Some(Event::CustomRequestIn {
+                                peer_id,
+                                chain_id: ChainId(chain_index),
+                                protocol_index,
+                                request: request_payload,
+                                substream_id,
+                            });
+                        }
                         // Any other protocol is declined when the protocol is negotiated.
                         _ => unreachable!(),
                     }
                 }
 
                 collection::Event::RequestInCancel { substream_id } => {
-                    let _was_in = self.substreams.remove(&substream_id);
-                    debug_assert!(_was_in.is_some());
+                    let substream_info = self
+                        .substreams
+                        .remove(&substream_id)
+                        .unwrap_or_else(|| unreachable!());
+                    if self
+                        .inbound_requests_received
+                        .remove(&substream_id)
+                        .is_some()
+                    {
+                        if let Some(stats) =
+                            self.inbound_requests_stats_mut(substream_info.protocol)
+                        {
+                            stats.cancelled += 1;
+                        }
+                    }
                     return Some(Event::RequestInCancel { substream_id });
                 }
 
@@ -1433,21 +3174,38 @@ where
                         .unwrap_or_else(|| unreachable!())
                         .clone();
 
-                    let _was_in = self.notification_substreams_by_peer_id.remove(&(
+                    // Custom notifications protocols aren't tied to the gossip links, and the
+                    // outcome is directly reported to the API user.
+                    if let Protocol::CustomNotifications {
+                        chain_index,
+                        protocol_index,
+                    } = substream_info.protocol
+                    {
+                        return Some(Event::CustomNotificationsOutResult {
+                            peer_id,
+                            chain_id: ChainId(chain_index),
+                            protocol_index,
+                            substream_id,
+                            result,
+                        });
+                    }
+
+                    let _removed = self.gossip_link_substream_remove(
                         substream_info.protocol.try_into().unwrap(),
-                        peer_id.clone(),
+                        &peer_id,
                         SubstreamDirection::Out,
-                        NotificationsSubstreamState::Pending,
-                        substream_id,
-                    ));
-                    debug_assert!(_was_in);
+                    );
+                    debug_assert_eq!(
+                        _removed,
+                        Some((NotificationsSubstreamState::Pending, substream_id))
+                    );
 
                     // The behaviour is very specific to the protocol.
                     match substream_info.protocol {
                         Protocol::BlockAnnounces { chain_index } => {
                             let result = match &result {
                                 Ok(handshake) => {
-                                    match protocol::decode_block_announces_handshake(
+                                    match codec::decode_block_announces_handshake(
                                         self.chains[chain_index].block_number_bytes,
                                         &handshake,
                                     ) {
@@ -1472,43 +3230,26 @@ where
 
                             match result {
                                 Ok(decoded_handshake) => {
-                                    let _was_inserted =
-                                        self.notification_substreams_by_peer_id.insert((
-                                            NotificationsProtocol::BlockAnnounces { chain_index },
-                                            peer_id.clone(),
-                                            SubstreamDirection::Out,
-                                            NotificationsSubstreamState::Open,
-                                            substream_id,
-                                        ));
-                                    debug_assert!(_was_inserted);
+                                    self.gossip_link_substream_insert(
+                                        NotificationsProtocol::BlockAnnounces { chain_index },
+                                        &peer_id,
+                                        SubstreamDirection::Out,
+                                        NotificationsSubstreamState::Open,
+                                        substream_id,
+                                    );
 
                                     if self
-                                        .notification_substreams_by_peer_id
-                                        .range(
-                                            (
-                                                NotificationsProtocol::Transactions { chain_index },
-                                                peer_id.clone(),
-                                                SubstreamDirection::Out,
-                                                NotificationsSubstreamState::min_value(),
-                                                SubstreamId::min_value(),
-                                            )
-                                                ..=(
-                                                    NotificationsProtocol::Transactions {
-                                                        chain_index,
-                                                    },
-                                                    peer_id.clone(),
-                                                    SubstreamDirection::Out,
-                                                    NotificationsSubstreamState::max_value(),
-                                                    SubstreamId::max_value(),
-                                                ),
+                                        .gossip_link_substream(
+                                            NotificationsProtocol::Transactions { chain_index },
+                                            &peer_id,
+                                            SubstreamDirection::Out,
                                         )
-                                        .next()
                                         .is_none()
                                     {
                                         let new_substream_id = self.inner.open_out_notifications(
                                             connection_id,
-                                            protocol::encode_protocol_name_string(
-                                                protocol::ProtocolName::Transactions {
+                                            codec::encode_protocol_name_string(
+                                                codec::ProtocolName::Transactions {
                                                     genesis_hash: self.chains[chain_index]
                                                         .genesis_hash,
                                                     fork_id: self.chains[chain_index]
@@ -1516,9 +3257,10 @@ where
                                                         .as_deref(),
                                                 },
                                             ),
-                                            Duration::from_secs(10), // TODO: arbitrary
+                                            self.chains[chain_index].notifications_open_timeout,
                                             Vec::new(),
-                                            128, // TODO: arbitrary
+                                            self.chains[chain_index]
+                                                .max_notifications_handshake_size,
                                         );
 
                                         self.substreams.insert(
@@ -1526,46 +3268,32 @@ where
                                             SubstreamInfo {
                                                 connection_id,
                                                 protocol: Protocol::Transactions { chain_index },
+                                                schema: None,
                                             },
                                         );
 
-                                        self.notification_substreams_by_peer_id.insert((
+                                        self.gossip_link_substream_insert(
                                             NotificationsProtocol::Transactions { chain_index },
-                                            peer_id.clone(),
+                                            &peer_id,
                                             SubstreamDirection::Out,
                                             NotificationsSubstreamState::Pending,
                                             new_substream_id,
-                                        ));
+                                        );
                                     }
 
                                     if self.chains[chain_index].grandpa_protocol_config.is_some()
                                         && self
-                                            .notification_substreams_by_peer_id
-                                            .range(
-                                                (
-                                                    NotificationsProtocol::Grandpa { chain_index },
-                                                    peer_id.clone(),
-                                                    SubstreamDirection::Out,
-                                                    NotificationsSubstreamState::min_value(),
-                                                    SubstreamId::min_value(),
-                                                )
-                                                    ..=(
-                                                        NotificationsProtocol::Grandpa {
-                                                            chain_index,
-                                                        },
-                                                        peer_id.clone(),
-                                                        SubstreamDirection::Out,
-                                                        NotificationsSubstreamState::max_value(),
-                                                        SubstreamId::max_value(),
-                                                    ),
+                                            .gossip_link_substream(
+                                                NotificationsProtocol::Grandpa { chain_index },
+                                                &peer_id,
+                                                SubstreamDirection::Out,
                                             )
-                                            .next()
                                             .is_none()
                                     {
                                         let new_substream_id = self.inner.open_out_notifications(
                                             connection_id,
-                                            protocol::encode_protocol_name_string(
-                                                protocol::ProtocolName::Grandpa {
+                                            codec::encode_protocol_name_string(
+                                                codec::ProtocolName::Grandpa {
                                                     genesis_hash: self.chains[chain_index]
                                                         .genesis_hash,
                                                     fork_id: self.chains[chain_index]
@@ -1573,9 +3301,10 @@ where
                                                         .as_deref(),
                                                 },
                                             ),
-                                            Duration::from_secs(10), // TODO: arbitrary
+                                            self.chains[chain_index].notifications_open_timeout,
                                             self.chains[chain_index].role.scale_encoding().to_vec(),
-                                            1024 * 1024, // TODO: arbitrary
+                                            self.chains[chain_index]
+                                                .max_notifications_handshake_size,
                                         );
 
                                         self.substreams.insert(
@@ -1583,22 +3312,39 @@ where
                                             SubstreamInfo {
                                                 connection_id,
                                                 protocol: Protocol::Grandpa { chain_index },
+                                                schema: None,
                                             },
                                         );
 
-                                        self.notification_substreams_by_peer_id.insert((
+                                        self.gossip_link_substream_insert(
                                             NotificationsProtocol::Grandpa { chain_index },
-                                            peer_id.clone(),
+                                            &peer_id,
                                             SubstreamDirection::Out,
                                             NotificationsSubstreamState::Pending,
                                             new_substream_id,
+                                        );
+                                    }
+
+                                    let connection_direction = self.inner[connection_id].direction;
+                                    if let Some(link) = self
+                                        .gossip_links
+                                        .get_mut(&GossipLinkKey(chain_index, &peer_id))
+                                    {
+                                        link.info.connection_direction = Some(connection_direction);
+                                        link.info.consecutive_open_failures = 0;
+                                        link.best_block = Some((
+                                            decoded_handshake.best_number,
+                                            *decoded_handshake.best_hash,
                                         ));
                                     }
 
+                                    self.debug_check_gossip_link(chain_index, &peer_id);
+
                                     return Some(Event::GossipConnected {
                                         peer_id,
                                         chain_id: ChainId(chain_index),
                                         kind: GossipKind::ConsensusTransactions,
+                                        connection_direction,
                                         role: decoded_handshake.role,
                                         best_number: decoded_handshake.best_number,
                                         best_hash: *decoded_handshake.best_hash,
@@ -1622,30 +3368,16 @@ where
                                             peer_id.clone(),
                                         ))
                                     {
-                                        debug_assert!(self
-                                            .notification_substreams_by_peer_id
-                                            .range(
-                                                (
-                                                    NotificationsProtocol::BlockAnnounces {
-                                                        chain_index
-                                                    },
-                                                    peer_id.clone(),
-                                                    SubstreamDirection::Out,
-                                                    NotificationsSubstreamState::Open,
-                                                    SubstreamId::min_value(),
-                                                )
-                                                    ..=(
-                                                        NotificationsProtocol::BlockAnnounces {
-                                                            chain_index
-                                                        },
-                                                        peer_id.clone(),
-                                                        SubstreamDirection::Out,
-                                                        NotificationsSubstreamState::Open,
-                                                        SubstreamId::max_value(),
-                                                    ),
-                                            )
-                                            .next()
-                                            .is_none());
+                                        debug_assert!(!matches!(
+                                            self.gossip_link_substream(
+                                                NotificationsProtocol::BlockAnnounces {
+                                                    chain_index
+                                                },
+                                                &peer_id,
+                                                SubstreamDirection::Out
+                                            ),
+                                            Some((NotificationsSubstreamState::Open, _))
+                                        ));
 
                                         self.connected_unopened_gossip_desired.insert((
                                             peer_id.clone(),
@@ -1665,59 +3397,26 @@ where
                                     {
                                         self.inner.close_out_notifications(substream_id);
                                         self.substreams.remove(&substream_id).unwrap();
+                                        self.notifications_dropped.remove(&substream_id);
                                     }
 
-                                    for substream_id in self
-                                        .notification_substreams_by_peer_id
-                                        .range(
-                                            (
-                                                NotificationsProtocol::Transactions { chain_index },
-                                                peer_id.clone(),
-                                                SubstreamDirection::Out,
-                                                NotificationsSubstreamState::min_value(),
-                                                SubstreamId::min_value(),
-                                            )
-                                                ..=(
-                                                    NotificationsProtocol::Transactions {
-                                                        chain_index,
-                                                    },
-                                                    peer_id.clone(),
-                                                    SubstreamDirection::Out,
-                                                    NotificationsSubstreamState::max_value(),
-                                                    SubstreamId::max_value(),
-                                                ),
-                                        )
-                                        .map(|(_, _, _, _, s)| *s)
-                                        .collect::<Vec<_>>()
-                                    {
-                                        self.inner.close_out_notifications(substream_id);
-                                    }
+                                    // The transactions and GrandPa substreams are only opened after the
+                                    // block announces substream has successfully opened, and thus
+                                    // don't need to be closed here.
 
-                                    for substream_id in self
-                                        .notification_substreams_by_peer_id
-                                        .range(
-                                            (
-                                                NotificationsProtocol::Grandpa { chain_index },
-                                                peer_id.clone(),
-                                                SubstreamDirection::Out,
-                                                NotificationsSubstreamState::min_value(),
-                                                SubstreamId::min_value(),
-                                            )
-                                                ..=(
-                                                    NotificationsProtocol::Grandpa { chain_index },
-                                                    peer_id.clone(),
-                                                    SubstreamDirection::Out,
-                                                    NotificationsSubstreamState::max_value(),
-                                                    SubstreamId::max_value(),
-                                                ),
-                                        )
-                                        .map(|(_, _, _, _, s)| *s)
-                                        .collect::<Vec<_>>()
-                                    {
-                                        self.inner.close_out_notifications(substream_id);
-                                    }
+                                    // The inbound substreams, if any, remain in the gossip link
+                                    // until the remote closes them.
+
+                                    self.gossip_link_cleanup(chain_index, &peer_id);
+                                    self.debug_check_gossip_link(chain_index, &peer_id);
 
-                                    // TODO: also close the ingoing ba+tx+gp substreams
+                                    if self.gossip_open_failure_schedule_retry(
+                                        chain_index,
+                                        &peer_id,
+                                        &error,
+                                    ) {
+                                        continue;
+                                    }
 
                                     return Some(Event::GossipOpenFailed {
                                         peer_id,
@@ -1734,26 +3433,7 @@ where
                             // This can only happen if we have a block announces substream with
                             // that peer, otherwise the substream opening attempt should have
                             // been cancelled.
-                            debug_assert!(self
-                                .notification_substreams_by_peer_id
-                                .range(
-                                    (
-                                        NotificationsProtocol::BlockAnnounces { chain_index },
-                                        peer_id.clone(),
-                                        SubstreamDirection::Out,
-                                        NotificationsSubstreamState::Open,
-                                        SubstreamId::min_value()
-                                    )
-                                        ..=(
-                                            NotificationsProtocol::BlockAnnounces { chain_index },
-                                            peer_id.clone(),
-                                            SubstreamDirection::Out,
-                                            NotificationsSubstreamState::Open,
-                                            SubstreamId::max_value()
-                                        )
-                                )
-                                .next()
-                                .is_some());
+                            self.debug_check_gossip_link(chain_index, &peer_id);
 
                             // If the substream failed to open, we simply try again.
                             // Trying agains means that we might be hammering the remote with
@@ -1764,10 +3444,10 @@ where
                             {
                                 let new_substream_id = self.inner.open_out_notifications(
                                     connection_id,
-                                    protocol::encode_protocol_name_string(
+                                    codec::encode_protocol_name_string(
                                         match substream_info.protocol {
                                             Protocol::Transactions { .. } => {
-                                                protocol::ProtocolName::Transactions {
+                                                codec::ProtocolName::Transactions {
                                                     genesis_hash: self.chains[chain_index]
                                                         .genesis_hash,
                                                     fork_id: self.chains[chain_index]
@@ -1776,7 +3456,7 @@ where
                                                 }
                                             }
                                             Protocol::Grandpa { .. } => {
-                                                protocol::ProtocolName::Grandpa {
+                                                codec::ProtocolName::Grandpa {
                                                     genesis_hash: self.chains[chain_index]
                                                         .genesis_hash,
                                                     fork_id: self.chains[chain_index]
@@ -1787,7 +3467,7 @@ where
                                             _ => unreachable!(),
                                         },
                                     ),
-                                    Duration::from_secs(10), // TODO: arbitrary
+                                    self.chains[chain_index].notifications_open_timeout,
                                     match substream_info.protocol {
                                         Protocol::Transactions { .. } => Vec::new(),
                                         Protocol::Grandpa { .. } => {
@@ -1795,25 +3475,24 @@ where
                                         }
                                         _ => unreachable!(),
                                     },
-                                    1024 * 1024, // TODO: arbitrary
+                                    self.chains[chain_index].max_notifications_handshake_size,
                                 );
 
-                                let _was_inserted =
-                                    self.notification_substreams_by_peer_id.insert((
-                                        NotificationsProtocol::try_from(substream_info.protocol)
-                                            .unwrap(),
-                                        peer_id.clone(),
-                                        SubstreamDirection::Out,
-                                        NotificationsSubstreamState::Pending,
-                                        new_substream_id,
-                                    ));
-                                debug_assert!(_was_inserted);
+                                self.gossip_link_substream_insert(
+                                    NotificationsProtocol::try_from(substream_info.protocol)
+                                        .unwrap(),
+                                    &peer_id,
+                                    SubstreamDirection::Out,
+                                    NotificationsSubstreamState::Pending,
+                                    new_substream_id,
+                                );
 
                                 let _prev_value = self.substreams.insert(
                                     new_substream_id,
                                     SubstreamInfo {
                                         connection_id,
                                         protocol: substream_info.protocol.clone(),
+                                        schema: None,
                                     },
                                 );
                                 debug_assert!(_prev_value.is_none());
@@ -1821,14 +3500,13 @@ where
                                 continue;
                             }
 
-                            let _was_inserted = self.notification_substreams_by_peer_id.insert((
+                            self.gossip_link_substream_insert(
                                 NotificationsProtocol::try_from(substream_info.protocol).unwrap(),
-                                peer_id.clone(),
+                                &peer_id,
                                 SubstreamDirection::Out,
                                 NotificationsSubstreamState::Open,
                                 substream_id,
-                            ));
-                            debug_assert!(_was_inserted);
+                            );
 
                             // In case of Grandpa, we immediately send a neighbor packet with
                             // the current local state.
@@ -1837,8 +3515,8 @@ where
                                     .grandpa_protocol_config
                                     .as_ref()
                                     .unwrap();
-                                let packet = protocol::GrandpaNotificationRef::Neighbor(
-                                    protocol::NeighborPacket {
+                                let packet = codec::GrandpaNotificationRef::Neighbor(
+                                    codec::NeighborPacket {
                                         round_number: grandpa_state.round_number,
                                         set_id: grandpa_state.set_id,
                                         commit_finalized_height: grandpa_state
@@ -1850,10 +3528,21 @@ where
                                     a.extend_from_slice(b.as_ref());
                                     a
                                 });
-                                match self.inner.queue_notification(substream_id, packet) {
+                                let packet_len = packet.len();
+                                let result = self.inner.queue_notification(substream_id, packet);
+                                self.protocols_metrics
+                                    .entry(Protocol::Grandpa { chain_index })
+                                    .or_default()
+                                    .record_notification_out(packet_len, result.is_ok());
+                                match result {
                                     Ok(()) => {}
                                     Err(collection::QueueNotificationError::QueueFull) => {
-                                        unreachable!()
+                                        record_dropped_notification(
+                                            &mut self.notifications_dropped,
+                                            substream_id,
+                                            &peer_id,
+                                            NotificationsProtocol::Grandpa { chain_index },
+                                        );
                                     }
                                 }
                             }
@@ -1868,7 +3557,9 @@ where
                         | Protocol::LightCall { .. }
                         | Protocol::Kad { .. }
                         | Protocol::SyncWarp { .. }
-                        | Protocol::State { .. } => unreachable!(),
+                        | Protocol::State { .. }
+                        | Protocol::Custom { .. }
+                        | Protocol::CustomNotifications { .. } => unreachable!(),
                     }
                 }
 
@@ -1888,6 +3579,13 @@ where
                         .substreams
                         .remove(&substream_id)
                         .unwrap_or_else(|| unreachable!());
+                    self.notifications_dropped.remove(&substream_id);
+
+                    // Custom notifications protocols aren't tied to the gossip links.
+                    if let Protocol::CustomNotifications { .. } = substream_info.protocol {
+                        return Some(Event::CustomNotificationsOutClose { substream_id });
+                    }
+
                     let connection_id = substream_info.connection_id;
                     let connection_info = &self.inner[connection_id];
                     // Notification substreams can only happen on connections after their
@@ -1899,14 +3597,15 @@ where
                         .clone();
 
                     // Clean up the local state.
-                    let _was_in = self.notification_substreams_by_peer_id.remove(&(
+                    let _removed = self.gossip_link_substream_remove(
                         NotificationsProtocol::try_from(substream_info.protocol).unwrap(),
-                        peer_id.clone(), // TODO: cloning overhead :-/
+                        &peer_id,
                         SubstreamDirection::Out,
-                        NotificationsSubstreamState::Open,
-                        substream_id,
-                    ));
-                    debug_assert!(_was_in);
+                    );
+                    debug_assert_eq!(
+                        _removed,
+                        Some((NotificationsSubstreamState::Open, substream_id))
+                    );
 
                     // Some substreams are tied to the state of the block announces substream.
                     match substream_info.protocol {
@@ -1933,28 +3632,14 @@ where
                                     !state.shutting_down
                                 })
                             {
-                                debug_assert!(self
-                                    .notification_substreams_by_peer_id
-                                    .range(
-                                        (
-                                            NotificationsProtocol::BlockAnnounces { chain_index },
-                                            peer_id.clone(),
-                                            SubstreamDirection::Out,
-                                            NotificationsSubstreamState::Open,
-                                            SubstreamId::min_value(),
-                                        )
-                                            ..=(
-                                                NotificationsProtocol::BlockAnnounces {
-                                                    chain_index
-                                                },
-                                                peer_id.clone(),
-                                                SubstreamDirection::Out,
-                                                NotificationsSubstreamState::Open,
-                                                SubstreamId::max_value(),
-                                            ),
-                                    )
-                                    .next()
-                                    .is_none());
+                                debug_assert!(!matches!(
+                                    self.gossip_link_substream(
+                                        NotificationsProtocol::BlockAnnounces { chain_index },
+                                        &peer_id,
+                                        SubstreamDirection::Out
+                                    ),
+                                    Some((NotificationsSubstreamState::Open, _))
+                                ));
 
                                 let _was_inserted =
                                     self.connected_unopened_gossip_desired.insert((
@@ -1965,44 +3650,7 @@ where
                                 debug_assert!(_was_inserted);
                             }
 
-                            for proto in [
-                                NotificationsProtocol::Transactions { chain_index },
-                                NotificationsProtocol::Grandpa { chain_index },
-                            ] {
-                                for (substream_state, substream_id) in self
-                                    .notification_substreams_by_peer_id
-                                    .range(
-                                        (
-                                            proto,
-                                            peer_id.clone(),
-                                            SubstreamDirection::Out,
-                                            NotificationsSubstreamState::min_value(),
-                                            SubstreamId::min_value(),
-                                        )
-                                            ..=(
-                                                proto,
-                                                peer_id.clone(),
-                                                SubstreamDirection::Out,
-                                                NotificationsSubstreamState::max_value(),
-                                                SubstreamId::max_value(),
-                                            ),
-                                    )
-                                    .map(|(_, _, _, state, substream_id)| (*state, *substream_id))
-                                    .collect::<Vec<_>>()
-                                {
-                                    self.inner.close_out_notifications(substream_id);
-                                    self.substreams.remove(&substream_id);
-                                    self.notification_substreams_by_peer_id.remove(&(
-                                        proto,
-                                        peer_id.clone(),
-                                        SubstreamDirection::Out,
-                                        substream_state,
-                                        substream_id,
-                                    ));
-                                }
-                            }
-
-                            // TODO: also close inbound substreams?
+                            self.gossip_link_closed(chain_index, &peer_id);
 
                             return Some(Event::GossipDisconnected {
                                 peer_id: peer_id.clone(),
@@ -2016,64 +3664,67 @@ where
                         Protocol::Transactions { chain_index } => {
                             let new_substream_id = self.inner.open_out_notifications(
                                 connection_id,
-                                protocol::encode_protocol_name_string(
-                                    protocol::ProtocolName::Transactions {
+                                codec::encode_protocol_name_string(
+                                    codec::ProtocolName::Transactions {
                                         genesis_hash: self.chains[chain_index].genesis_hash,
                                         fork_id: self.chains[chain_index].fork_id.as_deref(),
                                     },
                                 ),
-                                Duration::from_secs(10), // TODO: arbitrary
+                                self.chains[chain_index].notifications_open_timeout,
                                 Vec::new(),
-                                1024 * 1024, // TODO: arbitrary
+                                self.chains[chain_index].max_notifications_handshake_size,
                             );
                             self.substreams.insert(
                                 new_substream_id,
                                 SubstreamInfo {
                                     connection_id,
                                     protocol: Protocol::Transactions { chain_index },
+                                    schema: None,
                                 },
                             );
-                            self.notification_substreams_by_peer_id.insert((
+                            self.gossip_link_substream_insert(
                                 NotificationsProtocol::Transactions { chain_index },
-                                peer_id.clone(),
+                                &peer_id,
                                 SubstreamDirection::Out,
                                 NotificationsSubstreamState::Pending,
                                 new_substream_id,
-                            ));
+                            );
                         }
                         Protocol::Grandpa { chain_index } => {
                             let new_substream_id = self.inner.open_out_notifications(
                                 connection_id,
-                                protocol::encode_protocol_name_string(
-                                    protocol::ProtocolName::Grandpa {
-                                        genesis_hash: self.chains[chain_index].genesis_hash,
-                                        fork_id: self.chains[chain_index].fork_id.as_deref(),
-                                    },
-                                ),
-                                Duration::from_secs(10), // TODO: arbitrary
+                                codec::encode_protocol_name_string(codec::ProtocolName::Grandpa {
+                                    genesis_hash: self.chains[chain_index].genesis_hash,
+                                    fork_id: self.chains[chain_index].fork_id.as_deref(),
+                                }),
+                                self.chains[chain_index].notifications_open_timeout,
                                 self.chains[chain_index].role.scale_encoding().to_vec(),
-                                1024 * 1024, // TODO: arbitrary
+                                self.chains[chain_index].max_notifications_handshake_size,
                             );
                             self.substreams.insert(
                                 new_substream_id,
                                 SubstreamInfo {
                                     connection_id,
                                     protocol: Protocol::Grandpa { chain_index },
+                                    schema: None,
                                 },
                             );
-                            self.notification_substreams_by_peer_id.insert((
+                            self.gossip_link_substream_insert(
                                 NotificationsProtocol::Grandpa { chain_index },
-                                peer_id.clone(),
+                                &peer_id,
                                 SubstreamDirection::Out,
                                 NotificationsSubstreamState::Pending,
                                 new_substream_id,
-                            ));
+                            );
                         }
                         _ => unreachable!(),
                     }
                 }
 
-                collection::Event::NotificationsInOpen { substream_id, .. } => {
+                collection::Event::NotificationsInOpen {
+                    substream_id,
+                    remote_handshake,
+                } => {
                     // Remote would like to open a notifications substream with us.
 
                     // There exists three possible ways to handle this event:
@@ -2088,6 +3739,9 @@ where
                     //   open.
                     // - Generate an event to ask the API user whether to accept the demand. This
                     //   happens specifically for block announce substreams.
+                    //
+                    // Substreams of custom notifications protocols are instead always reported
+                    // to the API user, unless the remote is banned.
 
                     let substream_info = self
                         .substreams
@@ -2101,27 +3755,34 @@ where
                         .as_ref()
                         .unwrap_or_else(|| unreachable!());
 
+                    if let Protocol::CustomNotifications {
+                        chain_index,
+                        protocol_index,
+                    } = substream_info.protocol
+                    {
+                        if self.is_banned(peer_id) {
+                            self.inner.reject_in_notifications(substream_id);
+                            self.substreams.remove(&substream_id);
+                            continue;
+                        }
+
+                        return Some(Event::CustomNotificationsInOpen {
+                            peer_id: peer_id.clone(),
+                            chain_id: ChainId(chain_index),
+                            protocol_index,
+                            substream_id,
+                            handshake: remote_handshake,
+                        });
+                    }
+
                     // Check whether a substream with the same protocol already exists with that
                     // peer, and if so deny the request.
                     if self
-                        .notification_substreams_by_peer_id
-                        .range(
-                            (
-                                substream_info.protocol.try_into().unwrap(),
-                                peer_id.clone(),
-                                SubstreamDirection::In,
-                                NotificationsSubstreamState::min_value(),
-                                SubstreamId::min_value(),
-                            )
-                                ..=(
-                                    substream_info.protocol.try_into().unwrap(),
-                                    peer_id.clone(),
-                                    SubstreamDirection::In,
-                                    NotificationsSubstreamState::max_value(),
-                                    SubstreamId::max_value(),
-                                ),
+                        .gossip_link_substream(
+                            substream_info.protocol.try_into().unwrap(),
+                            peer_id,
+                            SubstreamDirection::In,
                         )
-                        .next()
                         .is_some()
                     {
                         self.inner.reject_in_notifications(substream_id);
@@ -2138,40 +3799,51 @@ where
                         unreachable!()
                     };
 
+                    // Gossip substreams opened by banned peers are always refused.
+                    if self.is_banned(peer_id) {
+                        self.inner.reject_in_notifications(substream_id);
+                        self.substreams.remove(&substream_id);
+                        continue;
+                    }
+
+                    // If another chain has the same genesis hash but a different fork ID, the
+                    // remote must not open substreams on the protocols of both chains, as it
+                    // can't be a member of both at the same time.
+                    if self.has_other_fork_id_substreams(chain_index, peer_id) {
+                        let peer_id = peer_id.clone();
+                        self.inner.reject_in_notifications(substream_id);
+                        self.substreams.remove(&substream_id);
+                        return Some(Event::ProtocolError {
+                            peer_id,
+                            error: ProtocolError::ForkIdMismatch,
+                        });
+                    }
+
+                    let protocol = NotificationsProtocol::try_from(substream_info.protocol)
+                        .unwrap_or_else(|_| unreachable!());
+                    let peer_id = peer_id.clone();
+
                     // If an outgoing block announces notifications protocol (either pending or
                     // fully open) exists, accept the substream immediately.
                     if self
-                        .notification_substreams_by_peer_id
-                        .range(
-                            (
-                                NotificationsProtocol::BlockAnnounces { chain_index },
-                                peer_id.clone(),
-                                SubstreamDirection::Out,
-                                NotificationsSubstreamState::min_value(),
-                                SubstreamId::min_value(),
-                            )
-                                ..=(
-                                    NotificationsProtocol::BlockAnnounces { chain_index },
-                                    peer_id.clone(),
-                                    SubstreamDirection::Out,
-                                    NotificationsSubstreamState::max_value(),
-                                    SubstreamId::max_value(),
-                                ),
+                        .gossip_link_substream(
+                            NotificationsProtocol::BlockAnnounces { chain_index },
+                            &peer_id,
+                            SubstreamDirection::Out,
                         )
-                        .next()
                         .is_some()
                     {
-                        self.notification_substreams_by_peer_id.insert((
-                            substream_info.protocol.try_into().unwrap(),
-                            peer_id.clone(),
+                        self.gossip_link_substream_insert(
+                            protocol,
+                            &peer_id,
                             SubstreamDirection::In,
                             NotificationsSubstreamState::Open,
                             substream_id,
-                        ));
-                        let handshake = match substream_info.protocol {
-                            Protocol::BlockAnnounces { .. } => {
-                                protocol::encode_block_announces_handshake(
-                                    protocol::BlockAnnouncesHandshakeRef {
+                        );
+                        let handshake = match protocol {
+                            NotificationsProtocol::BlockAnnounces { .. } => {
+                                codec::encode_block_announces_handshake(
+                                    codec::BlockAnnouncesHandshakeRef {
                                         best_hash: &self.chains[chain_index].best_hash,
                                         best_number: self.chains[chain_index].best_number,
                                         role: self.chains[chain_index].role,
@@ -2184,38 +3856,84 @@ where
                                     a
                                 })
                             }
-                            Protocol::Grandpa { .. } => {
+                            NotificationsProtocol::Grandpa { .. } => {
                                 self.chains[chain_index].role.scale_encoding().to_vec()
                             }
-                            Protocol::Transactions { .. } => Vec::new(),
-                            _ => unreachable!(),
+                            NotificationsProtocol::Transactions { .. } => Vec::new(),
                         };
                         self.inner.accept_in_notifications(
                             substream_id,
                             handshake,
-                            1024 * 1024, // TODO: ?!
+                            self.chains[chain_index].max_notification_size,
                         );
                         continue;
                     }
 
                     // It is forbidden to cold-open a substream other than the block announces
-                    // substream.
-                    if !matches!(substream_info.protocol, Protocol::BlockAnnounces { .. }) {
-                        self.inner.reject_in_notifications(substream_id);
-                        self.substreams.remove(&substream_id);
+                    // substream. However, remotes typically open the block announces,
+                    // transactions, and GrandPa substreams in quick succession. If the remote
+                    // has an inbound block announces substream waiting for the API user to
+                    // accept or refuse it, the substream is buffered until this decision is made
+                    // instead of being rejected.
+                    if !matches!(protocol, NotificationsProtocol::BlockAnnounces { .. }) {
+                        if matches!(
+                            self.gossip_link_substream(
+                                NotificationsProtocol::BlockAnnounces { chain_index },
+                                &peer_id,
+                                SubstreamDirection::In
+                            ),
+                            Some((NotificationsSubstreamState::Pending, _))
+                        ) {
+                            self.gossip_link_substream_insert(
+                                protocol,
+                                &peer_id,
+                                SubstreamDirection::In,
+                                NotificationsSubstreamState::Pending,
+                                substream_id,
+                            );
+                        } else {
+                            self.inner.reject_in_notifications(substream_id);
+                            self.substreams.remove(&substream_id);
+                        }
                         continue;
                     }
 
-                    // Update the local state and return the event.
-                    self.notification_substreams_by_peer_id.insert((
+                    // Update the local state.
+                    self.gossip_link_substream_insert(
                         NotificationsProtocol::BlockAnnounces { chain_index },
-                        peer_id.clone(),
+                        &peer_id,
                         SubstreamDirection::In,
                         NotificationsSubstreamState::Pending,
                         substream_id,
-                    ));
+                    );
+
+                    // If the gossip links of the chain are managed automatically, accept or
+                    // reject the substream immediately. Otherwise, let the API user decide.
+                    match self.gossip_slots_in_decision(ChainId(chain_index), &peer_id) {
+                        Some(Ok(())) => {
+                            let _result = self.gossip_open(
+                                ChainId(chain_index),
+                                &peer_id,
+                                GossipKind::ConsensusTransactions,
+                            );
+                            debug_assert!(_result.is_ok());
+                            continue;
+                        }
+                        Some(Err(reason)) => {
+                            let _result = self.gossip_reject_in(
+                                ChainId(chain_index),
+                                &peer_id,
+                                GossipKind::ConsensusTransactions,
+                                reason,
+                            );
+                            debug_assert!(_result.is_ok());
+                            continue;
+                        }
+                        None => {}
+                    }
+
                     return Some(Event::GossipInDesired {
-                        peer_id: peer_id.clone(),
+                        peer_id,
                         chain_id: ChainId(chain_index),
                         kind: GossipKind::ConsensusTransactions,
                     });
@@ -2237,25 +3955,59 @@ where
                         .unwrap_or_else(|| unreachable!());
 
                     // All incoming notification substreams are immediately accepted/rejected
-                    // except for block announce substreams. Therefore, this event can only happen
-                    // for block announce substreams.
-                    let Protocol::BlockAnnounces { chain_index } = substream_info.protocol else {
-                        unreachable!()
+                    // except for block announce substreams and the transactions and GrandPa
+                    // substreams that have been buffered while waiting for the block announces
+                    // substream to be accepted or rejected.
+                    let chain_index = match substream_info.protocol {
+                        Protocol::BlockAnnounces { chain_index } => chain_index,
+                        protocol @ (Protocol::Transactions { .. } | Protocol::Grandpa { .. }) => {
+                            let peer_id = peer_id.clone();
+                            let _removed = self.gossip_link_substream_remove(
+                                protocol.try_into().unwrap(),
+                                &peer_id,
+                                SubstreamDirection::In,
+                            );
+                            debug_assert_eq!(
+                                _removed,
+                                Some((NotificationsSubstreamState::Pending, substream_id))
+                            );
+                            self.substreams.remove(&substream_id);
+                            let chain_index = NotificationsProtocol::try_from(protocol)
+                                .unwrap()
+                                .chain_index();
+                            self.gossip_link_cleanup(chain_index, &peer_id);
+                            self.debug_check_gossip_link(chain_index, &peer_id);
+                            continue;
+                        }
+                        Protocol::CustomNotifications { .. } => {
+                            self.substreams.remove(&substream_id);
+                            return Some(Event::CustomNotificationsInOpenCancel { substream_id });
+                        }
+                        _ => unreachable!(),
                     };
 
                     // Clean up the local state.
-                    let _was_in = self.notification_substreams_by_peer_id.remove(&(
+                    let peer_id = peer_id.clone();
+                    let _removed = self.gossip_link_substream_remove(
                         NotificationsProtocol::BlockAnnounces { chain_index },
-                        peer_id.clone(), // TODO: cloning overhead :-/
-                        SubstreamDirection::Out,
-                        NotificationsSubstreamState::Open,
-                        substream_id,
-                    ));
-                    debug_assert!(_was_in);
+                        &peer_id,
+                        SubstreamDirection::In,
+                    );
+                    debug_assert_eq!(
+                        _removed,
+                        Some((NotificationsSubstreamState::Pending, substream_id))
+                    );
+                    self.substreams.remove(&substream_id);
+
+                    // The transactions and GrandPa substreams that have been buffered while
+                    // waiting for the block announces substream can no longer be accepted.
+                    self.settle_buffered_in_gossip_substreams(chain_index, &peer_id, false);
+                    self.gossip_link_cleanup(chain_index, &peer_id);
+                    self.debug_check_gossip_link(chain_index, &peer_id);
 
                     // Notify API user.
                     return Some(Event::GossipInDesiredCancel {
-                        peer_id: peer_id.clone(),
+                        peer_id,
                         chain_id: ChainId(chain_index),
                         kind: GossipKind::ConsensusTransactions,
                     });
@@ -2270,6 +4022,22 @@ where
                         .substreams
                         .get(&substream_id)
                         .unwrap_or_else(|| unreachable!());
+
+                    let protocol_metrics = self
+                        .protocols_metrics
+                        .entry(substream_info.protocol)
+                        .or_default();
+                    protocol_metrics.notifications_received += 1;
+                    protocol_metrics.record_received(notification.len());
+
+                    // Notifications of custom protocols are reported as they are.
+                    if let Protocol::CustomNotifications { .. } = substream_info.protocol {
+                        return Some(Event::CustomNotificationIn {
+                            substream_id,
+                            notification,
+                        });
+                    }
+
                     let chain_index = match substream_info.protocol {
                         Protocol::BlockAnnounces { chain_index } => chain_index,
                         Protocol::Transactions { chain_index } => chain_index,
@@ -2283,12 +4051,13 @@ where
                         | Protocol::LightCall { .. }
                         | Protocol::Kad { .. }
                         | Protocol::SyncWarp { .. }
-                        | Protocol::State { .. } => unreachable!(),
+                        | Protocol::State { .. }
+                        | Protocol::Custom { .. }
+                        | Protocol::CustomNotifications { .. } => unreachable!(),
                     };
-                    let connection_info = &self.inner[substream_info.connection_id];
                     // Notification substreams can only happen on connections after their
                     // handshake phase is finished, therefore their `PeerId` is known.
-                    let peer_id = connection_info
+                    let peer_id = self.inner[substream_info.connection_id]
                         .peer_id
                         .as_ref()
                         .unwrap_or_else(|| unreachable!());
@@ -2296,47 +4065,82 @@ where
                     // Check whether there is an open outgoing block announces substream, as this
                     // means that we are "gossip-connected". If not, then the notification is
                     // silently discarded.
-                    // TODO: cloning of the peer_id
-                    if self
-                        .notification_substreams_by_peer_id
-                        .range(
-                            (
-                                NotificationsProtocol::BlockAnnounces { chain_index },
-                                peer_id.clone(),
-                                SubstreamDirection::Out,
-                                NotificationsSubstreamState::Open,
-                                collection::SubstreamId::min_value(),
-                            )
-                                ..=(
-                                    NotificationsProtocol::BlockAnnounces { chain_index },
-                                    peer_id.clone(),
-                                    SubstreamDirection::Out,
-                                    NotificationsSubstreamState::Open,
-                                    collection::SubstreamId::max_value(),
-                                ),
-                        )
-                        .next()
-                        .is_none()
-                    {
+                    // The `PeerId` is only cloned afterwards, so that discarded notifications
+                    // don't allocate.
+                    if !matches!(
+                        self.gossip_link_substream(
+                            NotificationsProtocol::BlockAnnounces { chain_index },
+                            peer_id,
+                            SubstreamDirection::Out
+                        ),
+                        Some((NotificationsSubstreamState::Open, _))
+                    ) {
                         continue;
                     }
+                    let peer_id = peer_id.clone();
+
+                    // Update the health metrics of the gossip link.
+                    // The entry might not exist if the gossip link was opened without calling
+                    // `gossip_open`.
+                    let mut link = self
+                        .gossip_links
+                        .get_mut(&GossipLinkKey(chain_index, &peer_id));
+                    if let Some(link) = link.as_mut() {
+                        link.info.bytes_received = link
+                            .info
+                            .bytes_received
+                            .saturating_add(u64::try_from(notification.len()).unwrap_or(u64::MAX));
+                    }
 
                     // Decode the notification and return an event.
                     match substream_info.protocol {
                         Protocol::BlockAnnounces { .. } => {
-                            if let Err(err) = protocol::decode_block_announce(
+                            let block_number_bytes = self.chains[chain_index].block_number_bytes;
+                            let announce = match codec::decode_block_announce(
                                 &notification,
-                                self.chains[chain_index].block_number_bytes,
+                                block_number_bytes,
                             ) {
-                                return Some(Event::ProtocolError {
-                                    error: ProtocolError::BadBlockAnnounce(err),
-                                    peer_id: peer_id.clone(),
-                                });
+                                Ok(announce) => announce,
+                                Err(err) => {
+                                    self.report_peer(
+                                        now,
+                                        &peer_id,
+                                        PROTOCOL_ERROR_REPUTATION_CHANGE,
+                                    );
+                                    return Some(Event::ProtocolError {
+                                        error: ProtocolError::BadBlockAnnounce(err),
+                                        peer_id,
+                                    });
+                                }
+                            };
+
+                            if let Some(link) = link {
+                                link.info.announces_received =
+                                    link.info.announces_received.saturating_add(1);
+
+                                // Keep track of the best block of the peer. The header has
+                                // already been successfully decoded by `decode_block_announce`.
+                                if announce.is_best {
+                                    if let (Some(best_block), Ok(header)) = (
+                                        link.best_block.as_mut(),
+                                        header::decode(
+                                            announce.scale_encoded_header,
+                                            block_number_bytes,
+                                        ),
+                                    ) {
+                                        *best_block = (
+                                            header.number,
+                                            header::hash_from_scale_encoded_header(
+                                                announce.scale_encoded_header,
+                                            ),
+                                        );
+                                    }
+                                }
                             }
 
                             return Some(Event::BlockAnnounce {
                                 chain_id: ChainId(chain_index),
-                                peer_id: peer_id.clone(),
+                                peer_id,
                                 announce: EncodedBlockAnnounce {
                                     message: notification,
                                     block_number_bytes: self.chains[chain_index].block_number_bytes,
@@ -2344,27 +4148,52 @@ where
                             });
                         }
                         Protocol::Transactions { .. } => {
-                            // TODO: not implemented
+                            let transactions =
+                                match codec::decode_transactions_notification(&notification) {
+                                    Ok(t) => t.into_iter().map(|tx| tx.to_vec()).collect(),
+                                    Err(err) => {
+                                        self.report_peer(
+                                            now,
+                                            &peer_id,
+                                            PROTOCOL_ERROR_REPUTATION_CHANGE,
+                                        );
+                                        return Some(Event::ProtocolError {
+                                            error: ProtocolError::BadTransactionsNotification(err),
+                                            peer_id,
+                                        });
+                                    }
+                                };
+
+                            return Some(Event::TransactionsReceived {
+                                chain_id: ChainId(chain_index),
+                                peer_id,
+                                transactions,
+                            });
                         }
                         Protocol::Grandpa { .. } => {
-                            let decoded_notif = match protocol::decode_grandpa_notification(
+                            let decoded_notif = match codec::decode_grandpa_notification(
                                 &notification,
                                 self.chains[chain_index].block_number_bytes,
                             ) {
                                 Ok(n) => n,
                                 Err(err) => {
+                                    self.report_peer(
+                                        now,
+                                        &peer_id,
+                                        PROTOCOL_ERROR_REPUTATION_CHANGE,
+                                    );
                                     return Some(Event::ProtocolError {
                                         error: ProtocolError::BadGrandpaNotification(err),
-                                        peer_id: peer_id.clone(),
-                                    })
+                                        peer_id,
+                                    });
                                 }
                             };
 
                             match decoded_notif {
-                                protocol::GrandpaNotificationRef::Commit(_) => {
+                                codec::GrandpaNotificationRef::Commit(_) => {
                                     return Some(Event::GrandpaCommitMessage {
                                         chain_id: ChainId(chain_index),
-                                        peer_id: peer_id.clone(),
+                                        peer_id,
                                         message: EncodedGrandpaCommitMessage {
                                             message: notification,
                                             block_number_bytes: self.chains[chain_index]
@@ -2372,10 +4201,10 @@ where
                                         },
                                     })
                                 }
-                                protocol::GrandpaNotificationRef::Neighbor(n) => {
+                                codec::GrandpaNotificationRef::Neighbor(n) => {
                                     return Some(Event::GrandpaNeighborPacket {
                                         chain_id: ChainId(chain_index),
-                                        peer_id: peer_id.clone(),
+                                        peer_id,
                                         state: GrandpaState {
                                             round_number: n.round_number,
                                             set_id: n.set_id,
@@ -2383,9 +4212,34 @@ where
                                         },
                                     })
                                 }
-                                _ => {
-                                    // Any other type of message is currently ignored. Support
-                                    // for them could be added in the future.
+                                codec::GrandpaNotificationRef::Vote(_) => {
+                                    return Some(Event::GrandpaVoteMessage {
+                                        chain_id: ChainId(chain_index),
+                                        peer_id,
+                                        message: EncodedGrandpaVoteMessage {
+                                            message: notification,
+                                            block_number_bytes: self.chains[chain_index]
+                                                .block_number_bytes,
+                                        },
+                                    })
+                                }
+                                codec::GrandpaNotificationRef::CatchUpRequest(request) => {
+                                    return Some(Event::GrandpaCatchUpRequest {
+                                        chain_id: ChainId(chain_index),
+                                        peer_id,
+                                        request,
+                                    })
+                                }
+                                codec::GrandpaNotificationRef::CatchUp(_) => {
+                                    return Some(Event::GrandpaCatchUp {
+                                        chain_id: ChainId(chain_index),
+                                        peer_id,
+                                        message: EncodedGrandpaCatchUp {
+                                            message: notification,
+                                            block_number_bytes: self.chains[chain_index]
+                                                .block_number_bytes,
+                                        },
+                                    })
                                 }
                             }
                         }
@@ -2399,23 +4253,115 @@ where
                         | Protocol::LightCall { .. }
                         | Protocol::Kad { .. }
                         | Protocol::SyncWarp { .. }
-                        | Protocol::State { .. } => unreachable!(),
+                        | Protocol::State { .. }
+                        | Protocol::Custom { .. }
+                        | Protocol::CustomNotifications { .. } => unreachable!(),
                     }
                 }
 
                 collection::Event::NotificationsInClose { substream_id, .. } => {
                     // An incoming notifications substream has been closed.
-                    // Nothing to do except clean up the local state.
-                    let _was_in = self.substreams.remove(&substream_id);
-                    debug_assert!(_was_in.is_some());
-                }
+                    // Nothing to do except clean up the local state, and report the closing of
+                    // substreams of custom protocols.
+                    let substream_info = self
+                        .substreams
+                        .remove(&substream_id)
+                        .unwrap_or_else(|| unreachable!());
+                    if let Protocol::CustomNotifications { .. } = substream_info.protocol {
+                        return Some(Event::CustomNotificationsInClose { substream_id });
+                    }
 
-                collection::Event::PingOutSuccess { .. } => {
-                    // We ignore ping events.
-                    // TODO: report to end user or something
-                }
-            }
-        }
+                    // Notification substreams can only happen on connections after their
+                    // handshake phase is finished, therefore their `PeerId` is known.
+                    let peer_id = self.inner[substream_info.connection_id]
+                        .peer_id
+                        .clone()
+                        .unwrap_or_else(|| unreachable!());
+                    let protocol = NotificationsProtocol::try_from(substream_info.protocol)
+                        .unwrap_or_else(|_| unreachable!());
+                    let _removed = self.gossip_link_substream_remove(
+                        protocol,
+                        &peer_id,
+                        SubstreamDirection::In,
+                    );
+                    debug_assert_eq!(_removed.map(|(_, id)| id), Some(substream_id));
+                    self.gossip_link_cleanup(protocol.chain_index(), &peer_id);
+                    self.debug_check_gossip_link(protocol.chain_index(), &peer_id);
+                }
+
+                collection::Event::NotificationsOutQueueDrained { substream_id } => {
+                    let substream_info = self
+                        .substreams
+                        .get(&substream_id)
+                        .unwrap_or_else(|| unreachable!());
+
+                    let (chain_index, protocol) = match substream_info.protocol {
+                        Protocol::CustomNotifications { .. } => {
+                            return Some(Event::CustomNotificationsOutQueueDrained {
+                                substream_id,
+                            });
+                        }
+                        Protocol::BlockAnnounces { chain_index } => {
+                            (chain_index, GossipNotificationsProtocol::BlockAnnounces)
+                        }
+                        Protocol::Transactions { chain_index } => {
+                            (chain_index, GossipNotificationsProtocol::Transactions)
+                        }
+                        Protocol::Grandpa { chain_index } => {
+                            (chain_index, GossipNotificationsProtocol::Grandpa)
+                        }
+                        // Other protocols are not notification protocols.
+                        Protocol::Identify
+                        | Protocol::Ping
+                        | Protocol::Sync { .. }
+                        | Protocol::LightUnknown { .. }
+                        | Protocol::LightStorage { .. }
+                        | Protocol::LightCall { .. }
+                        | Protocol::Kad { .. }
+                        | Protocol::SyncWarp { .. }
+                        | Protocol::State { .. }
+                        | Protocol::Custom { .. } => unreachable!(),
+                    };
+
+                    // Notification substreams can only happen on connections after their
+                    // handshake phase is finished, therefore their `PeerId` is known.
+                    let peer_id = self.inner[substream_info.connection_id]
+                        .peer_id
+                        .clone()
+                        .unwrap_or_else(|| unreachable!());
+
+                    return Some(Event::GossipOutQueueDrained {
+                        peer_id,
+                        chain_id: ChainId(chain_index),
+                        protocol,
+                    });
+                }
+
+                collection::Event::PingOutSuccess { id, ping_time } => {
+                    let connection_info = &mut self.inner[id];
+                    connection_info.consecutive_ping_failures = 0;
+                    connection_info.ping_rtt_estimate =
+                        Some(match connection_info.ping_rtt_estimate {
+                            Some(estimate) => (estimate * 7 + ping_time) / 8,
+                            None => ping_time,
+                        });
+
+                    // Pings are only ever sent on established connections.
+                    let peer_id = connection_info.peer_id.clone().unwrap();
+
+                    return Some(Event::PingResult {
+                        peer_id,
+                        rtt: ping_time,
+                    });
+                }
+
+                collection::Event::SubstreamOpened { .. }
+                | collection::Event::SubstreamClosed { .. } => {
+                    // These events are disabled in the configuration of the collection.
+                    unreachable!()
+                }
+            }
+        }
     }
 
     /// Sends a blocks request to the given peer.
@@ -2436,19 +4382,21 @@ where
         &mut self,
         target: &PeerId,
         chain_id: ChainId,
-        config: protocol::BlocksRequestConfig,
+        config: codec::BlocksRequestConfig,
         timeout: Duration,
     ) -> Result<SubstreamId, StartRequestError> {
-        let request_data =
-            protocol::build_block_request(self.chains[chain_id.0].block_number_bytes, &config)
-                .fold(Vec::new(), |mut a, b| {
-                    a.extend_from_slice(b.as_ref());
-                    a
-                });
+        let request_data = codec::build_block_request(
+            self.chains[chain_id.0].block_number_bytes,
+            &config,
+        )
+        .fold(Vec::new(), |mut a, b| {
+            a.extend_from_slice(b.as_ref());
+            a
+        });
 
         self.start_request(
             target,
-            request_data,
+            Some(request_data),
             Protocol::Sync {
                 chain_index: chain_id.0,
             },
@@ -2456,12 +4404,23 @@ where
         )
     }
 
+    /// Sends a GrandPa warp sync request to the given peer, asking for a proof starting at the
+    /// block whose hash is `begin_hash`.
+    ///
+    /// Responses have a size limit, and a proof might not fit in a single response. When that
+    /// happens, the peer sends back a truncated proof and sets
+    /// [`codec::GrandpaWarpSyncResponse::is_finished`] to `false`. The rest of the proof can
+    /// be obtained by sending a new request whose `begin_hash` is the hash of the header of the
+    /// last fragment that was received. The warp sync state machine found in the `sync` module
+    /// automatically takes care of doing so.
+    ///
+    /// This function might generate a message destined a connection. Use
+    /// [`ChainNetwork::pull_message_to_connection`] to process messages after it has returned.
     ///
     /// # Panic
     ///
     /// Panics if the [`ChainId`] is invalid.
     ///
-    // TODO: docs
     pub fn start_grandpa_warp_sync_request(
         &mut self,
         target: &PeerId,
@@ -2473,7 +4432,7 @@ where
 
         self.start_request(
             target,
-            request_data,
+            Some(request_data),
             Protocol::SyncWarp {
                 chain_index: chain_id.0,
             },
@@ -2505,10 +4464,10 @@ where
         target: &PeerId,
         chain_id: ChainId,
         block_hash: &[u8; 32],
-        start_key: protocol::StateRequestStart,
+        start_key: codec::StateRequestStart,
         timeout: Duration,
     ) -> Result<SubstreamId, StartRequestError> {
-        let request_data = protocol::build_state_request(protocol::StateRequest {
+        let request_data = codec::build_state_request(codec::StateRequest {
             block_hash,
             start_key,
         })
@@ -2519,7 +4478,7 @@ where
 
         self.start_request(
             target,
-            request_data,
+            Some(request_data),
             Protocol::State {
                 chain_index: chain_id.0,
             },
@@ -2529,6 +4488,9 @@ where
 
     /// Sends a storage request to the given peer.
     ///
+    /// Returns [`StartRequestMaybeTooLargeError::RequestTooLarge`] if the request is larger than
+    /// [`LIGHT_PROTOCOL_MAX_REQUEST_SIZE`].
+    ///
     /// This function might generate a message destined a connection. Use
     /// [`ChainNetwork::pull_message_to_connection`] to process messages after it has returned.
     ///
@@ -2541,22 +4503,24 @@ where
         &mut self,
         target: &PeerId,
         chain_id: ChainId,
-        config: protocol::StorageProofRequestConfig<impl Iterator<Item = impl AsRef<[u8]> + Clone>>,
+        config: codec::StorageProofRequestConfig<impl Iterator<Item = impl AsRef<[u8]> + Clone>>,
         timeout: Duration,
     ) -> Result<SubstreamId, StartRequestMaybeTooLargeError> {
         let request_data =
-            protocol::build_storage_proof_request(config).fold(Vec::new(), |mut a, b| {
+            codec::build_storage_proof_request(config).fold(Vec::new(), |mut a, b| {
                 a.extend_from_slice(b.as_ref());
                 a
             });
 
         // The request data can possibly by higher than the protocol limit, especially due to the
         // call data.
-        // TODO: check limit
+        if request_data.len() > LIGHT_PROTOCOL_MAX_REQUEST_SIZE {
+            return Err(StartRequestMaybeTooLargeError::RequestTooLarge);
+        }
 
         Ok(self.start_request(
             target,
-            request_data,
+            Some(request_data),
             Protocol::LightStorage {
                 chain_index: chain_id.0,
             },
@@ -2575,6 +4539,9 @@ where
     /// this method is just an optimization. When performing the actual call, regular storage proof
     /// requests should be performed if the key is not present in the call proof response.
     ///
+    /// Returns [`StartRequestMaybeTooLargeError::RequestTooLarge`] if the request, which notably
+    /// contains the call parameters, is larger than [`LIGHT_PROTOCOL_MAX_REQUEST_SIZE`].
+    ///
     /// This function might generate a message destined a connection. Use
     /// [`ChainNetwork::pull_message_to_connection`] to process messages after it has returned.
     ///
@@ -2586,22 +4553,23 @@ where
         &mut self,
         target: &PeerId,
         chain_id: ChainId,
-        config: protocol::CallProofRequestConfig<'_, impl Iterator<Item = impl AsRef<[u8]>>>,
+        config: codec::CallProofRequestConfig<'_, impl Iterator<Item = impl AsRef<[u8]>>>,
         timeout: Duration,
     ) -> Result<SubstreamId, StartRequestMaybeTooLargeError> {
-        let request_data =
-            protocol::build_call_proof_request(config).fold(Vec::new(), |mut a, b| {
-                a.extend_from_slice(b.as_ref());
-                a
-            });
+        let request_data = codec::build_call_proof_request(config).fold(Vec::new(), |mut a, b| {
+            a.extend_from_slice(b.as_ref());
+            a
+        });
 
         // The request data can possibly by higher than the protocol limit, especially due to the
         // call data.
-        // TODO: check limit
+        if request_data.len() > LIGHT_PROTOCOL_MAX_REQUEST_SIZE {
+            return Err(StartRequestMaybeTooLargeError::RequestTooLarge);
+        }
 
         Ok(self.start_request(
             target,
-            request_data,
+            Some(request_data),
             Protocol::LightCall {
                 chain_index: chain_id.0,
             },
@@ -2609,6 +4577,23 @@ where
         )?)
     }
 
+    /// Sends an identify request to the given peer.
+    ///
+    /// The response is reported through a [`RequestResult::Identify`]. It contains information
+    /// about the remote, notably the addresses it is listening on and the address of the local
+    /// node as observed by the remote.
+    ///
+    /// This function might generate a message destined a connection. Use
+    /// [`ChainNetwork::pull_message_to_connection`] to process messages after it has returned.
+    pub fn start_identify_request(
+        &mut self,
+        target: &PeerId,
+        timeout: Duration,
+    ) -> Result<SubstreamId, StartRequestError> {
+        // Identify requests don't have any body, not even a length prefix.
+        self.start_request(target, None, Protocol::Identify, timeout)
+    }
+
     /// Sends a Kademlia find node request to the given peer.
     ///
     /// This function might generate a message destined a connection. Use
@@ -2625,7 +4610,7 @@ where
         peer_id_to_find: &PeerId,
         timeout: Duration,
     ) -> Result<SubstreamId, StartRequestError> {
-        let request_data = protocol::build_find_node_request(peer_id_to_find.as_bytes());
+        let request_data = codec::build_find_node_request(peer_id_to_find.as_bytes());
 
         // The request data can possibly by higher than the protocol limit, especially due to the
         // call data.
@@ -2633,7 +4618,7 @@ where
 
         Ok(self.start_request(
             target,
-            request_data,
+            Some(request_data),
             Protocol::Kad {
                 chain_index: chain_id.0,
             },
@@ -2641,16 +4626,172 @@ where
         )?)
     }
 
+    /// Adds a request-response protocol to the given chain, on top of the ones passed through
+    /// [`ChainConfig::custom_request_response_protocols`].
+    ///
+    /// On success, returns the index of the new protocol, to pass to
+    /// [`ChainNetwork::start_custom_request`]. Indices of protocols added through this function
+    /// follow the indices of the protocols passed through
+    /// [`ChainConfig::custom_request_response_protocols`].
+    ///
+    /// Inbound requests on the new protocol are accepted from now on, including on existing
+    /// connections, if [`CustomRequestResponseProtocolConfig::allow_inbound_requests`] is `true`.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the [`ChainId`] is invalid.
+    ///
+    pub fn add_custom_request_response_protocol(
+        &mut self,
+        chain_id: ChainId,
+        config: CustomRequestResponseProtocolConfig,
+    ) -> Result<usize, AddCustomProtocolError> {
+        let chain = &mut self.chains[chain_id.0];
+        let name =
+            custom_protocol_full_name(&chain.genesis_hash, chain.fork_id.as_deref(), &config.name);
+
+        if codec::decode_protocol_name(&name).is_ok()
+            || self.custom_protocols_by_name.contains_key(&name)
+        {
+            return Err(AddCustomProtocolError::Conflict);
+        }
+
+        let protocol_index = chain.custom_protocols.len();
+        self.custom_protocols_by_name.insert(
+            name.clone(),
+            Protocol::Custom {
+                chain_index: chain_id.0,
+                protocol_index,
+            },
+        );
+        chain.custom_protocols.push(CustomProtocol {
+            name,
+            config,
+            inbound_requests_stats: InboundRequestsStats::default(),
+        });
+
+        Ok(protocol_index)
+    }
+
+    /// Sends a request on one of the [`ChainConfig::custom_request_response_protocols`] of the
+    /// given chain, or on a protocol added with
+    /// [`ChainNetwork::add_custom_request_response_protocol`]. `protocol_index` is the index of
+    /// the protocol.
+    ///
+    /// The request and its response are opaque to the [`ChainNetwork`]. The response is reported
+    /// through a [`RequestResult::Custom`].
+    ///
+    /// Returns [`StartRequestMaybeTooLargeError::RequestTooLarge`] if the request is larger than
+    /// [`CustomRequestResponseProtocolConfig::max_request_size`].
+    ///
+    /// This function might generate a message destined a connection. Use
+    /// [`ChainNetwork::pull_message_to_connection`] to process messages after it has returned.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the [`ChainId`] or the protocol index is invalid.
+    ///
+    pub fn start_custom_request(
+        &mut self,
+        target: &PeerId,
+        chain_id: ChainId,
+        protocol_index: usize,
+        request: Vec<u8>,
+        timeout: Duration,
+    ) -> Result<SubstreamId, StartRequestMaybeTooLargeError> {
+        if request.len()
+            > self.chains[chain_id.0].custom_protocols[protocol_index]
+                .config
+                .max_request_size
+        {
+            return Err(StartRequestMaybeTooLargeError::RequestTooLarge);
+        }
+
+        Ok(self.start_request(
+            target,
+            Some(request),
+            Protocol::Custom {
+                chain_index: chain_id.0,
+                protocol_index,
+            },
+            timeout,
+        )?)
+    }
+
+    /// Builds the response to a Kademlia find node request that `requester` has sent.
+    ///
+    /// `known_peers` must contain the peers known by the API user alongside with their addresses,
+    /// for example the content of its k-buckets. The response contains the peers of this list
+    /// that are the closest to the requested key, as defined by the Kademlia XOR metric.
+    /// `requester`, the local node, and peers without any address are never included in the
+    /// response.
+    ///
+    /// Each item of `known_peers` can optionally contain the signed envelope of the peer record
+    /// of the peer, in which case it is included in the response alongside with the addresses.
+    /// See [`codec::build_find_node_response`].
+    ///
+    /// Returns an error if `request` isn't a valid find node request.
+    pub fn build_kademlia_find_node_response<'a>(
+        &self,
+        requester: &PeerId,
+        request: &[u8],
+        known_peers: impl Iterator<Item = (&'a PeerId, &'a [Multiaddr], Option<&'a [u8]>)>,
+    ) -> Result<Vec<u8>, codec::DecodeFindNodeRequestError> {
+        // Maximum number of peers in a response, as defined by the Kademlia specification.
+        const MAX_PEERS: usize = 20;
+
+        let target_hash: [u8; 32] =
+            <sha2::Sha256 as sha2::Digest>::digest(codec::decode_find_node_request(request)?)
+                .into();
+
+        let local_peer_id = PeerId::from_public_key(&peer_id::PublicKey::Ed25519(
+            *self.noise_key.libp2p_public_ed25519_key(),
+        ));
+
+        let mut closer_peers = known_peers
+            .filter(|(peer_id, addrs, _)| {
+                !addrs.is_empty() && *peer_id != requester && **peer_id != local_peer_id
+            })
+            .map(|(peer_id, addrs, signed_envelope)| {
+                let mut distance: [u8; 32] =
+                    <sha2::Sha256 as sha2::Digest>::digest(peer_id.as_bytes()).into();
+                for (byte, target_byte) in distance.iter_mut().zip(target_hash.iter()) {
+                    *byte ^= *target_byte;
+                }
+                (distance, peer_id, addrs, signed_envelope)
+            })
+            .collect::<Vec<_>>();
+        closer_peers.sort_unstable_by_key(|(distance, _, _, _)| *distance);
+        closer_peers.dedup_by(|(a, _, _, _), (b, _, _, _)| a == b);
+        closer_peers.truncate(MAX_PEERS);
+
+        Ok(codec::build_find_node_response(
+            closer_peers
+                .into_iter()
+                .map(|(_, peer_id, addrs, signed_envelope)| {
+                    (
+                        peer_id.as_bytes(),
+                        addrs.iter().map(|addr| addr.as_ref()),
+                        signed_envelope,
+                    )
+                }),
+        ))
+    }
+
     /// Underlying implementation of all the functions that start requests.
     fn start_request(
         &mut self,
         target: &PeerId,
-        request_data: Vec<u8>,
+        request_data: Option<Vec<u8>>,
         protocol: Protocol,
         timeout: Duration,
     ) -> Result<SubstreamId, StartRequestError> {
+        let protocol_name = self.protocol_name(protocol);
+
+        // Connections on which the remote has recently refused the protocol are skipped.
         // TODO: cloning of `PeerId` overhead
         // TODO: this is O(n) but is it really a problem? you're only supposed to have max 1 or 2 connections per PeerId
+        let mut any_connection = false;
         let connection_id = self
             .connections_by_peer_id
             .range(
@@ -2658,97 +4799,44 @@ where
                     ..=(target.clone(), collection::ConnectionId::max_value()),
             )
             .map(|(_, connection_id)| *connection_id)
-            .find(|connection_id| {
+            .filter(|connection_id| {
                 let state = self.inner.connection_state(*connection_id);
                 state.established && !state.shutting_down
             })
-            .ok_or(StartRequestError::NoConnection)?;
-
-        let protocol_name = {
-            let protocol_name = match protocol {
-                Protocol::Identify => protocol::ProtocolName::Identify,
-                Protocol::Ping => protocol::ProtocolName::Ping,
-                Protocol::BlockAnnounces { chain_index } => {
-                    let chain_info = &self.chains[chain_index];
-                    protocol::ProtocolName::BlockAnnounces {
-                        genesis_hash: chain_info.genesis_hash,
-                        fork_id: chain_info.fork_id.as_deref(),
-                    }
-                }
-                Protocol::Transactions { chain_index } => {
-                    let chain_info = &self.chains[chain_index];
-                    protocol::ProtocolName::Transactions {
-                        genesis_hash: chain_info.genesis_hash,
-                        fork_id: chain_info.fork_id.as_deref(),
-                    }
-                }
-                Protocol::Grandpa { chain_index } => {
-                    let chain_info = &self.chains[chain_index];
-                    protocol::ProtocolName::Grandpa {
-                        genesis_hash: chain_info.genesis_hash,
-                        fork_id: chain_info.fork_id.as_deref(),
-                    }
-                }
-                Protocol::Sync { chain_index } => {
-                    let chain_info = &self.chains[chain_index];
-                    protocol::ProtocolName::Sync {
-                        genesis_hash: chain_info.genesis_hash,
-                        fork_id: chain_info.fork_id.as_deref(),
-                    }
-                }
-                Protocol::LightUnknown { chain_index } => {
-                    let chain_info = &self.chains[chain_index];
-                    protocol::ProtocolName::Light {
-                        genesis_hash: chain_info.genesis_hash,
-                        fork_id: chain_info.fork_id.as_deref(),
-                    }
-                }
-                Protocol::LightStorage { chain_index } => {
-                    let chain_info = &self.chains[chain_index];
-                    protocol::ProtocolName::Light {
-                        genesis_hash: chain_info.genesis_hash,
-                        fork_id: chain_info.fork_id.as_deref(),
-                    }
-                }
-                Protocol::LightCall { chain_index } => {
-                    let chain_info = &self.chains[chain_index];
-                    protocol::ProtocolName::Light {
-                        genesis_hash: chain_info.genesis_hash,
-                        fork_id: chain_info.fork_id.as_deref(),
-                    }
-                }
-                Protocol::Kad { chain_index } => {
-                    let chain_info = &self.chains[chain_index];
-                    protocol::ProtocolName::Kad {
-                        genesis_hash: chain_info.genesis_hash,
-                        fork_id: chain_info.fork_id.as_deref(),
-                    }
-                }
-                Protocol::SyncWarp { chain_index } => {
-                    let chain_info = &self.chains[chain_index];
-                    protocol::ProtocolName::SyncWarp {
-                        genesis_hash: chain_info.genesis_hash,
-                        fork_id: chain_info.fork_id.as_deref(),
-                    }
-                }
-                Protocol::State { chain_index } => {
-                    let chain_info = &self.chains[chain_index];
-                    protocol::ProtocolName::State {
-                        genesis_hash: chain_info.genesis_hash,
-                        fork_id: chain_info.fork_id.as_deref(),
-                    }
-                }
-            };
+            .find(|connection_id| {
+                any_connection = true;
+                !self
+                    .refused_protocols
+                    .contains_key(&(*connection_id, protocol_name.clone()))
+            });
+        let connection_id = match connection_id {
+            Some(connection_id) => connection_id,
+            None if any_connection => return Err(StartRequestError::ProtocolNotAvailable),
+            None => return Err(StartRequestError::NoConnection),
+        };
 
-            protocol::encode_protocol_name_string(protocol_name)
+        let max_response_size = match protocol {
+            Protocol::Custom {
+                chain_index,
+                protocol_index,
+            } => {
+                self.chains[chain_index].custom_protocols[protocol_index]
+                    .config
+                    .max_response_size
+            }
+            _ => 16 * 1024 * 1024,
         };
 
+        let protocol_metrics = self.protocols_metrics.entry(protocol).or_default();
+        protocol_metrics.requests_started += 1;
+        protocol_metrics.record_sent(request_data.as_ref().map_or(0, |data| data.len()));
+
         let substream_id = self.inner.start_request(
             connection_id,
             protocol_name,
-            Some(request_data),
+            request_data,
             timeout,
-            16 * 1024 * 1024,
+            max_response_size,
         );
 
         let _prev_value = self.substreams.insert(
@@ -2756,6 +4844,7 @@ where
             SubstreamInfo {
                 connection_id,
                 protocol,
+                schema: None,
             },
         );
         debug_assert!(_prev_value.is_none());
@@ -2763,11 +4852,86 @@ where
         Ok(substream_id)
     }
 
+    /// Cancels a request started with one of the `start_*_request` functions, for example
+    /// because its result is no longer needed.
+    ///
+    /// No [`Event::RequestResult`] will be generated for this request. The substream of the
+    /// request is reset.
+    ///
+    /// This function might generate a message destined a connection. Use
+    /// [`ChainNetwork::pull_message_to_connection`] to process messages after it has returned.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the [`SubstreamId`] is invalid or doesn't correspond to an outgoing request
+    /// whose [`Event::RequestResult`] hasn't been generated yet.
+    ///
+    pub fn cancel_request(&mut self, substream_id: SubstreamId) {
+        let _substream_info = self.substreams.remove(&substream_id).unwrap();
+        self.inner.cancel_request(substream_id);
+    }
+
+    /// Returns the names of the protocols that remotes are allowed to open inbound substreams
+    /// with, as reported in the responses to identify requests.
+    ///
+    /// This list is derived from the chains that have been added and from their configuration,
+    /// and thus changes whenever a chain or a custom protocol is added.
+    pub fn supported_protocols(&'_ self) -> impl Iterator<Item = String> + '_ {
+        // This must be kept in sync with the handling of `InboundNegotiated` events.
+        [codec::ProtocolName::Identify, codec::ProtocolName::Ping]
+            .into_iter()
+            .map(codec::encode_protocol_name_string)
+            .chain(self.chains.iter().flat_map(|(_, chain)| {
+                let genesis_hash = chain.genesis_hash;
+                let fork_id = chain.fork_id.as_deref();
+                [
+                    Some(codec::ProtocolName::BlockAnnounces {
+                        genesis_hash,
+                        fork_id,
+                    }),
+                    Some(codec::ProtocolName::Transactions {
+                        genesis_hash,
+                        fork_id,
+                    }),
+                    chain.grandpa_protocol_config.is_some().then_some(
+                        codec::ProtocolName::Grandpa {
+                            genesis_hash,
+                            fork_id,
+                        },
+                    ),
+                    chain
+                        .allow_inbound_block_requests
+                        .then_some(codec::ProtocolName::Sync {
+                            genesis_hash,
+                            fork_id,
+                        }),
+                ]
+                .into_iter()
+                .flatten()
+                .map(codec::encode_protocol_name_string)
+                .chain(
+                    chain
+                        .custom_protocols
+                        .iter()
+                        .filter(|p| p.config.allow_inbound_requests)
+                        .map(|p| p.name.clone()),
+                )
+                .chain(
+                    chain
+                        .custom_notifications_protocols
+                        .iter()
+                        .filter(|p| p.config.allow_inbound_substreams)
+                        .map(|p| p.name.clone()),
+                )
+            }))
+    }
+
     /// Responds to an identify request. Call this function in response to
     /// a [`Event::IdentifyRequestIn`].
     ///
     /// Only the `agent_version` needs to be specified. The other fields are automatically
-    /// filled by the [`ChainNetwork`].
+    /// filled by the [`ChainNetwork`]. In particular, the listen addresses that are reported are
+    /// the ones passed to [`ChainNetwork::add_local_address`].
     ///
     /// This function might generate a message destined a connection. Use
     /// [`ChainNetwork::pull_message_to_connection`] to process messages after it has returned.
@@ -2777,25 +4941,31 @@ where
     /// Panics if the [`SubstreamId`] is invalid or doesn't correspond to a blocks request or
     /// if the request has been cancelled with a [`Event::RequestInCancel`].
     ///
-    pub fn respond_identify(&mut self, substream_id: SubstreamId, agent_version: &str) {
+    pub fn respond_identify(&mut self, now: &TNow, substream_id: SubstreamId, agent_version: &str) {
         let substream_info = self.substreams.remove(&substream_id).unwrap();
         assert!(matches!(substream_info.protocol, Protocol::Identify { .. }));
 
-        let response = {
-            let observed_addr = &self.inner[substream_info.connection_id].address;
+        let response_time = self
+            .inbound_requests_received
+            .remove(&substream_id)
+            .map(|received| now.clone() - received);
+        self.inbound_identify_requests_stats
+            .record_answer(true, response_time);
 
-            // TODO: all protocols
-            let supported_protocols = [protocol::ProtocolName::Ping].into_iter();
+        let response = {
+            let connection_info = &self.inner[substream_info.connection_id];
+            let observed_addr = &connection_info.address;
 
-            let supported_protocols_names = supported_protocols
-                .map(|proto| protocol::encode_protocol_name_string(proto))
-                .collect::<Vec<_>>();
+            let supported_protocols_names = self.supported_protocols().collect::<Vec<_>>();
 
-            protocol::build_identify_response(protocol::IdentifyResponse {
+            codec::build_identify_response(codec::IdentifyResponse {
                 protocol_version: "/substrate/1.0", // TODO: same value as in Substrate, see also https://github.com/paritytech/substrate/issues/14331
                 agent_version,
-                ed25519_public_key: *self.noise_key.libp2p_public_ed25519_key(),
-                listen_addrs: iter::empty(), // TODO:
+                // The public key sent back is the one the connection has been authenticated with,
+                // as the remote would otherwise see an identity mismatch if the Noise key has
+                // been changed in the meantime.
+                ed25519_public_key: connection_info.local_ed25519_public_key,
+                listen_addrs: self.local_addresses.iter().map(|a| &a[..]),
                 observed_addr,
                 protocols: supported_protocols_names.iter().map(|p| &p[..]),
             })
@@ -2805,6 +4975,11 @@ where
             })
         };
 
+        self.protocols_metrics
+            .entry(Protocol::Identify)
+            .or_default()
+            .record_sent(response.len());
+
         self.inner.respond_in_request(substream_id, Ok(response));
     }
 
@@ -2824,15 +4999,26 @@ where
     // TOOD: more zero-cost parameter
     pub fn respond_blocks(
         &mut self,
+        now: &TNow,
         substream_id: SubstreamId,
-        response: Option<Vec<protocol::BlockData>>,
+        response: Option<Vec<codec::BlockData>>,
     ) {
         let substream_info = self.substreams.remove(&substream_id).unwrap();
-        assert!(matches!(substream_info.protocol, Protocol::Sync { .. }));
+        let Protocol::Sync { chain_index } = substream_info.protocol else {
+            panic!()
+        };
+
+        let response_time = self
+            .inbound_requests_received
+            .remove(&substream_id)
+            .map(|received| now.clone() - received);
+        self.chains[chain_index]
+            .inbound_blocks_requests_stats
+            .record_answer(response.is_some(), response_time);
 
         let response = if let Some(response) = response {
             Ok(
-                protocol::build_block_response(response).fold(Vec::new(), |mut a, b| {
+                codec::build_block_response(response).fold(Vec::new(), |mut a, b| {
                     a.extend_from_slice(b.as_ref());
                     a
                 }),
@@ -2841,34 +5027,833 @@ where
             Err(())
         };
 
-        self.inner.respond_in_request(substream_id, response);
+        if let Ok(response) = &response {
+            self.protocols_metrics
+                .entry(substream_info.protocol)
+                .or_default()
+                .record_sent(response.len());
+        }
+
+        self.inner.respond_in_request(substream_id, response);
+    }
+
+    /// Responds to a request on a custom protocol. Call this function in response to
+    /// a [`Event::CustomRequestIn`], or once a request reported with an
+    /// [`Event::CustomRequestInStart`] has been fully received.
+    ///
+    /// Pass `None` in order to deny the request.
+    ///
+    /// This function might generate a message destined a connection. Use
+    /// [`ChainNetwork::pull_message_to_connection`] to process messages after it has returned.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the [`SubstreamId`] is invalid or doesn't correspond to a request on a custom
+    /// protocol or if the request has been cancelled with a [`Event::RequestInCancel`].
+    ///
+    pub fn respond_custom(
+        &mut self,
+        now: &TNow,
+        substream_id: SubstreamId,
+        response: Option<Vec<u8>>,
+    ) {
+        let substream_info = self.substreams.remove(&substream_id).unwrap();
+        let Protocol::Custom {
+            chain_index,
+            protocol_index,
+        } = substream_info.protocol
+        else {
+            panic!()
+        };
+
+        let response_time = self
+            .inbound_requests_received
+            .remove(&substream_id)
+            .map(|received| now.clone() - received);
+        self.chains[chain_index].custom_protocols[protocol_index]
+            .inbound_requests_stats
+            .record_answer(response.is_some(), response_time);
+
+        if let Some(response) = &response {
+            self.protocols_metrics
+                .entry(substream_info.protocol)
+                .or_default()
+                .record_sent(response.len());
+        }
+
+        self.inner
+            .respond_in_request(substream_id, response.ok_or(()));
+    }
+
+    /// Returns metrics about the activity of the network: number of connections opened and
+    /// closed, and counters of each protocol of each chain.
+    ///
+    /// The ping protocol, which is handled entirely by the connections, isn't covered.
+    pub fn metrics(&self) -> NetworkMetrics {
+        let mut metrics = NetworkMetrics {
+            connections_opened: self.connections_opened,
+            connections_closed: self.connections_closed,
+            protocols: BTreeMap::new(),
+            chains: BTreeMap::new(),
+        };
+
+        for (protocol, protocol_metrics) in &self.protocols_metrics {
+            let chain_index = match *protocol {
+                Protocol::Identify | Protocol::Ping => None,
+                Protocol::BlockAnnounces { chain_index }
+                | Protocol::Transactions { chain_index }
+                | Protocol::Grandpa { chain_index }
+                | Protocol::Sync { chain_index }
+                | Protocol::LightUnknown { chain_index }
+                | Protocol::LightStorage { chain_index }
+                | Protocol::LightCall { chain_index }
+                | Protocol::Kad { chain_index }
+                | Protocol::SyncWarp { chain_index }
+                | Protocol::State { chain_index }
+                | Protocol::Custom { chain_index, .. }
+                | Protocol::CustomNotifications { chain_index, .. } => Some(chain_index),
+            };
+
+            // Multiple variants of `Protocol` can share the same protocol name, in which case
+            // their counters are added together.
+            let map = match chain_index {
+                Some(chain_index) => metrics.chains.entry(ChainId(chain_index)).or_default(),
+                None => &mut metrics.protocols,
+            };
+            map.entry(self.protocol_name(*protocol))
+                .or_default()
+                .merge(protocol_metrics);
+        }
+
+        metrics
+    }
+
+    /// Returns statistics about the inbound requests of the given protocol.
+    ///
+    /// These statistics are accumulated since the [`ChainNetwork`] has been created (or, for
+    /// chain-specific protocols, since the chain has been added) and are never reset.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the [`ChainId`] is invalid.
+    ///
+    pub fn inbound_requests_stats(
+        &self,
+        protocol: InboundRequestsProtocol,
+    ) -> &InboundRequestsStats {
+        match protocol {
+            InboundRequestsProtocol::Identify => &self.inbound_identify_requests_stats,
+            InboundRequestsProtocol::Blocks { chain_id } => {
+                &self.chains[chain_id.0].inbound_blocks_requests_stats
+            }
+            InboundRequestsProtocol::Custom {
+                chain_id,
+                protocol_index,
+            } => &self.chains[chain_id.0].custom_protocols[protocol_index].inbound_requests_stats,
+        }
+    }
+
+    /// Returns the statistics to update for inbound requests of the given protocol, or `None`
+    /// if no statistics are tracked for this protocol.
+    fn inbound_requests_stats_mut(
+        &mut self,
+        protocol: Protocol,
+    ) -> Option<&mut InboundRequestsStats> {
+        match protocol {
+            Protocol::Identify => Some(&mut self.inbound_identify_requests_stats),
+            Protocol::Sync { chain_index } => {
+                Some(&mut self.chains[chain_index].inbound_blocks_requests_stats)
+            }
+            Protocol::Custom {
+                chain_index,
+                protocol_index,
+            } => Some(
+                &mut self.chains[chain_index].custom_protocols[protocol_index]
+                    .inbound_requests_stats,
+            ),
+            _ => None,
+        }
+    }
+
+    /// Returns the reputation of the given peer.
+    ///
+    /// The reputation of a peer starts at 0, and is lowered when the peer violates a networking
+    /// protocol, for example by sending an invalid request or notification, or when a request
+    /// sent to it times out. It can also be modified with [`ChainNetwork::report_peer`].
+    ///
+    /// If [`Config::reputation_ban`] is `Some`, the peer is automatically banned (see
+    /// [`ChainNetwork::ban_peer`]) when its reputation reaches the configured threshold.
+    pub fn peer_reputation(&self, peer_id: &PeerId) -> i32 {
+        self.peers_reputation
+            .get(peer_id)
+            .map_or(0, |reputation| reputation.score)
+    }
+
+    /// Adds `change` to the reputation of the given peer. See [`ChainNetwork::peer_reputation`].
+    ///
+    /// If [`Config::reputation_ban`] is `Some` and the new reputation is below the configured
+    /// threshold, the peer is banned for the configured duration, starting at `now`.
+    pub fn report_peer(&mut self, now: &TNow, peer_id: &PeerId, change: i32) {
+        let reputation = self
+            .peers_reputation
+            .entry(peer_id.clone())
+            .or_insert(PeerReputation {
+                score: 0,
+                banned_until: None,
+            });
+        reputation.score = reputation.score.saturating_add(change);
+
+        let should_ban = reputation.banned_until.is_none()
+            && self
+                .reputation_ban
+                .as_ref()
+                .map_or(false, |config| reputation.score <= config.threshold);
+        let can_forget = reputation.score == 0 && reputation.banned_until.is_none();
+
+        if should_ban {
+            let until = now.clone() + self.reputation_ban.as_ref().unwrap().duration;
+            self.ban_peer(peer_id, until);
+        } else if can_forget {
+            self.peers_reputation.remove(peer_id);
+        }
+    }
+
+    /// Bans the given peer until the given moment. If the peer is already banned, the ban is
+    /// replaced with the new one.
+    ///
+    /// Banned peers are no longer returned by [`ChainNetwork::unconnected_desired`], and the
+    /// gossip substreams that they open are refused. Existing connections and gossip links with
+    /// the peer are unaffected and must be closed by the API user if desired.
+    ///
+    /// Bans are lifted when [`ChainNetwork::next_event`] is called with a `now` superior or equal
+    /// to the end of the ban, at which point the reputation of the peer is reset to 0. See also
+    /// [`ChainNetwork::next_ban_expiration`].
+    pub fn ban_peer(&mut self, peer_id: &PeerId, until: TNow) {
+        let reputation = self
+            .peers_reputation
+            .entry(peer_id.clone())
+            .or_insert(PeerReputation {
+                score: 0,
+                banned_until: None,
+            });
+
+        if let Some(previous) = reputation.banned_until.replace(until.clone()) {
+            let _was_in = self
+                .peers_bans_expiration
+                .remove(&(previous, peer_id.clone()));
+            debug_assert!(_was_in);
+        }
+
+        let _was_inserted = self.peers_bans_expiration.insert((until, peer_id.clone()));
+        debug_assert!(_was_inserted);
+    }
+
+    /// Returns the moment when the given peer stops being banned, or `None` if it isn't banned.
+    /// See [`ChainNetwork::ban_peer`].
+    pub fn peer_banned_until(&self, peer_id: &PeerId) -> Option<&TNow> {
+        self.peers_reputation
+            .get(peer_id)
+            .and_then(|reputation| reputation.banned_until.as_ref())
+    }
+
+    /// Returns the moment when the earliest ban expires, or `None` if no peer is banned.
+    ///
+    /// The API user is encouraged to call [`ChainNetwork::next_event`] at this moment in order
+    /// for the ban to be lifted.
+    pub fn next_ban_expiration(&self) -> Option<&TNow> {
+        self.peers_bans_expiration.first().map(|(when, _)| when)
+    }
+
+    fn is_banned(&self, peer_id: &PeerId) -> bool {
+        self.peer_banned_until(peer_id).is_some()
+    }
+
+    /// Returns the name of the given request-response protocol, as negotiated with remotes.
+    fn protocol_name(&self, protocol: Protocol) -> String {
+        if let Protocol::Custom {
+            chain_index,
+            protocol_index,
+        } = protocol
+        {
+            self.chains[chain_index].custom_protocols[protocol_index]
+                .name
+                .clone()
+        } else if let Protocol::CustomNotifications {
+            chain_index,
+            protocol_index,
+        } = protocol
+        {
+            self.chains[chain_index].custom_notifications_protocols[protocol_index]
+                .name
+                .clone()
+        } else {
+            let protocol_name = match protocol {
+                Protocol::Identify => codec::ProtocolName::Identify,
+                Protocol::Ping => codec::ProtocolName::Ping,
+                Protocol::BlockAnnounces { chain_index } => {
+                    let chain_info = &self.chains[chain_index];
+                    codec::ProtocolName::BlockAnnounces {
+                        genesis_hash: chain_info.genesis_hash,
+                        fork_id: chain_info.fork_id.as_deref(),
+                    }
+                }
+                Protocol::Transactions { chain_index } => {
+                    let chain_info = &self.chains[chain_index];
+                    codec::ProtocolName::Transactions {
+                        genesis_hash: chain_info.genesis_hash,
+                        fork_id: chain_info.fork_id.as_deref(),
+                    }
+                }
+                Protocol::Grandpa { chain_index } => {
+                    let chain_info = &self.chains[chain_index];
+                    codec::ProtocolName::Grandpa {
+                        genesis_hash: chain_info.genesis_hash,
+                        fork_id: chain_info.fork_id.as_deref(),
+                    }
+                }
+                Protocol::Sync { chain_index } => {
+                    let chain_info = &self.chains[chain_index];
+                    codec::ProtocolName::Sync {
+                        genesis_hash: chain_info.genesis_hash,
+                        fork_id: chain_info.fork_id.as_deref(),
+                    }
+                }
+                Protocol::LightUnknown { chain_index } => {
+                    let chain_info = &self.chains[chain_index];
+                    codec::ProtocolName::Light {
+                        genesis_hash: chain_info.genesis_hash,
+                        fork_id: chain_info.fork_id.as_deref(),
+                    }
+                }
+                Protocol::LightStorage { chain_index } => {
+                    let chain_info = &self.chains[chain_index];
+                    codec::ProtocolName::Light {
+                        genesis_hash: chain_info.genesis_hash,
+                        fork_id: chain_info.fork_id.as_deref(),
+                    }
+                }
+                Protocol::LightCall { chain_index } => {
+                    let chain_info = &self.chains[chain_index];
+                    codec::ProtocolName::Light {
+                        genesis_hash: chain_info.genesis_hash,
+                        fork_id: chain_info.fork_id.as_deref(),
+                    }
+                }
+                Protocol::Kad { chain_index } => {
+                    let chain_info = &self.chains[chain_index];
+                    codec::ProtocolName::Kad {
+                        genesis_hash: chain_info.genesis_hash,
+                        fork_id: chain_info.fork_id.as_deref(),
+                    }
+                }
+                Protocol::SyncWarp { chain_index } => {
+                    let chain_info = &self.chains[chain_index];
+                    codec::ProtocolName::SyncWarp {
+                        genesis_hash: chain_info.genesis_hash,
+                        fork_id: chain_info.fork_id.as_deref(),
+                    }
+                }
+                Protocol::State { chain_index } => {
+                    let chain_info = &self.chains[chain_index];
+                    codec::ProtocolName::State {
+                        genesis_hash: chain_info.genesis_hash,
+                        fork_id: chain_info.fork_id.as_deref(),
+                    }
+                }
+                Protocol::Custom { .. } | Protocol::CustomNotifications { .. } => unreachable!(),
+            };
+
+            codec::encode_protocol_name_string(protocol_name)
+        }
+    }
+
+    fn insert_protocol_refusal(
+        &mut self,
+        connection_id: collection::ConnectionId,
+        protocol_name: String,
+        until: TNow,
+    ) {
+        if let Some(previous_until) = self
+            .refused_protocols
+            .insert((connection_id, protocol_name.clone()), until.clone())
+        {
+            let _was_removed = self.refused_protocols_expiration.remove(&(
+                previous_until,
+                connection_id,
+                protocol_name.clone(),
+            ));
+            debug_assert!(_was_removed);
+        }
+
+        let _was_inserted =
+            self.refused_protocols_expiration
+                .insert((until, connection_id, protocol_name));
+        debug_assert!(_was_inserted);
+    }
+
+    fn remove_protocol_refusals(&mut self, connection_id: collection::ConnectionId) {
+        let protocol_names = self
+            .refused_protocols
+            .range((connection_id, String::new())..)
+            .take_while(|((c, _), _)| *c == connection_id)
+            .map(|((_, protocol_name), _)| protocol_name.clone())
+            .collect::<Vec<_>>();
+
+        for protocol_name in protocol_names {
+            let until = self
+                .refused_protocols
+                .remove(&(connection_id, protocol_name.clone()))
+                .unwrap();
+            let _was_removed =
+                self.refused_protocols_expiration
+                    .remove(&(until, connection_id, protocol_name));
+            debug_assert!(_was_removed);
+        }
+    }
+
+    fn lift_expired_protocol_refusals(&mut self, now: &TNow) {
+        while self
+            .refused_protocols_expiration
+            .first()
+            .map_or(false, |(when, _, _)| *when <= *now)
+        {
+            let (_, connection_id, protocol_name) =
+                self.refused_protocols_expiration.pop_first().unwrap();
+            let _was_in = self
+                .refused_protocols
+                .remove(&(connection_id, protocol_name));
+            debug_assert!(_was_in.is_some());
+        }
+    }
+
+    /// Removes the bans that expire before or at `now`, and resets the reputation of the
+    /// corresponding peers.
+    fn lift_expired_bans(&mut self, now: &TNow) {
+        while self
+            .peers_bans_expiration
+            .first()
+            .map_or(false, |(when, _)| *when <= *now)
+        {
+            let (_, peer_id) = self.peers_bans_expiration.pop_first().unwrap();
+            let _was_in = self.peers_reputation.remove(&peer_id);
+            debug_assert!(_was_in.is_some());
+        }
+    }
+
+    /// Returns the list of all peers for a [`Event::GossipConnected`] event of the given kind has
+    /// been emitted.
+    /// It is possible to send gossip notifications to these peers.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the [`ChainId`] is invalid.
+    ///
+    pub fn gossip_connected_peers(
+        &'_ self,
+        chain_id: ChainId,
+        kind: GossipKind,
+    ) -> impl Iterator<Item = &'_ PeerId> + '_ {
+        assert!(self.chains.contains(chain_id.0));
+        let GossipKind::ConsensusTransactions = kind;
+        // TODO: O(n) ; optimize this by using range(), but that's a bit complicated
+        self.gossip_links
+            .iter()
+            .filter(move |((chain_index, _), link)| {
+                *chain_index == chain_id.0
+                    && matches!(
+                        link.out_substreams.block_announces,
+                        Some((NotificationsSubstreamState::Open, _))
+                    )
+            })
+            .map(|((_, peer_id), _)| peer_id)
+    }
+
+    /// Returns the list of peers with an open gossip link on the given chain that are assumed to
+    /// know the given block, according to the best block they have reported in their block
+    /// announces handshake and in their block announces.
+    ///
+    /// When a block number is passed, peers whose best block number is superior or equal to it
+    /// are returned, under the assumption that all peers are on the same chain as the local
+    /// node. When a block hash is passed, only the peers whose best block has this hash are
+    /// returned.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the [`ChainId`] is invalid.
+    ///
+    pub fn peers_with_block(
+        &'_ self,
+        chain_id: ChainId,
+        block: BlockHashOrNumber,
+    ) -> impl Iterator<Item = &'_ PeerId> + '_ {
+        assert!(self.chains.contains(chain_id.0));
+        // TODO: O(n) ; optimize this by using range(), but that's a bit complicated
+        self.gossip_links
+            .iter()
+            .filter(move |((chain_index, _), link)| {
+                *chain_index == chain_id.0
+                    && match (block, link.best_block) {
+                        (_, None) => false,
+                        (BlockHashOrNumber::Hash(hash), Some((_, best_hash))) => best_hash == hash,
+                        (BlockHashOrNumber::Number(number), Some((best_number, _))) => {
+                            best_number >= number
+                        }
+                    }
+            })
+            .map(|((_, peer_id), _)| peer_id)
+    }
+
+    /// Returns the best block number and hash of the given peer on the given chain, as reported
+    /// in its block announces handshake and in its block announces.
+    ///
+    /// Returns `None` if there is no open gossip link with this peer.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the [`ChainId`] is invalid.
+    ///
+    pub fn gossip_peer_best_block(
+        &self,
+        chain_id: ChainId,
+        peer_id: &PeerId,
+    ) -> Option<(u64, [u8; 32])> {
+        assert!(self.chains.contains(chain_id.0));
+        self.gossip_links
+            .get(&GossipLinkKey(chain_id.0, peer_id))
+            .and_then(|link| link.best_block)
+    }
+
+    /// Returns the health metrics of the gossip link with the given peer on the given chain.
+    ///
+    /// Returns `None` if neither [`ChainNetwork::gossip_open`] has been called for this peer nor
+    /// the peer has opened any gossip substream, or if the peer is no longer desired and all the
+    /// substreams of the gossip link have been closed since.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the [`ChainId`] is invalid.
+    ///
+    pub fn gossip_link_info(
+        &self,
+        chain_id: ChainId,
+        peer_id: &PeerId,
+        kind: GossipKind,
+    ) -> Option<&GossipLinkInfo> {
+        assert!(self.chains.contains(chain_id.0));
+        let GossipKind::ConsensusTransactions = kind;
+        self.gossip_links
+            .get(&GossipLinkKey(chain_id.0, peer_id))
+            .map(|link| &link.info)
+    }
+
+    /// Removes the entry of [`ChainNetwork::gossip_links`] corresponding to the given
+    /// chain and peer if the peer isn't desired and no substream of the gossip link exists
+    /// anymore.
+    ///
+    /// Must be called after substreams have been removed from a gossip link.
+    fn gossip_link_cleanup(&mut self, chain_index: usize, peer_id: &PeerId) {
+        if self.gossip_desired_peers.contains(&(
+            peer_id.clone(),
+            GossipKind::ConsensusTransactions,
+            chain_index,
+        )) {
+            return;
+        }
+
+        // A peer that is no longer desired must not be automatically re-opened.
+        self.gossip_open_retries
+            .remove(&(chain_index, peer_id.clone()));
+
+        if self
+            .gossip_links
+            .get(&GossipLinkKey(chain_index, peer_id))
+            .map_or(false, |link| {
+                link.in_substreams.is_empty() && link.out_substreams.is_empty()
+            })
+        {
+            self.gossip_links
+                .remove(&GossipLinkKey(chain_index, peer_id));
+        }
+    }
+
+    /// Updates the local state after the outbound block announces substream of the gossip link
+    /// with the given peer has been closed, and closes the outbound transactions and GrandPa
+    /// substreams, which are tied to the block announces substream.
+    ///
+    /// The block announces substream must have been removed from the gossip link beforehand.
+    ///
+    /// The inbound substreams are left untouched. They can only be closed by the remote, which
+    /// normally does so after noticing that the outbound block announces substream is closed.
+    fn gossip_link_closed(&mut self, chain_index: usize, peer_id: &PeerId) {
+        if let Some(link) = self
+            .gossip_links
+            .get_mut(&GossipLinkKey(chain_index, peer_id))
+        {
+            debug_assert!(link.out_substreams.block_announces.is_none());
+            for (_, substream_id) in [
+                link.out_substreams.transactions.take(),
+                link.out_substreams.grandpa.take(),
+            ]
+            .into_iter()
+            .flatten()
+            {
+                self.inner.close_out_notifications(substream_id);
+                self.substreams.remove(&substream_id);
+                self.notifications_dropped.remove(&substream_id);
+            }
+
+            link.info.connection_direction = None;
+            link.best_block = None;
+        }
+
+        self.gossip_link_cleanup(chain_index, peer_id);
+        self.debug_check_gossip_link(chain_index, peer_id);
+    }
+
+    /// Returns the state and identifier of the substream of the given notifications protocol and
+    /// direction of the gossip link with the given peer, if any.
+    fn gossip_link_substream(
+        &self,
+        protocol: NotificationsProtocol,
+        peer_id: &PeerId,
+        direction: SubstreamDirection,
+    ) -> Option<(NotificationsSubstreamState, SubstreamId)> {
+        self.gossip_links
+            .get(&GossipLinkKey(protocol.chain_index(), peer_id))?
+            .substreams(direction)
+            .get(protocol)
+    }
+
+    /// Sets the state and identifier of the substream of the given notifications protocol and
+    /// direction of the gossip link with the given peer, creating the gossip link if necessary.
+    ///
+    /// If a substream with the same protocol and direction exists, it must have the same
+    /// identifier. In other words, this function either inserts a substream or updates its
+    /// state.
+    fn gossip_link_substream_insert(
+        &mut self,
+        protocol: NotificationsProtocol,
+        peer_id: &PeerId,
+        direction: SubstreamDirection,
+        state: NotificationsSubstreamState,
+        substream_id: SubstreamId,
+    ) {
+        let link = match self
+            .gossip_links
+            .get_mut(&GossipLinkKey(protocol.chain_index(), peer_id))
+        {
+            Some(link) => link,
+            None => self
+                .gossip_links
+                .entry((protocol.chain_index(), peer_id.clone()))
+                .or_default(),
+        };
+
+        let slot = link.substreams_mut(direction).get_mut(protocol);
+        debug_assert!(slot.map_or(true, |(_, id)| id == substream_id));
+        *slot = Some((state, substream_id));
+    }
+
+    /// Removes the substream of the given notifications protocol and direction from the gossip
+    /// link with the given peer, and returns its state and identifier.
+    ///
+    /// The gossip link itself is never removed. [`ChainNetwork::gossip_link_cleanup`] must be
+    /// called afterwards.
+    fn gossip_link_substream_remove(
+        &mut self,
+        protocol: NotificationsProtocol,
+        peer_id: &PeerId,
+        direction: SubstreamDirection,
+    ) -> Option<(NotificationsSubstreamState, SubstreamId)> {
+        self.gossip_links
+            .get_mut(&GossipLinkKey(protocol.chain_index(), peer_id))?
+            .substreams_mut(direction)
+            .get_mut(protocol)
+            .take()
+    }
+
+    /// Verifies, when debug assertions are enabled, the invariants of the gossip link with the
+    /// given peer.
+    ///
+    /// Must be called after every modification of the state of a gossip link.
+    fn debug_check_gossip_link(&self, chain_index: usize, peer_id: &PeerId) {
+        if !cfg!(debug_assertions) {
+            return;
+        }
+
+        let Some(link) = self.gossip_links.get(&GossipLinkKey(chain_index, peer_id)) else {
+            return;
+        };
+
+        // The entry of the gossip link is removed once it is useless.
+        debug_assert!(
+            !link.in_substreams.is_empty()
+                || !link.out_substreams.is_empty()
+                || self.gossip_desired_peers.contains(&(
+                    peer_id.clone(),
+                    GossipKind::ConsensusTransactions,
+                    chain_index
+                ))
+        );
+
+        // All the substreams of the gossip link are known and belong to the right protocol.
+        for direction in [SubstreamDirection::In, SubstreamDirection::Out] {
+            for protocol in [
+                NotificationsProtocol::BlockAnnounces { chain_index },
+                NotificationsProtocol::Transactions { chain_index },
+                NotificationsProtocol::Grandpa { chain_index },
+            ] {
+                if let Some((_, substream_id)) = link.substreams(direction).get(protocol) {
+                    debug_assert_eq!(
+                        self.substreams
+                            .get(&substream_id)
+                            .map(|substream| substream.protocol),
+                        Some(Protocol::from(protocol))
+                    );
+                }
+            }
+        }
+
+        let out_block_announces_open = matches!(
+            link.out_substreams.block_announces,
+            Some((NotificationsSubstreamState::Open, _))
+        );
+
+        // The outbound transactions and GrandPa substreams only exist while the outbound block
+        // announces substream is open.
+        if !out_block_announces_open {
+            debug_assert!(link.out_substreams.transactions.is_none());
+            debug_assert!(link.out_substreams.grandpa.is_none());
+        }
+
+        // An inbound block announces substream is only left pending if no outbound block
+        // announces substream exists, and the inbound transactions and GrandPa substreams are
+        // only left pending while the inbound block announces substream is pending.
+        let in_block_announces_pending = matches!(
+            link.in_substreams.block_announces,
+            Some((NotificationsSubstreamState::Pending, _))
+        );
+        if in_block_announces_pending {
+            debug_assert!(link.out_substreams.block_announces.is_none());
+        } else {
+            debug_assert!(!matches!(
+                link.in_substreams.transactions,
+                Some((NotificationsSubstreamState::Pending, _))
+            ));
+            debug_assert!(!matches!(
+                link.in_substreams.grandpa,
+                Some((NotificationsSubstreamState::Pending, _))
+            ));
+        }
+
+        // The information only known while the link is open is cleared when it closes.
+        debug_assert_eq!(link.best_block.is_some(), out_block_announces_open);
+        debug_assert_eq!(
+            link.info.connection_direction.is_some(),
+            out_block_announces_open
+        );
     }
 
-    /// Returns the list of all peers for a [`Event::GossipConnected`] event of the given kind has
-    /// been emitted.
-    /// It is possible to send gossip notifications to these peers.
+    /// Called when opening the gossip link with the given peer has failed. Updates
+    /// [`GossipLinkInfo::consecutive_open_failures`] and, if [`Config::gossip_open_retry`]
+    /// allows it, schedules an automatic re-opening.
     ///
-    /// # Panic
+    /// Returns `true` if a re-opening has been scheduled, in which case the failure must not be
+    /// reported to the API user.
+    fn gossip_open_failure_schedule_retry(
+        &mut self,
+        chain_index: usize,
+        peer_id: &PeerId,
+        error: &GossipConnectError,
+    ) -> bool {
+        // The link info is missing if the peer is no longer desired.
+        let Some(link) = self
+            .gossip_links
+            .get_mut(&GossipLinkKey(chain_index, peer_id))
+        else {
+            return false;
+        };
+
+        link.info.consecutive_open_failures = link.info.consecutive_open_failures.saturating_add(1);
+
+        let Some(retry_config) = &self.gossip_open_retry else {
+            return false;
+        };
+
+        // A handshake that can't be decoded or a genesis hash mismatch is unlikely to be solved
+        // by trying again.
+        if !matches!(error, GossipConnectError::Substream(_))
+            || link.info.consecutive_open_failures >= retry_config.max_attempts.get()
+        {
+            return false;
+        }
+
+        // The peer has been put back in `connected_unopened_gossip_desired` only if it is still
+        // desired and connected. If the connection is gone, the failure is reported as usual.
+        if !self.connected_unopened_gossip_desired.remove(&(
+            peer_id.clone(),
+            ChainId(chain_index),
+            GossipKind::ConsensusTransactions,
+        )) {
+            return false;
+        }
+
+        self.gossip_open_retries
+            .insert((chain_index, peer_id.clone()), None);
+        true
+    }
+
+    /// Re-opens the gossip links whose automatic re-opening is due. See
+    /// [`Config::gossip_open_retry`].
     ///
-    /// Panics if the [`ChainId`] is invalid.
+    /// The delay before re-opening a gossip link starts at the first call to this function
+    /// following the failure. This function should therefore be called after every call to
+    /// [`ChainNetwork::next_event`].
     ///
-    pub fn gossip_connected_peers(
-        &'_ self,
-        chain_id: ChainId,
-        kind: GossipKind,
-    ) -> impl Iterator<Item = &'_ PeerId> + '_ {
-        assert!(self.chains.contains(chain_id.0));
-        let GossipKind::ConsensusTransactions = kind;
-        // TODO: O(n) ; optimize this by using range(), but that's a bit complicated
-        self.notification_substreams_by_peer_id
-            .iter()
-            .filter(move |(p, _, d, s, _)| {
-                *p == NotificationsProtocol::BlockAnnounces {
-                    chain_index: chain_id.0,
-                } && *d == SubstreamDirection::Out
-                    && *s == NotificationsSubstreamState::Open
-            })
-            .map(|(_, peer_id, _, _, _)| peer_id)
+    /// Returns the moment when this function should be called again, or `None` if no
+    /// re-opening is pending. A [`Event::GossipConnected`] or [`Event::GossipOpenFailed`] is
+    /// later generated for each gossip link that has been re-opened, similar to
+    /// [`ChainNetwork::gossip_open`].
+    pub fn gossip_open_retry(&mut self, now: &TNow) -> Option<TNow> {
+        let Some(retry_config) = &self.gossip_open_retry else {
+            debug_assert!(self.gossip_open_retries.is_empty());
+            return None;
+        };
+
+        let mut due = Vec::new();
+        let mut next_wake_up: Option<TNow> = None;
+
+        for ((chain_index, peer_id), when) in &mut self.gossip_open_retries {
+            let when = when.get_or_insert_with(|| {
+                let consecutive_failures = self
+                    .gossip_links
+                    .get(&GossipLinkKey(*chain_index, peer_id))
+                    .map_or(1, |link| link.info.consecutive_open_failures);
+                now.clone() + gossip_open_backoff(retry_config, consecutive_failures)
+            });
+
+            if *when <= *now {
+                due.push((*chain_index, peer_id.clone()));
+            } else if next_wake_up.as_ref().is_none_or(|w| *w > *when) {
+                next_wake_up = Some(when.clone());
+            }
+        }
+
+        for (chain_index, peer_id) in due {
+            self.gossip_open_retries
+                .remove(&(chain_index, peer_id.clone()));
+
+            // Opening fails if the connection has been closed in the meanwhile, in which case the
+            // peer will be opened again after it reconnects, or if a gossip link has already been
+            // opened.
+            let _ = self.gossip_open(
+                ChainId(chain_index),
+                &peer_id,
+                GossipKind::ConsensusTransactions,
+            );
+        }
+
+        next_wake_up
     }
 
     /// Open a gossiping substream with the given peer on the given chain.
@@ -2880,56 +5865,47 @@ where
     ///
     /// Panics if the [`ChainId`] is invalid.
     ///
-    // TODO: proper error
     pub fn gossip_open(
         &mut self,
         chain_id: ChainId,
         target: &PeerId,
         kind: GossipKind,
-    ) -> Result<(), ()> {
+    ) -> Result<(), GossipOpenError> {
         let GossipKind::ConsensusTransactions = kind;
 
         let chain_info = &self.chains[chain_id.0];
 
         // It is forbidden to open more than one gossip notifications substream with any given
         // peer.
-        if self
-            .notification_substreams_by_peer_id
-            .range(
-                (
-                    NotificationsProtocol::BlockAnnounces {
-                        chain_index: chain_id.0,
-                    },
-                    target.clone(),
-                    SubstreamDirection::Out,
-                    NotificationsSubstreamState::min_value(),
-                    SubstreamId::min_value(),
-                )
-                    ..=(
-                        NotificationsProtocol::BlockAnnounces {
-                            chain_index: chain_id.0,
-                        },
-                        target.clone(),
-                        SubstreamDirection::Out,
-                        NotificationsSubstreamState::max_value(),
-                        SubstreamId::max_value(),
-                    ),
-            )
-            .next()
-            .is_some()
-        {
-            return Err(());
+        if let Some((state, _)) = self.gossip_link_substream(
+            NotificationsProtocol::BlockAnnounces {
+                chain_index: chain_id.0,
+            },
+            target,
+            SubstreamDirection::Out,
+        ) {
+            return Err(match state {
+                NotificationsSubstreamState::Pending => GossipOpenError::AlreadyOpening,
+                NotificationsSubstreamState::Open => GossipOpenError::AlreadyOpen,
+            });
+        }
+
+        // It is forbidden to open a gossip link with a peer that already uses the protocols of
+        // a chain with the same genesis hash but a different fork ID.
+        if self.has_other_fork_id_substreams(chain_id.0, target) {
+            return Err(GossipOpenError::ForkIdConflict);
         }
 
         let protocol_name =
-            protocol::encode_protocol_name_string(protocol::ProtocolName::BlockAnnounces {
+            codec::encode_protocol_name_string(codec::ProtocolName::BlockAnnounces {
                 genesis_hash: chain_info.genesis_hash,
                 fork_id: chain_info.fork_id.as_deref(),
             });
 
         // TODO: cloning of `PeerId` overhead
         // TODO: this is O(n) but is it really a problem? you're only supposed to have max 1 or 2 connections per PeerId
-        let connection_id = self
+        let mut any_shutting_down = false;
+        let Some(connection_id) = self
             .connections_by_peer_id
             .range(
                 (target.clone(), collection::ConnectionId::min_value())
@@ -2938,12 +5914,19 @@ where
             .map(|(_, connection_id)| *connection_id)
             .find(|connection_id| {
                 let state = self.inner.connection_state(*connection_id);
+                any_shutting_down |= state.established && state.shutting_down;
                 state.established && !state.shutting_down
             })
-            .ok_or(())?;
+        else {
+            return Err(if any_shutting_down {
+                GossipOpenError::ShuttingDown
+            } else {
+                GossipOpenError::NoConnection
+            });
+        };
 
-        let handshake = protocol::encode_block_announces_handshake(
-            protocol::BlockAnnouncesHandshakeRef {
+        let handshake = codec::encode_block_announces_handshake(
+            codec::BlockAnnouncesHandshakeRef {
                 best_hash: &chain_info.best_hash,
                 best_number: chain_info.best_number,
                 role: chain_info.role,
@@ -2956,12 +5939,32 @@ where
             a
         });
 
+        // If the remote has opened a block announces substream that is waiting to be accepted or
+        // rejected, it is accepted with the same handshake.
+        let pending_in_block_announces = match self.gossip_link_substream(
+            NotificationsProtocol::BlockAnnounces {
+                chain_index: chain_id.0,
+            },
+            target,
+            SubstreamDirection::In,
+        ) {
+            Some((NotificationsSubstreamState::Pending, substream_id)) => {
+                self.inner.accept_in_notifications(
+                    substream_id,
+                    handshake.clone(),
+                    self.chains[chain_id.0].max_notification_size,
+                );
+                Some(substream_id)
+            }
+            _ => None,
+        };
+
         let substream_id = self.inner.open_out_notifications(
             connection_id,
             protocol_name,
-            Duration::from_secs(10), // TODO: arbitrary
+            self.chains[chain_id.0].notifications_open_timeout,
             handshake,
-            1024 * 1024, // TODO: arbitrary
+            self.chains[chain_id.0].max_notifications_handshake_size,
         );
 
         let _prev_value = self.substreams.insert(
@@ -2971,20 +5974,31 @@ where
                 protocol: Protocol::BlockAnnounces {
                     chain_index: chain_id.0,
                 },
+                schema: None,
             },
         );
         debug_assert!(_prev_value.is_none());
 
-        let _was_inserted = self.notification_substreams_by_peer_id.insert((
+        self.gossip_link_substream_insert(
             NotificationsProtocol::BlockAnnounces {
                 chain_index: chain_id.0,
             },
-            target.clone(),
+            target,
             SubstreamDirection::Out,
             NotificationsSubstreamState::Pending,
             substream_id,
-        ));
-        debug_assert!(_was_inserted);
+        );
+        if let Some(in_substream_id) = pending_in_block_announces {
+            self.gossip_link_substream_insert(
+                NotificationsProtocol::BlockAnnounces {
+                    chain_index: chain_id.0,
+                },
+                target,
+                SubstreamDirection::In,
+                NotificationsSubstreamState::Open,
+                in_substream_id,
+            );
+        }
 
         if !self
             .gossip_desired_peers
@@ -3001,6 +6015,21 @@ where
         self.connected_unopened_gossip_desired
             .remove(&(target.clone(), chain_id, kind)); // TODO: clone
 
+        let link = self
+            .gossip_links
+            .entry((chain_id.0, target.clone()))
+            .or_default();
+        link.info.open_attempts = link.info.open_attempts.saturating_add(1);
+
+        self.gossip_open_retries
+            .remove(&(chain_id.0, target.clone()));
+
+        // Inbound transactions and GrandPa substreams opened by the remote before this call are
+        // now accepted, as they would have been if they had been opened after this call.
+        self.settle_buffered_in_gossip_substreams(chain_id.0, target, true);
+
+        self.debug_check_gossip_link(chain_id.0, target);
+
         Ok(())
     }
 
@@ -3012,7 +6041,14 @@ where
     /// which case no [`Event::GossipConnected`] or [`Event::GossipOpenFailed`] is generated.
     /// - To close a fully open gossip link. All the notifications that have been queued are still
     /// delivered. No event is generated.
-    /// - To respond to a [`Event::GossipInDesired`] by rejecting the request.
+    /// - To respond to a [`Event::GossipInDesired`] by rejecting the request. Prefer
+    /// [`ChainNetwork::gossip_reject_in`], which doesn't risk closing other links and updates
+    /// the rejection statistics.
+    ///
+    /// # Panic
+    ///
+    /// Returns an error if there was neither a gossip link open or being opened nor a pending
+    /// inbound request, in which case this function has no effect.
     ///
     /// # Panic
     ///
@@ -3023,8 +6059,7 @@ where
         chain_id: ChainId,
         peer_id: &PeerId,
         kind: GossipKind,
-    ) -> Result<(), ()> {
-        // TODO: proper return value
+    ) -> Result<(), GossipCloseError> {
         let GossipKind::ConsensusTransactions = kind;
 
         // An `assert!` is necessary because we don't actually access the chain information
@@ -3032,109 +6067,290 @@ where
         assert!(self.chains.contains(chain_id.0));
 
         // Reject inbound requests, if any.
-        if let Some(substream_id) = self
-            .notification_substreams_by_peer_id
-            .range(
-                (
-                    NotificationsProtocol::BlockAnnounces {
-                        chain_index: chain_id.0,
-                    },
-                    peer_id.clone(),
-                    SubstreamDirection::In,
-                    NotificationsSubstreamState::Pending,
-                    SubstreamId::min_value(),
-                )
-                    ..=(
-                        NotificationsProtocol::BlockAnnounces {
-                            chain_index: chain_id.0,
-                        },
-                        peer_id.clone(),
-                        SubstreamDirection::In,
-                        NotificationsSubstreamState::Pending,
-                        SubstreamId::max_value(),
-                    ),
-            )
-            .next()
-            .map(|(_, _, _, _, substream_id)| *substream_id)
-        {
-            self.inner.reject_in_notifications(substream_id);
+        let mut closed_any = self.reject_pending_in_gossip(chain_id, peer_id);
 
-            let _was_in = self.notification_substreams_by_peer_id.remove(&(
-                NotificationsProtocol::BlockAnnounces {
-                    chain_index: chain_id.0,
-                },
-                peer_id.clone(),
-                SubstreamDirection::In,
-                NotificationsSubstreamState::Pending,
-                substream_id,
-            ));
-            debug_assert!(_was_in);
+        // Close the outbound block announces substream, if any. The transactions and GrandPa
+        // substreams are closed alongside with it.
+        // The inbound substreams are left untouched, as they can only be closed by the remote.
+        // See `gossip_link_closed`.
+        if let Some((_, substream_id)) = self.gossip_link_substream_remove(
+            NotificationsProtocol::BlockAnnounces {
+                chain_index: chain_id.0,
+            },
+            peer_id,
+            SubstreamDirection::Out,
+        ) {
+            self.inner.close_out_notifications(substream_id);
 
             let _was_in = self.substreams.remove(&substream_id);
             debug_assert!(_was_in.is_some());
+            self.notifications_dropped.remove(&substream_id);
 
-            self.opened_gossip_undesired.remove(&(
-                chain_id,
-                peer_id.clone(),
-                GossipKind::ConsensusTransactions,
-            ));
+            closed_any = true;
+        }
 
-            // TODO: debug_assert that there's no inbound tx/gp substream?
+        self.gossip_link_closed(chain_id.0, peer_id);
+
+        if closed_any {
+            Ok(())
+        } else {
+            Err(GossipCloseError::NotOpen)
         }
+    }
 
-        // Close outbound substreams, if any.
-        for protocol in [
-            NotificationsProtocol::BlockAnnounces {
-                chain_index: chain_id.0,
-            },
-            NotificationsProtocol::Transactions {
-                chain_index: chain_id.0,
-            },
-            NotificationsProtocol::Grandpa {
+    /// Responds to a [`Event::GossipInDesired`] by rejecting the request.
+    ///
+    /// Contrary to [`ChainNetwork::gossip_close`], this function only concerns the pending
+    /// inbound gossip link and never closes a link that is already open or being opened.
+    ///
+    /// The given reason is used to update the statistics returned by
+    /// [`ChainNetwork::gossip_in_rejects_stats`]. The notifications protocols don't provide any
+    /// way to communicate a reason to the remote, and the reason is thus never sent out.
+    ///
+    /// # Panic
+    ///
+    /// Panics if [`ChainId`] is invalid.
+    ///
+    pub fn gossip_reject_in(
+        &mut self,
+        chain_id: ChainId,
+        peer_id: &PeerId,
+        kind: GossipKind,
+        reason: GossipRejectReason,
+    ) -> Result<(), GossipRejectInError> {
+        let GossipKind::ConsensusTransactions = kind;
+
+        // An `assert!` is necessary in order to panic if the chain is invalid even if there is
+        // no pending request.
+        assert!(self.chains.contains(chain_id.0));
+
+        if !self.reject_pending_in_gossip(chain_id, peer_id) {
+            return Err(GossipRejectInError::NoPendingRequest);
+        }
+
+        self.chains[chain_id.0]
+            .gossip_in_rejects_stats
+            .record(reason);
+        Ok(())
+    }
+
+    /// Returns statistics about the inbound gossip links of the given chain that have been
+    /// rejected with [`ChainNetwork::gossip_reject_in`].
+    ///
+    /// These statistics are accumulated since the chain has been added and are never reset.
+    ///
+    /// # Panic
+    ///
+    /// Panics if [`ChainId`] is invalid.
+    ///
+    pub fn gossip_in_rejects_stats(&self, chain_id: ChainId) -> &GossipInRejectsStats {
+        &self.chains[chain_id.0].gossip_in_rejects_stats
+    }
+
+    /// Marks the given peer as desired on the given chain, similar to
+    /// [`ChainNetwork::gossip_insert_desired`], but only if an outbound slot is available.
+    ///
+    /// The peer keeps its slot until it is removed with [`ChainNetwork::gossip_remove_desired`],
+    /// including if it is later added to and removed from the reserved peers.
+    ///
+    /// # Panic
+    ///
+    /// Panics if [`ChainId`] is invalid, or if the chain has been added without
+    /// [`ChainConfig::gossip_slots`].
+    ///
+    pub fn gossip_assign_out_slot(
+        &mut self,
+        chain_id: ChainId,
+        peer_id: PeerId,
+    ) -> Result<(), GossipAssignOutSlotError> {
+        let slots = self.chains[chain_id.0].gossip_slots.as_ref().unwrap();
+
+        if self.gossip_desired_peers.contains(&(
+            peer_id.clone(),
+            GossipKind::ConsensusTransactions,
+            chain_id.0,
+        )) {
+            return Err(GossipAssignOutSlotError::AlreadyAssigned);
+        }
+
+        if slots.reserved_only {
+            return Err(GossipAssignOutSlotError::ReservedOnly);
+        }
+
+        // Reserved peers only occupy a slot if they have been assigned one before becoming
+        // reserved.
+        // TODO: O(n), optimize
+        let num_assigned = self
+            .gossip_desired_peers_by_chain
+            .iter()
+            .filter(|(c, k, p)| {
+                *c == chain_id.0
+                    && *k == GossipKind::ConsensusTransactions
+                    && (!slots.reserved_peers.contains(p) || slots.out_slots_assigned.contains(p))
+            })
+            .count();
+        if num_assigned >= slots.out_slots {
+            return Err(GossipAssignOutSlotError::SlotsFull);
+        }
+
+        let _was_inserted = self.gossip_insert_desired(
+            chain_id,
+            peer_id.clone(),
+            GossipKind::ConsensusTransactions,
+        );
+        debug_assert!(_was_inserted);
+        let _was_inserted = self.chains[chain_id.0]
+            .gossip_slots
+            .as_mut()
+            .unwrap()
+            .out_slots_assigned
+            .insert(peer_id);
+        debug_assert!(_was_inserted);
+        Ok(())
+    }
+
+    /// Adds the given peer to the reserved peers of the given chain. See
+    /// [`GossipSlotsConfig::reserved_peers`].
+    ///
+    /// The peer is marked as desired, and doesn't occupy any slot. If the peer has previously
+    /// been assigned an outbound slot with [`ChainNetwork::gossip_assign_out_slot`], it keeps
+    /// this slot.
+    ///
+    /// Returns `false` if the peer was already reserved.
+    ///
+    /// # Panic
+    ///
+    /// Panics if [`ChainId`] is invalid, or if the chain has been added without
+    /// [`ChainConfig::gossip_slots`].
+    ///
+    pub fn gossip_add_reserved(&mut self, chain_id: ChainId, peer_id: PeerId) -> bool {
+        let slots = self.chains[chain_id.0].gossip_slots.as_mut().unwrap();
+        if !slots.reserved_peers.insert(peer_id.clone()) {
+            return false;
+        }
+
+        self.gossip_insert_desired(chain_id, peer_id, GossipKind::ConsensusTransactions);
+        true
+    }
+
+    /// Removes the given peer from the reserved peers of the given chain.
+    ///
+    /// The peer is no longer marked as desired, unless it has been assigned an outbound slot
+    /// with [`ChainNetwork::gossip_assign_out_slot`]. If the peer is no longer desired and a
+    /// gossip link with this peer is open, it is kept open but occupies an inbound slot. Use
+    /// [`ChainNetwork::gossip_close`] in order to close it.
+    ///
+    /// Returns `false` if the peer wasn't reserved.
+    ///
+    /// # Panic
+    ///
+    /// Panics if [`ChainId`] is invalid, or if the chain has been added without
+    /// [`ChainConfig::gossip_slots`].
+    ///
+    pub fn gossip_remove_reserved(&mut self, chain_id: ChainId, peer_id: &PeerId) -> bool {
+        let slots = self.chains[chain_id.0].gossip_slots.as_mut().unwrap();
+        if !slots.reserved_peers.remove(peer_id) {
+            return false;
+        }
+
+        if !slots.out_slots_assigned.contains(peer_id) {
+            self.gossip_remove_desired(chain_id, peer_id, GossipKind::ConsensusTransactions);
+        }
+        true
+    }
+
+    /// Enables or disables the "reserved only" mode of the given chain. See
+    /// [`GossipSlotsConfig::reserved_only`].
+    ///
+    /// Enabling this mode doesn't affect the gossip links that are already open or the outbound
+    /// slots that are already assigned. Use [`ChainNetwork::gossip_remove_desired`] and
+    /// [`ChainNetwork::gossip_close`] in order to remove them.
+    ///
+    /// # Panic
+    ///
+    /// Panics if [`ChainId`] is invalid, or if the chain has been added without
+    /// [`ChainConfig::gossip_slots`].
+    ///
+    pub fn gossip_set_reserved_only(&mut self, chain_id: ChainId, reserved_only: bool) {
+        self.chains[chain_id.0]
+            .gossip_slots
+            .as_mut()
+            .unwrap()
+            .reserved_only = reserved_only;
+    }
+
+    /// Determines whether an inbound gossip link with the given peer must be accepted or
+    /// rejected according to [`ChainConfig::gossip_slots`].
+    ///
+    /// Returns `None` if the chain doesn't have any slots configuration, in which case the
+    /// API user must decide.
+    fn gossip_slots_in_decision(
+        &self,
+        chain_id: ChainId,
+        peer_id: &PeerId,
+    ) -> Option<Result<(), GossipRejectReason>> {
+        let slots = self.chains[chain_id.0].gossip_slots.as_ref()?;
+
+        // Note that reserved peers are always desired.
+        if self.gossip_desired_peers.contains(&(
+            peer_id.clone(),
+            GossipKind::ConsensusTransactions,
+            chain_id.0,
+        )) {
+            return Some(Ok(()));
+        }
+
+        if slots.reserved_only {
+            return Some(Err(GossipRejectReason::NotReserved));
+        }
+
+        if self.opened_gossip_undesired_by_chain(chain_id).count() >= slots.in_slots {
+            return Some(Err(GossipRejectReason::SlotsFull));
+        }
+
+        Some(Ok(()))
+    }
+
+    /// Rejects the pending inbound block announces substream of the given peer on the given
+    /// chain, if any, alongside with the transactions and GrandPa substreams that have been
+    /// buffered.
+    ///
+    /// Returns `false` if there wasn't any pending inbound substream.
+    fn reject_pending_in_gossip(&mut self, chain_id: ChainId, peer_id: &PeerId) -> bool {
+        let Some((NotificationsSubstreamState::Pending, substream_id)) = self
+            .gossip_link_substream(
+                NotificationsProtocol::BlockAnnounces {
+                    chain_index: chain_id.0,
+                },
+                peer_id,
+                SubstreamDirection::In,
+            )
+        else {
+            return false;
+        };
+
+        self.inner.reject_in_notifications(substream_id);
+        self.gossip_link_substream_remove(
+            NotificationsProtocol::BlockAnnounces {
                 chain_index: chain_id.0,
             },
-        ] {
-            if let Some((substream_id, state)) = self
-                .notification_substreams_by_peer_id
-                .range(
-                    (
-                        protocol,
-                        peer_id.clone(),
-                        SubstreamDirection::Out,
-                        NotificationsSubstreamState::min_value(),
-                        SubstreamId::min_value(),
-                    )
-                        ..=(
-                            protocol,
-                            peer_id.clone(),
-                            SubstreamDirection::Out,
-                            NotificationsSubstreamState::max_value(),
-                            SubstreamId::max_value(),
-                        ),
-                )
-                .next()
-                .map(|(_, _, _, state, substream_id)| (*substream_id, *state))
-            {
-                self.inner.close_out_notifications(substream_id);
-
-                let _was_in = self.notification_substreams_by_peer_id.remove(&(
-                    protocol,
-                    peer_id.clone(),
-                    SubstreamDirection::Out,
-                    state,
-                    substream_id,
-                ));
-                debug_assert!(_was_in);
+            peer_id,
+            SubstreamDirection::In,
+        );
 
-                let _was_in = self.substreams.remove(&substream_id);
-                debug_assert!(_was_in.is_some());
+        let _was_in = self.substreams.remove(&substream_id);
+        debug_assert!(_was_in.is_some());
 
-                // TODO: close tx and gp as well
-                // TODO: doesn't close inbound substreams
-            }
-        }
+        self.opened_gossip_undesired.remove(&(
+            chain_id,
+            peer_id.clone(),
+            GossipKind::ConsensusTransactions,
+        ));
 
-        Ok(())
+        self.settle_buffered_in_gossip_substreams(chain_id.0, peer_id, false);
+        self.gossip_link_cleanup(chain_id.0, peer_id);
+        self.debug_check_gossip_link(chain_id.0, peer_id);
+        true
     }
 
     /// Update the state of the local node with regards to GrandPa rounds.
@@ -3165,7 +6381,7 @@ where
         grandpa_state: GrandpaState,
     ) {
         // Bytes of the neighbor packet to send out.
-        let packet = protocol::GrandpaNotificationRef::Neighbor(protocol::NeighborPacket {
+        let packet = codec::GrandpaNotificationRef::Neighbor(codec::NeighborPacket {
             round_number: grandpa_state.round_number,
             set_id: grandpa_state.set_id,
             commit_finalized_height: grandpa_state.commit_finalized_height,
@@ -3178,19 +6394,34 @@ where
 
         // Now sending out to all the grandpa substreams that exist.
         // TODO: O(n)
-        for (_, _, _, _, substream_id) in
-            self.notification_substreams_by_peer_id
-                .iter()
-                .filter(|(p, _, d, s, _)| {
-                    *p == NotificationsProtocol::Grandpa {
-                        chain_index: chain_id.0,
-                    } && *d == SubstreamDirection::Out
-                        && *s == NotificationsSubstreamState::Open
-                })
-        {
-            match self.inner.queue_notification(*substream_id, packet.clone()) {
+        for ((chain_index, peer_id), link) in &self.gossip_links {
+            if *chain_index != chain_id.0 {
+                continue;
+            }
+            let Some((NotificationsSubstreamState::Open, substream_id)) =
+                link.out_substreams.grandpa
+            else {
+                continue;
+            };
+
+            let protocol = NotificationsProtocol::Grandpa {
+                chain_index: chain_id.0,
+            };
+            let result = self.inner.queue_notification(substream_id, packet.clone());
+            self.protocols_metrics
+                .entry(Protocol::from(protocol))
+                .or_default()
+                .record_notification_out(packet.len(), result.is_ok());
+            match result {
                 Ok(()) => {}
-                Err(collection::QueueNotificationError::QueueFull) => {}
+                Err(collection::QueueNotificationError::QueueFull) => {
+                    record_dropped_notification(
+                        &mut self.notifications_dropped,
+                        substream_id,
+                        peer_id,
+                        protocol,
+                    );
+                }
             }
         }
 
@@ -3201,6 +6432,109 @@ where
             .unwrap() = grandpa_state;
     }
 
+    /// Sends a GrandPa vote message (prevote, precommit, or primary propose) to the given peer.
+    ///
+    /// > **Note**: The message isn't validated in any way by this method.
+    ///
+    /// If no [`Event::GossipConnected`] event of kind [`GossipKind::ConsensusTransactions`] has
+    /// been emitted for the given peer, then a [`QueueNotificationError::NoConnection`] will be
+    /// returned.
+    ///
+    /// This function might generate a message destined a connection. Use
+    /// [`ChainNetwork::pull_message_to_connection`] to process messages after it has returned.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the [`ChainId`] is invalid.
+    ///
+    pub fn gossip_send_grandpa_vote(
+        &mut self,
+        target: &PeerId,
+        chain_id: ChainId,
+        vote: codec::VoteMessageRef,
+    ) -> Result<(), QueueNotificationError> {
+        self.queue_grandpa_notification(target, chain_id, codec::GrandpaNotificationRef::Vote(vote))
+    }
+
+    /// Sends a GrandPa catch-up request to the given peer, asking for the prevotes and
+    /// precommits of the latest round it has completed. The peer might answer with a
+    /// [`Event::GrandpaCatchUp`].
+    ///
+    /// If no [`Event::GossipConnected`] event of kind [`GossipKind::ConsensusTransactions`] has
+    /// been emitted for the given peer, then a [`QueueNotificationError::NoConnection`] will be
+    /// returned.
+    ///
+    /// This function might generate a message destined a connection. Use
+    /// [`ChainNetwork::pull_message_to_connection`] to process messages after it has returned.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the [`ChainId`] is invalid.
+    ///
+    pub fn gossip_send_grandpa_catch_up_request(
+        &mut self,
+        target: &PeerId,
+        chain_id: ChainId,
+        request: codec::CatchUpRequest,
+    ) -> Result<(), QueueNotificationError> {
+        self.queue_grandpa_notification(
+            target,
+            chain_id,
+            codec::GrandpaNotificationRef::CatchUpRequest(request),
+        )
+    }
+
+    /// Sends a GrandPa catch-up message to the given peer, normally in response to a
+    /// [`Event::GrandpaCatchUpRequest`].
+    ///
+    /// > **Note**: The message isn't validated in any way by this method.
+    ///
+    /// If no [`Event::GossipConnected`] event of kind [`GossipKind::ConsensusTransactions`] has
+    /// been emitted for the given peer, then a [`QueueNotificationError::NoConnection`] will be
+    /// returned.
+    ///
+    /// This function might generate a message destined a connection. Use
+    /// [`ChainNetwork::pull_message_to_connection`] to process messages after it has returned.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the [`ChainId`] is invalid.
+    ///
+    pub fn gossip_send_grandpa_catch_up(
+        &mut self,
+        target: &PeerId,
+        chain_id: ChainId,
+        catch_up: codec::CatchUpRef,
+    ) -> Result<(), QueueNotificationError> {
+        self.queue_grandpa_notification(
+            target,
+            chain_id,
+            codec::GrandpaNotificationRef::CatchUp(catch_up),
+        )
+    }
+
+    fn queue_grandpa_notification(
+        &mut self,
+        target: &PeerId,
+        chain_id: ChainId,
+        notification: codec::GrandpaNotificationRef,
+    ) -> Result<(), QueueNotificationError> {
+        let notification = notification
+            .scale_encoding(self.chains[chain_id.0].block_number_bytes)
+            .fold(Vec::new(), |mut a, b| {
+                a.extend_from_slice(b.as_ref());
+                a
+            });
+
+        self.queue_notification(
+            target,
+            NotificationsProtocol::Grandpa {
+                chain_index: chain_id.0,
+            },
+            notification,
+        )
+    }
+
     /// Sends a block announce gossip message to the given peer.
     ///
     /// If no [`Event::GossipConnected`] event of kind [`GossipKind::ConsensusTransactions`] has
@@ -3222,7 +6556,7 @@ where
         scale_encoded_header: &[u8],
         is_best: bool,
     ) -> Result<(), QueueNotificationError> {
-        let notification = protocol::encode_block_announce(protocol::BlockAnnounceRef {
+        let notification = codec::encode_block_announce(codec::BlockAnnounceRef {
             scale_encoded_header,
             is_best,
         })
@@ -3274,73 +6608,389 @@ where
         )
     }
 
+    /// Starts opening an outbound substream with the given peer on one of the
+    /// [`ChainConfig::custom_notifications_protocols`] of the given chain. `protocol_index` is
+    /// the index of the protocol within this list.
+    ///
+    /// `handshake` is sent to the remote as is. Contrary to the standard notifications
+    /// protocols, multiple substreams of the same protocol can be opened with the same peer.
+    ///
+    /// An [`Event::CustomNotificationsOutResult`] is guaranteed to later be generated, unless
+    /// [`ChainNetwork::custom_notifications_close`] is called in the meanwhile.
+    ///
+    /// This function might generate a message destined a connection. Use
+    /// [`ChainNetwork::pull_message_to_connection`] to process messages after it has returned.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the [`ChainId`] or the protocol index is invalid.
+    ///
+    pub fn custom_notifications_open(
+        &mut self,
+        target: &PeerId,
+        chain_id: ChainId,
+        protocol_index: usize,
+        handshake: Vec<u8>,
+        handshake_timeout: Duration,
+    ) -> Result<SubstreamId, StartRequestError> {
+        let custom_protocol =
+            &self.chains[chain_id.0].custom_notifications_protocols[protocol_index];
+
+        // TODO: cloning of `PeerId` overhead
+        let connection_id = self
+            .connections_by_peer_id
+            .range(
+                (target.clone(), collection::ConnectionId::min_value())
+                    ..=(target.clone(), collection::ConnectionId::max_value()),
+            )
+            .map(|(_, connection_id)| *connection_id)
+            .find(|connection_id| {
+                let state = self.inner.connection_state(*connection_id);
+                state.established && !state.shutting_down
+            })
+            .ok_or(StartRequestError::NoConnection)?;
+
+        let substream_id = self.inner.open_out_notifications(
+            connection_id,
+            custom_protocol.name.clone(),
+            handshake_timeout,
+            handshake,
+            custom_protocol.config.max_handshake_size,
+        );
+
+        let _prev_value = self.substreams.insert(
+            substream_id,
+            SubstreamInfo {
+                connection_id,
+                protocol: Protocol::CustomNotifications {
+                    chain_index: chain_id.0,
+                    protocol_index,
+                },
+                schema: None,
+            },
+        );
+        debug_assert!(_prev_value.is_none());
+
+        Ok(substream_id)
+    }
+
+    /// Closes an outbound substream of a custom notifications protocol, or cancels its opening.
+    ///
+    /// All the notifications that have been queued are still delivered. No event is generated.
+    /// The [`SubstreamId`] is considered invalid after this function returns.
+    ///
+    /// This function might generate a message destined a connection. Use
+    /// [`ChainNetwork::pull_message_to_connection`] to process messages after it has returned.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the [`SubstreamId`] is invalid or doesn't correspond to an outbound substream
+    /// opened with [`ChainNetwork::custom_notifications_open`].
+    ///
+    pub fn custom_notifications_close(&mut self, substream_id: SubstreamId) {
+        let substream_info = self.substreams.remove(&substream_id).unwrap();
+        assert!(matches!(
+            substream_info.protocol,
+            Protocol::CustomNotifications { .. }
+        ));
+        self.inner.close_out_notifications(substream_id);
+    }
+
+    /// Accepts an inbound substream of a custom notifications protocol reported by an
+    /// [`Event::CustomNotificationsInOpen`]. `handshake` is sent back to the remote as is.
+    ///
+    /// This function might generate a message destined a connection. Use
+    /// [`ChainNetwork::pull_message_to_connection`] to process messages after it has returned.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the [`SubstreamId`] is invalid or doesn't correspond to an inbound substream
+    /// of a custom notifications protocol that is waiting to be accepted or rejected.
+    ///
+    pub fn custom_notifications_accept(&mut self, substream_id: SubstreamId, handshake: Vec<u8>) {
+        let Protocol::CustomNotifications {
+            chain_index,
+            protocol_index,
+        } = self.substreams.get(&substream_id).unwrap().protocol
+        else {
+            panic!()
+        };
+
+        let max_notification_size = self.chains[chain_index].custom_notifications_protocols
+            [protocol_index]
+            .config
+            .max_notification_size;
+        self.inner
+            .accept_in_notifications(substream_id, handshake, max_notification_size);
+    }
+
+    /// Rejects an inbound substream of a custom notifications protocol reported by an
+    /// [`Event::CustomNotificationsInOpen`].
+    ///
+    /// The [`SubstreamId`] is considered invalid after this function returns.
+    ///
+    /// This function might generate a message destined a connection. Use
+    /// [`ChainNetwork::pull_message_to_connection`] to process messages after it has returned.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the [`SubstreamId`] is invalid or doesn't correspond to an inbound substream
+    /// of a custom notifications protocol that is waiting to be accepted or rejected.
+    ///
+    pub fn custom_notifications_reject(&mut self, substream_id: SubstreamId) {
+        let substream_info = self.substreams.remove(&substream_id).unwrap();
+        assert!(matches!(
+            substream_info.protocol,
+            Protocol::CustomNotifications { .. }
+        ));
+        self.inner.reject_in_notifications(substream_id);
+    }
+
+    /// Queues a notification on an outbound substream of a custom notifications protocol.
+    ///
+    /// Returns [`QueueNotificationError::QueueFull`] if the queue of notifications of the
+    /// substream is full, in which case the notification is discarded.
+    ///
+    /// This function might generate a message destined a connection. Use
+    /// [`ChainNetwork::pull_message_to_connection`] to process messages after it has returned.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the [`SubstreamId`] is invalid or doesn't correspond to an outbound substream
+    /// of a custom notifications protocol whose opening has succeeded.
+    ///
+    pub fn queue_custom_notification(
+        &mut self,
+        substream_id: SubstreamId,
+        notification: Vec<u8>,
+    ) -> Result<(), QueueNotificationError> {
+        let protocol = self.substreams.get(&substream_id).unwrap().protocol;
+        assert!(matches!(protocol, Protocol::CustomNotifications { .. }));
+
+        let notification_len = notification.len();
+        let result = self.inner.queue_notification(substream_id, notification);
+        self.protocols_metrics
+            .entry(protocol)
+            .or_default()
+            .record_notification_out(notification_len, result.is_ok());
+
+        match result {
+            Ok(()) => Ok(()),
+            Err(collection::QueueNotificationError::QueueFull) => {
+                Err(QueueNotificationError::QueueFull)
+            }
+        }
+    }
+
+    /// Returns the number of bytes that can be queued on the outbound substream of the given
+    /// gossip protocol with the given peer before notifications are dropped.
+    ///
+    /// Returns `None` if the peer isn't gossip-connected on this chain or if no substream of the
+    /// requested protocol is open with it.
+    ///
+    /// This value is only an indication, as the length of each notification is also counted
+    /// (in addition to its content) when determining whether the queue is full. After a
+    /// notification has been dropped because the queue was full, an
+    /// [`Event::GossipOutQueueDrained`] is generated once the queue has drained.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the [`ChainId`] is invalid.
+    ///
+    pub fn gossip_notification_queue_capacity(
+        &self,
+        target: &PeerId,
+        chain_id: ChainId,
+        protocol: GossipNotificationsProtocol,
+    ) -> Option<usize> {
+        let chain_index = chain_id.0;
+        let protocol = match protocol {
+            GossipNotificationsProtocol::BlockAnnounces => {
+                NotificationsProtocol::BlockAnnounces { chain_index }
+            }
+            GossipNotificationsProtocol::Transactions => {
+                NotificationsProtocol::Transactions { chain_index }
+            }
+            GossipNotificationsProtocol::Grandpa => NotificationsProtocol::Grandpa { chain_index },
+        };
+
+        let substream_id = self
+            .gossip_notification_substream(target, protocol)
+            .ok()
+            .flatten()?;
+        Some(self.inner.notification_queue_capacity(substream_id))
+    }
+
+    /// Returns the number of bytes that can be queued on the given outbound substream of a
+    /// custom notifications protocol before [`ChainNetwork::queue_custom_notification`] returns
+    /// [`QueueNotificationError::QueueFull`].
+    ///
+    /// This value is only an indication, as the length of each notification is also counted
+    /// (in addition to its content) when determining whether the queue is full.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the [`SubstreamId`] is invalid or doesn't correspond to an outbound substream
+    /// of a custom notifications protocol whose opening has succeeded.
+    ///
+    pub fn custom_notification_queue_capacity(&self, substream_id: SubstreamId) -> usize {
+        let protocol = self.substreams.get(&substream_id).unwrap().protocol;
+        assert!(matches!(protocol, Protocol::CustomNotifications { .. }));
+        self.inner.notification_queue_capacity(substream_id)
+    }
+
     /// Inner implementation for all the notifications sends.
     fn queue_notification(
         &mut self,
         target: &PeerId,
         protocol: NotificationsProtocol,
-        notification: Vec<u8>,
-    ) -> Result<(), QueueNotificationError> {
-        let chain_index = match protocol {
-            NotificationsProtocol::BlockAnnounces { chain_index } => chain_index,
-            NotificationsProtocol::Transactions { chain_index } => chain_index,
-            NotificationsProtocol::Grandpa { chain_index } => chain_index,
-        };
-
+        notification: Vec<u8>,
+    ) -> Result<(), QueueNotificationError> {
+        let Some(substream_id) = self.gossip_notification_substream(target, protocol)? else {
+            // If we are "gossip-connected" but no open transaction/grandpa substream exists, we
+            // silently discard the notification.
+            // TODO: this is a questionable behavior
+            return Ok(());
+        };
+
+        let notification_len = notification.len();
+        let result = self.inner.queue_notification(substream_id, notification);
+        self.protocols_metrics
+            .entry(Protocol::from(protocol))
+            .or_default()
+            .record_notification_out(notification_len, result.is_ok());
+
+        match result {
+            Ok(()) => Ok(()),
+            Err(collection::QueueNotificationError::QueueFull) => {
+                record_dropped_notification(
+                    &mut self.notifications_dropped,
+                    substream_id,
+                    target,
+                    protocol,
+                );
+                Err(QueueNotificationError::QueueFull)
+            }
+        }
+    }
+
+    /// Finds the outbound substream that notifications of the given protocol destined to the
+    /// given peer should be queued onto.
+    ///
+    /// Returns an error if the peer isn't gossip-connected, and `Ok(None)` if the peer is
+    /// gossip-connected but no substream of the requested protocol is open.
+    fn gossip_notification_substream(
+        &self,
+        target: &PeerId,
+        protocol: NotificationsProtocol,
+    ) -> Result<Option<SubstreamId>, QueueNotificationError> {
+        let chain_index = protocol.chain_index();
         assert!(self.chains.contains(chain_index));
 
         // We first find a block announces substream for that peer.
         // TODO: only relevant for GossipKind::ConsensusTransactions
         // If none is found, then we are not considered "gossip-connected", and return an error
         // no matter what, even if a substream of the requested protocol exists.
-        // TODO: O(n) ; optimize this by using range()
-        let block_announces_substream = self
-            .notification_substreams_by_peer_id
-            .iter()
-            .find(move |(p, id, d, s, _)| {
-                *p == NotificationsProtocol::BlockAnnounces { chain_index }
-                    && id == target
-                    && *d == SubstreamDirection::Out
-                    && *s == NotificationsSubstreamState::Open
-            })
-            .map(|(_, _, _, _, substream_id)| *substream_id)
+        let link = self
+            .gossip_links
+            .get(&GossipLinkKey(chain_index, target))
             .ok_or(QueueNotificationError::NoConnection)?;
+        let Some((NotificationsSubstreamState::Open, _)) = link.out_substreams.block_announces
+        else {
+            return Err(QueueNotificationError::NoConnection);
+        };
 
         // Now find a substream of the requested protocol.
-        let substream_id = if matches!(protocol, NotificationsProtocol::BlockAnnounces { .. }) {
-            block_announces_substream
-        } else {
-            // TODO: O(n) ; optimize this by using range()
-            let id = self
-                .notification_substreams_by_peer_id
-                .iter()
-                .find(move |(p, id, d, s, _)| {
-                    *p == protocol
-                        && id == target
-                        && *d == SubstreamDirection::Out
-                        && *s == NotificationsSubstreamState::Open
-                })
-                .map(|(_, _, _, _, substream_id)| *substream_id);
-            // If we are "gossip-connected" but no open transaction/grandpa substream exists, we
-            // silently discard the notification.
-            // TODO: this is a questionable behavior
-            let Some(id) = id else { return Ok(()) };
-            id
-        };
+        match link.out_substreams.get(protocol) {
+            Some((NotificationsSubstreamState::Open, substream_id)) => Ok(Some(substream_id)),
+            _ => Ok(None),
+        }
+    }
 
-        match self.inner.queue_notification(substream_id, notification) {
-            Ok(()) => Ok(()),
-            Err(collection::QueueNotificationError::QueueFull) => {
-                Err(QueueNotificationError::QueueFull)
+    /// Returns `true` if a notifications substream, in any direction and state, exists with the
+    /// given peer on a chain other than the given one but with the same genesis hash. In other
+    /// words, on a chain whose protocol names only differ by their fork ID.
+    fn has_other_fork_id_substreams(&self, chain_index: usize, peer_id: &PeerId) -> bool {
+        let genesis_hash = &self.chains[chain_index].genesis_hash;
+
+        self.chains
+            .iter()
+            .filter(|(idx, chain)| *idx != chain_index && chain.genesis_hash == *genesis_hash)
+            .any(|(other_chain_index, _)| {
+                self.gossip_links
+                    .get(&GossipLinkKey(other_chain_index, peer_id))
+                    .map_or(false, |link| {
+                        !link.in_substreams.is_empty() || !link.out_substreams.is_empty()
+                    })
+            })
+    }
+
+    /// Accepts or rejects the inbound transactions and GrandPa substreams of the given peer on
+    /// the given chain that have been buffered while the inbound block announces substream was
+    /// waiting to be accepted or rejected.
+    fn settle_buffered_in_gossip_substreams(
+        &mut self,
+        chain_index: usize,
+        peer_id: &PeerId,
+        accept: bool,
+    ) {
+        for protocol in [
+            NotificationsProtocol::Transactions { chain_index },
+            NotificationsProtocol::Grandpa { chain_index },
+        ] {
+            // At most one substream per protocol exists, as additional substreams of the same
+            // protocol are rejected when they are opened.
+            let Some((NotificationsSubstreamState::Pending, substream_id)) =
+                self.gossip_link_substream(protocol, peer_id, SubstreamDirection::In)
+            else {
+                continue;
+            };
+
+            if !accept {
+                self.gossip_link_substream_remove(protocol, peer_id, SubstreamDirection::In);
+                self.inner.reject_in_notifications(substream_id);
+                self.substreams.remove(&substream_id);
+                continue;
             }
+
+            let handshake = match protocol {
+                NotificationsProtocol::Grandpa { .. } => {
+                    self.chains[chain_index].role.scale_encoding().to_vec()
+                }
+                NotificationsProtocol::Transactions { .. } => Vec::new(),
+                NotificationsProtocol::BlockAnnounces { .. } => unreachable!(),
+            };
+            self.inner.accept_in_notifications(
+                substream_id,
+                handshake,
+                self.chains[chain_index].max_notification_size,
+            );
+
+            self.gossip_link_substream_insert(
+                protocol,
+                peer_id,
+                SubstreamDirection::In,
+                NotificationsSubstreamState::Open,
+                substream_id,
+            );
         }
     }
 
-    fn recognize_protocol(&self, protocol_name: &str) -> Result<Protocol, ()> {
-        Ok(match protocol::decode_protocol_name(protocol_name)? {
-            protocol::ProtocolName::Identify => Protocol::Identify,
-            protocol::ProtocolName::Ping => Protocol::Ping,
-            protocol::ProtocolName::BlockAnnounces {
+    fn recognize_protocol(
+        &self,
+        protocol_name: &str,
+    ) -> Result<(Protocol, Option<&'static codec::ProtocolSchema>), ()> {
+        if let Some(protocol) = self.custom_protocols_by_name.get(protocol_name) {
+            return Ok((*protocol, None));
+        }
+
+        let (protocol_name, schema) =
+            codec::decode_protocol_schema(protocol_name).map_err(|_| ())?;
+
+        let protocol = match protocol_name {
+            codec::ProtocolName::Identify => Protocol::Identify,
+            codec::ProtocolName::Ping => Protocol::Ping,
+            codec::ProtocolName::BlockAnnounces {
                 genesis_hash,
                 fork_id,
             } => Protocol::BlockAnnounces {
@@ -3349,7 +6999,7 @@ where
                     .get(&(genesis_hash, fork_id.map(|fork_id| fork_id.to_owned())))
                     .ok_or(())?,
             },
-            protocol::ProtocolName::Transactions {
+            codec::ProtocolName::Transactions {
                 genesis_hash,
                 fork_id,
             } => Protocol::Transactions {
@@ -3358,7 +7008,7 @@ where
                     .get(&(genesis_hash, fork_id.map(|fork_id| fork_id.to_owned())))
                     .ok_or(())?,
             },
-            protocol::ProtocolName::Grandpa {
+            codec::ProtocolName::Grandpa {
                 genesis_hash,
                 fork_id,
             } => Protocol::Grandpa {
@@ -3367,7 +7017,7 @@ where
                     .get(&(genesis_hash, fork_id.map(|fork_id| fork_id.to_owned())))
                     .ok_or(())?,
             },
-            protocol::ProtocolName::Sync {
+            codec::ProtocolName::Sync {
                 genesis_hash,
                 fork_id,
             } => Protocol::Sync {
@@ -3376,7 +7026,7 @@ where
                     .get(&(genesis_hash, fork_id.map(|fork_id| fork_id.to_owned())))
                     .ok_or(())?,
             },
-            protocol::ProtocolName::Light {
+            codec::ProtocolName::Light {
                 genesis_hash,
                 fork_id,
             } => Protocol::LightUnknown {
@@ -3385,7 +7035,7 @@ where
                     .get(&(genesis_hash, fork_id.map(|fork_id| fork_id.to_owned())))
                     .ok_or(())?,
             },
-            protocol::ProtocolName::Kad {
+            codec::ProtocolName::Kad {
                 genesis_hash,
                 fork_id,
             } => Protocol::Kad {
@@ -3394,7 +7044,7 @@ where
                     .get(&(genesis_hash, fork_id.map(|fork_id| fork_id.to_owned())))
                     .ok_or(())?,
             },
-            protocol::ProtocolName::SyncWarp {
+            codec::ProtocolName::SyncWarp {
                 genesis_hash,
                 fork_id,
             } => Protocol::SyncWarp {
@@ -3403,7 +7053,7 @@ where
                     .get(&(genesis_hash, fork_id.map(|fork_id| fork_id.to_owned())))
                     .ok_or(())?,
             },
-            protocol::ProtocolName::State {
+            codec::ProtocolName::State {
                 genesis_hash,
                 fork_id,
             } => Protocol::State {
@@ -3412,7 +7062,9 @@ where
                     .get(&(genesis_hash, fork_id.map(|fork_id| fork_id.to_owned())))
                     .ok_or(())?,
             },
-        })
+        };
+
+        Ok((protocol, schema))
     }
 }
 
@@ -3446,11 +7098,102 @@ pub enum MultiStreamHandshakeKind {
     },
 }
 
+/// Whether a connection has been initiated locally or by the remote.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum ConnectionDirection {
+    /// The connection has been initiated by the remote.
+    Inbound,
+    /// The connection has been initiated locally.
+    Outbound,
+}
+
+impl ConnectionDirection {
+    fn from_is_initiator(is_initiator: bool) -> Self {
+        if is_initiator {
+            ConnectionDirection::Outbound
+        } else {
+            ConnectionDirection::Inbound
+        }
+    }
+}
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum GossipKind {
     ConsensusTransactions,
 }
 
+/// Reason for rejecting an inbound gossip link. See [`ChainNetwork::gossip_reject_in`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum GossipRejectReason {
+    /// All the slots dedicated to inbound gossip links are occupied.
+    SlotsFull,
+    /// The peer is banned or is otherwise not trusted.
+    Banned,
+    /// Only reserved peers are accepted, and the peer isn't one of them. See
+    /// [`GossipSlotsConfig::reserved_only`].
+    NotReserved,
+    /// Any other reason.
+    Other,
+}
+
+/// Error potentially returned by [`ChainNetwork::gossip_open`].
+#[derive(Debug, Clone, PartialEq, Eq, derive_more::Display)]
+pub enum GossipOpenError {
+    /// A gossip link with this peer is already open.
+    AlreadyOpen,
+    /// A gossip link with this peer is already being opened.
+    AlreadyOpening,
+    /// The peer already uses the protocols of a chain with the same genesis hash but a
+    /// different fork ID.
+    ForkIdConflict,
+    /// There isn't any connection with this peer whose handshake has finished. Opening can be
+    /// attempted again after a connection has been established.
+    NoConnection,
+    /// All the connections with this peer whose handshake has finished are shutting down.
+    /// Opening can be attempted again after a new connection has been established.
+    ShuttingDown,
+}
+
+/// Error potentially returned by [`ChainNetwork::gossip_close`].
+#[derive(Debug, Clone, PartialEq, Eq, derive_more::Display)]
+pub enum GossipCloseError {
+    /// There isn't any gossip link with this peer that is open or being opened, nor any pending
+    /// inbound gossip link request.
+    NotOpen,
+}
+
+/// Error potentially returned by [`ChainNetwork::gossip_assign_out_slot`].
+#[derive(Debug, Clone, derive_more::Display)]
+pub enum GossipAssignOutSlotError {
+    /// The peer is already marked as desired, either because it has already been assigned a
+    /// slot or because it is reserved.
+    AlreadyAssigned,
+    /// Only reserved peers are allowed. See [`GossipSlotsConfig::reserved_only`].
+    ReservedOnly,
+    /// All the outbound slots are occupied.
+    SlotsFull,
+}
+
+/// Error potentially returned by [`ChainNetwork::gossip_reject_in`].
+#[derive(Debug, Clone, derive_more::Display)]
+pub enum GossipRejectInError {
+    /// There isn't any pending inbound gossip link request from this peer, either because no
+    /// [`Event::GossipInDesired`] has been generated or because it has already been answered.
+    NoPendingRequest,
+}
+
+/// Notifications protocol concerned by an [`Event::NotificationsDropped`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum GossipNotificationsProtocol {
+    /// Block announces.
+    BlockAnnounces,
+    /// Transactions.
+    Transactions,
+    /// GrandPa messages, including the neighbor packets automatically sent by the
+    /// [`ChainNetwork`].
+    Grandpa,
+}
+
 /// Error returned by [`ChainNetwork::add_chain`].
 #[derive(Debug, derive_more::Display, Clone)]
 pub enum AddChainError {
@@ -3460,6 +7203,30 @@ pub enum AddChainError {
         /// Identifier of the chain that uses the same genesis hash and fork id.
         existing_identical: ChainId,
     },
+    /// The name of one of the [`ChainConfig::custom_request_response_protocols`] conflicts with
+    /// another protocol.
+    #[display(fmt = "Name of custom protocol #{protocol_index} conflicts with another protocol.")]
+    CustomProtocolConflict {
+        /// Index of the protocol within [`ChainConfig::custom_request_response_protocols`].
+        protocol_index: usize,
+    },
+    /// The name of one of the [`ChainConfig::custom_notifications_protocols`] conflicts with
+    /// another protocol.
+    #[display(
+        fmt = "Name of custom notifications protocol #{protocol_index} conflicts with another protocol."
+    )]
+    CustomNotificationsProtocolConflict {
+        /// Index of the protocol within [`ChainConfig::custom_notifications_protocols`].
+        protocol_index: usize,
+    },
+}
+
+/// Error potentially returned by [`ChainNetwork::add_custom_request_response_protocol`].
+#[derive(Debug, derive_more::Display, Clone)]
+pub enum AddCustomProtocolError {
+    /// The name of the protocol conflicts with another protocol.
+    #[display(fmt = "Name of custom protocol conflicts with another protocol.")]
+    Conflict,
 }
 
 /// Event generated by [`ChainNetwork::next_event`].
@@ -3471,6 +7238,26 @@ pub enum Event {
     HandshakeFinished {
         /// Identifier of the connection.
         id: ConnectionId,
+        /// Whether the connection has been initiated locally or by the remote.
+        direction: ConnectionDirection,
+        /// Parameter that was passed to [`ChainNetwork::add_single_stream_connection`] or
+        /// [`ChainNetwork::add_multi_stream_connection`].
+        expected_peer_id: Option<PeerId>,
+        /// Actual [`PeerId`] of the connection.
+        peer_id: PeerId,
+    },
+
+    /// A connection has finished its handshake, but the limit to the number of connections with
+    /// this peer has been reached. See [`Config::max_connections_per_peer`].
+    ///
+    /// This event is generated instead of [`Event::HandshakeFinished`]. The connection is
+    /// automatically shut down, and a [`Event::Disconnected`] will later be generated for it.
+    /// The API user is encouraged to avoid dialing peers it is already connected to.
+    SupernumeraryConnection {
+        /// Identifier of the connection.
+        id: ConnectionId,
+        /// Whether the connection has been initiated locally or by the remote.
+        direction: ConnectionDirection,
         /// Parameter that was passed to [`ChainNetwork::add_single_stream_connection`] or
         /// [`ChainNetwork::add_multi_stream_connection`].
         expected_peer_id: Option<PeerId>,
@@ -3485,9 +7272,13 @@ pub enum Event {
         /// Parameter that was passed to [`ChainNetwork::add_single_stream_connection`] or
         /// [`ChainNetwork::add_multi_stream_connection`].
         address: Vec<u8>,
+        /// Whether the connection has been initiated locally or by the remote.
+        direction: ConnectionDirection,
         /// Parameter that was passed to [`ChainNetwork::add_single_stream_connection`] or
         /// [`ChainNetwork::add_multi_stream_connection`].
         expected_peer_id: Option<PeerId>,
+        /// Reason why the connection has shut down.
+        reason: DisconnectReason,
     },
 
     /// A connection has shut down after finishing its handshake.
@@ -3497,8 +7288,12 @@ pub enum Event {
         /// Parameter that was passed to [`ChainNetwork::add_single_stream_connection`] or
         /// [`ChainNetwork::add_multi_stream_connection`].
         address: Vec<u8>,
+        /// Whether the connection has been initiated locally or by the remote.
+        direction: ConnectionDirection,
         /// Peer that was connected.
         peer_id: PeerId,
+        /// Reason why the connection has shut down.
+        reason: DisconnectReason,
     },
 
     /// Now connected to the given peer for gossiping purposes.
@@ -3511,6 +7306,9 @@ pub enum Event {
         chain_id: ChainId,
         /// Which kind of gossip link is concerned.
         kind: GossipKind,
+        /// Direction of the connection the gossip link has been opened on. Also available
+        /// afterwards through [`GossipLinkInfo::connection_direction`].
+        connection_direction: ConnectionDirection,
         /// Role the node reports playing on the network.
         role: Role,
         /// Height of the best block according to this node.
@@ -3521,7 +7319,11 @@ pub enum Event {
 
     /// An attempt has been made to open the given chain, but something wrong happened.
     ///
-    /// This event can only happen as a result of a call to [`ChainNetwork::gossip_open`].
+    /// This event can only happen as a result of a call to [`ChainNetwork::gossip_open`] or
+    /// [`ChainNetwork::gossip_open_retry`].
+    ///
+    /// If [`Config::gossip_open_retry`] is `Some`, failures that can be solved by trying again
+    /// are only reported once the maximum number of attempts has been reached.
     GossipOpenFailed {
         /// Peer concerned by the event.
         peer_id: PeerId,
@@ -3544,6 +7346,9 @@ pub enum Event {
     },
 
     /// A peer would like to open a gossiping link with the local node.
+    ///
+    /// Never generated for chains that have been added with a [`ChainConfig::gossip_slots`], as
+    /// the decision is then made automatically.
     // TODO: document what to do
     // TODO: include handshake content?
     GossipInDesired {
@@ -3612,6 +7417,85 @@ pub enum Event {
         message: EncodedGrandpaCommitMessage,
     },
 
+    /// Received a GrandPa vote message (prevote, precommit, or primary propose) from the
+    /// network. The signature of the vote hasn't been verified.
+    ///
+    /// Can only happen after a [`Event::GossipConnected`] with the given [`PeerId`] and [`ChainId`]
+    /// combination has happened.
+    GrandpaVoteMessage {
+        /// Identity of the sender of the message.
+        peer_id: PeerId,
+        /// Index of the chain the vote message relates to.
+        chain_id: ChainId,
+        message: EncodedGrandpaVoteMessage,
+    },
+
+    /// Received a GrandPa catch-up request from the network. The peer asks for the prevotes and
+    /// precommits of the latest completed round. Answer with
+    /// [`ChainNetwork::gossip_send_grandpa_catch_up`].
+    ///
+    /// Can only happen after a [`Event::GossipConnected`] with the given [`PeerId`] and [`ChainId`]
+    /// combination has happened.
+    GrandpaCatchUpRequest {
+        /// Identity of the sender of the message.
+        peer_id: PeerId,
+        /// Index of the chain the request relates to.
+        chain_id: ChainId,
+        request: codec::CatchUpRequest,
+    },
+
+    /// Received a GrandPa catch-up message from the network, normally in response to a
+    /// catch-up request sent with [`ChainNetwork::gossip_send_grandpa_catch_up_request`]. The
+    /// signatures of the votes haven't been verified.
+    ///
+    /// Can only happen after a [`Event::GossipConnected`] with the given [`PeerId`] and [`ChainId`]
+    /// combination has happened.
+    GrandpaCatchUp {
+        /// Identity of the sender of the message.
+        peer_id: PeerId,
+        /// Index of the chain the catch-up message relates to.
+        chain_id: ChainId,
+        message: EncodedGrandpaCatchUp,
+    },
+
+    /// Some notifications destined to the given peer have been dropped because the queue of
+    /// notifications of the substream was full, which typically indicates that the peer is too
+    /// slow to process them.
+    ///
+    /// This includes notifications whose sending has failed with
+    /// [`QueueNotificationError::QueueFull`], but also the notifications that the
+    /// [`ChainNetwork`] sends automatically, such as GrandPa neighbor packets.
+    ///
+    /// Drops are reported before the other events, and the drops that happen between two calls
+    /// to [`ChainNetwork::next_event`] are grouped together. `count` is the number of drops since
+    /// the previous report concerning the same substream. Drops that concern a substream that
+    /// has been closed before they could be reported aren't reported.
+    NotificationsDropped {
+        /// Peer the notifications were destined to.
+        peer_id: PeerId,
+        /// Chain the notifications relate to.
+        chain_id: ChainId,
+        /// Protocol of the notifications.
+        protocol: GossipNotificationsProtocol,
+        /// Number of notifications that have been dropped. Always non-zero.
+        count: u32,
+    },
+
+    /// The queue of notifications destined to a peer, which was previously full, has drained
+    /// enough for notifications to be sent again.
+    ///
+    /// This event is only generated after a notification has been dropped because the queue
+    /// was full, and can be used to implement flow control. See also
+    /// [`ChainNetwork::gossip_notification_queue_capacity`].
+    GossipOutQueueDrained {
+        /// Peer the notifications are destined to.
+        peer_id: PeerId,
+        /// Chain the notifications relate to.
+        chain_id: ChainId,
+        /// Protocol of the notifications.
+        protocol: GossipNotificationsProtocol,
+    },
+
     /// Error in the protocol in a connection, such as failure to decode a message. This event
     /// doesn't have any consequence on the health of the connection, and is purely for diagnostic
     /// purposes.
@@ -3623,6 +7507,20 @@ pub enum Event {
         error: ProtocolError,
     },
 
+    /// A remote has opened a substream that couldn't be processed, for example because the
+    /// protocol negotiation was malformed.
+    ///
+    /// This event doesn't have any consequence on the health of the connection, and is purely
+    /// for diagnostic purposes. In order to avoid flooding the API user, only a limited number
+    /// of these events are generated per period of time, and the errors beyond this limit are
+    /// silently discarded.
+    InboundProtocolError {
+        /// Peer that has opened the substream.
+        peer_id: PeerId,
+        /// Error that happened.
+        error: InboundError,
+    },
+
     /// A remote has sent a request for identification information.
     ///
     /// You are strongly encouraged to call [`ChainNetwork::respond_identify`].
@@ -3644,11 +7542,147 @@ pub enum Event {
         /// Index of the chain concerned by the request.
         chain_id: ChainId,
         /// Information about the request.
-        config: protocol::BlocksRequestConfig,
+        config: codec::BlocksRequestConfig,
         /// Identifier of the request. Necessary to send back the answer.
         substream_id: SubstreamId,
     },
 
+    /// A remote has sent a request on one of the [`ChainConfig::custom_request_response_protocols`]
+    /// whose [`CustomRequestResponseProtocolConfig::allow_inbound_requests`] is `true`.
+    ///
+    /// You are strongly encouraged to call [`ChainNetwork::respond_custom`].
+    CustomRequestIn {
+        /// Remote that has sent the request.
+        peer_id: PeerId,
+        /// Index of the chain concerned by the request.
+        chain_id: ChainId,
+        /// Index of the protocol within [`ChainConfig::custom_request_response_protocols`].
+        protocol_index: usize,
+        /// Payload of the request, as sent by the remote. Not verified in any way.
+        request: Vec<u8>,
+        /// Identifier of the request. Necessary to send back the answer.
+        substream_id: SubstreamId,
+    },
+
+    /// A remote has started sending a request on one of the
+    /// [`ChainConfig::custom_request_response_protocols`] whose
+    /// [`CustomRequestResponseProtocolConfig::stream_inbound_requests`] is `true`.
+    ///
+    /// If `request_size` is 0, the request has been fully received. Otherwise, its payload is
+    /// reported through [`Event::CustomRequestInChunk`]s.
+    ///
+    /// Once the request has been fully received, you are strongly encouraged to call
+    /// [`ChainNetwork::respond_custom`].
+    CustomRequestInStart {
+        /// Remote that is sending the request.
+        peer_id: PeerId,
+        /// Index of the chain concerned by the request.
+        chain_id: ChainId,
+        /// Index of the protocol within [`ChainConfig::custom_request_response_protocols`].
+        protocol_index: usize,
+        /// Total size, in bytes, of the payload of the request. Guaranteed to be inferior or
+        /// equal to [`CustomRequestResponseProtocolConfig::max_request_size`].
+        request_size: usize,
+        /// Identifier of the request. Necessary to send back the answer.
+        substream_id: SubstreamId,
+    },
+
+    /// Part of the payload of a request previously reported with an
+    /// [`Event::CustomRequestInStart`].
+    CustomRequestInChunk {
+        /// Identifier of the request.
+        substream_id: SubstreamId,
+        /// Next bytes of the payload of the request, as sent by the remote. Not verified in any
+        /// way.
+        chunk: Vec<u8>,
+        /// `true` if this is the last chunk of the request. The request is now fully received.
+        is_last: bool,
+    },
+
+    /// Outcome of the opening of a substream started with
+    /// [`ChainNetwork::custom_notifications_open`].
+    ///
+    /// If `Ok`, notifications can now be queued with
+    /// [`ChainNetwork::queue_custom_notification`]. If `Err`, the [`SubstreamId`] is now invalid.
+    CustomNotificationsOutResult {
+        /// Peer the substream has been opened with.
+        peer_id: PeerId,
+        /// Index of the chain the protocol belongs to.
+        chain_id: ChainId,
+        /// Index of the protocol within [`ChainConfig::custom_notifications_protocols`].
+        protocol_index: usize,
+        /// Identifier of the substream.
+        substream_id: SubstreamId,
+        /// If `Ok`, contains the handshake sent back by the remote. Not verified in any way.
+        result: Result<Vec<u8>, NotificationsOutErr>,
+    },
+
+    /// An outbound substream of a custom notifications protocol has been closed by the remote
+    /// or as a consequence of the connection shutting down.
+    ///
+    /// The [`SubstreamId`] is now invalid.
+    CustomNotificationsOutClose {
+        /// Identifier of the substream.
+        substream_id: SubstreamId,
+    },
+
+    /// The queue of notifications of an outbound substream of a custom notifications protocol,
+    /// which was previously full, has drained enough for notifications to be queued again.
+    ///
+    /// This event is only generated after [`ChainNetwork::queue_custom_notification`] has
+    /// returned [`QueueNotificationError::QueueFull`] for this substream. See also
+    /// [`ChainNetwork::custom_notification_queue_capacity`].
+    CustomNotificationsOutQueueDrained {
+        /// Identifier of the substream.
+        substream_id: SubstreamId,
+    },
+
+    /// A remote would like to open a substream on one of the
+    /// [`ChainConfig::custom_notifications_protocols`] whose
+    /// [`CustomNotificationsProtocolConfig::allow_inbound_substreams`] is `true`.
+    ///
+    /// Must be answered with [`ChainNetwork::custom_notifications_accept`] or
+    /// [`ChainNetwork::custom_notifications_reject`].
+    CustomNotificationsInOpen {
+        /// Remote that would like to open the substream.
+        peer_id: PeerId,
+        /// Index of the chain the protocol belongs to.
+        chain_id: ChainId,
+        /// Index of the protocol within [`ChainConfig::custom_notifications_protocols`].
+        protocol_index: usize,
+        /// Identifier of the substream. Necessary to accept or reject it.
+        substream_id: SubstreamId,
+        /// Handshake sent by the remote. Not verified in any way.
+        handshake: Vec<u8>,
+    },
+
+    /// A remote has cancelled the opening of a substream reported with an
+    /// [`Event::CustomNotificationsInOpen`] before it has been accepted or rejected.
+    ///
+    /// The [`SubstreamId`] is now invalid.
+    CustomNotificationsInOpenCancel {
+        /// Identifier of the substream.
+        substream_id: SubstreamId,
+    },
+
+    /// Received a notification on an inbound substream of a custom notifications protocol
+    /// accepted with [`ChainNetwork::custom_notifications_accept`].
+    CustomNotificationIn {
+        /// Identifier of the substream.
+        substream_id: SubstreamId,
+        /// Notification sent by the remote. Not verified in any way.
+        notification: Vec<u8>,
+    },
+
+    /// An inbound substream of a custom notifications protocol accepted with
+    /// [`ChainNetwork::custom_notifications_accept`] has been closed.
+    ///
+    /// The [`SubstreamId`] is now invalid.
+    CustomNotificationsInClose {
+        /// Identifier of the substream.
+        substream_id: SubstreamId,
+    },
+
     /// A remote is no longer interested in the response to a request.
     ///
     /// Calling [`ChainNetwork::respond_identify`], [`ChainNetwork::respond_blocks`], or similar
@@ -3659,10 +7693,62 @@ pub enum Event {
         /// This [`SubstreamId`] is considered dead and no longer valid.
         substream_id: SubstreamId,
     },
-    /*Transactions {
+
+    /// Received transactions from a peer on the transactions notifications protocol.
+    ///
+    /// The transactions haven't been verified in any way.
+    TransactionsReceived {
+        /// Identity of the peer that has sent the transactions.
+        peer_id: PeerId,
+        /// Index of the chain the transactions relate to.
+        chain_id: ChainId,
+        /// List of SCALE-encoded transactions, in the same format as the one accepted by
+        /// [`ChainNetwork::gossip_send_transaction`].
+        transactions: Vec<Vec<u8>>,
+    },
+
+    /// An outgoing ping has been answered by a peer.
+    ///
+    /// Pings are automatically sent on each connection. See [`Config::ping_interval`].
+    PingResult {
+        /// Peer that has answered the ping.
         peer_id: PeerId,
-        transactions: EncodedTransactions,
-    }*/
+        /// Time it took for the peer to answer.
+        rtt: Duration,
+    },
+}
+
+/// Reason why a connection has shut down. See [`Event::Disconnected`] and
+/// [`Event::PreHandshakeDisconnected`].
+#[derive(Debug, derive_more::Display)]
+pub enum DisconnectReason {
+    /// Shutdown was demanded by the remote and performed cleanly.
+    CleanShutdown,
+    /// Remote has abruptly reset the connection.
+    RemoteReset,
+    /// Handshake phase took too long.
+    #[display(fmt = "Handshake timeout during {_0}")]
+    HandshakeTimeout(HandshakeTimeoutStage),
+    /// Error in the protocol of the handshake.
+    HandshakeError(HandshakeError),
+    /// Error in the connection protocol of a fully established connection.
+    ProtocolError(crate::libp2p::connection::established::Error),
+    /// Too many outgoing pings have failed in a row. See [`Config::max_ping_failures`].
+    PingFailures,
+    /// Shutdown has been initiated locally, either through [`ChainNetwork::start_shutdown`] or
+    /// [`ChainNetwork::disconnect_peer`], or because of [`Config::max_connections_per_peer`].
+    LocallyRequested,
+}
+
+impl DisconnectReason {
+    /// Returns `true` if the shutdown is caused by a faulty behavior by the remote. Returns
+    /// `false` if the shutdown can happen in normal situations, such as a network issue.
+    pub fn is_protocol_error(&self) -> bool {
+        matches!(
+            self,
+            DisconnectReason::HandshakeError(_) | DisconnectReason::ProtocolError(_)
+        )
+    }
 }
 
 /// See [`Event::ProtocolError`].
@@ -3677,15 +7763,22 @@ pub enum ProtocolError {
     BadBlockAnnouncesHandshake(BlockAnnouncesHandshakeDecodeError),
     /// Error while decoding a received block announce.
     #[display(fmt = "Error while decoding a received block announce: {_0}")]
-    BadBlockAnnounce(protocol::DecodeBlockAnnounceError),
+    BadBlockAnnounce(codec::DecodeBlockAnnounceError),
     /// Error while decoding a received Grandpa notification.
     #[display(fmt = "Error while decoding a received Grandpa notification: {_0}")]
-    BadGrandpaNotification(protocol::DecodeGrandpaNotificationError),
+    BadGrandpaNotification(codec::DecodeGrandpaNotificationError),
+    /// Error while decoding a received transactions notification.
+    #[display(fmt = "Error while decoding a received transactions notification: {_0}")]
+    BadTransactionsNotification(codec::DecodeTransactionsNotificationError),
     /// Received an invalid identify request.
     BadIdentifyRequest,
     /// Error while decoding a received blocks request.
     #[display(fmt = "Error while decoding a received blocks request: {_0}")]
-    BadBlocksRequest(protocol::DecodeBlockRequestError),
+    BadBlocksRequest(codec::DecodeBlockRequestError),
+    /// Remote has tried to open a notifications substream on the protocol of a chain while
+    /// substreams exist on the protocol of another chain with the same genesis hash but a
+    /// different fork ID.
+    ForkIdMismatch,
 }
 
 /// Error potentially returned when starting a request.
@@ -3693,13 +7786,23 @@ pub enum ProtocolError {
 pub enum StartRequestError {
     /// There is no valid connection to the given peer on which the request can be started.
     NoConnection,
+    /// The peer has recently refused to negotiate the protocol of the request on all the
+    /// connections with it. See [`Config::refused_protocols_cooldown`].
+    ProtocolNotAvailable,
 }
 
+/// Maximum size, in bytes, of a request on the light client protocol. Matches the limit
+/// enforced by Substrate nodes, which refuse requests above this size.
+pub const LIGHT_PROTOCOL_MAX_REQUEST_SIZE: usize = 1024 * 1024;
+
 /// Error potentially returned when starting a request that might be too large.
 #[derive(Debug, Clone, derive_more::Display)]
 pub enum StartRequestMaybeTooLargeError {
     /// There is no valid connection to the given peer on which the request can be started.
     NoConnection,
+    /// The peer has recently refused to negotiate the protocol of the request on all the
+    /// connections with it. See [`Config::refused_protocols_cooldown`].
+    ProtocolNotAvailable,
     /// Size of the request is over maximum allowed by the protocol.
     RequestTooLarge,
 }
@@ -3708,6 +7811,9 @@ impl From<StartRequestError> for StartRequestMaybeTooLargeError {
     fn from(err: StartRequestError) -> StartRequestMaybeTooLargeError {
         match err {
             StartRequestError::NoConnection => StartRequestMaybeTooLargeError::NoConnection,
+            StartRequestError::ProtocolNotAvailable => {
+                StartRequestMaybeTooLargeError::ProtocolNotAvailable
+            }
         }
     }
 }
@@ -3717,12 +7823,79 @@ impl From<StartRequestError> for StartRequestMaybeTooLargeError {
 /// See [`Event::RequestResult`̀].
 #[derive(Debug)]
 pub enum RequestResult {
-    Blocks(Result<Vec<protocol::BlockData>, BlocksRequestError>),
+    Blocks(Result<Vec<codec::BlockData>, BlocksRequestError>),
     GrandpaWarpSync(Result<EncodedGrandpaWarpSyncResponse, GrandpaWarpSyncRequestError>),
     State(Result<EncodedStateResponse, StateRequestError>),
     StorageProof(Result<EncodedMerkleProof, StorageProofRequestError>),
     CallProof(Result<EncodedMerkleProof, CallProofRequestError>),
     KademliaFindNode(Result<Vec<(peer_id::PeerId, Vec<Vec<u8>>)>, KademliaFindNodeError>),
+    /// Response to a request started with [`ChainNetwork::start_identify_request`].
+    Identify(Result<IdentifyInfo, IdentifyRequestError>),
+    /// Response to a request started with [`ChainNetwork::start_custom_request`]. Not verified
+    /// in any way.
+    Custom(Result<Vec<u8>, RequestError>),
+}
+
+/// Information about a peer, obtained through [`ChainNetwork::start_identify_request`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IdentifyInfo {
+    /// Name of the set of protocols supported by the peer.
+    pub protocol_version: String,
+    /// Name and version of the software of the peer. Similar to `User-Agent` in the HTTP
+    /// protocol.
+    pub agent_version: String,
+    /// List of addresses the peer is listening on. Addresses that couldn't be decoded or that
+    /// have been refused by [`multiaddr::sanitize`] are silently skipped.
+    pub listen_addrs: Vec<Multiaddr>,
+    /// Address of the local node, as observed by the peer. `None` if the peer didn't provide it
+    /// or if it couldn't be decoded.
+    pub observed_addr: Option<Multiaddr>,
+    /// Names of the protocols supported by the peer.
+    pub protocols: Vec<String>,
+}
+
+/// Error returned by [`ChainNetwork::start_identify_request`].
+#[derive(Debug, derive_more::Display)]
+pub enum IdentifyRequestError {
+    /// Error while waiting for the response from the peer.
+    #[display(fmt = "{_0}")]
+    Request(RequestError),
+    /// Error while decoding the response returned by the peer.
+    #[display(fmt = "Response decoding error: {_0}")]
+    Decode(codec::DecodeIdentifyResponseError),
+    /// The public key found in the response doesn't match the identity of the peer.
+    PublicKeyMismatch,
+}
+
+/// Decodes the response to an identify request sent to the given peer.
+fn decode_identify_info(
+    peer_id: &PeerId,
+    payload: &[u8],
+    address_sanitize_policy: &multiaddr::SanitizePolicy,
+) -> Result<IdentifyInfo, IdentifyRequestError> {
+    let response =
+        codec::decode_identify_response(payload).map_err(IdentifyRequestError::Decode)?;
+
+    if PeerId::from_public_key(&peer_id::PublicKey::Ed25519(response.ed25519_public_key))
+        != *peer_id
+    {
+        return Err(IdentifyRequestError::PublicKeyMismatch);
+    }
+
+    Ok(IdentifyInfo {
+        protocol_version: response.protocol_version.to_owned(),
+        agent_version: response.agent_version.to_owned(),
+        listen_addrs: response
+            .decoded_listen_addrs()
+            .filter(|addr| multiaddr::sanitize(addr, peer_id, address_sanitize_policy).is_ok())
+            .collect(),
+        observed_addr: if response.observed_addr.is_empty() {
+            None
+        } else {
+            Multiaddr::try_from(response.observed_addr.to_vec()).ok()
+        },
+        protocols: response.protocols.map(|p| p.to_owned()).collect(),
+    })
 }
 
 /// Error returned by [`ChainNetwork::start_blocks_request`].
@@ -3733,7 +7906,7 @@ pub enum BlocksRequestError {
     Request(RequestError),
     /// Error while decoding the response returned by the peer.
     #[display(fmt = "Response decoding error: {_0}")]
-    Decode(protocol::DecodeBlockResponseError),
+    Decode(codec::DecodeBlockResponseError),
 }
 
 /// Error returned by [`ChainNetwork::start_storage_proof_request`].
@@ -3742,7 +7915,7 @@ pub enum StorageProofRequestError {
     #[display(fmt = "{_0}")]
     Request(RequestError),
     #[display(fmt = "Response decoding error: {_0}")]
-    Decode(protocol::DecodeStorageCallProofResponseError),
+    Decode(codec::DecodeStorageCallProofResponseError),
     /// The remote is incapable of answering this specific request.
     RemoteCouldntAnswer,
 }
@@ -3753,7 +7926,7 @@ pub enum CallProofRequestError {
     #[display(fmt = "{_0}")]
     Request(RequestError),
     #[display(fmt = "Response decoding error: {_0}")]
-    Decode(protocol::DecodeStorageCallProofResponseError),
+    Decode(codec::DecodeStorageCallProofResponseError),
     /// The remote is incapable of answering this specific request.
     RemoteCouldntAnswer,
 }
@@ -3776,7 +7949,7 @@ pub enum GrandpaWarpSyncRequestError {
     #[display(fmt = "{_0}")]
     Request(RequestError),
     #[display(fmt = "Response decoding error: {_0}")]
-    Decode(protocol::DecodeGrandpaWarpSyncResponseError),
+    Decode(codec::DecodeGrandpaWarpSyncResponseError),
 }
 
 /// Error returned by [`ChainNetwork::start_state_request`].
@@ -3785,7 +7958,7 @@ pub enum StateRequestError {
     #[display(fmt = "{_0}")]
     Request(RequestError),
     #[display(fmt = "Response decoding error: {_0}")]
-    Decode(protocol::DecodeStateResponseError),
+    Decode(codec::DecodeStateResponseError),
 }
 
 /// Error during [`ChainNetwork::start_kademlia_find_node_request`].
@@ -3796,7 +7969,7 @@ pub enum KademliaFindNodeError {
     RequestFailed(RequestError),
     /// Failed to decode the response.
     #[display(fmt = "Response decoding error: {_0}")]
-    DecodeError(protocol::DecodeFindNodeResponseError),
+    DecodeError(codec::DecodeFindNodeResponseError),
 }
 
 /// Error potentially returned when queueing a notification.
@@ -3817,8 +7990,8 @@ pub struct EncodedBlockAnnounce {
 
 impl EncodedBlockAnnounce {
     /// Returns the decoded version of the announcement.
-    pub fn decode(&self) -> protocol::BlockAnnounceRef {
-        protocol::decode_block_announce(&self.message, self.block_number_bytes).unwrap()
+    pub fn decode(&self) -> codec::BlockAnnounceRef {
+        codec::decode_block_announce(&self.message, self.block_number_bytes).unwrap()
     }
 }
 
@@ -3830,12 +8003,12 @@ impl fmt::Debug for EncodedBlockAnnounce {
 
 /// Undecoded but valid Merkle proof.
 #[derive(Clone)]
-pub struct EncodedMerkleProof(Vec<u8>, protocol::StorageOrCallProof);
+pub struct EncodedMerkleProof(Vec<u8>, codec::StorageOrCallProof);
 
 impl EncodedMerkleProof {
     /// Returns the SCALE-encoded Merkle proof.
     pub fn decode(&self) -> &[u8] {
-        protocol::decode_storage_or_call_proof_response(self.1, &self.0)
+        codec::decode_storage_or_call_proof_response(self.1, &self.0)
             .unwrap()
             .unwrap()
     }
@@ -3861,8 +8034,8 @@ impl EncodedGrandpaWarpSyncResponse {
     }
 
     /// Returns the decoded version of the warp sync message.
-    pub fn decode(&self) -> protocol::GrandpaWarpSyncResponse {
-        match protocol::decode_grandpa_warp_sync_response(&self.message, self.block_number_bytes) {
+    pub fn decode(&self) -> codec::GrandpaWarpSyncResponse {
+        match codec::decode_grandpa_warp_sync_response(&self.message, self.block_number_bytes) {
             Ok(msg) => msg,
             _ => unreachable!(),
         }
@@ -3883,7 +8056,7 @@ pub struct EncodedStateResponse(Vec<u8>);
 impl EncodedStateResponse {
     /// Returns the Merkle proof of the state response.
     pub fn decode(&self) -> &[u8] {
-        match protocol::decode_state_response(&self.0) {
+        match codec::decode_state_response(&self.0) {
             Ok(r) => r,
             Err(_) => unreachable!(),
         }
@@ -3915,9 +8088,8 @@ pub struct EncodedBlockAnnounceHandshake {
 
 impl EncodedBlockAnnounceHandshake {
     /// Returns the decoded version of the handshake.
-    pub fn decode(&self) -> protocol::BlockAnnouncesHandshakeRef {
-        protocol::decode_block_announces_handshake(self.block_number_bytes, &self.handshake)
-            .unwrap()
+    pub fn decode(&self) -> codec::BlockAnnouncesHandshakeRef {
+        codec::decode_block_announces_handshake(self.block_number_bytes, &self.handshake).unwrap()
     }
 }
 
@@ -3967,9 +8139,9 @@ impl EncodedGrandpaCommitMessage {
     }
 
     /// Returns the decoded version of the commit message.
-    pub fn decode(&self) -> protocol::CommitMessageRef {
-        match protocol::decode_grandpa_notification(&self.message, self.block_number_bytes) {
-            Ok(protocol::GrandpaNotificationRef::Commit(msg)) => msg,
+    pub fn decode(&self) -> codec::CommitMessageRef {
+        match codec::decode_grandpa_notification(&self.message, self.block_number_bytes) {
+            Ok(codec::GrandpaNotificationRef::Commit(msg)) => msg,
             _ => unreachable!(),
         }
     }
@@ -3980,3 +8152,61 @@ impl fmt::Debug for EncodedGrandpaCommitMessage {
         fmt::Debug::fmt(&self.decode(), f)
     }
 }
+
+/// Undecoded but valid GrandPa vote message.
+#[derive(Clone)]
+pub struct EncodedGrandpaVoteMessage {
+    message: Vec<u8>,
+    block_number_bytes: usize,
+}
+
+impl EncodedGrandpaVoteMessage {
+    /// Returns the encoded bytes of the vote message.
+    pub fn as_encoded(&self) -> &[u8] {
+        // Skip the first byte because `self.message` is a `GrandpaNotificationRef`.
+        &self.message[1..]
+    }
+
+    /// Returns the decoded version of the vote message.
+    pub fn decode(&self) -> codec::VoteMessageRef {
+        match codec::decode_grandpa_notification(&self.message, self.block_number_bytes) {
+            Ok(codec::GrandpaNotificationRef::Vote(msg)) => msg,
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl fmt::Debug for EncodedGrandpaVoteMessage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&self.decode(), f)
+    }
+}
+
+/// Undecoded but valid GrandPa catch-up message.
+#[derive(Clone)]
+pub struct EncodedGrandpaCatchUp {
+    message: Vec<u8>,
+    block_number_bytes: usize,
+}
+
+impl EncodedGrandpaCatchUp {
+    /// Returns the encoded bytes of the catch-up message.
+    pub fn as_encoded(&self) -> &[u8] {
+        // Skip the first byte because `self.message` is a `GrandpaNotificationRef`.
+        &self.message[1..]
+    }
+
+    /// Returns the decoded version of the catch-up message.
+    pub fn decode(&self) -> codec::CatchUpRef {
+        match codec::decode_grandpa_notification(&self.message, self.block_number_bytes) {
+            Ok(codec::GrandpaNotificationRef::CatchUp(msg)) => msg,
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl fmt::Debug for EncodedGrandpaCatchUp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&self.decode(), f)
+    }
+}
@@ -0,0 +1,2288 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+#![cfg(test)]
+
+use super::{
+    codec, collection, custom_protocol_full_name, decode_identify_info, gossip_open_backoff,
+    header, multiaddr, peer_id, AddChainError, AddCustomProtocolError, BlockHashOrNumber,
+    ChainConfig, ChainId, ChainNetwork, Config, ConnectionDirection, ConnectionId,
+    CustomNotificationsProtocolConfig, CustomRequestResponseProtocolConfig, DisconnectReason,
+    Event, GossipAssignOutSlotError, GossipCloseError, GossipConnectError, GossipDesiredStatus,
+    GossipInRejectsStats, GossipKind, GossipOpenError, GossipOpenRetryConfig, GossipRejectInError,
+    GossipRejectReason, GossipSlotsConfig, GrandpaState, IdentifyRequestError,
+    InboundRequestsProtocol, Multiaddr, NoiseKey, NotificationsProtocol,
+    NotificationsSubstreamState, PeerId, Protocol, ReputationBanConfig, Role,
+    SingleStreamConnectionTask, SingleStreamHandshakeKind, StartRequestError,
+    StartRequestMaybeTooLargeError,
+};
+use crate::libp2p::connection::established;
+use core::{
+    mem,
+    num::{NonZeroU32, NonZeroUsize},
+    time::Duration,
+};
+
+fn test_config() -> Config {
+    Config {
+        connections_capacity: 0,
+        chains_capacity: 1,
+        randomness_seed: [0; 32],
+        deterministic_ordering: false,
+        noise_key: NoiseKey::new(&[0; 32], &[0; 32]),
+        handshake_timeout: Duration::from_secs(5),
+        gossip_open_retry: None,
+        reputation_ban: None,
+        refused_protocols_cooldown: None,
+        ping_interval: Duration::from_secs(20),
+        ping_timeout: Duration::from_secs(10),
+        max_ping_failures: NonZeroU32::new(1).unwrap(),
+        max_connections_per_peer: None,
+        max_inbound_substreams: None,
+        max_queued_notifications_bytes: 1024 * 1024,
+        address_sanitize_policy: Default::default(),
+    }
+}
+
+fn test_chain_config() -> ChainConfig {
+    ChainConfig {
+        genesis_hash: [0; 32],
+        fork_id: None,
+        block_number_bytes: 4,
+        grandpa_protocol_config: None,
+        allow_inbound_block_requests: false,
+        custom_request_response_protocols: Vec::new(),
+        custom_notifications_protocols: Vec::new(),
+        best_hash: [0; 32],
+        best_number: 0,
+        role: Role::Light,
+        gossip_slots: None,
+        notifications_open_timeout: Duration::from_secs(10),
+        max_notifications_handshake_size: 1024 * 1024,
+        max_notification_size: 1024 * 1024,
+    }
+}
+
+/// A [`ChainNetwork`] connected to a raw [`collection::Network`]. The latter makes it
+/// possible to simulate remotes whose behaviour differs from the one of [`ChainNetwork`].
+struct NetworkAndRemote {
+    now: Duration,
+    network: ChainNetwork<Duration>,
+    network_connection_id: ConnectionId,
+    remote: collection::Network<(), Duration>,
+    remote_connection_id: collection::ConnectionId,
+    connections: Vec<ConnectionPair>,
+    /// If `Some`, the remote accepts the gossip substreams of this chain that the network
+    /// opens towards it.
+    remote_gossip_chain: Option<ChainConfig>,
+    /// Handshakes to send back on the inbound gossip substreams of the remote that have been
+    /// negotiated but not opened yet.
+    remote_gossip_handshakes: Vec<(collection::SubstreamId, Vec<u8>)>,
+}
+
+/// One connection between the [`ChainNetwork`] and the remote of a [`NetworkAndRemote`].
+///
+/// Setting one of the tasks to `None` simulates a side that no longer answers. When a task
+/// finishes by itself, the socket is instead considered as closed by the other side.
+struct ConnectionPair {
+    network_connection_id: ConnectionId,
+    network_task: Option<SingleStreamConnectionTask<Duration>>,
+    network_finished: bool,
+    network_to_remote: Vec<u8>,
+    remote_connection_id: collection::ConnectionId,
+    remote_task: Option<collection::SingleStreamConnectionTask<Duration>>,
+    remote_finished: bool,
+    remote_to_network: Vec<u8>,
+}
+
+impl NetworkAndRemote {
+    /// Creates a new network with one chain, connects it to a remote, and waits for the
+    /// handshake to finish. Returns the [`PeerId`] of the remote.
+    fn new(network: ChainNetwork<Duration>) -> (Self, PeerId) {
+        let remote = collection::Network::new(collection::Config {
+            randomness_seed: [1; 32],
+            capacity: 1,
+            max_inbound_substreams: 16,
+            handshake_timeout: Duration::from_secs(5),
+            ping_protocol: "/ipfs/ping/1.0.0".to_owned(),
+            ping_interval: Duration::from_secs(20),
+            ping_timeout: Duration::from_secs(10),
+            max_queued_notifications_bytes: 1024 * 1024,
+            substreams_lifecycle_events: false,
+        });
+
+        let mut connection = NetworkAndRemote {
+            now: Duration::new(0, 0),
+            network,
+            network_connection_id: ConnectionId::min_value(),
+            remote,
+            remote_connection_id: collection::ConnectionId::min_value(),
+            connections: Vec::new(),
+            remote_gossip_chain: None,
+            remote_gossip_handshakes: Vec::new(),
+        };
+
+        let (network_connection_id, remote_connection_id) = connection.add_connection(true);
+        connection.network_connection_id = network_connection_id;
+        connection.remote_connection_id = remote_connection_id;
+
+        let mut remote_peer_id = None;
+        while let Some(event) = connection.run_until_event() {
+            match event {
+                either::Left(Event::HandshakeFinished { peer_id, .. }) => {
+                    remote_peer_id = Some(peer_id)
+                }
+                either::Right(collection::Event::HandshakeFinished { .. }) => {}
+                ev => panic!("{ev:?}"),
+            }
+        }
+
+        (connection, remote_peer_id.unwrap())
+    }
+
+    /// Opens an additional connection between the network and the same remote. The handshake
+    /// is performed by [`NetworkAndRemote::run_until_event`].
+    fn add_connection(
+        &mut self,
+        network_is_initiator: bool,
+    ) -> (ConnectionId, collection::ConnectionId) {
+        let (network_connection_id, network_task) = self.network.add_single_stream_connection(
+            self.now,
+            SingleStreamHandshakeKind::MultistreamSelectNoiseYamux {
+                is_initiator: network_is_initiator,
+            },
+            Vec::new(),
+            None,
+        );
+
+        let (remote_connection_id, remote_task) = self.remote.insert_single_stream(
+            self.now,
+            collection::SingleStreamHandshakeKind::MultistreamSelectNoiseYamux {
+                is_initiator: !network_is_initiator,
+                noise_key: &NoiseKey::new(&[1; 32], &[1; 32]),
+            },
+            16,
+            256,
+            (),
+        );
+
+        self.connections.push(ConnectionPair {
+            network_connection_id,
+            network_task: Some(network_task),
+            network_finished: false,
+            network_to_remote: Vec::new(),
+            remote_connection_id,
+            remote_task: Some(remote_task),
+            remote_finished: false,
+            remote_to_network: Vec::new(),
+        });
+
+        (network_connection_id, remote_connection_id)
+    }
+
+    /// Transfers data and messages between the network and the remote until either of them
+    /// generates an event. Returns `None` if nothing more happens.
+    ///
+    /// Inbound ping substreams are automatically accepted by the remote. Inbound gossip
+    /// substreams are accepted if [`NetworkAndRemote::remote_gossip_chain`] is `Some`. The
+    /// other inbound substreams are rejected. Cancellations of accepted inbound substreams are
+    /// ignored.
+    fn run_until_event(&mut self) -> Option<either::Either<Event, collection::Event<()>>> {
+        loop {
+            let mut progress = false;
+
+            while let Some((id, messages)) = self.network.pull_messages_to_connection(16) {
+                let pair = self
+                    .connections
+                    .iter_mut()
+                    .find(|pair| pair.network_connection_id == id)
+                    .unwrap();
+                for message in messages {
+                    if let Some(task) = pair.network_task.as_mut() {
+                        task.inject_coordinator_message(&self.now, message);
+                    }
+                }
+                progress = true;
+            }
+            while let Some((id, message)) = self.remote.pull_message_to_connection() {
+                let pair = self
+                    .connections
+                    .iter_mut()
+                    .find(|pair| pair.remote_connection_id == id)
+                    .unwrap();
+                if let Some(task) = pair.remote_task.as_mut() {
+                    task.inject_coordinator_message(&self.now, message);
+                }
+                progress = true;
+            }
+
+            for pair in &mut self.connections {
+                if let Some(task) = pair.network_task.as_mut() {
+                    progress |= Self::read_write(
+                        &self.now,
+                        |rw| task.read_write(rw),
+                        pair.remote_finished,
+                        &mut pair.remote_to_network,
+                        &mut pair.network_to_remote,
+                    );
+                }
+                if let Some(task) = pair.remote_task.as_mut() {
+                    progress |= Self::read_write(
+                        &self.now,
+                        |rw| task.read_write(rw),
+                        pair.network_finished,
+                        &mut pair.network_to_remote,
+                        &mut pair.remote_to_network,
+                    );
+                }
+
+                while let Some(task) = pair.network_task.take() {
+                    let (task, message) = task.pull_message_to_coordinator();
+                    pair.network_finished = task.is_none();
+                    pair.network_task = task;
+                    let Some(message) = message else { break };
+                    self.network
+                        .inject_connection_message(pair.network_connection_id, message);
+                    progress = true;
+                }
+                while let Some(task) = pair.remote_task.take() {
+                    let (task, message) = task.pull_message_to_coordinator();
+                    pair.remote_finished = task.is_none();
+                    pair.remote_task = task;
+                    let Some(message) = message else { break };
+                    self.remote
+                        .inject_connection_message(pair.remote_connection_id, message);
+                    progress = true;
+                }
+            }
+
+            if let Some(event) = self.network.next_event(&self.now) {
+                return Some(either::Left(event));
+            }
+
+            match self.remote.next_event() {
+                Some(collection::Event::InboundNegotiated {
+                    substream_id,
+                    protocol_name,
+                    ..
+                }) if protocol_name == "/ipfs/ping/1.0.0" => {
+                    self.remote
+                        .accept_inbound(substream_id, collection::InboundTy::Ping);
+                    continue;
+                }
+                Some(collection::Event::InboundNegotiated {
+                    substream_id,
+                    protocol_name,
+                    ..
+                }) => {
+                    let handshake = match (
+                        &self.remote_gossip_chain,
+                        codec::decode_protocol_name(&protocol_name),
+                    ) {
+                        (
+                            Some(chain_config),
+                            Ok(
+                                protocol @ (codec::ProtocolName::BlockAnnounces { .. }
+                                | codec::ProtocolName::Transactions { .. }
+                                | codec::ProtocolName::Grandpa { .. }),
+                            ),
+                        ) => gossip_handshake(chain_config, protocol),
+                        _ => {
+                            self.remote.reject_inbound(substream_id);
+                            continue;
+                        }
+                    };
+                    self.remote.accept_inbound(
+                        substream_id,
+                        collection::InboundTy::Notifications {
+                            max_handshake_size: 1024,
+                        },
+                    );
+                    self.remote_gossip_handshakes
+                        .push((substream_id, handshake));
+                    continue;
+                }
+                Some(collection::Event::NotificationsInOpen { substream_id, .. })
+                    if self
+                        .remote_gossip_handshakes
+                        .iter()
+                        .any(|(id, _)| *id == substream_id) =>
+                {
+                    let index = self
+                        .remote_gossip_handshakes
+                        .iter()
+                        .position(|(id, _)| *id == substream_id)
+                        .unwrap();
+                    let (_, handshake) = self.remote_gossip_handshakes.remove(index);
+                    self.remote
+                        .accept_in_notifications(substream_id, handshake, 1024 * 1024);
+                    continue;
+                }
+                Some(collection::Event::PingOutSuccess { .. })
+                | Some(collection::Event::InboundAcceptedCancel { .. }) => continue,
+                Some(event) => return Some(either::Right(event)),
+                None => {}
+            }
+
+            if !progress {
+                return None;
+            }
+        }
+    }
+
+    /// Calls `read_write` with the given incoming and outgoing buffers. Returns `true` if
+    /// any data has been read or written.
+    ///
+    /// If `socket_closed` is `true`, both the reading and writing sides of the socket are
+    /// reported as closed.
+    fn read_write(
+        now: &Duration,
+        read_write: impl FnOnce(&mut collection::ReadWrite<Duration>),
+        socket_closed: bool,
+        incoming: &mut Vec<u8>,
+        outgoing: &mut Vec<u8>,
+    ) -> bool {
+        let mut rw = collection::ReadWrite {
+            now: *now,
+            incoming_buffer: mem::take(incoming),
+            expected_incoming_bytes: if socket_closed { None } else { Some(0) },
+            read_bytes: 0,
+            write_buffers: Vec::new(),
+            write_bytes_queued: 0,
+            write_bytes_queueable: if socket_closed {
+                None
+            } else {
+                Some(1024 * 1024)
+            },
+            wake_up_after: None,
+        };
+        read_write(&mut rw);
+        *incoming = rw.incoming_buffer;
+        let mut written = false;
+        for buffer in rw.write_buffers {
+            written |= !buffer.is_empty();
+            outgoing.extend_from_slice(&buffer);
+        }
+        rw.read_bytes != 0 || written || rw.wake_up_after.map_or(false, |when| when <= *now)
+    }
+
+    /// Opens a notifications substream from the remote towards the network for the given
+    /// protocol of the given chain, similar to what other implementations do.
+    fn remote_open_gossip(
+        &mut self,
+        chain_config: &ChainConfig,
+        protocol: codec::ProtocolName,
+    ) -> collection::SubstreamId {
+        let handshake = gossip_handshake(chain_config, protocol);
+        self.remote.open_out_notifications(
+            self.remote_connection_id,
+            codec::encode_protocol_name_string(protocol),
+            Duration::from_secs(10),
+            handshake,
+            1024 * 1024,
+        )
+    }
+}
+
+impl NetworkAndRemote {
+    /// Opens the block announces, transactions, and, if configured, GrandPa substreams from
+    /// the remote towards the network, and accepts them on the side of the network with
+    /// [`ChainNetwork::gossip_open`]. Returns the substreams in this order.
+    fn remote_open_gossip_link(
+        &mut self,
+        chain_id: ChainId,
+        chain_config: &ChainConfig,
+    ) -> Vec<collection::SubstreamId> {
+        let genesis_hash = chain_config.genesis_hash;
+        let mut protocols = vec![
+            codec::ProtocolName::BlockAnnounces {
+                genesis_hash,
+                fork_id: None,
+            },
+            codec::ProtocolName::Transactions {
+                genesis_hash,
+                fork_id: None,
+            },
+        ];
+        if chain_config.grandpa_protocol_config.is_some() {
+            protocols.push(codec::ProtocolName::Grandpa {
+                genesis_hash,
+                fork_id: None,
+            });
+        }
+
+        let substreams = protocols
+            .into_iter()
+            .map(|protocol| self.remote_open_gossip(chain_config, protocol))
+            .collect::<Vec<_>>();
+
+        let mut num_open = 0;
+        while let Some(event) = self.run_until_event() {
+            match event {
+                either::Left(Event::GossipInDesired { peer_id, .. }) => {
+                    self.network
+                        .gossip_open(chain_id, &peer_id, GossipKind::ConsensusTransactions)
+                        .unwrap();
+                }
+                either::Right(collection::Event::NotificationsOutResult {
+                    substream_id,
+                    result: Ok(_),
+                }) if substreams.contains(&substream_id) => num_open += 1,
+                ev => panic!("{ev:?}"),
+            }
+        }
+        assert_eq!(num_open, substreams.len());
+
+        substreams
+    }
+}
+
+/// Returns the handshake that a full node of the given chain sends on a notifications
+/// substream of the given protocol.
+fn gossip_handshake(chain_config: &ChainConfig, protocol: codec::ProtocolName) -> Vec<u8> {
+    match protocol {
+        codec::ProtocolName::BlockAnnounces { .. } => codec::encode_block_announces_handshake(
+            codec::BlockAnnouncesHandshakeRef {
+                best_hash: &chain_config.best_hash,
+                best_number: chain_config.best_number,
+                role: Role::Full,
+                genesis_hash: &chain_config.genesis_hash,
+            },
+            chain_config.block_number_bytes,
+        )
+        .fold(Vec::new(), |mut a, b| {
+            a.extend_from_slice(b.as_ref());
+            a
+        }),
+        codec::ProtocolName::Grandpa { .. } => Role::Full.scale_encoding().to_vec(),
+        _ => Vec::new(),
+    }
+}
+
+impl NetworkAndRemote {
+    /// Sends an identify request from the remote on the given connection, answers it with
+    /// [`ChainNetwork::respond_identify`], and returns the encoded response.
+    fn request_identify(&mut self, remote_connection_id: collection::ConnectionId) -> Vec<u8> {
+        let request_id = self.remote.start_request(
+            remote_connection_id,
+            codec::encode_protocol_name_string(codec::ProtocolName::Identify),
+            None,
+            Duration::from_secs(10),
+            1024 * 1024,
+        );
+
+        let mut response = None;
+        while let Some(event) = self.run_until_event() {
+            match event {
+                either::Left(Event::IdentifyRequestIn { substream_id, .. }) => {
+                    let now = self.now;
+                    self.network.respond_identify(&now, substream_id, "test");
+                }
+                either::Right(collection::Event::Response {
+                    substream_id,
+                    response: Ok(bytes),
+                }) if substream_id == request_id => {
+                    response = Some(bytes);
+                }
+                ev => panic!("{ev:?}"),
+            }
+        }
+
+        response.unwrap()
+    }
+}
+
+fn unconnected_desired_order(randomness_seed: [u8; 32]) -> Vec<PeerId> {
+    let mut network = ChainNetwork::<Duration>::new(Config {
+        randomness_seed,
+        deterministic_ordering: true,
+        ..test_config()
+    });
+
+    let chain_id = network.add_chain(test_chain_config()).unwrap();
+
+    for n in 0..64u8 {
+        let peer_id = PeerId::from_public_key(&peer_id::PublicKey::Ed25519([n; 32]));
+        network.gossip_insert_desired(chain_id, peer_id, GossipKind::ConsensusTransactions);
+    }
+
+    network.unconnected_desired().cloned().collect()
+}
+
+#[test]
+fn deterministic_ordering_independent_of_seed() {
+    assert_eq!(
+        unconnected_desired_order([1; 32]),
+        unconnected_desired_order([2; 32])
+    );
+}
+
+#[test]
+fn gossip_desired_status_unconnected() {
+    let mut network = ChainNetwork::<Duration>::new(test_config());
+
+    let chain_id = network.add_chain(test_chain_config()).unwrap();
+
+    for n in 0..3u8 {
+        let peer_id = PeerId::from_public_key(&peer_id::PublicKey::Ed25519([n; 32]));
+        network.gossip_insert_desired(chain_id, peer_id, GossipKind::ConsensusTransactions);
+    }
+
+    assert_eq!(
+        network.gossip_desired_status(chain_id, GossipKind::ConsensusTransactions),
+        GossipDesiredStatus {
+            unconnected: 3,
+            connected_unopened: 0,
+            opening: 0,
+            open: 0,
+        }
+    );
+
+    let peer_id = PeerId::from_public_key(&peer_id::PublicKey::Ed25519([0; 32]));
+    assert!(network.gossip_remove_desired(chain_id, &peer_id, GossipKind::ConsensusTransactions));
+    assert_eq!(
+        network
+            .gossip_desired_status(chain_id, GossipKind::ConsensusTransactions)
+            .unconnected,
+        2
+    );
+}
+
+#[test]
+fn disconnect_peer_shuts_down_connections() {
+    let mut network = ChainNetwork::<Duration>::new(test_config());
+
+    let chain_id = network.add_chain(test_chain_config()).unwrap();
+
+    let peer_id = PeerId::from_public_key(&peer_id::PublicKey::Ed25519([0; 32]));
+    network.gossip_insert_desired(chain_id, peer_id.clone(), GossipKind::ConsensusTransactions);
+    assert_eq!(network.disconnect_peer(&peer_id), 0);
+
+    let mut connections = Vec::new();
+    for _ in 0..2 {
+        let (connection_id, _task) = network.add_single_stream_connection(
+            Duration::new(0, 0),
+            SingleStreamHandshakeKind::MultistreamSelectNoiseYamux { is_initiator: true },
+            Vec::new(),
+            Some(peer_id.clone()),
+        );
+        connections.push(connection_id);
+    }
+    assert_eq!(network.unconnected_desired().count(), 0);
+
+    network.start_shutdown(connections[0]);
+    assert_eq!(network.unconnected_desired().count(), 0);
+
+    assert_eq!(network.disconnect_peer(&peer_id), 1);
+    assert_eq!(
+        network.unconnected_desired().cloned().collect::<Vec<_>>(),
+        vec![peer_id.clone()]
+    );
+    assert_eq!(network.disconnect_peer(&peer_id), 0);
+
+    for connection_id in connections {
+        assert!(matches!(
+            network.inner[connection_id].shutdown_reason,
+            Some(DisconnectReason::LocallyRequested)
+        ));
+    }
+}
+
+#[test]
+fn disconnect_reason_handshake_timeout() {
+    let mut network = ChainNetwork::<Duration>::new(test_config());
+    network.add_chain(test_chain_config()).unwrap();
+    let (mut connection, _) = NetworkAndRemote::new(network);
+
+    // The remote side of the new connection never answers the handshake.
+    let (connection_id, _) = connection.add_connection(true);
+    connection.connections.last_mut().unwrap().remote_task = None;
+    connection.now += Duration::from_secs(6);
+
+    let mut disconnected = false;
+    while let Some(event) = connection.run_until_event() {
+        match event {
+            either::Left(Event::PreHandshakeDisconnected { id, reason, .. }) => {
+                assert_eq!(id, connection_id);
+                assert!(matches!(reason, DisconnectReason::HandshakeTimeout(_)));
+                assert!(!reason.is_protocol_error());
+                disconnected = true;
+            }
+            ev => panic!("{ev:?}"),
+        }
+    }
+    assert!(disconnected);
+}
+
+#[test]
+fn disconnect_reason_remote_shutdown() {
+    let mut network = ChainNetwork::<Duration>::new(test_config());
+    network.add_chain(test_chain_config()).unwrap();
+    let (mut connection, remote_peer_id) = NetworkAndRemote::new(network);
+
+    connection
+        .remote
+        .start_shutdown(connection.remote_connection_id);
+
+    let mut disconnected = false;
+    while let Some(event) = connection.run_until_event() {
+        match event {
+            either::Left(Event::Disconnected {
+                id,
+                peer_id,
+                reason,
+                ..
+            }) => {
+                assert_eq!(id, connection.network_connection_id);
+                assert_eq!(peer_id, remote_peer_id);
+                assert!(matches!(reason, DisconnectReason::CleanShutdown));
+                disconnected = true;
+            }
+            either::Right(collection::Event::Shutdown { .. }) => {}
+            ev => panic!("{ev:?}"),
+        }
+    }
+    assert!(disconnected);
+}
+
+#[test]
+fn reputation_ban_and_expiration() {
+    let mut network = ChainNetwork::<Duration>::new(Config {
+        reputation_ban: Some(ReputationBanConfig {
+            threshold: -50,
+            duration: Duration::from_secs(10),
+        }),
+        ..test_config()
+    });
+
+    let chain_id = network.add_chain(test_chain_config()).unwrap();
+
+    let peer_id = PeerId::from_public_key(&peer_id::PublicKey::Ed25519([0; 32]));
+    network.gossip_insert_desired(chain_id, peer_id.clone(), GossipKind::ConsensusTransactions);
+
+    network.report_peer(&Duration::from_secs(1), &peer_id, -30);
+    assert_eq!(network.peer_reputation(&peer_id), -30);
+    assert!(network.peer_banned_until(&peer_id).is_none());
+    assert_eq!(network.unconnected_desired().count(), 1);
+
+    network.report_peer(&Duration::from_secs(2), &peer_id, -30);
+    assert_eq!(
+        network.peer_banned_until(&peer_id),
+        Some(&Duration::from_secs(12))
+    );
+    assert_eq!(
+        network.next_ban_expiration(),
+        Some(&Duration::from_secs(12))
+    );
+    assert_eq!(network.unconnected_desired().count(), 0);
+
+    assert!(network.next_event(&Duration::from_secs(11)).is_none());
+    assert!(network.peer_banned_until(&peer_id).is_some());
+
+    assert!(network.next_event(&Duration::from_secs(12)).is_none());
+    assert!(network.peer_banned_until(&peer_id).is_none());
+    assert_eq!(network.peer_reputation(&peer_id), 0);
+    assert_eq!(network.next_ban_expiration(), None);
+    assert_eq!(network.unconnected_desired().count(), 1);
+}
+
+#[test]
+fn reputation_ban_threshold_and_multiple_expirations() {
+    let mut network = ChainNetwork::<Duration>::new(Config {
+        reputation_ban: Some(ReputationBanConfig {
+            threshold: -50,
+            duration: Duration::from_secs(10),
+        }),
+        ..test_config()
+    });
+
+    let peer1 = PeerId::from_public_key(&peer_id::PublicKey::Ed25519([1; 32]));
+    let peer2 = PeerId::from_public_key(&peer_id::PublicKey::Ed25519([2; 32]));
+
+    // Reaching the threshold exactly is enough to be banned.
+    network.report_peer(&Duration::from_secs(5), &peer1, -50);
+    assert_eq!(
+        network.peer_banned_until(&peer1),
+        Some(&Duration::from_secs(15))
+    );
+
+    // Further reports don't extend the ban.
+    network.report_peer(&Duration::from_secs(6), &peer1, -50);
+    assert_eq!(
+        network.peer_banned_until(&peer1),
+        Some(&Duration::from_secs(15))
+    );
+
+    network.report_peer(&Duration::from_secs(1), &peer2, -80);
+    assert_eq!(
+        network.next_ban_expiration(),
+        Some(&Duration::from_secs(11))
+    );
+
+    // Only the expired ban is lifted.
+    assert!(network.next_event(&Duration::from_secs(11)).is_none());
+    assert!(network.peer_banned_until(&peer2).is_none());
+    assert_eq!(network.peer_reputation(&peer2), 0);
+    assert!(network.peer_banned_until(&peer1).is_some());
+    assert_eq!(
+        network.next_ban_expiration(),
+        Some(&Duration::from_secs(15))
+    );
+
+    assert!(network.next_event(&Duration::from_secs(20)).is_none());
+    assert!(network.peer_banned_until(&peer1).is_none());
+    assert_eq!(network.next_ban_expiration(), None);
+}
+
+#[test]
+fn reputation_without_ban_config() {
+    let mut network = ChainNetwork::<Duration>::new(test_config());
+
+    let peer_id = PeerId::from_public_key(&peer_id::PublicKey::Ed25519([0; 32]));
+    network.report_peer(&Duration::from_secs(1), &peer_id, -1000);
+    assert_eq!(network.peer_reputation(&peer_id), -1000);
+    assert!(network.peer_banned_until(&peer_id).is_none());
+    assert_eq!(network.next_ban_expiration(), None);
+
+    // Peers whose reputation goes back to 0 are forgotten.
+    network.report_peer(&Duration::from_secs(2), &peer_id, 1000);
+    assert_eq!(network.peer_reputation(&peer_id), 0);
+}
+
+/// Builds a network with one chain using GrandPa, connects it to a remote, and makes the
+/// remote open a block announces substream followed with a transactions substream and a
+/// GrandPa substream before the block announces substream is accepted or rejected.
+///
+/// Returns the substreams of the remote, the block announces substream first.
+fn buffered_gossip_substreams_setup() -> (
+    NetworkAndRemote,
+    ChainId,
+    PeerId,
+    Vec<collection::SubstreamId>,
+) {
+    let chain_config = || ChainConfig {
+        grandpa_protocol_config: Some(GrandpaState {
+            round_number: 1,
+            set_id: 0,
+            commit_finalized_height: 0,
+        }),
+        ..test_chain_config()
+    };
+
+    let mut network = ChainNetwork::<Duration>::new(test_config());
+    let chain_id = network.add_chain(chain_config()).unwrap();
+    let (mut connection, remote_peer_id) = NetworkAndRemote::new(network);
+
+    let genesis_hash = chain_config().genesis_hash;
+    let substreams = [
+        codec::ProtocolName::BlockAnnounces {
+            genesis_hash,
+            fork_id: None,
+        },
+        codec::ProtocolName::Transactions {
+            genesis_hash,
+            fork_id: None,
+        },
+        codec::ProtocolName::Grandpa {
+            genesis_hash,
+            fork_id: None,
+        },
+    ]
+    .into_iter()
+    .map(|protocol| connection.remote_open_gossip(&chain_config(), protocol))
+    .collect::<Vec<_>>();
+
+    // A second transactions substream is refused, as only one substream per protocol is
+    // allowed.
+    let duplicate = connection.remote_open_gossip(
+        &chain_config(),
+        codec::ProtocolName::Transactions {
+            genesis_hash,
+            fork_id: None,
+        },
+    );
+
+    let mut gossip_in_desired = false;
+    let mut duplicate_refused = false;
+    while let Some(event) = connection.run_until_event() {
+        match event {
+            either::Left(Event::GossipInDesired { peer_id, .. }) => {
+                assert_eq!(peer_id, remote_peer_id);
+                gossip_in_desired = true;
+            }
+            either::Right(collection::Event::NotificationsOutResult {
+                substream_id,
+                result: Err(_),
+            }) if substream_id == duplicate => {
+                duplicate_refused = true;
+            }
+            ev => panic!("{ev:?}"),
+        }
+    }
+    assert!(gossip_in_desired && duplicate_refused);
+    let link = &connection.network.gossip_links[&(chain_id.0, remote_peer_id.clone())];
+    assert!(link.out_substreams.is_empty());
+    for protocol in [
+        NotificationsProtocol::BlockAnnounces {
+            chain_index: chain_id.0,
+        },
+        NotificationsProtocol::Transactions {
+            chain_index: chain_id.0,
+        },
+        NotificationsProtocol::Grandpa {
+            chain_index: chain_id.0,
+        },
+    ] {
+        assert!(matches!(
+            link.in_substreams.get(protocol),
+            Some((NotificationsSubstreamState::Pending, _))
+        ));
+    }
+
+    (connection, chain_id, remote_peer_id, substreams)
+}
+
+/// Runs the given connection until nothing more happens, and returns the outcome of the
+/// opening of each of the given substreams of the remote.
+fn remote_substreams_results(
+    connection: &mut NetworkAndRemote,
+    substreams: &[collection::SubstreamId],
+) -> Vec<Option<bool>> {
+    let mut results = vec![None; substreams.len()];
+    while let Some(event) = connection.run_until_event() {
+        if let either::Right(collection::Event::NotificationsOutResult {
+            substream_id,
+            result,
+        }) = event
+        {
+            if let Some(index) = substreams.iter().position(|s| *s == substream_id) {
+                results[index] = Some(result.is_ok());
+            }
+        }
+    }
+    results
+}
+
+#[test]
+fn buffered_gossip_substreams_accepted() {
+    let (mut connection, chain_id, remote_peer_id, substreams) = buffered_gossip_substreams_setup();
+
+    connection
+        .network
+        .gossip_open(chain_id, &remote_peer_id, GossipKind::ConsensusTransactions)
+        .unwrap();
+
+    // The pending block announces substream is accepted alongside with the buffered
+    // substreams. The remote refuses the outbound block announces substream of the network,
+    // which doesn't matter here.
+    assert_eq!(
+        remote_substreams_results(&mut connection, &substreams),
+        vec![Some(true); 3]
+    );
+}
+
+#[test]
+fn gossip_link_removed_once_remote_closes_inbound_substreams() {
+    let (mut connection, chain_id, remote_peer_id, substreams) = buffered_gossip_substreams_setup();
+
+    connection
+        .network
+        .gossip_open(chain_id, &remote_peer_id, GossipKind::ConsensusTransactions)
+        .unwrap();
+    assert_eq!(
+        remote_substreams_results(&mut connection, &substreams),
+        vec![Some(true); 3]
+    );
+
+    // The remote has refused the outbound block announces substream of the network, but the
+    // gossip link remains as long as the inbound substreams are open.
+    let link = &connection.network.gossip_links[&(chain_id.0, remote_peer_id.clone())];
+    assert!(link.out_substreams.is_empty());
+    assert!(link.best_block.is_none());
+    for protocol in [
+        NotificationsProtocol::BlockAnnounces {
+            chain_index: chain_id.0,
+        },
+        NotificationsProtocol::Transactions {
+            chain_index: chain_id.0,
+        },
+        NotificationsProtocol::Grandpa {
+            chain_index: chain_id.0,
+        },
+    ] {
+        assert!(matches!(
+            link.in_substreams.get(protocol),
+            Some((NotificationsSubstreamState::Open, _))
+        ));
+    }
+
+    for substream_id in &substreams {
+        connection.remote.close_out_notifications(*substream_id);
+    }
+    while connection.run_until_event().is_some() {}
+
+    assert!(connection.network.gossip_links.is_empty());
+    assert!(connection
+        .network
+        .gossip_link_info(chain_id, &remote_peer_id, GossipKind::ConsensusTransactions)
+        .is_none());
+    assert!(connection.network.substreams.values().all(|s| !matches!(
+        s.protocol,
+        Protocol::BlockAnnounces { .. } | Protocol::Transactions { .. } | Protocol::Grandpa { .. }
+    )));
+}
+
+#[test]
+fn buffered_gossip_substreams_rejected() {
+    let (mut connection, chain_id, remote_peer_id, substreams) = buffered_gossip_substreams_setup();
+
+    connection
+        .network
+        .gossip_reject_in(
+            chain_id,
+            &remote_peer_id,
+            GossipKind::ConsensusTransactions,
+            GossipRejectReason::Other,
+        )
+        .unwrap();
+
+    assert_eq!(
+        remote_substreams_results(&mut connection, &substreams),
+        vec![Some(false); 3]
+    );
+    assert!(connection.network.gossip_links.is_empty());
+}
+
+#[test]
+fn buffered_gossip_substreams_block_announces_cancelled() {
+    let (mut connection, chain_id, remote_peer_id, substreams) = buffered_gossip_substreams_setup();
+
+    connection.remote.close_out_notifications(substreams[0]);
+
+    match connection.run_until_event() {
+        Some(either::Left(Event::GossipInDesiredCancel {
+            peer_id,
+            chain_id: cancelled_chain_id,
+            ..
+        })) => {
+            assert_eq!(peer_id, remote_peer_id);
+            assert_eq!(cancelled_chain_id, chain_id);
+        }
+        ev => panic!("{ev:?}"),
+    }
+
+    assert_eq!(
+        remote_substreams_results(&mut connection, &substreams[1..]),
+        vec![Some(false); 2]
+    );
+    assert!(connection.network.gossip_links.is_empty());
+    assert!(connection.network.substreams.values().all(|s| !matches!(
+        s.protocol,
+        Protocol::BlockAnnounces { .. } | Protocol::Transactions { .. } | Protocol::Grandpa { .. }
+    )));
+}
+
+#[test]
+fn custom_request_response_protocols() {
+    let mut network = ChainNetwork::<Duration>::new(Config {
+        chains_capacity: 2,
+        ..test_config()
+    });
+
+    let chain_config = |name: &str| ChainConfig {
+        custom_request_response_protocols: vec![CustomRequestResponseProtocolConfig {
+            name: name.to_owned(),
+            max_request_size: 16,
+            max_response_size: 1024,
+            allow_inbound_requests: true,
+            stream_inbound_requests: false,
+        }],
+        ..test_chain_config()
+    };
+
+    // Conflicts with the name of a standard protocol.
+    assert!(matches!(
+        network.add_chain(chain_config("sync/2")),
+        Err(AddChainError::CustomProtocolConflict { protocol_index: 0 })
+    ));
+
+    let chain_id = network.add_chain(chain_config("das/1")).unwrap();
+    let peer_id = PeerId::from_public_key(&peer_id::PublicKey::Ed25519([1; 32]));
+
+    assert!(matches!(
+        network.start_custom_request(&peer_id, chain_id, 0, vec![0; 17], Duration::from_secs(5)),
+        Err(StartRequestMaybeTooLargeError::RequestTooLarge)
+    ));
+    assert!(matches!(
+        network.start_custom_request(&peer_id, chain_id, 0, vec![0; 16], Duration::from_secs(5)),
+        Err(StartRequestMaybeTooLargeError::NoConnection)
+    ));
+
+    // Protocols added later are appended to the list.
+    let new_protocol = |name: &str| CustomRequestResponseProtocolConfig {
+        name: name.to_owned(),
+        max_request_size: 32,
+        max_response_size: 1024,
+        allow_inbound_requests: false,
+        stream_inbound_requests: false,
+    };
+    assert!(matches!(
+        network.add_custom_request_response_protocol(chain_id, new_protocol("das/1")),
+        Err(AddCustomProtocolError::Conflict)
+    ));
+    assert!(matches!(
+        network.add_custom_request_response_protocol(chain_id, new_protocol("sync/2")),
+        Err(AddCustomProtocolError::Conflict)
+    ));
+    assert_eq!(
+        network
+            .add_custom_request_response_protocol(chain_id, new_protocol("das/2"))
+            .unwrap(),
+        1
+    );
+    assert!(matches!(
+        network.start_custom_request(&peer_id, chain_id, 1, vec![0; 32], Duration::from_secs(5)),
+        Err(StartRequestMaybeTooLargeError::NoConnection)
+    ));
+}
+
+#[test]
+fn custom_request_streamed_inbound() {
+    let mut network = ChainNetwork::<Duration>::new(test_config());
+    let chain_id = network
+        .add_chain(ChainConfig {
+            custom_request_response_protocols: vec![CustomRequestResponseProtocolConfig {
+                name: "das/1".to_owned(),
+                max_request_size: 128 * 1024,
+                max_response_size: 1024,
+                allow_inbound_requests: true,
+                stream_inbound_requests: true,
+            }],
+            ..test_chain_config()
+        })
+        .unwrap();
+    let (mut connection, remote_peer_id) = NetworkAndRemote::new(network);
+
+    // The request is large enough to be reported in multiple chunks.
+    let request = (0..100 * 1024).map(|n| n as u8).collect::<Vec<_>>();
+    let request_id = connection.remote.start_request(
+        connection.remote_connection_id,
+        custom_protocol_full_name(&[0; 32], None, "das/1"),
+        Some(request.clone()),
+        Duration::from_secs(10),
+        1024,
+    );
+
+    let mut substream_id = None;
+    let mut received = Vec::new();
+    let mut num_chunks = 0;
+    let mut response_received = false;
+    while let Some(event) = connection.run_until_event() {
+        match event {
+            either::Left(Event::CustomRequestInStart {
+                peer_id,
+                chain_id: request_chain_id,
+                protocol_index: 0,
+                request_size,
+                substream_id: id,
+            }) => {
+                assert_eq!(peer_id, remote_peer_id);
+                assert_eq!(request_chain_id, chain_id);
+                assert_eq!(request_size, request.len());
+                assert!(substream_id.is_none());
+                substream_id = Some(id);
+            }
+            either::Left(Event::CustomRequestInChunk {
+                substream_id: id,
+                chunk,
+                is_last,
+            }) => {
+                assert_eq!(Some(id), substream_id);
+                received.extend_from_slice(&chunk);
+                num_chunks += 1;
+                if is_last {
+                    assert_eq!(received, request);
+                    let now = connection.now;
+                    connection
+                        .network
+                        .respond_custom(&now, id, Some(b"hello".to_vec()));
+                }
+            }
+            either::Right(collection::Event::Response { substream_id, .. })
+                if substream_id == request_id =>
+            {
+                response_received = true;
+            }
+            ev => panic!("{ev:?}"),
+        }
+    }
+
+    assert!(num_chunks > 1);
+    assert!(response_received);
+    let stats = connection
+        .network
+        .inbound_requests_stats(InboundRequestsProtocol::Custom {
+            chain_id,
+            protocol_index: 0,
+        });
+    assert_eq!(stats.received, 1);
+    assert_eq!(stats.served, 1);
+}
+
+#[test]
+fn custom_notifications_protocols() {
+    let mut network = ChainNetwork::<Duration>::new(Config {
+        chains_capacity: 2,
+        ..test_config()
+    });
+
+    let chain_config = |name: &str| ChainConfig {
+        custom_request_response_protocols: vec![CustomRequestResponseProtocolConfig {
+            name: "statement/1".to_owned(),
+            max_request_size: 16,
+            max_response_size: 1024,
+            allow_inbound_requests: true,
+            stream_inbound_requests: false,
+        }],
+        custom_notifications_protocols: vec![CustomNotificationsProtocolConfig {
+            name: name.to_owned(),
+            max_handshake_size: 16,
+            max_notification_size: 1024,
+            allow_inbound_substreams: true,
+        }],
+        ..test_chain_config()
+    };
+
+    // Conflicts with the name of the custom request-response protocol.
+    assert!(matches!(
+        network.add_chain(chain_config("statement/1")),
+        Err(AddChainError::CustomNotificationsProtocolConflict { protocol_index: 0 })
+    ));
+
+    let chain_id = network
+        .add_chain(chain_config("statement/gossip/1"))
+        .unwrap();
+    let peer_id = PeerId::from_public_key(&peer_id::PublicKey::Ed25519([1; 32]));
+
+    assert!(matches!(
+        network.custom_notifications_open(
+            &peer_id,
+            chain_id,
+            0,
+            Vec::new(),
+            Duration::from_secs(5)
+        ),
+        Err(StartRequestError::NoConnection)
+    ));
+}
+
+#[test]
+fn protocol_refusals_expire() {
+    let mut network = ChainNetwork::<Duration>::new(Config {
+        refused_protocols_cooldown: Some(Duration::from_secs(10)),
+        ..test_config()
+    });
+
+    let connection_id = ConnectionId::min_value();
+    network.insert_protocol_refusal(connection_id, "a".to_owned(), Duration::from_secs(10));
+    network.insert_protocol_refusal(connection_id, "b".to_owned(), Duration::from_secs(5));
+    network.insert_protocol_refusal(connection_id, "a".to_owned(), Duration::from_secs(12));
+    assert_eq!(network.refused_protocols.len(), 2);
+    assert_eq!(network.refused_protocols_expiration.len(), 2);
+
+    assert!(network.next_event(&Duration::from_secs(6)).is_none());
+    assert!(!network
+        .refused_protocols
+        .contains_key(&(connection_id, "b".to_owned())));
+    assert!(network
+        .refused_protocols
+        .contains_key(&(connection_id, "a".to_owned())));
+
+    network.remove_protocol_refusals(connection_id);
+    assert!(network.refused_protocols.is_empty());
+    assert!(network.refused_protocols_expiration.is_empty());
+}
+
+#[test]
+fn gossip_reject_in_without_pending_request() {
+    let mut network = ChainNetwork::<Duration>::new(test_config());
+
+    let chain_id = network.add_chain(test_chain_config()).unwrap();
+
+    let peer_id = PeerId::from_public_key(&peer_id::PublicKey::Ed25519([0; 32]));
+    assert!(matches!(
+        network.gossip_reject_in(
+            chain_id,
+            &peer_id,
+            GossipKind::ConsensusTransactions,
+            GossipRejectReason::SlotsFull
+        ),
+        Err(GossipRejectInError::NoPendingRequest)
+    ));
+    assert_eq!(network.gossip_in_rejects_stats(chain_id).total(), 0);
+
+    let mut stats = GossipInRejectsStats::default();
+    stats.record(GossipRejectReason::SlotsFull);
+    stats.record(GossipRejectReason::SlotsFull);
+    stats.record(GossipRejectReason::Banned);
+    assert_eq!(stats.slots_full, 2);
+    assert_eq!(stats.banned, 1);
+    assert_eq!(stats.total(), 3);
+}
+
+#[test]
+fn gossip_open_close_without_connection() {
+    let mut network = ChainNetwork::<Duration>::new(test_config());
+
+    let chain_id = network.add_chain(test_chain_config()).unwrap();
+
+    let peer_id = PeerId::from_public_key(&peer_id::PublicKey::Ed25519([0; 32]));
+    assert_eq!(
+        network.gossip_open(chain_id, &peer_id, GossipKind::ConsensusTransactions),
+        Err(GossipOpenError::NoConnection)
+    );
+    assert!(network
+        .gossip_link_info(chain_id, &peer_id, GossipKind::ConsensusTransactions)
+        .is_none());
+    assert_eq!(
+        network.gossip_close(chain_id, &peer_id, GossipKind::ConsensusTransactions),
+        Err(GossipCloseError::NotOpen)
+    );
+}
+
+#[test]
+fn max_inbound_substreams_depends_on_chains() {
+    let new_network = |max_inbound_substreams| {
+        ChainNetwork::<Duration>::new(Config {
+            chains_capacity: 2,
+            max_inbound_substreams,
+            ..test_config()
+        })
+    };
+
+    let chain_config = |genesis_hash| ChainConfig {
+        genesis_hash,
+        ..test_chain_config()
+    };
+
+    let mut network = new_network(None);
+    let initial = network.max_inbound_substreams();
+    network.add_chain(chain_config([0; 32])).unwrap();
+    let one_chain = network.max_inbound_substreams();
+    network.add_chain(chain_config([1; 32])).unwrap();
+    let two_chains = network.max_inbound_substreams();
+    assert!(initial < one_chain);
+    assert!(one_chain < two_chains);
+
+    let mut network = new_network(Some(10));
+    assert_eq!(network.max_inbound_substreams(), 10);
+    network.add_chain(chain_config([0; 32])).unwrap();
+    network.add_chain(chain_config([1; 32])).unwrap();
+    assert_eq!(network.max_inbound_substreams(), 10);
+}
+
+#[test]
+fn announce_local_best_block_without_connection() {
+    let mut network = ChainNetwork::<Duration>::new(test_config());
+
+    let chain_id = network.add_chain(test_chain_config()).unwrap();
+
+    assert!(network
+        .set_chain_local_best_block_and_announce(chain_id, &[1, 2, 3])
+        .is_err());
+
+    assert!(matches!(
+        network.set_chain_local_best_block_and_announce(chain_id, &test_header(5)),
+        Ok(0)
+    ));
+}
+
+/// Returns a SCALE-encoded header with the given number, which must be inferior to 64.
+fn test_header(number: u8) -> Vec<u8> {
+    // Parent hash, number (compact-encoded), state root, extrinsics root, empty digest.
+    let mut header = vec![0; 32];
+    header.push(number << 2);
+    header.extend_from_slice(&[0; 64]);
+    header.push(0);
+    header
+}
+
+/// Returns an encoded block announce of [`test_header`].
+fn test_block_announce(number: u8, is_best: bool) -> Vec<u8> {
+    codec::encode_block_announce(codec::BlockAnnounceRef {
+        scale_encoded_header: &test_header(number),
+        is_best,
+    })
+    .fold(Vec::new(), |mut a, b| {
+        a.extend_from_slice(b.as_ref());
+        a
+    })
+}
+
+/// Connects a network to a remote that accepts the gossip substreams, and opens a gossip
+/// link towards it. The notifications that the remote receives while the link is opening,
+/// such as GrandPa neighbor packets, are ignored.
+fn open_gossip_link_setup(
+    config: Config,
+    chain_config: impl Fn() -> ChainConfig,
+) -> (NetworkAndRemote, ChainId, PeerId) {
+    let mut network = ChainNetwork::<Duration>::new(config);
+    let chain_id = network.add_chain(chain_config()).unwrap();
+    let (mut connection, remote_peer_id) = NetworkAndRemote::new(network);
+    connection.remote_gossip_chain = Some(chain_config());
+
+    connection
+        .network
+        .gossip_open(chain_id, &remote_peer_id, GossipKind::ConsensusTransactions)
+        .unwrap();
+
+    let mut connected = false;
+    while let Some(event) = connection.run_until_event() {
+        match event {
+            either::Left(Event::GossipConnected { peer_id, .. }) => {
+                assert_eq!(peer_id, remote_peer_id);
+                connected = true;
+            }
+            either::Right(collection::Event::NotificationsIn { .. }) => {}
+            ev => panic!("{ev:?}"),
+        }
+    }
+    assert!(connected);
+
+    (connection, chain_id, remote_peer_id)
+}
+
+#[test]
+fn announce_local_best_block_received_by_remote() {
+    let (mut connection, chain_id, _) = open_gossip_link_setup(test_config(), test_chain_config);
+
+    assert!(matches!(
+        connection
+            .network
+            .set_chain_local_best_block_and_announce(chain_id, &test_header(5)),
+        Ok(1)
+    ));
+
+    let mut announces = Vec::new();
+    while let Some(event) = connection.run_until_event() {
+        match event {
+            either::Right(collection::Event::NotificationsIn { notification, .. }) => {
+                announces.push(notification)
+            }
+            ev => panic!("{ev:?}"),
+        }
+    }
+
+    assert_eq!(announces.len(), 1);
+    let announce = codec::decode_block_announce(&announces[0], 4).unwrap();
+    assert!(announce.is_best);
+    assert_eq!(announce.scale_encoded_header, &test_header(5)[..]);
+}
+
+#[test]
+fn gossip_link_info_counts_announces() {
+    let (mut connection, chain_id, remote_peer_id) =
+        open_gossip_link_setup(test_config(), test_chain_config);
+    let substreams = connection.remote_open_gossip_link(chain_id, &test_chain_config());
+
+    let info = connection
+        .network
+        .gossip_link_info(chain_id, &remote_peer_id, GossipKind::ConsensusTransactions)
+        .unwrap()
+        .clone();
+    assert_eq!(info.open_attempts, 1);
+    assert_eq!(info.consecutive_open_failures, 0);
+    assert_eq!(info.announces_received, 0);
+    assert!(matches!(
+        info.connection_direction,
+        Some(ConnectionDirection::Outbound)
+    ));
+
+    // A valid announce and an announce whose header can't be decoded.
+    let announce = test_block_announce(5, true);
+    for notification in [announce.clone(), vec![1, 2, 3]] {
+        connection
+            .remote
+            .queue_notification(substreams[0], notification)
+            .unwrap();
+    }
+
+    let mut num_announces = 0;
+    while let Some(event) = connection.run_until_event() {
+        match event {
+            either::Left(Event::BlockAnnounce { peer_id, .. }) => {
+                assert_eq!(peer_id, remote_peer_id);
+                num_announces += 1;
+            }
+            either::Left(Event::ProtocolError { .. }) => {}
+            ev => panic!("{ev:?}"),
+        }
+    }
+    assert_eq!(num_announces, 1);
+
+    let info = connection
+        .network
+        .gossip_link_info(chain_id, &remote_peer_id, GossipKind::ConsensusTransactions)
+        .unwrap();
+    assert_eq!(info.announces_received, 1);
+    assert_eq!(
+        info.bytes_received,
+        u64::try_from(announce.len() + 3).unwrap()
+    );
+}
+
+#[test]
+fn peers_with_block_follows_announces() {
+    let (mut connection, chain_id, remote_peer_id) =
+        open_gossip_link_setup(test_config(), test_chain_config);
+    let substreams = connection.remote_open_gossip_link(chain_id, &test_chain_config());
+
+    let peers_with_block = |connection: &NetworkAndRemote, block| {
+        connection
+            .network
+            .peers_with_block(chain_id, block)
+            .cloned()
+            .collect::<Vec<_>>()
+    };
+
+    // Initially, the best block is the one of the handshake of the remote.
+    assert_eq!(
+        peers_with_block(&connection, BlockHashOrNumber::Number(0)),
+        vec![remote_peer_id.clone()]
+    );
+    assert!(peers_with_block(&connection, BlockHashOrNumber::Number(5)).is_empty());
+
+    // Only announces of new best blocks update the best block of the remote.
+    for announce in [test_block_announce(5, true), test_block_announce(7, false)] {
+        connection
+            .remote
+            .queue_notification(substreams[0], announce)
+            .unwrap();
+    }
+    let mut num_announces = 0;
+    while let Some(event) = connection.run_until_event() {
+        match event {
+            either::Left(Event::BlockAnnounce { .. }) => num_announces += 1,
+            ev => panic!("{ev:?}"),
+        }
+    }
+    assert_eq!(num_announces, 2);
+
+    let hash = header::hash_from_scale_encoded_header(test_header(5));
+    assert_eq!(
+        peers_with_block(&connection, BlockHashOrNumber::Number(5)),
+        vec![remote_peer_id.clone()]
+    );
+    assert_eq!(
+        peers_with_block(&connection, BlockHashOrNumber::Hash(hash)),
+        vec![remote_peer_id.clone()]
+    );
+    assert!(peers_with_block(&connection, BlockHashOrNumber::Number(6)).is_empty());
+    assert!(peers_with_block(&connection, BlockHashOrNumber::Hash([0; 32])).is_empty());
+    assert_eq!(
+        connection
+            .network
+            .gossip_peer_best_block(chain_id, &remote_peer_id),
+        Some((5, hash))
+    );
+}
+
+#[test]
+fn transactions_received_from_remote() {
+    let (mut connection, chain_id, remote_peer_id) =
+        open_gossip_link_setup(test_config(), test_chain_config);
+    let substreams = connection.remote_open_gossip_link(chain_id, &test_chain_config());
+
+    // A notification containing two transactions, followed with an invalid notification.
+    for notification in [
+        vec![2 << 2, 3 << 2, 1, 2, 3, 1 << 2, 4],
+        vec![1 << 2, 5 << 2],
+    ] {
+        connection
+            .remote
+            .queue_notification(substreams[1], notification)
+            .unwrap();
+    }
+
+    let mut transactions = None;
+    let mut protocol_error = false;
+    while let Some(event) = connection.run_until_event() {
+        match event {
+            either::Left(Event::TransactionsReceived {
+                peer_id,
+                chain_id: event_chain_id,
+                transactions: received,
+            }) => {
+                assert_eq!(peer_id, remote_peer_id);
+                assert_eq!(event_chain_id, chain_id);
+                assert!(transactions.is_none());
+                transactions = Some(received);
+            }
+            either::Left(Event::ProtocolError { peer_id, .. }) => {
+                assert_eq!(peer_id, remote_peer_id);
+                assert!(transactions.is_some());
+                protocol_error = true;
+            }
+            ev => panic!("{ev:?}"),
+        }
+    }
+    let transactions = transactions.unwrap();
+    assert_eq!(transactions, vec![vec![3 << 2, 1, 2, 3], vec![1 << 2, 4]]);
+    assert!(protocol_error);
+
+    // The transactions can be sent back as they are.
+    connection
+        .network
+        .gossip_send_transaction(&remote_peer_id, chain_id, &transactions[0])
+        .unwrap();
+    let mut notifications = Vec::new();
+    while let Some(event) = connection.run_until_event() {
+        match event {
+            either::Right(collection::Event::NotificationsIn { notification, .. }) => {
+                notifications.push(notification)
+            }
+            ev => panic!("{ev:?}"),
+        }
+    }
+    assert_eq!(notifications, vec![vec![1 << 2, 3 << 2, 1, 2, 3]]);
+}
+
+#[test]
+fn grandpa_votes_and_catch_up_exchanged_with_remote() {
+    let chain_config = || ChainConfig {
+        grandpa_protocol_config: Some(GrandpaState {
+            round_number: 3,
+            set_id: 1,
+            commit_finalized_height: 0,
+        }),
+        ..test_chain_config()
+    };
+    let (mut connection, chain_id, remote_peer_id) =
+        open_gossip_link_setup(test_config(), chain_config);
+    let substreams = connection.remote_open_gossip_link(chain_id, &chain_config());
+
+    let vote = codec::VoteMessageRef {
+        round_number: 3,
+        set_id: 1,
+        message: codec::MessageRef::Prevote(codec::UnsignedPrevoteRef {
+            target_hash: &[1; 32],
+            target_number: 5,
+        }),
+        signature: &[2; 64],
+        authority_public_key: &[3; 32],
+    };
+    let request = codec::CatchUpRequest {
+        round_number: 2,
+        set_id: 1,
+    };
+    let catch_up = codec::CatchUpRef {
+        set_id: 1,
+        round_number: 2,
+        prevotes: vec![codec::PrevoteRef {
+            target_hash: &[1; 32],
+            target_number: 5,
+            signature: &[2; 64],
+            authority_public_key: &[3; 32],
+        }],
+        precommits: Vec::new(),
+        base_hash: &[4; 32],
+        base_number: 4,
+    };
+
+    connection
+        .network
+        .gossip_send_grandpa_vote(&remote_peer_id, chain_id, vote.clone())
+        .unwrap();
+    connection
+        .network
+        .gossip_send_grandpa_catch_up_request(&remote_peer_id, chain_id, request.clone())
+        .unwrap();
+    connection
+        .network
+        .gossip_send_grandpa_catch_up(&remote_peer_id, chain_id, catch_up.clone())
+        .unwrap();
+
+    // The remote receives the messages, and sends them back.
+    let mut num_received = 0;
+    let mut num_events = 0;
+    while let Some(event) = connection.run_until_event() {
+        match event {
+            either::Right(collection::Event::NotificationsIn { notification, .. }) => {
+                num_received += 1;
+                connection
+                    .remote
+                    .queue_notification(substreams[2], notification)
+                    .unwrap();
+            }
+            either::Left(Event::GrandpaVoteMessage {
+                peer_id, message, ..
+            }) => {
+                assert_eq!(peer_id, remote_peer_id);
+                assert_eq!(message.decode(), vote);
+                num_events += 1;
+            }
+            either::Left(Event::GrandpaCatchUpRequest {
+                peer_id,
+                request: received,
+                ..
+            }) => {
+                assert_eq!(peer_id, remote_peer_id);
+                assert_eq!(received, request);
+                num_events += 1;
+            }
+            either::Left(Event::GrandpaCatchUp {
+                peer_id, message, ..
+            }) => {
+                assert_eq!(peer_id, remote_peer_id);
+                assert_eq!(message.decode(), catch_up);
+                num_events += 1;
+            }
+            ev => panic!("{ev:?}"),
+        }
+    }
+    assert_eq!(num_received, 3);
+    assert_eq!(num_events, 3);
+}
+
+#[test]
+fn gossip_open_follows_chain_timeout() {
+    let mut network = ChainNetwork::<Duration>::new(test_config());
+    let chain_id = network
+        .add_chain(ChainConfig {
+            notifications_open_timeout: Duration::from_secs(3),
+            ..test_chain_config()
+        })
+        .unwrap();
+    let (mut connection, remote_peer_id) = NetworkAndRemote::new(network);
+
+    // The remote no longer answers, so that the opening of the substream never finishes.
+    connection.connections[0].remote_task = None;
+    connection
+        .network
+        .gossip_open(chain_id, &remote_peer_id, GossipKind::ConsensusTransactions)
+        .unwrap();
+    assert!(connection.run_until_event().is_none());
+
+    connection.now += Duration::from_secs(2);
+    assert!(connection.run_until_event().is_none());
+
+    connection.now += Duration::from_secs(2);
+    let mut open_failed = false;
+    while let Some(event) = connection.run_until_event() {
+        match event {
+            either::Left(Event::GossipOpenFailed {
+                peer_id,
+                error:
+                    GossipConnectError::Substream(collection::NotificationsOutErr::Substream(
+                        established::NotificationsOutErr::Timeout,
+                    )),
+                ..
+            }) => {
+                assert_eq!(peer_id, remote_peer_id);
+                open_failed = true;
+            }
+            ev => panic!("{ev:?}"),
+        }
+    }
+    assert!(open_failed);
+}
+
+#[test]
+fn notifications_above_chain_max_size_refused() {
+    let chain_config = || ChainConfig {
+        max_notification_size: 64,
+        ..test_chain_config()
+    };
+    let (mut connection, chain_id, remote_peer_id) =
+        open_gossip_link_setup(test_config(), chain_config);
+    let substreams = connection.remote_open_gossip_link(chain_id, &chain_config());
+
+    // A notification below the limit followed with a notification above the limit.
+    for transaction_len in [40, 80] {
+        let mut notification = vec![1 << 2, transaction_len << 2];
+        notification.extend_from_slice(&vec![0; usize::from(transaction_len)]);
+        connection
+            .remote
+            .queue_notification(substreams[1], notification)
+            .unwrap();
+    }
+
+    let mut num_transactions = 0;
+    let mut close_demanded = false;
+    while let Some(event) = connection.run_until_event() {
+        match event {
+            either::Left(Event::TransactionsReceived {
+                peer_id,
+                transactions,
+                ..
+            }) => {
+                assert_eq!(peer_id, remote_peer_id);
+                assert_eq!(transactions[0].len(), 41);
+                num_transactions += 1;
+            }
+            either::Right(collection::Event::NotificationsOutCloseDemanded { substream_id }) => {
+                assert_eq!(substream_id, substreams[1]);
+                close_demanded = true;
+                connection.remote.close_out_notifications(substream_id);
+            }
+            ev => panic!("{ev:?}"),
+        }
+    }
+    assert_eq!(num_transactions, 1);
+    assert!(close_demanded);
+
+    // Only the inbound transactions substream has been closed.
+    let link = &connection.network.gossip_links[&(chain_id.0, remote_peer_id)];
+    assert!(link
+        .in_substreams
+        .get(NotificationsProtocol::Transactions {
+            chain_index: chain_id.0
+        })
+        .is_none());
+    assert!(link
+        .in_substreams
+        .get(NotificationsProtocol::BlockAnnounces {
+            chain_index: chain_id.0
+        })
+        .is_some());
+}
+
+#[test]
+fn metrics_count_notifications_and_identify() {
+    let (mut connection, chain_id, remote_peer_id) =
+        open_gossip_link_setup(test_config(), test_chain_config);
+    let substreams = connection.remote_open_gossip_link(chain_id, &test_chain_config());
+
+    connection
+        .network
+        .gossip_send_block_announce(&remote_peer_id, chain_id, &test_header(5), true)
+        .unwrap();
+    connection
+        .remote
+        .queue_notification(substreams[1], vec![1 << 2, 1 << 2, 7])
+        .unwrap();
+    while let Some(event) = connection.run_until_event() {
+        match event {
+            either::Left(Event::TransactionsReceived { .. })
+            | either::Right(collection::Event::NotificationsIn { .. }) => {}
+            ev => panic!("{ev:?}"),
+        }
+    }
+    let remote_connection_id = connection.remote_connection_id;
+    let identify_response = connection.request_identify(remote_connection_id);
+
+    let metrics = connection.network.metrics();
+    assert_eq!(metrics.connections_opened, 1);
+    assert_eq!(metrics.connections_closed, 0);
+
+    let identify = &metrics.protocols["/ipfs/id/1.0.0"];
+    assert_eq!(
+        identify.bytes_sent,
+        u64::try_from(identify_response.len()).unwrap()
+    );
+
+    let chain_metrics = &metrics.chains[&chain_id];
+    let protocol_metrics =
+        |protocol| chain_metrics[&codec::encode_protocol_name_string(protocol)].clone();
+    let block_announces = protocol_metrics(codec::ProtocolName::BlockAnnounces {
+        genesis_hash: [0; 32],
+        fork_id: None,
+    });
+    assert_eq!(block_announces.notifications_queued, 1);
+    assert_eq!(
+        block_announces.bytes_sent,
+        u64::try_from(test_block_announce(5, true).len()).unwrap()
+    );
+    assert_eq!(block_announces.notifications_received, 0);
+    let transactions = protocol_metrics(codec::ProtocolName::Transactions {
+        genesis_hash: [0; 32],
+        fork_id: None,
+    });
+    assert_eq!(transactions.notifications_received, 1);
+    assert_eq!(transactions.bytes_received, 3);
+    assert_eq!(transactions.notifications_queued, 0);
+}
+
+#[test]
+fn announce_local_best_block_skips_full_queue() {
+    let (mut connection, chain_id, remote_peer_id) = open_gossip_link_setup(
+        Config {
+            max_queued_notifications_bytes: 128,
+            ..test_config()
+        },
+        test_chain_config,
+    );
+
+    // The first announce isn't transferred to the connection and thus fills the queue.
+    connection
+        .network
+        .gossip_send_block_announce(&remote_peer_id, chain_id, &test_header(4), false)
+        .unwrap();
+
+    assert!(matches!(
+        connection
+            .network
+            .set_chain_local_best_block_and_announce(chain_id, &test_header(5)),
+        Ok(0)
+    ));
+
+    // The local best block is modified anyway.
+    assert_eq!(connection.network.chains[chain_id.0].best_number, 5);
+}
+
+#[test]
+fn gossip_slots_out_assignment() {
+    let mut network = ChainNetwork::<Duration>::new(test_config());
+
+    let peer = |n: u8| PeerId::from_public_key(&peer_id::PublicKey::Ed25519([n; 32]));
+
+    let chain_id = network
+        .add_chain(ChainConfig {
+            gossip_slots: Some(GossipSlotsConfig {
+                in_slots: 1,
+                out_slots: 1,
+                reserved_peers: vec![peer(1)],
+                reserved_only: false,
+            }),
+            ..test_chain_config()
+        })
+        .unwrap();
+
+    // Reserved peers are desired but don't occupy a slot.
+    assert!(network.unconnected_desired().any(|p| *p == peer(1)));
+    assert!(matches!(
+        network.gossip_assign_out_slot(chain_id, peer(1)),
+        Err(GossipAssignOutSlotError::AlreadyAssigned)
+    ));
+
+    network.gossip_assign_out_slot(chain_id, peer(2)).unwrap();
+    assert!(matches!(
+        network.gossip_assign_out_slot(chain_id, peer(3)),
+        Err(GossipAssignOutSlotError::SlotsFull)
+    ));
+
+    assert!(network.gossip_remove_desired(chain_id, &peer(2), GossipKind::ConsensusTransactions));
+    network.gossip_set_reserved_only(chain_id, true);
+    assert!(matches!(
+        network.gossip_assign_out_slot(chain_id, peer(3)),
+        Err(GossipAssignOutSlotError::ReservedOnly)
+    ));
+
+    assert!(network.gossip_add_reserved(chain_id, peer(3)));
+    assert!(!network.gossip_add_reserved(chain_id, peer(3)));
+    assert!(network.unconnected_desired().any(|p| *p == peer(3)));
+    assert!(network.gossip_remove_reserved(chain_id, &peer(3)));
+    assert!(!network.unconnected_desired().any(|p| *p == peer(3)));
+}
+
+#[test]
+fn gossip_slots_out_kept_when_reserved() {
+    let mut network = ChainNetwork::<Duration>::new(test_config());
+
+    let peer = |n: u8| PeerId::from_public_key(&peer_id::PublicKey::Ed25519([n; 32]));
+
+    let chain_id = network
+        .add_chain(ChainConfig {
+            gossip_slots: Some(GossipSlotsConfig {
+                in_slots: 1,
+                out_slots: 1,
+                reserved_peers: Vec::new(),
+                reserved_only: false,
+            }),
+            ..test_chain_config()
+        })
+        .unwrap();
+
+    network.gossip_assign_out_slot(chain_id, peer(1)).unwrap();
+
+    // The peer keeps its slot while it is reserved, and is still desired afterwards.
+    assert!(network.gossip_add_reserved(chain_id, peer(1)));
+    assert!(matches!(
+        network.gossip_assign_out_slot(chain_id, peer(2)),
+        Err(GossipAssignOutSlotError::SlotsFull)
+    ));
+    assert!(network.gossip_remove_reserved(chain_id, &peer(1)));
+    assert!(network.unconnected_desired().any(|p| *p == peer(1)));
+    assert!(matches!(
+        network.gossip_assign_out_slot(chain_id, peer(2)),
+        Err(GossipAssignOutSlotError::SlotsFull)
+    ));
+
+    // Removing the peer from the desired peers frees its slot, and removing it from the
+    // reserved peers no longer keeps it desired.
+    assert!(network.gossip_add_reserved(chain_id, peer(1)));
+    assert!(network.gossip_remove_desired(chain_id, &peer(1), GossipKind::ConsensusTransactions));
+    network.gossip_assign_out_slot(chain_id, peer(2)).unwrap();
+    assert!(network.gossip_insert_desired(chain_id, peer(1), GossipKind::ConsensusTransactions));
+    assert!(network.gossip_remove_reserved(chain_id, &peer(1)));
+    assert!(!network.unconnected_desired().any(|p| *p == peer(1)));
+}
+
+#[test]
+fn identify_response_decoding() {
+    let listen_addr = "/ip4/1.2.3.4/tcp/30333".parse::<Multiaddr>().unwrap();
+    let unspecified_addr = "/ip4/0.0.0.0/tcp/30333".parse::<Multiaddr>().unwrap();
+    let loopback_addr = "/ip4/127.0.0.1/tcp/30333".parse::<Multiaddr>().unwrap();
+    let encoded = codec::build_identify_response(codec::IdentifyResponse {
+        protocol_version: "/substrate/1.0",
+        agent_version: "smoldot",
+        ed25519_public_key: [5; 32],
+        listen_addrs: [
+            listen_addr.as_ref(),
+            unspecified_addr.as_ref(),
+            loopback_addr.as_ref(),
+        ]
+        .into_iter(),
+        observed_addr: &[],
+        protocols: ["/ipfs/id/1.0.0"].into_iter(),
+    })
+    .fold(Vec::new(), |mut a, b| {
+        a.extend_from_slice(b.as_ref());
+        a
+    });
+
+    let peer_id = PeerId::from_public_key(&peer_id::PublicKey::Ed25519([5; 32]));
+    let info = decode_identify_info(&peer_id, &encoded, &Default::default()).unwrap();
+    assert_eq!(info.agent_version, "smoldot");
+    assert_eq!(info.listen_addrs, vec![listen_addr.clone()]);
+    assert_eq!(info.observed_addr, None);
+    assert_eq!(info.protocols, vec!["/ipfs/id/1.0.0".to_owned()]);
+
+    let info = decode_identify_info(
+        &peer_id,
+        &encoded,
+        &multiaddr::SanitizePolicy {
+            allow_loopback: true,
+        },
+    )
+    .unwrap();
+    assert_eq!(info.listen_addrs, vec![listen_addr, loopback_addr]);
+
+    let other_peer_id = PeerId::from_public_key(&peer_id::PublicKey::Ed25519([6; 32]));
+    assert!(matches!(
+        decode_identify_info(&other_peer_id, &encoded, &Default::default()),
+        Err(IdentifyRequestError::PublicKeyMismatch)
+    ));
+}
+
+#[test]
+fn supported_protocols_follow_chains() {
+    let mut network = ChainNetwork::<Duration>::new(test_config());
+
+    let block_announces = codec::encode_protocol_name_string(codec::ProtocolName::BlockAnnounces {
+        genesis_hash: [0; 32],
+        fork_id: None,
+    });
+
+    let protocols = network.supported_protocols().collect::<Vec<_>>();
+    assert!(protocols.contains(&"/ipfs/ping/1.0.0".to_owned()));
+    assert!(!protocols.contains(&block_announces));
+
+    let _chain_id = network.add_chain(test_chain_config()).unwrap();
+
+    assert!(network
+        .supported_protocols()
+        .any(|protocol| protocol == block_announces));
+}
+
+#[test]
+fn supernumerary_connection_shut_down() {
+    let mut network = ChainNetwork::<Duration>::new(Config {
+        max_connections_per_peer: Some(NonZeroUsize::new(1).unwrap()),
+        ..test_config()
+    });
+    let _chain_id = network.add_chain(test_chain_config()).unwrap();
+    let (mut connection, remote_peer_id) = NetworkAndRemote::new(network);
+    let first_connection_id = connection.network_connection_id;
+
+    // The second connection has the same direction as the first one, and is thus the one
+    // shut down no matter the `PeerId`s.
+    let (second_connection_id, _) = connection.add_connection(true);
+
+    let mut supernumerary = false;
+    let mut disconnected = false;
+    while let Some(event) = connection.run_until_event() {
+        match event {
+            either::Left(Event::SupernumeraryConnection {
+                id,
+                direction: ConnectionDirection::Outbound,
+                peer_id,
+                ..
+            }) if id == second_connection_id => {
+                assert_eq!(peer_id, remote_peer_id);
+                supernumerary = true;
+            }
+            either::Left(Event::Disconnected { id, .. }) => {
+                assert_eq!(id, second_connection_id);
+                disconnected = true;
+            }
+            either::Right(
+                collection::Event::HandshakeFinished { .. }
+                | collection::Event::StartShutdown { .. }
+                | collection::Event::Shutdown { .. },
+            ) => {}
+            ev => panic!("{ev:?}"),
+        }
+    }
+
+    assert!(supernumerary);
+    assert!(disconnected);
+    let state = connection
+        .network
+        .inner
+        .connection_state(first_connection_id);
+    assert!(state.established && !state.shutting_down);
+}
+
+#[test]
+fn simultaneous_dial_keeps_connection_of_lowest_peer_id() {
+    // Try various local keys in order to cover both orderings of the `PeerId`s.
+    let mut tested_orderings = (false, false);
+    for key_byte in 0..8 {
+        let mut network = ChainNetwork::<Duration>::new(Config {
+            noise_key: NoiseKey::new(&[key_byte; 32], &[key_byte; 32]),
+            max_connections_per_peer: Some(NonZeroUsize::new(1).unwrap()),
+            ..test_config()
+        });
+        let _chain_id = network.add_chain(test_chain_config()).unwrap();
+        let (mut connection, remote_peer_id) = NetworkAndRemote::new(network);
+        let outbound_connection_id = connection.network_connection_id;
+
+        let local_peer_id = PeerId::from_public_key(&peer_id::PublicKey::Ed25519(
+            *connection.network.noise_key().libp2p_public_ed25519_key(),
+        ));
+        if local_peer_id == remote_peer_id {
+            continue;
+        }
+
+        // The remote dials the network while the connection dialed by the network is
+        // already established.
+        let (inbound_connection_id, _) = connection.add_connection(false);
+
+        let (kept, shut_down) = if local_peer_id < remote_peer_id {
+            tested_orderings.0 = true;
+            (outbound_connection_id, inbound_connection_id)
+        } else {
+            tested_orderings.1 = true;
+            (inbound_connection_id, outbound_connection_id)
+        };
+
+        let mut disconnected = false;
+        while let Some(event) = connection.run_until_event() {
+            match event {
+                either::Left(Event::SupernumeraryConnection { id, .. }) => {
+                    assert_eq!(id, inbound_connection_id);
+                    assert_eq!(shut_down, inbound_connection_id);
+                }
+                either::Left(Event::HandshakeFinished { id, .. }) => {
+                    assert_eq!(id, inbound_connection_id);
+                    assert_eq!(kept, inbound_connection_id);
+                }
+                either::Left(Event::Disconnected { id, .. }) => {
+                    assert_eq!(id, shut_down);
+                    disconnected = true;
+                }
+                either::Right(
+                    collection::Event::HandshakeFinished { .. }
+                    | collection::Event::StartShutdown { .. }
+                    | collection::Event::Shutdown { .. },
+                ) => {}
+                ev => panic!("{ev:?}"),
+            }
+        }
+
+        assert!(disconnected);
+        let state = connection.network.inner.connection_state(kept);
+        assert!(state.established && !state.shutting_down);
+    }
+
+    assert_eq!(tested_orderings, (true, true));
+}
+
+#[test]
+fn identify_response_follows_addresses_and_chains() {
+    fn request_identify(connection: &mut NetworkAndRemote) -> (Vec<Vec<u8>>, Vec<String>) {
+        let remote_connection_id = connection.remote_connection_id;
+        let response = connection.request_identify(remote_connection_id);
+        let decoded = codec::decode_identify_response(&response).unwrap();
+        assert_eq!(decoded.agent_version, "test");
+        (
+            decoded.listen_addrs.map(|a| a.to_vec()).collect(),
+            decoded.protocols.map(|p| p.to_owned()).collect(),
+        )
+    }
+
+    let address1 = "/ip4/1.2.3.4/tcp/30333"
+        .parse::<Multiaddr>()
+        .unwrap()
+        .to_vec();
+    let address2 = "/dns/example.com/tcp/443/wss"
+        .parse::<Multiaddr>()
+        .unwrap()
+        .to_vec();
+
+    let mut network = ChainNetwork::<Duration>::new(test_config());
+    let _chain_id = network.add_chain(test_chain_config()).unwrap();
+    assert!(network.add_local_address(address1.clone()));
+    assert!(network.add_local_address(address2.clone()));
+    let (mut connection, _) = NetworkAndRemote::new(network);
+
+    let (listen_addrs, protocols) = request_identify(&mut connection);
+    assert_eq!(listen_addrs, vec![address1.clone(), address2.clone()]);
+    assert_eq!(
+        protocols,
+        connection.network.supported_protocols().collect::<Vec<_>>()
+    );
+
+    assert!(connection.network.remove_local_address(&address1));
+    let _chain_id = connection
+        .network
+        .add_chain(ChainConfig {
+            genesis_hash: [1; 32],
+            ..test_chain_config()
+        })
+        .unwrap();
+
+    let block_announces = codec::encode_protocol_name_string(codec::ProtocolName::BlockAnnounces {
+        genesis_hash: [1; 32],
+        fork_id: None,
+    });
+    let (listen_addrs2, protocols2) = request_identify(&mut connection);
+    assert_eq!(listen_addrs2, vec![address2]);
+    assert!(!protocols.contains(&block_announces));
+    assert!(protocols2.contains(&block_announces));
+    assert_eq!(
+        protocols2,
+        connection.network.supported_protocols().collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn identify_response_after_noise_key_rotation() {
+    let old_key = NoiseKey::new(&[0; 32], &[0; 32]);
+    let new_key = NoiseKey::new(&[2; 32], &[2; 32]);
+
+    let mut network = ChainNetwork::<Duration>::new(test_config());
+    let _chain_id = network.add_chain(test_chain_config()).unwrap();
+    let (mut connection, _) = NetworkAndRemote::new(network);
+    let old_connection_id = connection.remote_connection_id;
+
+    connection
+        .network
+        .set_noise_key(NoiseKey::new(&[2; 32], &[2; 32]));
+    assert_eq!(
+        connection.network.noise_key().libp2p_public_ed25519_key(),
+        new_key.libp2p_public_ed25519_key()
+    );
+
+    // The connection that existed before the rotation keeps reporting the previous key.
+    let response = connection.request_identify(old_connection_id);
+    assert_eq!(
+        codec::decode_identify_response(&response)
+            .unwrap()
+            .ed25519_public_key,
+        *old_key.libp2p_public_ed25519_key()
+    );
+
+    // A connection added after the rotation reports the new key.
+    let (_, new_connection_id) = connection.add_connection(true);
+    while let Some(event) = connection.run_until_event() {
+        match event {
+            either::Left(Event::HandshakeFinished { .. })
+            | either::Right(collection::Event::HandshakeFinished { .. }) => {}
+            ev => panic!("{ev:?}"),
+        }
+    }
+    let response = connection.request_identify(new_connection_id);
+    assert_eq!(
+        codec::decode_identify_response(&response)
+            .unwrap()
+            .ed25519_public_key,
+        *new_key.libp2p_public_ed25519_key()
+    );
+
+    // Requesting again on the old connection still gives the previous key.
+    let response = connection.request_identify(old_connection_id);
+    assert_eq!(
+        codec::decode_identify_response(&response)
+            .unwrap()
+            .ed25519_public_key,
+        *old_key.libp2p_public_ed25519_key()
+    );
+}
+
+#[test]
+fn gossip_open_backoff_doubles_until_max() {
+    let config = GossipOpenRetryConfig {
+        initial_backoff: Duration::from_secs(2),
+        max_backoff: Duration::from_secs(30),
+        max_attempts: NonZeroU32::new(10).unwrap(),
+    };
+
+    assert_eq!(gossip_open_backoff(&config, 1), Duration::from_secs(2));
+    assert_eq!(gossip_open_backoff(&config, 2), Duration::from_secs(4));
+    assert_eq!(gossip_open_backoff(&config, 4), Duration::from_secs(16));
+    assert_eq!(gossip_open_backoff(&config, 5), Duration::from_secs(30));
+    assert_eq!(
+        gossip_open_backoff(&config, u32::MAX),
+        Duration::from_secs(30)
+    );
+}
+
+#[test]
+fn kademlia_find_node_response_signed_envelopes() {
+    let network = ChainNetwork::<Duration>::new(test_config());
+
+    let requester = PeerId::from_public_key(&peer_id::PublicKey::Ed25519([1; 32]));
+    let peer_with_envelope = PeerId::from_public_key(&peer_id::PublicKey::Ed25519([2; 32]));
+    let peer_without_envelope = PeerId::from_public_key(&peer_id::PublicKey::Ed25519([3; 32]));
+    let addrs = ["/ip4/1.2.3.4/tcp/30333".parse::<Multiaddr>().unwrap()];
+    let envelope = [0xde, 0xad, 0xbe, 0xef];
+
+    let request = codec::build_find_node_request(requester.as_bytes());
+    let response = network
+        .build_kademlia_find_node_response(
+            &requester,
+            &request,
+            [
+                (&requester, &addrs[..], Some(&envelope[..])),
+                (&peer_with_envelope, &addrs[..], Some(&envelope[..])),
+                (&peer_without_envelope, &addrs[..], None),
+            ]
+            .into_iter(),
+        )
+        .unwrap();
+
+    // The requester is left out, so the envelope is found exactly once in the response.
+    assert_eq!(
+        response
+            .windows(envelope.len())
+            .filter(|w| *w == envelope)
+            .count(),
+        1
+    );
+
+    let mut decoded = codec::decode_find_node_response(&response)
+        .unwrap()
+        .into_iter()
+        .map(|(peer_id, _)| peer_id)
+        .collect::<Vec<_>>();
+    decoded.sort();
+    let mut expected = vec![peer_with_envelope, peer_without_envelope];
+    expected.sort();
+    assert_eq!(decoded, expected);
+}
@@ -100,6 +100,12 @@ pub struct Config {
     /// See [`all_forks::Config::max_requests_per_block`] for more information.
     pub max_requests_per_block: NonZeroU32,
 
+    /// Maximum number of blocks to request at once when searching for the ancestry of a block
+    /// whose parent is unknown.
+    ///
+    /// See [`all_forks::Config::max_ancestry_search_depth`] for more information.
+    pub max_ancestry_search_depth: NonZeroU64,
+
     /// Number of blocks to download ahead of the best verified block.
     ///
     /// Whenever the latest best block is updated, the state machine will start block
@@ -124,6 +130,21 @@ pub struct Config {
     /// but if the hint matches it saves a big download.
     // TODO: provide only in non-full mode?
     pub code_trie_node_hint: Option<ConfigCodeTrieNodeHint>,
+
+    /// List of hashes of blocks that must always be considered as invalid, typically because
+    /// they have been produced during a consensus incident.
+    ///
+    /// Block announces concerning these blocks or their children are rejected, and so are
+    /// GrandPa warp sync responses containing these blocks.
+    pub bad_blocks: Vec<[u8; 32]>,
+
+    /// List of block numbers and hashes of blocks that are known to be part of the canonical
+    /// chain, typically because the chain has been forked in order to recover from a consensus
+    /// incident.
+    ///
+    /// Block announces and GrandPa warp sync responses containing a block that has one of these
+    /// numbers but a different hash are rejected.
+    pub fork_blocks: Vec<(u64, [u8; 32])>,
 }
 
 /// Identifier for a source in the [`AllSync`].
@@ -236,8 +257,11 @@ impl<TRq, TSrc, TBl> AllSync<TRq, TSrc, TBl> {
                 blocks_capacity: config.blocks_capacity,
                 max_disjoint_headers: config.max_disjoint_headers,
                 max_requests_per_block: config.max_requests_per_block,
+                max_ancestry_search_depth: config.max_ancestry_search_depth,
                 block_number_bytes: config.block_number_bytes,
                 allow_unknown_consensus_engines: config.allow_unknown_consensus_engines,
+                bad_blocks: config.bad_blocks.into_iter().collect(),
+                fork_blocks: config.fork_blocks.into_iter().collect(),
             },
         }
     }
@@ -1255,6 +1279,20 @@ impl<TRq, TSrc, TBl> AllSync<TRq, TSrc, TBl> {
     ) -> BlockAnnounceOutcome {
         let source_id = self.shared.sources.get(source_id.0).unwrap();
 
+        // Headers that fail to decode are reported below.
+        if let Ok(header) = header::decode(
+            &announced_scale_encoded_header,
+            self.shared.block_number_bytes,
+        ) {
+            if self.shared.is_blacklisted(
+                header.number,
+                &header::hash_from_scale_encoded_header(&announced_scale_encoded_header),
+                header.parent_hash,
+            ) {
+                return BlockAnnounceOutcome::BadBlock;
+            }
+        }
+
         match (&mut self.inner, source_id) {
             (AllSyncInner::AllForks(sync), &SourceMapping::AllForks(source_id)) => {
                 match sync.block_announce(source_id, announced_scale_encoded_header, is_best) {
@@ -1357,6 +1395,67 @@ impl<TRq, TSrc, TBl> AllSync<TRq, TSrc, TBl> {
         }
     }
 
+    /// Marks the given non-finalized block and all its ancestors as finalized, without verifying
+    /// any finality proof.
+    ///
+    /// This is meant to be used in testing environments, such as development chains where the
+    /// local node is the only block author and no finality mechanism is running.
+    ///
+    /// On success, always returns [`FinalityProofVerifyOutcome::NewFinalized`].
+    pub fn force_finalize(
+        &mut self,
+        block_hash: &[u8; 32],
+    ) -> Result<FinalityProofVerifyOutcome<TBl>, ForceFinalizeError> {
+        match &mut self.inner {
+            AllSyncInner::AllForks(sync) => match sync.force_finalize(block_hash) {
+                Ok(all_forks::FinalityProofVerifyOutcome::NewFinalized {
+                    finalized_blocks_newest_to_oldest,
+                    pruned_blocks,
+                    updates_best_block,
+                }) => Ok(FinalityProofVerifyOutcome::NewFinalized {
+                    finalized_blocks_newest_to_oldest: finalized_blocks_newest_to_oldest
+                        .into_iter()
+                        .map(|b| Block {
+                            full: None, // TODO: wrong
+                            header: b.0,
+                            justifications: Vec::new(),
+                            user_data: b.1.unwrap(),
+                        })
+                        .collect(),
+                    pruned_blocks: pruned_blocks
+                        .into_iter()
+                        .map(|b| b.0.hash(self.shared.block_number_bytes))
+                        .collect(),
+                    updates_best_block,
+                }),
+                Ok(_) => unreachable!(),
+                Err(blocks_tree::SetFinalizedError::UnknownBlock) => {
+                    Err(ForceFinalizeError::UnknownBlock)
+                }
+            },
+            AllSyncInner::Optimistic { inner } => match inner.force_finalize(block_hash) {
+                Ok(finalized_blocks) => Ok(FinalityProofVerifyOutcome::NewFinalized {
+                    finalized_blocks_newest_to_oldest: finalized_blocks
+                        .into_iter()
+                        .map(|b| Block {
+                            header: b.header,
+                            justifications: b.justifications,
+                            user_data: b.user_data,
+                            full: b.full.map(|b| BlockFull { body: b.body }),
+                        })
+                        .collect(),
+                    pruned_blocks: Vec::new(),
+                    updates_best_block: false,
+                }),
+                Err(blocks_tree::SetFinalizedError::UnknownBlock) => {
+                    Err(ForceFinalizeError::UnknownBlock)
+                }
+            },
+            AllSyncInner::WarpSync { .. } => Err(ForceFinalizeError::WarpSyncInProgress),
+            AllSyncInner::Poisoned => unreachable!(),
+        }
+    }
+
     /// Update the state machine with a Grandpa commit message received from the network.
     ///
     /// This function only inserts the commit message into the state machine, and does not
@@ -1571,6 +1670,55 @@ impl<TRq, TSrc, TBl> AllSync<TRq, TSrc, TBl> {
         self.grandpa_warp_sync_response_inner(request_id, None)
     }
 
+    /// Inject a failure to a previously-emitted GrandPa warp sync request because the source
+    /// has refused to negotiate the warp sync protocol.
+    ///
+    /// No more GrandPa warp sync request is emitted towards this source. If all the sources
+    /// refuse the warp sync protocol, for example because the chain has disabled it, the
+    /// warp syncing stops at the latest block that has been warp synced to. This is typically
+    /// the checkpoint found in the chain specification. The runtime of this block is downloaded,
+    /// then the rest of the chain is synchronized by downloading headers. See also
+    /// [`AllSync::is_warp_sync_header_sync_fallback`].
+    ///
+    /// # Panic
+    ///
+    /// Panics if the [`RequestId`] doesn't correspond to any request, or corresponds to a request
+    /// of a different type.
+    ///
+    pub fn grandpa_warp_sync_response_unsupported(
+        &mut self,
+        request_id: RequestId,
+    ) -> (TRq, ResponseOutcome) {
+        debug_assert!(self.shared.requests.contains(request_id.0));
+        let request = self.shared.requests.remove(request_id.0);
+
+        match (&mut self.inner, request) {
+            (AllSyncInner::WarpSync { inner, .. }, RequestMapping::WarpSync(request_id)) => {
+                let user_data = inner.warp_sync_request_unsupported(request_id);
+                (user_data.user_data, ResponseOutcome::Queued)
+            }
+
+            // Requests started by the GrandPa warp syncing are converted to `Inline` when the
+            // warp syncing finishes. The response is no longer relevant.
+            (_, RequestMapping::Inline(_, _, user_data)) => (user_data, ResponseOutcome::Outdated),
+
+            (_, _) => {
+                // Type of request doesn't correspond to a GrandPa warp sync request.
+                panic!()
+            }
+        }
+    }
+
+    /// Returns `true` if the warp syncing is in progress and all the sources have refused to
+    /// negotiate the warp sync protocol. See [`AllSync::grandpa_warp_sync_response_unsupported`].
+    pub fn is_warp_sync_header_sync_fallback(&self) -> bool {
+        match &self.inner {
+            AllSyncInner::WarpSync { inner, .. } => inner.is_header_sync_fallback(),
+            AllSyncInner::AllForks(_) | AllSyncInner::Optimistic { .. } => false,
+            AllSyncInner::Poisoned => unreachable!(),
+        }
+    }
+
     fn grandpa_warp_sync_response_inner(
         &mut self,
         request_id: RequestId,
@@ -1581,6 +1729,29 @@ impl<TRq, TSrc, TBl> AllSync<TRq, TSrc, TBl> {
 
         match (&mut self.inner, request) {
             (AllSyncInner::WarpSync { inner, .. }, RequestMapping::WarpSync(request_id)) => {
+                let contains_bad_block = response.as_ref().is_some_and(|(fragments, _)| {
+                    fragments.iter().any(|fragment| {
+                        header::decode(
+                            &fragment.scale_encoded_header,
+                            self.shared.block_number_bytes,
+                        )
+                        .is_ok_and(|header| {
+                            self.shared.is_blacklisted(
+                                header.number,
+                                &header::hash_from_scale_encoded_header(
+                                    &fragment.scale_encoded_header,
+                                ),
+                                header.parent_hash,
+                            )
+                        })
+                    })
+                });
+
+                if contains_bad_block {
+                    let user_data = inner.fail_request(request_id);
+                    return (user_data.user_data, ResponseOutcome::BadBlock);
+                }
+
                 let user_data = if let Some((fragments, is_finished)) = response {
                     inner.warp_sync_request_success(request_id, fragments, is_finished)
                 } else {
@@ -2047,6 +2218,10 @@ pub enum BlockAnnounceOutcome {
     /// Failed to decode announce header.
     InvalidHeader(header::Error),
 
+    /// Announced block or its parent is in the list of bad blocks, or conflicts with the list of
+    /// fork blocks. See [`Config::bad_blocks`] and [`Config::fork_blocks`].
+    BadBlock,
+
     /// Header cannot be verified now and has been silently discarded.
     Discarded,
 }
@@ -2131,6 +2306,11 @@ pub enum ResponseOutcome {
     /// This can happen if a block announce or different ancestry search response has been
     /// processed in between the request and response.
     AllAlreadyInChain,
+
+    /// Response contains a block that is in the list of bad blocks, or that conflicts with the
+    /// list of fork blocks. See [`Config::bad_blocks`] and [`Config::fork_blocks`]. The response
+    /// has been discarded.
+    BadBlock,
 }
 
 /// See [`AllSync::grandpa_commit_message`].
@@ -2200,6 +2380,21 @@ impl<TRq, TSrc, TBl> BlockVerify<TRq, TSrc, TBl> {
         }
     }
 
+    /// Returns the identifier and user data of the source that has provided the header of the
+    /// block to be verified. If the block turns out to be invalid, this source is the one that
+    /// is responsible for it.
+    ///
+    /// Returns `None` if this information isn't available.
+    pub fn header_sender(&self) -> Option<(SourceId, &TSrc)> {
+        match &self.inner {
+            BlockVerifyInner::AllForks(verify) => verify
+                .header_sender()
+                .map(|(_, ud)| (ud.outer_source_id, &ud.user_data)),
+            // TODO: not implemented for the optimistic syncing
+            BlockVerifyInner::Optimistic(_) => None,
+        }
+    }
+
     /// Returns the SCALE-encoded header of the block about to be verified.
     pub fn scale_encoded_header(&self) -> Vec<u8> {
         match &self.inner {
@@ -2309,6 +2504,32 @@ pub enum HeaderVerifyError {
     VerificationFailed(verify::header_only::Error),
 }
 
+impl HeaderVerifyError {
+    /// Returns `true` if the error is caused by the content of the header, and thus by the
+    /// source that has provided it, as opposed to for example the local node not supporting the
+    /// consensus of the chain or the local clock being wrong.
+    pub fn is_bad_block(&self) -> bool {
+        match self {
+            HeaderVerifyError::UnknownConsensusEngine | HeaderVerifyError::ConsensusMismatch => {
+                false
+            }
+            HeaderVerifyError::VerificationFailed(error) => !matches!(
+                error,
+                verify::header_only::Error::BadParentHash
+                    | verify::header_only::Error::UnknownConsensusEngine { .. }
+                    | verify::header_only::Error::AuraVerification(
+                        verify::aura::VerifyError::ParentIsntAuraConsensus
+                            | verify::aura::VerifyError::TooFarInFuture
+                            | verify::aura::VerifyError::EmptyAuthorities
+                    )
+                    | verify::header_only::Error::BabeVerification(
+                        verify::babe::VerifyError::ParentIsntBabeConsensus
+                    )
+            ),
+        }
+    }
+}
+
 pub struct HeaderVerifySuccess<TRq, TSrc, TBl> {
     inner: HeaderVerifySuccessInner<TRq, TSrc, TBl>,
     shared: Shared<TRq>,
@@ -2461,6 +2682,21 @@ enum FinalityProofVerifyInner<TRq, TSrc, TBl> {
 }
 
 impl<TRq, TSrc, TBl> FinalityProofVerify<TRq, TSrc, TBl> {
+    /// Returns the identifier and user data of the source that has sent the finality proof to be
+    /// verified.
+    ///
+    /// Returns `None` if this information isn't available.
+    pub fn sender(&self) -> Option<(SourceId, &TSrc)> {
+        match &self.inner {
+            FinalityProofVerifyInner::AllForks(verify) => {
+                let (_, ud) = verify.sender();
+                Some((ud.outer_source_id, &ud.user_data))
+            }
+            // TODO: not implemented for the optimistic syncing
+            FinalityProofVerifyInner::Optimistic(_) => None,
+        }
+    }
+
     /// Perform the verification.
     ///
     /// A randomness seed must be provided and will be used during the verification. Note that the
@@ -2510,6 +2746,16 @@ impl<TRq, TSrc, TBl> FinalityProofVerify<TRq, TSrc, TBl> {
                     (sync, all_forks::FinalityProofVerifyOutcome::GrandpaCommitError(error)) => {
                         (sync, FinalityProofVerifyOutcome::GrandpaCommitError(error))
                     }
+                    (
+                        sync,
+                        all_forks::FinalityProofVerifyOutcome::FinalityEquivocation(equivocation),
+                    ) => (
+                        sync,
+                        FinalityProofVerifyOutcome::FinalityEquivocation(equivocation),
+                    ),
+                    (sync, all_forks::FinalityProofVerifyOutcome::FinalityFrozen) => {
+                        (sync, FinalityProofVerifyOutcome::FinalityFrozen)
+                    }
                 };
 
                 (
@@ -2581,6 +2827,23 @@ pub enum FinalityProofVerifyOutcome<TBl> {
     JustificationError(blocks_tree::JustificationVerifyError),
     /// Problem while verifying GrandPa commit.
     GrandpaCommitError(blocks_tree::CommitVerifyError),
+    /// The finality proof is valid but targets a block that conflicts with the finalized block.
+    /// Finality is now frozen, and all further finality proofs will lead to
+    /// [`FinalityProofVerifyOutcome::FinalityFrozen`].
+    FinalityEquivocation(blocks_tree::FinalityEquivocation),
+    /// Finality proof has been ignored because a finality equivocation has previously been
+    /// detected.
+    FinalityFrozen,
+}
+
+/// Error potentially returned by [`AllSync::force_finalize`].
+#[derive(Debug, Clone, derive_more::Display)]
+pub enum ForceFinalizeError {
+    /// The block isn't in the list of non-finalized blocks.
+    UnknownBlock,
+    /// The state machine is currently performing a warp sync and doesn't know any
+    /// non-finalized block.
+    WarpSyncInProgress,
 }
 
 pub struct WarpSyncFragmentVerify<TRq, TSrc, TBl> {
@@ -2770,13 +3033,38 @@ struct Shared<TRq> {
     max_disjoint_headers: usize,
     /// Value passed through [`Config::max_requests_per_block`].
     max_requests_per_block: NonZeroU32,
+    /// Value passed through [`Config::max_ancestry_search_depth`].
+    max_ancestry_search_depth: NonZeroU64,
     /// Value passed through [`Config::block_number_bytes`].
     block_number_bytes: usize,
     /// Value passed through [`Config::allow_unknown_consensus_engines`].
     allow_unknown_consensus_engines: bool,
+    /// Value passed through [`Config::bad_blocks`].
+    bad_blocks: hashbrown::HashSet<[u8; 32], fnv::FnvBuildHasher>,
+    /// Value passed through [`Config::fork_blocks`], indexed by block number.
+    fork_blocks: hashbrown::HashMap<u64, [u8; 32], fnv::FnvBuildHasher>,
 }
 
 impl<TRq> Shared<TRq> {
+    /// Returns `true` if the block with the given number, hash, and parent hash, or its parent,
+    /// is in [`Shared::bad_blocks`] or conflicts with [`Shared::fork_blocks`].
+    fn is_blacklisted(&self, number: u64, hash: &[u8; 32], parent_hash: &[u8; 32]) -> bool {
+        if self.bad_blocks.contains(hash) || self.bad_blocks.contains(parent_hash) {
+            return true;
+        }
+
+        let conflicts_with_fork = |number: u64, hash: &[u8; 32]| {
+            self.fork_blocks
+                .get(&number)
+                .is_some_and(|expected| expected != hash)
+        };
+
+        conflicts_with_fork(number, hash)
+            || number
+                .checked_sub(1)
+                .is_some_and(|parent_number| conflicts_with_fork(parent_number, parent_hash))
+    }
+
     /// Transitions the sync state machine from the warp sync strategy to the "all-forks"
     /// strategy.
     fn transition_warp_sync_all_forks<TSrc, TBl>(
@@ -2800,6 +3088,7 @@ impl<TRq> Shared<TRq> {
             blocks_capacity: self.blocks_capacity,
             max_disjoint_headers: self.max_disjoint_headers,
             max_requests_per_block: self.max_requests_per_block,
+            max_ancestry_search_depth: self.max_ancestry_search_depth,
             allow_unknown_consensus_engines: self.allow_unknown_consensus_engines,
             full: false,
         });
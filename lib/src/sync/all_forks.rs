@@ -170,6 +170,17 @@ pub struct Config {
     /// The higher the value, the more bandwidth is potentially wasted.
     pub max_requests_per_block: NonZeroU32,
 
+    /// Maximum number of blocks to request at once when searching for the ancestry of a block
+    /// whose parent is unknown, for example a block announced by a source that is on a fork.
+    ///
+    /// Ancestry searches are performed by requesting blocks in descending order, starting from
+    /// the block whose parent is unknown, until a block that connects to the local tree of
+    /// blocks is found. If the fork is longer than this value, multiple requests are performed
+    /// in a row. A lower value makes each request faster to answer and lowers the amount of
+    /// data wasted if the source is malicious, while a higher value reduces the number of round
+    /// trips necessary when the fork is long. A good default is 64.
+    pub max_ancestry_search_depth: NonZeroU64,
+
     /// If true, the block bodies and storage are also synchronized.
     pub full: bool,
 }
@@ -187,10 +198,19 @@ pub struct AllForksSync<TBl, TRq, TSrc> {
 /// Extra fields. In a separate structure in order to be moved around.
 struct Inner<TBl, TRq, TSrc> {
     blocks: pending_blocks::PendingBlocks<PendingBlock<TBl>, TRq, Source<TSrc>>,
+
+    /// Hashes of the unverified blocks whose seal signature has already been verified with
+    /// [`blocks_tree::NonFinalizedTree::verify_headers_batch`].
+    seal_signatures_verified_ahead: hashbrown::HashSet<[u8; 32], fnv::FnvBuildHasher>,
 }
 
+/// Maximum number of blocks whose seal signatures are verified all at once.
+const SEAL_SIGNATURES_BATCH_SIZE: usize = 64;
+
 struct PendingBlock<TBl> {
     header: Option<header::Header>,
+    /// Source that has provided [`PendingBlock::header`]. `None` if the header isn't known.
+    header_source: Option<SourceId>,
     // TODO: add body: Option<Vec<Vec<u8>>>, when adding full node support
     user_data: TBl,
 }
@@ -439,9 +459,14 @@ impl<TBl, TRq, TSrc> AllForksSync<TBl, TRq, TSrc> {
                     blocks_capacity: config.blocks_capacity,
                     finalized_block_height,
                     max_requests_per_block: config.max_requests_per_block,
+                    max_ancestry_search_depth: config.max_ancestry_search_depth,
                     sources_capacity: config.sources_capacity,
                     verify_bodies: config.full,
                 }),
+                seal_signatures_verified_ahead: hashbrown::HashSet::with_capacity_and_hasher(
+                    0,
+                    Default::default(),
+                ),
             }),
         }
     }
@@ -839,7 +864,9 @@ impl<TBl, TRq, TSrc> AllForksSync<TBl, TRq, TSrc> {
 
     /// Call in response to a blocks request having failed.
     ///
-    /// This removes the request from the state machine and returns its user data.
+    /// This removes the request from the state machine and returns its user data. The same
+    /// request will later be desired again from one of the other sources that know about the
+    /// requested block, if any.
     ///
     /// # Panic
     ///
@@ -847,11 +874,11 @@ impl<TBl, TRq, TSrc> AllForksSync<TBl, TRq, TSrc> {
     ///
     // TODO: taking a `&mut self` instead of a `self` would be more correct, however this doesn't give any benefit and complicates the implementation at the moment, so it might not be worth doing
     pub fn ancestry_search_failed(
-        self,
+        mut self,
         request_id: RequestId,
     ) -> (TRq, AllForksSync<TBl, TRq, TSrc>) {
-        let (user_data, inner) = self.finish_ancestry_search(request_id);
-        (user_data, inner.finish())
+        let (_, _, user_data) = self.inner.blocks.fail_request(request_id);
+        (user_data, self)
     }
 
     /// Update the source with a newly-announced block.
@@ -972,6 +999,42 @@ impl<TBl, TRq, TSrc> AllForksSync<TBl, TRq, TSrc> {
         );
     }
 
+    /// Marks the given non-finalized block and all its ancestors as finalized, without verifying
+    /// any finality proof.
+    ///
+    /// On success, always returns [`FinalityProofVerifyOutcome::NewFinalized`].
+    pub fn force_finalize(
+        &mut self,
+        block_hash: &[u8; 32],
+    ) -> Result<FinalityProofVerifyOutcome<TBl>, blocks_tree::SetFinalizedError> {
+        let block_number_bytes = self.chain.block_number_bytes();
+
+        let finalized_blocks_iter = self.chain.set_finalized_block(block_hash)?;
+        let updates_best_block = finalized_blocks_iter.updates_best_block();
+        let mut finalized_blocks = Vec::new();
+        let mut pruned_blocks = Vec::new();
+        for block in finalized_blocks_iter {
+            let header = header::Header::from(
+                header::decode(&block.scale_encoded_header, block_number_bytes).unwrap(),
+            );
+            if matches!(block.ty, blocks_tree::RemovedBlockType::Finalized) {
+                finalized_blocks.push((header, block.user_data));
+            } else {
+                pruned_blocks.push((header, block.user_data));
+            }
+        }
+        let _finalized_blocks = self
+            .inner
+            .blocks
+            .set_finalized_block_height(finalized_blocks.last().unwrap().0.number);
+
+        Ok(FinalityProofVerifyOutcome::NewFinalized {
+            finalized_blocks_newest_to_oldest: finalized_blocks,
+            pruned_blocks,
+            updates_best_block,
+        })
+    }
+
     /// Update the state machine with a Grandpa commit message received from the network.
     ///
     /// This function only inserts the commit message into the state machine, and does not
@@ -1419,6 +1482,7 @@ impl<TBl, TRq, TSrc> AddBlockOccupied<TBl, TRq, TSrc> {
                 );
             if block_user_data.header.is_none() {
                 block_user_data.header = Some(self.decoded_header.clone());
+                block_user_data.header_source = Some(self.inner.source_id);
                 // TODO: copying bytes :-/
             }
 
@@ -1474,6 +1538,7 @@ impl<TBl, TRq, TSrc> AddBlockVacant<TBl, TRq, TSrc> {
             },
             PendingBlock {
                 header: Some(self.decoded_header.clone()),
+                header_source: Some(self.inner.source_id),
                 user_data,
             },
         );
@@ -1642,6 +1707,7 @@ impl<'a, TBl, TRq, TSrc> AnnouncedBlockKnown<'a, TBl, TRq, TSrc> {
             );
             if block_user_data.header.is_none() {
                 block_user_data.header = Some(self.announced_header_encoded);
+                block_user_data.header_source = Some(self.source_id);
             }
 
             // Mark block as bad if it is not part of the finalized chain.
@@ -1712,6 +1778,7 @@ impl<'a, TBl, TRq, TSrc> AnnouncedBlockUnknown<'a, TBl, TRq, TSrc> {
             },
             PendingBlock {
                 header: Some(self.announced_header_encoded),
+                header_source: Some(self.source_id),
                 user_data,
             },
         );
@@ -1916,6 +1983,7 @@ impl<'a, TBl, TRq, TSrc> AddSourceUnknown<'a, TBl, TRq, TSrc> {
             pending_blocks::UnverifiedBlockState::HeightHash,
             PendingBlock {
                 header: None,
+                header_source: None,
                 user_data: best_block_user_data,
             },
         );
@@ -1939,6 +2007,39 @@ impl<TBl, TRq, TSrc> BlockVerify<TBl, TRq, TSrc> {
         &self.block_to_verify.block_hash
     }
 
+    /// Returns the source that has provided the header of the block to be verified, if it is
+    /// still part of the state machine.
+    ///
+    /// Other sources might know the hash of the block, but only this source is responsible for
+    /// the content of the header.
+    pub fn header_sender(&self) -> Option<(SourceId, &TSrc)> {
+        let source_id = self
+            .parent
+            .inner
+            .blocks
+            .unverified_block_user_data(
+                self.block_to_verify.block_number,
+                &self.block_to_verify.block_hash,
+            )
+            .header_source?;
+
+        // The source might have been removed since the header has been received.
+        if !self
+            .parent
+            .inner
+            .blocks
+            .knows_non_finalized_block(
+                self.block_to_verify.block_number,
+                &self.block_to_verify.block_hash,
+            )
+            .any(|s| s == source_id)
+        {
+            return None;
+        }
+
+        Some((source_id, &self.parent[source_id]))
+    }
+
     /// Returns the SCALE-encoded header of the block about to be verified.
     pub fn scale_encoded_header(&self) -> Vec<u8> {
         self.parent
@@ -1959,6 +2060,61 @@ impl<TBl, TRq, TSrc> BlockVerify<TBl, TRq, TSrc> {
         mut self,
         now_from_unix_epoch: Duration,
     ) -> HeaderVerifyOutcome<TBl, TRq, TSrc> {
+        // Verifying the seal signatures of multiple blocks at once is considerably faster than
+        // verifying them one by one. The block to verify and its unverified descendants are
+        // thus batch-verified ahead of time.
+        if !self
+            .parent
+            .inner
+            .seal_signatures_verified_ahead
+            .remove(&self.block_to_verify.block_hash)
+        {
+            let mut chain = Vec::with_capacity(SEAL_SIGNATURES_BATCH_SIZE);
+            let mut block = (
+                self.block_to_verify.block_number,
+                self.block_to_verify.block_hash,
+            );
+            while chain.len() < SEAL_SIGNATURES_BATCH_SIZE {
+                let Some(header) = &self
+                    .parent
+                    .inner
+                    .blocks
+                    .unverified_block_user_data(block.0, &block.1)
+                    .header
+                else {
+                    break;
+                };
+                chain.push((
+                    block.1,
+                    header.scale_encoding_vec(self.parent.chain.block_number_bytes()),
+                ));
+
+                // In case of a fork, only one of the children is batch-verified.
+                match self
+                    .parent
+                    .inner
+                    .blocks
+                    .unverified_children(block.0, &block.1)
+                    .find(|(_, _, child)| child.header.is_some())
+                {
+                    Some((child_number, child_hash, _)) => block = (child_number, *child_hash),
+                    None => break,
+                }
+            }
+
+            let (hashes, headers): (Vec<_>, Vec<_>) = chain.into_iter().unzip();
+            let num_verified = self
+                .parent
+                .chain
+                .verify_headers_batch(headers.into_iter(), now_from_unix_epoch);
+
+            self.parent.inner.seal_signatures_verified_ahead.clear();
+            self.parent
+                .inner
+                .seal_signatures_verified_ahead
+                .extend(hashes.into_iter().take(num_verified).skip(1));
+        }
+
         let to_verify_scale_encoded_header = self.scale_encoded_header();
 
         let result = match self
@@ -2119,6 +2275,12 @@ pub struct FinalityProofVerify<TBl, TRq, TSrc> {
 }
 
 impl<TBl, TRq, TSrc> FinalityProofVerify<TBl, TRq, TSrc> {
+    /// Returns the identifier and user data of the source that has sent the finality proof to be
+    /// verified.
+    pub fn sender(&self) -> (SourceId, &TSrc) {
+        (self.source_id, &self.parent[self.source_id])
+    }
+
     /// Perform the verification.
     ///
     /// A randomness seed must be provided and will be used during the verification. Note that the
@@ -2148,6 +2310,21 @@ impl<TBl, TRq, TSrc> FinalityProofVerify<TBl, TRq, TSrc> {
                         | blocks_tree::FinalityVerifyError::BelowFinalized,
                     )) => return (self.parent, FinalityProofVerifyOutcome::AlreadyFinalized),
 
+                    // Finality proofs are ignored after an equivocation has been detected.
+                    Err(blocks_tree::CommitVerifyError::FinalityVerify(
+                        blocks_tree::FinalityVerifyError::FinalityFrozen,
+                    )) => return (self.parent, FinalityProofVerifyOutcome::FinalityFrozen),
+
+                    // The commit is valid but conflicts with the finalized block.
+                    Err(blocks_tree::CommitVerifyError::ConflictingFinality { .. }) => {
+                        let equivocation =
+                            self.parent.chain.finality_equivocation().unwrap().clone();
+                        return (
+                            self.parent,
+                            FinalityProofVerifyOutcome::FinalityEquivocation(equivocation),
+                        );
+                    }
+
                     // The commit can't be verified yet.
                     Err(
                         blocks_tree::CommitVerifyError::FinalityVerify(
@@ -2203,6 +2380,21 @@ impl<TBl, TRq, TSrc> FinalityProofVerify<TBl, TRq, TSrc> {
                         | blocks_tree::FinalityVerifyError::BelowFinalized,
                     )) => return (self.parent, FinalityProofVerifyOutcome::AlreadyFinalized),
 
+                    // Finality proofs are ignored after an equivocation has been detected.
+                    Err(blocks_tree::JustificationVerifyError::FinalityVerify(
+                        blocks_tree::FinalityVerifyError::FinalityFrozen,
+                    )) => return (self.parent, FinalityProofVerifyOutcome::FinalityFrozen),
+
+                    // The justification is valid but conflicts with the finalized block.
+                    Err(blocks_tree::JustificationVerifyError::ConflictingFinality { .. }) => {
+                        let equivocation =
+                            self.parent.chain.finality_equivocation().unwrap().clone();
+                        return (
+                            self.parent,
+                            FinalityProofVerifyOutcome::FinalityEquivocation(equivocation),
+                        );
+                    }
+
                     // Note that, contrary to commits, there's no such thing as a justification
                     // that can't be verified yet.
                     Err(err) => {
@@ -2335,6 +2527,13 @@ pub enum FinalityProofVerifyOutcome<TBl> {
     JustificationError(blocks_tree::JustificationVerifyError),
     /// Problem while verifying GrandPa commit.
     GrandpaCommitError(blocks_tree::CommitVerifyError),
+    /// The finality proof is valid but targets a block that conflicts with the finalized block.
+    /// Finality is now frozen, and all further finality proofs will lead to
+    /// [`FinalityProofVerifyOutcome::FinalityFrozen`].
+    FinalityEquivocation(blocks_tree::FinalityEquivocation),
+    /// Finality proof has been ignored because a finality equivocation has previously been
+    /// detected.
+    FinalityFrozen,
 }
 
 /// State of the processing of blocks.
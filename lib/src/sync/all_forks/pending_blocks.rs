@@ -85,6 +85,12 @@
 //! The user is encouraged to update the state machine according to the response, but this must
 //! be done manually.
 //!
+//! Identical requests are never desired simultaneously. While a request is in progress, the
+//! other sources that know about the block it targets are instead registered as waiting for
+//! this request (see [`PendingBlocks::request_waiters`]). Call [`PendingBlocks::fail_request`]
+//! if a request fails, in which case the same request will be desired again from one of the
+//! sources that were waiting.
+//!
 
 #![allow(dead_code)] // TODO: remove this after `all.rs` implements full node; right now many methods here are useless because expected to be used only for full node code
 
@@ -92,7 +98,7 @@ use super::{disjoint, sources};
 
 use alloc::{collections::BTreeSet, vec::Vec};
 use core::{
-    iter,
+    cmp, iter,
     num::{NonZeroU32, NonZeroU64},
     ops,
 };
@@ -129,7 +135,20 @@ pub struct Config {
     /// because of malicious sources.
     ///
     /// The higher the value, the more bandwidth is potentially wasted.
+    ///
+    /// > **Note**: Regardless of this value, two identical requests (i.e. with the same
+    /// >           [`RequestParams`]) are never started simultaneously. When multiple sources
+    /// >           know about the same block, only one of them is queried, and the others wait
+    /// >           for the outcome of this request and are queried only if it fails. See
+    /// >           [`PendingBlocks::request_waiters`].
     pub max_requests_per_block: NonZeroU32,
+
+    /// Maximum number of blocks to request at once when searching for the ancestry of a block
+    /// whose parent is unknown.
+    ///
+    /// Requests are made in descending order starting from the unknown block. If the ancestry
+    /// is deeper than this value, multiple successive requests are made.
+    pub max_ancestry_search_depth: NonZeroU64,
 }
 
 /// State of a block in the data structure.
@@ -198,12 +217,30 @@ pub struct PendingBlocks<TBl, TRq, TSrc> {
     /// The `request_id` is an index in [`PendingBlocks::requests`].
     source_occupations: BTreeSet<(SourceId, RequestId)>,
 
+    /// Set of `(request_params, request_id)`.
+    /// Contains the list of requests, indexed by their parameters. Used in order to avoid
+    /// starting multiple identical requests at the same time.
+    ///
+    /// The `request_id` is an index in [`PendingBlocks::requests`].
+    requests_by_params: BTreeSet<(RequestParams, RequestId)>,
+
+    /// Set of `(request_id, source_id)`.
+    /// Contains, for each request, the other sources that know about the first block of this
+    /// request and that wait for the outcome of this request rather than being sent an identical
+    /// one.
+    ///
+    /// The `request_id` is an index in [`PendingBlocks::requests`].
+    request_waiters: BTreeSet<(RequestId, SourceId)>,
+
     /// All ongoing requests.
     requests: slab::Slab<Request<TRq>>,
 
     /// See [`Config::max_requests_per_block`].
     /// Since it is always compared with `usize`s, converted to `usize` ahead of time.
     max_requests_per_block: usize,
+
+    /// See [`Config::max_ancestry_search_depth`].
+    max_ancestry_search_depth: NonZeroU64,
 }
 
 struct UnverifiedBlock<TBl> {
@@ -236,6 +273,8 @@ impl<TBl, TRq, TSrc> PendingBlocks<TBl, TRq, TSrc> {
             blocks_requests: Default::default(),
             requested_blocks: Default::default(),
             source_occupations: Default::default(),
+            requests_by_params: Default::default(),
+            request_waiters: Default::default(),
             requests: slab::Slab::with_capacity(
                 config.blocks_capacity
                     * usize::try_from(config.max_requests_per_block.get())
@@ -243,6 +282,7 @@ impl<TBl, TRq, TSrc> PendingBlocks<TBl, TRq, TSrc> {
             ),
             max_requests_per_block: usize::try_from(config.max_requests_per_block.get())
                 .unwrap_or(usize::max_value()),
+            max_ancestry_search_depth: config.max_ancestry_search_depth,
         }
     }
 
@@ -315,10 +355,23 @@ impl<TBl, TRq, TSrc> PendingBlocks<TBl, TRq, TSrc> {
             ));
             debug_assert!(_was_in);
 
+            let _was_in = self
+                .requests_by_params
+                .remove(&(request.detail, pending_request_id));
+            debug_assert!(_was_in);
+
+            self.remove_request_waiters(pending_request_id);
+
             pending_requests.push((pending_request_id, request.detail, request.user_data));
         }
 
         debug_assert_eq!(self.source_occupations.len(), self.requests.len());
+        debug_assert_eq!(self.requests_by_params.len(), self.requests.len());
+
+        // The source no longer waits for any request.
+        // TODO: O(n)
+        self.request_waiters
+            .retain(|(_, waiter_id)| *waiter_id != source_id);
 
         (user_data.user_data, pending_requests.into_iter())
     }
@@ -347,6 +400,7 @@ impl<TBl, TRq, TSrc> PendingBlocks<TBl, TRq, TSrc> {
     ///
     pub fn add_known_block_to_source(&mut self, source_id: SourceId, height: u64, hash: [u8; 32]) {
         self.sources.add_known_block(source_id, height, hash);
+        self.add_source_to_request_waiters(source_id, height, &hash);
     }
 
     /// Un-registers a new block that the source is aware of.
@@ -368,6 +422,12 @@ impl<TBl, TRq, TSrc> PendingBlocks<TBl, TRq, TSrc> {
     ) {
         self.sources
             .source_remove_known_block(source_id, height, hash);
+
+        // The source can no longer take over the requests concerning this block.
+        let waited_requests = self.requests_starting_at(height, hash).collect::<Vec<_>>();
+        for request_id in waited_requests {
+            self.request_waiters.remove(&(request_id, source_id));
+        }
     }
 
     /// Registers a new block that the source is aware of and sets it as its best block.
@@ -389,6 +449,7 @@ impl<TBl, TRq, TSrc> PendingBlocks<TBl, TRq, TSrc> {
     ) {
         self.sources
             .add_known_block_and_set_best(source_id, height, hash);
+        self.add_source_to_request_waiters(source_id, height, &hash);
     }
 
     /// Returns the current best block of the given source.
@@ -522,6 +583,17 @@ impl<TBl, TRq, TSrc> PendingBlocks<TBl, TRq, TSrc> {
         &self.blocks.user_data(height, hash).unwrap().user_data
     }
 
+    /// Returns the list of unverified blocks whose parent is the given block.
+    pub fn unverified_children(
+        &'_ self,
+        height: u64,
+        hash: &[u8; 32],
+    ) -> impl Iterator<Item = (u64, &'_ [u8; 32], &'_ TBl)> + '_ {
+        self.blocks
+            .children(height, hash)
+            .map(|(height, hash, block)| (height, hash, &block.user_data))
+    }
+
     /// Gives access to the user data stored for this block.
     ///
     /// # Panic
@@ -791,7 +863,11 @@ impl<TBl, TRq, TSrc> PendingBlocks<TBl, TRq, TSrc> {
         let _was_inserted = self.source_occupations.insert((source_id, request_id));
         debug_assert!(_was_inserted);
 
+        let _was_inserted = self.requests_by_params.insert((detail, request_id));
+        debug_assert!(_was_inserted);
+
         debug_assert_eq!(self.source_occupations.len(), self.requests.len());
+        debug_assert_eq!(self.requests_by_params.len(), self.requests.len());
 
         // Add in `blocks_requests` and `requested_blocks` an entry for each known block.
         let mut iter = (detail.first_block_height, detail.first_block_hash);
@@ -805,6 +881,18 @@ impl<TBl, TRq, TSrc> PendingBlocks<TBl, TRq, TSrc> {
             }
         }
 
+        // All the other sources that know about the requested block now wait for the outcome of
+        // this request.
+        if detail.first_block_height > self.sources.finalized_block_height() {
+            for waiter_id in self
+                .sources
+                .knows_non_finalized_block(detail.first_block_height, &detail.first_block_hash)
+                .filter(|id| *id != source_id)
+            {
+                self.request_waiters.insert((request_id, waiter_id));
+            }
+        }
+
         request_id
     }
 
@@ -855,12 +943,45 @@ impl<TBl, TRq, TSrc> PendingBlocks<TBl, TRq, TSrc> {
             .remove(&(request.source_id, request_id));
         debug_assert!(_was_in);
 
+        let _was_in = self
+            .requests_by_params
+            .remove(&(request.detail, request_id));
+        debug_assert!(_was_in);
+
+        self.remove_request_waiters(request_id);
+
         debug_assert_eq!(self.source_occupations.len(), self.requests.len());
+        debug_assert_eq!(self.requests_by_params.len(), self.requests.len());
         debug_assert_eq!(self.blocks_requests.len(), self.requested_blocks.len());
 
         (request.detail, request.source_id, request.user_data)
     }
 
+    /// Marks a request as failed.
+    ///
+    /// Returns the parameters that were passed to [`PendingBlocks::add_request`].
+    ///
+    /// Similar to [`PendingBlocks::finish_request`], except that the source of the request is
+    /// additionally considered as no longer knowing the requested block, as it is apparently
+    /// unable to serve it. The next call to [`PendingBlocks::desired_requests`] will return
+    /// this request again, towards one of the sources that were returned by
+    /// [`PendingBlocks::request_waiters`].
+    ///
+    /// # Panic
+    ///
+    /// Panics if the [`RequestId`] is invalid.
+    ///
+    #[track_caller]
+    pub fn fail_request(&mut self, request_id: RequestId) -> (RequestParams, SourceId, TRq) {
+        let (detail, source_id, user_data) = self.finish_request(request_id);
+        self.remove_known_block_of_source(
+            source_id,
+            detail.first_block_height,
+            &detail.first_block_hash,
+        );
+        (detail, source_id, user_data)
+    }
+
     /// Returns the source that the given request is being performed on.
     ///
     /// # Panic
@@ -872,6 +993,24 @@ impl<TBl, TRq, TSrc> PendingBlocks<TBl, TRq, TSrc> {
         self.requests.get(request_id.0).unwrap().source_id
     }
 
+    /// Returns the list of sources that know about the first block of the given request, and
+    /// that wait for the outcome of this request instead of being sent an identical one.
+    ///
+    /// If the request fails (see [`PendingBlocks::fail_request`]), it is re-issued to one of
+    /// these sources.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the [`RequestId`] is invalid.
+    ///
+    #[track_caller]
+    pub fn request_waiters(&'_ self, request_id: RequestId) -> impl Iterator<Item = SourceId> + '_ {
+        assert!(self.requests.contains(request_id.0));
+        self.request_waiters
+            .range((request_id, SourceId::min_value())..=(request_id, SourceId::max_value()))
+            .map(|(_, source_id)| *source_id)
+    }
+
     /// Returns a list of requests that are considered obsolete and can be removed using
     /// [`PendingBlocks::finish_request`].
     ///
@@ -942,6 +1081,80 @@ impl<TBl, TRq, TSrc> PendingBlocks<TBl, TRq, TSrc> {
         })
     }
 
+    /// Returns the number of blocks to request when searching for the ancestry of the unknown
+    /// block of the given height.
+    ///
+    /// The search goes down to the finalized block at most, and is capped by
+    /// [`Config::max_ancestry_search_depth`].
+    ///
+    /// # Panic
+    ///
+    /// Panics if the height is inferior or equal to the finalized block height.
+    ///
+    fn ancestry_search_num_blocks(&self, unknown_block_height: u64) -> NonZeroU64 {
+        let until_finalized =
+            NonZeroU64::new(unknown_block_height - self.sources.finalized_block_height()).unwrap();
+        cmp::min(until_finalized, self.max_ancestry_search_depth)
+    }
+
+    /// Returns the list of requests whose first block is the given one.
+    fn requests_starting_at<'a>(
+        &'a self,
+        height: u64,
+        hash: &'a [u8; 32],
+    ) -> impl Iterator<Item = RequestId> + 'a {
+        self.blocks_requests
+            .range(
+                (height, *hash, RequestId(usize::min_value()))
+                    ..=(height, *hash, RequestId(usize::max_value())),
+            )
+            .map(|(_, _, request_id)| *request_id)
+            .filter(move |request_id| {
+                let detail = &self.requests[request_id.0].detail;
+                detail.first_block_height == height && detail.first_block_hash == *hash
+            })
+    }
+
+    /// Registers the given source as waiting for the requests whose first block is the given
+    /// one, after the source has learned about this block.
+    fn add_source_to_request_waiters(&mut self, source_id: SourceId, height: u64, hash: &[u8; 32]) {
+        if height <= self.sources.finalized_block_height()
+            || !self
+                .sources
+                .source_knows_non_finalized_block(source_id, height, hash)
+        {
+            return;
+        }
+
+        let waited_requests = self
+            .requests_starting_at(height, hash)
+            .filter(|request_id| self.requests[request_id.0].source_id != source_id)
+            .collect::<Vec<_>>();
+        for request_id in waited_requests {
+            self.request_waiters.insert((request_id, source_id));
+        }
+    }
+
+    /// Removes from [`PendingBlocks::request_waiters`] all the entries of the given request.
+    fn remove_request_waiters(&mut self, request_id: RequestId) {
+        let waiters = self
+            .request_waiters
+            .range((request_id, SourceId::min_value())..=(request_id, SourceId::max_value()))
+            .copied()
+            .collect::<Vec<_>>();
+        for waiter in waiters {
+            self.request_waiters.remove(&waiter);
+        }
+    }
+
+    /// Returns `true` if a request with exactly the given parameters is currently in progress.
+    fn is_identical_request_in_progress(&self, params: &RequestParams) -> bool {
+        self.requests_by_params
+            .range((*params, RequestId(usize::MIN))..=(*params, RequestId(usize::MAX)))
+            .next()
+            .is_some()
+    }
+
     /// Inner implementation of [`PendingBlocks::desired_requests`] and
     /// [`PendingBlocks::source_desired_requests`].
     ///
@@ -1020,6 +1233,15 @@ impl<TBl, TRq, TSrc> PendingBlocks<TBl, TRq, TSrc> {
                 debug_assert!(num_existing_requests <= self.max_requests_per_block);
                 num_existing_requests < self.max_requests_per_block
             })
+            .filter(move |(unknown_block_height, unknown_block_hash)| {
+                // Don't start any request if an identical request is already in progress. The
+                // sources that know about this block will only be queried if that request fails.
+                !self.is_identical_request_in_progress(&RequestParams {
+                    first_block_hash: **unknown_block_hash,
+                    first_block_height: *unknown_block_height,
+                    num_blocks: self.ancestry_search_num_blocks(*unknown_block_height),
+                })
+            })
             .flat_map(move |(unknown_block_height, unknown_block_hash)| {
                 // Try to find all appropriate sources.
                 let possible_sources = if let Some(force_source) = force_source {
@@ -1072,10 +1294,7 @@ impl<TBl, TRq, TSrc> PendingBlocks<TBl, TRq, TSrc> {
                             request_params: RequestParams {
                                 first_block_hash: *unknown_block_hash,
                                 first_block_height: unknown_block_height,
-                                num_blocks: NonZeroU64::new(
-                                    unknown_block_height - self.sources.finalized_block_height(),
-                                )
-                                .unwrap(),
+                                num_blocks: self.ancestry_search_num_blocks(unknown_block_height),
                             },
                         }
                     })
@@ -1127,3 +1346,136 @@ pub struct RequestParams {
     /// than requested.
     pub num_blocks: NonZeroU64,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{Config, PendingBlocks, RequestParams, UnverifiedBlockState};
+    use core::num::{NonZeroU32, NonZeroU64};
+
+    fn new_pending_blocks() -> PendingBlocks<(), (), ()> {
+        PendingBlocks::new(Config {
+            blocks_capacity: 32,
+            sources_capacity: 4,
+            finalized_block_height: 10,
+            verify_bodies: false,
+            max_requests_per_block: NonZeroU32::new(1).unwrap(),
+            max_ancestry_search_depth: NonZeroU64::new(16).unwrap(),
+        })
+    }
+
+    #[test]
+    fn ancestry_search_capped_for_deep_fork() {
+        let mut pending_blocks = new_pending_blocks();
+        let source = pending_blocks.add_source((), 100, [1; 32]);
+        pending_blocks.insert_unverified_block(100, [1; 32], UnverifiedBlockState::HeightHash, ());
+
+        // The fork is 90 blocks deep, but only 16 blocks are requested at once.
+        let requests = pending_blocks.desired_requests().collect::<Vec<_>>();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].source_id, source);
+        assert_eq!(
+            requests[0].request_params,
+            RequestParams {
+                first_block_height: 100,
+                first_block_hash: [1; 32],
+                num_blocks: NonZeroU64::new(16).unwrap(),
+            }
+        );
+
+        // Once the header is known, the search continues from its parent.
+        pending_blocks.insert_unverified_block(
+            100,
+            [1; 32],
+            UnverifiedBlockState::Header {
+                parent_hash: [2; 32],
+            },
+            (),
+        );
+        pending_blocks.add_known_block_to_source(source, 99, [2; 32]);
+        let requests = pending_blocks.desired_requests().collect::<Vec<_>>();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(
+            requests[0].request_params,
+            RequestParams {
+                first_block_height: 99,
+                first_block_hash: [2; 32],
+                num_blocks: NonZeroU64::new(16).unwrap(),
+            }
+        );
+    }
+
+    #[test]
+    fn identical_requests_deduplicated() {
+        let mut pending_blocks = new_pending_blocks();
+        let source1 = pending_blocks.add_source((), 20, [1; 32]);
+        let source2 = pending_blocks.add_source((), 20, [1; 32]);
+        pending_blocks.insert_unverified_block(20, [1; 32], UnverifiedBlockState::HeightHash, ());
+
+        // Both sources could serve the block.
+        let requests = pending_blocks.desired_requests().collect::<Vec<_>>();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0].request_params, requests[1].request_params);
+
+        // Once a request has started, the other source waits for it instead of being queried.
+        let request_id = pending_blocks.add_request(source1, requests[0].request_params, ());
+        assert_eq!(pending_blocks.desired_requests().count(), 0);
+        assert_eq!(
+            pending_blocks
+                .request_waiters(request_id)
+                .collect::<Vec<_>>(),
+            vec![source2]
+        );
+
+        // A source learning about the block later also waits.
+        let source3 = pending_blocks.add_source((), 15, [3; 32]);
+        pending_blocks.add_known_block_to_source(source3, 20, [1; 32]);
+        assert_eq!(pending_blocks.desired_requests().count(), 0);
+        assert_eq!(
+            pending_blocks
+                .request_waiters(request_id)
+                .collect::<Vec<_>>(),
+            vec![source2, source3]
+        );
+    }
+
+    #[test]
+    fn failed_request_reissued_to_waiter() {
+        let mut pending_blocks = new_pending_blocks();
+        let source1 = pending_blocks.add_source((), 20, [1; 32]);
+        let source2 = pending_blocks.add_source((), 20, [1; 32]);
+        pending_blocks.insert_unverified_block(20, [1; 32], UnverifiedBlockState::HeightHash, ());
+
+        let request_params = RequestParams {
+            first_block_height: 20,
+            first_block_hash: [1; 32],
+            num_blocks: NonZeroU64::new(10).unwrap(),
+        };
+        let request_id = pending_blocks.add_request(source1, request_params, ());
+        assert_eq!(pending_blocks.desired_requests().count(), 0);
+
+        // The failed request is desired again, this time from the source that was waiting.
+        assert_eq!(
+            pending_blocks.fail_request(request_id),
+            (request_params, source1, ())
+        );
+        let requests = pending_blocks.desired_requests().collect::<Vec<_>>();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].source_id, source2);
+        assert_eq!(requests[0].request_params, request_params);
+    }
+
+    #[test]
+    fn ancestry_search_stops_at_finalized() {
+        let mut pending_blocks = new_pending_blocks();
+        pending_blocks.add_source((), 15, [1; 32]);
+        pending_blocks.insert_unverified_block(15, [1; 32], UnverifiedBlockState::HeightHash, ());
+
+        // Only 5 blocks separate the unknown block from the finalized block.
+        let requests = pending_blocks.desired_requests().collect::<Vec<_>>();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(
+            requests[0].request_params.num_blocks,
+            NonZeroU64::new(5).unwrap()
+        );
+    }
+}
@@ -35,6 +35,18 @@ use core::{fmt, ops};
 #[derive(Debug, Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash)]
 pub struct SourceId(u64);
 
+impl SourceId {
+    /// Returns a value that compares inferior or equal to any other [`SourceId`].
+    pub(super) fn min_value() -> Self {
+        Self(u64::min_value())
+    }
+
+    /// Returns a value that compares superior or equal to any other [`SourceId`].
+    pub(super) fn max_value() -> Self {
+        Self(u64::max_value())
+    }
+}
+
 /// Collection of sources and which blocks they know about.
 pub struct AllForksSources<TSrc> {
     /// Actual list of sources.
@@ -65,6 +65,9 @@ use hashbrown::HashMap;
 
 mod verification_queue;
 
+/// Maximum number of blocks whose seal signatures are verified all at once.
+const SEAL_SIGNATURES_BATCH_SIZE: usize = 64;
+
 /// Configuration for the [`OptimisticSync`].
 #[derive(Debug)]
 pub struct Config {
@@ -169,10 +172,16 @@ struct OptimisticSyncInner<TRq, TSrc, TBl> {
 
     /// Same as [`OptimisticSyncInner::obsolete_requests`], but ordered differently.
     obsolete_requests_by_source: BTreeSet<(SourceId, RequestId)>,
+
+    /// Number of blocks at the front of [`OptimisticSyncInner::verification_queue`] whose seal
+    /// signature has already been verified with
+    /// [`blocks_tree::NonFinalizedTree::verify_headers_batch`].
+    num_seal_signatures_verified_ahead: usize,
 }
 
 impl<TRq, TSrc, TBl> OptimisticSyncInner<TRq, TSrc, TBl> {
     fn make_requests_obsolete(&mut self, chain: &blocks_tree::NonFinalizedTree<Block<TBl>>) {
+        self.num_seal_signatures_verified_ahead = 0;
         let former_queue = mem::replace(
             &mut self.verification_queue,
             verification_queue::VerificationQueue::new(chain.best_block_header().number + 1),
@@ -276,6 +285,7 @@ impl<TRq, TSrc, TBl> OptimisticSync<TRq, TSrc, TBl> {
                 next_request_id: RequestId(0),
                 obsolete_requests: HashMap::with_capacity_and_hasher(0, Default::default()),
                 obsolete_requests_by_source: BTreeSet::new(),
+                num_seal_signatures_verified_ahead: 0,
             }),
         }
     }
@@ -347,6 +357,27 @@ impl<TRq, TSrc, TBl> OptimisticSync<TRq, TSrc, TBl> {
         self.chain.iter_ancestry_order()
     }
 
+    /// Marks the given non-finalized block and all its ancestors as finalized, without verifying
+    /// any finality proof.
+    ///
+    /// Returns the blocks that have been finalized, in decreasing block number.
+    pub fn force_finalize(
+        &mut self,
+        block_hash: &[u8; 32],
+    ) -> Result<Vec<Block<TBl>>, blocks_tree::SetFinalizedError> {
+        let finalized_blocks_newest_to_oldest = self
+            .chain
+            .set_finalized_block(block_hash)?
+            .filter(|b| matches!(b.ty, blocks_tree::RemovedBlockType::Finalized))
+            .map(|b| b.user_data)
+            .collect();
+
+        self.inner.finalized_chain_information.chain_information =
+            self.chain.as_chain_information().into();
+
+        Ok(finalized_blocks_newest_to_oldest)
+    }
+
     /// Disassembles the state machine into its raw components.
     pub fn disassemble(self) -> Disassemble<TRq, TSrc> {
         Disassemble {
@@ -459,6 +490,7 @@ impl<TRq, TSrc, TBl> OptimisticSync<TRq, TSrc, TBl> {
         );
 
         let src_user_data = self.inner.sources.remove(&source_id).unwrap().user_data;
+        self.inner.num_seal_signatures_verified_ahead = 0;
         let drain = RequestsDrain {
             iter: self.inner.verification_queue.drain_source(source_id),
         };
@@ -498,12 +530,21 @@ impl<TRq, TSrc, TBl> OptimisticSync<TRq, TSrc, TBl> {
     }
 
     /// Returns an iterator that yields all requests that could be started.
+    ///
+    /// Each range of blocks is yielded once per source that could serve it, but only one request
+    /// per range is ever in progress: once a request has been inserted with
+    /// [`OptimisticSync::insert_request`], its range is no longer returned, and the other sources
+    /// wait for its outcome. If the request fails, the range is yielded again, but not towards
+    /// the source that has failed it, unless all the sources have failed a request.
     pub fn desired_requests(&'_ self) -> impl Iterator<Item = RequestDetail> + '_ {
         let sources = &self.inner.sources;
         self.inner
             .verification_queue
             .desired_requests(self.inner.download_ahead_blocks)
             .flat_map(move |e| sources.iter().map(move |s| (e, s)))
+            // Sources that have failed a request are banned, so that the ranges of blocks of
+            // failed requests are requested again from a different source.
+            .filter(|(_, (_, source))| !source.banned)
             .filter_map(|((block_height, num_blocks), (source_id, source))| {
                 let source_avail_blocks = NonZeroU32::new(
                     u32::try_from(source.best_block_number.checked_sub(block_height.get())? + 1)
@@ -800,6 +841,24 @@ impl<TRq, TSrc, TBl> BlockVerify<TRq, TSrc, TBl> {
         mut self,
         now_from_unix_epoch: Duration,
     ) -> BlockVerification<TRq, TSrc, TBl> {
+        // Verifying the seal signatures of multiple blocks at once is considerably faster than
+        // verifying them one by one. The blocks that are ready are thus batch-verified ahead of
+        // time.
+        if self.inner.num_seal_signatures_verified_ahead == 0 {
+            self.inner.num_seal_signatures_verified_ahead = self.chain.verify_headers_batch(
+                self.inner
+                    .verification_queue
+                    .ready_blocks()
+                    .take(SEAL_SIGNATURES_BATCH_SIZE)
+                    .map(|block| block.scale_encoded_header.clone()),
+                now_from_unix_epoch,
+            );
+        }
+        self.inner.num_seal_signatures_verified_ahead = self
+            .inner
+            .num_seal_signatures_verified_ahead
+            .saturating_sub(1);
+
         // Extract the block to process. We are guaranteed that a block is available because a
         // `Verify` is built only when that is the case.
         // Be aware that `source_id` might refer to an obsolete source.
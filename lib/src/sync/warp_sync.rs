@@ -240,6 +240,8 @@ pub fn start_warp_sync<TSrc, TRq>(
         in_progress_requests_by_source: BTreeSet::new(),
         warp_sync_fragments_download: None,
         verify_queue: VecDeque::new(),
+        fragments_download_continuation: false,
+        header_sync_fallback: false,
         runtime_download: RuntimeDownload::NotStarted {
             hint_doesnt_match: false,
         },
@@ -355,6 +357,18 @@ pub struct WarpSync<TSrc, TRq> {
     warp_sync_fragments_download: Option<RequestId>,
     /// Queue of fragments that have been downloaded and need to be verified.
     verify_queue: VecDeque<PendingVerify>,
+    /// `true` if the most recently downloaded set of fragments wasn't the final one, in other
+    /// words if the source has indicated that its proof was truncated because of the response
+    /// size limit. When that is the case, the download continues starting from the last
+    /// downloaded header, regardless of [`WarpSync::warp_sync_minimum_gap`], and the runtime
+    /// isn't downloaded until the end of the proof has been reached.
+    fragments_download_continuation: bool,
+    /// `true` if all the sources have refused to negotiate the warp sync protocol. When that is
+    /// the case, no more fragments are downloaded, and the runtime and chain information are
+    /// instead downloaded for [`WarpSync::warped_header`], which is typically the starting
+    /// point found in the chain specification. The rest of the chain is then expected to be
+    /// synchronized by downloading headers one by one.
+    header_sync_fallback: bool,
     /// State of the download of the runtime and chain information call proofs.
     runtime_download: RuntimeDownload,
     /// For each call required by the chain information builder, whether it has been downloaded yet.
@@ -370,6 +384,9 @@ struct Source<TSrc> {
     /// Height of the finalized block of the source, as reported by the source. Contains `Err`
     /// if the source has sent invalid fragments or proofs in the past.
     finalized_block_height: Result<u64, ()>,
+    /// `true` if the source has refused to negotiate the warp sync protocol. See
+    /// [`WarpSync::warp_sync_request_unsupported`].
+    warp_sync_unsupported: bool,
 }
 
 /// See [`WarpSync::warped_block_ty`].
@@ -587,12 +604,19 @@ impl<TSrc, TRq> WarpSync<TSrc, TRq> {
     ///
     /// The source has a finalized block height of 0, which should later be updated using
     /// [`WarpSync::set_source_finality_state`].
+    ///
+    /// If the state machine had fallen back to downloading headers because all the sources had
+    /// refused the warp sync protocol (see [`WarpSync::is_header_sync_fallback`]), the fallback
+    /// is cancelled and the new source is tried with a warp sync request.
     pub fn add_source(&mut self, user_data: TSrc) -> SourceId {
         let source_id = SourceId(self.sources.insert(Source {
             user_data,
             finalized_block_height: Ok(0),
+            warp_sync_unsupported: false,
         }));
 
+        self.header_sync_fallback = false;
+
         let _inserted = self.sources_by_finalized_height.insert((0, source_id));
         debug_assert!(_inserted);
         debug_assert!(self.sources.len() >= self.sources_by_finalized_height.len());
@@ -728,7 +752,9 @@ impl<TSrc, TRq> WarpSync<TSrc, TRq> {
         &'_ self,
     ) -> impl Iterator<Item = (SourceId, &'_ TSrc, DesiredRequest)> + '_ {
         // If we are in the fragments download phase, return a fragments download request.
-        let mut desired_warp_sync_request = if self.warp_sync_fragments_download.is_none() {
+        let mut desired_warp_sync_request = if self.warp_sync_fragments_download.is_none()
+            && !self.header_sync_fallback
+        {
             if self.verify_queue.iter().fold(0, |sum, entry| {
                 sum + entry.fragments.len() - entry.next_fragment_to_verify_index
             }) < self.num_download_ahead_fragments
@@ -764,11 +790,21 @@ impl<TSrc, TRq> WarpSync<TSrc, TRq> {
                             .map(|header| header.number)
                     })
                     .unwrap_or(Some(self.warped_header_number));
-                let warp_sync_minimum_gap = self.warp_sync_minimum_gap;
+                // If the previous response indicated that more fragments are available, the
+                // proof must be continued no matter the gap.
+                let warp_sync_minimum_gap = if self.fragments_download_continuation {
+                    0
+                } else {
+                    self.warp_sync_minimum_gap
+                };
 
                 if let Some(verify_queue_tail_block_number) = verify_queue_tail_block_number {
                     // Combine the request with every single available source.
                     either::Left(self.sources.iter().filter_map(move |(src_id, src)| {
+                        if src.warp_sync_unsupported {
+                            return None;
+                        }
+
                         if src.finalized_block_height.map_or(true, |h| {
                             h <= verify_queue_tail_block_number.saturating_add(
                                 u64::try_from(warp_sync_minimum_gap).unwrap_or(u64::max_value()),
@@ -803,12 +839,14 @@ impl<TSrc, TRq> WarpSync<TSrc, TRq> {
             RuntimeDownload::NotStarted { hint_doesnt_match },
             None,
             true,
+            false,
             None,
         ) = (
             &self.warped_block_ty,
             &self.runtime_download,
             self.warp_sync_fragments_download,
             self.verify_queue.is_empty(),
+            self.fragments_download_continuation,
             desired_warp_sync_request.peek(),
         ) {
             let code_key_to_request = if let (false, Some(hint)) =
@@ -851,6 +889,7 @@ impl<TSrc, TRq> WarpSync<TSrc, TRq> {
         let desired_call_proofs = if matches!(self.warped_block_ty, WarpedBlockTy::Normal)
             && self.warp_sync_fragments_download.is_none()
             && self.verify_queue.is_empty()
+            && !self.fragments_download_continuation
             && desired_warp_sync_request.peek().is_none()
         {
             either::Left(
@@ -1029,6 +1068,49 @@ impl<TSrc, TRq> WarpSync<TSrc, TRq> {
         user_data
     }
 
+    /// Removes the given request from the state machine because the source has refused to
+    /// negotiate the warp sync protocol. Returns the user data that was associated to it.
+    ///
+    /// No more warp sync request is emitted towards this source. If all the sources have
+    /// refused the warp sync protocol, the state machine stops downloading fragments and
+    /// instead downloads the runtime and chain information of the latest block that has been
+    /// warp synced to, or of the starting point if no fragment has been downloaded. See also
+    /// [`WarpSync::is_header_sync_fallback`].
+    ///
+    /// # Panic
+    ///
+    /// Panics if the [`RequestId`] is invalid.
+    ///
+    pub fn warp_sync_request_unsupported(&mut self, id: RequestId) -> TRq {
+        let source_id = self.in_progress_requests[id.0].0;
+        self.sources[source_id.0].warp_sync_unsupported = true;
+
+        let user_data = self.fail_request(id);
+
+        if !self.header_sync_fallback
+            && self.verify_queue.is_empty()
+            && self.warp_sync_fragments_download.is_none()
+            && self.sources.iter().all(|(_, s)| s.warp_sync_unsupported)
+        {
+            self.header_sync_fallback = true;
+            self.fragments_download_continuation = false;
+            if matches!(self.warped_block_ty, WarpedBlockTy::AlreadyVerified) {
+                self.warped_block_ty = WarpedBlockTy::Normal;
+            }
+        }
+
+        user_data
+    }
+
+    /// Returns `true` if all the sources have refused to negotiate the warp sync protocol, and
+    /// that the state machine has thus stopped downloading fragments. See
+    /// [`WarpSync::warp_sync_request_unsupported`].
+    ///
+    /// Adding a new source through [`WarpSync::add_source`] resets this to `false`.
+    pub fn is_header_sync_fallback(&self) -> bool {
+        self.header_sync_fallback
+    }
+
     /// Injects a successful Merkle proof and removes the given request from the state machine.
     /// Returns the user data that was associated to it.
     ///
@@ -1187,6 +1269,11 @@ impl<TSrc, TRq> WarpSync<TSrc, TRq> {
         if self.warp_sync_fragments_download == Some(request_id) {
             self.warp_sync_fragments_download = None;
 
+            // A response that isn't final but doesn't contain any fragment can't be continued,
+            // as there is no header to continue from. It is treated the same way as a final
+            // response in order to avoid repeatedly sending the same request.
+            self.fragments_download_continuation = !final_set_of_fragments && !fragments.is_empty();
+
             self.verify_queue.push_back(PendingVerify {
                 final_set_of_fragments,
                 downloaded_source: Some(rq_source_id),
@@ -1430,6 +1517,7 @@ impl<TSrc, TRq> VerifyWarpSyncFragment<TSrc, TRq> {
                     self.inner.sources[source_id].finalized_block_height = Err(());
                 }
                 self.inner.verify_queue.clear();
+                self.inner.fragments_download_continuation = false;
                 self.inner.warp_sync_fragments_download = None;
                 return (self.inner, Err(VerifyFragmentError::InvalidHeader(err)));
             }
@@ -1444,6 +1532,7 @@ impl<TSrc, TRq> VerifyWarpSyncFragment<TSrc, TRq> {
                     self.inner.sources[source_id].finalized_block_height = Err(());
                 }
                 self.inner.verify_queue.clear();
+                self.inner.fragments_download_continuation = false;
                 self.inner.warp_sync_fragments_download = None;
                 return (
                     self.inner,
@@ -1458,6 +1547,7 @@ impl<TSrc, TRq> VerifyWarpSyncFragment<TSrc, TRq> {
                 self.inner.sources[source_id].finalized_block_height = Err(());
             }
             self.inner.verify_queue.clear();
+            self.inner.fragments_download_continuation = false;
             self.inner.warp_sync_fragments_download = None;
             return (
                 self.inner,
@@ -1479,6 +1569,7 @@ impl<TSrc, TRq> VerifyWarpSyncFragment<TSrc, TRq> {
                 self.inner.sources[source_id].finalized_block_height = Err(());
             }
             self.inner.verify_queue.clear();
+            self.inner.fragments_download_continuation = false;
             self.inner.warp_sync_fragments_download = None;
             return (self.inner, Err(error));
         }
@@ -1497,6 +1588,7 @@ impl<TSrc, TRq> VerifyWarpSyncFragment<TSrc, TRq> {
                 self.inner.sources[source_id].finalized_block_height = Err(());
             }
             self.inner.verify_queue.clear();
+            self.inner.fragments_download_continuation = false;
             self.inner.warp_sync_fragments_download = None;
             return (
                 self.inner,
@@ -1536,6 +1628,7 @@ impl<TSrc, TRq> VerifyWarpSyncFragment<TSrc, TRq> {
                 self.inner.sources[source_id].finalized_block_height = Err(());
             }
             self.inner.verify_queue.clear();
+            self.inner.fragments_download_continuation = false;
             self.inner.warp_sync_fragments_download = None;
             return (self.inner, Err(VerifyFragmentError::NonMinimalProof));
         }
@@ -2144,3 +2237,171 @@ fn parameters_equal(mut a: &[u8], b: impl Iterator<Item = impl AsRef<[u8]>>) ->
 
     true
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{Config, DesiredRequest, RequestDetail, WarpSync, WarpSyncFragment};
+    use crate::{chain::chain_information, header};
+    use alloc::{boxed::Box, vec::Vec};
+    use core::num::NonZeroU64;
+
+    fn start() -> WarpSync<(), ()> {
+        let chain_information = chain_information::ChainInformation {
+            finalized_block_header: Box::new(header::Header {
+                parent_hash: [0; 32],
+                number: 0,
+                state_root: [1; 32],
+                extrinsics_root: [2; 32],
+                digest: header::Digest::from(header::DigestRef::empty()),
+            }),
+            consensus: chain_information::ChainInformationConsensus::Aura {
+                finalized_authorities_list: Vec::new(),
+                slot_duration: NonZeroU64::new(6000).unwrap(),
+            },
+            finality: chain_information::ChainInformationFinality::Grandpa {
+                after_finalized_block_authorities_set_id: 0,
+                finalized_triggered_authorities: Vec::new(),
+                finalized_scheduled_change: None,
+            },
+        };
+
+        super::start_warp_sync(Config {
+            start_chain_information: chain_information.try_into().unwrap(),
+            block_number_bytes: 4,
+            sources_capacity: 8,
+            requests_capacity: 8,
+            code_trie_node_hint: None,
+            num_download_ahead_fragments: 128,
+            warp_sync_minimum_gap: 32,
+        })
+        .unwrap_or_else(|_| panic!())
+    }
+
+    fn fragment(parent_hash: [u8; 32], number: u64) -> WarpSyncFragment {
+        WarpSyncFragment {
+            scale_encoded_header: header::Header {
+                parent_hash,
+                number,
+                state_root: [1; 32],
+                extrinsics_root: [2; 32],
+                digest: header::Digest::from(header::DigestRef::empty()),
+            }
+            .scale_encoding_vec(4),
+            scale_encoded_justification: Vec::new(),
+        }
+    }
+
+    fn start_warp_sync_request(
+        sync: &mut WarpSync<(), ()>,
+    ) -> Option<(super::SourceId, super::RequestId)> {
+        let (source_id, block_hash) =
+            sync.desired_requests()
+                .find_map(|(source_id, _, request)| match request {
+                    DesiredRequest::WarpSyncRequest { block_hash } => Some((source_id, block_hash)),
+                    _ => None,
+                })?;
+        let request_id =
+            sync.add_request(source_id, (), RequestDetail::WarpSyncRequest { block_hash });
+        Some((source_id, request_id))
+    }
+
+    #[test]
+    fn header_sync_fallback_when_all_sources_refuse() {
+        let mut sync = start();
+
+        let source1 = sync.add_source(());
+        sync.set_source_finality_state(source1, 1000);
+        let source2 = sync.add_source(());
+        sync.set_source_finality_state(source2, 1000);
+
+        let (refused1, request1) = start_warp_sync_request(&mut sync).unwrap();
+        sync.warp_sync_request_unsupported(request1);
+        assert!(!sync.is_header_sync_fallback());
+
+        // The source that has refused isn't asked again.
+        let (refused2, request2) = start_warp_sync_request(&mut sync).unwrap();
+        assert_ne!(refused1, refused2);
+        sync.warp_sync_request_unsupported(request2);
+        assert!(sync.is_header_sync_fallback());
+        assert!(start_warp_sync_request(&mut sync).is_none());
+    }
+
+    #[test]
+    fn header_sync_fallback_reset_by_new_source() {
+        let mut sync = start();
+
+        let source1 = sync.add_source(());
+        sync.set_source_finality_state(source1, 1000);
+        let (_, request) = start_warp_sync_request(&mut sync).unwrap();
+        sync.warp_sync_request_unsupported(request);
+        assert!(sync.is_header_sync_fallback());
+
+        let source2 = sync.add_source(());
+        sync.set_source_finality_state(source2, 1000);
+        assert!(!sync.is_header_sync_fallback());
+
+        let (source_id, _) = start_warp_sync_request(&mut sync).unwrap();
+        assert_eq!(source_id, source2);
+    }
+
+    #[test]
+    fn fragments_download_continuation() {
+        let mut sync = start();
+        let genesis_hash = sync.warped_header_hash;
+
+        // The finalized block of the source is within `warp_sync_minimum_gap` of the end of the
+        // first response, meaning that only the continuation can trigger a second request.
+        let source_id = sync.add_source(());
+        sync.set_source_finality_state(source_id, 210);
+
+        let (_, request1) = start_warp_sync_request(&mut sync).unwrap();
+        assert!(matches!(
+            sync.in_progress_requests[request1.0].2,
+            RequestDetail::WarpSyncRequest { block_hash } if block_hash == genesis_hash
+        ));
+
+        let fragment100 = fragment(genesis_hash, 100);
+        let hash100 = header::hash_from_scale_encoded_header(&fragment100.scale_encoded_header);
+        let fragment200 = fragment(hash100, 200);
+        let hash200 = header::hash_from_scale_encoded_header(&fragment200.scale_encoded_header);
+        sync.warp_sync_request_success(request1, vec![fragment100, fragment200], false);
+        assert!(sync.fragments_download_continuation);
+
+        // The second request starts from the last header of the first response.
+        let (_, request2) = start_warp_sync_request(&mut sync).unwrap();
+        assert!(matches!(
+            sync.in_progress_requests[request2.0].2,
+            RequestDetail::WarpSyncRequest { block_hash } if block_hash == hash200
+        ));
+
+        let fragment205 = fragment(hash200, 205);
+        sync.warp_sync_request_success(request2, vec![fragment205], true);
+        assert!(!sync.fragments_download_continuation);
+
+        // The fragments of both responses are queued for verification one after the other.
+        let queued = sync
+            .verify_queue
+            .iter()
+            .map(|entry| {
+                (
+                    entry
+                        .fragments
+                        .iter()
+                        .map(|f| header::decode(&f.scale_encoded_header, 4).unwrap().number)
+                        .collect::<Vec<_>>(),
+                    entry.final_set_of_fragments,
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(queued, vec![(vec![100, 200], false), (vec![205], true)]);
+        assert_eq!(
+            header::decode(&sync.verify_queue[1].fragments[0].scale_encoded_header, 4)
+                .unwrap()
+                .parent_hash,
+            &hash200
+        );
+
+        // The proof is complete, and the source is too close to request anything else.
+        assert!(start_warp_sync_request(&mut sync).is_none());
+    }
+}
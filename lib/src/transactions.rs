@@ -87,4 +87,5 @@
 
 pub mod light_pool;
 pub mod pool;
+pub mod signed_extensions;
 pub mod validate;
@@ -0,0 +1,503 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Introspection of the signed extensions of a chain.
+//!
+//! A signed transaction consists in a call, a signature, and a list of so-called
+//! *signed extensions*. Each signed extension can add two kinds of payloads:
+//!
+//! - An *explicit* payload, that is included in the transaction itself. For example the nonce
+//! of the account, or the tip paid to the block author.
+//! - An *implicit* payload, that isn't included in the transaction but that is part of the
+//! payload that gets signed. For example the genesis hash of the chain.
+//!
+//! The list of signed extensions and their order depend on the runtime. This module decodes the
+//! runtime metadata, as returned by the `Metadata_metadata` runtime function, and extracts the
+//! list of signed extensions. This makes it possible to build or analyze transactions in a
+//! chain-agnostic way.
+//!
+//! Only versions 14 and 15 of the metadata format are supported.
+//!
+//! # Usage
+//!
+//! Call [`decode`] with the metadata, after its length prefix has been removed (see
+//! [`crate::json_rpc::methods::remove_metadata_length_prefix`]).
+//!
+//! Signed extensions whose identifier is known are reported with a [`SignedExtensionKind`]
+//! other than [`SignedExtensionKind::Unknown`], in which case the format of their payloads is
+//! known. The payloads of the signed extensions whose identifier is unknown can still be
+//! built if they are empty, as indicated by [`SignedExtension::explicit_is_empty`] and
+//! [`SignedExtension::implicit_is_empty`].
+
+use crate::util;
+
+use alloc::vec::Vec;
+use core::str;
+
+/// Decodes the given metadata and extracts the list of signed extensions.
+///
+/// The metadata must start with the `meta` magic number, in other words its length prefix must
+/// have been removed.
+pub fn decode(metadata: &[u8]) -> Result<SignedExtensions<'_>, DecodeError> {
+    let (after_header, version) = match nom::sequence::preceded(
+        nom::bytes::streaming::tag::<_, _, nom::error::Error<&[u8]>>(&b"meta"[..]),
+        nom::number::streaming::u8,
+    )(metadata)
+    {
+        Ok(v) => v,
+        Err(_) => return Err(DecodeError::InvalidMagicNumber),
+    };
+
+    let is_v15 = match version {
+        14 => false,
+        15 => true,
+        v => return Err(DecodeError::UnsupportedVersion(v)),
+    };
+
+    let (_, (types, extrinsic_version, extensions)) =
+        decode_metadata::<nom::error::Error<&[u8]>>(is_v15)(after_header)
+            .map_err(|_| DecodeError::ParseError)?;
+
+    let extensions = extensions
+        .into_iter()
+        .map(|(identifier, explicit_type_id, implicit_type_id)| {
+            Ok(SignedExtension {
+                identifier,
+                kind: SignedExtensionKind::from_identifier(identifier),
+                explicit_type_id,
+                explicit_is_empty: is_zero_sized(&types, explicit_type_id, 0)?,
+                implicit_type_id,
+                implicit_is_empty: is_zero_sized(&types, implicit_type_id, 0)?,
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(SignedExtensions {
+        extrinsic_version,
+        extensions,
+    })
+}
+
+/// Error potentially returned by [`decode`].
+#[derive(Debug, Clone, derive_more::Display)]
+pub enum DecodeError {
+    /// Metadata doesn't start with the expected magic number.
+    InvalidMagicNumber,
+    /// Version of the metadata format isn't supported.
+    #[display(fmt = "Unsupported metadata version: {_0}")]
+    UnsupportedVersion(u8),
+    /// Failed to parse the metadata.
+    ParseError,
+    /// A signed extension refers to a type that isn't in the types registry.
+    #[display(fmt = "Unknown type identifier: {_0}")]
+    UnknownTypeId(u32),
+}
+
+/// Signed extensions of a chain. See [`decode`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignedExtensions<'a> {
+    /// Version of the format of the transactions. Transactions must be encoded using this
+    /// version.
+    pub extrinsic_version: u8,
+
+    /// List of signed extensions, in the order in which their payloads must be encoded.
+    pub extensions: Vec<SignedExtension<'a>>,
+}
+
+/// Description of a signed extension.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignedExtension<'a> {
+    /// Name of the signed extension, as found in the metadata.
+    pub identifier: &'a str,
+
+    /// Kind of signed extension, deduced from [`SignedExtension::identifier`].
+    pub kind: SignedExtensionKind,
+
+    /// Identifier, in the types registry of the metadata, of the type of the payload that is
+    /// included in the transaction.
+    pub explicit_type_id: u32,
+
+    /// `true` if the payload that is included in the transaction is always empty.
+    pub explicit_is_empty: bool,
+
+    /// Identifier, in the types registry of the metadata, of the type of the payload that isn't
+    /// included in the transaction but that is part of the signed payload.
+    pub implicit_type_id: u32,
+
+    /// `true` if the payload that isn't included in the transaction but that is part of the
+    /// signed payload is always empty.
+    pub implicit_is_empty: bool,
+}
+
+/// Kind of a signed extension.
+///
+/// The format of the payloads indicated below is the one of the Substrate implementation of
+/// these signed extensions. Runtimes are free to use the same identifier for a different
+/// format, which is why [`SignedExtension::explicit_type_id`] and
+/// [`SignedExtension::implicit_type_id`] should be verified if in doubt.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum SignedExtensionKind {
+    /// Verifies that the sender isn't the zero account. Both payloads are empty.
+    CheckNonZeroSender,
+    /// Implicit payload is the `u32` specification version of the runtime.
+    CheckSpecVersion,
+    /// Implicit payload is the `u32` transaction version of the runtime.
+    CheckTxVersion,
+    /// Implicit payload is the hash of the genesis block.
+    CheckGenesis,
+    /// Explicit payload is the era of the transaction. Implicit payload is the hash of the
+    /// block where the era starts, or of the genesis block for immortal transactions.
+    CheckMortality,
+    /// Explicit payload is the SCALE-compact-encoded nonce of the sender.
+    CheckNonce,
+    /// Verifies the weight of the transaction. Both payloads are empty.
+    CheckWeight,
+    /// Explicit payload is the SCALE-compact-encoded tip paid to the block author.
+    ChargeTransactionPayment,
+    /// Explicit payload is the SCALE-compact-encoded tip paid to the block author, followed
+    /// with an optional identifier of the asset used to pay the fees.
+    ChargeAssetTxPayment,
+    /// Explicit payload is a byte indicating whether the metadata hash is verified. Implicit
+    /// payload is an optional 32 bytes hash of the metadata.
+    CheckMetadataHash,
+    /// Signed extension not known by this module.
+    Unknown,
+}
+
+impl SignedExtensionKind {
+    fn from_identifier(identifier: &str) -> Self {
+        match identifier {
+            "CheckNonZeroSender" => SignedExtensionKind::CheckNonZeroSender,
+            "CheckSpecVersion" => SignedExtensionKind::CheckSpecVersion,
+            "CheckTxVersion" => SignedExtensionKind::CheckTxVersion,
+            "CheckGenesis" => SignedExtensionKind::CheckGenesis,
+            "CheckMortality" | "CheckEra" => SignedExtensionKind::CheckMortality,
+            "CheckNonce" => SignedExtensionKind::CheckNonce,
+            "CheckWeight" => SignedExtensionKind::CheckWeight,
+            "ChargeTransactionPayment" => SignedExtensionKind::ChargeTransactionPayment,
+            "ChargeAssetTxPayment" => SignedExtensionKind::ChargeAssetTxPayment,
+            "CheckMetadataHash" => SignedExtensionKind::CheckMetadataHash,
+            _ => SignedExtensionKind::Unknown,
+        }
+    }
+}
+
+/// Subset of the information about a type of the types registry that is relevant to determine
+/// whether this type is zero-sized.
+enum TypeDef {
+    /// Structure or tuple whose fields have the given types.
+    Fields(Vec<u32>),
+    /// Fixed-size array.
+    Array { len: u32, item_type_id: u32 },
+    /// Any other type, which is never zero-sized.
+    Other,
+}
+
+/// Maximum number of nested types to go through when determining whether a type is zero-sized.
+/// Types nested deeper than this are considered as not zero-sized.
+const MAX_TYPE_DEPTH: u32 = 32;
+
+/// Returns `true` if the type with the given identifier always has an empty SCALE encoding.
+fn is_zero_sized(types: &[(u32, TypeDef)], type_id: u32, depth: u32) -> Result<bool, DecodeError> {
+    if depth >= MAX_TYPE_DEPTH {
+        return Ok(false);
+    }
+
+    // Types are normally ordered by identifier, but this isn't guaranteed.
+    let type_def = match types.get(usize::try_from(type_id).unwrap_or(usize::max_value())) {
+        Some((id, def)) if *id == type_id => def,
+        _ => match types.iter().find(|(id, _)| *id == type_id) {
+            Some((_, def)) => def,
+            None => return Err(DecodeError::UnknownTypeId(type_id)),
+        },
+    };
+
+    match type_def {
+        TypeDef::Fields(fields) => {
+            for field in fields {
+                if !is_zero_sized(types, *field, depth + 1)? {
+                    return Ok(false);
+                }
+            }
+            Ok(true)
+        }
+        TypeDef::Array { len: 0, .. } => Ok(true),
+        TypeDef::Array { item_type_id, .. } => is_zero_sized(types, *item_type_id, depth + 1),
+        TypeDef::Other => Ok(false),
+    }
+}
+
+/// Decodes the metadata that follows the magic number and version. Stops after the extrinsic
+/// information, as the rest isn't needed.
+///
+/// Returns the types registry, the extrinsic version, and the list of signed extensions
+/// (identifier, explicit payload type, implicit payload type).
+#[allow(clippy::type_complexity)]
+fn decode_metadata<'a, E>(
+    is_v15: bool,
+) -> impl FnMut(&'a [u8]) -> nom::IResult<&'a [u8], (Vec<(u32, TypeDef)>, u8, Vec<(&'a str, u32, u32)>), E>
+where
+    E: nom::error::ParseError<&'a [u8]> + nom::error::FromExternalError<&'a [u8], str::Utf8Error>,
+{
+    move |bytes| {
+        let (bytes, types) =
+            nom::multi::length_count(util::nom_scale_compact_usize, registry_type)(bytes)?;
+        let (bytes, _) =
+            nom::multi::length_count(util::nom_scale_compact_usize, pallet(is_v15))(bytes)?;
+
+        let (bytes, extrinsic_version) = if is_v15 {
+            // Version, followed with the address, call, signature, and extra types.
+            let (bytes, version) = nom::number::streaming::u8(bytes)?;
+            let (bytes, _) = nom::multi::count(compact_u32, 4)(bytes)?;
+            (bytes, version)
+        } else {
+            nom::sequence::preceded(compact_u32, nom::number::streaming::u8)(bytes)?
+        };
+
+        let (bytes, extensions) = nom::multi::length_count(
+            util::nom_scale_compact_usize,
+            nom::sequence::tuple((util::nom_string_decode, compact_u32, compact_u32)),
+        )(bytes)?;
+
+        Ok((bytes, (types, extrinsic_version, extensions)))
+    }
+}
+
+fn registry_type<'a, E>(bytes: &'a [u8]) -> nom::IResult<&'a [u8], (u32, TypeDef), E>
+where
+    E: nom::error::ParseError<&'a [u8]> + nom::error::FromExternalError<&'a [u8], str::Utf8Error>,
+{
+    let (bytes, id) = compact_u32(bytes)?;
+    // Path of the type.
+    let (bytes, _) = strings_list(bytes)?;
+    // Generic parameters of the type.
+    let (bytes, _) = nom::multi::length_count(
+        util::nom_scale_compact_usize,
+        nom::sequence::tuple((
+            util::nom_string_decode,
+            util::nom_option_decode(compact_u32),
+        )),
+    )(bytes)?;
+
+    let (bytes, type_def) = match nom::number::streaming::u8(bytes)? {
+        (bytes, 0) => nom::combinator::map(
+            nom::multi::length_count(util::nom_scale_compact_usize, field),
+            TypeDef::Fields,
+        )(bytes)?,
+        (bytes, 1) => nom::combinator::map(
+            nom::multi::length_count(
+                util::nom_scale_compact_usize,
+                nom::sequence::tuple((
+                    util::nom_string_decode,
+                    nom::multi::length_count(util::nom_scale_compact_usize, field),
+                    nom::number::streaming::u8,
+                    strings_list,
+                )),
+            ),
+            |_| TypeDef::Other,
+        )(bytes)?,
+        (bytes, 2) => nom::combinator::map(compact_u32, |_| TypeDef::Other)(bytes)?,
+        (bytes, 3) => nom::combinator::map(
+            nom::sequence::tuple((nom::number::streaming::le_u32, compact_u32)),
+            |(len, item_type_id)| TypeDef::Array { len, item_type_id },
+        )(bytes)?,
+        (bytes, 4) => nom::combinator::map(
+            nom::multi::length_count(util::nom_scale_compact_usize, compact_u32),
+            TypeDef::Fields,
+        )(bytes)?,
+        (bytes, 5) => nom::combinator::map(
+            nom::combinator::verify(nom::number::streaming::u8, |p| *p <= 15),
+            |_| TypeDef::Other,
+        )(bytes)?,
+        (bytes, 6) => nom::combinator::map(compact_u32, |_| TypeDef::Other)(bytes)?,
+        (bytes, 7) => {
+            nom::combinator::map(nom::sequence::tuple((compact_u32, compact_u32)), |_| {
+                TypeDef::Other
+            })(bytes)?
+        }
+        (bytes, _) => {
+            return Err(nom::Err::Error(nom::error::make_error(
+                bytes,
+                nom::error::ErrorKind::Tag,
+            )))
+        }
+    };
+
+    // Documentation of the type.
+    let (bytes, _) = strings_list(bytes)?;
+
+    Ok((bytes, (id, type_def)))
+}
+
+/// Decodes a field of a structure or enum variant. Returns the type of the field.
+fn field<'a, E>(bytes: &'a [u8]) -> nom::IResult<&'a [u8], u32, E>
+where
+    E: nom::error::ParseError<&'a [u8]> + nom::error::FromExternalError<&'a [u8], str::Utf8Error>,
+{
+    nom::combinator::map(
+        nom::sequence::tuple((
+            util::nom_option_decode(util::nom_string_decode),
+            compact_u32,
+            util::nom_option_decode(util::nom_string_decode),
+            strings_list,
+        )),
+        |(_, ty, _, _)| ty,
+    )(bytes)
+}
+
+/// Decodes and discards the information about a pallet.
+fn pallet<'a, E>(is_v15: bool) -> impl FnMut(&'a [u8]) -> nom::IResult<&'a [u8], (), E>
+where
+    E: nom::error::ParseError<&'a [u8]> + nom::error::FromExternalError<&'a [u8], str::Utf8Error>,
+{
+    move |bytes| {
+        let (bytes, _name) = util::nom_string_decode(bytes)?;
+        let (bytes, _storage) = util::nom_option_decode(nom::sequence::tuple((
+            util::nom_string_decode,
+            nom::multi::length_count(util::nom_scale_compact_usize, storage_entry),
+        )))(bytes)?;
+        // Calls and events.
+        let (bytes, _) = nom::multi::count(util::nom_option_decode(compact_u32), 2)(bytes)?;
+        let (bytes, _constants) = nom::multi::length_count(
+            util::nom_scale_compact_usize,
+            nom::sequence::tuple((
+                util::nom_string_decode,
+                compact_u32,
+                util::nom_bytes_decode,
+                strings_list,
+            )),
+        )(bytes)?;
+        let (bytes, _error) = util::nom_option_decode(compact_u32)(bytes)?;
+        let (bytes, _index) = nom::number::streaming::u8(bytes)?;
+        let (bytes, _) = if is_v15 {
+            nom::combinator::map(strings_list, |_| ())(bytes)?
+        } else {
+            (bytes, ())
+        };
+        Ok((bytes, ()))
+    }
+}
+
+/// Decodes and discards a storage entry of a pallet.
+fn storage_entry<'a, E>(bytes: &'a [u8]) -> nom::IResult<&'a [u8], (), E>
+where
+    E: nom::error::ParseError<&'a [u8]> + nom::error::FromExternalError<&'a [u8], str::Utf8Error>,
+{
+    let (bytes, _name) = util::nom_string_decode(bytes)?;
+    let (bytes, _modifier) = nom::number::streaming::u8(bytes)?;
+    let (bytes, _) = match nom::number::streaming::u8(bytes)? {
+        (bytes, 0) => nom::combinator::map(compact_u32, |_| ())(bytes)?,
+        (bytes, 1) => nom::combinator::map(
+            nom::sequence::tuple((util::nom_bytes_decode, compact_u32, compact_u32)),
+            |_| (),
+        )(bytes)?,
+        (bytes, _) => {
+            return Err(nom::Err::Error(nom::error::make_error(
+                bytes,
+                nom::error::ErrorKind::Tag,
+            )))
+        }
+    };
+    let (bytes, _default) = util::nom_bytes_decode(bytes)?;
+    let (bytes, _docs) = strings_list(bytes)?;
+    Ok((bytes, ()))
+}
+
+/// Decodes and discards a SCALE-encoded list of strings.
+fn strings_list<'a, E>(bytes: &'a [u8]) -> nom::IResult<&'a [u8], (), E>
+where
+    E: nom::error::ParseError<&'a [u8]> + nom::error::FromExternalError<&'a [u8], str::Utf8Error>,
+{
+    let (mut bytes, num_strings) = util::nom_scale_compact_usize(bytes)?;
+    for _ in 0..num_strings {
+        bytes = util::nom_string_decode(bytes)?.0;
+    }
+    Ok((bytes, ()))
+}
+
+fn compact_u32<'a, E: nom::error::ParseError<&'a [u8]>>(
+    bytes: &'a [u8],
+) -> nom::IResult<&'a [u8], u32, E> {
+    nom::combinator::map_opt(util::nom_scale_compact_usize, |n| u32::try_from(n).ok())(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SignedExtensionKind;
+
+    #[test]
+    fn basic_decode() {
+        let mut metadata = b"meta".to_vec();
+        metadata.push(14);
+
+        // Types registry.
+        metadata.push(3 << 2);
+        // Type 0: empty structure.
+        metadata.extend_from_slice(&[0, 0, 0, 0, 0, 0]);
+        // Type 1: `u32`.
+        metadata.extend_from_slice(&[1 << 2, 0, 0, 5, 5, 0]);
+        // Type 2: tuple containing an empty structure.
+        metadata.extend_from_slice(&[2 << 2, 0, 0, 4, 1 << 2, 0, 0]);
+
+        // Pallets.
+        metadata.push(0);
+
+        // Extrinsic.
+        metadata.extend_from_slice(&[0, 4, 2 << 2]);
+        metadata.push(10 << 2);
+        metadata.extend_from_slice(b"CheckNonce");
+        metadata.extend_from_slice(&[1 << 2, 2 << 2]);
+        metadata.push(7 << 2);
+        metadata.extend_from_slice(b"Foo_Bar");
+        metadata.extend_from_slice(&[0, 1 << 2]);
+
+        // Runtime type, ignored.
+        metadata.push(0);
+
+        let decoded = super::decode(&metadata).unwrap();
+        assert_eq!(decoded.extrinsic_version, 4);
+        assert_eq!(decoded.extensions.len(), 2);
+
+        assert_eq!(decoded.extensions[0].identifier, "CheckNonce");
+        assert_eq!(decoded.extensions[0].kind, SignedExtensionKind::CheckNonce);
+        assert!(!decoded.extensions[0].explicit_is_empty);
+        assert!(decoded.extensions[0].implicit_is_empty);
+
+        assert_eq!(decoded.extensions[1].identifier, "Foo_Bar");
+        assert_eq!(decoded.extensions[1].kind, SignedExtensionKind::Unknown);
+        assert!(decoded.extensions[1].explicit_is_empty);
+        assert!(!decoded.extensions[1].implicit_is_empty);
+    }
+
+    #[test]
+    fn unknown_type_id() {
+        let mut metadata = b"meta".to_vec();
+        metadata.extend_from_slice(&[14, 0, 0, 0, 4, 1 << 2, 1 << 2, b'A', 5 << 2, 5 << 2]);
+        assert!(matches!(
+            super::decode(&metadata),
+            Err(super::DecodeError::UnknownTypeId(5))
+        ));
+    }
+
+    #[test]
+    fn unsupported_version() {
+        assert!(matches!(
+            super::decode(b"meta\x0d"),
+            Err(super::DecodeError::UnsupportedVersion(13))
+        ));
+    }
+}
@@ -37,6 +37,9 @@
 //!
 //! Once decoded, one can examine the content of the proof, in other words the list of storage
 //! items and values.
+//!
+//! The [`decode_and_audit_proof`] function is similar to [`decode_and_verify_proof`], but accepts
+//! proofs that contain redundant entries and reports them instead of returning an error.
 
 use super::{nibble, trie_node, TrieEntryVersion};
 
@@ -62,6 +65,63 @@ pub fn decode_and_verify_proof<T>(config: Config<T>) -> Result<DecodedTrieProof<
 where
     T: AsRef<[u8]>,
 {
+    let (proof, _audit) = decode_and_verify_proof_inner(config, false)?;
+    debug_assert!(_audit.is_clean());
+    Ok(proof)
+}
+
+/// Similar to [`decode_and_verify_proof`], but tolerates proofs that contain duplicate entries or
+/// entries that are disconnected from the rest of the proof, and reports them in a
+/// [`ProofAudit`].
+///
+/// Some peers send proofs with redundant entries. While such proofs are still usable, the
+/// redundant entries might indicate an attempt at making the local node waste bandwidth and
+/// CPU. The [`ProofAudit`] makes it possible to take these entries into account when, for
+/// example, deciding whether to continue sending requests to the peer.
+///
+/// An error is still returned if the format of the proof or one of its trie nodes is invalid.
+pub fn decode_and_audit_proof<T>(
+    config: Config<T>,
+) -> Result<(DecodedTrieProof<T>, ProofAudit), Error>
+where
+    T: AsRef<[u8]>,
+{
+    decode_and_verify_proof_inner(config, true)
+}
+
+/// Decodes the list of entries of a proof, without verifying them.
+///
+/// A proof is a SCALE-encoded `Vec<Vec<u8>>`, each entry being either a trie node value or a
+/// standalone storage value. The entries are returned in the order in which they are found in
+/// the proof.
+///
+/// Returns an error if the format of the proof is invalid.
+pub fn decode_proof_entries(proof: &[u8]) -> Result<Vec<&[u8]>, Error> {
+    nom::combinator::all_consuming(nom::combinator::flat_map(
+        crate::util::nom_scale_compact_usize,
+        |num_elems| nom::multi::many_m_n(num_elems, num_elems, crate::util::nom_bytes_decode),
+    ))(proof)
+    .map(|(_, entries)| entries)
+    .map_err(|_: nom::Err<nom::error::Error<&[u8]>>| Error::InvalidFormat)
+}
+
+/// Implementation of [`decode_and_verify_proof`] and [`decode_and_audit_proof`].
+///
+/// If `tolerant` is `false`, returns an error if the proof contains a duplicate or an unused
+/// entry, in which case the returned [`ProofAudit`] is always clean.
+fn decode_and_verify_proof_inner<T>(
+    config: Config<T>,
+    tolerant: bool,
+) -> Result<(DecodedTrieProof<T>, ProofAudit), Error>
+where
+    T: AsRef<[u8]>,
+{
+    let mut audit = ProofAudit {
+        duplicate_entries: Vec::new(),
+        unused_entries: Vec::new(),
+        trie_roots: Vec::new(),
+    };
+
     // Call `as_ref()` once at the beginning in order to guarantee stability of the memory
     // location.
     let proof_as_ref = config.proof.as_ref();
@@ -80,49 +140,54 @@ where
     // the function actually take less time than if it was a legitimate proof.
     let merkle_values = {
         // TODO: don't use a Vec?
-        let (_, decoded_proof) = nom::combinator::all_consuming(nom::combinator::flat_map(
-            crate::util::nom_scale_compact_usize,
-            |num_elems| nom::multi::many_m_n(num_elems, num_elems, crate::util::nom_bytes_decode),
-        ))(config.proof.as_ref())
-        .map_err(|_: nom::Err<nom::error::Error<&[u8]>>| Error::InvalidFormat)?;
-
-        let merkle_values = decoded_proof
-            .iter()
-            .copied()
-            .enumerate()
-            .map(
-                |(proof_entry_num, proof_entry)| -> ([u8; 32], (usize, ops::Range<usize>)) {
-                    // The merkle value of a trie node is normally either its hash or the node
-                    // itself if its length is < 32. In the context of a proof, however, nodes
-                    // whose length is < 32 aren't supposed to be their own entry. For this reason,
-                    // we only hash each entry.
-                    let hash = *<&[u8; 32]>::try_from(
-                        blake2_rfc::blake2b::blake2b(32, &[], proof_entry).as_bytes(),
-                    )
-                    .unwrap();
+        let decoded_proof = decode_proof_entries(config.proof.as_ref())?;
+
+        let merkle_values_iter = decoded_proof.iter().copied().enumerate().map(
+            |(proof_entry_num, proof_entry)| -> ([u8; 32], (usize, ops::Range<usize>)) {
+                // The merkle value of a trie node is normally either its hash or the node
+                // itself if its length is < 32. In the context of a proof, however, nodes
+                // whose length is < 32 aren't supposed to be their own entry. For this reason,
+                // we only hash each entry.
+                let hash = *<&[u8; 32]>::try_from(
+                    blake2_rfc::blake2b::blake2b(32, &[], proof_entry).as_bytes(),
+                )
+                .unwrap();
 
-                    let proof_entry_offset = if proof_entry.is_empty() {
-                        0
-                    } else {
-                        proof_entry.as_ptr() as usize - proof_as_ref.as_ptr() as usize
-                    };
+                let proof_entry_offset = if proof_entry.is_empty() {
+                    0
+                } else {
+                    proof_entry.as_ptr() as usize - proof_as_ref.as_ptr() as usize
+                };
 
+                (
+                    hash,
                     (
-                        hash,
-                        (
-                            proof_entry_num,
-                            proof_entry_offset..(proof_entry_offset + proof_entry.len()),
-                        ),
-                    )
-                },
-            )
-            .collect::<hashbrown::HashMap<_, _, fnv::FnvBuildHasher>>();
+                        proof_entry_num,
+                        proof_entry_offset..(proof_entry_offset + proof_entry.len()),
+                    ),
+                )
+            },
+        );
 
         // Using a hashmap has the consequence that if multiple proof entries were identical, only
         // one would be tracked. For this reason, we make sure that the proof doesn't contain
-        // multiple identical entries.
-        if merkle_values.len() != decoded_proof.len() {
-            return Err(Error::DuplicateProofEntry);
+        // multiple identical entries, or keep track of them if `tolerant` is `true`.
+        let mut merkle_values = hashbrown::HashMap::with_capacity_and_hasher(
+            decoded_proof.len(),
+            fnv::FnvBuildHasher::default(),
+        );
+        for (hash, (proof_entry_num, proof_entry_range)) in merkle_values_iter {
+            match merkle_values.entry(hash) {
+                hashbrown::hash_map::Entry::Vacant(entry) => {
+                    entry.insert((proof_entry_num, proof_entry_range));
+                }
+                hashbrown::hash_map::Entry::Occupied(_) if tolerant => {
+                    audit.duplicate_entries.push(proof_entry_num);
+                }
+                hashbrown::hash_map::Entry::Occupied(_) => {
+                    return Err(Error::DuplicateProofEntry);
+                }
+            }
         }
 
         merkle_values
@@ -130,10 +195,13 @@ where
 
     // Dummy empty proofs are always valid.
     if merkle_values.is_empty() {
-        return Ok(DecodedTrieProof {
-            proof: config.proof,
-            entries: BTreeMap::new(),
-        });
+        return Ok((
+            DecodedTrieProof {
+                proof: config.proof,
+                entries: BTreeMap::new(),
+            },
+            audit,
+        ));
     }
 
     // Start by iterating over each element of the proof, and keep track of elements that are
@@ -157,6 +225,11 @@ where
         maybe_trie_roots
     };
 
+    if tolerant {
+        audit.trie_roots = trie_roots.iter().map(|hash| **hash).collect();
+        audit.trie_roots.sort_unstable();
+    }
+
     // The implementation below iterates down the tree of nodes represented by this proof, keeping
     // note of the traversed elements.
 
@@ -164,8 +237,10 @@ where
     let mut entries = BTreeMap::new();
 
     // Keep track of the proof entries that haven't been visited when traversing.
-    let mut unvisited_proof_entries =
-        (0..merkle_values.len()).collect::<hashbrown::HashSet<_, fnv::FnvBuildHasher>>();
+    let mut unvisited_proof_entries = merkle_values
+        .values()
+        .map(|(proof_entry_num, _)| *proof_entry_num)
+        .collect::<hashbrown::HashSet<_, fnv::FnvBuildHasher>>();
 
     // We repeat this operation for every trie root.
     for trie_root_hash in trie_roots {
@@ -317,13 +392,49 @@ where
     // The entire reason why we track the unvisited proof entries is to return this error if
     // necessary.
     if !unvisited_proof_entries.is_empty() {
-        return Err(Error::UnusedProofEntry);
+        if !tolerant {
+            return Err(Error::UnusedProofEntry);
+        }
+
+        audit.unused_entries = unvisited_proof_entries.into_iter().collect();
+        audit.unused_entries.sort_unstable();
     }
 
-    Ok(DecodedTrieProof {
-        proof: config.proof,
-        entries,
-    })
+    Ok((
+        DecodedTrieProof {
+            proof: config.proof,
+            entries,
+        },
+        audit,
+    ))
+}
+
+/// Report about the content of a proof, returned by [`decode_and_audit_proof`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProofAudit {
+    /// Indices within the proof of the entries that are identical to an entry found earlier in
+    /// the proof, in increasing order.
+    pub duplicate_entries: Vec<usize>,
+
+    /// Indices within the proof of the entries that aren't reachable from any of the trie roots
+    /// of the proof, in increasing order.
+    pub unused_entries: Vec<usize>,
+
+    /// Hashes of the trie root nodes found in the proof, in increasing order.
+    ///
+    /// A proof can legitimately contain multiple trie roots, for example when it concerns child
+    /// tries. Trie roots that the API user isn't interested in are however extraneous.
+    pub trie_roots: Vec<[u8; 32]>,
+}
+
+impl ProofAudit {
+    /// Returns `true` if the proof doesn't contain any duplicate or unused entry.
+    ///
+    /// > **Note**: Extraneous trie roots aren't taken into account, as this function can't know
+    /// >           which trie roots are expected.
+    pub fn is_clean(&self) -> bool {
+        self.duplicate_entries.is_empty() && self.unused_entries.is_empty()
+    }
 }
 
 /// Equivalent to [`StorageValue`] but contains offsets indexing [`DecodedTrieProof::proof`].
@@ -816,6 +927,37 @@ impl<T: AsRef<[u8]>> DecodedTrieProof<T> {
         }
     }
 
+    /// Sorts the given list of keys depending on whether the proof proves that they have a
+    /// storage value, proves that they don't have a storage value, or is incomplete.
+    ///
+    /// This makes it possible for the API user to use the parts of a proof that are usable, rather
+    /// than considering a proof as unusable as soon as one of the requested keys can't be found
+    /// in it.
+    ///
+    /// > **Note**: This function is a convenient wrapper around
+    /// >           [`DecodedTrieProof::storage_value`].
+    pub fn keys_summary<K: AsRef<[u8]>>(
+        &self,
+        trie_root_merkle_value: &[u8; 32],
+        keys: impl IntoIterator<Item = K>,
+    ) -> KeysSummary<K> {
+        let mut summary = KeysSummary {
+            proven_present: Vec::new(),
+            proven_absent: Vec::new(),
+            unproven: Vec::new(),
+        };
+
+        for key in keys {
+            match self.storage_value(trie_root_merkle_value, key.as_ref()) {
+                Ok(Some(_)) => summary.proven_present.push(key),
+                Ok(None) => summary.proven_absent.push(key),
+                Err(IncompleteProofError()) => summary.unproven.push(key),
+            }
+        }
+
+        summary
+    }
+
     /// Find in the proof the trie node that follows `key_before` in lexicographic order.
     ///
     /// If `or_equal` is `true`, then `key_before` is returned if it is equal to a node in the
@@ -1024,6 +1166,17 @@ impl<T: AsRef<[u8]>> DecodedTrieProof<T> {
     }
 }
 
+/// See [`DecodedTrieProof::keys_summary`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeysSummary<K> {
+    /// Keys whose storage value is found in the proof.
+    pub proven_present: Vec<K>,
+    /// Keys that are proven to not have any storage value.
+    pub proven_absent: Vec<K>,
+    /// Keys whose storage value can't be determined because the proof is incomplete.
+    pub unproven: Vec<K>,
+}
+
 /// Proof doesn't contain enough information to answer the request.
 #[derive(Debug, Clone, derive_more::Display)]
 pub struct IncompleteProofError();
@@ -1075,7 +1228,7 @@ impl<'a> fmt::Debug for StorageValue<'a> {
     }
 }
 
-/// Possible error returned by [`decode_and_verify_proof`].
+/// Possible error returned by [`decode_and_verify_proof`] and [`decode_and_audit_proof`].
 #[derive(Debug, Clone, derive_more::Display)]
 pub enum Error {
     /// Proof is in an invalid format.
@@ -1212,6 +1365,16 @@ mod tests {
         let _ = super::decode_and_verify_proof(super::Config { proof: &[0] }).unwrap();
     }
 
+    #[test]
+    fn decode_proof_entries_works() {
+        assert!(super::decode_proof_entries(&[0]).unwrap().is_empty());
+        assert_eq!(
+            super::decode_proof_entries(&[8, 4, 1, 0]).unwrap(),
+            vec![&[1u8][..], &[][..]]
+        );
+        assert!(super::decode_proof_entries(&[8, 4, 1]).is_err());
+    }
+
     #[test]
     fn basic_works() {
         // Key/value taken from the Polkadot genesis block.
@@ -1419,6 +1582,50 @@ mod tests {
             .is_ok());
     }
 
+    #[test]
+    fn keys_summary_works() {
+        // Root node with two children `0x00` and `0x10` whose storage value is `0x6869`.
+        let proof = super::decode_and_verify_proof(super::Config {
+            proof: &[
+                4, 60, 128, 3, 0, 20, 65, 0, 8, 104, 105, 20, 65, 0, 8, 104, 105,
+            ],
+        })
+        .unwrap();
+
+        let summary = proof.keys_summary(
+            &[
+                15, 224, 134, 90, 11, 145, 174, 197, 185, 253, 233, 197, 95, 101, 197, 10, 78, 28,
+                137, 217, 102, 198, 242, 100, 90, 96, 9, 204, 213, 69, 174, 4,
+            ],
+            [&[0x00][..], &[0x20][..], &[0x10][..], &[0x00, 0x00][..]],
+        );
+        assert_eq!(summary.proven_present, [&[0x00][..], &[0x10][..]]);
+        assert_eq!(summary.proven_absent, [&[0x20][..], &[0x00, 0x00][..]]);
+        assert!(summary.unproven.is_empty());
+
+        // Root node with two children `0x0` and `0x1` that aren't part of the proof.
+        let proof = super::decode_and_verify_proof(super::Config {
+            proof: &[
+                4, 21, 1, 128, 3, 0, 128, 205, 154, 249, 23, 88, 152, 61, 75, 170, 87, 182, 7, 127,
+                171, 174, 60, 2, 124, 79, 166, 31, 155, 155, 185, 182, 155, 250, 63, 139, 166, 222,
+                184, 128, 205, 154, 249, 23, 88, 152, 61, 75, 170, 87, 182, 7, 127, 171, 174, 60,
+                2, 124, 79, 166, 31, 155, 155, 185, 182, 155, 250, 63, 139, 166, 222, 184,
+            ],
+        })
+        .unwrap();
+
+        let summary = proof.keys_summary(
+            &[
+                198, 201, 55, 96, 115, 79, 43, 132, 215, 236, 180, 232, 125, 60, 98, 103, 17, 46,
+                150, 56, 154, 235, 33, 17, 222, 105, 142, 178, 235, 61, 88, 52,
+            ],
+            [&[0x00][..], &[0x20][..]],
+        );
+        assert!(summary.proven_present.is_empty());
+        assert_eq!(summary.proven_absent, [&[0x20][..]]);
+        assert_eq!(summary.unproven, [&[0x00][..]]);
+    }
+
     #[test]
     fn identical_non_inline_nodes() {
         // One root node with two identical children and the proof contains the two children
@@ -1446,6 +1653,31 @@ mod tests {
             Err(super::Error::DuplicateProofEntry)
         ));
 
+        // Same proof as above, but audited.
+        let (_, audit) = super::decode_and_audit_proof(super::Config {
+            proof: &[
+                12, 21, 1, 128, 3, 0, 128, 205, 154, 249, 23, 88, 152, 61, 75, 170, 87, 182, 7,
+                127, 171, 174, 60, 2, 124, 79, 166, 31, 155, 155, 185, 182, 155, 250, 63, 139, 166,
+                222, 184, 128, 205, 154, 249, 23, 88, 152, 61, 75, 170, 87, 182, 7, 127, 171, 174,
+                60, 2, 124, 79, 166, 31, 155, 155, 185, 182, 155, 250, 63, 139, 166, 222, 184, 97,
+                1, 65, 0, 81, 1, 108, 111, 110, 103, 32, 115, 116, 111, 114, 97, 103, 101, 32, 118,
+                97, 108, 117, 101, 32, 105, 110, 32, 111, 114, 100, 101, 114, 32, 116, 111, 32,
+                101, 110, 115, 117, 114, 101, 32, 116, 104, 97, 116, 32, 116, 104, 101, 32, 110,
+                111, 100, 101, 32, 118, 97, 108, 117, 101, 32, 105, 115, 32, 109, 111, 114, 101,
+                32, 116, 104, 97, 110, 32, 51, 50, 32, 98, 121, 116, 101, 115, 32, 108, 111, 110,
+                103, 97, 1, 65, 0, 81, 1, 108, 111, 110, 103, 32, 115, 116, 111, 114, 97, 103, 101,
+                32, 118, 97, 108, 117, 101, 32, 105, 110, 32, 111, 114, 100, 101, 114, 32, 116,
+                111, 32, 101, 110, 115, 117, 114, 101, 32, 116, 104, 97, 116, 32, 116, 104, 101,
+                32, 110, 111, 100, 101, 32, 118, 97, 108, 117, 101, 32, 105, 115, 32, 109, 111,
+                114, 101, 32, 116, 104, 97, 110, 32, 51, 50, 32, 98, 121, 116, 101, 115, 32, 108,
+                111, 110, 103,
+            ],
+        })
+        .unwrap();
+        assert_eq!(audit.duplicate_entries, [2]);
+        assert!(audit.unused_entries.is_empty());
+        assert!(!audit.is_clean());
+
         // One root node with two identical children that aren't inlined.
         // The proof is the same as above, just without two identical proof entries.
         super::decode_and_verify_proof(super::Config {
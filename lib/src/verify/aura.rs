@@ -45,6 +45,7 @@
 //! the block header (with the exclusion of the seal itself) made using the public key in question.
 //!
 
+use super::seal_signatures::SealSignature;
 use crate::header;
 
 use alloc::vec::Vec;
@@ -118,8 +119,27 @@ pub enum VerifyError {
 /// Panics if `config.parent_block_header` is invalid.
 ///
 pub fn verify_header<'a>(
-    mut config: VerifyConfig<'a, impl ExactSizeIterator<Item = header::AuraAuthorityRef<'a>>>,
+    config: VerifyConfig<'a, impl ExactSizeIterator<Item = header::AuraAuthorityRef<'a>>>,
 ) -> Result<VerifySuccess, VerifyError> {
+    let (success, seal_signature) = verify_header_deferred_seal(config)?;
+    if !seal_signature.verify() {
+        return Err(VerifyError::BadSignature);
+    }
+    Ok(success)
+}
+
+/// Same as [`verify_header`], except that the signature found in the seal of the header isn't
+/// verified and is instead returned, so that it can be verified later as part of a batch.
+///
+/// See [the `seal_signatures` module](super::seal_signatures) for more information.
+///
+/// # Panic
+///
+/// Panics if `config.parent_block_header` is invalid.
+///
+pub fn verify_header_deferred_seal<'a>(
+    mut config: VerifyConfig<'a, impl ExactSizeIterator<Item = header::AuraAuthorityRef<'a>>>,
+) -> Result<(VerifySuccess, SealSignature), VerifyError> {
     // TODO: handle OnDisabled
 
     // Gather the slot number from the header.
@@ -211,11 +231,9 @@ pub fn verify_header<'a>(
     )
     .unwrap();
 
-    // Now verifying the signature in the seal.
-    authority_public_key
-        .verify_simple(b"substrate", &pre_seal_hash, &seal_signature)
-        .map_err(|_| VerifyError::BadSignature)?;
+    // The signature in the seal is verified by the caller.
+    let seal_signature = SealSignature::new(authority_public_key, pre_seal_hash, seal_signature);
 
     // Success! 🚀
-    Ok(VerifySuccess { authorities_change })
+    Ok((VerifySuccess { authorities_change }, seal_signature))
 }
@@ -129,6 +129,7 @@
 //!
 //! See also the [`crate::chain::chain_information`] module for more help.
 
+use super::seal_signatures::SealSignature;
 use crate::{chain::chain_information, header};
 
 use core::{num::NonZeroU64, time::Duration};
@@ -235,6 +236,27 @@ pub enum VerifyError {
 /// Panics if `config.header.number` is not `config.parent_block_header.number + 1`.
 ///
 pub fn verify_header(config: VerifyConfig) -> Result<VerifySuccess, VerifyError> {
+    let (success, seal_signature) = verify_header_deferred_seal(config)?;
+    if !seal_signature.verify() {
+        return Err(VerifyError::BadSignature);
+    }
+    Ok(success)
+}
+
+/// Same as [`verify_header`], except that the signature found in the seal of the header isn't
+/// verified and is instead returned, so that it can be verified later as part of a batch.
+///
+/// > **Note**: The VRF proof, if any, is still verified by this function.
+///
+/// See [the `seal_signatures` module](super::seal_signatures) for more information.
+///
+/// # Panic
+///
+/// See [`verify_header`].
+///
+pub fn verify_header_deferred_seal(
+    config: VerifyConfig,
+) -> Result<(VerifySuccess, SealSignature), VerifyError> {
     // TODO: handle OnDisabled
 
     // Gather the BABE-related information from the header.
@@ -416,11 +438,6 @@ pub fn verify_header(config: VerifyConfig) -> Result<VerifySuccess, VerifyError>
     let signing_public_key =
         schnorrkel::PublicKey::from_bytes(signing_authority.public_key).unwrap();
 
-    // Now verifying the signature in the seal.
-    signing_public_key
-        .verify_simple(b"substrate", &pre_seal_hash, &seal_signature)
-        .map_err(|_| VerifyError::BadSignature)?;
-
     // Now verify the VRF output and proof, if any.
     // The lack of VRF output/proof in the header is checked when we check whether the slot
     // type is allowed by the current configuration.
@@ -490,12 +507,18 @@ pub fn verify_header(config: VerifyConfig) -> Result<VerifySuccess, VerifyError>
         }
     }
 
+    // The signature in the seal is verified by the caller.
+    let seal_signature = SealSignature::new(signing_public_key, pre_seal_hash, seal_signature);
+
     // Success! 🚀
-    Ok(VerifySuccess {
-        slot_number,
-        is_primary_slot,
-        epoch_transition_target,
-    })
+    Ok((
+        VerifySuccess {
+            slot_number,
+            is_primary_slot,
+            epoch_transition_target,
+        },
+        seal_signature,
+    ))
 }
 
 /// Calculates the primary selection threshold for a given authority, taking
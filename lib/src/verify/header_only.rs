@@ -18,7 +18,7 @@
 use crate::{
     chain::chain_information,
     header,
-    verify::{aura, babe},
+    verify::{aura, babe, seal_signatures::SealSignature},
 };
 
 use alloc::vec::Vec;
@@ -166,6 +166,27 @@ pub enum Error {
 
 /// Verifies whether a block is valid.
 pub fn verify(config: Config) -> Result<Success, Error> {
+    let (success, seal_signature) = verify_deferred_seal(config)?;
+    if !seal_signature.verify() {
+        return Err(bad_seal_signature_error(&success));
+    }
+    Ok(success)
+}
+
+/// Returns the error that [`verify`] returns if the signature in the seal of a header is invalid,
+/// where `success` is the value returned by [`verify_deferred_seal`] for this header.
+pub fn bad_seal_signature_error(success: &Success) -> Error {
+    match success {
+        Success::Aura { .. } => Error::AuraVerification(aura::VerifyError::BadSignature),
+        Success::Babe { .. } => Error::BabeVerification(babe::VerifyError::BadSignature),
+    }
+}
+
+/// Same as [`verify`], except that the signature found in the seal of the header isn't verified
+/// and is instead returned, so that it can be verified later as part of a batch.
+///
+/// See [the `seal_signatures` module](super::seal_signatures) for more information.
+pub fn verify_deferred_seal(config: Config) -> Result<(Success, SealSignature), Error> {
     // Check that there is no mismatch in the parent header hash.
     // Note that the user is expected to pass a parent block that matches the parent indicated by
     // the header to verify, and not blindly pass an "expected parent". As such, this check is
@@ -226,7 +247,7 @@ pub fn verify(config: Config) -> Result<Success, Error> {
                 return Err(Error::MultipleConsensusEngines);
             }
 
-            let result = aura::verify_header(aura::VerifyConfig {
+            let result = aura::verify_header_deferred_seal(aura::VerifyConfig {
                 header: config.block_header.clone(),
                 block_number_bytes: config.block_number_bytes,
                 parent_block_header: config.parent_block_header,
@@ -236,9 +257,12 @@ pub fn verify(config: Config) -> Result<Success, Error> {
             });
 
             match result {
-                Ok(s) => Ok(Success::Aura {
-                    authorities_change: s.authorities_change,
-                }),
+                Ok((s, seal_signature)) => Ok((
+                    Success::Aura {
+                        authorities_change: s.authorities_change,
+                    },
+                    seal_signature,
+                )),
                 Err(err) => Err(Error::AuraVerification(err)),
             }
         }
@@ -252,7 +276,7 @@ pub fn verify(config: Config) -> Result<Success, Error> {
                 return Err(Error::MultipleConsensusEngines);
             }
 
-            let result = babe::verify_header(babe::VerifyConfig {
+            let result = babe::verify_header_deferred_seal(babe::VerifyConfig {
                 header: config.block_header.clone(),
                 block_number_bytes: config.block_number_bytes,
                 parent_block_header: config.parent_block_header,
@@ -263,11 +287,14 @@ pub fn verify(config: Config) -> Result<Success, Error> {
             });
 
             match result {
-                Ok(s) => Ok(Success::Babe {
-                    epoch_transition_target: s.epoch_transition_target,
-                    is_primary_slot: s.is_primary_slot,
-                    slot_number: s.slot_number,
-                }),
+                Ok((s, seal_signature)) => Ok((
+                    Success::Babe {
+                        epoch_transition_target: s.epoch_transition_target,
+                        is_primary_slot: s.is_primary_slot,
+                        slot_number: s.slot_number,
+                    },
+                    seal_signature,
+                )),
                 Err(err) => Err(Error::BabeVerification(err)),
             }
         }
@@ -0,0 +1,175 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Batch verification of block seal signatures.
+//!
+//! Both the Aura and Babe consensus engines require the author of a block to sign the header of
+//! this block (with the exclusion of the seal itself) and put the signature in the seal. Verifying
+//! this signature is a significant part of the CPU time spent verifying a header.
+//!
+//! Rather than verifying these signatures one by one, the functions in the [`aura`](super::aura),
+//! [`babe`](super::babe), and [`header_only`](super::header_only) modules whose name ends with
+//! `_deferred_seal` perform all the verifications except for the one of the signature, and return
+//! a [`SealSignature`] instead. These signatures can then be accumulated in a [`SealSignatures`]
+//! and verified all at once, which is considerably faster than verifying them individually.
+//!
+//! > **Note**: Only the seal signatures can be batched. In the case of Babe, the VRF proof found
+//! >           in the header is still verified individually.
+//!
+//! # Trust
+//!
+//! A header whose seal signature hasn't been verified yet must not be considered as authentic.
+//! It is the responsibility of the API user to not use the outcome of the verification of a
+//! header before the [`SealSignatures`] it was pushed to has been successfully verified.
+//!
+
+use alloc::vec::Vec;
+
+/// Seal signature of a header that remains to be verified.
+#[derive(Debug, Clone)]
+pub struct SealSignature {
+    /// Public key of the authority that has supposedly signed the header.
+    public_key: schnorrkel::PublicKey,
+    /// Hash of the header without its seal. This is the message that has been signed.
+    pre_seal_hash: [u8; 32],
+    /// Signature found in the seal.
+    signature: schnorrkel::Signature,
+}
+
+impl SealSignature {
+    pub(super) fn new(
+        public_key: schnorrkel::PublicKey,
+        pre_seal_hash: [u8; 32],
+        signature: schnorrkel::Signature,
+    ) -> Self {
+        SealSignature {
+            public_key,
+            pre_seal_hash,
+            signature,
+        }
+    }
+
+    /// Returns the public key of the authority that has supposedly signed the header.
+    pub fn public_key(&self) -> [u8; 32] {
+        self.public_key.to_bytes()
+    }
+
+    /// Verifies the signature on its own. Returns `true` if the signature is valid.
+    pub fn verify(&self) -> bool {
+        self.public_key
+            .verify_simple(b"substrate", &self.pre_seal_hash, &self.signature)
+            .is_ok()
+    }
+}
+
+/// Collection of [`SealSignature`]s to verify all at once.
+#[derive(Debug, Clone, Default)]
+pub struct SealSignatures {
+    /// List of signatures, in the order in which they have been pushed.
+    signatures: Vec<SealSignature>,
+}
+
+impl SealSignatures {
+    /// Creates a new empty collection.
+    pub fn new() -> Self {
+        Self::with_capacity(0)
+    }
+
+    /// Creates a new empty collection with the given capacity pre-allocated.
+    pub fn with_capacity(capacity: usize) -> Self {
+        SealSignatures {
+            signatures: Vec::with_capacity(capacity),
+        }
+    }
+
+    /// Adds a signature to the collection. Returns the index of this signature within the
+    /// collection.
+    pub fn push(&mut self, signature: SealSignature) -> usize {
+        self.signatures.push(signature);
+        self.signatures.len() - 1
+    }
+
+    /// Returns the number of signatures in the collection.
+    pub fn len(&self) -> usize {
+        self.signatures.len()
+    }
+
+    /// Returns `true` if the collection is empty.
+    pub fn is_empty(&self) -> bool {
+        self.signatures.is_empty()
+    }
+
+    /// Removes all the signatures from the collection.
+    pub fn clear(&mut self) {
+        self.signatures.clear();
+    }
+
+    /// Verifies all the signatures in the collection.
+    ///
+    /// If at least one signature is invalid, returns the index of the first invalid signature.
+    ///
+    /// > **Note**: Finding out which signature is invalid requires verifying the signatures
+    /// >           individually. This is only done if the batch verification fails, which should
+    /// >           normally not happen.
+    pub fn verify(&self) -> Result<(), InvalidSignatureError> {
+        if self.signatures.is_empty() {
+            return Ok(());
+        }
+
+        let transcripts = self
+            .signatures
+            .iter()
+            .map(|s| schnorrkel::signing_context(b"substrate").bytes(&s.pre_seal_hash));
+        let signatures = self
+            .signatures
+            .iter()
+            .map(|s| s.signature)
+            .collect::<Vec<_>>();
+        let public_keys = self
+            .signatures
+            .iter()
+            .map(|s| s.public_key)
+            .collect::<Vec<_>>();
+
+        // The deterministic version of the batch verification is used, as it doesn't require
+        // a source of randomness. Public keys are very often identical within a batch (the same
+        // authorities produce blocks over and over again), hence the de-duplication.
+        if schnorrkel::verify_batch_deterministic(transcripts, &signatures, &public_keys, true)
+            .is_ok()
+        {
+            return Ok(());
+        }
+
+        let index = self
+            .signatures
+            .iter()
+            .position(|s| !s.verify())
+            // A batch verification failure while all individual verifications succeed isn't
+            // supposed to be possible, but we report the first signature just in case.
+            .unwrap_or(0);
+        Err(InvalidSignatureError { index })
+    }
+}
+
+/// Error potentially returned by [`SealSignatures::verify`].
+#[derive(Debug, Clone, derive_more::Display)]
+#[display(fmt = "Invalid seal signature at index {index}")]
+pub struct InvalidSignatureError {
+    /// Index of the first invalid signature within the collection. Corresponds to the value
+    /// returned by [`SealSignatures::push`].
+    pub index: usize,
+}
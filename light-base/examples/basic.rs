@@ -61,11 +61,36 @@ fn main() {
                 // start a lot of subscriptions, and a value such as 1024 is recommended.
                 // Similarly, if you don't want any limit, feel free to pass `u32::max_value()`.
                 max_subscriptions: 1024,
+                // Number of attempts and timeout of the networking requests used to answer
+                // JSON-RPC requests. The default values are appropriate in most situations.
+                network_requests: Default::default(),
             },
 
             // This field is necessary only if adding a parachain.
             potential_relay_chains: iter::empty(),
 
+            // Whether to download in the background the headers of the blocks that have been
+            // skipped during the warp sync. This is disabled here in order to save memory.
+            gap_sync: false,
+
+            // Only relevant for chains that don't have any finality mechanism.
+            pseudo_finality_depth: None,
+
+            nonce_tracking: false,
+            track_peers_quality: false,
+            trusted_rpc_fallback: None,
+
+            // Which addresses to dial first when a peer advertises both IPv4 and IPv6 addresses.
+            ip_family_policy: smoldot_light::IpFamilyPolicy::NoPreference,
+
+            // Which block to report as best block. Following the peer-to-peer network is the
+            // usual choice.
+            best_block_policy: smoldot_light::BestBlockPolicy::FollowInput,
+
+            // Makes it possible to configure the verbosity of the logs of this specific chain.
+            // The default configuration doesn't discard any log.
+            logs: Default::default(),
+
             // After a chain has been added, it is possible to extract a "database" (in the form of a
             // simple string). This database can later be passed back the next time the same chain is
             // added again.
@@ -74,6 +99,11 @@ fn main() {
             // which is intentionally an invalid database content.
             database_content: "",
 
+            // A snapshot, previously exported with `Client::export_snapshot`, can be passed
+            // instead of a database in order to start from a "warm" state. Contrary to databases,
+            // the format of snapshots is versioned and invalid snapshots lead to an error.
+            snapshot: None,
+
             // The client gives the possibility to insert an opaque "user data" alongside each chain.
             // This avoids having to create a separate `HashMap<ChainId, ...>` in parallel of the
             // client.
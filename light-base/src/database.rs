@@ -26,6 +26,10 @@
 //! information. See [`DatabaseContent`].
 //!
 //! This module provides the function to encode and decode this so-called database.
+//!
+//! Contrary to the database, whose format can change at any time, a *snapshot* is a versioned
+//! document that contains the same information as the database plus the headers of the recent
+//! ancestors of the finalized block. See [`encode_snapshot`] and [`decode_snapshot`].
 
 use alloc::{
     borrow::ToOwned as _,
@@ -34,7 +38,7 @@ use alloc::{
     string::{String, ToString as _},
     vec::Vec,
 };
-use core::cmp;
+use core::{cmp, time::Duration};
 use smoldot::{
     chain,
     database::finalized_serialize,
@@ -57,6 +61,12 @@ pub struct DatabaseContent {
     /// was encoded.
     pub known_nodes: Vec<(PeerId, Vec<multiaddr::Multiaddr>)>,
 
+    /// Quality of some of the nodes of [`DatabaseContent::known_nodes`], as returned by
+    /// [`network_service::NetworkService::peers_quality`] when the database was encoded.
+    ///
+    /// Empty if the tracking of the quality of the peers wasn't enabled.
+    pub known_nodes_quality: Vec<(PeerId, network_service::PeerQuality)>,
+
     /// Known valid Merkle value and storage value combination for the `:code` key.
     ///
     /// Does **not** necessarily match the finalized block found in
@@ -64,6 +74,34 @@ pub struct DatabaseContent {
     pub runtime_code_hint: Option<DatabaseContentRuntimeCodeHint>,
 }
 
+/// Version of the format of the snapshots generated by [`encode_snapshot`]. Snapshots using a
+/// different version are refused by [`decode_snapshot`].
+pub const SNAPSHOT_FORMAT_VERSION: u32 = 1;
+
+/// Maximum number of headers of ancestors of the finalized block included in a snapshot.
+const SNAPSHOT_MAX_RECENT_HEADERS: usize = 512;
+
+/// A decoded snapshot.
+pub struct SnapshotContent {
+    /// Same content as a database.
+    pub database: DatabaseContent,
+
+    /// SCALE-encoded headers of ancestors of the finalized block of
+    /// [`DatabaseContent::chain_information`], ordered by decreasing block number.
+    pub recent_headers: Vec<Vec<u8>>,
+}
+
+/// Error potentially returned by [`decode_snapshot`].
+#[derive(Debug, derive_more::Display, Clone)]
+pub enum SnapshotDecodeError {
+    /// Snapshot isn't in the expected format, or contains invalid data.
+    #[display(fmt = "Invalid snapshot format")]
+    InvalidFormat,
+    /// Snapshot has been generated with an unsupported version of the format.
+    #[display(fmt = "Unsupported snapshot format version: {_0}")]
+    UnsupportedVersion(u32),
+}
+
 /// See [`DatabaseContent::runtime_code_hint`].
 #[derive(Debug, Clone)]
 pub struct DatabaseContentRuntimeCodeHint {
@@ -89,40 +127,15 @@ pub async fn encode_database<TPlat: platform::PlatformRef>(
     genesis_block_hash: &[u8; 32],
     max_size: usize,
 ) -> String {
-    let (code_storage_value, code_merkle_value, code_closest_ancestor_excluding) = runtime_service
-        .finalized_runtime_storage_merkle_values()
-        .await
-        .unwrap_or((None, None, None));
-
     // Craft the structure containing all the data that we would like to include.
-    let mut database_draft = SerdeDatabase {
-        genesis_hash: hex::encode(genesis_block_hash),
-        chain: sync_service.serialize_chain_information().await.map(|ci| {
-            let encoded = finalized_serialize::encode_chain(&ci, sync_service.block_number_bytes());
-            serde_json::from_str(&encoded).unwrap()
-        }),
-        nodes: network_service
-            .discovered_nodes(network_service_chain_id)
-            .await
-            .map(|(peer_id, addrs)| {
-                (
-                    peer_id.to_base58(),
-                    addrs.map(|a| a.to_string()).collect::<Vec<_>>(),
-                )
-            })
-            .collect(),
-        code_merkle_value: code_merkle_value.map(hex::encode),
-        // While it might seem like a good idea to compress the runtime code, in practice it is
-        // normally already zstd-compressed, and additional compressing shouldn't improve the size.
-        code_storage_value: code_storage_value.map(|data| {
-            base64::Engine::encode(&base64::engine::general_purpose::STANDARD_NO_PAD, data)
-        }),
-        code_closest_ancestor_excluding: code_closest_ancestor_excluding.map(|key| {
-            key.iter()
-                .map(|nibble| format!("{:x}", nibble))
-                .collect::<String>()
-        }),
-    };
+    let mut database_draft = database_draft(
+        network_service,
+        network_service_chain_id,
+        sync_service,
+        runtime_service,
+        genesis_block_hash,
+    )
+    .await;
 
     // Cap the database length to the maximum size.
     loop {
@@ -153,17 +166,117 @@ pub async fn encode_database<TPlat: platform::PlatformRef>(
 
         // Try to reduce the size of the database.
 
-        // Remove half of the nodes.
-        // Which nodes are removed doesn't really matter.
-        let mut nodes_to_remove = cmp::max(1, database_draft.nodes.len() / 2);
-        database_draft.nodes.retain(|_, _| {
-            if nodes_to_remove >= 1 {
-                nodes_to_remove -= 1;
-                false
-            } else {
-                true
-            }
-        });
+        // Remove half of the nodes, starting with the ones with the lowest quality. Nodes
+        // without a known quality are removed first, and the order in which they are removed
+        // doesn't really matter.
+        let mut nodes_by_quality = database_draft
+            .nodes
+            .keys()
+            .map(|peer_id| {
+                let quality = database_draft
+                    .nodes_quality
+                    .get(peer_id)
+                    .map(|q| (q.successful_requests, q.uptime_secs));
+                (quality, peer_id.clone())
+            })
+            .collect::<Vec<_>>();
+        nodes_by_quality.sort_unstable();
+        for (_, peer_id) in nodes_by_quality
+            .into_iter()
+            .take(cmp::max(1, database_draft.nodes.len() / 2))
+        {
+            database_draft.nodes.remove(&peer_id);
+            database_draft.nodes_quality.remove(&peer_id);
+        }
+    }
+}
+
+/// Serializes the finalized state of the chain and the recent headers known by the sync
+/// service into a snapshot, using the given services.
+///
+/// Contrary to [`encode_database`], the size of the output isn't capped.
+pub async fn encode_snapshot<TPlat: platform::PlatformRef>(
+    network_service: &network_service::NetworkService<TPlat>,
+    network_service_chain_id: network_service::ChainId,
+    sync_service: &sync_service::SyncService<TPlat>,
+    runtime_service: &runtime_service::RuntimeService<TPlat>,
+    genesis_block_hash: &[u8; 32],
+) -> String {
+    let snapshot = SerdeSnapshot {
+        version: SNAPSHOT_FORMAT_VERSION,
+        database: database_draft(
+            network_service,
+            network_service_chain_id,
+            sync_service,
+            runtime_service,
+            genesis_block_hash,
+        )
+        .await,
+        recent_headers: sync_service
+            .recent_headers(SNAPSHOT_MAX_RECENT_HEADERS)
+            .await
+            .into_iter()
+            .map(hex::encode)
+            .collect(),
+    };
+
+    serde_json::to_string(&snapshot).unwrap()
+}
+
+/// Gathers the information to put in a database or a snapshot.
+async fn database_draft<TPlat: platform::PlatformRef>(
+    network_service: &network_service::NetworkService<TPlat>,
+    network_service_chain_id: network_service::ChainId,
+    sync_service: &sync_service::SyncService<TPlat>,
+    runtime_service: &runtime_service::RuntimeService<TPlat>,
+    genesis_block_hash: &[u8; 32],
+) -> SerdeDatabase {
+    let (code_storage_value, code_merkle_value, code_closest_ancestor_excluding) = runtime_service
+        .finalized_runtime_storage_merkle_values()
+        .await
+        .unwrap_or((None, None, None));
+
+    SerdeDatabase {
+        genesis_hash: hex::encode(genesis_block_hash),
+        chain: sync_service.serialize_chain_information().await.map(|ci| {
+            let encoded = finalized_serialize::encode_chain(&ci, sync_service.block_number_bytes());
+            serde_json::from_str(&encoded).unwrap()
+        }),
+        nodes: network_service
+            .discovered_nodes(network_service_chain_id)
+            .await
+            .map(|(peer_id, addrs)| {
+                (
+                    peer_id.to_base58(),
+                    addrs.map(|a| a.to_string()).collect::<Vec<_>>(),
+                )
+            })
+            .collect(),
+        nodes_quality: network_service
+            .peers_quality(network_service_chain_id)
+            .await
+            .into_iter()
+            .map(|(peer_id, quality)| {
+                (
+                    peer_id.to_base58(),
+                    SerdePeerQuality {
+                        successful_requests: quality.successful_requests,
+                        uptime_secs: quality.uptime.as_secs(),
+                    },
+                )
+            })
+            .collect(),
+        code_merkle_value: code_merkle_value.map(hex::encode),
+        // While it might seem like a good idea to compress the runtime code, in practice it is
+        // normally already zstd-compressed, and additional compressing shouldn't improve the size.
+        code_storage_value: code_storage_value.map(|data| {
+            base64::Engine::encode(&base64::engine::general_purpose::STANDARD_NO_PAD, data)
+        }),
+        code_closest_ancestor_excluding: code_closest_ancestor_excluding.map(|key| {
+            key.iter()
+                .map(|nibble| format!("{:x}", nibble))
+                .collect::<String>()
+        }),
     }
 }
 
@@ -174,7 +287,48 @@ pub async fn encode_database<TPlat: platform::PlatformRef>(
 /// Must be passed the number of bytes used to encode the number of a block for the given chain.
 pub fn decode_database(encoded: &str, block_number_bytes: usize) -> Result<DatabaseContent, ()> {
     let decoded: SerdeDatabase = serde_json::from_str(encoded).map_err(|_| ())?;
+    decode_serde_database(decoded, block_number_bytes)
+}
 
+/// Tries to decode the given snapshot.
+///
+/// Must be passed the number of bytes used to encode the number of a block for the given chain.
+pub fn decode_snapshot(
+    encoded: &str,
+    block_number_bytes: usize,
+) -> Result<SnapshotContent, SnapshotDecodeError> {
+    // The version is decoded separately, in order to be able to report an unsupported version
+    // even if the rest of the format has changed.
+    let version = serde_json::from_str::<SerdeSnapshotVersion>(encoded)
+        .map_err(|_| SnapshotDecodeError::InvalidFormat)?
+        .version;
+    if version != SNAPSHOT_FORMAT_VERSION {
+        return Err(SnapshotDecodeError::UnsupportedVersion(version));
+    }
+
+    let decoded: SerdeSnapshot =
+        serde_json::from_str(encoded).map_err(|_| SnapshotDecodeError::InvalidFormat)?;
+
+    let database = decode_serde_database(decoded.database, block_number_bytes)
+        .map_err(|()| SnapshotDecodeError::InvalidFormat)?;
+
+    let recent_headers = decoded
+        .recent_headers
+        .into_iter()
+        .map(hex::decode)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| SnapshotDecodeError::InvalidFormat)?;
+
+    Ok(SnapshotContent {
+        database,
+        recent_headers,
+    })
+}
+
+fn decode_serde_database(
+    decoded: SerdeDatabase,
+    block_number_bytes: usize,
+) -> Result<DatabaseContent, ()> {
     let genesis_block_hash = if decoded.genesis_hash.len() == 64 {
         <[u8; 32]>::try_from(hex::decode(&decoded.genesis_hash).map_err(|_| ())?).unwrap()
     } else {
@@ -208,6 +362,19 @@ pub fn decode_database(encoded: &str, block_number_bytes: usize) -> Result<Datab
         })
         .collect::<Vec<_>>();
 
+    // Similarly, qualities whose node fails to decode are ignored.
+    let known_nodes_quality = decoded
+        .nodes_quality
+        .iter()
+        .filter_map(|(peer_id, quality)| {
+            let quality = network_service::PeerQuality {
+                successful_requests: quality.successful_requests,
+                uptime: Duration::from_secs(quality.uptime_secs),
+            };
+            Some((peer_id.parse::<PeerId>().ok()?, quality))
+        })
+        .collect::<Vec<_>>();
+
     let runtime_code_hint = match (
         decoded.code_merkle_value,
         decoded.code_storage_value,
@@ -232,10 +399,25 @@ pub fn decode_database(encoded: &str, block_number_bytes: usize) -> Result<Datab
         genesis_block_hash,
         chain_information,
         known_nodes,
+        known_nodes_quality,
         runtime_code_hint,
     })
 }
 
+#[derive(serde::Serialize, serde::Deserialize)]
+struct SerdeSnapshotVersion {
+    version: u32,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct SerdeSnapshot {
+    version: u32,
+    database: SerdeDatabase,
+    /// Hexadecimal-encoded SCALE-encoded headers. Have no `0x` prefix.
+    #[serde(rename = "recentHeaders")]
+    recent_headers: Vec<String>,
+}
+
 #[derive(serde::Serialize, serde::Deserialize)]
 struct SerdeDatabase {
     /// Hexadecimal-encoded hash of the genesis block header. Has no `0x` prefix.
@@ -244,6 +426,12 @@ struct SerdeDatabase {
     #[serde(default = "Default::default", skip_serializing_if = "Option::is_none")]
     chain: Option<Box<serde_json::value::RawValue>>,
     nodes: hashbrown::HashMap<String, Vec<String>, fnv::FnvBuildHasher>,
+    #[serde(
+        rename = "nodesQuality",
+        default = "Default::default",
+        skip_serializing_if = "hashbrown::HashMap::is_empty"
+    )]
+    nodes_quality: hashbrown::HashMap<String, SerdePeerQuality, fnv::FnvBuildHasher>,
     #[serde(
         rename = "runtimeCode",
         default = "Default::default",
@@ -263,3 +451,153 @@ struct SerdeDatabase {
     )]
     code_closest_ancestor_excluding: Option<String>,
 }
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct SerdePeerQuality {
+    #[serde(rename = "successfulRequests")]
+    successful_requests: u64,
+    #[serde(rename = "uptimeSecs")]
+    uptime_secs: u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        decode_snapshot, SerdeDatabase, SerdePeerQuality, SerdeSnapshot, SnapshotDecodeError,
+        SNAPSHOT_FORMAT_VERSION,
+    };
+    use alloc::{string::ToString as _, vec, vec::Vec};
+    use smoldot::libp2p::{peer_id::PublicKey, PeerId};
+
+    fn encoded_snapshot(genesis_hash: [u8; 32], peer_id: &PeerId) -> String {
+        let mut nodes = hashbrown::HashMap::default();
+        nodes.insert(
+            peer_id.to_base58(),
+            vec!["/ip4/1.2.3.4/tcp/30333".to_string()],
+        );
+        let mut nodes_quality = hashbrown::HashMap::default();
+        nodes_quality.insert(
+            peer_id.to_base58(),
+            SerdePeerQuality {
+                successful_requests: 12,
+                uptime_secs: 34,
+            },
+        );
+
+        serde_json::to_string(&SerdeSnapshot {
+            version: SNAPSHOT_FORMAT_VERSION,
+            database: SerdeDatabase {
+                genesis_hash: hex::encode(genesis_hash),
+                chain: None,
+                nodes,
+                nodes_quality,
+                code_storage_value: None,
+                code_merkle_value: None,
+                code_closest_ancestor_excluding: None,
+            },
+            recent_headers: vec![hex::encode([1, 2, 3]), hex::encode([4, 5])],
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn round_trip() {
+        let peer_id = PeerId::from_public_key(&PublicKey::Ed25519([7; 32]));
+        let decoded = decode_snapshot(&encoded_snapshot([5; 32], &peer_id), 4).unwrap();
+
+        assert_eq!(decoded.database.genesis_block_hash, [5; 32]);
+        assert!(decoded.database.chain_information.is_none());
+        assert_eq!(decoded.database.known_nodes.len(), 1);
+        assert_eq!(decoded.database.known_nodes[0].0, peer_id);
+        assert_eq!(
+            decoded.database.known_nodes[0]
+                .1
+                .iter()
+                .map(|a| a.to_string())
+                .collect::<Vec<_>>(),
+            vec!["/ip4/1.2.3.4/tcp/30333".to_string()]
+        );
+        assert_eq!(decoded.database.known_nodes_quality.len(), 1);
+        assert_eq!(decoded.database.known_nodes_quality[0].0, peer_id);
+        assert_eq!(
+            decoded.database.known_nodes_quality[0]
+                .1
+                .successful_requests,
+            12
+        );
+        assert_eq!(
+            decoded.database.known_nodes_quality[0].1.uptime.as_secs(),
+            34
+        );
+        assert_eq!(decoded.recent_headers, vec![vec![1, 2, 3], vec![4, 5]]);
+    }
+
+    #[test]
+    fn unsupported_version() {
+        let peer_id = PeerId::from_public_key(&PublicKey::Ed25519([7; 32]));
+        let encoded = encoded_snapshot([5; 32], &peer_id).replacen(
+            &format!("\"version\":{}", SNAPSHOT_FORMAT_VERSION),
+            "\"version\":2",
+            1,
+        );
+
+        assert!(matches!(
+            decode_snapshot(&encoded, 4),
+            Err(SnapshotDecodeError::UnsupportedVersion(2))
+        ));
+    }
+
+    #[test]
+    fn unsupported_version_with_unknown_format() {
+        // The version must be reported even if the rest of the snapshot can't be decoded.
+        assert!(matches!(
+            decode_snapshot(r#"{"version":2,"somethingNew":true}"#, 4),
+            Err(SnapshotDecodeError::UnsupportedVersion(2))
+        ));
+    }
+
+    #[test]
+    fn invalid_format() {
+        assert!(matches!(
+            decode_snapshot("not json", 4),
+            Err(SnapshotDecodeError::InvalidFormat)
+        ));
+        assert!(matches!(
+            decode_snapshot(r#"{"version":1}"#, 4),
+            Err(SnapshotDecodeError::InvalidFormat)
+        ));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn wrong_chain() {
+        let peer_id = PeerId::from_public_key(&PublicKey::Ed25519([7; 32]));
+        let snapshot = encoded_snapshot([0; 32], &peer_id);
+
+        let mut client = crate::Client::new(crate::platform::default::DefaultPlatform::new(
+            "test".into(),
+            "0.0.0".into(),
+        ));
+        let result = client.add_chain(crate::AddChainConfig {
+            specification: include_str!("../../demo-chain-specs/polkadot.json"),
+            database_content: "",
+            snapshot: Some(&snapshot),
+            user_data: (),
+            potential_relay_chains: core::iter::empty(),
+            json_rpc: crate::AddChainConfigJsonRpc::Disabled,
+            gap_sync: false,
+            pseudo_finality_depth: None,
+            nonce_tracking: false,
+            track_peers_quality: false,
+            trusted_rpc_fallback: None,
+            ip_family_policy: crate::IpFamilyPolicy::NoPreference,
+            best_block_policy: crate::BestBlockPolicy::FollowInput,
+            logs: Default::default(),
+        });
+
+        assert!(matches!(
+            result,
+            Err(crate::AddChainError::SnapshotWrongChain)
+        ));
+    }
+}
@@ -33,6 +33,16 @@
 //! queue grows past [`Config::max_pending_requests`] items, [`Frontend::queue_rpc_request`]
 //! will instead return an error.
 //!
+//! # Unverified responses
+//!
+//! If [`StartConfig::trusted_rpc_fallback`] is `Some`, some requests that the peer-to-peer
+//! network is unable to answer are forwarded to a trusted JSON-RPC server. The responses of this
+//! server can't be verified, and are sent back to the JSON-RPC client with an additional
+//! `smoldot_unverified` field set to `true` next to the `result` field, for example
+//! `{"jsonrpc":"2.0","id":1,"result":null,"smoldot_unverified":true}`. This field is a
+//! non-standard extension of the JSON-RPC specification. Responses that have been verified
+//! never contain this field.
+//!
 
 // TODO: doc
 // TODO: re-review this once finished
@@ -40,7 +50,8 @@
 mod background;
 
 use crate::{
-    network_service, platform::PlatformRef, runtime_service, sync_service, transactions_service,
+    network_service, nonce_service, platform::PlatformRef, runtime_service, sync_service,
+    transactions_service, trusted_rpc, util,
 };
 
 use alloc::{
@@ -48,7 +59,7 @@ use alloc::{
     string::{String, ToString as _},
     sync::Arc,
 };
-use core::num::NonZeroU32;
+use core::{num::NonZeroU32, time::Duration};
 use smoldot::{chain_spec, json_rpc::service, libp2p::PeerId};
 
 /// Configuration for [`service()`].
@@ -59,6 +70,9 @@ pub struct Config {
     /// >           have been filtered out from this name.
     pub log_name: String,
 
+    /// Maximum level of the logs emitted by this service.
+    pub log_max_level: log::LevelFilter,
+
     /// Maximum number of JSON-RPC requests that can be added to a queue if it is not ready to be
     /// processed immediately. Any additional request will be immediately rejected.
     ///
@@ -78,6 +92,66 @@ pub struct Config {
     /// This parameter is necessary in order to prevent users from using up too much memory within
     /// the client.
     pub max_parallel_requests: NonZeroU32,
+
+    /// Timeouts and number of attempts of the networking requests performed in order to answer
+    /// JSON-RPC requests.
+    pub network_requests: NetworkRequestsConfig,
+}
+
+/// Timeouts and number of attempts of the networking requests that the JSON-RPC service
+/// performs in order to answer JSON-RPC requests. See [`Config::network_requests`].
+///
+/// By default, the values used depend on the JSON-RPC function being answered. Each field, if
+/// `Some`, overrides these values for the corresponding category of requests. This is useful in
+/// high-latency environments, where the default timeouts might be too short.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct NetworkRequestsConfig {
+    /// Storage queries, including the downloads of the runtime code of blocks.
+    pub storage: Option<NetworkRequestConfig>,
+    /// Runtime call proof queries.
+    pub call: Option<NetworkRequestConfig>,
+    /// Queries of block headers and bodies.
+    pub blocks: Option<NetworkRequestConfig>,
+}
+
+/// See [`NetworkRequestsConfig`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct NetworkRequestConfig {
+    /// Number of requests to perform, each to a different peer, before giving up.
+    pub total_attempts: u32,
+    /// Maximum time to wait for each individual request to succeed.
+    pub timeout_per_request: Duration,
+}
+
+impl NetworkRequestsConfig {
+    /// Returns the number of attempts and the timeout to use for a storage query, given the
+    /// values to use if [`NetworkRequestsConfig::storage`] is `None`.
+    fn storage_or(&self, total_attempts: u32, timeout_per_request: Duration) -> (u32, Duration) {
+        Self::override_or(self.storage, total_attempts, timeout_per_request)
+    }
+
+    /// Returns the number of attempts and the timeout to use for a call proof query, given the
+    /// values to use if [`NetworkRequestsConfig::call`] is `None`.
+    fn call_or(&self, total_attempts: u32, timeout_per_request: Duration) -> (u32, Duration) {
+        Self::override_or(self.call, total_attempts, timeout_per_request)
+    }
+
+    /// Returns the number of attempts and the timeout to use for a block query, given the
+    /// values to use if [`NetworkRequestsConfig::blocks`] is `None`.
+    fn blocks_or(&self, total_attempts: u32, timeout_per_request: Duration) -> (u32, Duration) {
+        Self::override_or(self.blocks, total_attempts, timeout_per_request)
+    }
+
+    fn override_or(
+        config: Option<NetworkRequestConfig>,
+        total_attempts: u32,
+        timeout_per_request: Duration,
+    ) -> (u32, Duration) {
+        match config {
+            Some(config) => (config.total_attempts, config.timeout_per_request),
+            None => (total_attempts, timeout_per_request),
+        }
+    }
 }
 
 /// Creates a new JSON-RPC service with the given configuration.
@@ -87,7 +161,10 @@ pub struct Config {
 ///
 /// Destroying the [`Frontend`] automatically shuts down the service.
 pub fn service(config: Config) -> (Frontend, ServicePrototype) {
-    let log_target = format!("json-rpc-{}", config.log_name);
+    let log_target = util::LogTarget::new(
+        format!("json-rpc-{}", config.log_name),
+        config.log_max_level,
+    );
 
     let (requests_processing_task, requests_responses_io) =
         service::client_main_task(service::Config {
@@ -104,6 +181,7 @@ pub fn service(config: Config) -> (Frontend, ServicePrototype) {
         log_target,
         requests_processing_task,
         max_parallel_requests: config.max_parallel_requests,
+        network_requests: config.network_requests,
     };
 
     (frontend, prototype)
@@ -123,7 +201,7 @@ pub struct Frontend {
     requests_responses_io: Arc<service::SerializedRequestsIo>,
 
     /// Target to use when emitting logs.
-    log_target: String,
+    log_target: util::LogTarget,
 }
 
 impl Frontend {
@@ -142,8 +220,9 @@ impl Frontend {
             .try_send_request(json_rpc_request)
         {
             Ok(()) => {
-                log::debug!(
-                    target: &self.log_target,
+                util::log!(
+                    Debug,
+                    &self.log_target,
                     "JSON-RPC => {}",
                     log_friendly_request
                 );
@@ -172,13 +251,11 @@ impl Frontend {
             Err(service::WaitNextResponseError::ClientMainTaskDestroyed) => unreachable!(),
         };
 
-        log::debug!(
-            target: &self.log_target,
+        util::log!(
+            Debug,
+            &self.log_target,
             "JSON-RPC <= {}",
-            crate::util::truncated_str(
-                message.chars().filter(|c| !c.is_control()),
-                250,
-            )
+            crate::util::truncated_str(message.chars().filter(|c| !c.is_control()), 250,)
         );
 
         message
@@ -193,10 +270,13 @@ pub struct ServicePrototype {
     requests_processing_task: service::ClientMainTask,
 
     /// Target to use when emitting logs.
-    log_target: String,
+    log_target: util::LogTarget,
 
     /// Value obtained through [`Config::max_parallel_requests`].
     max_parallel_requests: NonZeroU32,
+
+    /// Value obtained through [`Config::network_requests`].
+    network_requests: NetworkRequestsConfig,
 }
 
 /// Configuration for a JSON-RPC service.
@@ -220,6 +300,14 @@ pub struct StartConfig<'a, TPlat: PlatformRef> {
     /// Service that provides a ready-to-be-called runtime for the current best block.
     pub runtime_service: Arc<runtime_service::RuntimeService<TPlat>>,
 
+    /// Service that hands out account nonces. `None` if nonce tracking is disabled for this
+    /// chain, in which case the corresponding JSON-RPC functions return an error.
+    pub nonce_service: Option<nonce_service::NonceService>,
+
+    /// Client for a trusted JSON-RPC server that is queried when the peer-to-peer network is
+    /// unable to answer some requests. `None` if no such server has been configured.
+    pub trusted_rpc_fallback: Option<Arc<trusted_rpc::TrustedRpcClient<TPlat>>>,
+
     /// Specification of the chain.
     pub chain_spec: &'a chain_spec::ChainSpec,
 
@@ -261,6 +349,7 @@ impl ServicePrototype {
             config,
             self.requests_processing_task,
             self.max_parallel_requests,
+            self.network_requests,
         )
     }
 }
@@ -16,11 +16,11 @@
 // along with this program.  If not, see <http://www.gnu.org/licenses/>.
 
 use crate::{
-    network_service, platform::PlatformRef, runtime_service, sync_service, transactions_service,
-    util,
+    network_service, nonce_service, platform::PlatformRef, runtime_service, sync_service,
+    transactions_service, trusted_rpc, util,
 };
 
-use super::StartConfig;
+use super::{NetworkRequestsConfig, StartConfig};
 
 use alloc::{
     borrow::ToOwned as _,
@@ -38,8 +38,11 @@ use core::{
     time::Duration,
 };
 use futures_channel::oneshot;
+use futures_util::StreamExt as _;
 use smoldot::{
     executor::{host, runtime_host},
+    identity::ss58,
+    informant::HashDisplay,
     json_rpc::{self, methods, service},
     libp2p::{multiaddr, PeerId},
 };
@@ -53,7 +56,7 @@ mod transactions;
 /// Fields used to process JSON-RPC requests in the background.
 struct Background<TPlat: PlatformRef> {
     /// Target to use for all the logs.
-    log_target: String,
+    log_target: util::LogTarget,
 
     /// Access to the platform's capabilities.
     platform: TPlat,
@@ -66,6 +69,10 @@ struct Background<TPlat: PlatformRef> {
     chain_properties_json: String,
     /// Whether the chain is a live network. Found in the chain specification.
     chain_is_live: bool,
+    /// SS58 prefix of the addresses of the chain, as found in the `ss58Format` property of the
+    /// chain specification. `None` if the property is missing or invalid, in which case the
+    /// prefix of the addresses passed through the JSON-RPC API isn't verified.
+    chain_ss58_prefix: Option<ss58::ChainPrefix>,
     /// See [`StartConfig::peer_id`]. The only use for this field is to send the Base58 encoding of
     /// the [`PeerId`]. Consequently, we store the conversion to Base58 ahead of time.
     peer_id_base58: String,
@@ -85,6 +92,12 @@ struct Background<TPlat: PlatformRef> {
     runtime_service: Arc<runtime_service::RuntimeService<TPlat>>,
     /// See [`StartConfig::transactions_service`].
     transactions_service: Arc<transactions_service::TransactionsService<TPlat>>,
+    /// See [`StartConfig::nonce_service`].
+    nonce_service: Option<nonce_service::NonceService>,
+    /// See [`StartConfig::trusted_rpc_fallback`].
+    trusted_rpc_fallback: Option<Arc<trusted_rpc::TrustedRpcClient<TPlat>>>,
+    /// See [`super::Config::network_requests`].
+    network_requests: NetworkRequestsConfig,
 
     /// Channel where to send requests that concern the legacy JSON-RPC API that are handled by
     /// a dedicated task.
@@ -106,6 +119,12 @@ struct Background<TPlat: PlatformRef> {
     /// flag prevents printing this message multiple times.
     printed_legacy_json_rpc_warning: atomic::AtomicBool,
 
+    /// Latest value yielded by [`runtime_service::RuntimeService::subscribe_near_head_of_chain`].
+    /// Kept up to date by a background task. `None` if the subscription hasn't yielded any
+    /// value yet, in which case [`runtime_service::RuntimeService::is_near_head_of_chain_heuristic`]
+    /// should be called instead.
+    near_head_of_chain: Mutex<Option<bool>>,
+
     /// For each `chainHead_follow` subscription ID, a channel to the task dedicated to processing
     /// this subscription.
     chain_head_follow_tasks: Mutex<
@@ -127,16 +146,18 @@ struct GetKeysPagedCacheKey {
 }
 
 pub(super) fn start<TPlat: PlatformRef>(
-    log_target: String,
+    log_target: util::LogTarget,
     config: StartConfig<'_, TPlat>,
     mut requests_processing_task: service::ClientMainTask,
     max_parallel_requests: NonZeroU32,
+    network_requests: NetworkRequestsConfig,
 ) {
     let to_legacy_tx = legacy_state_sub::start_task(legacy_state_sub::Config {
         platform: config.platform.clone(),
         log_target: log_target.clone(),
         sync_service: config.sync_service.clone(),
         runtime_service: config.runtime_service.clone(),
+        network_requests,
     });
 
     let me = Arc::new(Background {
@@ -144,7 +165,13 @@ pub(super) fn start<TPlat: PlatformRef>(
         chain_name: config.chain_spec.name().to_owned(),
         chain_ty: config.chain_spec.chain_type().to_owned(),
         chain_is_live: config.chain_spec.has_live_network(),
-        chain_properties_json: config.chain_spec.properties().to_owned(),
+        chain_properties_json: config.chain_spec.validated_properties(),
+        chain_ss58_prefix: config
+            .chain_spec
+            .ss58_format()
+            .ok()
+            .flatten()
+            .and_then(|prefix| ss58::ChainPrefix::try_from(prefix).ok()),
         peer_id_base58: config.peer_id.to_base58(),
         system_name: config.system_name.clone(),
         system_version: config.system_version.clone(),
@@ -152,6 +179,9 @@ pub(super) fn start<TPlat: PlatformRef>(
         sync_service: config.sync_service.clone(),
         runtime_service: config.runtime_service.clone(),
         transactions_service: config.transactions_service.clone(),
+        nonce_service: config.nonce_service.clone(),
+        trusted_rpc_fallback: config.trusted_rpc_fallback.clone(),
+        network_requests,
         to_legacy: Mutex::new(to_legacy_tx),
         state_get_keys_paged_cache: Mutex::new(lru::LruCache::with_hasher(
             NonZeroUsize::new(2).unwrap(),
@@ -163,6 +193,7 @@ pub(super) fn start<TPlat: PlatformRef>(
         )),
         genesis_block_hash: config.genesis_block_hash,
         printed_legacy_json_rpc_warning: atomic::AtomicBool::new(false),
+        near_head_of_chain: Mutex::new(None),
         chain_head_follow_tasks: Mutex::new(hashbrown::HashMap::with_hasher(Default::default())),
         platform: config.platform,
     });
@@ -237,6 +268,29 @@ pub(super) fn start<TPlat: PlatformRef>(
             }
         });
 
+    // Spawn a task that keeps `near_head_of_chain` up to date.
+    // The task only holds a weak reference to `me` in order to not prevent it from being
+    // destroyed. The subscription is closed by the runtime service if the task doesn't pull
+    // values quickly enough, in which case a new subscription is opened.
+    me.platform
+        .clone()
+        .spawn_task(format!("{}-near-head-of-chain", me.log_target).into(), {
+            let me = Arc::downgrade(&me);
+            async move {
+                loop {
+                    let mut subscription = {
+                        let Some(me) = me.upgrade() else { break };
+                        me.runtime_service.subscribe_near_head_of_chain(8).await
+                    };
+
+                    while let Some(near_head_of_chain) = subscription.next().await {
+                        let Some(me) = me.upgrade() else { return };
+                        *me.near_head_of_chain.lock().await = Some(near_head_of_chain);
+                    }
+                }
+            }
+        });
+
     // Spawn tasks dedicated to effectively process the JSON-RPC requests.
     for task_num in 0..max_parallel_requests.get() {
         me.platform.clone().spawn_task(
@@ -331,8 +385,9 @@ impl<TPlat: PlatformRef> Background<TPlat> {
                     .printed_legacy_json_rpc_warning
                     .swap(true, atomic::Ordering::Relaxed)
                 {
-                    log::warn!(
-                        target: &self.log_target,
+                    util::log!(
+                        Warn,
+                        &self.log_target,
                         "The JSON-RPC client has just called a JSON-RPC function from the legacy \
                         JSON-RPC API ({}). Legacy JSON-RPC functions have loose semantics and \
                         cannot be properly implemented on a light client. You are encouraged to \
@@ -344,7 +399,13 @@ impl<TPlat: PlatformRef> Background<TPlat> {
                     )
                 }
             }
-            methods::MethodCall::chainHead_unstable_body { .. }
+            methods::MethodCall::archive_unstable_body { .. }
+            | methods::MethodCall::archive_unstable_finalizedHeight { .. }
+            | methods::MethodCall::archive_unstable_genesisHash { .. }
+            | methods::MethodCall::archive_unstable_hashByHeight { .. }
+            | methods::MethodCall::archive_unstable_header { .. }
+            | methods::MethodCall::archive_unstable_storage { .. }
+            | methods::MethodCall::chainHead_unstable_body { .. }
             | methods::MethodCall::chainHead_unstable_call { .. }
             | methods::MethodCall::chainHead_unstable_continue { .. }
             | methods::MethodCall::chainHead_unstable_follow { .. }
@@ -356,6 +417,8 @@ impl<TPlat: PlatformRef> Background<TPlat> {
             | methods::MethodCall::chainSpec_v1_chainName { .. }
             | methods::MethodCall::chainSpec_v1_genesisHash { .. }
             | methods::MethodCall::chainSpec_v1_properties { .. }
+            | methods::MethodCall::engine_createBlock { .. }
+            | methods::MethodCall::engine_finalizeBlock { .. }
             | methods::MethodCall::rpc_methods { .. }
             | methods::MethodCall::sudo_unstable_p2pDiscover { .. }
             | methods::MethodCall::sudo_unstable_version { .. }
@@ -363,7 +426,11 @@ impl<TPlat: PlatformRef> Background<TPlat> {
             | methods::MethodCall::transaction_unstable_unwatch { .. }
             | methods::MethodCall::network_unstable_subscribeEvents { .. }
             | methods::MethodCall::network_unstable_unsubscribeEvents { .. }
-            | methods::MethodCall::chainHead_unstable_finalizedDatabase { .. } => {}
+            | methods::MethodCall::chainHead_unstable_finalizedDatabase { .. }
+            | methods::MethodCall::smoldot_unstable_reserveAccountNonce { .. }
+            | methods::MethodCall::smoldot_unstable_releaseAccountNonce { .. }
+            | methods::MethodCall::smoldot_unstable_submitTransaction { .. }
+            | methods::MethodCall::smoldot_unstable_checkRuntimeUpgrade { .. } => {}
         }
 
         // Each call is handled in a separate method.
@@ -407,6 +474,9 @@ impl<TPlat: PlatformRef> Background<TPlat> {
             methods::MethodCall::state_getMetadata { .. } => {
                 self.state_get_metadata(request).await;
             }
+            methods::MethodCall::state_getReadProof { .. } => {
+                self.state_get_read_proof(request).await;
+            }
             methods::MethodCall::state_getStorage { .. } => {
                 self.state_get_storage(request).await;
             }
@@ -422,6 +492,9 @@ impl<TPlat: PlatformRef> Background<TPlat> {
             methods::MethodCall::system_chainType {} => {
                 self.system_chain_type(request).await;
             }
+            methods::MethodCall::system_dryRun { .. } => {
+                self.system_dry_run(request).await;
+            }
             methods::MethodCall::system_health {} => {
                 self.system_health(request).await;
             }
@@ -486,6 +559,40 @@ impl<TPlat: PlatformRef> Background<TPlat> {
             methods::MethodCall::sudo_unstable_version {} => {
                 self.sudo_unstable_version(request).await;
             }
+            methods::MethodCall::smoldot_unstable_reserveAccountNonce { .. } => {
+                self.smoldot_unstable_reserve_account_nonce(request).await;
+            }
+            methods::MethodCall::smoldot_unstable_releaseAccountNonce { .. } => {
+                self.smoldot_unstable_release_account_nonce(request).await;
+            }
+            methods::MethodCall::smoldot_unstable_submitTransaction { .. } => {
+                self.smoldot_unstable_submit_transaction(request).await;
+            }
+
+            methods::MethodCall::archive_unstable_body { .. }
+            | methods::MethodCall::archive_unstable_finalizedHeight { .. }
+            | methods::MethodCall::archive_unstable_genesisHash { .. }
+            | methods::MethodCall::archive_unstable_hashByHeight { .. }
+            | methods::MethodCall::archive_unstable_header { .. }
+            | methods::MethodCall::archive_unstable_storage { .. } => {
+                // The `archive` functions require access to the entire history of the chain,
+                // which a light client doesn't have. As mandated by the JSON-RPC specification,
+                // they are treated as if they didn't exist.
+                request.fail(json_rpc::parse::ErrorResponse::MethodNotFound);
+            }
+
+            methods::MethodCall::engine_createBlock { .. }
+            | methods::MethodCall::engine_finalizeBlock { .. } => {
+                // The `engine` functions are meant to control the block production of a node
+                // used for testing purposes. A light client never produces blocks.
+                request.fail(json_rpc::parse::ErrorResponse::MethodNotFound);
+            }
+
+            methods::MethodCall::smoldot_unstable_checkRuntimeUpgrade { .. } => {
+                // Checking a runtime upgrade requires compiling the proposed runtime, which is
+                // considered too expensive for a light client.
+                request.fail(json_rpc::parse::ErrorResponse::MethodNotFound);
+            }
 
             _method @ (methods::MethodCall::account_nextIndex { .. }
             | methods::MethodCall::author_hasKey { .. }
@@ -502,16 +609,19 @@ impl<TPlat: PlatformRef> Background<TPlat> {
             | methods::MethodCall::offchain_localStorageGet { .. }
             | methods::MethodCall::offchain_localStorageSet { .. }
             | methods::MethodCall::state_getPairs { .. }
-            | methods::MethodCall::state_getReadProof { .. }
             | methods::MethodCall::state_getStorageHash { .. }
             | methods::MethodCall::state_getStorageSize { .. }
             | methods::MethodCall::state_queryStorage { .. }
             | methods::MethodCall::system_addReservedPeer { .. }
-            | methods::MethodCall::system_dryRun { .. }
             | methods::MethodCall::system_networkState { .. }
             | methods::MethodCall::system_removeReservedPeer { .. }) => {
                 // TODO: implement the ones that make sense to implement ^
-                log::error!(target: &self.log_target, "JSON-RPC call not supported yet: {:?}", _method);
+                util::log!(
+                    Error,
+                    &self.log_target,
+                    "JSON-RPC call not supported yet: {:?}",
+                    _method
+                );
                 request.fail(json_rpc::parse::ErrorResponse::ServerError(
                     -32000,
                     "Not implemented in smoldot yet",
@@ -528,7 +638,7 @@ impl<TPlat: PlatformRef> Background<TPlat> {
         request: service::SubscriptionStartProcess,
     ) {
         // TODO: restore some form of logging
-        /*log::debug!(target: &self.log_target, "PendingRequestsQueue => {}",
+        /*util::log!(Debug, &self.log_target, "PendingRequestsQueue => {}",
             crate::util::truncated_str(
                 json_rpc_request.chars().filter(|c| !c.is_control()),
                 100,
@@ -601,8 +711,9 @@ impl<TPlat: PlatformRef> Background<TPlat> {
                     .printed_legacy_json_rpc_warning
                     .swap(true, atomic::Ordering::Relaxed)
                 {
-                    log::warn!(
-                        target: &self.log_target,
+                    util::log!(
+                        Warn,
+                        &self.log_target,
                         "The JSON-RPC client has just called a JSON-RPC function from the legacy \
                         JSON-RPC API ({}). Legacy JSON-RPC functions have loose semantics and \
                         cannot be properly implemented on a light client. You are encouraged to \
@@ -614,7 +725,13 @@ impl<TPlat: PlatformRef> Background<TPlat> {
                     )
                 }
             }
-            methods::MethodCall::chainHead_unstable_body { .. }
+            methods::MethodCall::archive_unstable_body { .. }
+            | methods::MethodCall::archive_unstable_finalizedHeight { .. }
+            | methods::MethodCall::archive_unstable_genesisHash { .. }
+            | methods::MethodCall::archive_unstable_hashByHeight { .. }
+            | methods::MethodCall::archive_unstable_header { .. }
+            | methods::MethodCall::archive_unstable_storage { .. }
+            | methods::MethodCall::chainHead_unstable_body { .. }
             | methods::MethodCall::chainHead_unstable_call { .. }
             | methods::MethodCall::chainHead_unstable_continue { .. }
             | methods::MethodCall::chainHead_unstable_follow { .. }
@@ -626,6 +743,8 @@ impl<TPlat: PlatformRef> Background<TPlat> {
             | methods::MethodCall::chainSpec_v1_chainName { .. }
             | methods::MethodCall::chainSpec_v1_genesisHash { .. }
             | methods::MethodCall::chainSpec_v1_properties { .. }
+            | methods::MethodCall::engine_createBlock { .. }
+            | methods::MethodCall::engine_finalizeBlock { .. }
             | methods::MethodCall::rpc_methods { .. }
             | methods::MethodCall::sudo_unstable_p2pDiscover { .. }
             | methods::MethodCall::sudo_unstable_version { .. }
@@ -633,7 +752,11 @@ impl<TPlat: PlatformRef> Background<TPlat> {
             | methods::MethodCall::transaction_unstable_unwatch { .. }
             | methods::MethodCall::network_unstable_subscribeEvents { .. }
             | methods::MethodCall::network_unstable_unsubscribeEvents { .. }
-            | methods::MethodCall::chainHead_unstable_finalizedDatabase { .. } => {}
+            | methods::MethodCall::chainHead_unstable_finalizedDatabase { .. }
+            | methods::MethodCall::smoldot_unstable_reserveAccountNonce { .. }
+            | methods::MethodCall::smoldot_unstable_releaseAccountNonce { .. }
+            | methods::MethodCall::smoldot_unstable_submitTransaction { .. }
+            | methods::MethodCall::smoldot_unstable_checkRuntimeUpgrade { .. } => {}
         }
 
         // Each call is handled in a separate method.
@@ -658,7 +781,12 @@ impl<TPlat: PlatformRef> Background<TPlat> {
 
             _method @ methods::MethodCall::network_unstable_subscribeEvents { .. } => {
                 // TODO: implement the ones that make sense to implement ^
-                log::error!(target: &self.log_target, "JSON-RPC call not supported yet: {:?}", _method);
+                util::log!(
+                    Error,
+                    &self.log_target,
+                    "JSON-RPC call not supported yet: {:?}",
+                    _method
+                );
                 request.fail(json_rpc::parse::ErrorResponse::ServerError(
                     -32000,
                     "Not implemented in smoldot yet",
@@ -712,6 +840,99 @@ impl<TPlat: PlatformRef> Background<TPlat> {
         }
     }
 
+    /// Handles a call to [`methods::MethodCall::smoldot_unstable_reserveAccountNonce`].
+    async fn smoldot_unstable_reserve_account_nonce(
+        self: &Arc<Self>,
+        request: service::RequestProcess,
+    ) {
+        let methods::MethodCall::smoldot_unstable_reserveAccountNonce { account } =
+            request.request()
+        else {
+            unreachable!()
+        };
+
+        let Some(request) = self.check_account_prefix(request, &account) else {
+            return;
+        };
+
+        let Some(nonce_service) = &self.nonce_service else {
+            request.fail(json_rpc::parse::ErrorResponse::ServerError(
+                -32000,
+                "Nonce tracking is disabled for this chain",
+            ));
+            return;
+        };
+
+        match nonce_service.reserve_nonce(account.public_key).await {
+            Ok(nonce) => {
+                request.respond(methods::Response::smoldot_unstable_reserveAccountNonce(
+                    nonce,
+                ));
+            }
+            Err(error) => request.fail(json_rpc::parse::ErrorResponse::ServerError(
+                -32000,
+                &error.to_string(),
+            )),
+        }
+    }
+
+    /// Handles a call to [`methods::MethodCall::smoldot_unstable_releaseAccountNonce`].
+    async fn smoldot_unstable_release_account_nonce(
+        self: &Arc<Self>,
+        request: service::RequestProcess,
+    ) {
+        let methods::MethodCall::smoldot_unstable_releaseAccountNonce { account, nonce } =
+            request.request()
+        else {
+            unreachable!()
+        };
+
+        let Some(request) = self.check_account_prefix(request, &account) else {
+            return;
+        };
+
+        let Some(nonce_service) = &self.nonce_service else {
+            request.fail(json_rpc::parse::ErrorResponse::ServerError(
+                -32000,
+                "Nonce tracking is disabled for this chain",
+            ));
+            return;
+        };
+
+        nonce_service.release_nonce(account.public_key, nonce).await;
+        request.respond(methods::Response::smoldot_unstable_releaseAccountNonce(()));
+    }
+
+    /// Verifies that the SS58 prefix of the given account, passed as parameter of the given
+    /// request, matches the one of the chain.
+    ///
+    /// On mismatch, the request is answered with an error and `None` is returned. Otherwise, the
+    /// request is given back.
+    fn check_account_prefix(
+        &self,
+        request: service::RequestProcess,
+        account: &methods::AccountId,
+    ) -> Option<service::RequestProcess> {
+        let Some(expected_prefix) = self.chain_ss58_prefix else {
+            return Some(request);
+        };
+
+        match account.check_chain_prefix(expected_prefix) {
+            Ok(()) => Some(request),
+            Err(error) => {
+                request.fail_with_attached_json(
+                    json_rpc::parse::ErrorResponse::InvalidParams,
+                    &serde_json::to_string(&error.to_string()).unwrap(),
+                );
+                None
+            }
+        }
+    }
+
+    /// Queries the storage of the given block from the network.
+    ///
+    /// `total_attempts` and `timeout_per_request` are ignored if
+    /// [`NetworkRequestsConfig::storage`] is `Some`.
     async fn storage_query(
         &self,
         keys: impl Iterator<Item = impl AsRef<[u8]> + Clone> + Clone,
@@ -720,6 +941,10 @@ impl<TPlat: PlatformRef> Background<TPlat> {
         timeout_per_request: Duration,
         max_parallel: NonZeroU32,
     ) -> Result<Vec<Option<Vec<u8>>>, StorageQueryError> {
+        let (total_attempts, timeout_per_request) = self
+            .network_requests
+            .storage_or(total_attempts, timeout_per_request);
+
         let (state_trie_root_hash, block_number) = {
             let (tx, rx) = oneshot::channel();
             self.to_legacy
@@ -830,6 +1055,8 @@ impl<TPlat: PlatformRef> Background<TPlat> {
 
         // Download the runtime of this block. This takes a long time as the runtime is rather
         // big (around 1MiB in general).
+        let (total_attempts, timeout_per_request) =
+            self.network_requests.storage_or(3, Duration::from_secs(20));
         let (storage_code, storage_heap_pages, code_merkle_value, code_closest_ancestor_excluding) = {
             let entries = self
                 .sync_service
@@ -853,8 +1080,8 @@ impl<TPlat: PlatformRef> Background<TPlat> {
                         },
                     ]
                     .into_iter(),
-                    3,
-                    Duration::from_secs(20),
+                    total_attempts,
+                    timeout_per_request,
                     NonZeroU32::new(1).unwrap(),
                 )
                 .await
@@ -950,7 +1177,7 @@ impl<TPlat: PlatformRef> Background<TPlat> {
         timeout_per_request: Duration,
         max_parallel: NonZeroU32,
     ) -> Result<RuntimeCallResult, RuntimeCallError> {
-        let (return_value, api_version) = self
+        let result = self
             .runtime_call_inner(
                 block_hash,
                 Some((runtime_api, required_api_version_range)),
@@ -961,10 +1188,8 @@ impl<TPlat: PlatformRef> Background<TPlat> {
                 max_parallel,
             )
             .await?;
-        Ok(RuntimeCallResult {
-            return_value,
-            api_version: api_version.unwrap(),
-        })
+        debug_assert!(result.api_version.is_some());
+        Ok(result)
     }
 
     /// Performs a runtime call to a random block.
@@ -979,7 +1204,7 @@ impl<TPlat: PlatformRef> Background<TPlat> {
         timeout_per_request: Duration,
         max_parallel: NonZeroU32,
     ) -> Result<Vec<u8>, RuntimeCallError> {
-        let (return_value, _api_version) = self
+        let result = self
             .runtime_call_inner(
                 block_hash,
                 None::<(&str, ops::RangeFull)>,
@@ -990,11 +1215,14 @@ impl<TPlat: PlatformRef> Background<TPlat> {
                 max_parallel,
             )
             .await?;
-        debug_assert!(_api_version.is_none());
-        Ok(return_value)
+        debug_assert!(result.api_version.is_none());
+        Ok(result.return_value)
     }
 
     /// Performs a runtime call to a random block.
+    ///
+    /// `total_attempts` and `timeout_per_request` are ignored if
+    /// [`NetworkRequestsConfig::call`] is `Some`.
     async fn runtime_call_inner(
         self: &Arc<Self>,
         block_hash: &[u8; 32],
@@ -1004,7 +1232,11 @@ impl<TPlat: PlatformRef> Background<TPlat> {
         total_attempts: u32,
         timeout_per_request: Duration,
         max_parallel: NonZeroU32,
-    ) -> Result<(Vec<u8>, Option<u32>), RuntimeCallError> {
+    ) -> Result<RuntimeCallResult, RuntimeCallError> {
+        let (total_attempts, timeout_per_request) = self
+            .network_requests
+            .call_or(total_attempts, timeout_per_request);
+
         // This function contains two steps: obtaining the runtime of the block in question,
         // then performing the actual call. The first step is the longest and most difficult.
         let precall = self.runtime_access(block_hash).await?;
@@ -1016,6 +1248,9 @@ impl<TPlat: PlatformRef> Background<TPlat> {
                 total_attempts,
                 timeout_per_request,
                 max_parallel,
+                // JSON-RPC clients are typically waiting for the response, so the request is
+                // sent to a second peer if the first one is slow to answer.
+                Some(Duration::from_millis(500)),
             )
             .await
             .unwrap(); // TODO: don't unwrap
@@ -1052,7 +1287,7 @@ impl<TPlat: PlatformRef> Background<TPlat> {
             function_to_call,
             parameter: call_parameters,
             storage_main_trie_changes: Default::default(),
-            max_log_level: 0,
+            max_log_level: self.log_target.runtime_max_log_level(),
             calculate_trie_changes: false,
         }) {
             Ok(vm) => vm,
@@ -1062,12 +1297,17 @@ impl<TPlat: PlatformRef> Background<TPlat> {
             }
         };
 
-        loop {
+        let result = loop {
             match runtime_call {
                 runtime_host::RuntimeHostVm::Finished(Ok(success)) => {
-                    let output = success.virtual_machine.value().as_ref().to_vec();
+                    let return_value = success.virtual_machine.value().as_ref().to_vec();
                     runtime_call_lock.unlock(success.virtual_machine.into_prototype());
-                    break Ok((output, runtime_api_version));
+                    break Ok(RuntimeCallResult {
+                        return_value,
+                        api_version: runtime_api_version,
+                        logs: success.logs,
+                        logs_truncated: success.logs_truncated,
+                    });
                 }
                 runtime_host::RuntimeHostVm::Finished(Err(error)) => {
                     runtime_call_lock.unlock(error.prototype);
@@ -1146,7 +1386,27 @@ impl<TPlat: PlatformRef> Background<TPlat> {
                     break Err(RuntimeCallError::ForbiddenHostCall);
                 }
             }
+        };
+
+        if let Ok(result) = &result {
+            if !result.logs.is_empty() {
+                util::log!(
+                    Debug,
+                    &self.log_target,
+                    "RuntimeCall(block={}, function={}) => runtime logs{}:\n{}",
+                    HashDisplay(block_hash),
+                    function_to_call,
+                    if result.logs_truncated {
+                        " (truncated)"
+                    } else {
+                        ""
+                    },
+                    result.logs.trim_end()
+                );
+            }
         }
+
+        result
     }
 }
 
@@ -1188,5 +1448,12 @@ enum RuntimeCallError {
 #[derive(Debug)]
 struct RuntimeCallResult {
     return_value: Vec<u8>,
-    api_version: u32,
+    /// Version of the runtime API that has been checked. `None` if the version wasn't checked.
+    api_version: Option<u32>,
+    /// Concatenation of all the log messages printed by the runtime during the call. Always
+    /// empty if the `Debug` log level is disabled for this chain.
+    logs: String,
+    /// `true` if the runtime has printed more logs than could fit in
+    /// [`RuntimeCallResult::logs`], in which case the latest log messages are missing.
+    logs_truncated: bool,
 }
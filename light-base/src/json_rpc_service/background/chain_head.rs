@@ -19,7 +19,10 @@
 
 use super::Background;
 
-use crate::{platform::PlatformRef, runtime_service, sync_service};
+use crate::{
+    json_rpc_service::NetworkRequestsConfig, platform::PlatformRef, runtime_service, sync_service,
+    util,
+};
 
 use alloc::{
     borrow::ToOwned as _,
@@ -29,7 +32,7 @@ use alloc::{
     vec::Vec,
 };
 use core::{
-    iter,
+    cmp, iter,
     num::{NonZeroU32, NonZeroUsize},
     time::Duration,
 };
@@ -40,8 +43,9 @@ use smoldot::{
     chain::fork_tree,
     executor::{self, runtime_host},
     header,
+    informant::HashDisplay,
     json_rpc::{self, methods, service},
-    network::protocol,
+    network::codec,
 };
 
 impl<TPlat: PlatformRef> Background<TPlat> {
@@ -91,8 +95,9 @@ impl<TPlat: PlatformRef> Background<TPlat> {
             // JSON-RPC client implementations are made aware of this limit. This number of 2 might
             // be relaxed and/or configurable in the future.
             if lock.len() >= 2 {
-                log::warn!(
-                    target: &self.log_target,
+                util::log!(
+                    Warn,
+                    &self.log_target,
                     "Rejected `chainHead_unstable_follow` subscription due to limit reached."
                 );
                 request.fail(json_rpc::parse::ErrorResponse::ApplicationDefined(
@@ -120,12 +125,12 @@ impl<TPlat: PlatformRef> Background<TPlat> {
             either::Right(self.sync_service.subscribe_all(32, false).await)
         };
 
-        let (non_finalized_blocks, pinned_blocks_headers, events) = {
+        let (finalized_block_hash, non_finalized_blocks, pinned_blocks_headers, events) = {
             let mut pinned_blocks_headers =
                 HashMap::with_capacity_and_hasher(0, Default::default());
             let mut non_finalized_blocks = fork_tree::ForkTree::new();
 
-            match &events {
+            let finalized_block_hash = match &events {
                 either::Left((subscribe_all, _)) => {
                     let finalized_block_hash = header::hash_from_scale_encoded_header(
                         &subscribe_all.finalized_block_scale_encoded_header[..],
@@ -203,6 +208,8 @@ impl<TPlat: PlatformRef> Background<TPlat> {
                                 .await;
                         }
                     }
+
+                    finalized_block_hash
                 }
                 either::Right(subscribe_all) => {
                     let finalized_block_hash = header::hash_from_scale_encoded_header(
@@ -276,10 +283,17 @@ impl<TPlat: PlatformRef> Background<TPlat> {
                                 .await;
                         }
                     }
+
+                    finalized_block_hash
                 }
-            }
+            };
 
-            (non_finalized_blocks, pinned_blocks_headers, events)
+            (
+                finalized_block_hash,
+                non_finalized_blocks,
+                pinned_blocks_headers,
+                events,
+            )
         };
 
         self.platform
@@ -287,13 +301,19 @@ impl<TPlat: PlatformRef> Background<TPlat> {
                 let log_target = self.log_target.clone();
                 let runtime_service = self.runtime_service.clone();
                 let sync_service = self.sync_service.clone();
+                let network_requests = self.network_requests;
                 let platform = self.platform.clone();
                 let (to_operation_handlers, from_operation_handlers) = async_channel::bounded(8);
 
                 ChainHeadFollowTask {
                     platform,
+                    finalized_block_hash,
                     non_finalized_blocks,
                     pinned_blocks_headers,
+                    detached_pinned_blocks: hashbrown::HashSet::with_capacity_and_hasher(
+                        0,
+                        Default::default(),
+                    ),
                     subscription: match events {
                         either::Left((sub, id)) => Subscription::WithRuntime {
                             notifications: sub.new_blocks,
@@ -304,6 +324,7 @@ impl<TPlat: PlatformRef> Background<TPlat> {
                     log_target,
                     runtime_service,
                     sync_service,
+                    network_requests,
                     next_operation_id: 1,
                     to_main_task: to_operation_handlers,
                     from_operation_handlers,
@@ -493,21 +514,64 @@ fn convert_runtime_spec(
     }
 }
 
+/// Maximum number of blocks that a `chainHead_unstable_follow` subscription can keep pinned
+/// before the subscription is stopped.
+const MAX_PINNED_BLOCKS: usize = 256;
+
+/// Sends a `stop` event to the JSON-RPC client, followed with a
+/// [`methods::ServerToClient::smoldot_unstable_subscriptionStopped`] notification indicating
+/// the reason why the subscription has been stopped.
+async fn send_stop(
+    subscription: &mut service::Subscription,
+    subscription_id: &str,
+    reason: methods::SubscriptionStopReason,
+) {
+    subscription
+        .send_notification(methods::ServerToClient::chainHead_unstable_followEvent {
+            subscription: subscription_id.into(),
+            result: methods::FollowEvent::Stop {},
+        })
+        .await;
+    subscription
+        .send_notification(
+            methods::ServerToClient::smoldot_unstable_subscriptionStopped {
+                subscription: subscription_id.into(),
+                result: reason,
+            },
+        )
+        .await;
+}
+
 struct ChainHeadFollowTask<TPlat: PlatformRef> {
+    /// Hash of the current finalized block, as reported to the JSON-RPC client.
+    finalized_block_hash: [u8; 32],
+
     /// Tree of hashes of all the current non-finalized blocks. This includes unpinned blocks.
     non_finalized_blocks: fork_tree::ForkTree<[u8; 32]>,
 
     /// For each pinned block hash, the SCALE-encoded header of the block.
     pinned_blocks_headers: hashbrown::HashMap<[u8; 32], Vec<u8>, fnv::FnvBuildHasher>,
 
+    /// Subset of the keys of [`ChainHeadFollowTask::pinned_blocks_headers`] that were pinned
+    /// within a runtime service subscription that has since then been reset. These blocks are
+    /// still pinned from the point of view of the JSON-RPC client, but they are no longer pinned
+    /// within the runtime service, and runtime calls can't be performed on them.
+    ///
+    /// Always empty if [`ChainHeadFollowTask::subscription`] is
+    /// [`Subscription::WithoutRuntime`].
+    detached_pinned_blocks: hashbrown::HashSet<[u8; 32], fnv::FnvBuildHasher>,
+
     platform: TPlat,
 
     subscription: Subscription<TPlat>,
 
-    log_target: String,
+    log_target: util::LogTarget,
     runtime_service: Arc<runtime_service::RuntimeService<TPlat>>,
     sync_service: Arc<sync_service::SyncService<TPlat>>,
 
+    /// See [`super::Background::network_requests`].
+    network_requests: NetworkRequestsConfig,
+
     to_main_task: async_channel::Sender<OperationEvent>,
 
     from_operation_handlers: async_channel::Receiver<OperationEvent>,
@@ -606,19 +670,21 @@ impl<TPlat: PlatformRef> ChainHeadFollowTask<TPlat> {
                     .await
             };
 
-            // TODO: doesn't enforce any maximum number of pinned blocks
             match outcome {
                 WhatHappened::Unsubscribed => return,
                 WhatHappened::SubscriptionDead => {
-                    subscription
-                        .send_notification(
-                            methods::ServerToClient::chainHead_unstable_followEvent {
-                                subscription: (&subscription_id).into(),
-                                result: methods::FollowEvent::Stop {},
-                            },
+                    if !self
+                        .reconcile_after_reset(&mut subscription, &subscription_id)
+                        .await
+                    {
+                        send_stop(
+                            &mut subscription,
+                            &subscription_id,
+                            methods::SubscriptionStopReason::FinalityGap {},
                         )
                         .await;
-                    break;
+                        break;
+                    }
                 }
 
                 WhatHappened::OperationEvent {
@@ -665,6 +731,7 @@ impl<TPlat: PlatformRef> ChainHeadFollowTask<TPlat> {
                     let mut finalized_blocks_hashes = Vec::new();
                     let mut pruned_blocks_hashes = Vec::new();
 
+                    self.finalized_block_hash = hash;
                     let node_index = self.non_finalized_blocks.find(|b| *b == hash).unwrap();
                     for pruned in self.non_finalized_blocks.prune_ancestors(node_index) {
                         if pruned.is_prune_target_ancestor {
@@ -804,9 +871,248 @@ impl<TPlat: PlatformRef> ChainHeadFollowTask<TPlat> {
                 }
                 WhatHappened::NewRequest(rq) => self.on_foreground_message(rq).await,
             }
+
+            // The JSON-RPC client is expected to unpin blocks as soon as it doesn't need them
+            // anymore. If it doesn't, the subscription is stopped rather than letting the number
+            // of pinned blocks grow indefinitely.
+            if self.pinned_blocks_headers.len() > MAX_PINNED_BLOCKS {
+                util::log!(
+                    Debug,
+                    &self.log_target,
+                    "chainHead_follow subscription {} stopped due to too many pinned blocks",
+                    subscription_id
+                );
+                send_stop(
+                    &mut subscription,
+                    &subscription_id,
+                    methods::SubscriptionStopReason::TooManyPinnedBlocks {
+                        max_pinned_blocks: u32::try_from(MAX_PINNED_BLOCKS).unwrap(),
+                    },
+                )
+                .await;
+                break;
+            }
         }
     }
 
+    /// Called when the subscription to the runtime or sync service has been closed, for example
+    /// after a gap in the finality caused by a warp sync.
+    ///
+    /// Subscribes again and, if the new finalized block is already known to the JSON-RPC client,
+    /// reports the differences between the previous and the new state of the chain through
+    /// `newBlock`, `bestBlockChanged` and `finalized` events.
+    ///
+    /// Returns `false` if the new state of the chain can't be reconciled with the previous one,
+    /// in which case the JSON-RPC subscription must be stopped.
+    async fn reconcile_after_reset(
+        &mut self,
+        subscription: &mut service::Subscription,
+        subscription_id: &str,
+    ) -> bool {
+        struct NewBlock {
+            hash: [u8; 32],
+            parent_hash: [u8; 32],
+            scale_encoded_header: Vec<u8>,
+            is_new_best: bool,
+            new_runtime: Option<Result<executor::CoreVersion, runtime_service::RuntimeError>>,
+        }
+
+        // Subscribe again, and normalize the content of the new subscription.
+        // TODO: subscribing to the runtime service might take a long time, during which the requests aren't processed
+        let (new_subscription, new_finalized_block_header, new_blocks) = match self.subscription {
+            Subscription::WithRuntime { .. } => {
+                let subscribe_all = self
+                    .runtime_service
+                    .subscribe_all("chainHead_follow", 32, NonZeroUsize::new(32).unwrap())
+                    .await;
+                let new_blocks = subscribe_all
+                    .non_finalized_blocks_ancestry_order
+                    .into_iter()
+                    .map(|block| NewBlock {
+                        hash: header::hash_from_scale_encoded_header(&block.scale_encoded_header),
+                        parent_hash: block.parent_hash,
+                        scale_encoded_header: block.scale_encoded_header,
+                        is_new_best: block.is_new_best,
+                        new_runtime: block.new_runtime,
+                    })
+                    .collect::<Vec<_>>();
+                let subscription_id = subscribe_all.new_blocks.id();
+                (
+                    Subscription::WithRuntime {
+                        notifications: subscribe_all.new_blocks,
+                        subscription_id,
+                    },
+                    subscribe_all.finalized_block_scale_encoded_header,
+                    new_blocks,
+                )
+            }
+            Subscription::WithoutRuntime(_) => {
+                let subscribe_all = self.sync_service.subscribe_all(32, false).await;
+                let new_blocks = subscribe_all
+                    .non_finalized_blocks_ancestry_order
+                    .into_iter()
+                    .map(|block| NewBlock {
+                        hash: header::hash_from_scale_encoded_header(&block.scale_encoded_header),
+                        parent_hash: block.parent_hash,
+                        scale_encoded_header: block.scale_encoded_header,
+                        is_new_best: block.is_new_best,
+                        new_runtime: None,
+                    })
+                    .collect::<Vec<_>>();
+                (
+                    Subscription::WithoutRuntime(subscribe_all.new_blocks),
+                    subscribe_all.finalized_block_scale_encoded_header,
+                    new_blocks,
+                )
+            }
+        };
+
+        let new_finalized_block_hash =
+            header::hash_from_scale_encoded_header(&new_finalized_block_header);
+
+        // Reconciliation is only possible if the new finalized block is already known to the
+        // JSON-RPC client. This is typically not the case after a warp sync, as the new finalized
+        // block is then far ahead of the previous one.
+        let mut finalized_blocks_hashes = Vec::new();
+        let mut pruned_blocks_hashes = Vec::new();
+        if new_finalized_block_hash != self.finalized_block_hash {
+            // TODO: O(n)
+            let Some(node_index) = self
+                .non_finalized_blocks
+                .find(|b| *b == new_finalized_block_hash)
+            else {
+                return false;
+            };
+
+            for pruned in self.non_finalized_blocks.prune_ancestors(node_index) {
+                if pruned.is_prune_target_ancestor {
+                    finalized_blocks_hashes.push(methods::HashHexString(pruned.user_data));
+                } else {
+                    pruned_blocks_hashes.push(methods::HashHexString(pruned.user_data));
+                }
+            }
+        }
+
+        util::log!(
+            Debug,
+            &self.log_target,
+            "chainHead_follow subscription reset, reconciling with new finalized block {}",
+            HashDisplay(&new_finalized_block_hash)
+        );
+
+        // Blocks that were known to the JSON-RPC client and that descend from the new finalized
+        // block but aren't part of the new subscription are reported as pruned.
+        let previously_known = self
+            .non_finalized_blocks
+            .iter_unordered()
+            .map(|(_, hash)| *hash)
+            .chain(iter::once(new_finalized_block_hash))
+            .collect::<hashbrown::HashSet<_, fnv::FnvBuildHasher>>();
+        pruned_blocks_hashes.extend(
+            previously_known
+                .iter()
+                .filter(|hash| {
+                    **hash != new_finalized_block_hash
+                        && !new_blocks.iter().any(|block| block.hash == **hash)
+                })
+                .map(|hash| methods::HashHexString(*hash)),
+        );
+
+        // The blocks pinned within the previous runtime service subscription are no longer
+        // pinned. Those that are part of the new subscription are removed from this list below.
+        if let Subscription::WithRuntime { .. } = self.subscription {
+            self.detached_pinned_blocks
+                .extend(self.pinned_blocks_headers.keys().copied());
+        }
+        self.subscription = new_subscription;
+        self.finalized_block_hash = new_finalized_block_hash;
+        self.non_finalized_blocks = fork_tree::ForkTree::with_capacity(new_blocks.len());
+
+        // All the blocks of the new subscription are pinned within the runtime service. Blocks
+        // that are already known and that the JSON-RPC client has already unpinned must be
+        // unpinned.
+        for hash in iter::once(&new_finalized_block_hash)
+            .chain(new_blocks.iter().map(|block| &block.hash))
+            .filter(|hash| previously_known.contains(*hash))
+        {
+            if !self.pinned_blocks_headers.contains_key(hash) {
+                if let Subscription::WithRuntime {
+                    subscription_id, ..
+                } = self.subscription
+                {
+                    self.runtime_service
+                        .unpin_block(subscription_id, hash)
+                        .await;
+                }
+            } else {
+                self.detached_pinned_blocks.remove(hash);
+            }
+        }
+
+        let mut best_block_hash = new_finalized_block_hash;
+        for block in new_blocks {
+            let parent_node_index = if block.parent_hash == new_finalized_block_hash {
+                None
+            } else {
+                // TODO: O(n)
+                Some(
+                    self.non_finalized_blocks
+                        .find(|b| *b == block.parent_hash)
+                        .unwrap(),
+                )
+            };
+            self.non_finalized_blocks
+                .insert(parent_node_index, block.hash);
+
+            if block.is_new_best {
+                best_block_hash = block.hash;
+            }
+
+            if previously_known.contains(&block.hash) {
+                continue;
+            }
+
+            let _was_in = self
+                .pinned_blocks_headers
+                .insert(block.hash, block.scale_encoded_header);
+            debug_assert!(_was_in.is_none());
+
+            subscription
+                .send_notification(methods::ServerToClient::chainHead_unstable_followEvent {
+                    subscription: subscription_id.into(),
+                    result: methods::FollowEvent::NewBlock {
+                        block_hash: methods::HashHexString(block.hash),
+                        parent_block_hash: methods::HashHexString(block.parent_hash),
+                        new_runtime: block.new_runtime.as_ref().map(convert_runtime_spec),
+                    },
+                })
+                .await;
+        }
+
+        subscription
+            .send_notification(methods::ServerToClient::chainHead_unstable_followEvent {
+                subscription: subscription_id.into(),
+                result: methods::FollowEvent::BestBlockChanged {
+                    best_block_hash: methods::HashHexString(best_block_hash),
+                },
+            })
+            .await;
+
+        if !finalized_blocks_hashes.is_empty() || !pruned_blocks_hashes.is_empty() {
+            subscription
+                .send_notification(methods::ServerToClient::chainHead_unstable_followEvent {
+                    subscription: subscription_id.into(),
+                    result: methods::FollowEvent::Finalized {
+                        finalized_blocks_hashes,
+                        pruned_blocks_hashes,
+                    },
+                })
+                .await;
+        }
+
+        true
+    }
+
     async fn on_foreground_message(&mut self, request: service::RequestProcess) {
         match request.request() {
             methods::MethodCall::chainHead_unstable_body { .. } => {
@@ -853,6 +1159,10 @@ impl<TPlat: PlatformRef> ChainHeadFollowTask<TPlat> {
                 if is_valid {
                     for hash in all_hashes {
                         self.pinned_blocks_headers.remove(hash);
+                        if self.detached_pinned_blocks.remove(hash) {
+                            // Block is no longer pinned within the runtime service.
+                            continue;
+                        }
                         if let Subscription::WithRuntime {
                             subscription_id, ..
                         } = self.subscription
@@ -929,18 +1239,24 @@ impl<TPlat: PlatformRef> ChainHeadFollowTask<TPlat> {
         self.platform
             .spawn_task(format!("{}-chain-head-body", self.log_target).into(), {
                 let sync_service = self.sync_service.clone();
+                let (total_attempts, timeout_per_request) = self
+                    .network_requests
+                    .blocks_or(u32::MAX, Duration::from_secs(20));
                 async move {
-                    // TODO: right now we query the header because the underlying function returns an error if we don't
+                    // The header is queried as well in order to be able to verify the body.
+                    // All the peers that are assumed to know the block are tried one after the
+                    // other, as some of them might have pruned the block's body. The operation
+                    // is only reported as inaccessible once all of them have been tried.
                     let future = sync_service.clone().block_query(
                         block_number,
                         hash.0,
-                        protocol::BlocksRequestFields {
+                        codec::BlocksRequestFields {
                             header: true,
                             body: true,
                             justifications: false,
                         },
-                        3,
-                        Duration::from_secs(20),
+                        total_attempts,
+                        timeout_per_request,
                         NonZeroU32::new(2).unwrap(),
                     );
 
@@ -951,22 +1267,13 @@ impl<TPlat: PlatformRef> ChainHeadFollowTask<TPlat> {
                         None => return, // JSON-RPC client has unsubscribed in the meanwhile.
                     };
 
-                    // We must check whether the body is present in the response and valid.
-                    // TODO: should try the request again with a different peer instead of failing immediately
-                    let body = match outcome {
-                        Ok(outcome) => {
-                            if let Some(body) = outcome.body {
-                                if header::extrinsics_root(&body) == extrinsics_root {
-                                    Ok(body)
-                                } else {
-                                    Err(())
-                                }
-                            } else {
-                                Err(())
-                            }
-                        }
-                        Err(err) => Err(err),
-                    };
+                    // If successful, the `block_query` function guarantees that the body is
+                    // present and matches the header of the requested block.
+                    let body = outcome.map(|block| {
+                        let body = block.body.unwrap();
+                        debug_assert_eq!(header::extrinsics_root(&body), extrinsics_root);
+                        body
+                    });
 
                     // Send back the response.
                     match body {
@@ -1026,8 +1333,9 @@ impl<TPlat: PlatformRef> ChainHeadFollowTask<TPlat> {
                 -32000,
                 "Child key storage queries not supported yet",
             ));
-            log::warn!(
-                target: &self.log_target,
+            util::log!(
+                Warn,
+                &self.log_target,
                 "chainHead_unstable_storage has been called with a non-null childTrie. \
                 This isn't supported by smoldot yet."
             );
@@ -1098,6 +1406,9 @@ impl<TPlat: PlatformRef> ChainHeadFollowTask<TPlat> {
         self.platform
             .spawn_task(format!("{}-chain-head-storage", self.log_target).into(), {
                 let sync_service = self.sync_service.clone();
+                let (total_attempts, timeout_per_request) = self
+                    .network_requests
+                    .storage_or(3, Duration::from_secs(20));
                 async move {
                     let decoded_header = match header::decode(
                         &block_scale_encoded_header,
@@ -1150,8 +1461,8 @@ impl<TPlat: PlatformRef> ChainHeadFollowTask<TPlat> {
                         &hash.0,
                         decoded_header.state_root,
                         queries.into_iter(),
-                        3,
-                        Duration::from_secs(20),
+                        total_attempts,
+                        timeout_per_request,
                         NonZeroU32::new(2).unwrap(),
                     );
 
@@ -1249,18 +1560,24 @@ impl<TPlat: PlatformRef> ChainHeadFollowTask<TPlat> {
     }
 
     async fn start_chain_head_call(&mut self, request: service::RequestProcess) {
-        let (hash, function_to_call, call_parameters) = {
+        let (hash, function_to_call, call_parameters, report_runtime_logs) = {
             let methods::MethodCall::chainHead_unstable_call {
                 hash,
                 function,
                 call_parameters,
+                smoldot_runtime_logs,
                 ..
             } = request.request()
             else {
                 unreachable!()
             };
 
-            (hash, function.into_owned(), call_parameters.0)
+            (
+                hash,
+                function.into_owned(),
+                call_parameters.0,
+                smoldot_runtime_logs.unwrap_or(false),
+            )
         };
 
         // Check whether there is an operation slot available.
@@ -1285,6 +1602,15 @@ impl<TPlat: PlatformRef> ChainHeadFollowTask<TPlat> {
                     return;
                 }
 
+                if self.detached_pinned_blocks.contains(&hash.0) {
+                    // The block was pinned within a runtime service subscription that has been
+                    // reset, and its runtime is no longer accessible.
+                    request.respond(methods::Response::chainHead_unstable_call(
+                        methods::ChainHeadBodyCallReturn::LimitReached {},
+                    ));
+                    return;
+                }
+
                 match self
                     .runtime_service
                     .pinned_block_runtime_access(subscription_id, &hash.0)
@@ -1332,14 +1658,19 @@ impl<TPlat: PlatformRef> ChainHeadFollowTask<TPlat> {
         // Finish the call asynchronously.
         self.platform
             .spawn_task(format!("{}-chain-head-call", self.log_target).into(), {
+            let log_target = self.log_target.clone();
+            let (total_attempts, timeout_per_request) = self
+                .network_requests
+                .call_or(3, Duration::from_secs(20));
             async move {
                 let pre_runtime_call = {
                     let call_future = pre_runtime_call.start(
                         &function_to_call,
                         iter::once(&call_parameters),
-                        3,
-                        Duration::from_secs(20),
+                        total_attempts,
+                        timeout_per_request,
                         NonZeroU32::new(2).unwrap(),
+                        Some(Duration::from_millis(500)),
                     );
 
                     // Drive the future, but cancel execution if the JSON-RPC client unsubscribes.
@@ -1356,7 +1687,14 @@ impl<TPlat: PlatformRef> ChainHeadFollowTask<TPlat> {
                             function_to_call: &function_to_call,
                             parameter: iter::once(&call_parameters),
                             storage_main_trie_changes: Default::default(),
-                            max_log_level: 0,
+                            // If the JSON-RPC client has asked for the runtime logs, they
+                            // are captured up to the `Debug` level even if the logger doesn't
+                            // re-emit them.
+                            max_log_level: if report_runtime_logs {
+                                cmp::max(4, log_target.runtime_max_log_level())
+                            } else {
+                                log_target.runtime_max_log_level()
+                            },
                             calculate_trie_changes: false,
                         }) {
                             Err((error, prototype)) => {
@@ -1373,6 +1711,17 @@ impl<TPlat: PlatformRef> ChainHeadFollowTask<TPlat> {
                                 loop {
                                     match runtime_call {
                                         runtime_host::RuntimeHostVm::Finished(Ok(success)) => {
+                                            if !success.logs.is_empty() {
+                                                util::log!(
+                                                    Debug,
+                                                    &log_target,
+                                                    "ChainHeadCall(operation_id={}, function={}) => runtime logs{}:\n{}",
+                                                    operation_id,
+                                                    function_to_call,
+                                                    if success.logs_truncated { " (truncated)" } else { "" },
+                                                    success.logs.trim_end()
+                                                );
+                                            }
                                             let output =
                                                 success.virtual_machine.value().as_ref().to_owned();
                                             runtime_call_lock
@@ -1383,6 +1732,14 @@ impl<TPlat: PlatformRef> ChainHeadFollowTask<TPlat> {
                                                 notification: methods::FollowEvent::OperationCallDone {
                                                 operation_id: operation_id.clone().into(),
                                                 output: methods::HexString(output),
+                                                smoldot_runtime_logs: if report_runtime_logs {
+                                                    Some(methods::RuntimeLogs {
+                                                        logs: success.logs.into(),
+                                                        truncated: success.logs_truncated,
+                                                    })
+                                                } else {
+                                                    None
+                                                },
                                             }}).await;
                                             break;
                                         }
@@ -1570,6 +1927,17 @@ impl<TPlat: PlatformRef> ChainHeadFollowTask<TPlat> {
                             }
                         }).await;
                     }
+                    Err(error @ runtime_service::RuntimeCallError::LocalExecutionUnsupported)
+                    | Err(error @ runtime_service::RuntimeCallError::MissingFetchedEntry) => {
+                        let _ = to_main_task.send(OperationEvent {
+                            operation_id: operation_id.clone(),
+                            is_done: true,
+                            notification: methods::FollowEvent::OperationError {
+                                operation_id: operation_id.clone().into(),
+                                error: error.to_string().into(),
+                            }
+                        }).await;
+                    }
                 }
             }
         });
@@ -18,15 +18,63 @@
 //! All JSON-RPC method handlers that do nothing but return a value already found in the node.
 
 use super::{Background, PlatformRef};
+use crate::sync_service;
 
 use alloc::{borrow::Cow, format, string::ToString as _, sync::Arc, vec::Vec};
 use core::num::NonZeroUsize;
 use smoldot::{
     header,
     json_rpc::{methods, service},
-    network::protocol,
+    network::codec,
 };
 
+/// List of JSON-RPC methods that are known but not implemented by the light client, and that
+/// are thus not reported by `rpc_methods`.
+///
+/// Must be kept in sync with the list of methods that return an error in `background.rs`.
+const NOT_IMPLEMENTED_METHODS: &[&str] = &[
+    "account_nextIndex",
+    "author_hasKey",
+    "author_hasSessionKeys",
+    "author_insertKey",
+    "author_removeExtrinsic",
+    "author_rotateKeys",
+    "babe_epochAuthorship",
+    "childstate_getKeys",
+    "childstate_getStorage",
+    "childstate_getStorageHash",
+    "childstate_getStorageSize",
+    "grandpa_roundState",
+    "offchain_localStorageGet",
+    "offchain_localStorageSet",
+    "state_getPairs",
+    "state_getStorageHash",
+    "state_getStorageSize",
+    "state_queryStorage",
+    "system_addReservedPeer",
+    "system_networkState",
+    "system_removeReservedPeer",
+];
+
+/// List of JSON-RPC methods that are only functional when nonce tracking is enabled for the
+/// chain, and that are thus not reported by `rpc_methods` otherwise.
+///
+/// Must be kept in sync with the list of methods that require the nonce service in
+/// `background.rs`.
+const NONCE_TRACKING_METHODS: &[&str] = &[
+    "smoldot_unstable_reserveAccountNonce",
+    "smoldot_unstable_releaseAccountNonce",
+];
+
+/// Returns the list of JSON-RPC methods reported by `rpc_methods`.
+fn advertised_method_names(nonce_tracking: bool) -> impl Iterator<Item = &'static str> {
+    methods::MethodCall::method_names()
+        .filter(|n| !n.starts_with("archive_") && !n.starts_with("engine_"))
+        .filter(|n| *n != "smoldot_unstable_checkRuntimeUpgrade")
+        .filter(|n| !NOT_IMPLEMENTED_METHODS.contains(n))
+        .filter(move |n| nonce_tracking || !NONCE_TRACKING_METHODS.contains(n))
+}
+
 impl<TPlat: PlatformRef> Background<TPlat> {
     /// Handles a call to [`methods::MethodCall::chain_getFinalizedHead`].
     pub(super) async fn chain_get_finalized_head(
@@ -78,7 +126,7 @@ impl<TPlat: PlatformRef> Background<TPlat> {
     /// Handles a call to [`methods::MethodCall::rpc_methods`].
     pub(super) async fn rpc_methods(self: &Arc<Self>, request: service::RequestProcess) {
         request.respond(methods::Response::rpc_methods(methods::RpcMethods {
-            methods: methods::MethodCall::method_names()
+            methods: advertised_method_names(self.nonce_service.is_some())
                 .map(|n| n.into())
                 .collect(),
         }));
@@ -103,11 +151,16 @@ impl<TPlat: PlatformRef> Background<TPlat> {
 
     /// Handles a call to [`methods::MethodCall::system_health`].
     pub(super) async fn system_health(self: &Arc<Self>, request: service::RequestProcess) {
+        let near_head_of_chain = match *self.near_head_of_chain.lock().await {
+            Some(near_head_of_chain) => near_head_of_chain,
+            None => self.runtime_service.is_near_head_of_chain_heuristic().await,
+        };
+
         request.respond(methods::Response::system_health(methods::SystemHealth {
             // In smoldot, `is_syncing` equal to `false` means that GrandPa warp sync
             // is finished and that the block notifications report blocks that are
             // believed to be near the head of the chain.
-            is_syncing: !self.runtime_service.is_near_head_of_chain_heuristic().await,
+            is_syncing: !near_head_of_chain,
             peers: u64::try_from(self.sync_service.syncing_peers().await.len())
                 .unwrap_or(u64::max_value()),
             should_have_peers: self.chain_is_live,
@@ -144,24 +197,32 @@ impl<TPlat: PlatformRef> Background<TPlat> {
 
     /// Handles a call to [`methods::MethodCall::system_peers`].
     pub(super) async fn system_peers(self: &Arc<Self>, request: service::RequestProcess) {
-        request.respond(methods::Response::system_peers(
-            self.sync_service
-                .syncing_peers()
-                .await
-                .map(
-                    |(peer_id, role, best_number, best_hash)| methods::SystemPeer {
-                        peer_id: peer_id.to_string(),
-                        roles: match role {
-                            protocol::Role::Authority => methods::SystemPeerRole::Authority,
-                            protocol::Role::Full => methods::SystemPeerRole::Full,
-                            protocol::Role::Light => methods::SystemPeerRole::Light,
-                        },
-                        best_hash: methods::HashHexString(best_hash),
-                        best_number,
-                    },
-                )
-                .collect(),
-        ));
+        let mut peers = Vec::new();
+        for (peer_id, role, best_number, best_hash) in self.sync_service.syncing_peers().await {
+            let state_retention = match self.sync_service.peer_state_retention(&peer_id).await {
+                Some(sync_service::PeerStateRetention::Archive) => {
+                    Some(methods::SystemPeerStateRetention::Archive)
+                }
+                Some(sync_service::PeerStateRetention::Pruned) => {
+                    Some(methods::SystemPeerStateRetention::Pruned)
+                }
+                None => None,
+            };
+
+            peers.push(methods::SystemPeer {
+                peer_id: peer_id.to_string(),
+                roles: match role {
+                    codec::Role::Authority => methods::SystemPeerRole::Authority,
+                    codec::Role::Full => methods::SystemPeerRole::Full,
+                    codec::Role::Light => methods::SystemPeerRole::Light,
+                },
+                best_hash: methods::HashHexString(best_hash),
+                best_number,
+                state_retention,
+            });
+        }
+
+        request.respond(methods::Response::system_peers(peers));
     }
 
     /// Handles a call to [`methods::MethodCall::system_properties`].
@@ -205,3 +266,42 @@ impl<TPlat: PlatformRef> Background<TPlat> {
         ));
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{advertised_method_names, NONCE_TRACKING_METHODS, NOT_IMPLEMENTED_METHODS};
+    use alloc::vec::Vec;
+    use smoldot::json_rpc::methods;
+
+    #[test]
+    fn filtered_methods_exist() {
+        for name in NOT_IMPLEMENTED_METHODS.iter().chain(NONCE_TRACKING_METHODS) {
+            assert!(
+                methods::MethodCall::method_names().any(|n| n == *name),
+                "{name}"
+            );
+        }
+    }
+
+    #[test]
+    fn nonce_methods_only_advertised_when_enabled() {
+        let with = advertised_method_names(true).collect::<Vec<_>>();
+        let without = advertised_method_names(false).collect::<Vec<_>>();
+
+        for name in NONCE_TRACKING_METHODS {
+            assert!(with.contains(name));
+            assert!(!without.contains(name));
+        }
+
+        assert_eq!(with.len(), without.len() + NONCE_TRACKING_METHODS.len());
+        assert!(without.iter().all(|n| with.contains(n)));
+    }
+
+    #[test]
+    fn not_implemented_methods_never_advertised() {
+        for name in advertised_method_names(true) {
+            assert!(!NOT_IMPLEMENTED_METHODS.contains(&name));
+            assert!(!name.starts_with("archive_") && !name.starts_with("engine_"));
+        }
+    }
+}
@@ -31,10 +31,13 @@ use smoldot::{
     executor, header,
     informant::HashDisplay,
     json_rpc::{self, methods, service},
-    network::protocol,
+    network::codec,
 };
 
-use crate::{platform::PlatformRef, runtime_service, sync_service};
+use crate::{
+    json_rpc_service::NetworkRequestsConfig, platform::PlatformRef, runtime_service, sync_service,
+    util,
+};
 
 /// Message that can be passed to the task started with [`start_task`].
 pub(super) enum Message<TPlat: PlatformRef> {
@@ -109,12 +112,14 @@ pub(super) struct Config<TPlat: PlatformRef> {
     /// Access to the platform bindings.
     pub platform: TPlat,
     /// Prefix used for all the logging in this module.
-    pub log_target: String,
+    pub log_target: util::LogTarget,
     /// Sync service used to start networking requests.
     pub sync_service: Arc<sync_service::SyncService<TPlat>>,
     /// Runtime service used to subscribe to notifications regarding blocks and report them to
     /// the JSON-RPC client.
     pub runtime_service: Arc<runtime_service::RuntimeService<TPlat>>,
+    /// Timeouts and number of attempts of the networking requests.
+    pub network_requests: NetworkRequestsConfig,
 }
 
 /// Error potentially returned by [`Message::BlockStateRootAndNumber`].
@@ -145,6 +150,7 @@ pub(super) fn start_task<TPlat: PlatformRef>(
             best_block_report: Vec::with_capacity(4),
             sync_service: config.sync_service,
             runtime_service: config.runtime_service,
+            network_requests: config.network_requests,
             subscription: Subscription::NotCreated,
             requests_tx: async_channel::Sender::downgrade(&requests_tx),
             requests_rx,
@@ -189,13 +195,15 @@ pub(super) fn start_task<TPlat: PlatformRef>(
 
 struct Task<TPlat: PlatformRef> {
     /// See [`Config::log_target`].
-    log_target: String,
+    log_target: util::LogTarget,
     /// See [`Config::platform`].
     platform: TPlat,
     /// See [`Config::sync_service`].
     sync_service: Arc<sync_service::SyncService<TPlat>>,
     /// See [`Config::runtime_service`].
     runtime_service: Arc<runtime_service::RuntimeService<TPlat>>,
+    /// See [`Config::network_requests`].
+    network_requests: NetworkRequestsConfig,
 
     /// State of the subscription towards the runtime service.
     subscription: Subscription<TPlat>,
@@ -364,8 +372,9 @@ async fn run<TPlat: PlatformRef>(mut task: Task<TPlat>) {
                     ) {
                         Ok(h) => h,
                         Err(error) => {
-                            log::warn!(
-                                target: &task.log_target,
+                            util::log!(
+                                Warn,
+                                &task.log_target,
                                 "`chain_subscribeFinalizedHeads` subscription has skipped block \
                                 due to undecodable header. Hash: {}. Error: {}",
                                 HashDisplay(current_finalized_block),
@@ -408,8 +417,9 @@ async fn run<TPlat: PlatformRef>(mut task: Task<TPlat>) {
                 ) {
                     Ok(h) => h,
                     Err(error) => {
-                        log::warn!(
-                            target: &task.log_target,
+                        util::log!(
+                            Warn,
+                            &task.log_target,
                             "`chain_subscribeNewHeads` subscription has skipped block due to \
                             undecodable header. Hash: {}. Error: {}",
                             HashDisplay(current_best_block),
@@ -512,6 +522,8 @@ async fn run<TPlat: PlatformRef>(mut task: Task<TPlat>) {
                         let block_hash = *current_best_block;
                         let sync_service = task.sync_service.clone();
                         let requests_tx = task.requests_tx.clone();
+                        let (total_attempts, timeout_per_request) =
+                            task.network_requests.storage_or(4, Duration::from_secs(12));
                         async move {
                             let result = sync_service
                                 .clone()
@@ -524,8 +536,8 @@ async fn run<TPlat: PlatformRef>(mut task: Task<TPlat>) {
                                             key,
                                             ty: sync_service::StorageRequestItemTy::Value,
                                         }),
-                                    4,
-                                    Duration::from_secs(12),
+                                    total_attempts,
+                                    timeout_per_request,
                                     NonZeroU32::new(2).unwrap(),
                                 )
                                 .await;
@@ -673,8 +685,9 @@ async fn run<TPlat: PlatformRef>(mut task: Task<TPlat>) {
                 ) {
                     Ok(h) => h,
                     Err(error) => {
-                        log::warn!(
-                            target: &task.log_target,
+                        util::log!(
+                            Warn,
+                            &task.log_target,
                             "`chain_subscribeAllHeads` subscription has skipped block due to \
                             undecodable header. Hash: {}. Error: {}",
                             HashDisplay(&header::hash_from_scale_encoded_header(
@@ -802,8 +815,9 @@ async fn run<TPlat: PlatformRef>(mut task: Task<TPlat>) {
                             ) {
                                 Ok(h) => h,
                                 Err(error) => {
-                                    log::warn!(
-                                        target: &task.log_target,
+                                    util::log!(
+                                        Warn,
+                                        &task.log_target,
                                         "`chain_subscribeNewHeads` subscription has skipped \
                                         block due to undecodable header. Hash: {}. Error: {}",
                                         HashDisplay(current_best_block),
@@ -846,8 +860,9 @@ async fn run<TPlat: PlatformRef>(mut task: Task<TPlat>) {
                             ) {
                                 Ok(h) => h,
                                 Err(error) => {
-                                    log::warn!(
-                                        target: &task.log_target,
+                                    util::log!(
+                                        Warn,
+                                        &task.log_target,
                                         "`chain_subscribeFinalizedHeads` subscription has skipped \
                                         block due to undecodable header. Hash: {}. Error: {}",
                                         HashDisplay(current_finalized_block),
@@ -1114,6 +1129,8 @@ async fn run<TPlat: PlatformRef>(mut task: Task<TPlat>) {
                             // but do not actually start doing anything now.
                             let fetch = {
                                 let sync_service = task.sync_service.clone();
+                                let (total_attempts, timeout_per_request) =
+                                    task.network_requests.blocks_or(4, Duration::from_secs(8));
                                 async move {
                                     // The sync service knows which peers are potentially aware of
                                     // this block.
@@ -1121,13 +1138,13 @@ async fn run<TPlat: PlatformRef>(mut task: Task<TPlat>) {
                                         .clone()
                                         .block_query_unknown_number(
                                             block_hash,
-                                            protocol::BlocksRequestFields {
+                                            codec::BlocksRequestFields {
                                                 header: true,
                                                 body: false,
                                                 justifications: false,
                                             },
-                                            4,
-                                            Duration::from_secs(8),
+                                            total_attempts,
+                                            timeout_per_request,
                                             NonZeroU32::new(2).unwrap(),
                                         )
                                         .await;
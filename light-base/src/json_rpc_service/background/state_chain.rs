@@ -19,15 +19,21 @@
 
 use super::{legacy_state_sub, Background, GetKeysPagedCacheKey, PlatformRef};
 
-use crate::sync_service;
-
-use alloc::{format, string::ToString as _, sync::Arc, vec, vec::Vec};
+use crate::{sync_service, util};
+
+use alloc::{
+    format,
+    string::{String, ToString as _},
+    sync::Arc,
+    vec,
+    vec::Vec,
+};
 use core::{iter, num::NonZeroU32, time::Duration};
 use futures_channel::oneshot;
 use smoldot::{
     header,
     json_rpc::{self, methods, service},
-    network::protocol,
+    network::codec,
 };
 
 impl<TPlat: PlatformRef> Background<TPlat> {
@@ -37,6 +43,10 @@ impl<TPlat: PlatformRef> Background<TPlat> {
             unreachable!()
         };
 
+        let Some(request) = self.check_account_prefix(request, &account) else {
+            return;
+        };
+
         let block_hash = {
             let (tx, rx) = oneshot::channel();
             self.to_legacy
@@ -54,7 +64,7 @@ impl<TPlat: PlatformRef> Background<TPlat> {
                 "AccountNonceApi",
                 1..=1,
                 "AccountNonceApi_account_nonce",
-                iter::once(&account.0),
+                iter::once(&account.public_key),
                 4,
                 Duration::from_secs(4),
                 NonZeroU32::new(2).unwrap(),
@@ -70,8 +80,9 @@ impl<TPlat: PlatformRef> Background<TPlat> {
                 request.respond(methods::Response::system_accountNextIndex(u64::from(index)));
             }
             Err(error) => {
-                log::warn!(
-                    target: &self.log_target,
+                util::log!(
+                    Warn,
+                    &self.log_target,
                     "Returning error from `system_accountNextIndex`. \
                     API user might not function properly. Error: {}",
                     error
@@ -124,19 +135,21 @@ impl<TPlat: PlatformRef> Background<TPlat> {
         };
 
         // Block bodies and headers aren't stored locally. Ask the network.
+        let (total_attempts, timeout_per_request) =
+            self.network_requests.blocks_or(3, Duration::from_secs(8));
         let mut result = if let Some(block_number) = block_number {
             self.sync_service
                 .clone()
                 .block_query(
                     block_number,
                     hash,
-                    protocol::BlocksRequestFields {
+                    codec::BlocksRequestFields {
                         header: true,
                         body: true,
                         justifications: false,
                     },
-                    3,
-                    Duration::from_secs(8),
+                    total_attempts,
+                    timeout_per_request,
                     NonZeroU32::new(1).unwrap(),
                 )
                 .await
@@ -145,13 +158,13 @@ impl<TPlat: PlatformRef> Background<TPlat> {
                 .clone()
                 .block_query_unknown_number(
                     hash,
-                    protocol::BlocksRequestFields {
+                    codec::BlocksRequestFields {
                         header: true,
                         body: true,
                         justifications: false,
                     },
-                    3,
-                    Duration::from_secs(8),
+                    total_attempts,
+                    timeout_per_request,
                     NonZeroU32::new(1).unwrap(),
                 )
                 .await
@@ -200,6 +213,13 @@ impl<TPlat: PlatformRef> Background<TPlat> {
                 // we always return an empty list.
                 justifications: None,
             }))
+        } else if let Some(result_json) = self
+            .query_trusted_rpc_fallback(methods::MethodCall::chain_getBlock {
+                hash: Some(methods::HashHexString(hash)),
+            })
+            .await
+        {
+            request.respond_unverified(&result_json)
         } else {
             request.respond_null()
         }
@@ -282,6 +302,9 @@ impl<TPlat: PlatformRef> Background<TPlat> {
 
             if let Some(header) = from_cache {
                 Ok(header)
+            } else if let Some(header) = self.sync_service.gap_sync_header(&hash).await {
+                // Header was skipped by the warp sync but downloaded later in the background.
+                Ok(header)
             } else {
                 // Header isn't known locally. We need to ask the network.
                 // First, try to determine the block number by looking into the cache.
@@ -303,19 +326,21 @@ impl<TPlat: PlatformRef> Background<TPlat> {
                 };
 
                 // Actual network query.
+                let (total_attempts, timeout_per_request) =
+                    self.network_requests.blocks_or(3, Duration::from_secs(8));
                 let result = if let Some(block_number) = block_number {
                     self.sync_service
                         .clone()
                         .block_query(
                             block_number,
                             hash,
-                            protocol::BlocksRequestFields {
+                            codec::BlocksRequestFields {
                                 header: true,
                                 body: false,
                                 justifications: false,
                             },
-                            3,
-                            Duration::from_secs(8),
+                            total_attempts,
+                            timeout_per_request,
                             NonZeroU32::new(1).unwrap(),
                         )
                         .await
@@ -324,13 +349,13 @@ impl<TPlat: PlatformRef> Background<TPlat> {
                         .clone()
                         .block_query_unknown_number(
                             hash,
-                            protocol::BlocksRequestFields {
+                            codec::BlocksRequestFields {
                                 header: true,
                                 body: false,
                                 justifications: false,
                             },
-                            3,
-                            Duration::from_secs(8),
+                            total_attempts,
+                            timeout_per_request,
                             NonZeroU32::new(1).unwrap(),
                         )
                         .await
@@ -366,8 +391,17 @@ impl<TPlat: PlatformRef> Background<TPlat> {
             }
             Err(()) => {
                 // Failed to retrieve the header.
-                // TODO: error or null?
-                request.respond_null();
+                if let Some(result_json) = self
+                    .query_trusted_rpc_fallback(methods::MethodCall::chain_getHeader {
+                        hash: Some(methods::HashHexString(hash)),
+                    })
+                    .await
+                {
+                    request.respond_unverified(&result_json);
+                } else {
+                    // TODO: error or null?
+                    request.respond_null();
+                }
             }
         }
     }
@@ -412,7 +446,7 @@ impl<TPlat: PlatformRef> Background<TPlat> {
         match result {
             Ok(result) => match json_rpc::payment_info::decode_payment_info(
                 &result.return_value,
-                result.api_version,
+                result.api_version.unwrap(),
             ) {
                 Ok(info) => request.respond(methods::Response::payment_queryInfo(info)),
                 Err(error) => request.fail(json_rpc::parse::ErrorResponse::ServerError(
@@ -421,8 +455,9 @@ impl<TPlat: PlatformRef> Background<TPlat> {
                 )),
             },
             Err(error) => {
-                log::warn!(
-                    target: &self.log_target,
+                util::log!(
+                    Warn,
+                    &self.log_target,
                     "Returning error from `payment_queryInfo`. \
                     API user might not function properly. Error: {}",
                     error
@@ -435,6 +470,67 @@ impl<TPlat: PlatformRef> Background<TPlat> {
         }
     }
 
+    /// Handles a call to [`methods::MethodCall::system_dryRun`].
+    pub(super) async fn system_dry_run(self: &Arc<Self>, request: service::RequestProcess) {
+        let methods::MethodCall::system_dryRun { extrinsic, at } = request.request() else {
+            unreachable!()
+        };
+
+        let block_hash = match at {
+            Some(h) => h.0,
+            None => {
+                let (tx, rx) = oneshot::channel();
+                self.to_legacy
+                    .lock()
+                    .await
+                    .send(legacy_state_sub::Message::CurrentBestBlockHash { result_tx: tx })
+                    .await
+                    .unwrap();
+                rx.await.unwrap()
+            }
+        };
+
+        // The extrinsic is applied on top of the storage of the block. The storage changes
+        // performed by the runtime are kept in the overlay of the virtual machine and are
+        // discarded once the call has finished.
+        // Only version 6 of the `BlockBuilder` API is accepted. In older versions,
+        // `BlockBuilder_apply_extrinsic` returns a `DispatchError` with a different encoding, and
+        // since the return value is passed as-is to the JSON-RPC client, the client would
+        // decode it as if it was version 6. Supporting these versions would require converting
+        // the return value, which isn't worth it given how old these runtimes are.
+        let result = self
+            .runtime_call(
+                &block_hash,
+                "BlockBuilder",
+                6..=6,
+                "BlockBuilder_apply_extrinsic",
+                iter::once(&extrinsic.0),
+                4,
+                Duration::from_secs(8),
+                NonZeroU32::new(2).unwrap(),
+            )
+            .await;
+
+        match result {
+            Ok(result) => request.respond(methods::Response::system_dryRun(methods::HexString(
+                result.return_value,
+            ))),
+            Err(error) => {
+                util::log!(
+                    Warn,
+                    &self.log_target,
+                    "Returning error from `system_dryRun`. \
+                    API user might not function properly. Error: {}",
+                    error
+                );
+                request.fail(json_rpc::parse::ErrorResponse::ServerError(
+                    -32000,
+                    &error.to_string(),
+                ));
+            }
+        }
+    }
+
     /// Handles a call to [`methods::MethodCall::state_call`].
     pub(super) async fn state_call(self: &Arc<Self>, request: service::RequestProcess) {
         let methods::MethodCall::state_call {
@@ -474,10 +570,30 @@ impl<TPlat: PlatformRef> Background<TPlat> {
             Ok(data) => request.respond(methods::Response::state_call(methods::HexString(
                 data.to_vec(),
             ))),
-            Err(error) => request.fail(json_rpc::parse::ErrorResponse::ServerError(
-                -32000,
-                &error.to_string(),
-            )),
+            Err(error) => {
+                let methods::MethodCall::state_call {
+                    name, parameters, ..
+                } = request.request()
+                else {
+                    unreachable!()
+                };
+
+                if let Some(result_json) = self
+                    .query_trusted_rpc_fallback(methods::MethodCall::state_call {
+                        name,
+                        parameters,
+                        hash: Some(methods::HashHexString(block_hash)),
+                    })
+                    .await
+                {
+                    request.respond_unverified(&result_json)
+                } else {
+                    request.fail(json_rpc::parse::ErrorResponse::ServerError(
+                        -32000,
+                        &error.to_string(),
+                    ))
+                }
+            }
         }
     }
 
@@ -744,8 +860,9 @@ impl<TPlat: PlatformRef> Background<TPlat> {
                 &format!("Failed to decode metadata from runtime. Error: {error}"),
             )),
             Err(error) => {
-                log::warn!(
-                    target: &self.log_target,
+                util::log!(
+                    Warn,
+                    &self.log_target,
                     "Returning error from `state_getMetadata`. API user might not function \
                     properly. Error: {error}"
                 );
@@ -848,6 +965,92 @@ impl<TPlat: PlatformRef> Background<TPlat> {
                 methods::HexString(value),
             )),
             Ok(None) => request.respond_null(),
+            Err(error) => {
+                if let Some(result_json) = self
+                    .query_trusted_rpc_fallback(methods::MethodCall::state_getStorage {
+                        key: methods::HexString(key.0.clone()),
+                        hash: Some(methods::HashHexString(hash)),
+                    })
+                    .await
+                {
+                    request.respond_unverified(&result_json)
+                } else {
+                    request.fail(json_rpc::parse::ErrorResponse::ServerError(
+                        -32000,
+                        &error.to_string(),
+                    ))
+                }
+            }
+        }
+    }
+
+    /// Handles a call to [`methods::MethodCall::state_getReadProof`].
+    pub(super) async fn state_get_read_proof(self: &Arc<Self>, request: service::RequestProcess) {
+        let methods::MethodCall::state_getReadProof { keys, at } = request.request() else {
+            unreachable!()
+        };
+
+        let at = match at {
+            Some(h) => h.0,
+            None => {
+                let (tx, rx) = oneshot::channel();
+                self.to_legacy
+                    .lock()
+                    .await
+                    .send(legacy_state_sub::Message::CurrentBestBlockHash { result_tx: tx })
+                    .await
+                    .unwrap();
+                rx.await.unwrap()
+            }
+        };
+
+        let (state_trie_root_hash, block_number) = {
+            let (tx, rx) = oneshot::channel();
+            self.to_legacy
+                .lock()
+                .await
+                .send(legacy_state_sub::Message::BlockStateRootAndNumber {
+                    block_hash: at,
+                    result_tx: tx,
+                })
+                .await
+                .unwrap();
+
+            match rx.await.unwrap() {
+                Ok(v) => v,
+                Err(err) => {
+                    request.fail(json_rpc::parse::ErrorResponse::ServerError(
+                        -32000,
+                        &format!("Failed to obtain block state trie root: {err}"),
+                    ));
+                    return;
+                }
+            }
+        };
+
+        let (total_attempts, timeout_per_request) =
+            self.network_requests.storage_or(3, Duration::from_secs(12));
+
+        let result = self
+            .sync_service
+            .clone()
+            .storage_proof_query(
+                block_number,
+                &at,
+                &state_trie_root_hash,
+                keys.into_iter().map(|key| key.0),
+                total_attempts,
+                timeout_per_request,
+            )
+            .await;
+
+        match result {
+            Ok(proof) => {
+                request.respond(methods::Response::state_getReadProof(methods::ReadProof {
+                    at: methods::HashHexString(at),
+                    proof: proof.into_iter().map(methods::HexString).collect(),
+                }))
+            }
             Err(error) => request.fail(json_rpc::parse::ErrorResponse::ServerError(
                 -32000,
                 &error.to_string(),
@@ -895,4 +1098,41 @@ impl<TPlat: PlatformRef> Background<TPlat> {
 
         request.respond(methods::Response::state_queryStorageAt(vec![out]));
     }
+
+    /// Sends the given request to the trusted JSON-RPC server configured with
+    /// [`super::StartConfig::trusted_rpc_fallback`], if any.
+    ///
+    /// Returns the JSON-formatted result of the request, or `None` if no server is configured or
+    /// if the request has failed. The result hasn't been verified and must be returned to the
+    /// JSON-RPC client with [`service::RequestProcess::respond_unverified`].
+    async fn query_trusted_rpc_fallback(&self, call: methods::MethodCall<'_>) -> Option<String> {
+        let trusted_rpc_fallback = self.trusted_rpc_fallback.as_ref()?;
+
+        match trusted_rpc_fallback
+            .request(call.name(), &call.params_to_json_object())
+            .await
+        {
+            Ok(result_json) => {
+                util::log!(
+                    Warn,
+                    &self.log_target,
+                    "Peer-to-peer network unable to answer {} request. Returning unverified \
+                    response from {}",
+                    call.name(),
+                    trusted_rpc_fallback.address()
+                );
+                Some(result_json)
+            }
+            Err(error) => {
+                util::log!(
+                    Debug,
+                    &self.log_target,
+                    "Trusted JSON-RPC server unable to answer {} request: {}",
+                    call.name(),
+                    error
+                );
+                None
+            }
+        }
+    }
 }
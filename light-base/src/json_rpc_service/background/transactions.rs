@@ -22,6 +22,7 @@ use super::{Background, PlatformRef};
 use crate::transactions_service;
 
 use alloc::{borrow::ToOwned as _, format, string::ToString as _, sync::Arc, vec::Vec};
+use core::num::NonZeroUsize;
 use futures_lite::future;
 use futures_util::StreamExt as _;
 use smoldot::json_rpc::{methods, service};
@@ -65,13 +66,55 @@ impl<TPlat: PlatformRef> Background<TPlat> {
         let mut transaction_hash: [u8; 32] = Default::default();
         transaction_hash.copy_from_slice(hash_context.finalize().as_bytes());
         self.transactions_service
-            .submit_transaction(transaction.0)
+            .submit_transaction(
+                transaction.0,
+                transactions_service::PropagationPolicy::AllPeers,
+            )
             .await;
         request.respond(methods::Response::author_submitExtrinsic(
             methods::HashHexString(transaction_hash),
         ));
     }
 
+    /// Handles a call to [`methods::MethodCall::smoldot_unstable_submitTransaction`].
+    ///
+    /// Similar to `author_submitExtrinsic`, except that the JSON-RPC client can choose to which
+    /// peers the transaction is gossiped. If `maxPeers` is absent, the transaction is gossiped to
+    /// all peers. If `maxPeers` is `0`, the transaction isn't gossiped at all.
+    pub(super) async fn smoldot_unstable_submit_transaction(
+        self: &Arc<Self>,
+        request: service::RequestProcess,
+    ) {
+        let methods::MethodCall::smoldot_unstable_submitTransaction {
+            transaction,
+            max_peers,
+        } = request.request()
+        else {
+            unreachable!()
+        };
+
+        let propagation = match max_peers {
+            None => transactions_service::PropagationPolicy::AllPeers,
+            Some(max_peers) => {
+                match NonZeroUsize::new(usize::try_from(max_peers).unwrap_or(usize::MAX)) {
+                    Some(max_peers) => transactions_service::PropagationPolicy::Subset(max_peers),
+                    None => transactions_service::PropagationPolicy::None,
+                }
+            }
+        };
+
+        let mut hash_context = blake2_rfc::blake2b::Blake2b::new(32);
+        hash_context.update(&transaction.0);
+        let mut transaction_hash: [u8; 32] = Default::default();
+        transaction_hash.copy_from_slice(hash_context.finalize().as_bytes());
+        self.transactions_service
+            .submit_transaction(transaction.0, propagation)
+            .await;
+        request.respond(methods::Response::smoldot_unstable_submitTransaction(
+            methods::HashHexString(transaction_hash),
+        ));
+    }
+
     /// Handles a call to [`methods::MethodCall::author_submitAndWatchExtrinsic`] (if `is_legacy`
     /// is `true`) or to [`methods::MethodCall::transaction_unstable_submitAndWatch`] (if
     /// `is_legacy` is `false`).
@@ -93,14 +136,17 @@ impl<TPlat: PlatformRef> Background<TPlat> {
             .spawn_task(format!("{}-transaction-watch", self.log_target).into(), {
                 let mut transaction_updates = self
                     .transactions_service
-                    .submit_and_watch_transaction(transaction.0, 16)
+                    .submit_and_watch_transaction(
+                        transaction.0,
+                        transactions_service::PropagationPolicy::AllPeers,
+                        16,
+                    )
                     .await;
 
                 async move {
                     let mut subscription = request.accept();
                     let subscription_id = subscription.subscription_id().to_owned();
 
-                    let mut included_block = None;
                     let mut num_broadcasted_peers = 0;
 
                     loop {
@@ -173,11 +219,10 @@ impl<TPlat: PlatformRef> Background<TPlat> {
 
                             (
                                 transactions_service::TransactionStatus::IncludedBlockUpdate {
-                                    block_hash: Some((block_hash, _)),
+                                    block_hash: (block_hash, _),
                                 },
                                 true,
                             ) => {
-                                included_block = Some(block_hash);
                                 subscription
                                     .send_notification(
                                         methods::ServerToClient::author_extrinsicUpdate {
@@ -190,31 +235,26 @@ impl<TPlat: PlatformRef> Background<TPlat> {
                                     .await;
                             }
                             (
-                                transactions_service::TransactionStatus::IncludedBlockUpdate {
-                                    block_hash: None,
-                                },
+                                transactions_service::TransactionStatus::Retracted { block_hash },
                                 true,
                             ) => {
-                                if let Some(block_hash) = included_block.take() {
-                                    subscription
-                                        .send_notification(
-                                            methods::ServerToClient::author_extrinsicUpdate {
-                                                subscription: (&subscription_id).into(),
-                                                result: methods::TransactionStatus::Retracted(
-                                                    methods::HashHexString(block_hash),
-                                                ),
-                                            },
-                                        )
-                                        .await;
-                                }
+                                subscription
+                                    .send_notification(
+                                        methods::ServerToClient::author_extrinsicUpdate {
+                                            subscription: (&subscription_id).into(),
+                                            result: methods::TransactionStatus::Retracted(
+                                                methods::HashHexString(block_hash),
+                                            ),
+                                        },
+                                    )
+                                    .await;
                             }
                             (
                                 transactions_service::TransactionStatus::IncludedBlockUpdate {
-                                    block_hash: Some((block_hash, index)),
+                                    block_hash: (block_hash, index),
                                 },
                                 false,
                             ) => {
-                                included_block = Some(block_hash);
                                 subscription.send_notification(methods::ServerToClient::transaction_unstable_watchEvent {
                                     subscription: (&subscription_id).into(),
                                     result:
@@ -227,9 +267,7 @@ impl<TPlat: PlatformRef> Background<TPlat> {
                                 }).await;
                             }
                             (
-                                transactions_service::TransactionStatus::IncludedBlockUpdate {
-                                    block_hash: None,
-                                },
+                                transactions_service::TransactionStatus::Retracted { .. },
                                 false,
                             ) => {
                                 subscription.send_notification(methods::ServerToClient::transaction_unstable_watchEvent {
@@ -72,12 +72,16 @@
 extern crate alloc;
 
 use alloc::{borrow::ToOwned as _, boxed::Box, format, string::String, sync::Arc, vec, vec::Vec};
-use core::{num::NonZeroU32, ops, pin};
-use futures_util::{future, FutureExt as _};
+use core::{
+    num::{NonZeroU32, NonZeroU64, NonZeroUsize},
+    ops, pin,
+    time::Duration,
+};
+use futures_util::{future, FutureExt as _, Stream};
 use hashbrown::{hash_map::Entry, HashMap};
 use itertools::Itertools as _;
 use smoldot::{
-    chain, chain_spec, header,
+    chain, chain_spec, executor, header,
     informant::HashDisplay,
     libp2p::{connection, multiaddr, peer_id},
 };
@@ -85,15 +89,32 @@ use smoldot::{
 mod database;
 mod json_rpc_service;
 mod network_service;
+mod nonce_service;
 mod runtime_service;
 mod sync_service;
 mod transactions_service;
+mod trusted_rpc;
 mod util;
 
 pub mod platform;
 
-pub use json_rpc_service::HandleRpcError;
+pub use chain::async_tree::BestBlockPolicy;
+pub use database::{SnapshotDecodeError, SNAPSHOT_FORMAT_VERSION};
+pub use json_rpc_service::{HandleRpcError, NetworkRequestConfig, NetworkRequestsConfig};
+pub use network_service::{DiscoverySchedule, IpFamilyPolicy};
+pub use nonce_service::ReserveNonceError;
 pub use peer_id::PeerId;
+pub use runtime_service::{Histogram, RuntimeCallMetrics};
+pub use sync_service::{
+    FinalityEquivocation, FinalityEquivocationProof, FinalityProofQueryError, GapSyncProgress,
+    StorageQueryCacheMetrics, SyncPhase, VerificationFailure, VerificationFailureKind,
+};
+
+/// Maximum size of the memory of the runtimes, in pages of 64kiB.
+///
+/// Substrate runtimes typically use around 130 MiB of memory. This limit is intentionally much
+/// higher, and is only meant to stop runaway runtimes.
+const RUNTIME_MAX_MEMORY_PAGES: executor::host::HeapPages = executor::host::HeapPages::new(8192);
 
 /// See [`Client::add_chain`].
 #[derive(Debug, Clone)]
@@ -114,6 +135,14 @@ pub struct AddChainConfig<'a, TChain, TRelays> {
     /// reserves the right to break the format of this data at any point.
     pub database_content: &'a str,
 
+    /// If `Some`, snapshot previously returned by [`Client::export_snapshot`], possibly by a
+    /// different instance of the client. Used instead of [`AddChainConfig::database_content`].
+    ///
+    /// Contrary to [`AddChainConfig::database_content`], the format of snapshots is versioned
+    /// (see [`SNAPSHOT_FORMAT_VERSION`]), and [`Client::add_chain`] returns an error if the
+    /// snapshot can't be decoded or doesn't belong to the chain being added.
+    pub snapshot: Option<&'a str>,
+
     /// If [`AddChainConfig`] defines a parachain, contains the list of relay chains to choose
     /// from. Ignored if not a parachain.
     ///
@@ -129,6 +158,134 @@ pub struct AddChainConfig<'a, TChain, TRelays> {
 
     /// Configuration for the JSON-RPC endpoint.
     pub json_rpc: AddChainConfigJsonRpc,
+
+    /// If `true`, after the chain has been warp synced, the headers of the blocks between the
+    /// starting point of the synchronization and the block reached by the warp sync are
+    /// downloaded in the background. These headers can then be returned by the JSON-RPC
+    /// endpoint without having to query the peer-to-peer network.
+    ///
+    /// The downloaded headers are kept in memory. Because of this, it is only reasonable to
+    /// enable this option if the number of blocks between the starting point and the head of
+    /// the chain is small.
+    ///
+    /// Ignored if [`AddChainConfig`] defines a parachain.
+    pub gap_sync: bool,
+
+    /// If `Some`, and if the chain doesn't have any finality mechanism (such as a standalone
+    /// chain using Aura without GrandPa), the blocks of the best chain that are this number of
+    /// blocks below the best block are automatically considered as finalized.
+    ///
+    /// Without this option, the finalized block of such chains never advances.
+    ///
+    /// Ignored if [`AddChainConfig`] defines a parachain, or if the chain uses GrandPa.
+    pub pseudo_finality_depth: Option<NonZeroU64>,
+
+    /// If `true`, the client keeps track of the account nonces handed out through
+    /// [`Client::reserve_account_nonce`] and the `smoldot_unstable_reserveAccountNonce`
+    /// JSON-RPC function, so that transactions submitted in a quick succession by the same
+    /// account don't use the same nonce.
+    ///
+    /// If `false`, [`Client::reserve_account_nonce`] and the JSON-RPC functions return an
+    /// error.
+    pub nonce_tracking: bool,
+
+    /// If `true`, the client keeps track of the quality of the peers of the chain (number of
+    /// requests successfully answered and uptime of the gossip links). This quality is then
+    /// included in the database content and snapshots, and the peers of highest quality found
+    /// in [`AddChainConfig::database_content`] or [`AddChainConfig::snapshot`] are connected to
+    /// first, which reduces the time it takes to find working peers on startup.
+    ///
+    /// Similarly to [`AddChainConfig::logs`], chains that are identical to a chain that has
+    /// previously been added keep following the value of the chain that has been added first.
+    pub track_peers_quality: bool,
+
+    /// Which addresses to dial first when a peer of the chain advertises both IPv4 and IPv6
+    /// addresses. [`IpFamilyPolicy::NoPreference`] is a reasonable default.
+    ///
+    /// Similarly to [`AddChainConfig::logs`], chains that are identical to a chain that has
+    /// previously been added keep following the value of the chain that has been added first.
+    pub ip_family_policy: IpFamilyPolicy,
+
+    /// Strategy used to determine which block is reported as the best block by the JSON-RPC
+    /// endpoint and by the other consumers of the runtime of the chain.
+    ///
+    /// [`BestBlockPolicy::FollowInput`] reports the best block according to the peer-to-peer
+    /// network, or its closest ancestor whose runtime is known, and is a reasonable default.
+    /// [`BestBlockPolicy::LongestOutputChain`] instead reports the longest chain of blocks whose
+    /// runtime is known, even if the peer-to-peer network considers a different fork as best.
+    ///
+    /// Similarly to [`AddChainConfig::logs`], chains that are identical to a chain that has
+    /// previously been added keep following the value of the chain that has been added first.
+    pub best_block_policy: BestBlockPolicy,
+
+    /// If `Some`, multiaddress of a JSON-RPC server (for example
+    /// `/dns/example.com/tcp/9944/ws`) that the JSON-RPC service queries when the peer-to-peer
+    /// network is unable to answer a `chain_getBlock`, `chain_getHeader`, `state_getStorage`, or
+    /// `state_call` request. The server must be reachable through a non-secure WebSocket
+    /// connection on top of TCP, and the platform must support TCP connections.
+    ///
+    /// The responses of this server are **not verified** and are blindly trusted. Responses that
+    /// come from this server contain an additional `smoldot_unverified` field set to `true`, for
+    /// example `{"jsonrpc":"2.0","id":1,"result":"0x...","smoldot_unverified":true}`. This field
+    /// isn't part of the JSON-RPC specification, and JSON-RPC clients that don't know about it
+    /// ignore it. See also [`smoldot::json_rpc::parse::build_unverified_success_response`].
+    ///
+    /// Ignored if [`AddChainConfig::json_rpc`] is [`AddChainConfigJsonRpc::Disabled`].
+    pub trusted_rpc_fallback: Option<&'a str>,
+
+    /// Configuration of the logs emitted by the services of this chain.
+    ///
+    /// Chains that are identical to a chain that has previously been added share its
+    /// networking, syncing, runtime, and transactions services, and the logs of these services
+    /// keep following the configuration of the chain that has been added first.
+    pub logs: AddChainConfigLogs,
+}
+
+/// See [`AddChainConfig::logs`].
+///
+/// Logs are emitted through the `log` crate. The configuration here only makes it possible to
+/// discard logs of a specific chain. The global configuration of the `log` crate (such as
+/// [`log::set_max_level`]) still applies.
+#[derive(Debug, Clone)]
+pub struct AddChainConfigLogs {
+    /// Maximum level of the logs emitted by the services of this chain.
+    pub max_level: log::LevelFilter,
+
+    /// If `false`, the logs of the syncing service of this chain are discarded.
+    pub sync_service: bool,
+
+    /// If `false`, the logs of the networking service of this chain are discarded.
+    pub network_service: bool,
+
+    /// If `false`, the logs of the runtime service of this chain are discarded.
+    pub runtime_service: bool,
+
+    /// If `false`, the logs of the JSON-RPC service of this chain are discarded.
+    pub json_rpc_service: bool,
+}
+
+impl AddChainConfigLogs {
+    /// Returns the maximum level of the logs of a service given whether its logs are enabled.
+    fn service_max_level(&self, enabled: bool) -> log::LevelFilter {
+        if enabled {
+            self.max_level
+        } else {
+            log::LevelFilter::Off
+        }
+    }
+}
+
+impl Default for AddChainConfigLogs {
+    /// Returns a configuration where no log is discarded.
+    fn default() -> Self {
+        AddChainConfigLogs {
+            max_level: log::LevelFilter::Trace,
+            sync_service: true,
+            network_service: true,
+            runtime_service: true,
+            json_rpc_service: true,
+        }
+    }
 }
 
 /// See [`AddChainConfig::json_rpc`].
@@ -164,6 +321,12 @@ pub enum AddChainConfigJsonRpc {
         /// While a typical reasonable value would be for example 64, existing UIs tend to start
         /// a lot of subscriptions, and a value such as 1024 is recommended.
         max_subscriptions: u32,
+
+        /// Number of attempts and timeout of the networking requests (storage, call proofs,
+        /// block bodies) that the JSON-RPC service sends in order to answer JSON-RPC requests.
+        ///
+        /// Every field left to `None` uses the default value of the corresponding request.
+        network_requests: NetworkRequestsConfig,
     },
 }
 
@@ -206,6 +369,13 @@ pub struct Client<TPlat: platform::PlatformRef, TChain = ()> {
     /// Because we use a `SipHasher`, this hashmap isn't created in the `new` function (as this
     /// function is `const`) but lazily the first time it is needed.
     chains_by_key: Option<HashMap<ChainKey, RunningChain<TPlat>, util::SipHasherBuild>>,
+
+    /// Cache of the headers found in block announces, shared between the network services of
+    /// all the chains.
+    ///
+    /// Similar to [`Client::chains_by_key`], this cache is created lazily the first time it is
+    /// needed.
+    announced_headers_cache: Option<Arc<network_service::AnnouncedHeadersCache>>,
 }
 
 struct PublicApiChain<TChain> {
@@ -224,6 +394,10 @@ struct PublicApiChain<TChain> {
     /// [`AddChainConfig::json_rpc`] was [`AddChainConfigJsonRpc::Disabled`] when adding the chain.
     json_rpc_frontend: Option<json_rpc_service::Frontend>,
 
+    /// Service that hands out account nonces. `None` iff [`AddChainConfig::nonce_tracking`] was
+    /// `false` when adding the chain.
+    nonce_service: Option<nonce_service::NonceService>,
+
     /// Notified when the [`PublicApiChain`] is destroyed, in order for the [`JsonRpcResponses`]
     /// to detect when the chain has been removed.
     public_api_chain_destroyed_event: event_listener::Event,
@@ -337,6 +511,50 @@ impl JsonRpcResponses {
     }
 }
 
+/// Snapshot of the status of a chain. See [`Client::chains_iter`].
+#[derive(Debug, Clone)]
+pub struct ChainStatus {
+    /// Number of peers the chain is currently synchronizing from.
+    pub num_peers: usize,
+
+    /// Height of the current best block of the chain.
+    pub best_block_number: u64,
+
+    /// Hash of the current best block of the chain.
+    pub best_block_hash: [u8; 32],
+
+    /// Height of the current finalized block of the chain.
+    pub finalized_block_number: u64,
+
+    /// Hash of the current finalized block of the chain.
+    pub finalized_block_hash: [u8; 32],
+
+    /// Phase the synchronization of the chain is in.
+    pub sync_phase: SyncPhase,
+
+    /// Progress of the background download of the headers between the checkpoint of the chain
+    /// and the block reached by the warp syncing. `None` if this download is disabled, which is
+    /// always the case for parachains.
+    pub gap_sync: Option<GapSyncProgress>,
+
+    /// Size in bytes of the database of the chain, in other words the value that would be
+    /// returned by the `chainHead_unstable_finalizedDatabase` JSON-RPC function if no maximum
+    /// size was provided.
+    pub database_size: usize,
+
+    /// Metrics about the runtime calls performed against the chain, for example by the JSON-RPC
+    /// functions.
+    pub runtime_call_metrics: RuntimeCallMetrics,
+
+    /// Metrics about the cache of the storage values downloaded from the network, for example
+    /// by the JSON-RPC functions.
+    pub storage_query_cache: StorageQueryCacheMetrics,
+
+    /// Schedule of the automatic discovery of peers. The discovery is shared between all the
+    /// chains, and this field is thus identical for all of them.
+    pub discovery_schedule: DiscoverySchedule,
+}
+
 impl<TPlat: platform::PlatformRef, TChain> Client<TPlat, TChain> {
     /// Initializes the smoldot client.
     pub const fn new(platform: TPlat) -> Self {
@@ -344,6 +562,7 @@ impl<TPlat: platform::PlatformRef, TChain> Client<TPlat, TChain> {
             platform,
             public_api_chains: slab::Slab::new(),
             chains_by_key: None,
+            announced_headers_cache: None,
         }
     }
 
@@ -371,6 +590,17 @@ impl<TPlat: platform::PlatformRef, TChain> Client<TPlat, TChain> {
             }
         };
 
+        // Parse the address of the trusted JSON-RPC server, if any.
+        let trusted_rpc_fallback = match config.trusted_rpc_fallback {
+            Some(address) => match address.parse::<multiaddr::Multiaddr>() {
+                Ok(address) if trusted_rpc::check_address(&self.platform, &address).is_ok() => {
+                    Some(address)
+                }
+                _ => return Err(AddChainError::InvalidTrustedRpcFallbackAddress),
+            },
+            None => None,
+        };
+
         // Build the genesis block, its hash, and information about the chain.
         let (
             genesis_chain_information,
@@ -414,22 +644,41 @@ impl<TPlat: platform::PlatformRef, TChain> Client<TPlat, TChain> {
 
         // Decode the database and make sure that it matches the chain by comparing the finalized
         // block header in it with the actual one.
-        let (database, database_was_wrong_chain) = {
-            let mut maybe_database = database::decode_database(
-                config.database_content,
-                chain_spec.block_number_bytes().into(),
-            )
-            .ok();
-            let mut database_was_wrong = false;
-            if maybe_database
-                .as_ref()
-                .map_or(false, |db| db.genesis_block_hash != genesis_block_hash)
-            {
-                maybe_database = None;
-                database_was_wrong = true;
-            }
-            (maybe_database, database_was_wrong)
-        };
+        // If a snapshot is provided, it replaces the database and also yields the headers of the
+        // ancestors of its finalized block. These headers are later ignored by the sync service
+        // if the snapshot isn't the source of the chain information used below.
+        let (database, database_was_wrong_chain, recent_headers) =
+            if let Some(snapshot) = config.snapshot {
+                let snapshot =
+                    database::decode_snapshot(snapshot, chain_spec.block_number_bytes().into())
+                        .map_err(AddChainError::InvalidSnapshot)?;
+                if snapshot.database.genesis_block_hash != genesis_block_hash {
+                    return Err(AddChainError::SnapshotWrongChain);
+                }
+                (Some(snapshot.database), false, snapshot.recent_headers)
+            } else {
+                let mut maybe_database = database::decode_database(
+                    config.database_content,
+                    chain_spec.block_number_bytes().into(),
+                )
+                .ok();
+                let mut database_was_wrong = false;
+                if maybe_database
+                    .as_ref()
+                    .map_or(false, |db| db.genesis_block_hash != genesis_block_hash)
+                {
+                    maybe_database = None;
+                    database_was_wrong = true;
+                }
+                (maybe_database, database_was_wrong, Vec::new())
+            };
+
+        // The quality of the nodes of the database is extracted ahead of time, as it is used no
+        // matter which chain information is picked below.
+        let known_nodes_quality = database
+            .as_ref()
+            .map(|db| db.known_nodes_quality.clone())
+            .unwrap_or_default();
 
         // Load the information about the chain. If a light sync state (also known as a checkpoint)
         // is present in the chain spec, it is possible to start syncing at the finalized block
@@ -683,6 +932,22 @@ impl<TPlat: platform::PlatformRef, TChain> Client<TPlat, TChain> {
                     connection::NoiseKey::new(&libp2p_key, &noise_static_key)
                 };
 
+                // Decoding the headers found in block announces is done through a cache shared
+                // between all the chains.
+                let announced_headers_cache = self
+                    .announced_headers_cache
+                    .get_or_insert_with(|| {
+                        Arc::new(network_service::AnnouncedHeadersCache::new(
+                            NonZeroUsize::new(256).unwrap(),
+                            {
+                                let mut seed = [0; 16];
+                                self.platform.fill_random_bytes(&mut seed);
+                                seed
+                            },
+                        ))
+                    })
+                    .clone();
+
                 // Version of the client when requested through the networking.
                 let network_identify_agent_version = format!(
                     "{} {}",
@@ -696,7 +961,11 @@ impl<TPlat: platform::PlatformRef, TChain> Client<TPlat, TChain> {
                     let platform = self.platform.clone();
                     let fork_id = chain_spec.fork_id().map(|f| f.to_owned());
                     let chain_name = chain_spec.name().to_owned();
-                    let has_bad_blocks = chain_spec.bad_blocks_hashes().count() != 0;
+                    let bad_blocks = chain_spec.bad_blocks_hashes().copied().collect::<Vec<_>>();
+                    let fork_blocks = chain_spec
+                        .fork_blocks()
+                        .map(|(n, h)| (n, *h))
+                        .collect::<Vec<_>>();
                     let has_protocol_id = chain_spec.protocol_id().is_some();
                     let has_telemetry_endpoints = chain_spec.telemetry_endpoints().count() != 0;
                     let log_name = log_name.clone();
@@ -712,6 +981,12 @@ impl<TPlat: platform::PlatformRef, TChain> Client<TPlat, TChain> {
                     if let (None, None) = (&relay_chain_ready_future, &chain_information) {
                         return Err(AddChainError::ChainSpecNeitherGenesisStorageNorCheckpoint);
                     }
+                    let gap_sync = config.gap_sync;
+                    let pseudo_finality_depth = config.pseudo_finality_depth;
+                    let logs = config.logs.clone();
+                    let track_peers_quality = config.track_peers_quality;
+                    let ip_family_policy = config.ip_family_policy;
+                    let best_block_policy = config.best_block_policy;
 
                     let future = async move {
                         // Wait until the relay chain has finished initializing, if necessary.
@@ -750,7 +1025,14 @@ impl<TPlat: platform::PlatformRef, TChain> Client<TPlat, TChain> {
                                     }
                                 }
                                 (None, Some(chain_information)) => {
-                                    StartServicesChainTy::RelayChain { chain_information }
+                                    StartServicesChainTy::RelayChain {
+                                        chain_information,
+                                        gap_sync,
+                                        pseudo_finality_depth,
+                                        bad_blocks: bad_blocks.clone(),
+                                        fork_blocks,
+                                        recent_headers,
+                                    }
                                 }
                                 (None, None) => {
                                     // Checked above.
@@ -760,14 +1042,19 @@ impl<TPlat: platform::PlatformRef, TChain> Client<TPlat, TChain> {
 
                             start_services(
                                 log_name.clone(),
+                                &logs,
                                 &platform,
                                 runtime_code_hint,
                                 genesis_block_header,
                                 block_number_bytes,
                                 fork_id,
+                                track_peers_quality,
+                                ip_family_policy,
+                                best_block_policy,
                                 config,
                                 network_identify_agent_version,
                                 network_noise_key,
+                                announced_headers_cache,
                             )
                             .await
                         };
@@ -831,18 +1118,15 @@ impl<TPlat: platform::PlatformRef, TChain> Client<TPlat, TChain> {
                         }
 
                         // TODO: remove after https://github.com/paritytech/smoldot/issues/2584
-                        if has_bad_blocks {
+                        if !bad_blocks.is_empty() && relay_chain.is_some() {
                             log::warn!(
                                 target: "smoldot",
                                 "Chain specification of {} contains a list of bad blocks. Bad \
-                                blocks are not implemented in the light client. An appropriate \
-                                way to silence this warning is to remove the bad blocks from the \
-                                chain specification, which can safely be done:\n\
-                                - For relay chains: if the chain specification contains a \
-                                checkpoint and that the bad blocks have a block number inferior \
-                                to this checkpoint.\n\
-                                - For parachains: if the bad blocks have a block number inferior \
-                                to the current parachain finalized block.", log_name
+                                blocks are not implemented in the light client for parachains. \
+                                An appropriate way to silence this warning is to remove the bad \
+                                blocks from the chain specification, which can safely be done if \
+                                the bad blocks have a block number inferior to the current \
+                                parachain finalized block.", log_name
                             );
                         }
 
@@ -923,6 +1207,13 @@ impl<TPlat: platform::PlatformRef, TChain> Client<TPlat, TChain> {
                         .network_service
                         .discover(running_chain.network_service_chain_id, known_nodes, false)
                         .await;
+                    running_chain
+                        .network_service
+                        .prioritize_peers(
+                            running_chain.network_service_chain_id,
+                            known_nodes_quality,
+                        )
+                        .await;
                     running_chain
                         .network_service
                         .discover(
@@ -935,11 +1226,53 @@ impl<TPlat: platform::PlatformRef, TChain> Client<TPlat, TChain> {
                 .boxed()
             });
 
+        // Nonce service initialization. Similar to the JSON-RPC service, this is done every time
+        // `add_chain` is called, even if a similar chain already existed.
+        let nonce_service = if config.nonce_tracking {
+            // Clone `running_chain_init`.
+            let mut running_chain_init = match services_init {
+                future::MaybeDone::Done(d) => future::MaybeDone::Done(d.clone()),
+                future::MaybeDone::Future(d) => future::MaybeDone::Future(d.clone()),
+                future::MaybeDone::Gone => unreachable!(),
+            };
+
+            let (nonce_service, service_starter) = nonce_service::service(nonce_service::Config {
+                log_name: log_name.clone(),
+                log_max_level: config.logs.max_level,
+                // Note that this value is intentionally not exposed in the publicly available
+                // configuration. A transaction that hasn't been finalized after this duration
+                // is very likely to have been dropped.
+                reservation_duration: Duration::from_secs(120),
+            });
+
+            let platform = self.platform.clone();
+            let init_future = async move {
+                // Wait for the chain to finish initializing before starting the service.
+                (&mut running_chain_init).await;
+                let running_chain = pin::Pin::new(&mut running_chain_init)
+                    .take_output()
+                    .unwrap();
+
+                service_starter.start(nonce_service::StartConfig {
+                    platform,
+                    sync_service: running_chain.sync_service,
+                })
+            };
+
+            self.platform
+                .spawn_task("nonce-service-init".into(), init_future.boxed());
+
+            Some(nonce_service)
+        } else {
+            None
+        };
+
         // JSON-RPC service initialization. This is done every time `add_chain` is called, even
         // if a similar chain already existed.
         let json_rpc_frontend = if let AddChainConfigJsonRpc::Enabled {
             max_pending_requests,
             max_subscriptions,
+            network_requests,
         } = config.json_rpc
         {
             // Clone `running_chain_init`.
@@ -951,8 +1284,10 @@ impl<TPlat: platform::PlatformRef, TChain> Client<TPlat, TChain> {
 
             let (frontend, service_starter) = json_rpc_service::service(json_rpc_service::Config {
                 log_name: log_name.clone(), // TODO: add a way to differentiate multiple different json-rpc services under the same chain
+                log_max_level: config.logs.service_max_level(config.logs.json_rpc_service),
                 max_pending_requests,
                 max_subscriptions,
+                network_requests,
                 // Note that the settings below are intentionally not exposed in the publicly
                 // available configuration, as "good" values depend on the global number of tasks.
                 // In other words, these constants are relative to the number of other things that
@@ -965,6 +1300,16 @@ impl<TPlat: platform::PlatformRef, TChain> Client<TPlat, TChain> {
             let system_name = self.platform.client_name().into_owned();
             let system_version = self.platform.client_version().into_owned();
             let platform = self.platform.clone();
+            let nonce_service = nonce_service.clone();
+            let trusted_rpc_fallback = trusted_rpc_fallback.map(|address| {
+                Arc::new(trusted_rpc::TrustedRpcClient::new(trusted_rpc::Config {
+                    platform: self.platform.clone(),
+                    log_name: log_name.clone(),
+                    log_max_level: config.logs.service_max_level(config.logs.json_rpc_service),
+                    address,
+                    request_timeout: Duration::from_secs(20),
+                }))
+            });
 
             let init_future = async move {
                 // Wait for the chain to finish initializing before starting the JSON-RPC service.
@@ -982,6 +1327,8 @@ impl<TPlat: platform::PlatformRef, TChain> Client<TPlat, TChain> {
                     ),
                     transactions_service: running_chain.transactions_service,
                     runtime_service: running_chain.runtime_service,
+                    nonce_service,
+                    trusted_rpc_fallback,
                     chain_spec: &chain_spec,
                     peer_id: &running_chain.network_identity,
                     system_name,
@@ -1007,6 +1354,7 @@ impl<TPlat: platform::PlatformRef, TChain> Client<TPlat, TChain> {
             key: new_chain_key,
             chain_spec_chain_id,
             json_rpc_frontend: json_rpc_frontend.clone(),
+            nonce_service,
             public_api_chain_destroyed_event,
         });
         Ok(AddChainSuccess {
@@ -1108,6 +1456,402 @@ impl<TPlat: platform::PlatformRef, TChain> Client<TPlat, TChain> {
 
         json_rpc_sender.queue_rpc_request(json_rpc_request)
     }
+
+    /// Returns the nonce to use for the next transaction emitted by the given account on the
+    /// given chain, and reserves it so that subsequent calls return a different nonce.
+    ///
+    /// The nonce is the maximum between the nonce found in the storage of the current best block
+    /// and the highest nonce currently reserved plus one. Reservations expire after a couple of
+    /// minutes, or can be released early using [`Client::release_account_nonce`].
+    ///
+    /// The account must be passed in its SCALE-encoded form, which is typically a 32 bytes
+    /// public key.
+    ///
+    /// Returns [`ReserveNonceError::NonceTrackingDisabled`] if
+    /// [`AddChainConfig::nonce_tracking`] was `false` when adding the chain.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the [`ChainId`] is invalid.
+    ///
+    pub fn reserve_account_nonce(
+        &self,
+        chain_id: ChainId,
+        account: Vec<u8>,
+    ) -> impl future::Future<Output = Result<u64, ReserveNonceError>> + Send + 'static {
+        let nonce_service = self.nonce_service(chain_id);
+        async move {
+            match nonce_service {
+                Some(nonce_service) => nonce_service.reserve_nonce(account).await,
+                None => Err(ReserveNonceError::NonceTrackingDisabled),
+            }
+        }
+    }
+
+    /// Releases a nonce previously returned by [`Client::reserve_account_nonce`], for example
+    /// because the transaction that uses it couldn't be submitted.
+    ///
+    /// Does nothing if [`AddChainConfig::nonce_tracking`] was `false` when adding the chain.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the [`ChainId`] is invalid.
+    ///
+    pub fn release_account_nonce(
+        &self,
+        chain_id: ChainId,
+        account: Vec<u8>,
+        nonce: u64,
+    ) -> impl future::Future<Output = ()> + Send + 'static {
+        let nonce_service = self.nonce_service(chain_id);
+        async move {
+            if let Some(nonce_service) = nonce_service {
+                nonce_service.release_nonce(account, nonce).await
+            }
+        }
+    }
+
+    /// Registers a "hot" runtime call on the given chain, in other words a runtime call that is
+    /// expected to be performed frequently, for example `Core_version` or
+    /// `TransactionPaymentApi_query_info` with a specific parameter.
+    ///
+    /// Every time the best block of the chain changes, the call proof of this call is downloaded
+    /// from the network ahead of time, so that performing the same call against the new best
+    /// block, for example through the JSON-RPC functions, doesn't have to wait for the
+    /// networking. `parameter` must be exactly the parameter later passed to the call.
+    ///
+    /// Chains that are identical to a chain that has previously been added share their hot
+    /// calls.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the [`ChainId`] is invalid.
+    ///
+    pub fn register_hot_runtime_call(
+        &self,
+        chain_id: ChainId,
+        method: String,
+        parameter: Vec<u8>,
+    ) -> impl future::Future<Output = ()> + Send + 'static {
+        let mut services_init = self.chain_services(chain_id);
+        async move {
+            (&mut services_init).await;
+            let services = pin::Pin::new(&mut services_init).take_output().unwrap();
+            services
+                .runtime_service
+                .register_hot_call(method, parameter)
+                .await;
+        }
+    }
+
+    /// Unregisters a runtime call previously registered with
+    /// [`Client::register_hot_runtime_call`].
+    ///
+    /// # Panic
+    ///
+    /// Panics if the [`ChainId`] is invalid.
+    ///
+    pub fn unregister_hot_runtime_call(
+        &self,
+        chain_id: ChainId,
+        method: String,
+        parameter: Vec<u8>,
+    ) -> impl future::Future<Output = ()> + Send + 'static {
+        let mut services_init = self.chain_services(chain_id);
+        async move {
+            (&mut services_init).await;
+            let services = pin::Pin::new(&mut services_init).take_output().unwrap();
+            services
+                .runtime_service
+                .unregister_hot_call(&method, &parameter)
+                .await;
+        }
+    }
+
+    /// Obtains from the network a GrandPa justification proving the finality of the given
+    /// block, and verifies it.
+    ///
+    /// On success, returns the SCALE-encoded justification. Justifications that have been
+    /// obtained in the past are kept in a cache.
+    ///
+    /// Justifications are verified against the GrandPa authorities of the current finalized
+    /// block. As such, only justifications of blocks finalized since the latest change of
+    /// authorities can be obtained.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the [`ChainId`] is invalid.
+    ///
+    pub fn finality_proof(
+        &self,
+        chain_id: ChainId,
+        block_number: u64,
+        block_hash: [u8; 32],
+    ) -> impl future::Future<Output = Result<Vec<u8>, FinalityProofQueryError>> + Send + 'static
+    {
+        let mut services_init = self.chain_services(chain_id);
+        async move {
+            (&mut services_init).await;
+            let services = pin::Pin::new(&mut services_init).take_output().unwrap();
+            services
+                .sync_service
+                .clone()
+                .finality_proof_query(block_number, block_hash, 3, Duration::from_secs(12))
+                .await
+        }
+    }
+
+    /// Subscribes to the failures to verify the block headers and finality proofs sent by the
+    /// peers of the given chain.
+    ///
+    /// Only up to `buffer_size` failures are buffered. Failures that happen while the buffer is
+    /// full are silently discarded. The stream ends when the chain is removed. If the chain is
+    /// a parachain, the stream ends immediately.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the [`ChainId`] is invalid.
+    ///
+    pub fn subscribe_verification_failures(
+        &self,
+        chain_id: ChainId,
+        buffer_size: usize,
+    ) -> impl future::Future<Output = impl Stream<Item = VerificationFailure> + Send + Unpin + 'static>
+           + Send
+           + 'static {
+        let mut services_init = self.chain_services(chain_id);
+        async move {
+            (&mut services_init).await;
+            let services = pin::Pin::new(&mut services_init).take_output().unwrap();
+            services
+                .sync_service
+                .subscribe_verification_failures(buffer_size)
+                .await
+        }
+    }
+
+    /// Subscribes to the finality equivocations detected on the given chain.
+    ///
+    /// A finality equivocation happens when a valid justification targets a block that
+    /// conflicts with a block that has already been finalized. Once an equivocation has been
+    /// detected, the finalized block of the chain no longer advances and its finality can't be
+    /// trusted anymore. Embedders are encouraged to prominently alert their users.
+    ///
+    /// Only up to `buffer_size` equivocations are buffered. The stream ends when the chain is
+    /// removed. If the chain is a parachain, the stream ends immediately.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the [`ChainId`] is invalid.
+    ///
+    pub fn subscribe_finality_equivocations(
+        &self,
+        chain_id: ChainId,
+        buffer_size: usize,
+    ) -> impl future::Future<
+        Output = impl Stream<Item = FinalityEquivocation> + Send + Unpin + 'static,
+    > + Send
+           + 'static {
+        let mut services_init = self.chain_services(chain_id);
+        async move {
+            (&mut services_init).await;
+            let services = pin::Pin::new(&mut services_init).take_output().unwrap();
+            services
+                .sync_service
+                .subscribe_finality_equivocations(buffer_size)
+                .await
+        }
+    }
+
+    /// Exports the current state of the given chain as a snapshot, which can later be passed
+    /// as [`AddChainConfig::snapshot`], possibly to a different instance of the client.
+    ///
+    /// The snapshot contains the state of the finalized block, the runtime code, the headers of
+    /// the recent ancestors of the finalized block, and the list of nodes known to be part of
+    /// the peer-to-peer network.
+    ///
+    /// If the chain is still initializing, the returned future waits for its initialization to
+    /// finish.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the [`ChainId`] is invalid.
+    ///
+    pub fn export_snapshot(
+        &self,
+        chain_id: ChainId,
+    ) -> impl future::Future<Output = String> + Send + 'static {
+        let public_api_chain = self.public_api_chains.get(chain_id.0).unwrap();
+        let running_chain = self
+            .chains_by_key
+            .as_ref()
+            .unwrap_or_else(|| unreachable!())
+            .get(&public_api_chain.key)
+            .unwrap();
+
+        // Clone `running_chain.services`.
+        let mut services_init = match &running_chain.services {
+            future::MaybeDone::Done(d) => future::MaybeDone::Done(d.clone()),
+            future::MaybeDone::Future(d) => future::MaybeDone::Future(d.clone()),
+            future::MaybeDone::Gone => unreachable!(),
+        };
+
+        let genesis_block_hash = public_api_chain.key.genesis_block_hash;
+
+        async move {
+            (&mut services_init).await;
+            let services = pin::Pin::new(&mut services_init).take_output().unwrap();
+            database::encode_snapshot(
+                &services.network_service,
+                services.network_service_chain_id,
+                &services.sync_service,
+                &services.runtime_service,
+                &genesis_block_hash,
+            )
+            .await
+        }
+    }
+
+    /// Returns a snapshot of the status of all the chains, in one call.
+    ///
+    /// This is equivalent to sending multiple JSON-RPC requests (such as `system_health`) to
+    /// each chain, and is meant for example to show an overview of all the chains to the user.
+    ///
+    /// If a chain is still initializing, the returned future waits for its initialization to
+    /// finish. Because the returned future doesn't borrow the [`Client`], chains removed in the
+    /// meanwhile are still included in the output.
+    pub fn chains_iter(
+        &self,
+    ) -> impl future::Future<Output = impl ExactSizeIterator<Item = (ChainId, ChainStatus)>>
+           + Send
+           + 'static {
+        // `chains_by_key` is created lazily when `add_chain` is called.
+        let chains_by_key = self.chains_by_key.as_ref();
+
+        let statuses = self
+            .public_api_chains
+            .iter()
+            .map(|(chain_id, public_api_chain)| {
+                let running_chain = chains_by_key
+                    .unwrap_or_else(|| unreachable!())
+                    .get(&public_api_chain.key)
+                    .unwrap();
+
+                // Clone `running_chain.services`.
+                let mut services_init = match &running_chain.services {
+                    future::MaybeDone::Done(d) => future::MaybeDone::Done(d.clone()),
+                    future::MaybeDone::Future(d) => future::MaybeDone::Future(d.clone()),
+                    future::MaybeDone::Gone => unreachable!(),
+                };
+
+                let genesis_block_hash = public_api_chain.key.genesis_block_hash;
+
+                async move {
+                    (&mut services_init).await;
+                    let services = pin::Pin::new(&mut services_init).take_output().unwrap();
+                    (
+                        ChainId(chain_id),
+                        chain_status(&services, &genesis_block_hash).await,
+                    )
+                }
+            })
+            .collect::<Vec<_>>();
+
+        async move { future::join_all(statuses).await.into_iter() }
+    }
+
+    fn nonce_service(&self, chain_id: ChainId) -> Option<nonce_service::NonceService> {
+        self.public_api_chains
+            .get(chain_id.0)
+            .unwrap()
+            .nonce_service
+            .clone()
+    }
+
+    /// Returns a clone of the services of the given chain, which might still be initializing.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the [`ChainId`] is invalid.
+    ///
+    fn chain_services(
+        &self,
+        chain_id: ChainId,
+    ) -> future::MaybeDone<future::Shared<future::RemoteHandle<ChainServices<TPlat>>>> {
+        let public_api_chain = self.public_api_chains.get(chain_id.0).unwrap();
+        let running_chain = self
+            .chains_by_key
+            .as_ref()
+            .unwrap_or_else(|| unreachable!())
+            .get(&public_api_chain.key)
+            .unwrap();
+
+        match &running_chain.services {
+            future::MaybeDone::Done(d) => future::MaybeDone::Done(d.clone()),
+            future::MaybeDone::Future(d) => future::MaybeDone::Future(d.clone()),
+            future::MaybeDone::Gone => unreachable!(),
+        }
+    }
+}
+
+/// Builds the [`ChainStatus`] of the chain whose services are passed as parameter.
+async fn chain_status<TPlat: platform::PlatformRef>(
+    services: &ChainServices<TPlat>,
+    genesis_block_hash: &[u8; 32],
+) -> ChainStatus {
+    let block_number_bytes = services.sync_service.block_number_bytes();
+
+    // The subscription is immediately dropped after the initial state has been obtained.
+    let (finalized_block_number, finalized_block_hash, best_block) = {
+        let subscription = services.sync_service.subscribe_all(32, false).await;
+        let finalized_block_number = header::decode(
+            &subscription.finalized_block_scale_encoded_header,
+            block_number_bytes,
+        )
+        .map_or(0, |h| h.number);
+        let finalized_block_hash = header::hash_from_scale_encoded_header(
+            &subscription.finalized_block_scale_encoded_header,
+        );
+        let best_block = subscription
+            .non_finalized_blocks_ancestry_order
+            .iter()
+            .find(|block| block.is_new_best)
+            .and_then(|block| {
+                let number = header::decode(&block.scale_encoded_header, block_number_bytes)
+                    .ok()?
+                    .number;
+                Some((
+                    number,
+                    header::hash_from_scale_encoded_header(&block.scale_encoded_header),
+                ))
+            });
+        (finalized_block_number, finalized_block_hash, best_block)
+    };
+    let (best_block_number, best_block_hash) =
+        best_block.unwrap_or((finalized_block_number, finalized_block_hash));
+
+    ChainStatus {
+        num_peers: services.sync_service.syncing_peers().await.len(),
+        best_block_number,
+        best_block_hash,
+        finalized_block_number,
+        finalized_block_hash,
+        sync_phase: services.sync_service.sync_phase().await,
+        gap_sync: services.sync_service.gap_sync_progress().await,
+        database_size: database::encode_database(
+            &services.network_service,
+            services.network_service_chain_id,
+            &services.sync_service,
+            &services.runtime_service,
+            genesis_block_hash,
+            usize::MAX,
+        )
+        .await
+        .len(),
+        runtime_call_metrics: services.runtime_service.runtime_call_metrics(),
+        storage_query_cache: services.sync_service.storage_query_cache_metrics().await,
+        discovery_schedule: services.network_service.discovery_schedule().await,
+    }
 }
 
 impl<TPlat: platform::PlatformRef, TChain> ops::Index<ChainId> for Client<TPlat, TChain> {
@@ -1149,11 +1893,27 @@ pub enum AddChainError {
     /// indicated in the chain specification of the parachain.
     #[display(fmt = "Multiple relevant relay chains found")]
     MultipleRelayChains,
+    /// [`AddChainConfig::trusted_rpc_fallback`] isn't a non-secure WebSocket multiaddress, or the
+    /// platform doesn't support TCP connections to it.
+    #[display(fmt = "Invalid trusted JSON-RPC server address")]
+    InvalidTrustedRpcFallbackAddress,
+    /// Failed to decode [`AddChainConfig::snapshot`].
+    #[display(fmt = "Failed to decode snapshot: {_0}")]
+    InvalidSnapshot(SnapshotDecodeError),
+    /// [`AddChainConfig::snapshot`] has been exported from a chain with a different genesis
+    /// block.
+    #[display(fmt = "Snapshot doesn't match the chain specification")]
+    SnapshotWrongChain,
 }
 
 enum StartServicesChainTy<'a, TPlat: platform::PlatformRef> {
     RelayChain {
         chain_information: chain::chain_information::ValidChainInformation,
+        gap_sync: bool,
+        pseudo_finality_depth: Option<NonZeroU64>,
+        bad_blocks: Vec<[u8; 32]>,
+        fork_blocks: Vec<(u64, [u8; 32])>,
+        recent_headers: Vec<Vec<u8>>,
     },
     Parachain {
         relay_chain: &'a ChainServices<TPlat>,
@@ -1168,14 +1928,19 @@ enum StartServicesChainTy<'a, TPlat: platform::PlatformRef> {
 /// other services will later shut down as well.
 async fn start_services<TPlat: platform::PlatformRef>(
     log_name: String,
+    logs: &AddChainConfigLogs,
     platform: &TPlat,
     runtime_code_hint: Option<database::DatabaseContentRuntimeCodeHint>,
     genesis_block_scale_encoded_header: Vec<u8>,
     block_number_bytes: usize,
     fork_id: Option<String>,
+    track_peers_quality: bool,
+    ip_family_policy: IpFamilyPolicy,
+    best_block_policy: BestBlockPolicy,
     config: StartServicesChainTy<'_, TPlat>,
     network_identify_agent_version: String,
     network_noise_key: connection::NoiseKey,
+    announced_headers_cache: Arc<network_service::AnnouncedHeadersCache>,
 ) -> ChainServices<TPlat> {
     // Since `network_noise_key` is moved out below, use it to build the network identity ahead
     // of the network service starting.
@@ -1189,10 +1954,18 @@ async fn start_services<TPlat: platform::PlatformRef>(
             num_events_receivers: 1, // Configures the length of `network_event_receivers`
             identify_agent_version: network_identify_agent_version,
             noise_key: network_noise_key,
+            log_max_level: logs.service_max_level(logs.network_service),
+            address_sanitize_policy: multiaddr::SanitizePolicy {
+                allow_loopback: false,
+            },
+            identity_pinning_policy: network_service::IdentityPinningPolicy::Warn,
+            ip_family_policy,
+            announced_headers_cache,
             chains: vec![network_service::ConfigChain {
                 log_name: log_name.clone(),
                 grandpa_protocol_finalized_block_height: if let StartServicesChainTy::RelayChain {
                     chain_information,
+                    ..
                 } = &config
                 {
                     if matches!(
@@ -1211,7 +1984,9 @@ async fn start_services<TPlat: platform::PlatformRef>(
                     &genesis_block_scale_encoded_header,
                 ),
                 best_block: match &config {
-                    StartServicesChainTy::RelayChain { chain_information } => (
+                    StartServicesChainTy::RelayChain {
+                        chain_information, ..
+                    } => (
                         chain_information.as_ref().finalized_block_header.number,
                         chain_information
                             .as_ref()
@@ -1241,6 +2016,7 @@ async fn start_services<TPlat: platform::PlatformRef>(
                 },
                 fork_id,
                 block_number_bytes: usize::from(block_number_bytes),
+                track_peers_quality,
             }],
         })
         .await;
@@ -1263,6 +2039,7 @@ async fn start_services<TPlat: platform::PlatformRef>(
                 sync_service::SyncService::new(sync_service::Config {
                     platform: platform.clone(),
                     log_name: log_name.clone(),
+                    log_max_level: logs.service_max_level(logs.sync_service),
                     block_number_bytes,
                     network_service: (network_service.clone(), network_service_chain_id),
                     network_events_receiver: network_event_receivers.pop().unwrap(),
@@ -1285,16 +2062,26 @@ async fn start_services<TPlat: platform::PlatformRef>(
             let runtime_service = Arc::new(
                 runtime_service::RuntimeService::new(runtime_service::Config {
                     log_name: log_name.clone(),
+                    log_max_level: logs.service_max_level(logs.runtime_service),
                     platform: platform.clone(),
                     sync_service: sync_service.clone(),
                     genesis_block_scale_encoded_header,
+                    max_memory_pages: Some(RUNTIME_MAX_MEMORY_PAGES),
+                    best_block_policy,
                 })
                 .await,
             );
 
             (sync_service, runtime_service)
         }
-        StartServicesChainTy::RelayChain { chain_information } => {
+        StartServicesChainTy::RelayChain {
+            chain_information,
+            gap_sync,
+            pseudo_finality_depth,
+            bad_blocks,
+            fork_blocks,
+            recent_headers,
+        } => {
             // Chain is a relay chain.
 
             // The sync service is leveraging the network service, downloads block headers,
@@ -1303,6 +2090,7 @@ async fn start_services<TPlat: platform::PlatformRef>(
             let sync_service = Arc::new(
                 sync_service::SyncService::new(sync_service::Config {
                     log_name: log_name.clone(),
+                    log_max_level: logs.service_max_level(logs.sync_service),
                     block_number_bytes,
                     platform: platform.clone(),
                     network_service: (network_service.clone(), network_service_chain_id),
@@ -1317,6 +2105,11 @@ async fn start_services<TPlat: platform::PlatformRef>(
                                     closest_ancestor_excluding: hint.closest_ancestor_excluding,
                                 }
                             }),
+                            gap_sync,
+                            pseudo_finality_depth,
+                            bad_blocks,
+                            fork_blocks,
+                            recent_headers,
                         },
                     ),
                 })
@@ -1328,9 +2121,12 @@ async fn start_services<TPlat: platform::PlatformRef>(
             let runtime_service = Arc::new(
                 runtime_service::RuntimeService::new(runtime_service::Config {
                     log_name: log_name.clone(),
+                    log_max_level: logs.service_max_level(logs.runtime_service),
                     platform: platform.clone(),
                     sync_service: sync_service.clone(),
                     genesis_block_scale_encoded_header,
+                    max_memory_pages: Some(RUNTIME_MAX_MEMORY_PAGES),
+                    best_block_policy,
                 })
                 .await,
             );
@@ -1346,6 +2142,7 @@ async fn start_services<TPlat: platform::PlatformRef>(
     let transactions_service = Arc::new(
         transactions_service::TransactionsService::new(transactions_service::Config {
             log_name,
+            log_max_level: logs.max_level,
             platform: platform.clone(),
             sync_service: sync_service.clone(),
             runtime_service: runtime_service.clone(),
@@ -36,22 +36,34 @@
 //! [`NetworkService::new`]. These channels inform the foreground about updates to the network
 //! connectivity.
 
-use crate::platform::{self, address_parse, PlatformRef};
+use crate::{
+    platform::{self, address_parse, PlatformRef},
+    util,
+};
 
 use alloc::{
     borrow::ToOwned as _,
     boxed::Box,
+    collections::VecDeque,
     format,
     string::{String, ToString as _},
     sync::Arc,
     vec::{self, Vec},
 };
-use core::{cmp, mem, pin::Pin, task::Poll, time::Duration};
+use async_lock::Mutex;
+use core::{
+    cmp, mem,
+    num::{NonZeroU32, NonZeroUsize},
+    pin::Pin,
+    task::Poll,
+    time::Duration,
+};
 use futures_channel::oneshot;
 use futures_lite::FutureExt as _;
 use futures_util::{future, stream, StreamExt as _};
 use hashbrown::{HashMap, HashSet};
 use itertools::Itertools as _;
+use rand::seq::SliceRandom as _;
 use rand_chacha::rand_core::SeedableRng as _;
 use smoldot::{
     header,
@@ -61,10 +73,10 @@ use smoldot::{
         multiaddr::{self, Multiaddr},
         peer_id::{self, PeerId},
     },
-    network::{basic_peering_strategy, protocol, service},
+    network::{basic_peering_strategy, codec, service},
 };
 
-pub use service::{ChainId, EncodedMerkleProof, QueueNotificationError};
+pub use service::{BlockHashOrNumber, ChainId, EncodedMerkleProof, QueueNotificationError};
 
 mod tasks;
 
@@ -84,6 +96,141 @@ pub struct Config<TPlat> {
 
     /// List of chains to connect to. Chains are later referred to by their index in this list.
     pub chains: Vec<ConfigChain>,
+
+    /// Maximum level of the logs emitted by this service.
+    ///
+    /// > **Note**: Logs emitted by the tasks dedicated to individual connections aren't affected.
+    pub log_max_level: log::LevelFilter,
+
+    /// Policy used to filter the addresses discovered through the Kademlia DHT before they are
+    /// stored.
+    pub address_sanitize_policy: multiaddr::SanitizePolicy,
+
+    /// What to do when an address that has previously been reached under a certain [`PeerId`]
+    /// is reached under a different one.
+    pub identity_pinning_policy: IdentityPinningPolicy,
+
+    /// Which addresses to dial first when a peer advertises both IPv4 and IPv6 addresses.
+    pub ip_family_policy: IpFamilyPolicy,
+
+    /// Cache of the headers found in block announces. Can be shared between multiple network
+    /// services in order to avoid decoding the same header multiple times.
+    pub announced_headers_cache: Arc<AnnouncedHeadersCache>,
+}
+
+/// Cache of the headers found in block announces. See [`Config::announced_headers_cache`].
+///
+/// Chains whose nodes are the same, such as chains that share the same genesis block, receive
+/// the same block announces. Sharing this cache between their network services ensures that each
+/// announced header is decoded only once.
+pub struct AnnouncedHeadersCache {
+    /// Decoded headers, indexed by hash and number of bytes of the block number.
+    entries: Mutex<lru::LruCache<([u8; 32], usize), Arc<AnnouncedHeader>, util::SipHasherBuild>>,
+}
+
+impl AnnouncedHeadersCache {
+    /// Creates a new empty cache holding at most `capacity` headers.
+    ///
+    /// The seed is used in order to randomize the hashing of the cache keys.
+    pub fn new(capacity: NonZeroUsize, randomness_seed: [u8; 16]) -> Self {
+        AnnouncedHeadersCache {
+            entries: Mutex::new(lru::LruCache::with_hasher(
+                capacity,
+                util::SipHasherBuild::new(randomness_seed),
+            )),
+        }
+    }
+
+    /// Returns the decoded version of the given header, decoding it and inserting it in the
+    /// cache if necessary.
+    async fn get_or_decode(
+        &self,
+        scale_encoded_header: &[u8],
+        block_number_bytes: usize,
+    ) -> Result<Arc<AnnouncedHeader>, header::Error> {
+        let hash = header::hash_from_scale_encoded_header(scale_encoded_header);
+
+        let mut entries = self.entries.lock().await;
+        if let Some(decoded) = entries.get(&(hash, block_number_bytes)) {
+            return Ok(decoded.clone());
+        }
+
+        let decoded_header = header::decode(scale_encoded_header, block_number_bytes)?;
+        let decoded = Arc::new(AnnouncedHeader {
+            hash,
+            number: decoded_header.number,
+            parent_hash: *decoded_header.parent_hash,
+        });
+        entries.put((hash, block_number_bytes), decoded.clone());
+        Ok(decoded)
+    }
+}
+
+/// Header of a block found in a block announce. See [`Event::BlockAnnounce`].
+#[derive(Debug)]
+pub struct AnnouncedHeader {
+    /// Hash of the header.
+    pub hash: [u8; 32],
+    /// Height of the block.
+    pub number: u64,
+    /// Hash of the parent of the block.
+    pub parent_hash: [u8; 32],
+}
+
+/// See [`Config::identity_pinning_policy`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum IdentityPinningPolicy {
+    /// The identities found behind addresses aren't remembered.
+    Disabled,
+    /// The identities found behind addresses are remembered, and a warning is printed if an
+    /// address presents a different identity. The address is otherwise used normally.
+    Warn,
+    /// Same as [`IdentityPinningPolicy::Warn`], but additionally the address is put in
+    /// quarantine and is no longer dialed.
+    Quarantine,
+}
+
+/// See [`Config::ip_family_policy`].
+///
+/// Only the addresses starting with an IP address or with a domain name that resolves to a
+/// specific IP family (`/dns4` or `/dns6`) are affected by this policy. Other addresses are
+/// considered as belonging to neither family.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum IpFamilyPolicy {
+    /// Addresses are dialed regardless of their IP family.
+    NoPreference,
+    /// IPv6 addresses are dialed before other addresses.
+    PreferIpv6,
+    /// IPv4 addresses are dialed before other addresses.
+    PreferIpv4,
+    /// IPv6 addresses are dialed first. If the connection attempt hasn't finished its handshake
+    /// after `fallback_delay`, or if it fails, an IPv4 address of the same peer is dialed in
+    /// parallel. Whichever connection finishes its handshake first is kept, and the other is
+    /// shut down.
+    ///
+    /// This corresponds to the "Happy Eyeballs" algorithm described in RFC 8305.
+    HappyEyeballs {
+        /// Delay after which the IPv4 address is dialed. RFC 8305 recommends 250 milliseconds.
+        fallback_delay: Duration,
+    },
+}
+
+impl Default for IpFamilyPolicy {
+    /// Returns [`IpFamilyPolicy::NoPreference`].
+    fn default() -> Self {
+        IpFamilyPolicy::NoPreference
+    }
+}
+
+impl IpFamilyPolicy {
+    /// Returns the family of the addresses to dial first, if any.
+    fn preferred_family(&self) -> Option<IpFamily> {
+        match self {
+            IpFamilyPolicy::NoPreference => None,
+            IpFamilyPolicy::PreferIpv6 | IpFamilyPolicy::HappyEyeballs { .. } => Some(IpFamily::V6),
+            IpFamilyPolicy::PreferIpv4 => Some(IpFamily::V4),
+        }
+    }
 }
 
 /// See [`Config::chains`].
@@ -116,9 +263,17 @@ pub struct ConfigChain {
     /// Must be `Some` if and only if the chain uses the GrandPa networking protocol. Contains the
     /// number of the finalized block at the time of the initialization.
     pub grandpa_protocol_finalized_block_height: Option<u64>,
+
+    /// If `true`, the quality of the peers of this chain is tracked and can be retrieved with
+    /// [`NetworkService::peers_quality`]. If `false`, [`NetworkService::peers_quality`] always
+    /// returns an empty list and [`NetworkService::prioritize_peers`] does nothing.
+    pub track_peers_quality: bool,
 }
 
 pub struct NetworkService<TPlat: PlatformRef> {
+    /// Target of the logs emitted by the service.
+    log_target: util::LogTarget,
+
     /// Names of the various chains the network service connects to. Used only for logging
     /// purposes.
     log_chain_names: hashbrown::HashMap<ChainId, String, fnv::FnvBuildHasher>,
@@ -152,6 +307,8 @@ impl<TPlat: PlatformRef> NetworkService<TPlat> {
 
         let mut log_chain_names =
             hashbrown::HashMap::with_capacity_and_hasher(config.chains.len(), Default::default());
+        let mut peers_quality_chains =
+            HashSet::with_capacity_and_hasher(config.chains.len(), Default::default());
         let mut chain_ids = Vec::with_capacity(config.chains.len());
 
         let mut network = service::ChainNetwork::new(service::Config {
@@ -164,6 +321,21 @@ impl<TPlat: PlatformRef> NetworkService<TPlat> {
                 config.platform.fill_random_bytes(&mut seed);
                 seed
             },
+            deterministic_ordering: false,
+            // Re-opening gossip links is handled by the peering strategy.
+            gossip_open_retry: None,
+            reputation_ban: Some(service::ReputationBanConfig {
+                threshold: REPUTATION_BAN_THRESHOLD,
+                duration: REPUTATION_BAN_DURATION,
+            }),
+            refused_protocols_cooldown: Some(Duration::from_secs(60)),
+            ping_interval: Duration::from_secs(20),
+            ping_timeout: Duration::from_secs(10),
+            max_ping_failures: NonZeroU32::new(1).unwrap(),
+            max_connections_per_peer: None,
+            max_inbound_substreams: None,
+            max_queued_notifications_bytes: 1024 * 1024,
+            address_sanitize_policy: config.address_sanitize_policy.clone(),
         });
 
         for chain in config.chains {
@@ -187,46 +359,30 @@ impl<TPlat: PlatformRef> NetworkService<TPlat> {
                     best_hash: chain.best_block.1,
                     best_number: chain.best_block.0,
                     genesis_hash: chain.genesis_block_hash,
-                    role: protocol::Role::Light,
+                    role: codec::Role::Light,
+                    gossip_slots: None,
+                    notifications_open_timeout: Duration::from_secs(10),
+                    max_notifications_handshake_size: 1024 * 1024,
+                    max_notification_size: 1024 * 1024,
                     allow_inbound_block_requests: false,
+                    custom_request_response_protocols: Vec::new(),
+                    custom_notifications_protocols: Vec::new(),
                 })
                 .unwrap();
 
             log_chain_names.insert(chain_id, chain.log_name);
+            if chain.track_peers_quality {
+                peers_quality_chains.insert(chain_id);
+            }
             chain_ids.push(chain_id);
         }
 
+        let log_target = util::LogTarget::new("network".to_owned(), config.log_max_level);
+
         let on_service_killed = event_listener::Event::new();
 
         let (messages_tx, messages_rx) = async_channel::bounded(32);
 
-        // Spawn task starts a discovery request at a periodic interval.
-        // This is done through a separate task due to ease of implementation.
-        config.platform.spawn_task(
-            "network-discovery".into(),
-            Box::pin({
-                let platform = config.platform.clone();
-                let messages_tx = messages_tx.clone();
-                async move {
-                    let mut next_discovery = Duration::from_secs(5);
-
-                    loop {
-                        platform.sleep(next_discovery).await;
-                        next_discovery = cmp::min(next_discovery * 2, Duration::from_secs(120));
-
-                        if messages_tx
-                            .send(ToBackground::StartDiscovery)
-                            .await
-                            .is_err()
-                        {
-                            break;
-                        }
-                    }
-                }
-                .or(on_service_killed.listen())
-            }),
-        );
-
         // Spawn main task that processes the network service.
         let task = Box::pin(
             background_task(BackgroundTask {
@@ -236,6 +392,14 @@ impl<TPlat: PlatformRef> NetworkService<TPlat> {
                     seed
                 }),
                 identify_agent_version: config.identify_agent_version,
+                address_sanitize_policy: config.address_sanitize_policy,
+                identity_pinning_policy: config.identity_pinning_policy,
+                ip_family_policy: config.ip_family_policy,
+                log_target: log_target.clone(),
+                connections_log_target: util::LogTarget::new(
+                    "connections".to_owned(),
+                    config.log_max_level,
+                ),
                 log_chain_names: log_chain_names.clone(),
                 messages_tx: messages_tx.clone(),
                 peering_strategy: basic_peering_strategy::BasicPeeringStrategy::new(),
@@ -256,18 +420,34 @@ impl<TPlat: PlatformRef> NetworkService<TPlat> {
                     2,
                     Default::default(),
                 ),
+                announced_headers_cache: config.announced_headers_cache,
+                gossip_links_last_announce: HashMap::with_capacity_and_hasher(
+                    32,
+                    Default::default(),
+                ),
+                gossip_links_closed_locally: VecDeque::new(),
+                peers_quality_chains,
+                peers_quality: HashMap::with_capacity_and_hasher(32, Default::default()),
+                prioritized_peers: HashMap::with_capacity_and_hasher(0, Default::default()),
+                requests_targets: HashMap::with_capacity_and_hasher(8, Default::default()),
+                dial_races: HashMap::with_capacity_and_hasher(0, Default::default()),
+                discovery_period: DISCOVERY_MIN_PERIOD,
+                next_discovery_when: config.platform.now() + DISCOVERY_MIN_PERIOD,
+                next_discovery: Box::pin(config.platform.sleep(DISCOVERY_MIN_PERIOD)),
             })
             .or(on_service_killed.listen()),
         );
 
-        config
-            .platform
-            .spawn_task("network-service".into(), async move {
+        config.platform.spawn_task("network-service".into(), {
+            let log_target = log_target.clone();
+            async move {
                 task.await;
-                log::debug!(target: "network", "Shutdown")
-            });
+                util::log!(Debug, &log_target, "Shutdown")
+            }
+        });
 
         let final_network_service = Arc::new(NetworkService {
+            log_target,
             log_chain_names,
             messages_tx,
             on_service_killed,
@@ -296,9 +476,9 @@ impl<TPlat: PlatformRef> NetworkService<TPlat> {
         self: Arc<Self>,
         target: PeerId,
         chain_id: ChainId,
-        config: protocol::BlocksRequestConfig,
+        config: codec::BlocksRequestConfig,
         timeout: Duration,
-    ) -> Result<Vec<protocol::BlockData>, BlocksRequestError> {
+    ) -> Result<Vec<codec::BlockData>, BlocksRequestError> {
         let (tx, rx) = oneshot::channel();
 
         self.messages_tx
@@ -316,8 +496,8 @@ impl<TPlat: PlatformRef> NetworkService<TPlat> {
 
         match &result {
             Ok(blocks) => {
-                log::debug!(
-                    target: "network",
+                util::log!(Debug,
+                    &self.log_target,
                     "Connections({}) => BlocksRequest(chain={}, num_blocks={}, block_data_total_size={})",
                     target,
                     self.log_chain_names[&chain_id],
@@ -331,8 +511,9 @@ impl<TPlat: PlatformRef> NetworkService<TPlat> {
                 );
             }
             Err(err) => {
-                log::debug!(
-                    target: "network",
+                util::log!(
+                    Debug,
+                    &self.log_target,
                     "Connections({}) => BlocksRequest(chain={}, error={:?})",
                     target,
                     self.log_chain_names[&chain_id],
@@ -341,14 +522,15 @@ impl<TPlat: PlatformRef> NetworkService<TPlat> {
             }
         }
 
-        if !log::log_enabled!(log::Level::Debug) {
+        if self.log_target.max_level() < log::Level::Debug || !log::log_enabled!(log::Level::Debug)
+        {
             match &result {
                 Ok(_) | Err(BlocksRequestError::NoConnection) => {}
                 Err(BlocksRequestError::Request(service::BlocksRequestError::Request(err)))
                     if !err.is_protocol_error() => {}
                 Err(err) => {
-                    log::warn!(
-                        target: "network",
+                    util::log!(Warn,
+                        &self.log_target,
                         "Error in block request with {}. This might indicate an incompatibility. Error: {}",
                         target,
                         err
@@ -388,8 +570,9 @@ impl<TPlat: PlatformRef> NetworkService<TPlat> {
             Ok(response) => {
                 // TODO: print total bytes size
                 let decoded = response.decode();
-                log::debug!(
-                    target: "network",
+                util::log!(
+                    Debug,
+                    &self.log_target,
                     "Connections({}) => WarpSyncRequest(chain={}, num_fragments={}, finished={:?})",
                     target,
                     self.log_chain_names[&chain_id],
@@ -398,8 +581,9 @@ impl<TPlat: PlatformRef> NetworkService<TPlat> {
                 );
             }
             Err(err) => {
-                log::debug!(
-                    target: "network",
+                util::log!(
+                    Debug,
+                    &self.log_target,
                     "Connections({}) => WarpSyncRequest(chain={}, error={:?})",
                     target,
                     self.log_chain_names[&chain_id],
@@ -447,7 +631,7 @@ impl<TPlat: PlatformRef> NetworkService<TPlat> {
         self: Arc<Self>,
         chain_id: ChainId,
         target: PeerId, // TODO: takes by value because of futures longevity issue
-        config: protocol::StorageProofRequestConfig<impl Iterator<Item = impl AsRef<[u8]> + Clone>>,
+        config: codec::StorageProofRequestConfig<impl Iterator<Item = impl AsRef<[u8]> + Clone>>,
         timeout: Duration,
     ) -> Result<service::EncodedMerkleProof, StorageProofRequestError> {
         let (tx, rx) = oneshot::channel();
@@ -456,7 +640,7 @@ impl<TPlat: PlatformRef> NetworkService<TPlat> {
             .send(ToBackground::StartStorageProofRequest {
                 target: target.clone(),
                 chain_id,
-                config: protocol::StorageProofRequestConfig {
+                config: codec::StorageProofRequestConfig {
                     block_hash: config.block_hash,
                     keys: config
                         .keys
@@ -475,8 +659,9 @@ impl<TPlat: PlatformRef> NetworkService<TPlat> {
         match &result {
             Ok(items) => {
                 let decoded = items.decode();
-                log::debug!(
-                    target: "network",
+                util::log!(
+                    Debug,
+                    &self.log_target,
                     "Connections({}) => StorageProofRequest(chain={}, total_size={})",
                     target,
                     self.log_chain_names[&chain_id],
@@ -484,8 +669,9 @@ impl<TPlat: PlatformRef> NetworkService<TPlat> {
                 );
             }
             Err(err) => {
-                log::debug!(
-                    target: "network",
+                util::log!(
+                    Debug,
+                    &self.log_target,
                     "Connections({}) => StorageProofRequest(chain={}, error={:?})",
                     target,
                     self.log_chain_names[&chain_id],
@@ -505,7 +691,7 @@ impl<TPlat: PlatformRef> NetworkService<TPlat> {
         self: Arc<Self>,
         chain_id: ChainId,
         target: PeerId, // TODO: takes by value because of futures longevity issue
-        config: protocol::CallProofRequestConfig<'_, impl Iterator<Item = impl AsRef<[u8]>>>,
+        config: codec::CallProofRequestConfig<'_, impl Iterator<Item = impl AsRef<[u8]>>>,
         timeout: Duration,
     ) -> Result<EncodedMerkleProof, CallProofRequestError> {
         let (tx, rx) = oneshot::channel();
@@ -514,7 +700,7 @@ impl<TPlat: PlatformRef> NetworkService<TPlat> {
             .send(ToBackground::StartCallProofRequest {
                 target: target.clone(),
                 chain_id,
-                config: protocol::CallProofRequestConfig {
+                config: codec::CallProofRequestConfig {
                     block_hash: config.block_hash,
                     method: config.method.into_owned().into(),
                     parameter_vectored: config
@@ -534,8 +720,9 @@ impl<TPlat: PlatformRef> NetworkService<TPlat> {
         match &result {
             Ok(items) => {
                 let decoded = items.decode();
-                log::debug!(
-                    target: "network",
+                util::log!(
+                    Debug,
+                    &self.log_target,
                     "Connections({}) => CallProofRequest({}, total_size: {})",
                     target,
                     self.log_chain_names[&chain_id],
@@ -543,8 +730,9 @@ impl<TPlat: PlatformRef> NetworkService<TPlat> {
                 );
             }
             Err(err) => {
-                log::debug!(
-                    target: "network",
+                util::log!(
+                    Debug,
+                    &self.log_target,
                     "Connections({}) => CallProofRequest({}, {})",
                     target,
                     self.log_chain_names[&chain_id],
@@ -556,7 +744,9 @@ impl<TPlat: PlatformRef> NetworkService<TPlat> {
         result
     }
 
-    /// Announces transaction to the peers we are connected to.
+    /// Announces transaction to the peers we are connected to, or to at most `max_peers` of
+    /// them. If there are more than `max_peers` peers, they are chosen randomly, meaning that
+    /// calling this function multiple times targets a different subset each time.
     ///
     /// Returns a list of peers that we have sent the transaction to. Can return an empty `Vec`
     /// if we didn't send the transaction to any peer.
@@ -569,6 +759,7 @@ impl<TPlat: PlatformRef> NetworkService<TPlat> {
         self: Arc<Self>,
         chain_id: ChainId,
         transaction: &[u8],
+        max_peers: usize,
     ) -> Vec<PeerId> {
         let (tx, rx) = oneshot::channel();
 
@@ -576,6 +767,7 @@ impl<TPlat: PlatformRef> NetworkService<TPlat> {
             .send(ToBackground::AnnounceTransaction {
                 chain_id,
                 transaction: transaction.to_vec(), // TODO: ovheread
+                max_peers,
                 result: tx,
             })
             .await
@@ -662,6 +854,103 @@ impl<TPlat: PlatformRef> NetworkService<TPlat> {
             .map(|(peer_id, addrs)| (peer_id, addrs.into_iter()))
     }
 
+    /// Returns the quality of the peers of the given chain that have been tracked so far.
+    ///
+    /// Always returns an empty list if [`ConfigChain::track_peers_quality`] was `false`.
+    pub async fn peers_quality(&self, chain_id: ChainId) -> Vec<(PeerId, PeerQuality)> {
+        let (tx, rx) = oneshot::channel();
+
+        self.messages_tx
+            .send(ToBackground::PeersQuality {
+                chain_id,
+                result: tx,
+            })
+            .await
+            .unwrap();
+
+        rx.await.unwrap()
+    }
+
+    /// Adds to the quality of the given peers the given values, for example values that have
+    /// been returned by [`NetworkService::peers_quality`] in the past, and makes the service try
+    /// to open gossip links with these peers in priority, in decreasing order of quality.
+    ///
+    /// The peers must have been added beforehand with [`NetworkService::discover`]. Peers that
+    /// aren't known or that are currently banned are ignored.
+    ///
+    /// Does nothing if [`ConfigChain::track_peers_quality`] was `false`.
+    pub async fn prioritize_peers(
+        &self,
+        chain_id: ChainId,
+        list: impl IntoIterator<Item = (PeerId, PeerQuality)>,
+    ) {
+        self.messages_tx
+            .send(ToBackground::PrioritizePeers {
+                chain_id,
+                list: list.into_iter().collect(),
+            })
+            .await
+            .unwrap();
+    }
+
+    /// Returns the health metrics of the gossip link with the given peer on the given chain, or
+    /// `None` if no gossip link with this peer is known.
+    pub async fn gossip_link_info(
+        &self,
+        chain_id: ChainId,
+        peer_id: PeerId,
+    ) -> Option<GossipLinkInfo> {
+        let (tx, rx) = oneshot::channel();
+        self.messages_tx
+            .send(ToBackground::GossipLinkInfo {
+                chain_id,
+                peer_id,
+                result: tx,
+            })
+            .await
+            .unwrap();
+        rx.await.unwrap()
+    }
+
+    /// Returns the current schedule of the automatic discovery of peers.
+    ///
+    /// Intended for debugging purposes.
+    pub async fn discovery_schedule(&self) -> DiscoverySchedule {
+        let (tx, rx) = oneshot::channel();
+        self.messages_tx
+            .send(ToBackground::DiscoverySchedule { result: tx })
+            .await
+            .unwrap();
+        rx.await.unwrap()
+    }
+
+    /// Adds `reputation_change` to the reputation of the given peer.
+    ///
+    /// Intended to be used when a peer has been caught misbehaving, for example by sending
+    /// invalid blocks. The `reason` is used for logging purposes.
+    ///
+    /// If the reputation of the peer falls below a certain threshold, the peer is banned for a
+    /// certain duration: its gossip links are closed, and it isn't assigned a slot again until
+    /// the end of the ban. An [`Event::Disconnected`] is generated for each gossip link that
+    /// was open. See [`service::ChainNetwork::report_peer`].
+    pub async fn report_peer(
+        &self,
+        peer_id: PeerId,
+        chain_id: ChainId,
+        reputation_change: i32,
+        reason: &'static str,
+    ) {
+        self.messages_tx
+            .send(ToBackground::ReportPeer {
+                peer_id,
+                chain_id,
+                reputation_change,
+                reason,
+            })
+            .await
+            .unwrap();
+    }
+
     /// Returns an iterator to the list of [`PeerId`]s that we have an established connection
     /// with.
     pub async fn peers_list(&self, chain_id: ChainId) -> impl Iterator<Item = PeerId> {
@@ -675,6 +964,27 @@ impl<TPlat: PlatformRef> NetworkService<TPlat> {
             .unwrap();
         rx.await.unwrap().into_iter()
     }
+
+    /// Returns the list of peers with an open gossip link that are assumed to know the given
+    /// block, according to the best block they have reported.
+    ///
+    /// See [`service::ChainNetwork::peers_with_block`].
+    pub async fn peers_with_block(
+        &self,
+        chain_id: ChainId,
+        block: BlockHashOrNumber,
+    ) -> impl Iterator<Item = PeerId> {
+        let (tx, rx) = oneshot::channel();
+        self.messages_tx
+            .send(ToBackground::PeersWithBlock {
+                chain_id,
+                block,
+                result: tx,
+            })
+            .await
+            .unwrap();
+        rx.await.unwrap().into_iter()
+    }
 }
 
 impl<TPlat: PlatformRef> Drop for NetworkService<TPlat> {
@@ -683,13 +993,70 @@ impl<TPlat: PlatformRef> Drop for NetworkService<TPlat> {
     }
 }
 
+/// Health metrics of a gossip link. See [`NetworkService::gossip_link_info`].
+#[derive(Debug, Clone)]
+pub struct GossipLinkInfo {
+    /// Number of valid block announces received from the peer.
+    pub announces_received: u64,
+    /// Time elapsed between the Unix Epoch and when the last block announce has been received
+    /// from the peer. `None` if no block announce has been received since the gossip link has
+    /// been opened.
+    pub last_announce_from_unix_epoch: Option<Duration>,
+    /// Total size, in bytes, of the gossip notifications received from the peer.
+    pub bytes_received: u64,
+    /// Number of times opening the gossip link has been attempted.
+    pub open_attempts: u32,
+}
+
+/// Quality of a peer. See [`NetworkService::peers_quality`].
+///
+/// Qualities are ordered by number of successful requests first, then by uptime.
+#[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct PeerQuality {
+    /// Number of requests that have been successfully answered by the peer.
+    pub successful_requests: u64,
+    /// Total duration during which a gossip link with the peer has been open.
+    pub uptime: Duration,
+}
+
+/// Schedule of the automatic discovery of peers. See [`NetworkService::discovery_schedule`].
+#[derive(Debug, Clone)]
+pub struct DiscoverySchedule {
+    /// Current delay between two consecutive discoveries. Shortened when the number of gossip
+    /// links of a chain is below the target, and lengthened when the target is reached for all
+    /// chains.
+    pub period: Duration,
+    /// Time elapsed between the Unix Epoch and when the next discovery will start.
+    pub next_discovery_from_unix_epoch: Duration,
+}
+
+/// Number of gossip links that the service tries to maintain for each chain.
+// TODO: arbitrary constant, make configurable
+const GOSSIP_DESIRED_PEERS: usize = 4;
+
+/// Minimum delay between two consecutive discoveries.
+const DISCOVERY_MIN_PERIOD: Duration = Duration::from_secs(5);
+
+/// Maximum delay between two consecutive discoveries.
+const DISCOVERY_MAX_PERIOD: Duration = Duration::from_secs(120);
+
+/// Maximum number of messages destined to the same connection that are sent at once to the task
+/// of this connection.
+const MAX_MESSAGES_TO_CONNECTION_BATCH: usize = 32;
+
+/// Reputation at or below which a peer is banned. See [`NetworkService::report_peer`].
+const REPUTATION_BAN_THRESHOLD: i32 = -150;
+
+/// Duration of the ban of a peer whose reputation has reached [`REPUTATION_BAN_THRESHOLD`].
+const REPUTATION_BAN_DURATION: Duration = Duration::from_secs(120);
+
 /// Event that can happen on the network service.
 #[derive(Debug, Clone)]
 pub enum Event {
     Connected {
         peer_id: PeerId,
         chain_id: ChainId,
-        role: protocol::Role,
+        role: codec::Role,
         best_block_number: u64,
         best_block_hash: [u8; 32],
     },
@@ -701,6 +1068,9 @@ pub enum Event {
         peer_id: PeerId,
         chain_id: ChainId,
         announce: service::EncodedBlockAnnounce,
+        /// Decoded version of the header found in [`Event::BlockAnnounce::announce`], shared
+        /// between all the announces of the same block.
+        header: Result<Arc<AnnouncedHeader>, header::Error>,
     },
     GrandpaNeighborPacket {
         peer_id: PeerId,
@@ -780,9 +1150,9 @@ enum ToBackground {
     StartBlocksRequest {
         target: PeerId, // TODO: takes by value because of future longevity issue
         chain_id: ChainId,
-        config: protocol::BlocksRequestConfig,
+        config: codec::BlocksRequestConfig,
         timeout: Duration,
-        result: oneshot::Sender<Result<Vec<protocol::BlockData>, BlocksRequestError>>,
+        result: oneshot::Sender<Result<Vec<codec::BlockData>, BlocksRequestError>>,
     },
     // TODO: serialize the request before sending over channel
     StartWarpSyncRequest {
@@ -797,7 +1167,7 @@ enum ToBackground {
     StartStorageProofRequest {
         chain_id: ChainId,
         target: PeerId,
-        config: protocol::StorageProofRequestConfig<vec::IntoIter<Vec<u8>>>,
+        config: codec::StorageProofRequestConfig<vec::IntoIter<Vec<u8>>>,
         timeout: Duration,
         result: oneshot::Sender<Result<service::EncodedMerkleProof, StorageProofRequestError>>,
     },
@@ -805,7 +1175,7 @@ enum ToBackground {
     StartCallProofRequest {
         chain_id: ChainId,
         target: PeerId, // TODO: takes by value because of futures longevity issue
-        config: protocol::CallProofRequestConfig<'static, vec::IntoIter<Vec<u8>>>,
+        config: codec::CallProofRequestConfig<'static, vec::IntoIter<Vec<u8>>>,
         timeout: Duration,
         result: oneshot::Sender<Result<service::EncodedMerkleProof, CallProofRequestError>>,
     },
@@ -821,6 +1191,7 @@ enum ToBackground {
     AnnounceTransaction {
         chain_id: ChainId,
         transaction: Vec<u8>,
+        max_peers: usize,
         result: oneshot::Sender<Vec<PeerId>>,
     },
     SendBlockAnnounce {
@@ -843,7 +1214,33 @@ enum ToBackground {
         chain_id: ChainId,
         result: oneshot::Sender<Vec<PeerId>>,
     },
-    StartDiscovery,
+    PeersWithBlock {
+        chain_id: ChainId,
+        block: BlockHashOrNumber,
+        result: oneshot::Sender<Vec<PeerId>>,
+    },
+    GossipLinkInfo {
+        chain_id: ChainId,
+        peer_id: PeerId,
+        result: oneshot::Sender<Option<GossipLinkInfo>>,
+    },
+    PeersQuality {
+        chain_id: ChainId,
+        result: oneshot::Sender<Vec<(PeerId, PeerQuality)>>,
+    },
+    PrioritizePeers {
+        chain_id: ChainId,
+        list: Vec<(PeerId, PeerQuality)>,
+    },
+    DiscoverySchedule {
+        result: oneshot::Sender<DiscoverySchedule>,
+    },
+    ReportPeer {
+        peer_id: PeerId,
+        chain_id: ChainId,
+        reputation_change: i32,
+        reason: &'static str,
+    },
 }
 
 struct BackgroundTask<TPlat: PlatformRef> {
@@ -856,6 +1253,21 @@ struct BackgroundTask<TPlat: PlatformRef> {
     /// Value provided through [`Config::identify_agent_version`].
     identify_agent_version: String,
 
+    /// Value provided through [`Config::address_sanitize_policy`].
+    address_sanitize_policy: multiaddr::SanitizePolicy,
+
+    /// Value provided through [`Config::identity_pinning_policy`].
+    identity_pinning_policy: IdentityPinningPolicy,
+
+    /// Value provided through [`Config::ip_family_policy`].
+    ip_family_policy: IpFamilyPolicy,
+
+    /// Target of the logs emitted by the service.
+    log_target: util::LogTarget,
+
+    /// Target of the logs related to individual connections.
+    connections_log_target: util::LogTarget,
+
     /// Names of the various chains the network service connects to. Used only for logging
     /// purposes.
     // TODO: add a user data to the network state machine
@@ -887,13 +1299,13 @@ struct BackgroundTask<TPlat: PlatformRef> {
 
     active_connections: HashMap<
         service::ConnectionId,
-        async_channel::Sender<service::CoordinatorToConnection>,
+        async_channel::Sender<Vec<service::CoordinatorToConnection>>,
         fnv::FnvBuildHasher,
     >,
 
     blocks_requests: HashMap<
         service::SubstreamId,
-        oneshot::Sender<Result<Vec<protocol::BlockData>, BlocksRequestError>>,
+        oneshot::Sender<Result<Vec<codec::BlockData>, BlocksRequestError>>,
         fnv::FnvBuildHasher,
     >,
 
@@ -916,6 +1328,247 @@ struct BackgroundTask<TPlat: PlatformRef> {
     >,
 
     kademlia_find_node_requests: HashMap<service::SubstreamId, ChainId, fnv::FnvBuildHasher>,
+
+    /// See [`Config::announced_headers_cache`].
+    announced_headers_cache: Arc<AnnouncedHeadersCache>,
+
+    /// For each open gossip link, when the last block announce has been received.
+    gossip_links_last_announce: HashMap<(ChainId, PeerId), TPlat::Instant, fnv::FnvBuildHasher>,
+
+    /// Gossip links that have been closed because of a ban (see [`NetworkService::report_peer`])
+    /// and whose closure must still be reported with an [`Event::Disconnected`].
+    gossip_links_closed_locally: VecDeque<(ChainId, PeerId)>,
+
+    /// Chains whose [`ConfigChain::track_peers_quality`] was `true`.
+    peers_quality_chains: HashSet<ChainId, fnv::FnvBuildHasher>,
+
+    /// Quality of the peers of the chains of [`BackgroundTask::peers_quality_chains`], and
+    /// when their gossip link has been opened if it is currently open.
+    peers_quality:
+        HashMap<(ChainId, PeerId), (PeerQuality, Option<TPlat::Instant>), fnv::FnvBuildHasher>,
+
+    /// For each chain, peers passed to [`NetworkService::prioritize_peers`] that haven't been
+    /// assigned a slot yet, ordered by increasing quality.
+    prioritized_peers: HashMap<ChainId, Vec<PeerId>, fnv::FnvBuildHasher>,
+
+    /// Chain and target of the requests in progress whose outcome updates
+    /// [`BackgroundTask::peers_quality`].
+    requests_targets: HashMap<service::SubstreamId, (ChainId, PeerId), fnv::FnvBuildHasher>,
+
+    /// Connection attempts in progress started according to [`IpFamilyPolicy::HappyEyeballs`],
+    /// indexed by the [`PeerId`] that was dialed. Entries are removed once one of the
+    /// connections has finished its handshake.
+    dial_races: HashMap<PeerId, DialRace<TPlat::Instant>, fnv::FnvBuildHasher>,
+
+    /// Current delay between two consecutive discoveries.
+    discovery_period: Duration,
+
+    /// Moment when the next discovery starts.
+    next_discovery_when: TPlat::Instant,
+
+    /// Future that is ready at [`BackgroundTask::next_discovery_when`].
+    next_discovery: Pin<Box<TPlat::Delay>>,
+}
+
+impl<TPlat: PlatformRef> BackgroundTask<TPlat> {
+    /// Schedules the next discovery to start at the given moment.
+    fn schedule_discovery(&mut self, when: TPlat::Instant) {
+        util::log!(
+            Debug,
+            &self.log_target,
+            "Discovery <= Schedule(period={:?}, next_in={:?})",
+            self.discovery_period,
+            when.clone() - self.platform.now()
+        );
+
+        self.next_discovery = Box::pin(self.platform.sleep_until(when.clone()));
+        self.next_discovery_when = when;
+    }
+
+    /// Must be called when a request has been started, in order to track the quality of its
+    /// target.
+    fn on_request_started(
+        &mut self,
+        substream_id: service::SubstreamId,
+        chain_id: ChainId,
+        target: &PeerId,
+    ) {
+        if self.peers_quality_chains.contains(&chain_id) {
+            self.requests_targets
+                .insert(substream_id, (chain_id, target.clone()));
+        }
+    }
+
+    /// Must be called when a request has finished or has been cancelled.
+    fn on_request_finished(&mut self, substream_id: service::SubstreamId, success: bool) {
+        let Some(key) = self.requests_targets.remove(&substream_id) else {
+            return;
+        };
+
+        if success {
+            self.peers_quality
+                .entry(key)
+                .or_default()
+                .0
+                .successful_requests += 1;
+        }
+    }
+
+    /// Closes the gossip link with the given peer on the given chain, if any, and prevents this
+    /// peer from being assigned a slot on this chain again until the given moment.
+    ///
+    /// An [`Event::Disconnected`] is later generated if the gossip link was open.
+    fn unassign_slot_and_close_gossip(
+        &mut self,
+        chain_id: ChainId,
+        peer_id: &PeerId,
+        banned_until: TPlat::Instant,
+    ) {
+        self.peering_strategy
+            .unassign_slot_and_ban(&chain_id, peer_id, banned_until);
+        self.network.gossip_remove_desired(
+            chain_id,
+            peer_id,
+            service::GossipKind::ConsensusTransactions,
+        );
+
+        // Closing an open gossip link doesn't generate any event from the network state
+        // machine. The closure is reported later, when events can be generated.
+        if self
+            .network
+            .gossip_connected_peers(chain_id, service::GossipKind::ConsensusTransactions)
+            .any(|p| p == peer_id)
+        {
+            self.network
+                .gossip_close(
+                    chain_id,
+                    peer_id,
+                    service::GossipKind::ConsensusTransactions,
+                )
+                .unwrap();
+            self.gossip_links_closed_locally
+                .push_back((chain_id, peer_id.clone()));
+        }
+    }
+
+    /// Must be called when a gossip link has been opened.
+    fn on_gossip_link_opened(&mut self, chain_id: ChainId, peer_id: &PeerId) {
+        if self.peers_quality_chains.contains(&chain_id) {
+            self.peers_quality
+                .entry((chain_id, peer_id.clone()))
+                .or_default()
+                .1 = Some(self.platform.now());
+        }
+    }
+
+    /// Must be called when a gossip link has been closed.
+    fn on_gossip_link_closed(&mut self, chain_id: ChainId, peer_id: &PeerId) {
+        let Some((quality, opened)) = self.peers_quality.get_mut(&(chain_id, peer_id.clone()))
+        else {
+            return;
+        };
+
+        if let Some(opened) = opened.take() {
+            quality.uptime += self.platform.now() - opened;
+        }
+    }
+
+    /// Must be called when a connection that was expected to reach the given peer has finished
+    /// its handshake.
+    ///
+    /// If this connection is part of a dial race, the other connections of the race are shut
+    /// down.
+    fn on_dial_race_handshake_finished(
+        &mut self,
+        expected_peer_id: &PeerId,
+        connection_id: service::ConnectionId,
+    ) {
+        if !self
+            .dial_races
+            .get(expected_peer_id)
+            .is_some_and(|race| race.connections.contains(&connection_id))
+        {
+            return;
+        }
+
+        let race = self.dial_races.remove(expected_peer_id).unwrap();
+        for loser in race.connections {
+            if loser == connection_id || self.network.is_connection_shutting_down(loser) {
+                continue;
+            }
+
+            util::log!(
+                Debug,
+                &self.connections_log_target,
+                "Connections({}) <= CancelDialRaceLoser({:?})",
+                expected_peer_id,
+                loser
+            );
+            self.network.start_shutdown(loser);
+        }
+    }
+
+    /// Must be called when a connection that was expected to reach the given peer has shut down
+    /// before finishing its handshake.
+    fn on_dial_race_connection_failed(
+        &mut self,
+        expected_peer_id: &PeerId,
+        connection_id: service::ConnectionId,
+    ) {
+        let Some(race) = self.dial_races.get_mut(expected_peer_id) else {
+            return;
+        };
+
+        race.connections.retain(|c| *c != connection_id);
+
+        // If the fallback address hasn't been dialed yet, the race is kept. Since there is no
+        // connection to the peer anymore, the peer is normally immediately returned by
+        // `unconnected_desired`, at which point the fallback address is dialed.
+        if race.connections.is_empty() && race.fallback_when.is_none() {
+            self.dial_races.remove(expected_peer_id);
+        }
+    }
+}
+
+/// See [`BackgroundTask::dial_races`].
+struct DialRace<TInstant> {
+    /// Family of the address that has been dialed first.
+    first_family: IpFamily,
+
+    /// Connections of this race that haven't shut down yet.
+    connections: Vec<service::ConnectionId>,
+
+    /// When to dial an address of the other family. `None` if it has already been dialed.
+    fallback_when: Option<TInstant>,
+}
+
+/// IP family of an address. See [`Config::ip_family_policy`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum IpFamily {
+    V4,
+    V6,
+}
+
+impl IpFamily {
+    /// Returns the other IP family.
+    fn other(&self) -> IpFamily {
+        match self {
+            IpFamily::V4 => IpFamily::V6,
+            IpFamily::V6 => IpFamily::V4,
+        }
+    }
+
+    /// Returns the IP family of the given encoded multiaddress, or `None` if it belongs to
+    /// neither family or is invalid.
+    fn of_address(address: &[u8]) -> Option<IpFamily> {
+        let address = Multiaddr::try_from(address.to_vec()).ok()?;
+        let family = match address.iter().next()? {
+            multiaddr::ProtocolRef::Ip4(_) | multiaddr::ProtocolRef::Dns4(_) => IpFamily::V4,
+            multiaddr::ProtocolRef::Ip6(_) | multiaddr::ProtocolRef::Dns6(_) => IpFamily::V6,
+            _ => return None,
+        };
+        Some(family)
+    }
 }
 
 async fn background_task<TPlat: PlatformRef>(mut task: BackgroundTask<TPlat>) {
@@ -927,25 +1580,47 @@ async fn background_task<TPlat: PlatformRef>(mut task: BackgroundTask<TPlat>) {
         // TODO: doc
         for chain_id in task.log_chain_names.keys() {
             loop {
-                // TODO: 4 is an arbitrary constant, make configurable
                 if task
                     .network
                     .gossip_desired_num(*chain_id, service::GossipKind::ConsensusTransactions)
-                    >= 4
+                    >= GOSSIP_DESIRED_PEERS
                 {
                     break;
                 }
 
-                let peer_id = match task.peering_strategy.assign_slot(chain_id, &task.platform.now()) {
+                // Peers passed to `prioritize_peers` are assigned a slot first.
+                let mut prioritized_peer = None;
+                if let Some(prioritized) = task.prioritized_peers.get_mut(chain_id) {
+                    while let Some(peer_id) = prioritized.pop() {
+                        if task.peering_strategy.assign_slot_to_peer(
+                            chain_id,
+                            &peer_id,
+                            &task.platform.now(),
+                        ) {
+                            prioritized_peer = Some(peer_id);
+                            break;
+                        }
+                    }
+                    if prioritized.is_empty() {
+                        task.prioritized_peers.remove(chain_id);
+                    }
+                }
+
+                let peer_id = if let Some(peer_id) = prioritized_peer {
+                    peer_id
+                } else {
+                    match task.peering_strategy.assign_slot(chain_id, &task.platform.now()) {
                     basic_peering_strategy::AssignSlotOutcome::Assigned(peer_id) => {
                         peer_id.clone()
                     }
                     basic_peering_strategy::AssignSlotOutcome::AllPeersBanned { .. }  // TODO: handle `AllPeersBanned` by waking up when a ban expires
                     | basic_peering_strategy::AssignSlotOutcome::NoPeer => break,
+                    }
                 };
 
-                log::debug!(
-                    target: "connections",
+                util::log!(
+                    Debug,
+                    &task.connections_log_target,
                     "OutSlots({}) ∋ {}",
                     &task.log_chain_names[chain_id],
                     peer_id
@@ -979,8 +1654,9 @@ async fn background_task<TPlat: PlatformRef>(mut task: BackgroundTask<TPlat>) {
                 )
                 .unwrap();
 
-            log::debug!(
-                target: "network",
+            util::log!(
+                Debug,
+                &task.log_target,
                 "Gossip({}, {}) <= Open",
                 peer_id,
                 &task.log_chain_names[&chain_id],
@@ -990,35 +1666,63 @@ async fn background_task<TPlat: PlatformRef>(mut task: BackgroundTask<TPlat>) {
         enum WhatHappened {
             Message(ToBackground),
             NetworkEvent(service::Event),
-            StartConnect(PeerId),
+            StartConnect {
+                peer_id: PeerId,
+                /// `true` if the connection must be started with an address of the other IP
+                /// family of a dial race. See [`BackgroundTask::dial_races`].
+                is_fallback: bool,
+            },
             MessageToConnection {
                 connection_id: service::ConnectionId,
-                message: service::CoordinatorToConnection,
+                messages: Vec<service::CoordinatorToConnection>,
             },
             EventSendersReady,
+            RequestCancelled(service::SubstreamId),
+            StartDiscovery,
+            GossipClosedLocally(ChainId, PeerId),
+            BanExpired,
         }
 
         let what_happened = {
+            let can_generate_event = matches!(task.event_senders, either::Left(_));
+
+            // Bans are lifted by the network state machine when pulling an event. Wake up when
+            // the earliest ban expires so that the peer can be connected to again.
+            let next_ban_expiration = if can_generate_event {
+                task.network.next_ban_expiration().cloned()
+            } else {
+                None
+            };
+
             let message_received =
                 async { WhatHappened::Message(task.messages_rx.next().await.unwrap()) };
-            let can_generate_event = matches!(task.event_senders, either::Left(_));
             let service_event = async {
                 // TODO: move down, but causes borrowck errors
                 let start_connect = task.network.unconnected_desired().next().cloned();
-                if let Some(event) = if can_generate_event {
-                    task.network.next_event()
+                if let Some((chain_id, peer_id)) = if can_generate_event {
+                    task.gossip_links_closed_locally.pop_front()
+                } else {
+                    None
+                } {
+                    WhatHappened::GossipClosedLocally(chain_id, peer_id)
+                } else if let Some(event) = if can_generate_event {
+                    task.network.next_event(&task.platform.now())
                 } else {
                     None
                 } {
                     WhatHappened::NetworkEvent(event)
                 } else if let Some(start_connect) = start_connect {
-                    WhatHappened::StartConnect(start_connect)
-                } else if let Some((connection_id, message)) =
-                    task.network.pull_message_to_connection()
+                    WhatHappened::StartConnect {
+                        peer_id: start_connect,
+                        is_fallback: false,
+                    }
+                } else if let Some((connection_id, messages)) = task
+                    .network
+                    .pull_messages_to_connection(MAX_MESSAGES_TO_CONNECTION_BATCH)
                 {
                     WhatHappened::MessageToConnection {
                         connection_id,
-                        message,
+                        messages,
                     }
                 } else {
                     future::pending().await
@@ -1034,9 +1738,73 @@ async fn background_task<TPlat: PlatformRef>(mut task: BackgroundTask<TPlat>) {
                 }
             };
 
+            // Requests whose result is no longer awaited by anyone, for example because the
+            // future that was waiting for it has been dropped, are cancelled.
+            let request_cancelled = future::poll_fn(|cx| {
+                let cancelled = task
+                    .blocks_requests
+                    .iter_mut()
+                    .find_map(|(id, s)| s.poll_canceled(cx).is_ready().then_some(*id))
+                    .or_else(|| {
+                        task.grandpa_warp_sync_requests
+                            .iter_mut()
+                            .find_map(|(id, s)| s.poll_canceled(cx).is_ready().then_some(*id))
+                    })
+                    .or_else(|| {
+                        task.storage_proof_requests
+                            .iter_mut()
+                            .find_map(|(id, s)| s.poll_canceled(cx).is_ready().then_some(*id))
+                    })
+                    .or_else(|| {
+                        task.call_proof_requests
+                            .iter_mut()
+                            .find_map(|(id, s)| s.poll_canceled(cx).is_ready().then_some(*id))
+                    });
+                match cancelled {
+                    Some(substream_id) => Poll::Ready(WhatHappened::RequestCancelled(substream_id)),
+                    None => Poll::Pending,
+                }
+            });
+
+            let start_discovery = async {
+                (&mut task.next_discovery).await;
+                WhatHappened::StartDiscovery
+            };
+
+            let next_fallback_dial = task
+                .dial_races
+                .iter()
+                .filter_map(|(peer_id, race)| Some((race.fallback_when.as_ref()?, peer_id)))
+                .min_by_key(|(when, _)| *when)
+                .map(|(when, peer_id)| (when.clone(), peer_id.clone()));
+            let start_fallback_dial = async {
+                if let Some((when, peer_id)) = next_fallback_dial {
+                    task.platform.sleep_until(when).await;
+                    WhatHappened::StartConnect {
+                        peer_id,
+                        is_fallback: true,
+                    }
+                } else {
+                    future::pending().await
+                }
+            };
+
+            let ban_expired = async {
+                if let Some(when) = next_ban_expiration {
+                    task.platform.sleep_until(when).await;
+                    WhatHappened::BanExpired
+                } else {
+                    future::pending().await
+                }
+            };
+
             message_received
                 .or(service_event)
                 .or(finished_sending_event)
+                .or(request_cancelled)
+                .or(start_discovery)
+                .or(start_fallback_dial)
+                .or(ban_expired)
                 .await
         };
 
@@ -1045,6 +1813,27 @@ async fn background_task<TPlat: PlatformRef>(mut task: BackgroundTask<TPlat>) {
                 // Nothing to do. Just loop again, as we can now generate events.
                 continue;
             }
+            WhatHappened::RequestCancelled(substream_id) => {
+                let _was_in = task.blocks_requests.remove(&substream_id).is_some()
+                    || task
+                        .grandpa_warp_sync_requests
+                        .remove(&substream_id)
+                        .is_some()
+                    || task.storage_proof_requests.remove(&substream_id).is_some()
+                    || task.call_proof_requests.remove(&substream_id).is_some();
+                debug_assert!(_was_in);
+                task.on_request_finished(substream_id, false);
+
+                util::log!(
+                    Debug,
+                    &task.log_target,
+                    "RequestCancelled(substream={:?})",
+                    substream_id
+                );
+
+                task.network.cancel_request(substream_id);
+                continue;
+            }
             WhatHappened::Message(ToBackground::ConnectionMessage {
                 connection_id,
                 message,
@@ -1066,33 +1855,41 @@ async fn background_task<TPlat: PlatformRef>(mut task: BackgroundTask<TPlat>) {
                 {
                     Ok(substream_id) => {
                         match &config.start {
-                            protocol::BlocksRequestConfigStart::Hash(hash) => {
-                                log::debug!(
-                                    target: "network",
+                            codec::BlocksRequestConfigStart::Hash(hash) => {
+                                util::log!(Debug,
+                                    &task.log_target,
                                     "Connections({}) <= BlocksRequest(chain={}, start={}, num={}, descending={:?}, header={:?}, body={:?}, justifications={:?})",
                                     target, task.log_chain_names[&chain_id], HashDisplay(hash),
                                     config.desired_count.get(),
-                                    matches!(config.direction, protocol::BlocksRequestDirection::Descending),
+                                    matches!(config.direction, codec::BlocksRequestDirection::Descending),
                                     config.fields.header, config.fields.body, config.fields.justifications
                                 );
                             }
-                            protocol::BlocksRequestConfigStart::Number(number) => {
-                                log::debug!(
-                                    target: "network",
+                            codec::BlocksRequestConfigStart::Number(number) => {
+                                util::log!(Debug,
+                                    &task.log_target,
                                     "Connections({}) <= BlocksRequest(chain={}, start=#{}, num={}, descending={:?}, header={:?}, body={:?}, justifications={:?})",
                                     target, task.log_chain_names[&chain_id], number,
                                     config.desired_count.get(),
-                                    matches!(config.direction, protocol::BlocksRequestDirection::Descending),
+                                    matches!(config.direction, codec::BlocksRequestDirection::Descending),
                                     config.fields.header, config.fields.body, config.fields.justifications
                                 );
                             }
                         }
 
                         task.blocks_requests.insert(substream_id, result);
+                        task.on_request_started(substream_id, chain_id, &target);
                     }
                     Err(service::StartRequestError::NoConnection) => {
                         let _ = result.send(Err(BlocksRequestError::NoConnection));
                     }
+                    Err(service::StartRequestError::ProtocolNotAvailable) => {
+                        let _ = result.send(Err(BlocksRequestError::Request(
+                            service::BlocksRequestError::Request(service::RequestError::Substream(
+                                connection::established::RequestError::ProtocolNotAvailable,
+                            )),
+                        )));
+                    }
                 }
 
                 continue;
@@ -1109,16 +1906,30 @@ async fn background_task<TPlat: PlatformRef>(mut task: BackgroundTask<TPlat>) {
                     .start_grandpa_warp_sync_request(&target, chain_id, begin_hash, timeout)
                 {
                     Ok(substream_id) => {
-                        log::debug!(
-                            target: "network", "Connections({}) <= WarpSyncRequest(chain={}, start={})",
-                            target, task.log_chain_names[&chain_id], HashDisplay(&begin_hash)
+                        util::log!(
+                            Debug,
+                            &task.log_target,
+                            "Connections({}) <= WarpSyncRequest(chain={}, start={})",
+                            target,
+                            task.log_chain_names[&chain_id],
+                            HashDisplay(&begin_hash)
                         );
 
                         task.grandpa_warp_sync_requests.insert(substream_id, result);
+                        task.on_request_started(substream_id, chain_id, &target);
                     }
                     Err(service::StartRequestError::NoConnection) => {
                         let _ = result.send(Err(WarpSyncRequestError::NoConnection));
                     }
+                    Err(service::StartRequestError::ProtocolNotAvailable) => {
+                        let _ = result.send(Err(WarpSyncRequestError::Request(
+                            service::GrandpaWarpSyncRequestError::Request(
+                                service::RequestError::Substream(
+                                    connection::established::RequestError::ProtocolNotAvailable,
+                                ),
+                            ),
+                        )));
+                    }
                 }
 
                 continue;
@@ -1137,8 +1948,9 @@ async fn background_task<TPlat: PlatformRef>(mut task: BackgroundTask<TPlat>) {
                     timeout,
                 ) {
                     Ok(substream_id) => {
-                        log::debug!(
-                            target: "network",
+                        util::log!(
+                            Debug,
+                            &task.log_target,
                             "Connections({}) <= StorageProofRequest(chain={}, block={})",
                             target,
                             task.log_chain_names[&chain_id],
@@ -1146,10 +1958,20 @@ async fn background_task<TPlat: PlatformRef>(mut task: BackgroundTask<TPlat>) {
                         );
 
                         task.storage_proof_requests.insert(substream_id, result);
+                        task.on_request_started(substream_id, chain_id, &target);
                     }
                     Err(service::StartRequestMaybeTooLargeError::NoConnection) => {
                         let _ = result.send(Err(StorageProofRequestError::NoConnection));
                     }
+                    Err(service::StartRequestMaybeTooLargeError::ProtocolNotAvailable) => {
+                        let _ = result.send(Err(StorageProofRequestError::Request(
+                            service::StorageProofRequestError::Request(
+                                service::RequestError::Substream(
+                                    connection::established::RequestError::ProtocolNotAvailable,
+                                ),
+                            ),
+                        )));
+                    }
                     Err(service::StartRequestMaybeTooLargeError::RequestTooLarge) => {
                         let _ = result.send(Err(StorageProofRequestError::RequestTooLarge));
                     }
@@ -1171,8 +1993,9 @@ async fn background_task<TPlat: PlatformRef>(mut task: BackgroundTask<TPlat>) {
                     timeout,
                 ) {
                     Ok(substream_id) => {
-                        log::debug!(
-                            target: "network",
+                        util::log!(
+                            Debug,
+                            &task.log_target,
                             "Connections({}) <= CallProofRequest({}, {}, {})",
                             target,
                             task.log_chain_names[&chain_id],
@@ -1181,10 +2004,20 @@ async fn background_task<TPlat: PlatformRef>(mut task: BackgroundTask<TPlat>) {
                         );
 
                         task.call_proof_requests.insert(substream_id, result);
+                        task.on_request_started(substream_id, chain_id, &target);
                     }
                     Err(service::StartRequestMaybeTooLargeError::NoConnection) => {
                         let _ = result.send(Err(CallProofRequestError::NoConnection));
                     }
+                    Err(service::StartRequestMaybeTooLargeError::ProtocolNotAvailable) => {
+                        let _ = result.send(Err(CallProofRequestError::Request(
+                            service::CallProofRequestError::Request(
+                                service::RequestError::Substream(
+                                    connection::established::RequestError::ProtocolNotAvailable,
+                                ),
+                            ),
+                        )));
+                    }
                     Err(service::StartRequestMaybeTooLargeError::RequestTooLarge) => {
                         let _ = result.send(Err(CallProofRequestError::RequestTooLarge));
                     }
@@ -1205,8 +2038,9 @@ async fn background_task<TPlat: PlatformRef>(mut task: BackgroundTask<TPlat>) {
                 chain_id,
                 grandpa_state,
             }) => {
-                log::debug!(
-                    target: "network",
+                util::log!(
+                    Debug,
+                    &task.log_target,
                     "Chain({}) <= SetLocalGrandpaState(set_id: {}, commit_finalized_height: {})",
                     task.log_chain_names[&chain_id],
                     grandpa_state.set_id,
@@ -1222,6 +2056,7 @@ async fn background_task<TPlat: PlatformRef>(mut task: BackgroundTask<TPlat>) {
             WhatHappened::Message(ToBackground::AnnounceTransaction {
                 chain_id,
                 transaction,
+                max_peers,
                 result,
             }) => {
                 let mut sent_peers = Vec::with_capacity(16); // TODO: capacity?
@@ -1229,18 +2064,28 @@ async fn background_task<TPlat: PlatformRef>(mut task: BackgroundTask<TPlat>) {
                 // TODO: keep track of which peer knows about which transaction, and don't send it again
 
                 // TODO: collecting in a Vec :-/
-                for peer in task
-                    .network
-                    .gossip_connected_peers(chain_id, service::GossipKind::ConsensusTransactions)
-                    .cloned()
-                    .collect::<Vec<_>>()
-                {
+                let peers = transaction_announce_order(
+                    task.network
+                        .gossip_connected_peers(
+                            chain_id,
+                            service::GossipKind::ConsensusTransactions,
+                        )
+                        .cloned()
+                        .collect::<Vec<_>>(),
+                    max_peers,
+                    &mut task.randomness,
+                );
+
+                for peer in peers {
                     if task
                         .network
                         .gossip_send_transaction(&peer, chain_id, &transaction)
                         .is_ok()
                     {
                         sent_peers.push(peer);
+                        if sent_peers.len() >= max_peers {
+                            break;
+                        }
                     };
                 }
 
@@ -1311,7 +2156,155 @@ async fn background_task<TPlat: PlatformRef>(mut task: BackgroundTask<TPlat>) {
                 );
                 continue;
             }
-            WhatHappened::Message(ToBackground::StartDiscovery) => {
+            WhatHappened::Message(ToBackground::PeersWithBlock {
+                chain_id,
+                block,
+                result,
+            }) => {
+                let _ = result.send(
+                    task.network
+                        .peers_with_block(chain_id, block)
+                        .cloned()
+                        .collect(),
+                );
+                continue;
+            }
+            WhatHappened::Message(ToBackground::GossipLinkInfo {
+                chain_id,
+                peer_id,
+                result,
+            }) => {
+                let info = task
+                    .network
+                    .gossip_link_info(
+                        chain_id,
+                        &peer_id,
+                        service::GossipKind::ConsensusTransactions,
+                    )
+                    .map(|info| GossipLinkInfo {
+                        announces_received: info.announces_received,
+                        last_announce_from_unix_epoch: task
+                            .gossip_links_last_announce
+                            .get(&(chain_id, peer_id.clone()))
+                            .map(|when| task.platform.instant_to_unix_epoch(when.clone())),
+                        bytes_received: info.bytes_received,
+                        open_attempts: info.open_attempts,
+                    });
+                let _ = result.send(info);
+                continue;
+            }
+            WhatHappened::Message(ToBackground::PeersQuality { chain_id, result }) => {
+                let now = task.platform.now();
+                let _ = result.send(
+                    task.peers_quality
+                        .iter()
+                        .filter(|((c, _), _)| *c == chain_id)
+                        .map(|((_, peer_id), (quality, opened))| {
+                            let mut quality = quality.clone();
+                            if let Some(opened) = opened {
+                                quality.uptime += now.clone() - opened.clone();
+                            }
+                            (peer_id.clone(), quality)
+                        })
+                        .collect(),
+                );
+                continue;
+            }
+            WhatHappened::Message(ToBackground::PrioritizePeers { chain_id, list }) => {
+                if !task.peers_quality_chains.contains(&chain_id) {
+                    continue;
+                }
+
+                let mut prioritized = Vec::with_capacity(list.len());
+                for (peer_id, quality) in list {
+                    let (tracked, _) = task
+                        .peers_quality
+                        .entry((chain_id, peer_id.clone()))
+                        .or_default();
+                    tracked.successful_requests += quality.successful_requests;
+                    tracked.uptime += quality.uptime;
+                    prioritized.push((tracked.clone(), peer_id));
+                }
+
+                // Sorted by increasing quality, as peers are popped from the end.
+                prioritized.sort();
+                task.prioritized_peers
+                    .insert(chain_id, prioritized.into_iter().map(|(_, p)| p).collect());
+                continue;
+            }
+            WhatHappened::Message(ToBackground::DiscoverySchedule { result }) => {
+                let _ = result.send(DiscoverySchedule {
+                    period: task.discovery_period,
+                    next_discovery_from_unix_epoch: task
+                        .platform
+                        .instant_to_unix_epoch(task.next_discovery_when.clone()),
+                });
+                continue;
+            }
+            WhatHappened::Message(ToBackground::ReportPeer {
+                peer_id,
+                chain_id,
+                reputation_change,
+                reason,
+            }) => {
+                let now = task.platform.now();
+                let was_banned = task.network.peer_banned_until(&peer_id).is_some();
+                task.network.report_peer(&now, &peer_id, reputation_change);
+
+                util::log!(
+                    Debug,
+                    &task.log_target,
+                    "Reputation({}, {}) <= Report(change={}, reason={}, reputation={})",
+                    peer_id,
+                    &task.log_chain_names[&chain_id],
+                    reputation_change,
+                    reason,
+                    task.network.peer_reputation(&peer_id),
+                );
+
+                if was_banned {
+                    continue;
+                }
+                let Some(banned_until) = task.network.peer_banned_until(&peer_id).cloned() else {
+                    continue;
+                };
+
+                util::log!(
+                    Debug,
+                    &task.log_target,
+                    "Reputation({}) => Banned(duration={:?}, reason={})",
+                    peer_id,
+                    banned_until.clone() - now,
+                    reason,
+                );
+
+                // The ban applies to all chains.
+                let chain_ids = task.log_chain_names.keys().copied().collect::<Vec<_>>();
+                for chain_id in chain_ids {
+                    task.unassign_slot_and_close_gossip(chain_id, &peer_id, banned_until.clone());
+                }
+
+                continue;
+            }
+            WhatHappened::BanExpired => {
+                // Nothing to do. The ban is lifted when the next event is pulled from the
+                // network state machine.
+                continue;
+            }
+            WhatHappened::GossipClosedLocally(chain_id, peer_id) => {
+                util::log!(
+                    Debug,
+                    &task.log_target,
+                    "Connection({}, {}) => GossipDisconnected",
+                    peer_id,
+                    &task.log_chain_names[&chain_id],
+                );
+                task.gossip_links_last_announce
+                    .remove(&(chain_id, peer_id.clone()));
+                task.on_gossip_link_closed(chain_id, &peer_id);
+                Event::Disconnected { peer_id, chain_id }
+            }
+            WhatHappened::StartDiscovery => {
                 for chain_id in task.log_chain_names.keys() {
                     let random_peer_id = {
                         let mut pub_key = [0; 32];
@@ -1341,6 +2334,11 @@ async fn background_task<TPlat: PlatformRef>(mut task: BackgroundTask<TPlat>) {
                         ) {
                             Ok(s) => s,
                             Err(service::StartRequestError::NoConnection) => unreachable!(),
+                            Err(service::StartRequestError::ProtocolNotAvailable) => {
+                                // The target has recently refused the Kademlia protocol.
+                                // Discovery is skipped for this chain until next time.
+                                continue;
+                            }
                         };
 
                         let _prev_value = task
@@ -1352,51 +2350,157 @@ async fn background_task<TPlat: PlatformRef>(mut task: BackgroundTask<TPlat>) {
                     }
                 }
 
+                // Discover more often as long as a chain has less gossip links than desired,
+                // and less often once all chains have enough of them.
+                let below_target = task.log_chain_names.keys().any(|chain_id| {
+                    task.network
+                        .gossip_desired_status(
+                            *chain_id,
+                            service::GossipKind::ConsensusTransactions,
+                        )
+                        .open
+                        < GOSSIP_DESIRED_PEERS
+                });
+                task.discovery_period = if below_target {
+                    cmp::max(task.discovery_period / 2, DISCOVERY_MIN_PERIOD)
+                } else {
+                    cmp::min(task.discovery_period * 2, DISCOVERY_MAX_PERIOD)
+                };
+                let when = task.platform.now() + task.discovery_period;
+                task.schedule_discovery(when);
                 continue;
             }
             WhatHappened::NetworkEvent(service::Event::HandshakeFinished {
                 peer_id,
                 expected_peer_id,
                 id,
+                ..
             }) => {
+                if let Some(expected_peer_id) = &expected_peer_id {
+                    task.on_dial_race_handshake_finished(expected_peer_id, id);
+                }
+
                 let remote_addr =
                     Multiaddr::try_from(task.network.connection_remote_addr(id).to_owned())
                         .unwrap(); // TODO: review this unwrap
                 if let Some(expected_peer_id) = expected_peer_id.as_ref().filter(|p| **p != peer_id)
                 {
-                    log::debug!(target: "network", "Connections({}, {}) => HandshakePeerIdMismatch(actual={})", expected_peer_id, remote_addr, peer_id);
+                    util::log!(
+                        Debug,
+                        &task.log_target,
+                        "Connections({}, {}) => HandshakePeerIdMismatch(actual={})",
+                        expected_peer_id,
+                        remote_addr,
+                        peer_id
+                    );
 
                     task.peering_strategy
                         .remove_address(expected_peer_id, remote_addr.as_ref());
                     task.peering_strategy
                         .insert_connected_address(&peer_id, remote_addr.clone().into_vec());
                 } else {
-                    log::debug!(target: "network", "Connections({}, {}) => HandshakeFinished", peer_id, remote_addr);
+                    util::log!(
+                        Debug,
+                        &task.log_target,
+                        "Connections({}, {}) => HandshakeFinished",
+                        peer_id,
+                        remote_addr
+                    );
+                }
+
+                if task.identity_pinning_policy != IdentityPinningPolicy::Disabled {
+                    if let basic_peering_strategy::PinAddressIdentityOutcome::Changed { pinned } =
+                        task.peering_strategy
+                            .pin_address_identity(remote_addr.as_ref(), &peer_id)
+                    {
+                        util::log!(
+                            Warn,
+                            &task.log_target,
+                            "Connections({}, {}) => IdentityChanged(pinned={})",
+                            peer_id,
+                            remote_addr,
+                            pinned
+                        );
+
+                        if task.identity_pinning_policy == IdentityPinningPolicy::Quarantine {
+                            task.peering_strategy
+                                .quarantine_address(remote_addr.as_ref());
+                        }
+                    }
+                }
+                continue;
+            }
+            WhatHappened::NetworkEvent(service::Event::SupernumeraryConnection {
+                peer_id,
+                expected_peer_id,
+                id,
+                ..
+            }) => {
+                if let Some(expected_peer_id) = &expected_peer_id {
+                    task.on_dial_race_handshake_finished(expected_peer_id, id);
+                }
+
+                let remote_addr =
+                    Multiaddr::try_from(task.network.connection_remote_addr(id).to_owned())
+                        .unwrap(); // TODO: review this unwrap
+                if let Some(expected_peer_id) = expected_peer_id.as_ref().filter(|p| **p != peer_id)
+                {
+                    task.peering_strategy
+                        .remove_address(expected_peer_id, remote_addr.as_ref());
+                    task.peering_strategy
+                        .insert_connected_address(&peer_id, remote_addr.clone().into_vec());
                 }
+                util::log!(
+                    Debug,
+                    &task.log_target,
+                    "Connections({}, {}) => SupernumeraryConnection",
+                    peer_id,
+                    remote_addr
+                );
                 continue;
             }
             WhatHappened::NetworkEvent(service::Event::PreHandshakeDisconnected {
+                id,
                 address,
                 expected_peer_id,
+                reason,
                 ..
             }) => {
                 if let Some(expected_peer_id) = expected_peer_id {
+                    task.on_dial_race_connection_failed(&expected_peer_id, id);
                     task.peering_strategy
                         .disconnect_addr(&expected_peer_id, &address)
                         .unwrap();
                     let address = Multiaddr::try_from(address).unwrap();
-                    log::debug!(target: "network", "Connections({}, {}) => Shutdown(handshake_finished=false)", expected_peer_id, address);
+                    util::log!(
+                        Debug,
+                        &task.log_target,
+                        "Connections({}, {}) => Shutdown(handshake_finished=false, reason={})",
+                        expected_peer_id,
+                        address,
+                        reason
+                    );
                 }
                 continue;
             }
             WhatHappened::NetworkEvent(service::Event::Disconnected {
-                address, peer_id, ..
+                address,
+                peer_id,
+                reason,
+                ..
             }) => {
                 task.peering_strategy
                     .disconnect_addr(&peer_id, &address)
                     .unwrap();
                 let address = Multiaddr::try_from(address).unwrap();
-                log::debug!(target: "network", "Connections({}, {}) => Shutdown(handshake_finished=true)", peer_id, address);
+                util::log!(
+                    Debug,
+                    &task.log_target,
+                    "Connections({}, {}) => Shutdown(handshake_finished=true, reason={})",
+                    peer_id,
+                    address,
+                    reason
+                );
                 continue;
             }
             WhatHappened::NetworkEvent(service::Event::BlockAnnounce {
@@ -1404,18 +2508,34 @@ async fn background_task<TPlat: PlatformRef>(mut task: BackgroundTask<TPlat>) {
                 peer_id,
                 announce,
             }) => {
-                log::debug!(
-                    target: "network",
+                let decoded = announce.decode();
+                let header = task
+                    .announced_headers_cache
+                    .get_or_decode(
+                        decoded.scale_encoded_header,
+                        task.network.block_number_bytes(chain_id),
+                    )
+                    .await;
+                util::log!(
+                    Debug,
+                    &task.log_target,
                     "Connection({}, {}) => BlockAnnounce(best_hash={}, is_best={})",
                     peer_id,
                     &task.log_chain_names[&chain_id],
-                    HashDisplay(&header::hash_from_scale_encoded_header(announce.decode().scale_encoded_header)),
-                    announce.decode().is_best
+                    HashDisplay(&match &header {
+                        Ok(header) => header.hash,
+                        Err(_) =>
+                            header::hash_from_scale_encoded_header(decoded.scale_encoded_header),
+                    }),
+                    decoded.is_best
                 );
+                task.gossip_links_last_announce
+                    .insert((chain_id, peer_id.clone()), task.platform.now());
                 Event::BlockAnnounce {
                     chain_id,
                     peer_id,
                     announce,
+                    header,
                 }
             }
             WhatHappened::NetworkEvent(service::Event::GossipConnected {
@@ -1425,15 +2545,18 @@ async fn background_task<TPlat: PlatformRef>(mut task: BackgroundTask<TPlat>) {
                 best_number,
                 best_hash,
                 kind: service::GossipKind::ConsensusTransactions,
+                ..
             }) => {
-                log::debug!(
-                    target: "network",
+                util::log!(
+                    Debug,
+                    &task.log_target,
                     "Gossip({}, {}) => Opened(best_height={}, best_hash={})",
                     peer_id,
                     &task.log_chain_names[&chain_id],
                     best_number,
                     HashDisplay(&best_hash)
                 );
+                task.on_gossip_link_opened(chain_id, &peer_id);
                 Event::Connected {
                     peer_id,
                     chain_id,
@@ -1448,14 +2571,17 @@ async fn background_task<TPlat: PlatformRef>(mut task: BackgroundTask<TPlat>) {
                 error,
                 kind: service::GossipKind::ConsensusTransactions,
             }) => {
-                log::debug!(
-                    target: "network",
+                util::log!(
+                    Debug,
+                    &task.log_target,
                     "Gossip({}, {}) => OpenFailed(error={:?})",
                     &task.log_chain_names[&chain_id],
-                    peer_id, error,
+                    peer_id,
+                    error,
                 );
-                log::debug!(
-                    target: "connections",
+                util::log!(
+                    Debug,
+                    &task.connections_log_target,
                     "{}Slots ∌ {}", // TODO:
                     &task.log_chain_names[&chain_id],
                     peer_id
@@ -1467,6 +2593,8 @@ async fn background_task<TPlat: PlatformRef>(mut task: BackgroundTask<TPlat>) {
                     &peer_id,
                     service::GossipKind::ConsensusTransactions,
                 );
+                task.gossip_links_last_announce
+                    .remove(&(chain_id, peer_id.clone()));
                 if let service::GossipConnectError::GenesisMismatch { .. } = error {
                     task.peering_strategy
                         .unassign_slot_and_remove_chain_peer(&chain_id, &peer_id);
@@ -1484,14 +2612,16 @@ async fn background_task<TPlat: PlatformRef>(mut task: BackgroundTask<TPlat>) {
                 chain_id,
                 kind: service::GossipKind::ConsensusTransactions,
             }) => {
-                log::debug!(
-                    target: "network",
+                util::log!(
+                    Debug,
+                    &task.log_target,
                     "Connection({}, {}) => GossipDisconnected",
                     peer_id,
                     &task.log_chain_names[&chain_id],
                 );
-                log::debug!(
-                    target: "connections",
+                util::log!(
+                    Debug,
+                    &task.connections_log_target,
                     "{}Slots ∌ {}", // TODO:
                     &task.log_chain_names[&chain_id],
                     peer_id
@@ -1508,12 +2638,33 @@ async fn background_task<TPlat: PlatformRef>(mut task: BackgroundTask<TPlat>) {
                     &peer_id,
                     service::GossipKind::ConsensusTransactions,
                 );
+                task.gossip_links_last_announce
+                    .remove(&(chain_id, peer_id.clone()));
+                task.on_gossip_link_closed(chain_id, &peer_id);
+
+                // If most of the gossip links of this chain are gone, for example because of a
+                // network issue, discover new peers soon rather than waiting for the next
+                // scheduled discovery.
+                if task
+                    .network
+                    .gossip_desired_status(chain_id, service::GossipKind::ConsensusTransactions)
+                    .open
+                    < GOSSIP_DESIRED_PEERS / 2
+                {
+                    let when = task.platform.now() + DISCOVERY_MIN_PERIOD;
+                    if task.next_discovery_when > when {
+                        task.discovery_period = DISCOVERY_MIN_PERIOD;
+                        task.schedule_discovery(when);
+                    }
+                }
+
                 Event::Disconnected { peer_id, chain_id }
             }
             WhatHappened::NetworkEvent(service::Event::RequestResult {
                 substream_id,
                 response: service::RequestResult::Blocks(response),
             }) => {
+                task.on_request_finished(substream_id, response.is_ok());
                 let _ = task
                     .blocks_requests
                     .remove(&substream_id)
@@ -1525,6 +2676,7 @@ async fn background_task<TPlat: PlatformRef>(mut task: BackgroundTask<TPlat>) {
                 substream_id,
                 response: service::RequestResult::GrandpaWarpSync(response),
             }) => {
+                task.on_request_finished(substream_id, response.is_ok());
                 let _ = task
                     .grandpa_warp_sync_requests
                     .remove(&substream_id)
@@ -1536,6 +2688,7 @@ async fn background_task<TPlat: PlatformRef>(mut task: BackgroundTask<TPlat>) {
                 substream_id,
                 response: service::RequestResult::StorageProof(response),
             }) => {
+                task.on_request_finished(substream_id, response.is_ok());
                 let _ = task
                     .storage_proof_requests
                     .remove(&substream_id)
@@ -1547,6 +2700,7 @@ async fn background_task<TPlat: PlatformRef>(mut task: BackgroundTask<TPlat>) {
                 substream_id,
                 response: service::RequestResult::CallProof(response),
             }) => {
+                task.on_request_finished(substream_id, response.is_ok());
                 let _ = task
                     .call_proof_requests
                     .remove(&substream_id)
@@ -1563,8 +2717,10 @@ async fn background_task<TPlat: PlatformRef>(mut task: BackgroundTask<TPlat>) {
                     .remove(&substream_id)
                     .unwrap();
 
-                log::debug!(
-                    target: "connections", "On chain {}, discovered: {}",
+                util::log!(
+                    Debug,
+                    &task.connections_log_target,
+                    "On chain {}, discovered: {}",
                     &task.log_chain_names[&chain_id],
                     nodes.iter().map(|(p, _)| p.to_string()).join(", ")
                 );
@@ -1573,10 +2729,25 @@ async fn background_task<TPlat: PlatformRef>(mut task: BackgroundTask<TPlat>) {
                     let mut valid_addrs = Vec::with_capacity(addrs.len());
                     for addr in addrs {
                         match Multiaddr::try_from(addr) {
-                            Ok(a) => valid_addrs.push(a),
+                            Ok(a) => {
+                                if let Err(err) =
+                                    multiaddr::sanitize(&a, &peer_id, &task.address_sanitize_policy)
+                                {
+                                    util::log!(
+                                        Debug,
+                                        &task.connections_log_target,
+                                        "Discovery => RefusedAddress({}, {})",
+                                        a,
+                                        err
+                                    );
+                                    continue;
+                                }
+                                valid_addrs.push(a);
+                            }
                             Err(err) => {
-                                log::debug!(
-                                    target: "connections",
+                                util::log!(
+                                    Debug,
+                                    &task.connections_log_target,
                                     "Discovery => InvalidAddress({})",
                                     hex::encode(&err.addr)
                                 );
@@ -1607,8 +2778,9 @@ async fn background_task<TPlat: PlatformRef>(mut task: BackgroundTask<TPlat>) {
                     .remove(&substream_id)
                     .unwrap();
 
-                log::debug!(
-                    target: "connections",
+                util::log!(
+                    Debug,
+                    &task.connections_log_target,
                     "Discovery({}) => {:?}",
                     &task.log_chain_names[&chain_id],
                     error
@@ -1626,8 +2798,9 @@ async fn background_task<TPlat: PlatformRef>(mut task: BackgroundTask<TPlat>) {
                         ),
                     ) => {
                         // TODO: remove this warning in a long time
-                        log::warn!(
-                            target: "connections",
+                        util::log!(
+                            Warn,
+                            &task.connections_log_target,
                             "Problem during discovery on {}: protocol not available. \
                             This might indicate that the version of Substrate used by \
                             the chain doesn't include \
@@ -1636,8 +2809,9 @@ async fn background_task<TPlat: PlatformRef>(mut task: BackgroundTask<TPlat>) {
                         );
                     }
                     _ => {
-                        log::warn!(
-                            target: "connections",
+                        util::log!(
+                            Warn,
+                            &task.connections_log_target,
                             "Problem during discovery on {}: {}",
                             &task.log_chain_names[&chain_id],
                             error
@@ -1666,8 +2840,9 @@ async fn background_task<TPlat: PlatformRef>(mut task: BackgroundTask<TPlat>) {
                     .count()
                     < 4
                 {
-                    log::debug!(
-                        target: "connections",
+                    util::log!(
+                        Debug,
+                        &task.connections_log_target,
                         "InSlots({}) ∋ {}",
                         &task.log_chain_names[&chain_id],
                         peer_id
@@ -1680,17 +2855,19 @@ async fn background_task<TPlat: PlatformRef>(mut task: BackgroundTask<TPlat>) {
                         )
                         .unwrap();
                 } else {
-                    log::debug!(
-                        target: "connections",
+                    util::log!(
+                        Debug,
+                        &task.connections_log_target,
                         "Connections({}) => GossipInDesiredRejected(chain={}, error=full)",
                         peer_id,
                         &task.log_chain_names[&chain_id],
                     );
                     task.network
-                        .gossip_close(
+                        .gossip_reject_in(
                             chain_id,
                             &peer_id,
                             service::GossipKind::ConsensusTransactions,
+                            service::GossipRejectReason::SlotsFull,
                         )
                         .unwrap();
                 }
@@ -1705,27 +2882,60 @@ async fn background_task<TPlat: PlatformRef>(mut task: BackgroundTask<TPlat>) {
                 peer_id,
                 substream_id,
             }) => {
-                log::debug!(
-                    target: "network",
+                util::log!(
+                    Debug,
+                    &task.log_target,
                     "Connections({}) => IdentifyRequest",
                     peer_id,
                 );
-                task.network
-                    .respond_identify(substream_id, &task.identify_agent_version);
+                task.network.respond_identify(
+                    &task.platform.now(),
+                    substream_id,
+                    &task.identify_agent_version,
+                );
                 continue;
             }
             WhatHappened::NetworkEvent(service::Event::BlocksRequestIn { .. }) => unreachable!(),
+            WhatHappened::NetworkEvent(
+                service::Event::CustomRequestIn { .. }
+                | service::Event::CustomRequestInStart { .. }
+                | service::Event::CustomRequestInChunk { .. }
+                | service::Event::CustomNotificationsOutResult { .. }
+                | service::Event::CustomNotificationsOutClose { .. }
+                | service::Event::CustomNotificationsOutQueueDrained { .. }
+                | service::Event::CustomNotificationsInOpen { .. }
+                | service::Event::CustomNotificationsInOpenCancel { .. }
+                | service::Event::CustomNotificationIn { .. }
+                | service::Event::CustomNotificationsInClose { .. },
+            ) => unreachable!(),
             WhatHappened::NetworkEvent(service::Event::RequestInCancel { .. }) => {
                 // All incoming requests are immediately answered.
                 unreachable!()
             }
+            WhatHappened::NetworkEvent(service::Event::TransactionsReceived {
+                chain_id,
+                peer_id,
+                transactions,
+            }) => {
+                // The light client doesn't maintain a pool of transactions gossiped by other
+                // nodes. Received transactions are simply discarded.
+                util::log!(
+                    Debug,
+                    &task.log_target,
+                    "Gossip({}, {}) => TransactionsReceived(num_transactions={})",
+                    peer_id,
+                    &task.log_chain_names[&chain_id],
+                    transactions.len(),
+                );
+                continue;
+            }
             WhatHappened::NetworkEvent(service::Event::GrandpaNeighborPacket {
                 chain_id,
                 peer_id,
                 state,
             }) => {
-                log::debug!(
-                    target: "network",
+                util::log!(Debug,
+                    &task.log_target,
                     "Gossip({}, {}) => GrandpaNeighborPacket(round_number={}, set_id={}, commit_finalized_height={})",
                     peer_id,
                     &task.log_chain_names[&chain_id],
@@ -1744,8 +2954,9 @@ async fn background_task<TPlat: PlatformRef>(mut task: BackgroundTask<TPlat>) {
                 peer_id,
                 message,
             }) => {
-                log::debug!(
-                    target: "network",
+                util::log!(
+                    Debug,
+                    &task.log_target,
                     "Gossip({}, {}) => GrandpaCommitMessage(target_block_hash={})",
                     peer_id,
                     &task.log_chain_names[&chain_id],
@@ -1757,10 +2968,64 @@ async fn background_task<TPlat: PlatformRef>(mut task: BackgroundTask<TPlat>) {
                     message,
                 }
             }
+            WhatHappened::NetworkEvent(
+                service::Event::GrandpaVoteMessage { .. }
+                | service::Event::GrandpaCatchUpRequest { .. }
+                | service::Event::GrandpaCatchUp { .. },
+            ) => {
+                // The light client doesn't take part in GrandPa voting rounds, and these
+                // messages are simply ignored.
+                continue;
+            }
+            WhatHappened::NetworkEvent(service::Event::NotificationsDropped {
+                peer_id,
+                chain_id,
+                protocol,
+                count,
+            }) => {
+                util::log!(
+                    Debug,
+                    &task.log_target,
+                    "Gossip({}, {}) => NotificationsDropped(protocol={:?}, count={})",
+                    peer_id,
+                    &task.log_chain_names[&chain_id],
+                    protocol,
+                    count,
+                );
+                continue;
+            }
+            WhatHappened::NetworkEvent(service::Event::GossipOutQueueDrained {
+                peer_id,
+                chain_id,
+                protocol,
+            }) => {
+                // Notifications that can't be queued are simply discarded, and there is
+                // consequently nothing to resend when a queue drains.
+                util::log!(
+                    Debug,
+                    &task.log_target,
+                    "Gossip({}, {}) => OutQueueDrained(protocol={:?})",
+                    peer_id,
+                    &task.log_chain_names[&chain_id],
+                    protocol,
+                );
+                continue;
+            }
+            WhatHappened::NetworkEvent(service::Event::PingResult { peer_id, rtt }) => {
+                util::log!(
+                    Debug,
+                    &task.log_target,
+                    "Connections({}) => PingResult(rtt={:?})",
+                    peer_id,
+                    rtt,
+                );
+                continue;
+            }
             WhatHappened::NetworkEvent(service::Event::ProtocolError { peer_id, error }) => {
                 // TODO: handle properly?
-                log::warn!(
-                    target: "network",
+                util::log!(
+                    Warn,
+                    &task.log_target,
                     "Connections({}) => ProtocolError(error={:?})",
                     peer_id,
                     error,
@@ -1769,10 +3034,78 @@ async fn background_task<TPlat: PlatformRef>(mut task: BackgroundTask<TPlat>) {
                 // TODO: disconnect peer
                 continue;
             }
-            WhatHappened::StartConnect(peer_id) => {
+            WhatHappened::NetworkEvent(service::Event::InboundProtocolError { peer_id, error }) => {
+                util::log!(
+                    Debug,
+                    &task.log_target,
+                    "Connections({}) => InboundProtocolError(error={})",
+                    peer_id,
+                    error,
+                );
+                continue;
+            }
+            WhatHappened::StartConnect {
+                peer_id,
+                is_fallback,
+            } => {
                 // TODO: restore rate limiting
 
-                let Some(multiaddr) = task.peering_strategy.addr_to_connected(&peer_id) else {
+                // Determine which IP family to dial, if any.
+                let family = if is_fallback {
+                    let race = task.dial_races.get_mut(&peer_id).unwrap();
+                    race.fallback_when = None;
+                    if race
+                        .connections
+                        .iter()
+                        .all(|id| task.network.is_connection_shutting_down(*id))
+                    {
+                        // The connections of the race are all shutting down, for example because
+                        // the peer has been banned. If the peer is still desired, a new
+                        // connection will be started through the normal process.
+                        if race.connections.is_empty() {
+                            task.dial_races.remove(&peer_id);
+                        }
+                        continue;
+                    }
+                    Some(race.first_family.other())
+                } else {
+                    match task.dial_races.remove(&peer_id) {
+                        // The first connection of the race has failed before the fallback
+                        // address has been dialed. Dial it immediately.
+                        Some(DialRace {
+                            first_family,
+                            fallback_when: Some(_),
+                            ..
+                        }) => Some(first_family.other()),
+                        _ => task.ip_family_policy.preferred_family(),
+                    }
+                };
+
+                let multiaddr = family
+                    .and_then(|family| {
+                        task.peering_strategy
+                            .addr_to_connected_filtered(&peer_id, |addr| {
+                                IpFamily::of_address(addr) == Some(family)
+                            })
+                            .map(|addr| addr.to_owned())
+                    })
+                    .or_else(|| {
+                        if is_fallback {
+                            None
+                        } else {
+                            task.peering_strategy
+                                .addr_to_connected(&peer_id)
+                                .map(|addr| addr.to_owned())
+                        }
+                    });
+
+                let Some(multiaddr) = multiaddr else {
+                    if is_fallback {
+                        // The address of the other family is no longer available. The first
+                        // connection of the race continues normally.
+                        continue;
+                    }
+
                     // There is no address for that peer in the address book.
                     task.network.gossip_remove_desired_all(
                         &peer_id,
@@ -1785,7 +3118,7 @@ async fn background_task<TPlat: PlatformRef>(mut task: BackgroundTask<TPlat>) {
                     continue;
                 };
 
-                let multiaddr = match multiaddr::Multiaddr::try_from(multiaddr.to_owned()) {
+                let multiaddr = match multiaddr::Multiaddr::try_from(multiaddr) {
                     Ok(a) => a,
                     Err(multiaddr::FromVecError { addr }) => {
                         // Address is in an invalid format.
@@ -1817,8 +3150,9 @@ async fn background_task<TPlat: PlatformRef>(mut task: BackgroundTask<TPlat>) {
                     continue;
                 };
 
-                log::debug!(
-                    target: "connections",
+                util::log!(
+                    Debug,
+                    &task.connections_log_target,
                     "Connections({}) <= StartConnecting({})",
                     peer_id,
                     multiaddr
@@ -1827,6 +3161,7 @@ async fn background_task<TPlat: PlatformRef>(mut task: BackgroundTask<TPlat>) {
                 let (coordinator_to_connection_tx, coordinator_to_connection_rx) =
                     async_channel::bounded(8);
                 let task_name = format!("connection-{}-{}", peer_id, multiaddr);
+                let dialed_family = IpFamily::of_address(multiaddr.as_ref());
 
                 let connection_id = match address {
                     address_parse::AddressOrMultiStreamAddress::Address(_) => {
@@ -1926,11 +3261,40 @@ async fn background_task<TPlat: PlatformRef>(mut task: BackgroundTask<TPlat>) {
                     .insert(connection_id, coordinator_to_connection_tx);
                 debug_assert!(_prev_value.is_none());
 
+                if is_fallback {
+                    task.dial_races
+                        .get_mut(&peer_id)
+                        .unwrap()
+                        .connections
+                        .push(connection_id);
+                } else if let (
+                    IpFamilyPolicy::HappyEyeballs { fallback_delay },
+                    Some(first_family),
+                ) = (task.ip_family_policy, dialed_family)
+                {
+                    // A race is started only if the peer can be reached through the other
+                    // family.
+                    if task
+                        .peering_strategy
+                        .peer_addresses(&peer_id)
+                        .any(|addr| IpFamily::of_address(addr) == Some(first_family.other()))
+                    {
+                        task.dial_races.insert(
+                            peer_id,
+                            DialRace {
+                                first_family,
+                                connections: alloc::vec![connection_id],
+                                fallback_when: Some(task.platform.now() + fallback_delay),
+                            },
+                        );
+                    }
+                }
+
                 continue;
             }
             WhatHappened::MessageToConnection {
                 connection_id,
-                message,
+                messages,
             } => {
                 // Note that it is critical for the sending to not take too long here, in order to not
                 // block the process of the network service.
@@ -1941,7 +3305,7 @@ async fn background_task<TPlat: PlatformRef>(mut task: BackgroundTask<TPlat>) {
                 task.active_connections
                     .get_mut(&connection_id)
                     .unwrap()
-                    .send(message)
+                    .send(messages)
                     .await
                     .unwrap();
                 continue;
@@ -1974,3 +3338,62 @@ async fn background_task<TPlat: PlatformRef>(mut task: BackgroundTask<TPlat>) {
         }));
     }
 }
+
+/// Returns the order in which `peers` should be tried when announcing a transaction to at most
+/// `max_peers` of them.
+///
+/// If there are more peers than `max_peers`, they are shuffled so that re-announcing the
+/// transaction reaches different peers every time.
+fn transaction_announce_order(
+    mut peers: Vec<PeerId>,
+    max_peers: usize,
+    randomness: &mut rand_chacha::ChaCha20Rng,
+) -> Vec<PeerId> {
+    if max_peers < peers.len() {
+        peers.shuffle(randomness);
+    }
+    peers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::transaction_announce_order;
+    use alloc::vec::Vec;
+    use rand_chacha::rand_core::SeedableRng as _;
+    use smoldot::libp2p::{peer_id, PeerId};
+
+    fn peers() -> Vec<PeerId> {
+        (0..8u8)
+            .map(|n| PeerId::from_public_key(&peer_id::PublicKey::Ed25519([n; 32])))
+            .collect()
+    }
+
+    #[test]
+    fn transaction_announce_order_all_peers() {
+        let mut randomness = rand_chacha::ChaCha20Rng::from_seed([0; 32]);
+        for max_peers in [8, usize::MAX] {
+            assert_eq!(
+                transaction_announce_order(peers(), max_peers, &mut randomness),
+                peers()
+            );
+        }
+    }
+
+    #[test]
+    fn transaction_announce_order_subset_rotates() {
+        let mut randomness = rand_chacha::ChaCha20Rng::from_seed([0; 32]);
+
+        let mut subsets = Vec::new();
+        for _ in 0..8 {
+            let mut order = transaction_announce_order(peers(), 2, &mut randomness);
+            assert_eq!(order.len(), 8);
+            order.truncate(2);
+            order.sort();
+            subsets.push(order);
+        }
+
+        // The same two peers aren't targeted every time.
+        subsets.dedup();
+        assert!(subsets.len() > 1);
+    }
+}
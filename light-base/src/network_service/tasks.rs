@@ -21,6 +21,7 @@ use crate::platform::{address_parse, PlatformRef, SubstreamDirection};
 use alloc::{
     boxed::Box,
     string::{String, ToString as _},
+    vec::Vec,
 };
 use core::{pin, time::Duration};
 use futures_lite::FutureExt as _;
@@ -36,7 +37,7 @@ pub(super) async fn single_stream_connection_task<TPlat: PlatformRef>(
     platform: TPlat,
     connection_id: service::ConnectionId,
     mut connection_task: service::SingleStreamConnectionTask<TPlat::Instant>,
-    mut coordinator_to_connection: async_channel::Receiver<service::CoordinatorToConnection>,
+    mut coordinator_to_connection: async_channel::Receiver<Vec<service::CoordinatorToConnection>>,
     connection_to_coordinator: async_channel::Sender<ToBackground>,
 ) {
     let address_string = address.to_string();
@@ -138,7 +139,7 @@ pub(super) async fn single_stream_connection_task<TPlat: PlatformRef>(
         // Now wait for something interesting to happen before looping again.
 
         enum WhatHappened {
-            CoordinatorMessage(service::CoordinatorToConnection),
+            CoordinatorMessage(Vec<service::CoordinatorToConnection>),
             CoordinatorDead,
             SocketEvent,
             MessageSent,
@@ -190,8 +191,11 @@ pub(super) async fn single_stream_connection_task<TPlat: PlatformRef>(
         };
 
         match what_happened {
-            WhatHappened::CoordinatorMessage(message) => {
-                connection_task.inject_coordinator_message(&platform.now(), message);
+            WhatHappened::CoordinatorMessage(messages) => {
+                let now = platform.now();
+                for message in messages {
+                    connection_task.inject_coordinator_message(&now, message);
+                }
             }
             WhatHappened::CoordinatorDead => return,
             WhatHappened::SocketEvent => {}
@@ -212,7 +216,7 @@ pub(super) async fn webrtc_multi_stream_connection_task<TPlat: PlatformRef>(
     platform: TPlat,
     connection_id: service::ConnectionId,
     mut connection_task: service::MultiStreamConnectionTask<TPlat::Instant, usize>,
-    mut coordinator_to_connection: async_channel::Receiver<service::CoordinatorToConnection>,
+    mut coordinator_to_connection: async_channel::Receiver<Vec<service::CoordinatorToConnection>>,
     connection_to_coordinator: async_channel::Sender<ToBackground>,
 ) {
     // Future that sends a message to the coordinator. Only one message is sent to the coordinator
@@ -242,7 +246,7 @@ pub(super) async fn webrtc_multi_stream_connection_task<TPlat: PlatformRef>(
         // Now wait for something interesting to happen before looping again.
 
         enum WhatHappened<TPlat: PlatformRef> {
-            CoordinatorMessage(service::CoordinatorToConnection),
+            CoordinatorMessage(Vec<service::CoordinatorToConnection>),
             CoordinatorDead,
             SocketEvent(pin::Pin<Box<TPlat::Stream>>, usize),
             MessageSent,
@@ -313,8 +317,11 @@ pub(super) async fn webrtc_multi_stream_connection_task<TPlat: PlatformRef>(
         };
 
         match what_happened {
-            WhatHappened::CoordinatorMessage(message) => {
-                connection_task.inject_coordinator_message(&platform.now(), message);
+            WhatHappened::CoordinatorMessage(messages) => {
+                let now = platform.now();
+                for message in messages {
+                    connection_task.inject_coordinator_message(&now, message);
+                }
             }
             WhatHappened::CoordinatorDead => return,
             WhatHappened::SocketEvent(mut socket, substream_id) => {
@@ -0,0 +1,608 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Background service that hands out account nonces.
+//!
+//! Submitting multiple transactions from the same account in a short period of time is
+//! error-prone: the nonce found in the storage of the best block doesn't take into account the
+//! transactions that are still pending, and two transactions built from the same storage value
+//! end up with the same nonce, meaning that one of them is invalid.
+//!
+//! The [`NonceService`] solves this problem by keeping track of the nonces that have been handed
+//! out through [`NonceService::reserve_nonce`]. The next nonce of an account is the maximum
+//! between the nonce found in the `System::Account` storage entry of the current best block and
+//! the highest reserved nonce plus one.
+//!
+//! Reservations are kept even after the nonce in the storage of the best block has caught up
+//! with them, so that a re-organization of the chain that removes the transaction from the best
+//! chain doesn't lead to the same nonce being handed out a second time. Reservations expire after
+//! [`Config::reservation_duration`], after which the transaction is assumed to either have been
+//! included in the finalized chain or to have been dropped. A reservation can also be released
+//! early with [`NonceService::release_nonce`], for example if the transaction has failed to be
+//! submitted.
+//!
+//! The service is created in two steps: [`service`] returns a [`NonceService`] that can be used
+//! immediately, and requests are processed once [`ServicePrototype::start`] has been called.
+
+use crate::{platform::PlatformRef, sync_service, util};
+
+use alloc::{
+    collections::BTreeMap,
+    format,
+    string::{String, ToString as _},
+    sync::Arc,
+    vec,
+    vec::Vec,
+};
+use core::{cmp, iter, num::NonZeroU32, ops, time::Duration};
+use futures_channel::oneshot;
+use futures_lite::FutureExt as _;
+use futures_util::{future, stream, StreamExt as _};
+use smoldot::{header, informant::HashDisplay};
+
+/// Storage key prefix of the `System::Account` storage map. Equal to
+/// `twox128("System") ++ twox128("Account")`.
+const SYSTEM_ACCOUNT_PREFIX: [u8; 32] = [
+    0x26, 0xaa, 0x39, 0x4e, 0xea, 0x56, 0x30, 0xe0, 0x7c, 0x48, 0xae, 0x0c, 0x95, 0x58, 0xce, 0xf7,
+    0xb9, 0x9d, 0x88, 0x0e, 0xc6, 0x81, 0x79, 0x9c, 0x0c, 0xf3, 0x0e, 0x88, 0x86, 0x37, 0x1d, 0xa9,
+];
+
+/// Configuration for a [`NonceService`].
+pub struct Config {
+    /// Name of the chain, for logging purposes.
+    ///
+    /// > **Note**: This name will be directly printed out. Any special character should already
+    /// >           have been filtered out from this name.
+    pub log_name: String,
+
+    /// Maximum level of the logs emitted by this service.
+    pub log_max_level: log::LevelFilter,
+
+    /// Duration after which a nonce handed out by [`NonceService::reserve_nonce`] is no longer
+    /// considered as reserved.
+    pub reservation_duration: Duration,
+}
+
+/// Creates a new nonce service.
+///
+/// Requests sent to the returned [`NonceService`] are queued until [`ServicePrototype::start`]
+/// is called.
+pub fn service(config: Config) -> (NonceService, ServicePrototype) {
+    let log_target = util::LogTarget::new(
+        format!("nonce-service-{}", config.log_name),
+        config.log_max_level,
+    );
+    let (to_background, from_foreground) = async_channel::bounded(8);
+
+    let service = NonceService { to_background };
+    let prototype = ServicePrototype {
+        log_target,
+        reservation_duration: config.reservation_duration,
+        from_foreground,
+    };
+
+    (service, prototype)
+}
+
+/// See [the module-level documentation](..).
+#[derive(Clone)]
+pub struct NonceService {
+    /// Sending messages to the background task.
+    to_background: async_channel::Sender<ToBackground>,
+}
+
+impl NonceService {
+    /// Returns the nonce to use for the next transaction emitted by the given account, and
+    /// reserves it.
+    ///
+    /// The account must be passed in its SCALE-encoded form, which is typically a 32 bytes
+    /// public key.
+    pub async fn reserve_nonce(&self, account: Vec<u8>) -> Result<u64, ReserveNonceError> {
+        let (result_tx, result_rx) = oneshot::channel();
+
+        self.to_background
+            .send(ToBackground::ReserveNonce { account, result_tx })
+            .await
+            .unwrap();
+
+        result_rx.await.unwrap()
+    }
+
+    /// Removes a reservation previously created with [`NonceService::reserve_nonce`].
+    ///
+    /// Has no effect if the given nonce isn't reserved.
+    pub async fn release_nonce(&self, account: Vec<u8>, nonce: u64) {
+        self.to_background
+            .send(ToBackground::ReleaseNonce { account, nonce })
+            .await
+            .unwrap();
+    }
+}
+
+/// Prototype for a nonce service.
+pub struct ServicePrototype {
+    /// Target to use when emitting logs.
+    log_target: util::LogTarget,
+
+    /// See [`Config::reservation_duration`].
+    reservation_duration: Duration,
+
+    /// Receiving messages from the [`NonceService`].
+    from_foreground: async_channel::Receiver<ToBackground>,
+}
+
+/// Configuration for starting a nonce service.
+pub struct StartConfig<TPlat: PlatformRef> {
+    /// Access to the platform's capabilities.
+    pub platform: TPlat,
+
+    /// Service responsible for synchronizing the chain.
+    pub sync_service: Arc<sync_service::SyncService<TPlat>>,
+}
+
+impl ServicePrototype {
+    /// Consumes this prototype and starts the service through [`PlatformRef::spawn_task`].
+    pub fn start<TPlat: PlatformRef>(self, config: StartConfig<TPlat>) {
+        let log_target = self.log_target.clone();
+        config
+            .platform
+            .clone()
+            .spawn_task(log_target.to_string().into(), async move {
+                background_task(self, config).await;
+                util::log!(Debug, &log_target, "Shutdown");
+            });
+    }
+}
+
+/// Error potentially returned by [`NonceService::reserve_nonce`].
+#[derive(Debug, derive_more::Display, Clone)]
+pub enum ReserveNonceError {
+    /// Failed to download the `System::Account` storage entry of the account.
+    #[display(fmt = "Failed to download account information: {_0}")]
+    StorageQuery(sync_service::StorageQueryError),
+    /// The `System::Account` storage entry of the account couldn't be decoded.
+    #[display(fmt = "Failed to decode account information")]
+    InvalidAccountInfo,
+    /// Nonce tracking is disabled for this chain.
+    #[display(fmt = "Nonce tracking is disabled for this chain")]
+    NonceTrackingDisabled,
+}
+
+enum ToBackground {
+    ReserveNonce {
+        account: Vec<u8>,
+        result_tx: oneshot::Sender<Result<u64, ReserveNonceError>>,
+    },
+    ReleaseNonce {
+        account: Vec<u8>,
+        nonce: u64,
+    },
+}
+
+async fn background_task<TPlat: PlatformRef>(
+    mut prototype: ServicePrototype,
+    config: StartConfig<TPlat>,
+) {
+    let mut reservations = NonceReservations::new(prototype.reservation_duration);
+
+    // Storage queries started in response to a `ReserveNonce` message. Once a query has
+    // finished, the nonce is reserved based on the reservations that exist at that moment.
+    let mut pending_queries = stream::FuturesUnordered::<
+        future::BoxFuture<
+            'static,
+            (
+                Vec<u8>,
+                [u8; 32],
+                oneshot::Sender<Result<u64, ReserveNonceError>>,
+                Result<u64, ReserveNonceError>,
+            ),
+        >,
+    >::new();
+
+    // Outer loop. Subscribes to the sync service, then processes the events. The subscription
+    // gets reset in case of a gap in the chain, in which case we subscribe again.
+    loop {
+        let mut subscription = config.sync_service.subscribe_all(32, false).await;
+
+        // Parent hash and header of the finalized block and of all the non-finalized blocks,
+        // indexed by hash. The parent of the finalized block is never in this list.
+        let mut headers =
+            hashbrown::HashMap::<[u8; 32], ([u8; 32], Vec<u8>), fnv::FnvBuildHasher>::default();
+        let mut finalized_block_hash = header::hash_from_scale_encoded_header(
+            &subscription.finalized_block_scale_encoded_header,
+        );
+        insert_header(
+            &mut headers,
+            finalized_block_hash,
+            subscription.finalized_block_scale_encoded_header,
+            config.sync_service.block_number_bytes(),
+        );
+        let mut best_block_hash = finalized_block_hash;
+        for block in subscription.non_finalized_blocks_ancestry_order {
+            let hash = header::hash_from_scale_encoded_header(&block.scale_encoded_header);
+            if block.is_new_best {
+                best_block_hash = hash;
+            }
+            insert_header(
+                &mut headers,
+                hash,
+                block.scale_encoded_header,
+                config.sync_service.block_number_bytes(),
+            );
+        }
+
+        // Inner loop. Process incoming events.
+        loop {
+            enum WhatHappened {
+                Notification(Option<sync_service::Notification>),
+                Message(Option<ToBackground>),
+                QueryFinished(
+                    Vec<u8>,
+                    [u8; 32],
+                    oneshot::Sender<Result<u64, ReserveNonceError>>,
+                    Result<u64, ReserveNonceError>,
+                ),
+            }
+
+            let what_happened =
+                async { WhatHappened::Notification(subscription.new_blocks.next().await) }
+                    .or(async { WhatHappened::Message(prototype.from_foreground.next().await) })
+                    .or(async {
+                        if pending_queries.is_empty() {
+                            future::pending::<()>().await;
+                        }
+                        let (account, block_hash, result_tx, result) =
+                            pending_queries.select_next_some().await;
+                        WhatHappened::QueryFinished(account, block_hash, result_tx, result)
+                    })
+                    .await;
+
+            match what_happened {
+                WhatHappened::Message(None) => return,
+                WhatHappened::Notification(None) => break,
+                WhatHappened::Notification(Some(sync_service::Notification::Block(block))) => {
+                    let hash = header::hash_from_scale_encoded_header(&block.scale_encoded_header);
+                    if block.is_new_best {
+                        best_block_hash = hash;
+                    }
+                    insert_header(
+                        &mut headers,
+                        hash,
+                        block.scale_encoded_header,
+                        config.sync_service.block_number_bytes(),
+                    );
+                }
+                WhatHappened::Notification(Some(
+                    sync_service::Notification::BestBlockChanged { hash },
+                )) => {
+                    best_block_hash = hash;
+                }
+                WhatHappened::Notification(Some(sync_service::Notification::Finalized {
+                    hash,
+                    best_block_hash: new_best_block_hash,
+                })) => {
+                    finalized_block_hash = hash;
+                    best_block_hash = new_best_block_hash;
+
+                    // Remove the headers of the blocks that don't descend from the new
+                    // finalized block. This is done by walking the tree of blocks downwards
+                    // starting from the new finalized block.
+                    let mut children = hashbrown::HashMap::<
+                        [u8; 32],
+                        Vec<[u8; 32]>,
+                        fnv::FnvBuildHasher,
+                    >::default();
+                    for (hash, (parent_hash, _)) in &headers {
+                        children.entry(*parent_hash).or_default().push(*hash);
+                    }
+
+                    let mut to_keep =
+                        hashbrown::HashSet::<[u8; 32], fnv::FnvBuildHasher>::default();
+                    let mut to_visit = vec![finalized_block_hash];
+                    while let Some(hash) = to_visit.pop() {
+                        to_keep.insert(hash);
+                        to_visit.extend(children.remove(&hash).into_iter().flatten());
+                    }
+                    headers.retain(|hash, _| to_keep.contains(hash));
+                }
+                WhatHappened::Message(Some(ToBackground::ReserveNonce { account, result_tx })) => {
+                    // The storage query is performed in the background, in order to not block
+                    // the processing of the other messages and notifications.
+                    pending_queries.push({
+                        let sync_service = config.sync_service.clone();
+                        let block_hash = best_block_hash;
+                        let block_scale_encoded_header = headers[&best_block_hash].1.clone();
+                        async move {
+                            let result = query_on_chain_nonce(
+                                &sync_service,
+                                &block_hash,
+                                &block_scale_encoded_header,
+                                &account,
+                            )
+                            .await;
+                            (account, block_hash, result_tx, result)
+                        }
+                        .boxed()
+                    });
+                }
+                WhatHappened::QueryFinished(account, block_hash, result_tx, Err(error)) => {
+                    util::log!(
+                        Debug,
+                        &prototype.log_target,
+                        "ReserveNonce(account=0x{}, block={}) => {}",
+                        hex::encode(&account),
+                        HashDisplay(&block_hash),
+                        error
+                    );
+                    let _ = result_tx.send(Err(error));
+                }
+                WhatHappened::QueryFinished(account, block_hash, result_tx, Ok(on_chain_nonce)) => {
+                    // Note that another nonce of the same account might have been reserved
+                    // while the query was in progress, which is taken into account here.
+                    let nonce =
+                        reservations.reserve(account, on_chain_nonce, config.platform.now());
+
+                    util::log!(
+                        Debug,
+                        &prototype.log_target,
+                        "ReserveNonce(block={}, on_chain_nonce={}) => {}",
+                        HashDisplay(&block_hash),
+                        on_chain_nonce,
+                        nonce
+                    );
+
+                    let _ = result_tx.send(Ok(nonce));
+                }
+                WhatHappened::Message(Some(ToBackground::ReleaseNonce { account, nonce })) => {
+                    reservations.release(&account, nonce);
+                }
+            }
+        }
+    }
+}
+
+/// Nonces currently reserved, for each account.
+///
+/// This is the part of the service that decides which nonce to hand out, independently of how
+/// the on-chain nonce is obtained.
+struct NonceReservations<TInstant> {
+    /// For each account, the nonces currently reserved and the moment when the reservation
+    /// expires. Accounts without any reservation are never in this list.
+    reservations: hashbrown::HashMap<Vec<u8>, BTreeMap<u64, TInstant>, fnv::FnvBuildHasher>,
+
+    /// See [`Config::reservation_duration`].
+    reservation_duration: Duration,
+}
+
+impl<TInstant> NonceReservations<TInstant>
+where
+    TInstant: Clone + Ord + ops::Add<Duration, Output = TInstant>,
+{
+    /// Initializes a new empty collection.
+    fn new(reservation_duration: Duration) -> Self {
+        NonceReservations {
+            reservations: Default::default(),
+            reservation_duration,
+        }
+    }
+
+    /// Reserves and returns the next nonce of the given account, given the nonce found in the
+    /// storage of the block that the query was performed against.
+    ///
+    /// The returned nonce is the maximum between `on_chain_nonce` and the highest nonce
+    /// currently reserved for this account plus one. Reservations that have expired at `now`
+    /// are removed beforehand, for all the accounts.
+    fn reserve(&mut self, account: Vec<u8>, on_chain_nonce: u64, now: TInstant) -> u64 {
+        self.reservations.retain(|_, account_reservations| {
+            account_reservations.retain(|_, expiration| *expiration > now);
+            !account_reservations.is_empty()
+        });
+
+        let account_reservations = self.reservations.entry(account).or_default();
+        let nonce = cmp::max(
+            on_chain_nonce,
+            account_reservations
+                .last_key_value()
+                .map_or(0, |(nonce, _)| *nonce + 1),
+        );
+        account_reservations.insert(nonce, now + self.reservation_duration);
+        nonce
+    }
+
+    /// Removes the reservation of the given nonce. Has no effect if the nonce isn't reserved.
+    fn release(&mut self, account: &[u8], nonce: u64) {
+        if let Some(account_reservations) = self.reservations.get_mut(account) {
+            account_reservations.remove(&nonce);
+            if account_reservations.is_empty() {
+                self.reservations.remove(account);
+            }
+        }
+    }
+}
+
+/// Inserts in `headers` the given block header alongside with its parent hash.
+fn insert_header(
+    headers: &mut hashbrown::HashMap<[u8; 32], ([u8; 32], Vec<u8>), fnv::FnvBuildHasher>,
+    hash: [u8; 32],
+    scale_encoded_header: Vec<u8>,
+    block_number_bytes: usize,
+) {
+    // The header has been verified by the sync service, and as such is known to be valid.
+    let parent_hash = *header::decode(&scale_encoded_header, block_number_bytes)
+        .unwrap()
+        .parent_hash;
+    headers.insert(hash, (parent_hash, scale_encoded_header));
+}
+
+/// Downloads the `System::Account` storage entry of the given account at the given block and
+/// returns the nonce that it contains.
+async fn query_on_chain_nonce<TPlat: PlatformRef>(
+    sync_service: &Arc<sync_service::SyncService<TPlat>>,
+    block_hash: &[u8; 32],
+    block_scale_encoded_header: &[u8],
+    account: &[u8],
+) -> Result<u64, ReserveNonceError> {
+    // The header has been verified by the sync service, and as such is known to be valid.
+    let decoded_header = header::decode(
+        block_scale_encoded_header,
+        sync_service.block_number_bytes(),
+    )
+    .unwrap();
+
+    // Storage key is `twox128("System") ++ twox128("Account") ++ blake2_128(account) ++ account`.
+    let key = SYSTEM_ACCOUNT_PREFIX
+        .iter()
+        .copied()
+        .chain(
+            blake2_rfc::blake2b::blake2b(16, &[], account)
+                .as_bytes()
+                .iter()
+                .copied(),
+        )
+        .chain(account.iter().copied())
+        .collect::<Vec<_>>();
+
+    let result = sync_service
+        .clone()
+        .storage_query(
+            decoded_header.number,
+            block_hash,
+            decoded_header.state_root,
+            iter::once(sync_service::StorageRequestItem {
+                key,
+                ty: sync_service::StorageRequestItemTy::Value,
+            }),
+            3,
+            Duration::from_secs(8),
+            NonZeroU32::new(1).unwrap(),
+        )
+        .await
+        .map_err(ReserveNonceError::StorageQuery)?;
+
+    let value = result
+        .into_iter()
+        .find_map(|item| match item {
+            sync_service::StorageResultItem::Value { value, .. } => Some(value),
+            _ => None,
+        })
+        .flatten();
+
+    // The `AccountInfo` struct starts with the nonce of the account, which is a `u32` on all the
+    // known chains. A missing entry means that the account has never emitted any transaction.
+    match value {
+        None => Ok(0),
+        Some(value) => match <[u8; 4]>::try_from(value.get(..4).unwrap_or(&[])) {
+            Ok(nonce) => Ok(u64::from(u32::from_le_bytes(nonce))),
+            Err(_) => Err(ReserveNonceError::InvalidAccountInfo),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::NonceReservations;
+    use alloc::vec;
+    use core::time::Duration;
+
+    fn new_reservations() -> NonceReservations<Duration> {
+        NonceReservations::new(Duration::from_secs(60))
+    }
+
+    #[test]
+    fn next_nonce_is_max_of_on_chain_and_reservations() {
+        let mut reservations = new_reservations();
+        let now = Duration::from_secs(0);
+
+        // Without reservation, the on-chain nonce is used.
+        assert_eq!(reservations.reserve(vec![1], 5, now), 5);
+        // The reservation is higher than the on-chain nonce.
+        assert_eq!(reservations.reserve(vec![1], 5, now), 6);
+        assert_eq!(reservations.reserve(vec![1], 3, now), 7);
+        // The on-chain nonce is higher than the reservations.
+        assert_eq!(reservations.reserve(vec![1], 10, now), 10);
+        assert_eq!(reservations.reserve(vec![1], 10, now), 11);
+
+        // Accounts are independent from each other.
+        assert_eq!(reservations.reserve(vec![2], 0, now), 0);
+    }
+
+    #[test]
+    fn reservations_released() {
+        let mut reservations = new_reservations();
+        let now = Duration::from_secs(0);
+
+        assert_eq!(reservations.reserve(vec![1], 5, now), 5);
+        assert_eq!(reservations.reserve(vec![1], 5, now), 6);
+
+        // Releasing the highest nonce makes it available again.
+        reservations.release(&[1], 6);
+        assert_eq!(reservations.reserve(vec![1], 5, now), 6);
+
+        // Releasing a nonce that isn't reserved has no effect.
+        reservations.release(&[1], 100);
+        reservations.release(&[2], 5);
+        assert_eq!(reservations.reserve(vec![1], 5, now), 7);
+
+        // Releasing all the nonces of an account removes it entirely.
+        for nonce in 5..=7 {
+            reservations.release(&[1], nonce);
+        }
+        assert!(reservations.reservations.is_empty());
+        assert_eq!(reservations.reserve(vec![1], 5, now), 5);
+    }
+
+    #[test]
+    fn reservations_expire() {
+        let mut reservations = new_reservations();
+
+        assert_eq!(reservations.reserve(vec![1], 5, Duration::from_secs(0)), 5);
+        assert_eq!(reservations.reserve(vec![1], 5, Duration::from_secs(30)), 6);
+
+        // The first reservation has expired, but not the second one.
+        assert_eq!(reservations.reserve(vec![1], 5, Duration::from_secs(70)), 7);
+
+        // All the reservations have expired, and the on-chain nonce is used again.
+        assert_eq!(
+            reservations.reserve(vec![1], 5, Duration::from_secs(200)),
+            5
+        );
+    }
+
+    #[test]
+    fn no_nonce_handed_out_twice_across_reorg() {
+        let mut reservations = new_reservations();
+        let now = Duration::from_secs(0);
+
+        // A transaction is emitted with the nonce found in the best block.
+        assert_eq!(reservations.reserve(vec![1], 5, now), 5);
+
+        // The transaction gets included in the new best block, then a re-organization
+        // happens and the best block no longer contains it. Two queries were in progress
+        // against each of these best blocks, and finish in any order.
+        let after_inclusion = reservations.reserve(vec![1], 6, now);
+        let after_reorg = reservations.reserve(vec![1], 5, now);
+        assert_eq!(after_inclusion, 6);
+        assert_eq!(after_reorg, 7);
+
+        let mut reservations = new_reservations();
+        assert_eq!(reservations.reserve(vec![1], 5, now), 5);
+        let after_reorg = reservations.reserve(vec![1], 5, now);
+        let after_inclusion = reservations.reserve(vec![1], 6, now);
+        assert_eq!(after_reorg, 6);
+        assert_eq!(after_inclusion, 7);
+    }
+}
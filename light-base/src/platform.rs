@@ -27,6 +27,7 @@ pub use smoldot::libp2p::with_buffers;
 
 pub mod address_parse;
 pub mod default;
+pub mod deterministic;
 
 /// Access to a platform's capabilities.
 ///
@@ -83,6 +84,30 @@ pub trait PlatformRef: Clone + Send + Sync + 'static {
     /// Returns an object that represents "now".
     fn now(&self) -> Self::Instant;
 
+    /// Converts an [`PlatformRef::Instant`] into the time elapsed since the Unix Epoch, in the
+    /// same way as [`PlatformRef::now_from_unix_epoch`].
+    ///
+    /// This is done by correlating [`PlatformRef::now`] with
+    /// [`PlatformRef::now_from_unix_epoch`]. Since the system time can be adjusted while
+    /// instants are monotonic, the value returned by this function for a given instant might
+    /// change over time.
+    ///
+    /// Instants from before the Unix Epoch are clamped to the Unix Epoch.
+    ///
+    /// # Panic
+    ///
+    /// Panics in the same situations as [`PlatformRef::now_from_unix_epoch`].
+    ///
+    fn instant_to_unix_epoch(&self, instant: Self::Instant) -> Duration {
+        let now = self.now();
+        let now_from_unix_epoch = self.now_from_unix_epoch();
+        if instant <= now {
+            now_from_unix_epoch.saturating_sub(now - instant)
+        } else {
+            now_from_unix_epoch + (instant - now)
+        }
+    }
+
     /// The given buffer must be completely filled with pseudo-random bytes.
     ///
     /// # Panic
@@ -390,3 +415,36 @@ pub struct ConnectError {
     /// Human-readable error message.
     pub message: String,
 }
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::{default::DefaultPlatform, PlatformRef as _};
+    use core::time::Duration;
+
+    /// Returns `true` if the two durations are less than a second apart, to account for the
+    /// time elapsed between the calls to the platform.
+    fn roughly_equal(a: Duration, b: Duration) -> bool {
+        a.max(b) - a.min(b) < Duration::from_secs(1)
+    }
+
+    #[test]
+    fn instant_to_unix_epoch_correlates_with_now() {
+        let platform = DefaultPlatform::new("test".into(), "0.0.0".into());
+
+        let now = platform.now();
+        let now_from_unix_epoch = platform.now_from_unix_epoch();
+
+        assert!(roughly_equal(
+            platform.instant_to_unix_epoch(now),
+            now_from_unix_epoch
+        ));
+        assert!(roughly_equal(
+            platform.instant_to_unix_epoch(now + Duration::from_secs(60)),
+            now_from_unix_epoch + Duration::from_secs(60)
+        ));
+        assert!(roughly_equal(
+            platform.instant_to_unix_epoch(now - Duration::from_secs(60)),
+            now_from_unix_epoch - Duration::from_secs(60)
+        ));
+    }
+}
@@ -0,0 +1,425 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Implementation of the [`PlatformRef`] trait that wraps around another platform and runs all
+//! the tasks spawned through it in a reproducible order.
+//!
+//! This is meant to be used in order to reproduce bugs caused by a race between two services of
+//! the light client. All the tasks spawned through a [`DeterministicPlatform`] are driven by a
+//! single cooperative scheduler, itself spawned as one task of the inner platform. Whenever
+//! multiple tasks are ready to make progress, the scheduler picks the next task to poll using a
+//! pseudo-random number generator initialized from a seed. Random bytes returned by
+//! [`PlatformRef::fill_random_bytes`] are also derived from this seed.
+//!
+//! Using the same seed twice thus leads to the same interleaving of tasks, as long as the events
+//! that come from the inner platform (timers, network activity) also happen in the same order.
+//! In other words, this platform removes the non-determinism introduced by the tasks executor,
+//! but not the one introduced by the outside world.
+//!
+//! > **Note**: Because all the random bytes are derived from the seed, the network identity of
+//! >           the light client and the keys it generates are predictable. This platform must
+//! >           only be used for debugging purposes.
+
+#![cfg(feature = "std")]
+#![cfg_attr(docsrs, doc(cfg(feature = "std")))]
+
+use super::{Address, ConnectionType, MultiStreamAddress, PlatformRef};
+
+use alloc::{borrow::Cow, boxed::Box, collections::BTreeSet, sync::Arc, vec::Vec};
+use core::{
+    future::Future,
+    mem,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+use futures_util::{future, task};
+use rand::{Rng as _, RngCore as _, SeedableRng as _};
+
+/// Maximum number of tasks polled in a row by the scheduler before it yields back to the inner
+/// platform.
+const MAX_POLLS_BEFORE_YIELD: usize = 64;
+
+/// Implementation of the [`PlatformRef`] trait that runs tasks in a reproducible order.
+///
+/// See [the module-level documentation](self).
+pub struct DeterministicPlatform<TPlat> {
+    inner: TPlat,
+    shared: Arc<Shared>,
+}
+
+struct Shared {
+    /// Seed that was passed to [`DeterministicPlatform::new`].
+    seed: u64,
+
+    /// Tasks that have been spawned but not yet inserted in the list of tasks of the scheduler.
+    new_tasks: parking_lot::Mutex<Vec<future::BoxFuture<'static, ()>>>,
+
+    /// Indices of the tasks of the scheduler that have been woken up and must be polled again.
+    woken_tasks: parking_lot::Mutex<BTreeSet<usize>>,
+
+    /// Waker of the scheduler task. Woken up whenever a task is spawned or woken up.
+    scheduler_waker: task::AtomicWaker,
+
+    /// Source of randomness of [`PlatformRef::fill_random_bytes`].
+    random_bytes: parking_lot::Mutex<rand_chacha::ChaCha20Rng>,
+}
+
+impl Shared {
+    fn new(seed: u64) -> Self {
+        let mut random_bytes = rand_chacha::ChaCha20Rng::seed_from_u64(seed);
+        random_bytes.set_stream(1);
+
+        Shared {
+            seed,
+            new_tasks: parking_lot::Mutex::new(Vec::new()),
+            woken_tasks: parking_lot::Mutex::new(BTreeSet::new()),
+            scheduler_waker: task::AtomicWaker::new(),
+            random_bytes: parking_lot::Mutex::new(random_bytes),
+        }
+    }
+}
+
+impl<TPlat: PlatformRef> DeterministicPlatform<TPlat> {
+    /// Creates a new [`DeterministicPlatform`] wrapping around the given platform.
+    ///
+    /// This spawns the scheduler as a task of the inner platform.
+    pub fn new(inner: TPlat, seed: u64) -> Self {
+        let shared = Arc::new(Shared::new(seed));
+
+        inner.spawn_task(
+            "deterministic-scheduler".into(),
+            run_scheduler(shared.clone()),
+        );
+
+        DeterministicPlatform { inner, shared }
+    }
+
+    /// Returns the seed that was passed to [`DeterministicPlatform::new`].
+    pub fn seed(&self) -> u64 {
+        self.shared.seed
+    }
+}
+
+impl<TPlat: Clone> Clone for DeterministicPlatform<TPlat> {
+    fn clone(&self) -> Self {
+        DeterministicPlatform {
+            inner: self.inner.clone(),
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<TPlat: PlatformRef> PlatformRef for DeterministicPlatform<TPlat> {
+    type Delay = TPlat::Delay;
+    type Instant = TPlat::Instant;
+    type MultiStream = TPlat::MultiStream;
+    type Stream = TPlat::Stream;
+    type StreamConnectFuture = TPlat::StreamConnectFuture;
+    type MultiStreamConnectFuture = TPlat::MultiStreamConnectFuture;
+    type ReadWriteAccess<'a> = TPlat::ReadWriteAccess<'a>;
+    type StreamUpdateFuture<'a> = TPlat::StreamUpdateFuture<'a>;
+    type StreamErrorRef<'a> = TPlat::StreamErrorRef<'a>;
+    type NextSubstreamFuture<'a> = TPlat::NextSubstreamFuture<'a>;
+
+    fn now_from_unix_epoch(&self) -> Duration {
+        self.inner.now_from_unix_epoch()
+    }
+
+    fn now(&self) -> Self::Instant {
+        self.inner.now()
+    }
+
+    fn fill_random_bytes(&self, buffer: &mut [u8]) {
+        self.shared.random_bytes.lock().fill_bytes(buffer);
+    }
+
+    fn sleep(&self, duration: Duration) -> Self::Delay {
+        self.inner.sleep(duration)
+    }
+
+    fn sleep_until(&self, when: Self::Instant) -> Self::Delay {
+        self.inner.sleep_until(when)
+    }
+
+    fn spawn_task(
+        &self,
+        _task_name: Cow<str>,
+        task: impl future::Future<Output = ()> + Send + 'static,
+    ) {
+        self.shared.new_tasks.lock().push(Box::pin(task));
+        self.shared.scheduler_waker.wake();
+    }
+
+    fn client_name(&self) -> Cow<str> {
+        self.inner.client_name()
+    }
+
+    fn client_version(&self) -> Cow<str> {
+        self.inner.client_version()
+    }
+
+    fn supports_connection_type(&self, connection_type: ConnectionType) -> bool {
+        self.inner.supports_connection_type(connection_type)
+    }
+
+    fn connect_stream(&self, address: Address) -> Self::StreamConnectFuture {
+        self.inner.connect_stream(address)
+    }
+
+    fn connect_multistream(&self, address: MultiStreamAddress) -> Self::MultiStreamConnectFuture {
+        self.inner.connect_multistream(address)
+    }
+
+    fn open_out_substream(&self, connection: &mut Self::MultiStream) {
+        self.inner.open_out_substream(connection)
+    }
+
+    fn next_substream<'a>(
+        &self,
+        connection: &'a mut Self::MultiStream,
+    ) -> Self::NextSubstreamFuture<'a> {
+        self.inner.next_substream(connection)
+    }
+
+    fn read_write_access<'a>(
+        &self,
+        stream: Pin<&'a mut Self::Stream>,
+    ) -> Result<Self::ReadWriteAccess<'a>, Self::StreamErrorRef<'a>> {
+        self.inner.read_write_access(stream)
+    }
+
+    fn wait_read_write_again<'a>(
+        &self,
+        stream: Pin<&'a mut Self::Stream>,
+    ) -> Self::StreamUpdateFuture<'a> {
+        self.inner.wait_read_write_again(stream)
+    }
+}
+
+/// Waker of an individual task of the scheduler.
+struct TaskWaker {
+    shared: Arc<Shared>,
+    task_index: usize,
+}
+
+impl task::ArcWake for TaskWaker {
+    fn wake_by_ref(arc_self: &Arc<Self>) {
+        arc_self
+            .shared
+            .woken_tasks
+            .lock()
+            .insert(arc_self.task_index);
+        arc_self.shared.scheduler_waker.wake();
+    }
+}
+
+/// Future that drives all the tasks spawned through a [`DeterministicPlatform`].
+///
+/// Never finishes.
+fn run_scheduler(shared: Arc<Shared>) -> impl Future<Output = ()> + Send + 'static {
+    let mut tasks = slab::Slab::<future::BoxFuture<'static, ()>>::new();
+    let mut rng = rand_chacha::ChaCha20Rng::seed_from_u64(shared.seed);
+
+    future::poll_fn(move |cx: &mut Context| {
+        shared.scheduler_waker.register(cx.waker());
+
+        for _ in 0..MAX_POLLS_BEFORE_YIELD {
+            // Insert the newly-spawned tasks in the order in which they have been spawned.
+            // They are all considered as woken up.
+            for new_task in mem::take(&mut *shared.new_tasks.lock()) {
+                let task_index = tasks.insert(new_task);
+                shared.woken_tasks.lock().insert(task_index);
+            }
+
+            // Pick the next task to poll among the ones that are woken up. Since the set of
+            // woken up tasks is ordered, the choice only depends on the state of the generator.
+            let task_index = {
+                let mut woken_tasks = shared.woken_tasks.lock();
+                if woken_tasks.is_empty() {
+                    return Poll::Pending;
+                }
+                let nth = rng.gen_range(0..woken_tasks.len());
+                let task_index = *woken_tasks.iter().nth(nth).unwrap();
+                woken_tasks.remove(&task_index);
+                task_index
+            };
+
+            // The task might have finished in the past, in which case its index is stale.
+            let Some(task) = tasks.get_mut(task_index) else {
+                continue;
+            };
+
+            let waker = task::waker(Arc::new(TaskWaker {
+                shared: shared.clone(),
+                task_index,
+            }));
+
+            if task
+                .as_mut()
+                .poll(&mut Context::from_waker(&waker))
+                .is_ready()
+            {
+                drop(tasks.remove(task_index));
+            }
+        }
+
+        // Yield back to the inner platform in order to not monopolize its executor, but make
+        // sure to be polled again as soon as possible.
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{run_scheduler, Shared};
+    use alloc::{boxed::Box, sync::Arc, vec::Vec};
+    use core::{
+        pin::Pin,
+        sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+        task::{Context, Poll, Waker},
+    };
+    use futures_util::{future, task};
+
+    /// Polls the scheduler until none of its tasks can make progress anymore.
+    fn run_until_idle(
+        shared: &Arc<Shared>,
+        mut scheduler: Pin<&mut impl future::Future<Output = ()>>,
+    ) {
+        loop {
+            assert!(scheduler
+                .as_mut()
+                .poll(&mut Context::from_waker(&task::noop_waker()))
+                .is_pending());
+            if shared.new_tasks.lock().is_empty() && shared.woken_tasks.lock().is_empty() {
+                break;
+            }
+        }
+    }
+
+    /// Runs a few tasks that each record their identifier every time they're polled, and
+    /// returns the list of recorded identifiers.
+    fn poll_order(seed: u64) -> Vec<usize> {
+        let shared = Arc::new(Shared::new(seed));
+        let order = Arc::new(parking_lot::Mutex::new(Vec::new()));
+
+        for task_id in 0..8 {
+            let order = order.clone();
+            let mut remaining_polls = 4;
+            shared
+                .new_tasks
+                .lock()
+                .push(Box::pin(future::poll_fn(move |cx: &mut Context| {
+                    order.lock().push(task_id);
+                    if remaining_polls == 0 {
+                        return Poll::Ready(());
+                    }
+                    remaining_polls -= 1;
+                    cx.waker().wake_by_ref();
+                    Poll::Pending
+                })));
+        }
+
+        let mut scheduler = Box::pin(run_scheduler(shared.clone()));
+        run_until_idle(&shared, scheduler.as_mut());
+
+        let order = order.lock().clone();
+        for task_id in 0..8 {
+            assert_eq!(order.iter().filter(|id| **id == task_id).count(), 5);
+        }
+        order
+    }
+
+    #[test]
+    fn same_seed_same_order() {
+        for seed in 0..16 {
+            assert_eq!(poll_order(seed), poll_order(seed));
+        }
+    }
+
+    #[test]
+    fn different_seed_different_order() {
+        assert_ne!(poll_order(0), poll_order(1));
+        assert_ne!(poll_order(1), poll_order(2));
+    }
+
+    #[test]
+    fn finished_task_index_reused() {
+        for seed in 0..16 {
+            let shared = Arc::new(Shared::new(seed));
+            let first_finished = Arc::new(AtomicBool::new(false));
+            let stale_waker = Arc::new(parking_lot::Mutex::new(None::<Waker>));
+            let third_polls = Arc::new(AtomicUsize::new(0));
+            let num_finished = Arc::new(AtomicUsize::new(0));
+
+            // The first task finishes immediately, but leaves its waker behind.
+            shared.new_tasks.lock().push(Box::pin({
+                let first_finished = first_finished.clone();
+                let stale_waker = stale_waker.clone();
+                let num_finished = num_finished.clone();
+                future::poll_fn(move |cx: &mut Context| {
+                    *stale_waker.lock() = Some(cx.waker().clone());
+                    first_finished.store(true, Ordering::SeqCst);
+                    num_finished.fetch_add(1, Ordering::SeqCst);
+                    Poll::Ready(())
+                })
+            }));
+
+            // The second task waits for the first one to finish, then spawns a third task, which
+            // takes the place of the first one in the list of tasks, and wakes up the waker of the
+            // first task.
+            shared.new_tasks.lock().push(Box::pin({
+                let shared = shared.clone();
+                let third_polls = third_polls.clone();
+                let num_finished = num_finished.clone();
+                future::poll_fn(move |cx: &mut Context| {
+                    if !first_finished.load(Ordering::SeqCst) {
+                        cx.waker().wake_by_ref();
+                        return Poll::Pending;
+                    }
+
+                    let third_polls = third_polls.clone();
+                    let num_finished_third = num_finished.clone();
+                    shared.new_tasks.lock().push(Box::pin(future::poll_fn(
+                        move |cx: &mut Context| {
+                            if third_polls.fetch_add(1, Ordering::SeqCst) == 2 {
+                                num_finished_third.fetch_add(1, Ordering::SeqCst);
+                                return Poll::Ready(());
+                            }
+                            cx.waker().wake_by_ref();
+                            Poll::Pending
+                        },
+                    )));
+                    shared.scheduler_waker.wake();
+
+                    stale_waker.lock().take().unwrap().wake();
+                    num_finished.fetch_add(1, Ordering::SeqCst);
+                    Poll::Ready(())
+                })
+            }));
+
+            let mut scheduler = Box::pin(run_scheduler(shared.clone()));
+            run_until_idle(&shared, scheduler.as_mut());
+
+            // A spurious wake up through the stale waker doesn't prevent the third task from
+            // being polled until it finishes.
+            assert_eq!(num_finished.load(Ordering::SeqCst), 3);
+            assert!(third_polls.load(Ordering::SeqCst) >= 3);
+        }
+    }
+}
@@ -54,12 +54,12 @@
 //! large, the subscription is force-killed by the [`RuntimeService`].
 //!
 
-use crate::{platform::PlatformRef, sync_service};
+use crate::{platform::PlatformRef, sync_service, util};
 
 use alloc::{
     borrow::ToOwned as _,
     boxed::Box,
-    collections::BTreeMap,
+    collections::{BTreeMap, VecDeque},
     format,
     string::{String, ToString as _},
     sync::{Arc, Weak},
@@ -70,6 +70,7 @@ use core::{
     iter, mem,
     num::{NonZeroU32, NonZeroUsize},
     pin::Pin,
+    sync::atomic::{AtomicU64, Ordering},
     time::Duration,
 };
 use futures_channel::mpsc;
@@ -77,9 +78,10 @@ use futures_util::{future, stream, FutureExt as _, Stream, StreamExt as _};
 use itertools::Itertools as _;
 use smoldot::{
     chain::async_tree,
-    executor, header,
+    executor::{self, runtime_host},
+    header,
     informant::{BytesDisplay, HashDisplay},
-    network::protocol,
+    network::codec,
     trie::{self, proof_decode, Nibble, TrieEntryVersion},
 };
 
@@ -91,6 +93,9 @@ pub struct Config<TPlat: PlatformRef> {
     /// >           have been filtered out from this name.
     pub log_name: String,
 
+    /// Maximum level of the logs emitted by this service.
+    pub log_max_level: log::LevelFilter,
+
     /// Access to the platform's capabilities.
     pub platform: TPlat,
 
@@ -99,6 +104,17 @@ pub struct Config<TPlat: PlatformRef> {
 
     /// Header of the genesis block of the chain, in SCALE encoding.
     pub genesis_block_scale_encoded_header: Vec<u8>,
+
+    /// Maximum size of the memory of each runtime, in pages of 64kiB. Runtime calls whose memory
+    /// usage grows above this limit fail with
+    /// [`executor::host::Error::MemoryLimitExceeded`]. `None` means no limit.
+    pub max_memory_pages: Option<executor::host::HeapPages>,
+
+    /// Strategy used to determine which block is reported as the best block to the subscribers.
+    ///
+    /// [`async_tree::BestBlockPolicy::FollowInput`] reports the best block of the sync service,
+    /// or its closest ancestor whose runtime is known.
+    pub best_block_policy: async_tree::BestBlockPolicy,
 }
 
 /// Identifies a runtime currently pinned within a [`RuntimeService`].
@@ -107,9 +123,15 @@ pub struct PinnedRuntimeId(Arc<Runtime>);
 
 /// See [the module-level documentation](..).
 pub struct RuntimeService<TPlat: PlatformRef> {
+    /// See [`Config::platform`].
+    platform: TPlat,
+
     /// See [`Config::sync_service`].
     sync_service: Arc<sync_service::SyncService<TPlat>>,
 
+    /// Metrics about the runtime calls. Shared with the background task.
+    metrics: Arc<Metrics>,
+
     /// Fields behind a `Mutex`. Should only be locked for short-lived operations.
     guarded: Arc<Mutex<Guarded<TPlat>>>,
 
@@ -124,7 +146,8 @@ impl<TPlat: PlatformRef> RuntimeService<TPlat> {
     /// necessary only for locking purposes.
     pub async fn new(config: Config<TPlat>) -> Self {
         // Target to use for all the logs of this service.
-        let log_target = format!("runtime-{}", config.log_name);
+        let log_target =
+            util::LogTarget::new(format!("runtime-{}", config.log_name), config.log_max_level);
 
         let best_near_head_of_chain = config.sync_service.is_near_head_of_chain_heuristic().await;
 
@@ -133,6 +156,7 @@ impl<TPlat: PlatformRef> RuntimeService<TPlat> {
                 finalized_async_user_data: None,
                 retry_after_failed: Duration::from_secs(10),
                 blocks_capacity: 32,
+                best_block_policy: config.best_block_policy,
             });
             let node_index = tree.input_insert_block(
                 Block {
@@ -156,13 +180,22 @@ impl<TPlat: PlatformRef> RuntimeService<TPlat> {
         let guarded = Arc::new(Mutex::new(Guarded {
             next_subscription_id: 0,
             best_near_head_of_chain,
+            reported_near_head_of_chain: best_near_head_of_chain,
+            near_head_of_chain_subscriptions: Vec::new(),
             tree,
             runtimes: slab::Slab::with_capacity(2),
+            max_memory_pages: config.max_memory_pages,
+            best_block_policy: config.best_block_policy,
+            hot_calls: Vec::new(),
+            hot_calls_proofs: BTreeMap::new(),
+            hot_calls_proofs_blocks: VecDeque::with_capacity(HOT_CALLS_PROOFS_MAX_BLOCKS),
         }));
 
+        let metrics = Arc::new(Metrics::new());
+
         // Spawns a task that runs in the background and updates the content of the mutex.
         let background_task_abort;
-        config.platform.spawn_task(log_target.clone().into(), {
+        config.platform.spawn_task(log_target.to_string().into(), {
             let sync_service = config.sync_service.clone();
             let guarded = guarded.clone();
             let platform = config.platform.clone();
@@ -171,22 +204,36 @@ impl<TPlat: PlatformRef> RuntimeService<TPlat> {
                 platform,
                 sync_service,
                 guarded,
+                metrics.clone(),
             ));
             background_task_abort = abort;
             abortable
                 .map(move |_| {
-                    log::debug!(target: &log_target, "Shutdown");
+                    util::log!(Debug, &log_target, "Shutdown");
                 })
                 .boxed()
         });
 
         RuntimeService {
+            platform: config.platform,
             sync_service: config.sync_service,
+            metrics,
             guarded,
             background_task_abort,
         }
     }
 
+    /// Returns a snapshot of the metrics about the runtime calls performed against this
+    /// runtime service and the runtimes it has compiled.
+    pub fn runtime_call_metrics(&self) -> RuntimeCallMetrics {
+        RuntimeCallMetrics {
+            call_duration_us: self.metrics.call_duration_us.snapshot(),
+            vm_instantiation_duration_us: self.metrics.vm_instantiation_duration_us.snapshot(),
+            call_proof_size_bytes: self.metrics.call_proof_size_bytes.snapshot(),
+            fallback_fetched_entries: self.metrics.fallback_fetched_entries.snapshot(),
+        }
+    }
+
     /// Calls [`sync_service::SyncService::block_number_bytes`] on the sync service associated to
     /// this runtime service.
     pub fn block_number_bytes(&self) -> usize {
@@ -500,7 +547,10 @@ impl<TPlat: PlatformRef> RuntimeService<TPlat> {
         };
 
         Ok(RuntimeAccess {
+            platform: self.platform.clone(),
             sync_service: self.sync_service.clone(),
+            metrics: self.metrics.clone(),
+            guarded: self.guarded.clone(),
             hash: block_hash,
             runtime: pinned_block.runtime,
             block_number: pinned_block.block_number,
@@ -526,7 +576,10 @@ impl<TPlat: PlatformRef> RuntimeService<TPlat> {
         block_state_trie_root_hash: [u8; 32],
     ) -> RuntimeAccess<TPlat> {
         RuntimeAccess {
+            platform: self.platform.clone(),
             sync_service: self.sync_service.clone(),
+            metrics: self.metrics.clone(),
+            guarded: self.guarded.clone(),
             hash: block_hash,
             runtime: pinned_runtime_id.0,
             block_number,
@@ -558,7 +611,14 @@ impl<TPlat: PlatformRef> RuntimeService<TPlat> {
             existing_runtime
         } else {
             // No identical runtime was found. Try compiling the new runtime.
-            let runtime = SuccessfulRuntime::from_storage(&storage_code, &storage_heap_pages).await;
+            let runtime = SuccessfulRuntime::from_storage(
+                &self.platform,
+                &self.metrics,
+                &storage_code,
+                &storage_heap_pages,
+                guarded.max_memory_pages,
+            )
+            .await;
             let runtime = Arc::new(Runtime {
                 heap_pages: storage_heap_pages,
                 runtime_code: storage_code,
@@ -592,6 +652,54 @@ impl<TPlat: PlatformRef> RuntimeService<TPlat> {
     pub async fn is_near_head_of_chain_heuristic(&self) -> bool {
         is_near_head_of_chain_heuristic(&self.sync_service, &self.guarded).await
     }
+
+    /// Subscribes to the changes of the value that
+    /// [`RuntimeService::is_near_head_of_chain_heuristic`] would return.
+    ///
+    /// The channel first yields the current value, then a new value every time it changes.
+    /// Changes are only detected when the runtime service updates its best block, and as such
+    /// the values yielded by the channel might lag slightly behind the return value of
+    /// [`RuntimeService::is_near_head_of_chain_heuristic`].
+    ///
+    /// Only up to `buffer_size` notifications are buffered in the channel. If the channel is
+    /// full when a new notification is attempted to be pushed, the channel gets closed.
+    pub async fn subscribe_near_head_of_chain(&self, buffer_size: usize) -> mpsc::Receiver<bool> {
+        let (mut tx, rx) = mpsc::channel(buffer_size);
+        let mut guarded = self.guarded.lock().await;
+        // Each sender has a guaranteed slot in the channel, meaning that this can't fail.
+        tx.try_send(guarded.reported_near_head_of_chain).unwrap();
+        guarded.near_head_of_chain_subscriptions.push(tx);
+        rx
+    }
+
+    /// Registers a "hot" runtime call, in other words a call that is expected to be performed
+    /// frequently.
+    ///
+    /// Whenever a new best block is reported, the call proofs of all the hot calls are
+    /// downloaded ahead of time, so that later calls made through [`RuntimeAccess::start`] with
+    /// the same function name and parameter don't have to wait for the networking.
+    ///
+    /// Registering a call that is already registered does nothing.
+    pub async fn register_hot_call(&self, method: String, parameter: Vec<u8>) {
+        let mut guarded = self.guarded.lock().await;
+        if !guarded
+            .hot_calls
+            .iter()
+            .any(|(m, p)| *m == method && *p == parameter)
+        {
+            guarded.hot_calls.push((method, parameter));
+        }
+    }
+
+    /// Unregisters a call previously registered with [`RuntimeService::register_hot_call`].
+    ///
+    /// Call proofs that have already been downloaded are kept until they are evicted.
+    pub async fn unregister_hot_call(&self, method: &str, parameter: &[u8]) {
+        let mut guarded = self.guarded.lock().await;
+        guarded
+            .hot_calls
+            .retain(|(m, p)| !(m == method && p == parameter));
+    }
 }
 
 impl<TPlat: PlatformRef> Drop for RuntimeService<TPlat> {
@@ -775,7 +883,10 @@ pub enum PinnedBlockRuntimeAccessError {
 /// See [`RuntimeService::pinned_block_runtime_access`].
 #[must_use]
 pub struct RuntimeAccess<TPlat: PlatformRef> {
+    platform: TPlat,
     sync_service: Arc<sync_service::SyncService<TPlat>>,
+    metrics: Arc<Metrics>,
+    guarded: Arc<Mutex<Guarded<TPlat>>>,
 
     block_number: u64,
     block_state_root_hash: [u8; 32],
@@ -797,6 +908,11 @@ impl<TPlat: PlatformRef> RuntimeAccess<TPlat> {
         }
     }
 
+    /// Starts a runtime call against the block.
+    ///
+    /// See [`SyncService::call_proof_query`](crate::sync_service::SyncService::call_proof_query)
+    /// for the meaning of `total_attempts`, `timeout_per_request`, `max_parallel`, and
+    /// `hedging_delay`.
     pub async fn start<'b>(
         &'b self,
         method: &'b str,
@@ -804,38 +920,57 @@ impl<TPlat: PlatformRef> RuntimeAccess<TPlat> {
         total_attempts: u32,
         timeout_per_request: Duration,
         max_parallel: NonZeroU32,
+        hedging_delay: Option<Duration>,
     ) -> Result<(RuntimeCall<'b>, executor::host::HostVmPrototype), RuntimeCallError> {
         // TODO: DRY :-/ this whole thing is messy
 
-        // Perform the call proof request.
+        let started_at = self.platform.now();
+
+        // If this call has been registered as hot, its call proof might have been prefetched.
+        let prefetched_call_proof = {
+            let guarded = self.guarded.lock().await;
+            if guarded.hot_calls_proofs.is_empty() {
+                None
+            } else {
+                let parameter = parameter_vectored
+                    .clone()
+                    .fold(Vec::new(), |mut acc, chunk| {
+                        acc.extend_from_slice(chunk.as_ref());
+                        acc
+                    });
+                guarded
+                    .hot_calls_proofs
+                    .get(&(self.hash, method.to_owned(), parameter))
+                    .cloned()
+            }
+        };
+
+        // Perform the call proof request, unless the proof has been prefetched.
         // Note that `guarded` is not locked.
         // TODO: there's no way to verify that the call proof is actually correct; we have to ban the peer and restart the whole call process if it turns out that it's not
         // TODO: also, an empty proof will be reported as an error right now, which is weird
-        let call_proof = self
-            .sync_service
-            .clone()
-            .call_proof_query(
-                self.block_number,
-                protocol::CallProofRequestConfig {
-                    block_hash: self.hash,
-                    method: method.into(),
-                    parameter_vectored: parameter_vectored.clone(),
-                },
-                total_attempts,
-                timeout_per_request,
-                max_parallel,
-            )
-            .await
-            .map_err(RuntimeCallError::CallProof);
-
-        let call_proof = call_proof.and_then(|call_proof| {
-            proof_decode::decode_and_verify_proof(proof_decode::Config {
-                proof: call_proof.decode().to_owned(), // TODO: to_owned() inefficiency, need some help from the networking to obtain the owned data
-            })
-            .map_err(RuntimeCallError::StorageRetrieval)
-        });
+        let call_proof = match prefetched_call_proof {
+            Some(call_proof) => Ok(call_proof),
+            None => self
+                .sync_service
+                .clone()
+                .call_proof_query(
+                    self.block_number,
+                    codec::CallProofRequestConfig {
+                        block_hash: self.hash,
+                        method: method.into(),
+                        parameter_vectored: parameter_vectored.clone(),
+                    },
+                    total_attempts,
+                    timeout_per_request,
+                    max_parallel,
+                    hedging_delay,
+                )
+                .await
+                .map(|call_proof| call_proof.decode().to_owned()), // TODO: to_owned() inefficiency, need some help from the networking to obtain the owned data
+        };
 
-        let (guarded, virtual_machine) = match self.runtime.runtime.as_ref() {
+        let (guarded, mut virtual_machine) = match self.runtime.runtime.as_ref() {
             Ok(r) => {
                 let mut lock = r.virtual_machine.lock().await;
                 let vm = lock.take().unwrap();
@@ -846,14 +981,253 @@ impl<TPlat: PlatformRef> RuntimeAccess<TPlat> {
             }
         };
 
+        let storage = match call_proof {
+            Ok(call_proof) => {
+                self.metrics
+                    .call_proof_size_bytes
+                    .record(u64::try_from(call_proof.len()).unwrap_or(u64::MAX));
+                proof_decode::decode_and_verify_proof(proof_decode::Config { proof: call_proof })
+                    .map(CallStorage::CallProof)
+                    .map_err(RuntimeCallError::StorageRetrieval)
+            }
+            Err(err) if err.is_request_too_large() => {
+                // The call proof request can't be sent, most likely because the call parameters
+                // are too large. Instead, the call is executed locally ahead of time in order to
+                // download the storage entries that it accesses.
+                // Note that the runtime is locked during this whole process.
+                let (vm, fetched) = self
+                    .fetch_storage_by_local_execution(
+                        method,
+                        parameter_vectored,
+                        virtual_machine,
+                        total_attempts,
+                        timeout_per_request,
+                        max_parallel,
+                    )
+                    .await;
+                virtual_machine = vm;
+                if let Ok(fetched) = &fetched {
+                    self.metrics.fallback_fetched_entries.record(
+                        u64::try_from(
+                            fetched.values.len() + fetched.closest_descendant_merkle_values.len(),
+                        )
+                        .unwrap_or(u64::MAX),
+                    );
+                }
+                fetched.map(CallStorage::Fetched)
+            }
+            Err(err) => Err(RuntimeCallError::CallProof(err)),
+        };
+
         let lock = RuntimeCall {
             guarded,
             block_state_root_hash: self.block_state_root_hash,
-            call_proof,
+            storage,
+            record_duration: Some(Box::new({
+                let platform = self.platform.clone();
+                let metrics = self.metrics.clone();
+                move || {
+                    metrics
+                        .call_duration_us
+                        .record(duration_to_micros(platform.now() - started_at));
+                }
+            })),
         };
 
         Ok((lock, virtual_machine))
     }
+
+    /// Executes the given call locally and downloads, one by one, the storage entries that it
+    /// accesses.
+    ///
+    /// This is used as a fallback when the call proof can't be requested from the network. The
+    /// API user later executes the call a second time using the downloaded entries.
+    ///
+    /// Errors that happen during the execution itself aren't reported, as the API user runs into
+    /// them when performing the call.
+    async fn fetch_storage_by_local_execution(
+        &self,
+        method: &str,
+        parameter_vectored: impl Iterator<Item = impl AsRef<[u8]>> + Clone,
+        virtual_machine: executor::host::HostVmPrototype,
+        total_attempts: u32,
+        timeout_per_request: Duration,
+        max_parallel: NonZeroU32,
+    ) -> (
+        executor::host::HostVmPrototype,
+        Result<FetchedStorage, RuntimeCallError>,
+    ) {
+        let mut fetched = FetchedStorage {
+            values: BTreeMap::new(),
+            closest_descendant_merkle_values: BTreeMap::new(),
+            state_version: virtual_machine
+                .runtime_version()
+                .decode()
+                .state_version
+                .unwrap_or(TrieEntryVersion::V0),
+        };
+
+        let mut call = match runtime_host::run(runtime_host::Config {
+            virtual_machine,
+            function_to_call: method,
+            parameter: parameter_vectored,
+            storage_main_trie_changes: Default::default(),
+            max_log_level: 0,
+            calculate_trie_changes: false,
+        }) {
+            Ok(call) => call,
+            Err((_, prototype)) => return (prototype, Ok(fetched)),
+        };
+
+        loop {
+            match call {
+                runtime_host::RuntimeHostVm::Finished(Ok(success)) => {
+                    return (success.virtual_machine.into_prototype(), Ok(fetched))
+                }
+                runtime_host::RuntimeHostVm::Finished(Err(error)) => {
+                    return (error.prototype, Ok(fetched))
+                }
+                runtime_host::RuntimeHostVm::StorageGet(get) => {
+                    // TODO: support child tries
+                    if get.child_trie().is_some() {
+                        return (
+                            runtime_host::RuntimeHostVm::StorageGet(get).into_prototype(),
+                            Err(RuntimeCallError::LocalExecutionUnsupported),
+                        );
+                    }
+
+                    let key = get.key().as_ref().to_vec();
+                    if !fetched.values.contains_key(&key) {
+                        let result = self
+                            .sync_service
+                            .clone()
+                            .storage_query(
+                                self.block_number,
+                                &self.hash,
+                                &self.block_state_root_hash,
+                                iter::once(sync_service::StorageRequestItem {
+                                    key: key.clone(),
+                                    ty: sync_service::StorageRequestItemTy::Value,
+                                }),
+                                total_attempts,
+                                timeout_per_request,
+                                max_parallel,
+                            )
+                            .await;
+
+                        let value = match result {
+                            Ok(items) => items
+                                .into_iter()
+                                .find_map(|item| match item {
+                                    sync_service::StorageResultItem::Value { value, .. } => {
+                                        Some(value)
+                                    }
+                                    _ => None,
+                                })
+                                .flatten(),
+                            Err(err) => {
+                                return (
+                                    runtime_host::RuntimeHostVm::StorageGet(get).into_prototype(),
+                                    Err(RuntimeCallError::StorageQuery(err)),
+                                );
+                            }
+                        };
+
+                        fetched.values.insert(key.clone(), value);
+                    }
+
+                    call = get.inject_value(
+                        fetched.values[&key]
+                            .as_ref()
+                            .map(|value| (iter::once(value), fetched.state_version)),
+                    );
+                }
+                runtime_host::RuntimeHostVm::ClosestDescendantMerkleValue(mv) => {
+                    let key = mv.key().collect::<Vec<_>>();
+
+                    // TODO: support child tries
+                    // The networking protocol only makes it possible to request keys made of
+                    // bytes, and thus an even number of nibbles.
+                    if mv.child_trie().is_some() || key.len() % 2 != 0 {
+                        return (
+                            runtime_host::RuntimeHostVm::ClosestDescendantMerkleValue(mv)
+                                .into_prototype(),
+                            Err(RuntimeCallError::LocalExecutionUnsupported),
+                        );
+                    }
+
+                    if !fetched.closest_descendant_merkle_values.contains_key(&key) {
+                        let result = self
+                            .sync_service
+                            .clone()
+                            .storage_query(
+                                self.block_number,
+                                &self.hash,
+                                &self.block_state_root_hash,
+                                iter::once(sync_service::StorageRequestItem {
+                                    key: trie::nibbles_to_bytes_truncate(key.iter().copied())
+                                        .collect(),
+                                    ty: sync_service::StorageRequestItemTy::ClosestDescendantMerkleValue,
+                                }),
+                                total_attempts,
+                                timeout_per_request,
+                                max_parallel,
+                            )
+                            .await;
+
+                        let merkle_value = match result {
+                            Ok(items) => items
+                                .into_iter()
+                                .find_map(|item| {
+                                    match item {
+                                    sync_service::StorageResultItem::ClosestDescendantMerkleValue {
+                                        closest_descendant_merkle_value,
+                                        ..
+                                    } => Some(closest_descendant_merkle_value),
+                                    _ => None,
+                                }
+                                })
+                                .flatten(),
+                            Err(err) => {
+                                return (
+                                    runtime_host::RuntimeHostVm::ClosestDescendantMerkleValue(mv)
+                                        .into_prototype(),
+                                    Err(RuntimeCallError::StorageQuery(err)),
+                                );
+                            }
+                        };
+
+                        fetched
+                            .closest_descendant_merkle_values
+                            .insert(key.clone(), merkle_value);
+                    }
+
+                    call = mv.inject_merkle_value(
+                        fetched.closest_descendant_merkle_values[&key].as_deref(),
+                    );
+                }
+                runtime_host::RuntimeHostVm::NextKey(nk) => {
+                    // TODO: support this, which requires downloading proofs of ranges of keys
+                    return (
+                        runtime_host::RuntimeHostVm::NextKey(nk).into_prototype(),
+                        Err(RuntimeCallError::LocalExecutionUnsupported),
+                    );
+                }
+                runtime_host::RuntimeHostVm::SignatureVerification(sig) => {
+                    call = sig.verify_and_resume();
+                }
+                runtime_host::RuntimeHostVm::OffchainStorageSet(req) => {
+                    call = req.resume();
+                }
+                runtime_host::RuntimeHostVm::Offchain(ctx) => {
+                    return (
+                        runtime_host::RuntimeHostVm::Offchain(ctx).into_prototype(),
+                        Ok(fetched),
+                    )
+                }
+            }
+        }
+    }
 }
 
 /// See [`RuntimeService::pinned_block_runtime_access`].
@@ -861,7 +1235,30 @@ impl<TPlat: PlatformRef> RuntimeAccess<TPlat> {
 pub struct RuntimeCall<'a> {
     guarded: MutexGuard<'a, Option<executor::host::HostVmPrototype>>,
     block_state_root_hash: [u8; 32],
-    call_proof: Result<trie::proof_decode::DecodedTrieProof<Vec<u8>>, RuntimeCallError>,
+    storage: Result<CallStorage, RuntimeCallError>,
+    /// Records the duration of the call in the metrics. Called when the call is unlocked.
+    /// Always `Some`, except after it has been called.
+    record_duration: Option<Box<dyn FnOnce() + Send + Sync + 'a>>,
+}
+
+/// See [`RuntimeCall::storage`].
+enum CallStorage {
+    /// Storage entries are found in a call proof downloaded from the network.
+    CallProof(trie::proof_decode::DecodedTrieProof<Vec<u8>>),
+    /// The call proof couldn't be requested. Instead, the storage entries accessed by the call
+    /// have been downloaded one by one by executing the call locally.
+    Fetched(FetchedStorage),
+}
+
+/// See [`CallStorage::Fetched`].
+struct FetchedStorage {
+    /// Storage values of the main trie, indexed by key.
+    values: BTreeMap<Vec<u8>, Option<Vec<u8>>>,
+    /// Merkle values of the closest descendants of keys of the main trie, indexed by key.
+    closest_descendant_merkle_values: BTreeMap<Vec<Nibble>, Option<Vec<u8>>>,
+    /// Trie entry version reported for all the values of [`FetchedStorage::values`]. The actual
+    /// version of each entry isn't known, and the state version of the runtime is used instead.
+    state_version: TrieEntryVersion,
 }
 
 impl<'a> RuntimeCall<'a> {
@@ -878,8 +1275,19 @@ impl<'a> RuntimeCall<'a> {
         child_trie: Option<&[u8]>,
         requested_key: &[u8],
     ) -> Result<Option<(&[u8], TrieEntryVersion)>, RuntimeCallError> {
-        let call_proof = match &self.call_proof {
-            Ok(p) => p,
+        let call_proof = match &self.storage {
+            Ok(CallStorage::CallProof(p)) => p,
+            Ok(CallStorage::Fetched(fetched)) => {
+                if child_trie.is_some() {
+                    return Err(RuntimeCallError::LocalExecutionUnsupported);
+                }
+                return match fetched.values.get(requested_key) {
+                    Some(value) => Ok(value
+                        .as_ref()
+                        .map(|value| (&value[..], fetched.state_version))),
+                    None => Err(RuntimeCallError::MissingFetchedEntry),
+                };
+            }
             Err(err) => return Err(err.clone()),
         };
 
@@ -922,8 +1330,9 @@ impl<'a> RuntimeCall<'a> {
         prefix: &[trie::Nibble],
         branch_nodes: bool,
     ) -> Result<Option<&'_ [trie::Nibble]>, RuntimeCallError> {
-        let call_proof = match &self.call_proof {
-            Ok(p) => p,
+        let call_proof = match &self.storage {
+            Ok(CallStorage::CallProof(p)) => p,
+            Ok(CallStorage::Fetched(_)) => return Err(RuntimeCallError::LocalExecutionUnsupported),
             Err(err) => return Err(err.clone()),
         };
 
@@ -959,8 +1368,17 @@ impl<'a> RuntimeCall<'a> {
         child_trie: Option<&[u8]>,
         key: &[trie::Nibble],
     ) -> Result<Option<&'_ [u8]>, RuntimeCallError> {
-        let call_proof = match &self.call_proof {
-            Ok(p) => p,
+        let call_proof = match &self.storage {
+            Ok(CallStorage::CallProof(p)) => p,
+            Ok(CallStorage::Fetched(fetched)) => {
+                if child_trie.is_some() {
+                    return Err(RuntimeCallError::LocalExecutionUnsupported);
+                }
+                return match fetched.closest_descendant_merkle_values.get(key) {
+                    Some(merkle_value) => Ok(merkle_value.as_deref()),
+                    None => Err(RuntimeCallError::MissingFetchedEntry),
+                };
+            }
             Err(err) => return Err(err.clone()),
         };
 
@@ -985,6 +1403,9 @@ impl<'a> RuntimeCall<'a> {
     pub fn unlock(mut self, vm: executor::host::HostVmPrototype) {
         debug_assert!(self.guarded.is_none());
         *self.guarded = Some(vm);
+        if let Some(record_duration) = self.record_duration.take() {
+            record_duration();
+        }
     }
 
     fn child_trie_root(
@@ -1018,6 +1439,132 @@ impl<'a> Drop for RuntimeCall<'a> {
     }
 }
 
+/// Return value of [`RuntimeService::runtime_call_metrics`].
+#[derive(Debug, Clone)]
+pub struct RuntimeCallMetrics {
+    /// Time, in microseconds, between the start of a runtime call and its end. This includes
+    /// the time needed to download the call proof.
+    pub call_duration_us: Histogram,
+
+    /// Time, in microseconds, needed to instantiate the virtual machine of a runtime.
+    pub vm_instantiation_duration_us: Histogram,
+
+    /// Size, in bytes, of the call proofs downloaded from the network.
+    pub call_proof_size_bytes: Histogram,
+
+    /// Number of storage entries downloaded one by one when a call proof can't be requested and
+    /// the call is instead executed locally ahead of time.
+    pub fallback_fetched_entries: Histogram,
+}
+
+/// Distribution of the values of a metric.
+#[derive(Debug, Clone)]
+pub struct Histogram {
+    /// For each bucket, the inclusive upper bound of the values it contains and the number of
+    /// values that have fallen into it. Buckets are ordered by increasing upper bound. The upper
+    /// bound of the last bucket is `None`, meaning that it is unbounded.
+    pub buckets: Vec<(Option<u64>, u64)>,
+
+    /// Total number of values.
+    pub count: u64,
+
+    /// Sum of all the values.
+    pub sum: u64,
+}
+
+/// See [`RuntimeService::metrics`].
+struct Metrics {
+    /// See [`RuntimeCallMetrics::call_duration_us`].
+    call_duration_us: AtomicHistogram,
+    /// See [`RuntimeCallMetrics::vm_instantiation_duration_us`].
+    vm_instantiation_duration_us: AtomicHistogram,
+    /// See [`RuntimeCallMetrics::call_proof_size_bytes`].
+    call_proof_size_bytes: AtomicHistogram,
+    /// See [`RuntimeCallMetrics::fallback_fetched_entries`].
+    fallback_fetched_entries: AtomicHistogram,
+}
+
+impl Metrics {
+    fn new() -> Self {
+        // TODO: the buckets bounds are arbitrary
+        Metrics {
+            call_duration_us: AtomicHistogram::new(&[
+                1_000, 5_000, 10_000, 50_000, 100_000, 500_000, 1_000_000, 5_000_000, 10_000_000,
+                30_000_000,
+            ]),
+            vm_instantiation_duration_us: AtomicHistogram::new(&[
+                10_000, 50_000, 100_000, 250_000, 500_000, 1_000_000, 2_500_000, 5_000_000,
+                10_000_000,
+            ]),
+            call_proof_size_bytes: AtomicHistogram::new(&[
+                1024,
+                4 * 1024,
+                16 * 1024,
+                64 * 1024,
+                256 * 1024,
+                1024 * 1024,
+                4 * 1024 * 1024,
+            ]),
+            fallback_fetched_entries: AtomicHistogram::new(&[1, 2, 5, 10, 20, 50, 100, 200, 500]),
+        }
+    }
+}
+
+/// Histogram that can be updated without locking.
+struct AtomicHistogram {
+    /// Inclusive upper bound of each bucket, except for the last bucket which is unbounded.
+    bounds: &'static [u64],
+    /// Number of values in each bucket. Contains one more element than
+    /// [`AtomicHistogram::bounds`].
+    buckets: Vec<AtomicU64>,
+    /// See [`Histogram::count`].
+    count: AtomicU64,
+    /// See [`Histogram::sum`].
+    sum: AtomicU64,
+}
+
+impl AtomicHistogram {
+    fn new(bounds: &'static [u64]) -> Self {
+        debug_assert!(bounds.windows(2).all(|w| w[0] < w[1]));
+        AtomicHistogram {
+            bounds,
+            buckets: (0..=bounds.len()).map(|_| AtomicU64::new(0)).collect(),
+            count: AtomicU64::new(0),
+            sum: AtomicU64::new(0),
+        }
+    }
+
+    fn record(&self, value: u64) {
+        let bucket = self
+            .bounds
+            .iter()
+            .position(|bound| value <= *bound)
+            .unwrap_or(self.bounds.len());
+        self.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.sum.fetch_add(value, Ordering::Relaxed);
+    }
+
+    fn snapshot(&self) -> Histogram {
+        Histogram {
+            buckets: self
+                .bounds
+                .iter()
+                .map(|bound| Some(*bound))
+                .chain(iter::once(None))
+                .zip(self.buckets.iter())
+                .map(|(bound, count)| (bound, count.load(Ordering::Relaxed)))
+                .collect(),
+            count: self.count.load(Ordering::Relaxed),
+            sum: self.sum.load(Ordering::Relaxed),
+        }
+    }
+}
+
+fn duration_to_micros(duration: Duration) -> u64 {
+    u64::try_from(duration.as_micros()).unwrap_or(u64::MAX)
+}
+
 /// Error that can happen when calling a runtime function.
 // TODO: clean up these errors
 #[derive(Debug, Clone, derive_more::Display)]
@@ -1040,6 +1587,14 @@ pub enum RuntimeCallError {
     /// Error while querying the storage of the block.
     #[display(fmt = "Error while querying block storage: {_0}")]
     StorageQuery(sync_service::StorageQueryError),
+    /// The call proof couldn't be requested because the request is too large, and the call
+    /// accesses the storage in a way that isn't supported when executing it locally.
+    #[display(fmt = "Call proof request is too large and the call can't be executed locally")]
+    LocalExecutionUnsupported,
+    /// The call has accessed a storage entry that wasn't accessed when it was executed locally
+    /// ahead of time. This indicates that the runtime isn't deterministic.
+    #[display(fmt = "Storage entry accessed by the call hasn't been downloaded")]
+    MissingFetchedEntry,
 }
 
 impl RuntimeCallError {
@@ -1053,6 +1608,8 @@ impl RuntimeCallError {
             RuntimeCallError::InvalidChildTrieRoot => false,
             RuntimeCallError::CallProof(err) => err.is_network_problem(),
             RuntimeCallError::StorageQuery(err) => err.is_network_problem(),
+            RuntimeCallError::LocalExecutionUnsupported => false,
+            RuntimeCallError::MissingFetchedEntry => false,
         }
     }
 }
@@ -1082,6 +1639,14 @@ struct Guarded<TPlat: PlatformRef> {
     /// after the latest best block update.
     best_near_head_of_chain: bool,
 
+    /// Latest value sent to [`Guarded::near_head_of_chain_subscriptions`].
+    reported_near_head_of_chain: bool,
+
+    /// List of senders that get notified when the value of
+    /// [`Guarded::reported_near_head_of_chain`] changes.
+    /// See [`RuntimeService::subscribe_near_head_of_chain`].
+    near_head_of_chain_subscriptions: Vec<mpsc::Sender<bool>>,
+
     /// List of runtimes referenced by the tree in [`GuardedInner`] and by
     /// [`GuardedInner::FinalizedBlockRuntimeKnown::pinned_blocks`].
     ///
@@ -1092,11 +1657,54 @@ struct Guarded<TPlat: PlatformRef> {
     /// the elements.
     runtimes: slab::Slab<Weak<Runtime>>,
 
+    /// See [`Config::max_memory_pages`].
+    max_memory_pages: Option<executor::host::HeapPages>,
+
+    /// See [`Config::best_block_policy`].
+    best_block_policy: async_tree::BestBlockPolicy,
+
+    /// List of calls registered with [`RuntimeService::register_hot_call`]. Each call consists
+    /// of a function name and its parameter.
+    hot_calls: Vec<(String, Vec<u8>)>,
+
+    /// Call proofs prefetched for the calls of [`Guarded::hot_calls`], indexed by block hash,
+    /// function name, and parameter.
+    hot_calls_proofs: BTreeMap<([u8; 32], String, Vec<u8>), Vec<u8>>,
+
+    /// Blocks found in [`Guarded::hot_calls_proofs`], from the oldest to the newest. Can't
+    /// contain more than [`HOT_CALLS_PROOFS_MAX_BLOCKS`] elements.
+    hot_calls_proofs_blocks: VecDeque<[u8; 32]>,
+
     /// Tree of blocks received from the sync service. Keeps track of which block has been
     /// reported to the outer API.
     tree: GuardedInner<TPlat>,
 }
 
+impl<TPlat: PlatformRef> Guarded<TPlat> {
+    /// Updates [`Guarded::best_near_head_of_chain`] and notifies the subscriptions if the
+    /// value of [`RuntimeService::is_near_head_of_chain_heuristic`] changes as a result.
+    ///
+    /// `sync_service_near_head_of_chain` must be the latest value returned by
+    /// [`sync_service::SyncService::is_near_head_of_chain_heuristic`].
+    fn set_best_near_head_of_chain(
+        &mut self,
+        best_near_head_of_chain: bool,
+        sync_service_near_head_of_chain: bool,
+    ) {
+        self.best_near_head_of_chain = best_near_head_of_chain;
+
+        // Must be kept in sync with the free function `is_near_head_of_chain_heuristic`.
+        let near_head_of_chain = sync_service_near_head_of_chain && best_near_head_of_chain;
+        if near_head_of_chain == self.reported_near_head_of_chain {
+            return;
+        }
+
+        self.reported_near_head_of_chain = near_head_of_chain;
+        self.near_head_of_chain_subscriptions
+            .retain_mut(|sender| sender.try_send(near_head_of_chain).is_ok());
+    }
+}
+
 enum GuardedInner<TPlat: PlatformRef> {
     FinalizedBlockRuntimeKnown {
         /// Tree of blocks. Holds the state of the download of everything. Always `Some` when the
@@ -1181,18 +1789,20 @@ struct Block {
 }
 
 async fn run_background<TPlat: PlatformRef>(
-    log_target: String,
+    log_target: util::LogTarget,
     platform: TPlat,
     sync_service: Arc<sync_service::SyncService<TPlat>>,
     guarded: Arc<Mutex<Guarded<TPlat>>>,
+    metrics: Arc<Metrics>,
 ) {
     loop {
         // The buffer size should be large enough so that, if the CPU is busy, it doesn't
         // become full before the execution of the runtime service resumes.
         let subscription = sync_service.subscribe_all(32, true).await;
 
-        log::debug!(
-            target: &log_target,
+        util::log!(
+            Debug,
+            &log_target,
             "Worker <= Reset(finalized_block: {})",
             HashDisplay(&header::hash_from_scale_encoded_header(
                 &subscription.finalized_block_scale_encoded_header
@@ -1251,16 +1861,18 @@ async fn run_background<TPlat: PlatformRef>(
 
                 match &runtime.runtime {
                     Ok(runtime) => {
-                        log::info!(
-                            target: &log_target,
+                        util::log!(
+                            Info,
+                            &log_target,
                             "Finalized block runtime ready. Spec version: {}. Size of `:code`: {}.",
                             runtime.runtime_spec.decode().spec_version,
                             BytesDisplay(storage_code_len)
                         );
                     }
                     Err(error) => {
-                        log::warn!(
-                            target: &log_target,
+                        util::log!(
+                            Warn,
+                            &log_target,
                             "Erroenous finalized block runtime. Size of `:code`: {}.\nError: {}\n\
                             This indicates an incompatibility between smoldot and the chain.",
                             BytesDisplay(storage_code_len),
@@ -1269,8 +1881,9 @@ async fn run_background<TPlat: PlatformRef>(
                     }
                 }
 
-                log::debug!(
-                    target: &log_target,
+                util::log!(
+                    Debug,
+                    &log_target,
                     "Worker => RuntimeKnown(finalized_hash={})",
                     HashDisplay(&finalized_block_hash)
                 );
@@ -1295,6 +1908,7 @@ async fn run_background<TPlat: PlatformRef>(
                                 finalized_async_user_data: runtime,
                                 retry_after_failed: Duration::from_secs(10), // TODO: hardcoded
                                 blocks_capacity: 32,
+                                best_block_policy: lock.best_block_policy,
                             });
 
                         for block in subscription.non_finalized_blocks_ancestry_order {
@@ -1341,6 +1955,7 @@ async fn run_background<TPlat: PlatformRef>(
                             finalized_async_user_data: None,
                             retry_after_failed: Duration::from_secs(10), // TODO: hardcoded
                             blocks_capacity: 32,
+                            best_block_policy: lock.best_block_policy,
                         });
                         let node_index = tree.input_insert_block(
                             Block {
@@ -1392,6 +2007,7 @@ async fn run_background<TPlat: PlatformRef>(
             platform: platform.clone(),
             sync_service: sync_service.clone(),
             guarded: guarded.clone(),
+            metrics: metrics.clone(),
             blocks_stream: subscription.new_blocks.boxed(),
             wake_up_new_necessary_download: future::pending().boxed().fuse(),
             runtime_downloads: stream::FuturesUnordered::new(),
@@ -1409,8 +2025,8 @@ async fn run_background<TPlat: PlatformRef>(
                     match notification {
                         None => break, // Break out of the inner loop in order to reset the background.
                         Some(sync_service::Notification::Block(new_block)) => {
-                            log::debug!(
-                                target: &log_target,
+                            util::log!(Debug,
+                                &log_target,
                                 "Worker <= InputNewBlock(hash={}, parent={}, is_new_best={})",
                                 HashDisplay(&header::hash_from_scale_encoded_header(&new_block.scale_encoded_header)),
                                 HashDisplay(&new_block.parent_hash),
@@ -1423,7 +2039,7 @@ async fn run_background<TPlat: PlatformRef>(
                             let guarded = &mut *guarded;
                             // TODO: note that this code is never reached for parachains
                             if new_block.is_new_best {
-                                guarded.best_near_head_of_chain = near_head_of_chain;
+                                guarded.set_best_near_head_of_chain(near_head_of_chain, near_head_of_chain);
                             }
 
                             let same_runtime_as_parent = same_runtime_as_parent(&new_block.scale_encoded_header, sync_service.block_number_bytes());
@@ -1455,8 +2071,8 @@ async fn run_background<TPlat: PlatformRef>(
                             background.advance_and_notify_subscribers(guarded);
                         },
                         Some(sync_service::Notification::Finalized { hash, best_block_hash }) => {
-                            log::debug!(
-                                target: &log_target,
+                            util::log!(Debug,
+                                &log_target,
                                 "Worker <= InputFinalized(hash={}, best={})",
                                 HashDisplay(&hash), HashDisplay(&best_block_hash)
                             );
@@ -1464,8 +2080,8 @@ async fn run_background<TPlat: PlatformRef>(
                             background.finalize(hash, best_block_hash).await;
                         }
                         Some(sync_service::Notification::BestBlockChanged { hash }) => {
-                            log::debug!(
-                                target: &log_target,
+                            util::log!(Debug,
+                                &log_target,
                                 "Worker <= BestBlockChanged(hash={})",
                                 HashDisplay(&hash)
                             );
@@ -1474,7 +2090,7 @@ async fn run_background<TPlat: PlatformRef>(
 
                             let mut guarded = background.guarded.lock().await;
                             let guarded = &mut *guarded;
-                            guarded.best_near_head_of_chain = near_head_of_chain;
+                            guarded.set_best_near_head_of_chain(near_head_of_chain, near_head_of_chain);
 
                             match &mut guarded.tree {
                                 GuardedInner::FinalizedBlockRuntimeKnown {
@@ -1515,28 +2131,32 @@ async fn run_background<TPlat: PlatformRef>(
 
                     match download_result {
                         Ok((storage_code, storage_heap_pages, code_merkle_value, closest_ancestor_excluding)) => {
-                            log::debug!(
-                                target: &log_target,
+                            util::log!(Debug,
+                                &log_target,
                                 "Worker <= SuccessfulDownload(blocks=[{}])",
                                 concerned_blocks
                             );
 
-                            // TODO: the line below is a complete hack; the code that updates this value is never reached for parachains, and as such the line below is here to update this field
-                            guarded.best_near_head_of_chain = true;
+                            // The sync service is queried without holding the lock, in order to
+                            // not block the other users of `guarded` while waiting.
                             drop(guarded);
+                            let near_head_of_chain = background.sync_service.is_near_head_of_chain_heuristic().await;
+
+                            // TODO: the line below is a complete hack; the code that updates this value is never reached for parachains, and as such the line below is here to update this field
+                            background.guarded.lock().await.set_best_near_head_of_chain(true, near_head_of_chain);
 
                             background.runtime_download_finished(async_op_id, storage_code, storage_heap_pages, code_merkle_value, closest_ancestor_excluding).await;
                         }
                         Err(error) => {
-                            log::debug!(
-                                target: &log_target,
+                            util::log!(Debug,
+                                &log_target,
                                 "Worker <= FailedDownload(blocks=[{}], error={:?})",
                                 concerned_blocks,
                                 error
                             );
                             if !error.is_network_problem() {
-                                log::warn!(
-                                    target: &log_target,
+                                util::log!(Warn,
+                                    &log_target,
                                     "Failed to download :code and :heappages of blocks {}: {}",
                                     concerned_blocks,
                                     error
@@ -1584,8 +2204,12 @@ impl RuntimeDownloadError {
     }
 }
 
+/// Maximum number of blocks whose hot calls proofs are kept in [`Guarded::hot_calls_proofs`].
+/// Proofs of the oldest blocks are evicted first.
+const HOT_CALLS_PROOFS_MAX_BLOCKS: usize = 4;
+
 struct Background<TPlat: PlatformRef> {
-    log_target: String,
+    log_target: util::LogTarget,
 
     /// See [`Config::platform`].
     platform: TPlat,
@@ -1594,6 +2218,9 @@ struct Background<TPlat: PlatformRef> {
 
     guarded: Arc<Mutex<Guarded<TPlat>>>,
 
+    /// See [`RuntimeService::metrics`].
+    metrics: Arc<Metrics>,
+
     /// Stream of notifications coming from the sync service.
     blocks_stream: Pin<Box<dyn Stream<Item = sync_service::Notification> + Send>>,
 
@@ -1647,22 +2274,35 @@ impl<TPlat: PlatformRef> Background<TPlat> {
         let runtime = if let Some(existing_runtime) = existing_runtime {
             existing_runtime
         } else {
-            let runtime = SuccessfulRuntime::from_storage(&storage_code, &storage_heap_pages).await;
+            let runtime = SuccessfulRuntime::from_storage(
+                &self.platform,
+                &self.metrics,
+                &storage_code,
+                &storage_heap_pages,
+                guarded.max_memory_pages,
+            )
+            .await;
             match &runtime {
                 Ok(runtime) => {
-                    log::info!(
-                        target: &self.log_target,
+                    util::log!(
+                        Info,
+                        &self.log_target,
                         "Successfully compiled runtime. Spec version: {}. Size of `:code`: {}.",
                         runtime.runtime_spec.decode().spec_version,
-                        BytesDisplay(u64::try_from(storage_code.as_ref().map_or(0, |v| v.len())).unwrap())
+                        BytesDisplay(
+                            u64::try_from(storage_code.as_ref().map_or(0, |v| v.len())).unwrap()
+                        )
                     );
                 }
                 Err(error) => {
-                    log::warn!(
-                        target: &self.log_target,
+                    util::log!(
+                        Warn,
+                        &self.log_target,
                         "Failed to compile runtime. Size of `:code`: {}.\nError: {}\n\
                         This indicates an incompatibility between smoldot and the chain.",
-                        BytesDisplay(u64::try_from(storage_code.as_ref().map_or(0, |v| v.len())).unwrap()),
+                        BytesDisplay(
+                            u64::try_from(storage_code.as_ref().map_or(0, |v| v.len())).unwrap()
+                        ),
                         error
                     );
                 }
@@ -1693,6 +2333,81 @@ impl<TPlat: PlatformRef> Background<TPlat> {
         self.advance_and_notify_subscribers(&mut guarded);
     }
 
+    /// Spawns a task that downloads the call proofs of the given hot calls against the given
+    /// block, and stores them in [`Guarded::hot_calls_proofs`].
+    fn prefetch_hot_calls(
+        &self,
+        hot_calls: &[(String, Vec<u8>)],
+        block_hash: [u8; 32],
+        block_number: u64,
+    ) {
+        if hot_calls.is_empty() {
+            return;
+        }
+
+        let hot_calls = hot_calls.to_vec();
+        let sync_service = self.sync_service.clone();
+        let guarded = self.guarded.clone();
+        let log_target = self.log_target.clone();
+
+        self.platform.spawn_task(
+            format!("{}-hot-calls-prefetch", self.log_target).into(),
+            async move {
+                let mut call_proofs = Vec::with_capacity(hot_calls.len());
+                for (method, parameter) in hot_calls {
+                    // Failing to prefetch a call proof isn't a problem, as the proof is
+                    // simply requested again when the call is actually performed.
+                    let result = sync_service
+                        .clone()
+                        .call_proof_query(
+                            block_number,
+                            codec::CallProofRequestConfig {
+                                block_hash,
+                                method: (&*method).into(),
+                                parameter_vectored: iter::once(&parameter),
+                            },
+                            3,
+                            Duration::from_secs(8),
+                            NonZeroU32::new(1).unwrap(),
+                            None,
+                        )
+                        .await;
+                    match result {
+                        Ok(call_proof) => {
+                            let call_proof = call_proof.decode().to_owned();
+                            call_proofs.push(((block_hash, method, parameter), call_proof));
+                        }
+                        Err(error) => {
+                            util::log!(
+                                Debug,
+                                &log_target,
+                                "HotCallPrefetchError(block={}, method={}, error={})",
+                                HashDisplay(&block_hash),
+                                method,
+                                error
+                            );
+                        }
+                    }
+                }
+
+                let mut guarded = guarded.lock().await;
+                let guarded = &mut *guarded;
+
+                if !guarded.hot_calls_proofs_blocks.contains(&block_hash) {
+                    if guarded.hot_calls_proofs_blocks.len() >= HOT_CALLS_PROOFS_MAX_BLOCKS {
+                        let evicted = guarded.hot_calls_proofs_blocks.pop_front().unwrap();
+                        guarded
+                            .hot_calls_proofs
+                            .retain(|(block_hash, _, _), _| *block_hash != evicted);
+                    }
+                    guarded.hot_calls_proofs_blocks.push_back(block_hash);
+                }
+
+                guarded.hot_calls_proofs.extend(call_proofs);
+            },
+        );
+    }
+
     fn advance_and_notify_subscribers(&self, guarded: &mut Guarded<TPlat>) {
         loop {
             match &mut guarded.tree {
@@ -1714,10 +2429,12 @@ impl<TPlat: PlatformRef> Background<TPlat> {
                         let best_block_hash = best_block_index
                             .map_or(finalized_block.hash, |idx| tree.block_user_data(idx).hash);
 
-                        log::debug!(
-                            target: &self.log_target,
+                        util::log!(
+                            Debug,
+                            &self.log_target,
                             "Worker => OutputFinalized(hash={}, best={})",
-                            HashDisplay(&finalized_block.hash), HashDisplay(&best_block_hash)
+                            HashDisplay(&finalized_block.hash),
+                            HashDisplay(&best_block_hash)
                         );
 
                         // The finalization might cause some runtimes in the list of runtimes
@@ -1796,13 +2513,18 @@ impl<TPlat: PlatformRef> Background<TPlat> {
                                 tree.block_async_user_data(idx).unwrap().clone()
                             });
 
-                        log::debug!(
-                            target: &self.log_target,
+                        util::log!(
+                            Debug,
+                            &self.log_target,
                             "Worker => OutputNewBlock(hash={}, is_new_best={})",
                             HashDisplay(&tree.block_user_data(block_index).hash),
                             is_new_best
                         );
 
+                        if is_new_best {
+                            self.prefetch_hot_calls(&guarded.hot_calls, block_hash, block_number);
+                        }
+
                         let notif = Notification::Block(BlockNotification {
                             parent_hash: tree
                                 .parent(block_index)
@@ -1852,16 +2574,25 @@ impl<TPlat: PlatformRef> Background<TPlat> {
                         }
                     }
                     Some(async_tree::OutputUpdate::BestBlockChanged { best_block_index }) => {
-                        let hash = best_block_index
-                            .map_or(&*finalized_block, |idx| tree.block_user_data(idx))
-                            .hash;
+                        let best_block = best_block_index
+                            .map_or(&*finalized_block, |idx| tree.block_user_data(idx));
+                        let hash = best_block.hash;
 
-                        log::debug!(
-                            target: &self.log_target,
+                        util::log!(
+                            Debug,
+                            &self.log_target,
                             "Worker => OutputBestBlockChanged(hash={})",
                             HashDisplay(&hash),
                         );
 
+                        let best_block_number = header::decode(
+                            &best_block.scale_encoded_header,
+                            self.sync_service.block_number_bytes(),
+                        )
+                        .unwrap()
+                        .number;
+                        self.prefetch_hot_calls(&guarded.hot_calls, hash, best_block_number);
+
                         let notif = Notification::BestBlockChanged { hash };
 
                         let mut to_remove = Vec::new();
@@ -1901,10 +2632,12 @@ impl<TPlat: PlatformRef> Background<TPlat> {
 
                         let best_block_hash = best_block_index
                             .map_or(new_finalized.hash, |idx| tree.block_user_data(idx).hash);
-                        log::debug!(
-                            target: &self.log_target,
+                        util::log!(
+                            Debug,
+                            &self.log_target,
                             "Worker => RuntimeKnown(finalized_hash={}, best={})",
-                            HashDisplay(&new_finalized.hash), HashDisplay(&best_block_hash)
+                            HashDisplay(&new_finalized.hash),
+                            HashDisplay(&best_block_hash)
                         );
 
                         // Substitute `tree` with a dummy empty tree just in order to extract
@@ -1918,6 +2651,7 @@ impl<TPlat: PlatformRef> Background<TPlat> {
                                 finalized_async_user_data: None,
                                 retry_after_failed: Duration::new(0, 0),
                                 blocks_capacity: 0,
+                                best_block_policy: async_tree::BestBlockPolicy::FollowInput,
                             }),
                         )
                         .map_async_op_user_data(|runtime_index| runtime_index.unwrap());
@@ -1975,8 +2709,9 @@ impl<TPlat: PlatformRef> Background<TPlat> {
                 }
             };
 
-            log::debug!(
-                target: &self.log_target,
+            util::log!(
+                Debug,
+                &self.log_target,
                 "Worker => NewDownload(block={})",
                 HashDisplay(&download_params.block_user_data.hash)
             );
@@ -2078,8 +2813,8 @@ impl<TPlat: PlatformRef> Background<TPlat> {
                         })
                     }
                     Err(error) => {
-                        log::warn!(
-                            target: &self.log_target,
+                        util::log!(Warn,
+                            &self.log_target,
                             "Failed to decode header from sync service: {}", error
                         );
 
@@ -2181,13 +2916,29 @@ struct SuccessfulRuntime {
 }
 
 impl SuccessfulRuntime {
-    async fn from_storage(
+    async fn from_storage<TPlat: PlatformRef>(
+        platform: &TPlat,
+        metrics: &Metrics,
         code: &Option<Vec<u8>>,
         heap_pages: &Option<Vec<u8>>,
+        max_memory_pages: Option<executor::host::HeapPages>,
     ) -> Result<Self, RuntimeError> {
         // Since compiling the runtime is a CPU-intensive operation, we yield once before.
         futures_lite::future::yield_now().await;
 
+        let started_at = platform.now();
+        let result = Self::instantiate(code, heap_pages, max_memory_pages);
+        metrics
+            .vm_instantiation_duration_us
+            .record(duration_to_micros(platform.now() - started_at));
+        result
+    }
+
+    fn instantiate(
+        code: &Option<Vec<u8>>,
+        heap_pages: &Option<Vec<u8>>,
+        max_memory_pages: Option<executor::host::HeapPages>,
+    ) -> Result<Self, RuntimeError> {
         // Parameters for `HostVmPrototype::new`.
         let module = code.as_ref().ok_or(RuntimeError::CodeNotFound)?;
         let heap_pages = executor::storage_heap_pages_to_value(heap_pages.as_deref())
@@ -2205,11 +2956,12 @@ impl SuccessfulRuntime {
             exec_hint,
             allow_unresolved_imports: false,
         }) {
-            Ok(vm) => {
+            Ok(mut vm) => {
+                vm.set_max_memory_pages(max_memory_pages);
                 return Ok(SuccessfulRuntime {
                     runtime_spec: vm.runtime_version().clone(),
                     virtual_machine: Mutex::new(Some(vm)),
-                })
+                });
             }
             Err(executor::host::NewErr::VirtualMachine(
                 executor::vm::NewErr::UnresolvedFunctionImport {
@@ -2223,7 +2975,8 @@ impl SuccessfulRuntime {
                     exec_hint,
                     allow_unresolved_imports: true,
                 }) {
-                    Ok(vm) => {
+                    Ok(mut vm) => {
+                        vm.set_max_memory_pages(max_memory_pages);
                         log::warn!(
                             "Unresolved host function in runtime: `{}`:`{}`. Smoldot might \
                             encounter errors later on. Please report this issue in \
@@ -26,19 +26,38 @@
 //!
 //! Use [`SyncService::subscribe_all`] to get notified about updates to the state of the chain.
 
-use crate::{network_service, platform::PlatformRef, runtime_service};
-
-use alloc::{borrow::ToOwned as _, boxed::Box, format, string::String, sync::Arc, vec::Vec};
-use core::{cmp, fmt, future::Future, mem, num::NonZeroU32, pin::Pin, time::Duration};
+use crate::{network_service, platform::PlatformRef, runtime_service, util};
+
+use alloc::{
+    borrow::ToOwned as _,
+    boxed::Box,
+    format,
+    string::{String, ToString as _},
+    sync::Arc,
+    vec::Vec,
+};
+use async_lock::Mutex;
+use core::{
+    cmp, fmt,
+    future::Future,
+    mem,
+    num::{NonZeroU32, NonZeroU64, NonZeroUsize},
+    pin::Pin,
+    time::Duration,
+};
 use futures_channel::oneshot;
-use futures_lite::stream;
+use futures_lite::{stream, FutureExt as _};
+use futures_util::{future, stream::FuturesUnordered, StreamExt as _};
 use rand::seq::IteratorRandom as _;
 use rand_chacha::rand_core::SeedableRng as _;
 use smoldot::{
     chain,
     executor::host,
+    finality::justification,
+    header,
+    informant::{BytesDisplay, HashDisplay},
     libp2p::PeerId,
-    network::{protocol, service},
+    network::{codec, service},
     trie::{self, prefix_proof, proof_decode, Nibble},
 };
 
@@ -53,6 +72,9 @@ pub struct Config<TPlat: PlatformRef> {
     /// >           have been filtered out from this name.
     pub log_name: String,
 
+    /// Maximum level of the logs emitted by this service.
+    pub log_max_level: log::LevelFilter,
+
     /// Number of bytes of the block number in the networking protocol.
     pub block_number_bytes: usize,
 
@@ -94,6 +116,39 @@ pub struct ConfigRelayChain {
     /// instead of downloading it. If the hint doesn't match, an extra round-trip will be needed,
     /// but if the hint matches it saves a big download.
     pub runtime_code_hint: Option<ConfigRelayChainRuntimeCodeHint>,
+
+    /// If `true`, after a GrandPa warp sync has finished, the headers of the blocks between the
+    /// block found in [`ConfigRelayChain::chain_information`] and the block the warp sync has
+    /// reached are downloaded in the background.
+    ///
+    /// This download is performed with a low priority, meaning that it only happens when no
+    /// other request needs to be started towards peers.
+    ///
+    /// > **Note**: Downloaded headers are kept in memory. Enabling this option on a chain with
+    /// >           a large number of blocks between the checkpoint and the head of the chain
+    /// >           can lead to a high memory usage.
+    pub gap_sync: bool,
+
+    /// If `Some`, and if [`ConfigRelayChain::chain_information`] indicates that the chain
+    /// doesn't have any finality mechanism, the block of the best chain that is this number of
+    /// blocks below the best block is automatically finalized whenever the best block changes.
+    pub pseudo_finality_depth: Option<NonZeroU64>,
+
+    /// List of hashes of blocks that must always be considered as invalid, as found in the
+    /// chain specification.
+    pub bad_blocks: Vec<[u8; 32]>,
+
+    /// List of block numbers and hashes of blocks that are known to be part of the canonical
+    /// chain, as found in the chain specification.
+    pub fork_blocks: Vec<(u64, [u8; 32])>,
+
+    /// List of SCALE-encoded headers of ancestors of the finalized block found in
+    /// [`ConfigRelayChain::chain_information`], for example coming from a snapshot.
+    ///
+    /// Only the headers that form an uninterrupted chain ending at the parent of the finalized
+    /// block are kept. The others are silently ignored. The headers that are kept can be
+    /// retrieved with [`SyncService::gap_sync_header`].
+    pub recent_headers: Vec<Vec<u8>>,
 }
 
 /// See [`ConfigRelayChain::runtime_code_hint`].
@@ -130,6 +185,15 @@ pub struct ConfigParachain<TPlat: PlatformRef> {
     pub para_id: u32,
 }
 
+/// Maximum number of bytes of keys and values stored in the cache of the results of
+/// [`SyncService::storage_query`].
+const STORAGE_QUERY_CACHE_MAX_BYTES: usize = 4 * 1024 * 1024;
+
+/// Storage values whose size is above this threshold aren't inserted in the cache of the results
+/// of [`SyncService::storage_query`], in order to not evict all the other entries for the sake
+/// of a value, such as the runtime code, that is rarely queried multiple times.
+const STORAGE_QUERY_CACHE_MAX_VALUE_BYTES: usize = 128 * 1024;
+
 /// Identifier for a blocks request to be performed.
 #[derive(Debug, Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash)]
 pub struct BlocksRequestId(usize);
@@ -147,13 +211,101 @@ pub struct SyncService<TPlat: PlatformRef> {
     network_chain_id: network_service::ChainId,
     /// See [`Config::block_number_bytes`].
     block_number_bytes: usize,
+
+    /// Target to use for the logs of the foreground.
+    log_target: util::LogTarget,
+
+    /// Storage values that have been verified in the past by [`SyncService::storage_query`].
+    storage_query_cache: Mutex<StorageQueryCache>,
+
+    /// State retention of the peers, as deduced from the outcome of the storage and call proof
+    /// requests concerning blocks older than [`ASSUMED_STATE_PRUNING_DEPTH`].
+    ///
+    /// Entries are evicted in a least-recently-used fashion in order to bound the memory usage.
+    peers_state_retention: Mutex<lru::LruCache<PeerId, PeerStateRetention, util::SipHasherBuild>>,
+
+    /// SCALE-encoded GrandPa justifications that have been verified in the past by
+    /// [`SyncService::finality_proof_query`], indexed by block hash.
+    finality_proofs_cache: Mutex<lru::LruCache<[u8; 32], Vec<u8>, util::SipHasherBuild>>,
+}
+
+/// Number of blocks below the head of the chain whose storage is assumed to be kept by all full
+/// nodes. This matches the default state pruning setting of Substrate.
+const ASSUMED_STATE_PRUNING_DEPTH: u64 = 256;
+
+/// See [`SyncService::storage_query_cache`].
+struct StorageQueryCache {
+    /// Storage values, indexed by block hash and key. Values are `None` if the entry is known to
+    /// not exist in the storage.
+    ///
+    /// Entries are evicted in a least-recently-used fashion, which in practice means that the
+    /// values of blocks that are no longer near the head of the chain are evicted first.
+    entries: lru::LruCache<StorageQueryCacheKey, Option<Vec<u8>>, util::SipHasherBuild>,
+
+    /// Sum of the sizes of the keys and values of [`StorageQueryCache::entries`]. Always inferior
+    /// or equal to [`STORAGE_QUERY_CACHE_MAX_BYTES`].
+    size_bytes: usize,
+
+    /// Number of requested items that have been found in the cache since the creation of the
+    /// service.
+    hits: u64,
+
+    /// Number of requested items that could have been found in the cache but weren't since the
+    /// creation of the service.
+    misses: u64,
+}
+
+/// Block hash and key of an entry of [`StorageQueryCache::entries`].
+type StorageQueryCacheKey = ([u8; 32], Vec<u8>);
+
+impl StorageQueryCache {
+    /// Size, in bytes, that an entry accounts for in [`StorageQueryCache::size_bytes`].
+    fn entry_size(key: &[u8], value: &Option<Vec<u8>>) -> usize {
+        32 + key.len() + value.as_ref().map_or(0, |v| v.len())
+    }
+
+    /// Returns the cached value of the given key, or `None` if the value isn't in cache.
+    fn get(&mut self, block_hash: &[u8; 32], key: &[u8]) -> Option<Option<Vec<u8>>> {
+        // TODO: overhead of cloning the key
+        let value = self.entries.get(&(*block_hash, key.to_vec())).cloned();
+        if value.is_some() {
+            self.hits += 1;
+        } else {
+            self.misses += 1;
+        }
+        value
+    }
+
+    /// Inserts a value in the cache, evicting older entries if necessary.
+    fn insert(&mut self, block_hash: [u8; 32], key: Vec<u8>, value: Option<Vec<u8>>) {
+        if value
+            .as_ref()
+            .is_some_and(|v| v.len() > STORAGE_QUERY_CACHE_MAX_VALUE_BYTES)
+        {
+            return;
+        }
+
+        let size = Self::entry_size(&key, &value);
+        if let Some((old_key, old_value)) = self.entries.push((block_hash, key), value) {
+            self.size_bytes -= Self::entry_size(&old_key.1, &old_value);
+        }
+        self.size_bytes += size;
+
+        while self.size_bytes > STORAGE_QUERY_CACHE_MAX_BYTES {
+            let ((_, key), value) = self.entries.pop_lru().unwrap();
+            self.size_bytes -= Self::entry_size(&key, &value);
+        }
+    }
 }
 
 impl<TPlat: PlatformRef> SyncService<TPlat> {
     pub async fn new(config: Config<TPlat>) -> Self {
         let (to_background, from_foreground) = async_channel::bounded(16);
 
-        let log_target = format!("sync-service-{}", config.log_name);
+        let log_target = util::LogTarget::new(
+            format!("sync-service-{}", config.log_name),
+            config.log_max_level,
+        );
 
         let task: Pin<Box<dyn Future<Output = ()> + Send>> = match config.chain_type {
             ConfigChainType::Parachain(config_parachain) => Box::pin(parachain::start_parachain(
@@ -173,23 +325,51 @@ impl<TPlat: PlatformRef> SyncService<TPlat> {
                 Box::pin(standalone::start_standalone_chain(
                     log_target.clone(),
                     config.platform.clone(),
-                    config_relay_chain.chain_information,
+                    config_relay_chain,
                     config.block_number_bytes,
-                    config_relay_chain.runtime_code_hint,
                     from_foreground,
-                    config.network_service.0.clone(),
-                    config.network_service.1,
+                    config.network_service.clone(),
                     config.network_events_receiver,
                 ))
             }
         };
 
-        config
-            .platform
-            .spawn_task(log_target.clone().into(), async move {
+        config.platform.spawn_task(log_target.to_string().into(), {
+            let log_target = log_target.clone();
+            async move {
                 task.await;
-                log::debug!(target: &log_target, "Shutdown");
-            });
+                util::log!(Debug, &log_target, "Shutdown");
+            }
+        });
+
+        let storage_query_cache = Mutex::new(StorageQueryCache {
+            entries: lru::LruCache::unbounded_with_hasher(util::SipHasherBuild::new({
+                let mut seed = [0; 16];
+                config.platform.fill_random_bytes(&mut seed);
+                seed
+            })),
+            size_bytes: 0,
+            hits: 0,
+            misses: 0,
+        });
+
+        let peers_state_retention = Mutex::new(lru::LruCache::with_hasher(
+            NonZeroUsize::new(512).unwrap(),
+            util::SipHasherBuild::new({
+                let mut seed = [0; 16];
+                config.platform.fill_random_bytes(&mut seed);
+                seed
+            }),
+        ));
+
+        let finality_proofs_cache = Mutex::new(lru::LruCache::with_hasher(
+            NonZeroUsize::new(64).unwrap(),
+            util::SipHasherBuild::new({
+                let mut seed = [0; 16];
+                config.platform.fill_random_bytes(&mut seed);
+                seed
+            }),
+        ));
 
         SyncService {
             to_background,
@@ -197,6 +377,10 @@ impl<TPlat: PlatformRef> SyncService<TPlat> {
             network_service: config.network_service.0,
             network_chain_id: config.network_service.1,
             block_number_bytes: config.block_number_bytes,
+            log_target,
+            storage_query_cache,
+            peers_state_retention,
+            finality_proofs_cache,
         }
     }
 
@@ -255,6 +439,64 @@ impl<TPlat: PlatformRef> SyncService<TPlat> {
         rx.await.unwrap()
     }
 
+    /// Subscribes to the failures to verify the block headers and finality proofs sent by the
+    /// peers of the chain.
+    ///
+    /// Only up to `buffer_size` failures are buffered in the channel. Contrary to
+    /// [`SyncService::subscribe_all`], failures that happen while the channel is full are
+    /// silently discarded.
+    ///
+    /// If the chain is a parachain, the channel is immediately closed, as the headers of
+    /// parachain blocks aren't verified.
+    ///
+    /// Peers that repeatedly send invalid data are automatically banned and disconnected by
+    /// the sync service, independently of whether there is any subscriber.
+    pub async fn subscribe_verification_failures(
+        &self,
+        buffer_size: usize,
+    ) -> async_channel::Receiver<VerificationFailure> {
+        let (send_back, rx) = oneshot::channel();
+
+        self.to_background
+            .send(ToBackground::SubscribeVerificationFailures {
+                send_back,
+                buffer_size,
+            })
+            .await
+            .unwrap();
+
+        rx.await.unwrap()
+    }
+
+    /// Subscribes to the finality equivocations detected on the chain.
+    ///
+    /// A finality equivocation happens when a valid justification targets a block that
+    /// conflicts with a block that has already been finalized. This can only happen if a large
+    /// number of Grandpa authorities are malicious. Once an equivocation has been detected, the
+    /// sync service stops advancing the finalized block, and the finality of the chain can't be
+    /// trusted anymore. Embedders are encouraged to prominently alert their users.
+    ///
+    /// Only up to `buffer_size` equivocations are buffered in the channel.
+    ///
+    /// If the chain is a parachain, the channel is immediately closed, as the finality of
+    /// parachain blocks isn't verified.
+    pub async fn subscribe_finality_equivocations(
+        &self,
+        buffer_size: usize,
+    ) -> async_channel::Receiver<FinalityEquivocation> {
+        let (send_back, rx) = oneshot::channel();
+
+        self.to_background
+            .send(ToBackground::SubscribeFinalityEquivocations {
+                send_back,
+                buffer_size,
+            })
+            .await
+            .unwrap();
+
+        rx.await.unwrap()
+    }
+
     /// Returns true if it is believed that we are near the head of the chain.
     ///
     /// The way this method is implemented is opaque and cannot be relied on. The return value
@@ -270,6 +512,21 @@ impl<TPlat: PlatformRef> SyncService<TPlat> {
         rx.await.unwrap()
     }
 
+    /// Returns the phase the synchronization is currently in.
+    ///
+    /// Similar to [`SyncService::is_near_head_of_chain_heuristic`], the return value should only
+    /// ever be shown to the user and not used for any meaningful logic.
+    pub async fn sync_phase(&self) -> SyncPhase {
+        let (send_back, rx) = oneshot::channel();
+
+        self.to_background
+            .send(ToBackground::SyncPhase { send_back })
+            .await
+            .unwrap();
+
+        rx.await.unwrap()
+    }
+
     /// Returns the list of peers from the [`network_service::NetworkService`] that are used to
     /// synchronize blocks.
     ///
@@ -280,7 +537,7 @@ impl<TPlat: PlatformRef> SyncService<TPlat> {
     /// meaningful logic
     pub async fn syncing_peers(
         &self,
-    ) -> impl ExactSizeIterator<Item = (PeerId, protocol::Role, u64, [u8; 32])> {
+    ) -> impl ExactSizeIterator<Item = (PeerId, codec::Role, u64, [u8; 32])> {
         let (send_back, rx) = oneshot::channel();
 
         self.to_background
@@ -299,6 +556,9 @@ impl<TPlat: PlatformRef> SyncService<TPlat> {
     /// block of the peer is above the requested block. In other words, it is assumed that all
     /// peers are always on the same finalized chain as the local node.
     ///
+    /// Peers whose latest best block reported to the networking is the requested block are
+    /// returned as well, after the ones above.
+    ///
     /// This function is subject to race condition. The list returned by this function is not
     /// necessarily exact, as a peer might have known about a block in the past but no longer
     /// does.
@@ -318,24 +578,195 @@ impl<TPlat: PlatformRef> SyncService<TPlat> {
             .await
             .unwrap();
 
-        rx.await.unwrap().into_iter()
+        let mut peers = rx.await.unwrap();
+
+        for peer_id in self
+            .network_service
+            .peers_with_block(
+                self.network_chain_id,
+                network_service::BlockHashOrNumber::Hash(*block_hash),
+            )
+            .await
+        {
+            if !peers.contains(&peer_id) {
+                peers.push(peer_id);
+            }
+        }
+
+        peers.into_iter()
     }
 
-    // TODO: doc; explain the guarantees
+    /// Returns what is known about how much of the historical state the given peer keeps.
+    ///
+    /// The state retention of a peer is deduced from the outcome of the storage and call proof
+    /// requests that have been sent to it and that concern old blocks. Returns `None` if no such
+    /// request has been sent to this peer yet, or if this information has been forgotten.
+    ///
+    /// The return value should only ever be shown to the user and not used for any meaningful
+    /// logic.
+    pub async fn peer_state_retention(&self, peer_id: &PeerId) -> Option<PeerStateRetention> {
+        self.peers_state_retention
+            .lock()
+            .await
+            .peek(peer_id)
+            .copied()
+    }
+
+    /// Returns the peers that are assumed to know the given block (see
+    /// [`SyncService::peers_assumed_know_blocks`]), and whether the block is old enough for its
+    /// storage to have possibly been pruned by these peers.
+    ///
+    /// If the block is old, the peers are ordered so that the ones known to be archive nodes
+    /// come first and the ones known to have pruned the state come last, and each peer is
+    /// returned alongside with its known state retention. Otherwise, the state retention of all
+    /// the peers is `None`.
+    async fn state_query_targets(
+        &self,
+        block_number: u64,
+        block_hash: &[u8; 32],
+    ) -> (bool, Vec<(PeerId, Option<PeerStateRetention>)>) {
+        let is_old_block = self
+            .syncing_peers()
+            .await
+            .map(|(_, _, best_number, _)| best_number)
+            .max()
+            .is_some_and(|best_number| {
+                best_number.saturating_sub(block_number) > ASSUMED_STATE_PRUNING_DEPTH
+            });
+
+        let mut targets = self
+            .peers_assumed_know_blocks(block_number, block_hash)
+            .await
+            .map(|peer_id| (peer_id, None))
+            .collect::<Vec<_>>();
+
+        if is_old_block {
+            let mut peers_state_retention = self.peers_state_retention.lock().await;
+            for (peer_id, retention) in &mut targets {
+                *retention = peers_state_retention.get(peer_id).copied();
+            }
+            targets.sort_by_key(|(_, retention)| match retention {
+                Some(PeerStateRetention::Archive) => 0,
+                None => 1,
+                Some(PeerStateRetention::Pruned) => 2,
+            });
+        }
+
+        (is_old_block, targets)
+    }
+
+    /// Updates the state retention of the given peer after a storage or call proof request
+    /// concerning an old block.
+    async fn record_peer_state_retention(&self, peer_id: &PeerId, retention: PeerStateRetention) {
+        let previous = self
+            .peers_state_retention
+            .lock()
+            .await
+            .put(peer_id.clone(), retention);
+
+        if previous != Some(retention) {
+            util::log!(
+                Debug,
+                &self.log_target,
+                "PeerStateRetention(peer={}) => {:?}",
+                peer_id,
+                retention
+            );
+        }
+    }
+
+    /// Returns the SCALE-encoded header of the block with the given hash if it has been
+    /// downloaded as part of the background download of the headers between the checkpoint and
+    /// the block reached by the warp syncing.
+    ///
+    /// Headers passed through [`ConfigRelayChain::recent_headers`] are also returned.
+    ///
+    /// Returns `None` if the block is unknown, which is always the case if
+    /// [`ConfigRelayChain::gap_sync`] was `false` and [`ConfigRelayChain::recent_headers`] was
+    /// empty, or if the chain is a parachain.
+    pub async fn gap_sync_header(&self, hash: &[u8; 32]) -> Option<Vec<u8>> {
+        let (send_back, rx) = oneshot::channel();
+
+        self.to_background
+            .send(ToBackground::GapSyncHeader {
+                send_back,
+                hash: *hash,
+            })
+            .await
+            .unwrap();
+
+        rx.await.unwrap()
+    }
+
+    /// Returns metrics about the cache of the results of [`SyncService::storage_query`].
+    pub async fn storage_query_cache_metrics(&self) -> StorageQueryCacheMetrics {
+        let cache = self.storage_query_cache.lock().await;
+        StorageQueryCacheMetrics {
+            hits: cache.hits,
+            misses: cache.misses,
+            num_entries: cache.entries.len(),
+            size_bytes: cache.size_bytes,
+        }
+    }
+
+    /// Returns the progress of the background download of the headers between the checkpoint
+    /// and the block reached by the warp syncing.
+    ///
+    /// Returns `None` if [`ConfigRelayChain::gap_sync`] was `false` or if the chain is a
+    /// parachain.
+    pub async fn gap_sync_progress(&self) -> Option<GapSyncProgress> {
+        let (send_back, rx) = oneshot::channel();
+
+        self.to_background
+            .send(ToBackground::GapSyncProgress { send_back })
+            .await
+            .unwrap();
+
+        rx.await.unwrap()
+    }
+
+    /// Returns the SCALE-encoded headers of the ancestors of the finalized block that are known
+    /// locally, either because they have been passed through [`ConfigRelayChain::recent_headers`]
+    /// or because they have been downloaded as part of the background download of
+    /// [`ConfigRelayChain::gap_sync`].
+    ///
+    /// The headers are ordered by decreasing block number. At most `max` headers are returned.
+    ///
+    /// Always returns an empty list if the chain is a parachain.
+    pub async fn recent_headers(&self, max: usize) -> Vec<Vec<u8>> {
+        let (send_back, rx) = oneshot::channel();
+
+        self.to_background
+            .send(ToBackground::RecentHeaders { send_back, max })
+            .await
+            .unwrap();
+
+        rx.await.unwrap()
+    }
+
+    /// Queries the given fields of the given block from the network.
+    ///
+    /// The request is sent to the peers that are assumed to know the block (see
+    /// [`SyncService::peers_assumed_know_blocks`]), one after the other, until one of them
+    /// returns a valid response or `total_attempts` peers have been tried.
+    ///
+    /// If successful, the returned header (if requested) is guaranteed to match the requested
+    /// hash, and the returned body (if requested alongside with the header) is guaranteed to
+    /// match the extrinsics root found in the header.
     pub async fn block_query(
         self: Arc<Self>,
         block_number: u64,
         hash: [u8; 32],
-        fields: protocol::BlocksRequestFields,
+        fields: codec::BlocksRequestFields,
         total_attempts: u32,
         timeout_per_request: Duration,
         _max_parallel: NonZeroU32,
-    ) -> Result<protocol::BlockData, ()> {
+    ) -> Result<codec::BlockData, ()> {
         // TODO: better error?
-        let request_config = protocol::BlocksRequestConfig {
-            start: protocol::BlocksRequestConfigStart::Hash(hash),
+        let request_config = codec::BlocksRequestConfig {
+            start: codec::BlocksRequestConfigStart::Hash(hash),
             desired_count: NonZeroU32::new(1).unwrap(),
-            direction: protocol::BlocksRequestDirection::Ascending,
+            direction: codec::BlocksRequestDirection::Ascending,
             fields: fields.clone(),
         };
 
@@ -350,7 +781,7 @@ impl<TPlat: PlatformRef> SyncService<TPlat> {
                 .network_service
                 .clone()
                 .blocks_request(
-                    target,
+                    target.clone(),
                     self.network_chain_id,
                     request_config.clone(),
                     timeout_per_request,
@@ -361,7 +792,21 @@ impl<TPlat: PlatformRef> SyncService<TPlat> {
                 Err(_) => continue,
             };
 
-            return Ok(result.remove(0));
+            // Peers are free to not have the block, for example because they have pruned it.
+            // In that situation, try the next peer.
+            let block = result.remove(0);
+            if !self.is_block_response_valid(&hash, &fields, &block) {
+                util::log!(
+                    Debug,
+                    &self.log_target,
+                    "BlockQuery => InvalidOrIncomplete(peer={}, block={})",
+                    target,
+                    HashDisplay(&hash)
+                );
+                continue;
+            }
+
+            return Ok(block);
         }
 
         Err(())
@@ -371,32 +816,53 @@ impl<TPlat: PlatformRef> SyncService<TPlat> {
     pub async fn block_query_unknown_number(
         self: Arc<Self>,
         hash: [u8; 32],
-        fields: protocol::BlocksRequestFields,
+        fields: codec::BlocksRequestFields,
         total_attempts: u32,
         timeout_per_request: Duration,
         _max_parallel: NonZeroU32,
-    ) -> Result<protocol::BlockData, ()> {
+    ) -> Result<codec::BlockData, ()> {
         // TODO: better error?
-        let request_config = protocol::BlocksRequestConfig {
-            start: protocol::BlocksRequestConfigStart::Hash(hash),
+        let request_config = codec::BlocksRequestConfig {
+            start: codec::BlocksRequestConfigStart::Hash(hash),
             desired_count: NonZeroU32::new(1).unwrap(),
-            direction: protocol::BlocksRequestDirection::Ascending,
+            direction: codec::BlocksRequestDirection::Ascending,
             fields: fields.clone(),
         };
 
+        // Peers that have recently sent a block announce are more likely to be responsive, and
+        // are thus tried first. Among them, peers whose gossip link had to be re-opened less
+        // often are preferred.
+        let mut targets = Vec::new();
+        for peer_id in self.network_service.peers_list(self.network_chain_id).await {
+            let link_info = self
+                .network_service
+                .gossip_link_info(self.network_chain_id, peer_id.clone())
+                .await;
+            targets.push((peer_id, link_info));
+        }
+        targets.sort_by_key(|(_, link_info)| {
+            (
+                cmp::Reverse(
+                    link_info
+                        .as_ref()
+                        .and_then(|info| info.last_announce_from_unix_epoch),
+                ),
+                link_info
+                    .as_ref()
+                    .map_or(u32::MAX, |info| info.open_attempts),
+            )
+        });
+
         // TODO: handle max_parallel
-        // TODO: better peers selection ; don't just take the first
-        for target in self
-            .network_service
-            .peers_list(self.network_chain_id)
-            .await
-            .take(usize::try_from(total_attempts).unwrap_or(usize::max_value()))
+        for (target, link_info) in targets
+            .into_iter()
+            .take(usize::try_from(total_attempts).unwrap_or(usize::MAX))
         {
             let mut result = match self
                 .network_service
                 .clone()
                 .blocks_request(
-                    target,
+                    target.clone(),
                     self.network_chain_id,
                     request_config.clone(),
                     timeout_per_request,
@@ -404,15 +870,206 @@ impl<TPlat: PlatformRef> SyncService<TPlat> {
                 .await
             {
                 Ok(b) => b,
-                Err(_) => continue,
+                Err(_) => {
+                    if let Some(link_info) = link_info {
+                        util::log!(Debug,
+                            &self.log_target,
+                            "BlockQuery => Failed(peer={}, announces_received={}, bytes_received={})",
+                            target,
+                            link_info.announces_received,
+                            BytesDisplay(link_info.bytes_received)
+                        );
+                    }
+                    continue;
+                }
             };
 
-            return Ok(result.remove(0));
+            let block = result.remove(0);
+            if !self.is_block_response_valid(&hash, &fields, &block) {
+                util::log!(
+                    Debug,
+                    &self.log_target,
+                    "BlockQuery => InvalidOrIncomplete(peer={}, block={})",
+                    target,
+                    HashDisplay(&hash)
+                );
+                continue;
+            }
+
+            return Ok(block);
         }
 
         Err(())
     }
 
+    /// Obtains a GrandPa justification proving the finality of the given block, and verifies it.
+    ///
+    /// Justifications that have been verified in the past are kept in a cache. If the
+    /// justification isn't in the cache, it is requested from the peers that are assumed to know
+    /// the block (see [`SyncService::peers_assumed_know_blocks`]), one after the other, until one
+    /// of them returns a valid justification or `total_attempts` peers have been tried.
+    ///
+    /// Justifications are verified against the GrandPa authorities of the current finalized
+    /// block. As such, only justifications of blocks finalized since the latest change of
+    /// authorities can be obtained.
+    ///
+    /// On success, returns the SCALE-encoded justification.
+    pub async fn finality_proof_query(
+        self: Arc<Self>,
+        block_number: u64,
+        hash: [u8; 32],
+        total_attempts: u32,
+        timeout_per_request: Duration,
+    ) -> Result<Vec<u8>, FinalityProofQueryError> {
+        if let Some(justification) = self.finality_proofs_cache.lock().await.get(&hash) {
+            return Ok(justification.clone());
+        }
+
+        let chain_information = self
+            .serialize_chain_information()
+            .await
+            .ok_or(FinalityProofQueryError::NotGrandpa)?;
+        let chain::chain_information::ChainInformationFinalityRef::Grandpa {
+            after_finalized_block_authorities_set_id,
+            finalized_triggered_authorities,
+            ..
+        } = chain_information.as_ref().finality
+        else {
+            return Err(FinalityProofQueryError::NotGrandpa);
+        };
+
+        let request_config = codec::BlocksRequestConfig {
+            start: codec::BlocksRequestConfigStart::Hash(hash),
+            desired_count: NonZeroU32::new(1).unwrap(),
+            direction: codec::BlocksRequestDirection::Ascending,
+            fields: codec::BlocksRequestFields {
+                header: false,
+                body: false,
+                justifications: true,
+            },
+        };
+
+        // TODO: better peers selection ; don't just take the first
+        for target in self
+            .peers_assumed_know_blocks(block_number, &hash)
+            .await
+            .take(usize::try_from(total_attempts).unwrap_or(usize::max_value()))
+        {
+            let Ok(result) = self
+                .network_service
+                .clone()
+                .blocks_request(
+                    target.clone(),
+                    self.network_chain_id,
+                    request_config.clone(),
+                    timeout_per_request,
+                )
+                .await
+            else {
+                continue;
+            };
+
+            let Some(justification) = result
+                .into_iter()
+                .filter(|block| block.hash == hash)
+                .flat_map(|block| block.justifications.unwrap_or_default())
+                .find(|j| j.engine_id == *b"FRNK")
+                .map(|j| j.justification)
+            else {
+                // Peers are free to not have a justification for the block, for example
+                // because it has been finalized by a justification of one of its descendants.
+                continue;
+            };
+
+            let verify_result = match justification::decode::decode_grandpa(
+                &justification,
+                self.block_number_bytes,
+            ) {
+                Ok(decoded) if *decoded.target_hash == hash => {
+                    justification::verify::verify(justification::verify::Config {
+                        justification: decoded,
+                        block_number_bytes: self.block_number_bytes,
+                        authorities_set_id: after_finalized_block_authorities_set_id,
+                        authorities_list: finalized_triggered_authorities
+                            .iter()
+                            .map(|a| &a.public_key[..]),
+                        randomness_seed: {
+                            let mut seed = [0; 32];
+                            self.platform.fill_random_bytes(&mut seed);
+                            seed
+                        },
+                    })
+                    .map_err(|err| err.to_string())
+                }
+                Ok(_) => Err("justification targets a different block".to_owned()),
+                Err(err) => Err(err.to_string()),
+            };
+
+            if let Err(error) = verify_result {
+                util::log!(
+                    Debug,
+                    &self.log_target,
+                    "FinalityProofQuery => InvalidJustification(peer={}, block={}, error={})",
+                    target,
+                    HashDisplay(&hash),
+                    error
+                );
+                continue;
+            }
+
+            self.finality_proofs_cache
+                .lock()
+                .await
+                .put(hash, justification.clone());
+            return Ok(justification);
+        }
+
+        Err(FinalityProofQueryError::NoValidJustification)
+    }
+
+    /// Returns `true` if the given block response to a request for the block with the given hash
+    /// contains the requested header and body, and if they match the block hash. Justifications
+    /// aren't checked, as blocks don't necessarily have any.
+    ///
+    /// The body can only be verified if the header has been requested as well, as the header
+    /// contains the root of the trie of the extrinsics.
+    fn is_block_response_valid(
+        &self,
+        hash: &[u8; 32],
+        fields: &codec::BlocksRequestFields,
+        block: &codec::BlockData,
+    ) -> bool {
+        if block.hash != *hash {
+            return false;
+        }
+
+        if fields.header {
+            let Some(scale_encoded_header) = &block.header else {
+                return false;
+            };
+            if header::hash_from_scale_encoded_header(scale_encoded_header) != *hash {
+                return false;
+            }
+        }
+
+        if fields.body {
+            let Some(body) = &block.body else {
+                return false;
+            };
+            if let Some(scale_encoded_header) = &block.header {
+                let Ok(decoded) = header::decode(scale_encoded_header, self.block_number_bytes)
+                else {
+                    return false;
+                };
+                if header::extrinsics_root(body) != *decoded.extrinsics_root {
+                    return false;
+                }
+            }
+        }
+
+        true
+    }
+
     /// Performs one or more storage proof requests in order to fulfill the `requests` passed as
     /// parameter.
     ///
@@ -423,6 +1080,10 @@ impl<TPlat: PlatformRef> SyncService<TPlat> {
     ///
     /// The result will contain items corresponding to the requests, but in no particular order.
     ///
+    /// The values of the [`StorageRequestItemTy::Value`] and [`StorageRequestItemTy::Hash`] items
+    /// are kept in a cache after they have been verified, and requests concerning these same
+    /// entries are later served from this cache instead of the network.
+    ///
     /// See the documentation of [`StorageRequestItem`] and [`StorageResultItem`] for more
     /// information.
     // TODO: should return the items in a streaming way, so that we don't need to wait for all the queries to have finished
@@ -452,7 +1113,67 @@ impl<TPlat: PlatformRef> SyncService<TPlat> {
             },
         }
 
+        let mut final_results = Vec::<StorageResultItem>::new();
+
+        // Values downloaded from the network, either because of a `Value` or a `Hash` request,
+        // to insert in the cache once the query is finished.
+        let mut cache_insertions = Vec::<(Vec<u8>, Option<Vec<u8>>)>::new();
+
+        // Serve from the cache the requests that can be served from it.
+        let requests = {
+            let mut cache = self.storage_query_cache.lock().await;
+            let mut num_hits = 0;
+            let requests = requests
+                .filter(|request| {
+                    let hash = match request.ty {
+                        StorageRequestItemTy::Value => false,
+                        StorageRequestItemTy::Hash => true,
+                        _ => return true,
+                    };
+
+                    let Some(value) = cache.get(block_hash, &request.key) else {
+                        return true;
+                    };
+
+                    num_hits += 1;
+                    final_results.push(if hash {
+                        StorageResultItem::Hash {
+                            key: request.key.clone(),
+                            hash: value.map(|value| {
+                                *<&[u8; 32]>::try_from(
+                                    blake2_rfc::blake2b::blake2b(32, &[], &value).as_bytes(),
+                                )
+                                .unwrap()
+                            }),
+                        }
+                    } else {
+                        StorageResultItem::Value {
+                            key: request.key.clone(),
+                            value,
+                        }
+                    });
+                    false
+                })
+                .collect::<Vec<_>>();
+
+            if num_hits != 0 {
+                util::log!(Debug,
+                    &self.log_target,
+                    "StorageQueryCache => Hits(block={}, hits={}, total_hits={}, total_misses={}, entries={}, size={})",
+                    HashDisplay(block_hash),
+                    num_hits,
+                    cache.hits,
+                    cache.misses,
+                    cache.entries.len(),
+                    BytesDisplay(u64::try_from(cache.size_bytes).unwrap_or(u64::MAX))
+                );
+            }
+
+            requests
+        };
+
         let mut requests_remaining = requests
+            .into_iter()
             .map(|request| match request.ty {
                 StorageRequestItemTy::DescendantsHashes
                 | StorageRequestItemTy::DescendantsValues => RequestImpl::PrefixScan {
@@ -483,8 +1204,7 @@ impl<TPlat: PlatformRef> SyncService<TPlat> {
         let total_attempts = usize::try_from(total_attempts).unwrap_or(usize::max_value());
         let mut outcome_errors = Vec::with_capacity(total_attempts);
 
-        let mut final_results =
-            Vec::<StorageResultItem>::with_capacity(requests_remaining.len() * 4);
+        final_results.reserve(requests_remaining.len() * 4);
 
         // Number of nodes that are possible in a response before exceeding the response size
         // limit. Because the size of a trie node is unknown, this can only ever be a gross
@@ -500,6 +1220,10 @@ impl<TPlat: PlatformRef> SyncService<TPlat> {
         loop {
             // Check if we're done.
             if requests_remaining.is_empty() {
+                let mut cache = self.storage_query_cache.lock().await;
+                for (key, value) in cache_insertions {
+                    cache.insert(*block_hash, key, value);
+                }
                 return Ok(final_results);
             }
 
@@ -509,11 +1233,14 @@ impl<TPlat: PlatformRef> SyncService<TPlat> {
                 });
             }
 
-            // Choose peer to query.
+            // Choose peer to query. Peers are picked randomly amongst the ones that are the most
+            // likely to still have the storage of the block.
             // TODO: better peers selection
-            let Some(target) = self
-                .peers_assumed_know_blocks(block_number, block_hash)
-                .await
+            let (is_old_block, targets) = self.state_query_targets(block_number, block_hash).await;
+            let Some(target) = targets
+                .iter()
+                .take_while(|(_, retention)| *retention == targets[0].1)
+                .map(|(peer_id, _)| peer_id.clone())
                 .choose(&mut randomness)
             else {
                 // No peer knows this block. Returning with a failure.
@@ -581,8 +1308,8 @@ impl<TPlat: PlatformRef> SyncService<TPlat> {
                 .clone()
                 .storage_proof_request(
                     self.network_chain_id,
-                    target,
-                    protocol::StorageProofRequestConfig {
+                    target.clone(),
+                    codec::StorageProofRequestConfig {
                         block_hash: *block_hash,
                         keys: keys_to_request.into_iter(),
                     },
@@ -721,6 +1448,9 @@ impl<TPlat: PlatformRef> SyncService<TPlat> {
                                 }
                                 proof_decode::StorageValue::Known { value, .. } => {
                                     proof_has_advanced_verification = true;
+                                    if value.len() <= STORAGE_QUERY_CACHE_MAX_VALUE_BYTES {
+                                        cache_insertions.push((key.clone(), Some(value.to_vec())));
+                                    }
                                     if hash {
                                         let hashed_value =
                                             blake2_rfc::blake2b::blake2b(32, &[], value);
@@ -740,6 +1470,7 @@ impl<TPlat: PlatformRef> SyncService<TPlat> {
                                 }
                                 proof_decode::StorageValue::None => {
                                     proof_has_advanced_verification = true;
+                                    cache_insertions.push((key.clone(), None));
                                     if hash {
                                         final_results
                                             .push(StorageResultItem::Hash { key, hash: None });
@@ -798,43 +1529,219 @@ impl<TPlat: PlatformRef> SyncService<TPlat> {
             if !proof_has_advanced_verification {
                 outcome_errors.push(StorageQueryErrorDetail::MissingProofEntry);
             }
+
+            // Full nodes respond to requests concerning blocks whose state they have pruned with
+            // an empty proof, which leads to a missing entry.
+            if is_old_block {
+                self.record_peer_state_retention(
+                    &target,
+                    if proof_has_advanced_verification {
+                        PeerStateRetention::Archive
+                    } else {
+                        PeerStateRetention::Pruned
+                    },
+                )
+                .await;
+            }
         }
     }
 
-    // TODO: documentation
-    // TODO: there's no proof that the call proof is actually correct
-    pub async fn call_proof_query(
+    /// Obtains from the network a proof of the storage values of the given keys, in the format
+    /// expected by the `state_getReadProof` JSON-RPC function.
+    ///
+    /// Must be passed a block hash, a block number, and the Merkle value of the root node of the
+    /// storage trie of this same block, similarly to [`SyncService::storage_query`].
+    ///
+    /// The request is sent to the peers that are assumed to know the block, one after the other,
+    /// until one of them returns a proof from which the storage values of all the keys can be
+    /// determined, or `total_attempts` peers have been tried. The proof is verified against
+    /// `main_trie_root_hash`.
+    ///
+    /// On success, returns the list of entries of the proof, in other words trie node values and
+    /// standalone storage values.
+    pub async fn storage_proof_query(
         self: Arc<Self>,
         block_number: u64,
-        config: protocol::CallProofRequestConfig<
-            '_,
-            impl Iterator<Item = impl AsRef<[u8]>> + Clone,
-        >,
+        block_hash: &[u8; 32],
+        main_trie_root_hash: &[u8; 32],
+        keys: impl Iterator<Item = Vec<u8>>,
         total_attempts: u32,
         timeout_per_request: Duration,
-        _max_parallel: NonZeroU32,
-    ) -> Result<network_service::EncodedMerkleProof, CallProofQueryError> {
+    ) -> Result<Vec<Vec<u8>>, StorageQueryError> {
+        let keys = keys.collect::<Vec<_>>();
         let mut outcome_errors =
             Vec::with_capacity(usize::try_from(total_attempts).unwrap_or(usize::max_value()));
 
         // TODO: better peers selection ; don't just take the first
-        // TODO: handle max_parallel
-        for target in self
-            .peers_assumed_know_blocks(block_number, &config.block_hash)
-            .await
-            .take(usize::try_from(total_attempts).unwrap_or(usize::max_value()))
+        let (_, targets) = self.state_query_targets(block_number, block_hash).await;
+
+        for (target, _) in targets
+            .into_iter()
+            .take(usize::try_from(total_attempts).unwrap_or(usize::MAX))
         {
             let result = self
                 .network_service
                 .clone()
-                .call_proof_request(
+                .storage_proof_request(
                     self.network_chain_id,
                     target,
-                    config.clone(),
+                    codec::StorageProofRequestConfig {
+                        block_hash: *block_hash,
+                        keys: keys.iter(),
+                    },
                     timeout_per_request,
                 )
                 .await;
 
+            let proof = match result {
+                Ok(r) => r,
+                Err(err) => {
+                    outcome_errors.push(StorageQueryErrorDetail::Network(err));
+                    continue;
+                }
+            };
+
+            let decoded_proof = match proof_decode::decode_and_verify_proof(proof_decode::Config {
+                proof: proof.decode(),
+            }) {
+                Ok(d) => d,
+                Err(err) => {
+                    outcome_errors.push(StorageQueryErrorDetail::ProofVerification(err));
+                    continue;
+                }
+            };
+
+            // The proof is only useful if it is complete. Partial proofs aren't merged together.
+            if keys.iter().any(|key| {
+                decoded_proof
+                    .storage_value(main_trie_root_hash, key)
+                    .is_err()
+            }) {
+                outcome_errors.push(StorageQueryErrorDetail::MissingProofEntry);
+                continue;
+            }
+
+            // The proof has been decoded above, and thus its format is guaranteed to be valid.
+            return Ok(proof_decode::decode_proof_entries(proof.decode())
+                .unwrap()
+                .into_iter()
+                .map(|entry| entry.to_vec())
+                .collect());
+        }
+
+        Err(StorageQueryError {
+            errors: outcome_errors,
+        })
+    }
+
+    /// Performs one or more call proof requests in order to obtain the proof of the given
+    /// runtime call.
+    ///
+    /// The request is sent to the peers that are assumed to know the block, one after the other,
+    /// until one of them returns a proof or `total_attempts` peers have been tried.
+    ///
+    /// If `hedging_delay` is `Some`, and the peer that is being queried hasn't answered after
+    /// this delay, the same request is also sent to the next peer. The first proof to arrive is
+    /// returned and the other request is cancelled. This trades a bit of bandwidth for a lower
+    /// latency in the situation where the first peer is slow, and should thus only be used for
+    /// latency-sensitive queries. At most two requests are in progress at any given time, and
+    /// the second request counts towards `total_attempts`.
+    // TODO: there's no proof that the call proof is actually correct
+    pub async fn call_proof_query(
+        self: Arc<Self>,
+        block_number: u64,
+        config: codec::CallProofRequestConfig<'_, impl Iterator<Item = impl AsRef<[u8]>> + Clone>,
+        total_attempts: u32,
+        timeout_per_request: Duration,
+        _max_parallel: NonZeroU32,
+        hedging_delay: Option<Duration>,
+    ) -> Result<network_service::EncodedMerkleProof, CallProofQueryError> {
+        let mut outcome_errors =
+            Vec::with_capacity(usize::try_from(total_attempts).unwrap_or(usize::max_value()));
+
+        // TODO: better peers selection ; don't just take the first
+        // TODO: handle max_parallel
+        let (is_old_block, targets) = self
+            .state_query_targets(block_number, &config.block_hash)
+            .await;
+        let mut targets = targets
+            .into_iter()
+            .map(|(peer_id, _)| peer_id)
+            .take(usize::try_from(total_attempts).unwrap_or(usize::MAX));
+
+        // Starts a request towards the given peer. The peer is returned alongside with the
+        // outcome of the request.
+        let start_request = |target: PeerId| {
+            let request = self.network_service.clone().call_proof_request(
+                self.network_chain_id,
+                target.clone(),
+                config.clone(),
+                timeout_per_request,
+            );
+            async move { (target, request.await) }
+        };
+
+        // Requests currently in progress. Never contains more than two elements.
+        let mut requests_in_progress = FuturesUnordered::new();
+        // Future that becomes ready when the next request should be started in parallel of the
+        // one in progress. `None` if hedging is disabled or if the hedging request has already
+        // been started.
+        let mut hedging_timer = None;
+
+        loop {
+            if requests_in_progress.is_empty() {
+                let Some(target) = targets.next() else {
+                    break;
+                };
+                requests_in_progress.push(start_request(target));
+                hedging_timer = hedging_delay.map(|delay| Box::pin(self.platform.sleep(delay)));
+            }
+
+            let (target, result) = {
+                let next_result = async { Some(requests_in_progress.next().await.unwrap()) };
+                let hedging = async {
+                    match hedging_timer.as_mut() {
+                        Some(timer) => timer.await,
+                        None => future::pending().await,
+                    }
+                    None
+                };
+                match next_result.or(hedging).await {
+                    Some(result) => result,
+                    None => {
+                        // The hedging delay has elapsed. Send the same request to the next
+                        // peer.
+                        hedging_timer = None;
+                        if let Some(target) = targets.next() {
+                            util::log!(
+                                Debug,
+                                &self.log_target,
+                                "CallProofQuery => Hedging(peer={})",
+                                target
+                            );
+                            requests_in_progress.push(start_request(target));
+                        }
+                        continue;
+                    }
+                }
+            };
+
+            // Substrate also responds with an empty proof to requests concerning blocks whose
+            // state it has pruned.
+            if is_old_block {
+                if let Ok(value) = &result {
+                    self.record_peer_state_retention(
+                        &target,
+                        if value.decode().is_empty() {
+                            PeerStateRetention::Pruned
+                        } else {
+                            PeerStateRetention::Archive
+                        },
+                    )
+                    .await;
+                }
+            }
+
             match result {
                 Ok(value) if !value.decode().is_empty() => return Ok(value),
                 // TODO: this check of emptiness is a bit of a hack; it is necessary because Substrate responds to requests about blocks it doesn't know with an empty proof
@@ -845,6 +1752,12 @@ impl<TPlat: PlatformRef> SyncService<TPlat> {
                         ),
                     ),
                 )),
+                Err(network_service::CallProofRequestError::RequestTooLarge) => {
+                    // The request is too large to be sent to any peer, so there's no point in
+                    // trying other peers.
+                    outcome_errors.push(network_service::CallProofRequestError::RequestTooLarge);
+                    break;
+                }
                 Err(err) => {
                     outcome_errors.push(err);
                 }
@@ -953,7 +1866,8 @@ pub enum StorageResultItem {
     },
 }
 
-/// Error that can happen when calling [`SyncService::storage_query`].
+/// Error that can happen when calling [`SyncService::storage_query`] or
+/// [`SyncService::storage_proof_query`].
 #[derive(Debug, Clone)]
 pub struct StorageQueryError {
     /// Contains one error per peer that has been contacted. If this list is empty, then we
@@ -1028,6 +1942,14 @@ impl CallProofQueryError {
     pub fn is_network_problem(&self) -> bool {
         self.errors.iter().all(|err| err.is_network_problem())
     }
+
+    /// Returns `true` if the call proof request couldn't be sent because it is too large, for
+    /// example because the call parameters are too large.
+    pub fn is_request_too_large(&self) -> bool {
+        self.errors
+            .iter()
+            .any(|err| matches!(err, network_service::CallProofRequestError::RequestTooLarge))
+    }
 }
 
 impl fmt::Display for CallProofQueryError {
@@ -1044,6 +1966,91 @@ impl fmt::Display for CallProofQueryError {
     }
 }
 
+/// Error that can happen when calling [`SyncService::finality_proof_query`].
+#[derive(Debug, Clone)]
+pub enum FinalityProofQueryError {
+    /// The finality of the chain isn't determined by GrandPa, or not enough is known about the
+    /// chain yet.
+    NotGrandpa,
+    /// No peer has provided a valid justification for the block.
+    NoValidJustification,
+}
+
+impl fmt::Display for FinalityProofQueryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FinalityProofQueryError::NotGrandpa => {
+                write!(f, "The finality of the chain isn't determined by GrandPa")
+            }
+            FinalityProofQueryError::NoValidJustification => {
+                write!(f, "No valid justification could be obtained")
+            }
+        }
+    }
+}
+
+/// Return value of [`SyncService::sync_phase`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyncPhase {
+    /// The chain is being warp synced. The finalized block is likely far behind the head of the
+    /// chain.
+    WarpSync,
+    /// Blocks are being downloaded one by one and verified, but the head of the chain hasn't
+    /// been reached yet.
+    CatchingUp,
+    /// It is believed that the head of the chain has been reached. See
+    /// [`SyncService::is_near_head_of_chain_heuristic`].
+    NearHead,
+}
+
+/// Return value of [`SyncService::gap_sync_progress`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GapSyncProgress {
+    /// Number of the block the chain has started from, in other words the lowest block whose
+    /// header is downloaded.
+    pub checkpoint_block_number: u64,
+
+    /// Number of the block reached by the latest warp sync, in other words the highest block
+    /// whose header is downloaded. `None` if no warp sync has finished yet.
+    pub warp_sync_block_number: Option<u64>,
+
+    /// Number of the next block whose header is going to be downloaded. `None` if no warp sync
+    /// has finished yet or if all the headers have been downloaded.
+    pub next_download_block_number: Option<u64>,
+
+    /// Number of headers that have been downloaded so far.
+    pub num_downloaded_headers: usize,
+}
+
+/// Return value of [`SyncService::storage_query_cache_metrics`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StorageQueryCacheMetrics {
+    /// Number of requested storage items that have been served from the cache since the
+    /// creation of the service.
+    pub hits: u64,
+
+    /// Number of requested storage items that could have been served from the cache but weren't
+    /// found in it since the creation of the service.
+    pub misses: u64,
+
+    /// Number of entries currently in the cache.
+    pub num_entries: usize,
+
+    /// Sum of the sizes in bytes of the keys and values currently in the cache.
+    pub size_bytes: usize,
+}
+
+/// Return value of [`SyncService::peer_state_retention`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PeerStateRetention {
+    /// The peer has successfully answered a request concerning the storage of an old block, and
+    /// is thus assumed to keep the storage of all the blocks.
+    Archive,
+    /// The peer has failed to answer a request concerning the storage of an old block, and is
+    /// thus assumed to only keep the storage of the blocks near the head of the chain.
+    Pruned,
+}
+
 /// Return value of [`SyncService::subscribe_all`].
 pub struct SubscribeAll {
     /// SCALE-encoded header of the finalized block at the time of the subscription.
@@ -1162,15 +2169,89 @@ pub struct BlockNotification {
     pub parent_hash: [u8; 32],
 }
 
+/// Failure to verify a block header or a finality proof sent by a peer.
+///
+/// See [`SyncService::subscribe_verification_failures`].
+#[derive(Debug, Clone)]
+pub struct VerificationFailure {
+    /// Peer that has sent the data that has failed to verify.
+    pub peer_id: PeerId,
+
+    /// BLAKE2 hash of the block whose header has failed to verify. `None` if the failure
+    /// concerns a finality proof.
+    pub block_hash: Option<[u8; 32]>,
+
+    /// What has failed to verify.
+    pub kind: VerificationFailureKind,
+
+    /// Human-readable description of the error.
+    pub error: String,
+}
+
+/// See [`VerificationFailure::kind`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum VerificationFailureKind {
+    /// The header of a block is invalid.
+    Header,
+    /// A justification is invalid.
+    Justification,
+    /// A GrandPa commit message is invalid.
+    GrandpaCommit,
+}
+
+/// Two conflicting blocks have been finalized at the same height.
+///
+/// See [`SyncService::subscribe_finality_equivocations`].
+#[derive(Debug, Clone)]
+pub struct FinalityEquivocation {
+    /// Peer that has sent the conflicting justification or commit, if known.
+    pub peer_id: Option<PeerId>,
+
+    /// Number of the two conflicting blocks.
+    pub block_number: u64,
+
+    /// Hash of the block that was finalized by the sync service.
+    pub finalized_block_hash: [u8; 32],
+
+    /// Hash of the block targeted by the conflicting justification or commit.
+    pub conflicting_block_hash: [u8; 32],
+
+    /// Conflicting justification or commit.
+    pub proof: FinalityEquivocationProof,
+}
+
+/// See [`FinalityEquivocation::proof`].
+#[derive(Debug, Clone)]
+pub enum FinalityEquivocationProof {
+    /// SCALE-encoded Grandpa justification.
+    Justification(Vec<u8>),
+    /// SCALE-encoded Grandpa commit message.
+    GrandpaCommit(Vec<u8>),
+}
+
 enum ToBackground {
     /// See [`SyncService::is_near_head_of_chain_heuristic`].
     IsNearHeadOfChainHeuristic { send_back: oneshot::Sender<bool> },
+    /// See [`SyncService::sync_phase`].
+    SyncPhase {
+        send_back: oneshot::Sender<SyncPhase>,
+    },
     /// See [`SyncService::subscribe_all`].
     SubscribeAll {
         send_back: oneshot::Sender<SubscribeAll>,
         buffer_size: usize,
         runtime_interest: bool,
     },
+    /// See [`SyncService::subscribe_verification_failures`].
+    SubscribeVerificationFailures {
+        send_back: oneshot::Sender<async_channel::Receiver<VerificationFailure>>,
+        buffer_size: usize,
+    },
+    /// See [`SyncService::subscribe_finality_equivocations`].
+    SubscribeFinalityEquivocations {
+        send_back: oneshot::Sender<async_channel::Receiver<FinalityEquivocation>>,
+        buffer_size: usize,
+    },
     /// See [`SyncService::peers_assumed_know_blocks`].
     PeersAssumedKnowBlock {
         send_back: oneshot::Sender<Vec<PeerId>>,
@@ -1179,10 +2260,24 @@ enum ToBackground {
     },
     /// See [`SyncService::syncing_peers`].
     SyncingPeers {
-        send_back: oneshot::Sender<Vec<(PeerId, protocol::Role, u64, [u8; 32])>>,
+        send_back: oneshot::Sender<Vec<(PeerId, codec::Role, u64, [u8; 32])>>,
     },
     /// See [`SyncService::serialize_chain_information`].
     SerializeChainInformation {
         send_back: oneshot::Sender<Option<chain::chain_information::ValidChainInformation>>,
     },
+    /// See [`SyncService::gap_sync_header`].
+    GapSyncHeader {
+        send_back: oneshot::Sender<Option<Vec<u8>>>,
+        hash: [u8; 32],
+    },
+    /// See [`SyncService::gap_sync_progress`].
+    GapSyncProgress {
+        send_back: oneshot::Sender<Option<GapSyncProgress>>,
+    },
+    /// See [`SyncService::recent_headers`].
+    RecentHeaders {
+        send_back: oneshot::Sender<Vec<Vec<u8>>>,
+        max: usize,
+    },
 }
@@ -18,7 +18,7 @@
 use super::ToBackground;
 use crate::{network_service, platform::PlatformRef, runtime_service, util};
 
-use alloc::{borrow::ToOwned as _, boxed::Box, string::String, sync::Arc, vec::Vec};
+use alloc::{borrow::ToOwned as _, boxed::Box, sync::Arc, vec::Vec};
 use core::{
     iter, mem,
     num::{NonZeroU32, NonZeroUsize},
@@ -35,13 +35,13 @@ use smoldot::{
     header,
     informant::HashDisplay,
     libp2p::PeerId,
-    network::protocol,
+    network::codec,
     sync::{all_forks::sources, para},
 };
 
 /// Starts a sync service background task to synchronize a parachain.
 pub(super) async fn start_parachain<TPlat: PlatformRef>(
-    log_target: String,
+    log_target: util::LogTarget,
     platform: TPlat,
     finalized_block_header: Vec<u8>,
     block_number_bytes: usize,
@@ -102,7 +102,7 @@ pub(super) async fn start_parachain<TPlat: PlatformRef>(
 /// Task that is running in the background.
 struct ParachainBackgroundTask<TPlat: PlatformRef> {
     /// Target to use for all logs.
-    log_target: String,
+    log_target: util::LogTarget,
 
     /// Access to the platform's capabilities.
     platform: TPlat,
@@ -140,7 +140,7 @@ struct ParachainBackgroundTask<TPlat: PlatformRef> {
     obsolete_finalized_parahead: Vec<u8>,
 
     /// State machine that tracks the list of parachain network sources and their known blocks.
-    sync_sources: sources::AllForksSources<(PeerId, protocol::Role)>,
+    sync_sources: sources::AllForksSources<(PeerId, codec::Role)>,
 
     /// Maps `PeerId`s to their indices within `sync_sources`.
     sync_sources_map: HashMap<PeerId, sources::SourceId, util::SipHasherBuild>,
@@ -355,7 +355,7 @@ impl<TPlat: PlatformRef> ParachainBackgroundTask<TPlat> {
 
                 WhatHappened::SubscriptionDead => {
                     // Recreate the channel.
-                    log::debug!(target: &self.log_target, "Subscriptions <= Reset");
+                    util::log!(Debug, &self.log_target, "Subscriptions <= Reset");
                     self.subscription_state = ParachainBackgroundState::NotSubscribed {
                         all_subscriptions: Vec::new(),
                         subscribe_future: {
@@ -419,6 +419,24 @@ impl<TPlat: PlatformRef> ParachainBackgroundTask<TPlat> {
                 // `false`.
                 let _ = send_back.send(false);
             }
+            (ToBackground::SyncPhase { send_back }, ParachainBackgroundState::Subscribed(sub))
+                if sub.async_tree.output_finalized_async_user_data().is_some() =>
+            {
+                // Parachains are never warp synced. See `IsNearHeadOfChainHeuristic` above.
+                let phase = if self
+                    .relay_chain_sync
+                    .is_near_head_of_chain_heuristic()
+                    .await
+                {
+                    super::SyncPhase::NearHead
+                } else {
+                    super::SyncPhase::CatchingUp
+                };
+                let _ = send_back.send(phase);
+            }
+            (ToBackground::SyncPhase { send_back }, _) => {
+                let _ = send_back.send(super::SyncPhase::CatchingUp);
+            }
             (
                 ToBackground::SubscribeAll {
                     send_back,
@@ -612,6 +630,27 @@ impl<TPlat: PlatformRef> ParachainBackgroundTask<TPlat> {
             (ToBackground::SerializeChainInformation { send_back }, _) => {
                 let _ = send_back.send(None);
             }
+            (ToBackground::GapSyncHeader { send_back, .. }, _) => {
+                let _ = send_back.send(None);
+            }
+            (ToBackground::GapSyncProgress { send_back }, _) => {
+                let _ = send_back.send(None);
+            }
+            (ToBackground::RecentHeaders { send_back, .. }, _) => {
+                let _ = send_back.send(Vec::new());
+            }
+            (ToBackground::SubscribeVerificationFailures { send_back, .. }, _) => {
+                // The headers of parachain blocks aren't verified. The sending side of the
+                // channel is immediately dropped.
+                let (_, rx) = async_channel::bounded(1);
+                let _ = send_back.send(rx);
+            }
+            (ToBackground::SubscribeFinalityEquivocations { send_back, .. }, _) => {
+                // The finality of parachain blocks isn't verified. The sending side of the
+                // channel is immediately dropped.
+                let (_, rx) = async_channel::bounded(1);
+                let _ = send_back.send(rx);
+            }
         }
     }
 
@@ -642,24 +681,17 @@ impl<TPlat: PlatformRef> ParachainBackgroundTask<TPlat> {
                 chain_id,
                 peer_id,
                 announce,
+                header,
             } if chain_id == self.network_chain_id => {
                 let local_id = *self.sync_sources_map.get(&peer_id).unwrap();
-                let decoded = announce.decode();
-                if let Ok(decoded_header) =
-                    header::decode(decoded.scale_encoded_header, self.block_number_bytes)
-                {
-                    let decoded_header_hash =
-                        header::hash_from_scale_encoded_header(decoded.scale_encoded_header);
-                    self.sync_sources.add_known_block(
-                        local_id,
-                        decoded_header.number,
-                        decoded_header_hash,
-                    );
-                    if decoded.is_best {
+                if let Ok(header) = header {
+                    self.sync_sources
+                        .add_known_block(local_id, header.number, header.hash);
+                    if announce.decode().is_best {
                         self.sync_sources.add_known_block_and_set_best(
                             local_id,
-                            decoded_header.number,
-                            decoded_header_hash,
+                            header.number,
+                            header.hash,
                         );
                     }
                 }
@@ -702,8 +734,9 @@ impl<TPlat: PlatformRef> ParachainBackgroundTask<TPlat> {
                     break;
                 }
                 async_tree::NextNecessaryAsyncOp::Ready(op) => {
-                    log::debug!(
-                        target: &self.log_target,
+                    util::log!(
+                        Debug,
+                        &self.log_target,
                         "ParaheadFetchOperations <= StartFetch(relay_block_hash={})",
                         HashDisplay(op.block_user_data),
                     );
@@ -746,11 +779,16 @@ impl<TPlat: PlatformRef> ParachainBackgroundTask<TPlat> {
 
         match parahead_result {
             Ok(parahead) => {
-                log::debug!(
-                    target: &self.log_target,
+                util::log!(
+                    Debug,
+                    &self.log_target,
                     "ParaheadFetchOperations => Parahead(hash={}, relay_blocks={})",
                     HashDisplay(blake2_rfc::blake2b::blake2b(32, b"", &parahead).as_bytes()),
-                    runtime_subscription.async_tree.async_op_blocks(async_op_id).map(|b| HashDisplay(b)).join(",")
+                    runtime_subscription
+                        .async_tree
+                        .async_op_blocks(async_op_id)
+                        .map(|b| HashDisplay(b))
+                        .join(",")
                 );
 
                 // Unpin the relay blocks whose parahead is now known.
@@ -769,7 +807,7 @@ impl<TPlat: PlatformRef> ParachainBackgroundTask<TPlat> {
                 // The relay chain runtime service has some kind of gap or issue and has discarded
                 // the runtime.
                 // Destroy the subscription and recreate the channels.
-                log::debug!(target: &self.log_target, "Subscriptions <= Reset");
+                util::log!(Debug, &self.log_target, "Subscriptions <= Reset");
                 self.subscription_state = ParachainBackgroundState::NotSubscribed {
                     all_subscriptions: Vec::new(),
                     subscribe_future: {
@@ -798,18 +836,28 @@ impl<TPlat: PlatformRef> ParachainBackgroundTask<TPlat> {
                     .await
                     && !error.is_network_problem()
                 {
-                    log::error!(
-                        target: &self.log_target,
+                    util::log!(
+                        Error,
+                        &self.log_target,
                         "Failed to fetch the parachain head from relay chain blocks {}: {}",
-                        runtime_subscription.async_tree.async_op_blocks(async_op_id).map(|b| HashDisplay(b)).join(", "),
+                        runtime_subscription
+                            .async_tree
+                            .async_op_blocks(async_op_id)
+                            .map(|b| HashDisplay(b))
+                            .join(", "),
                         error
                     );
                 }
 
-                log::debug!(
-                    target: &self.log_target,
+                util::log!(
+                    Debug,
+                    &self.log_target,
                     "ParaheadFetchOperations => Error(relay_blocks={}, error={:?})",
-                    runtime_subscription.async_tree.async_op_blocks(async_op_id).map(|b| HashDisplay(b)).join(","),
+                    runtime_subscription
+                        .async_tree
+                        .async_op_blocks(async_op_id)
+                        .map(|b| HashDisplay(b))
+                        .join(","),
                     error
                 );
 
@@ -874,8 +922,9 @@ impl<TPlat: PlatformRef> ParachainBackgroundTask<TPlat> {
                         }
                     }
 
-                    log::debug!(
-                        target: &self.log_target,
+                    util::log!(
+                        Debug,
+                        &self.log_target,
                         "Subscriptions <= ParablockFinalized(hash={})",
                         HashDisplay(&hash)
                     );
@@ -942,8 +991,9 @@ impl<TPlat: PlatformRef> ParachainBackgroundTask<TPlat> {
                                 .await;
                         }
 
-                        log::debug!(
-                            target: &self.log_target,
+                        util::log!(
+                            Debug,
+                            &self.log_target,
                             "Subscriptions <= BestBlockChanged(hash={})",
                             HashDisplay(&parahash)
                         );
@@ -1012,8 +1062,9 @@ impl<TPlat: PlatformRef> ParachainBackgroundTask<TPlat> {
                                     .await;
                             }
 
-                            log::debug!(
-                                target: &self.log_target,
+                            util::log!(
+                                Debug,
+                                &self.log_target,
                                 "Subscriptions <= BestBlockChanged(hash={})",
                                 HashDisplay(&parahash)
                             );
@@ -1034,8 +1085,9 @@ impl<TPlat: PlatformRef> ParachainBackgroundTask<TPlat> {
                         continue;
                     }
 
-                    log::debug!(
-                        target: &self.log_target,
+                    util::log!(
+                        Debug,
+                        &self.log_target,
                         "Subscriptions <= NewParablock(hash={})",
                         HashDisplay(&parahash)
                     );
@@ -1092,8 +1144,9 @@ impl<TPlat: PlatformRef> ParachainBackgroundTask<TPlat> {
                 best_block_hash,
                 ..
             } => {
-                log::debug!(
-                    target: &self.log_target,
+                util::log!(
+                    Debug,
+                    &self.log_target,
                     "RelayChain => Finalized(hash={})",
                     HashDisplay(&hash)
                 );
@@ -1117,8 +1170,9 @@ impl<TPlat: PlatformRef> ParachainBackgroundTask<TPlat> {
             runtime_service::Notification::Block(block) => {
                 let hash = header::hash_from_scale_encoded_header(&block.scale_encoded_header);
 
-                log::debug!(
-                    target: &self.log_target,
+                util::log!(
+                    Debug,
+                    &self.log_target,
                     "RelayChain => Block(hash={}, parent_hash={})",
                     HashDisplay(&hash),
                     HashDisplay(&block.parent_hash)
@@ -1137,8 +1191,9 @@ impl<TPlat: PlatformRef> ParachainBackgroundTask<TPlat> {
                 );
             }
             runtime_service::Notification::BestBlockChanged { hash } => {
-                log::debug!(
-                    target: &self.log_target,
+                util::log!(
+                    Debug,
+                    &self.log_target,
                     "RelayChain => BestBlockChanged(hash={})",
                     HashDisplay(&hash)
                 );
@@ -1162,14 +1217,15 @@ impl<TPlat: PlatformRef> ParachainBackgroundTask<TPlat> {
         relay_chain_subscribe_all: runtime_service::SubscribeAll<TPlat>,
     ) {
         // Subscription finished.
-        log::debug!(
-            target: &self.log_target,
+        util::log!(
+            Debug,
+            &self.log_target,
             "RelayChain => NewSubscription(finalized_hash={})",
             HashDisplay(&header::hash_from_scale_encoded_header(
                 &relay_chain_subscribe_all.finalized_block_scale_encoded_header
             ))
         );
-        log::debug!(target: &self.log_target, "ParaheadFetchOperations <= Clear");
+        util::log!(Debug, &self.log_target, "ParaheadFetchOperations <= Clear");
 
         let async_tree = {
             let mut async_tree =
@@ -1177,6 +1233,7 @@ impl<TPlat: PlatformRef> ParachainBackgroundTask<TPlat> {
                     finalized_async_user_data: None,
                     retry_after_failed: Duration::from_secs(5),
                     blocks_capacity: 32,
+                    best_block_policy: async_tree::BestBlockPolicy::FollowInput,
                 });
             let finalized_hash = header::hash_from_scale_encoded_header(
                 &relay_chain_subscribe_all.finalized_block_scale_encoded_header,
@@ -1241,6 +1298,7 @@ async fn parahead<TPlat: PlatformRef>(
             6,
             Duration::from_secs(10),
             NonZeroU32::new(2).unwrap(),
+            None,
         )
         .await
         .map_err(ParaheadError::Call)?;
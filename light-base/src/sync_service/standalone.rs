@@ -16,15 +16,16 @@
 // along with this program.  If not, see <http://www.gnu.org/licenses/>.
 
 use super::{
-    BlockNotification, ConfigRelayChainRuntimeCodeHint, FinalizedBlockRuntime, Notification,
-    SubscribeAll, ToBackground,
+    BlockNotification, ConfigRelayChain, FinalityEquivocation, FinalityEquivocationProof,
+    FinalizedBlockRuntime, GapSyncProgress, Notification, SubscribeAll, SyncPhase, ToBackground,
+    VerificationFailure, VerificationFailureKind,
 };
 use crate::{network_service, platform::PlatformRef, util};
 
 use alloc::{
     borrow::{Cow, ToOwned as _},
     boxed::Box,
-    string::{String, ToString as _},
+    string::ToString as _,
     sync::Arc,
     vec::Vec,
 };
@@ -41,22 +42,81 @@ use smoldot::{
     chain, header,
     informant::HashDisplay,
     libp2p,
-    network::{self, protocol},
+    network::{self, codec},
     sync::all,
 };
 
+/// Reputation change applied to a peer that has sent data that has failed to verify.
+const VERIFICATION_FAILURE_REPUTATION_CHANGE: i32 = -50;
+
 /// Starts a sync service background task to synchronize a standalone chain (relay chain or not).
 pub(super) async fn start_standalone_chain<TPlat: PlatformRef>(
-    log_target: String,
+    log_target: util::LogTarget,
     platform: TPlat,
-    chain_information: chain::chain_information::ValidChainInformation,
+    config: ConfigRelayChain,
     block_number_bytes: usize,
-    runtime_code_hint: Option<ConfigRelayChainRuntimeCodeHint>,
     mut from_foreground: async_channel::Receiver<ToBackground>,
-    network_service: Arc<network_service::NetworkService<TPlat>>,
-    network_chain_id: network_service::ChainId,
+    (network_service, network_chain_id): (
+        Arc<network_service::NetworkService<TPlat>>,
+        network_service::ChainId,
+    ),
     mut from_network_service: stream::BoxStream<'static, network_service::Event>,
 ) {
+    let ConfigRelayChain {
+        chain_information,
+        runtime_code_hint,
+        gap_sync,
+        pseudo_finality_depth,
+        bad_blocks,
+        fork_blocks,
+        recent_headers,
+    } = config;
+
+    // Only keep the recent headers that form a chain ending at the finalized block.
+    let recent_headers = {
+        let mut by_hash = recent_headers
+            .into_iter()
+            .filter_map(|scale_encoded_header| {
+                let decoded = header::decode(&scale_encoded_header, block_number_bytes).ok()?;
+                let parent_hash = *decoded.parent_hash;
+                Some((
+                    header::hash_from_scale_encoded_header(&scale_encoded_header),
+                    (parent_hash, scale_encoded_header),
+                ))
+            })
+            .collect::<HashMap<_, _, fnv::FnvBuildHasher>>();
+
+        let mut kept = HashMap::with_capacity_and_hasher(by_hash.len(), Default::default());
+        let mut next = *chain_information
+            .as_ref()
+            .finalized_block_header
+            .parent_hash;
+        while let Some((parent_hash, scale_encoded_header)) = by_hash.remove(&next) {
+            kept.insert(next, scale_encoded_header);
+            next = parent_hash;
+        }
+        kept
+    };
+
+    let gap_sync = if gap_sync {
+        Some(GapSync {
+            checkpoint_block_number: chain_information.as_ref().finalized_block_header.number,
+            checkpoint_block_hash: chain_information
+                .as_ref()
+                .finalized_block_header
+                .hash(block_number_bytes),
+            warp_sync_block_number: None,
+            next_download: None,
+            headers: HashMap::with_capacity_and_hasher(0, Default::default()),
+            // This constant corresponds to the maximum number of blocks that nodes will answer
+            // in one request.
+            max_blocks_per_request: NonZeroU32::new(64).unwrap(),
+            request_planner: None,
+        })
+    } else {
+        None
+    };
+
     let mut task = Task {
         sync: all::AllSync::new(all::Config {
             chain_information,
@@ -76,6 +136,8 @@ pub(super) async fn start_standalone_chain<TPlat: PlatformRef>(
             },
             max_disjoint_headers: 1024,
             max_requests_per_block: NonZeroU32::new(3).unwrap(),
+            // Matches the maximum number of blocks requested at once when starting requests.
+            max_ancestry_search_depth: NonZeroU64::new(64).unwrap(),
             download_ahead_blocks: {
                 // Verifying a block mostly consists in:
                 //
@@ -98,16 +160,24 @@ pub(super) async fn start_standalone_chain<TPlat: PlatformRef>(
                 storage_value: hint.storage_value,
                 closest_ancestor_excluding: hint.closest_ancestor_excluding,
             }),
+            bad_blocks,
+            fork_blocks,
         }),
         network_up_to_date_best: true,
         network_up_to_date_finalized: true,
         known_finalized_runtime: None,
         pending_requests: stream::FuturesUnordered::new(),
+        gap_sync,
+        gap_sync_request: None,
+        recent_headers,
+        pseudo_finality_depth,
         warp_sync_taking_long_time_warning: future::Either::Left(Box::pin(
             platform.sleep(Duration::from_secs(10)),
         ))
         .fuse(),
         all_notifications: Vec::<async_channel::Sender<Notification>>::new(),
+        verification_failures_subscribers: Vec::new(),
+        finality_equivocations_subscribers: Vec::new(),
         log_target,
         network_service,
         network_chain_id,
@@ -140,6 +210,10 @@ pub(super) async fn start_standalone_chain<TPlat: PlatformRef>(
             // syncing state machine would like to start.
             if task.start_next_request() {
                 queue_empty = false;
+            } else {
+                // Downloading the missing headers has a lower priority than the syncing itself,
+                // and is thus only done if the syncing doesn't need to start any request.
+                task.start_gap_sync_request();
             }
 
             // TODO: handle obsolete requests
@@ -220,6 +294,10 @@ pub(super) async fn start_standalone_chain<TPlat: PlatformRef>(
             ForegroundMessage(ToBackground),
             ForegroundClosed,
             RequestFinished(all::RequestId, Result<RequestOutcome, future::Aborted>),
+            GapSyncRequestFinished(
+                libp2p::PeerId,
+                Result<Vec<codec::BlockData>, network_service::BlocksRequestError>,
+            ),
             WarpSyncTakingLongTimeWarning,
             MustLoopAgain,
         }
@@ -242,6 +320,15 @@ pub(super) async fn start_standalone_chain<TPlat: PlatformRef>(
                 let (request_id, result) = task.pending_requests.select_next_some().await;
                 WhatHappened::RequestFinished(request_id, result)
             })
+            .or(async {
+                let Some(request) = task.gap_sync_request.as_mut() else {
+                    future::pending::<()>().await;
+                    unreachable!()
+                };
+                let (peer_id, result) = request.await;
+                task.gap_sync_request = None;
+                WhatHappened::GapSyncRequestFinished(peer_id, result)
+            })
             .or(async {
                 (&mut task.warp_sync_taking_long_time_warning).await;
                 task.warp_sync_taking_long_time_warning =
@@ -338,6 +425,29 @@ pub(super) async fn start_standalone_chain<TPlat: PlatformRef>(
                             )
                             .1
                     }
+                    RequestOutcome::WarpSync(Err(
+                        network_service::WarpSyncRequestError::Request(
+                            network::service::GrandpaWarpSyncRequestError::Request(
+                                network::service::RequestError::Substream(
+                                    libp2p::connection::established::RequestError::ProtocolNotAvailable,
+                                ),
+                            ),
+                        ),
+                    )) => {
+                        // The peer doesn't support warp syncing. If no peer does, the sync
+                        // state machine falls back to downloading headers from the checkpoint.
+                        let was_fallback = task.sync.is_warp_sync_header_sync_fallback();
+                        task.sync.grandpa_warp_sync_response_unsupported(request_id);
+                        if !was_fallback && task.sync.is_warp_sync_header_sync_fallback() {
+                            util::log!(
+                                Info,
+                                &task.log_target,
+                                "No peer supports GrandPa warp syncing. Synchronizing headers starting from block #{} instead.",
+                                task.sync.finalized_block_header().number
+                            );
+                        }
+                        continue;
+                    }
                     RequestOutcome::WarpSync(Err(_)) => {
                         // TODO: should disconnect peer
                         task.sync.grandpa_warp_sync_response_err(request_id);
@@ -355,6 +465,11 @@ pub(super) async fn start_standalone_chain<TPlat: PlatformRef>(
                 }
             }
 
+            WhatHappened::GapSyncRequestFinished(peer_id, result) => {
+                task.gap_sync_request_response(peer_id, result).await;
+                continue;
+            }
+
             WhatHappened::WarpSyncTakingLongTimeWarning => {
                 match task.sync.status() {
                     all::Status::Sync => {}
@@ -363,8 +478,9 @@ pub(super) async fn start_standalone_chain<TPlat: PlatformRef>(
                         finalized_block_hash,
                         finalized_block_number,
                     } => {
-                        log::warn!(
-                            target: &task.log_target,
+                        util::log!(
+                            Warn,
+                            &task.log_target,
                             "GrandPa warp sync idle at block #{} (0x{})",
                             finalized_block_number,
                             HashDisplay(&finalized_block_hash),
@@ -379,8 +495,9 @@ pub(super) async fn start_standalone_chain<TPlat: PlatformRef>(
                         finalized_block_hash,
                         finalized_block_number,
                     } => {
-                        log::warn!(
-                            target: &task.log_target,
+                        util::log!(
+                            Warn,
+                            &task.log_target,
                             "GrandPa warp sync in progress. Block: #{} (0x{}).",
                             finalized_block_number,
                             HashDisplay(&finalized_block_hash)
@@ -403,13 +520,21 @@ pub(super) async fn start_standalone_chain<TPlat: PlatformRef>(
             | all::ResponseOutcome::Queued
             | all::ResponseOutcome::NotFinalizedChain { .. }
             | all::ResponseOutcome::AllAlreadyInChain { .. } => {}
+            all::ResponseOutcome::BadBlock => {
+                util::log!(
+                    Warn,
+                    &task.log_target,
+                    "Discarded response containing a block marked as bad in the chain \
+                    specification"
+                );
+            }
         }
     }
 }
 
 struct Task<TPlat: PlatformRef> {
     /// Log target to use for all logs that are emitted.
-    log_target: String,
+    log_target: util::LogTarget,
 
     /// Access to the platform's capabilities.
     platform: TPlat,
@@ -419,7 +544,7 @@ struct Task<TPlat: PlatformRef> {
     ///
     /// For each request, we store a [`future::AbortHandle`] that can be used to abort the
     /// request if desired.
-    sync: all::AllSync<future::AbortHandle, (libp2p::PeerId, protocol::Role), ()>,
+    sync: all::AllSync<future::AbortHandle, (libp2p::PeerId, codec::Role), ()>,
 
     /// If `Some`, contains the runtime of the current finalized block.
     known_finalized_runtime: Option<FinalizedBlockRuntime>,
@@ -437,6 +562,12 @@ struct Task<TPlat: PlatformRef> {
     /// All event subscribers that are interested in events about the chain.
     all_notifications: Vec<async_channel::Sender<Notification>>,
 
+    /// All subscribers that are interested in verification failures.
+    verification_failures_subscribers: Vec<async_channel::Sender<VerificationFailure>>,
+
+    /// All subscribers that are interested in finality equivocations.
+    finality_equivocations_subscribers: Vec<async_channel::Sender<FinalityEquivocation>>,
+
     /// Contains a `Delay` after which we print a warning about GrandPa warp sync taking a long
     /// time. Set to `Pending` after the warp sync has finished, so that future remains pending
     /// forever.
@@ -453,10 +584,71 @@ struct Task<TPlat: PlatformRef> {
     pending_requests: stream::FuturesUnordered<
         future::BoxFuture<'static, (all::RequestId, Result<RequestOutcome, future::Aborted>)>,
     >,
+
+    /// State of the download of the headers between the checkpoint and the block reached by the
+    /// warp sync. `None` if disabled by the configuration.
+    gap_sync: Option<GapSync>,
+
+    /// SCALE-encoded headers passed through [`ConfigRelayChain::recent_headers`], indexed by hash.
+    recent_headers: HashMap<[u8; 32], Vec<u8>, fnv::FnvBuildHasher>,
+
+    /// Request in progress that downloads headers for [`Task::gap_sync`], alongside with the
+    /// peer the request has been sent to.
+    gap_sync_request: Option<
+        future::BoxFuture<
+            'static,
+            (
+                libp2p::PeerId,
+                Result<Vec<codec::BlockData>, network_service::BlocksRequestError>,
+            ),
+        >,
+    >,
+
+    /// See [`ConfigRelayChain::pseudo_finality_depth`].
+    pseudo_finality_depth: Option<NonZeroU64>,
+}
+
+/// See [`Task::gap_sync`].
+struct GapSync {
+    /// Number of the finalized block when the task started.
+    checkpoint_block_number: u64,
+    /// Hash of the finalized block when the task started.
+    checkpoint_block_hash: [u8; 32],
+    /// Number of the block reached by the latest warp sync, if any.
+    warp_sync_block_number: Option<u64>,
+    /// Number and hash of the next block whose header must be downloaded. `None` if no warp sync
+    /// has finished yet or if all the headers have been downloaded.
+    next_download: Option<(u64, [u8; 32])>,
+    /// SCALE-encoded headers that have been downloaded, indexed by hash, alongside with their
+    /// parent hash.
+    headers: HashMap<[u8; 32], ([u8; 32], Vec<u8>), fnv::FnvBuildHasher>,
+    /// Number of blocks to request at once. Lowered when a peer sends back fewer blocks than
+    /// requested.
+    max_blocks_per_request: NonZeroU32,
+    /// Planner of the request in [`Task::gap_sync_request`], if any.
+    request_planner: Option<codec::BlocksRequestPlanner>,
+}
+
+impl GapSync {
+    /// Updates [`GapSync::next_download`] by skipping over the blocks that are already known.
+    fn skip_known_blocks(&mut self) {
+        while let Some((number, hash)) = self.next_download {
+            if number <= self.checkpoint_block_number {
+                self.next_download = None;
+                break;
+            }
+
+            let Some((parent_hash, _)) = self.headers.get(&hash) else {
+                break;
+            };
+
+            self.next_download = Some((number - 1, *parent_hash));
+        }
+    }
 }
 
 enum RequestOutcome {
-    Block(Result<Vec<protocol::BlockData>, network_service::BlocksRequestError>),
+    Block(Result<Vec<codec::BlockData>, network_service::BlocksRequestError>),
     WarpSync(
         Result<
             network::service::EncodedGrandpaWarpSyncResponse,
@@ -507,22 +699,22 @@ impl<TPlat: PlatformRef> Task<TPlat> {
                 let block_request = self.network_service.clone().blocks_request(
                     peer_id,
                     self.network_chain_id,
-                    network::protocol::BlocksRequestConfig {
+                    network::codec::BlocksRequestConfig {
                         start: if let Some(first_block_hash) = first_block_hash {
-                            network::protocol::BlocksRequestConfigStart::Hash(first_block_hash)
+                            network::codec::BlocksRequestConfigStart::Hash(first_block_hash)
                         } else {
-                            network::protocol::BlocksRequestConfigStart::Number(first_block_height)
+                            network::codec::BlocksRequestConfigStart::Number(first_block_height)
                         },
                         desired_count: NonZeroU32::new(
                             u32::try_from(num_blocks.get()).unwrap_or(u32::max_value()),
                         )
                         .unwrap(),
                         direction: if ascending {
-                            network::protocol::BlocksRequestDirection::Ascending
+                            network::codec::BlocksRequestDirection::Ascending
                         } else {
-                            network::protocol::BlocksRequestDirection::Descending
+                            network::codec::BlocksRequestDirection::Descending
                         },
-                        fields: network::protocol::BlocksRequestFields {
+                        fields: network::codec::BlocksRequestFields {
                             header: request_headers,
                             body: request_bodies,
                             justifications: request_justification,
@@ -580,7 +772,7 @@ impl<TPlat: PlatformRef> Task<TPlat> {
                 let storage_request = self.network_service.clone().storage_proof_request(
                     self.network_chain_id,
                     peer_id,
-                    network::protocol::StorageProofRequestConfig {
+                    network::codec::StorageProofRequestConfig {
                         block_hash,
                         keys: keys.clone().into_iter(),
                     },
@@ -625,7 +817,7 @@ impl<TPlat: PlatformRef> Task<TPlat> {
                     let rq = network_service.call_proof_request(
                         network_chain_id,
                         peer_id,
-                        network::protocol::CallProofRequestConfig {
+                        network::codec::CallProofRequestConfig {
                             block_hash,
                             method: function_name,
                             parameter_vectored: iter::once(parameter_vectored),
@@ -678,17 +870,28 @@ impl<TPlat: PlatformRef> Task<TPlat> {
                 let elapsed = self.platform.now() - before_instant;
                 match error {
                     Ok(()) => {
-                        log::debug!(
-                            target: &self.log_target,
+                        util::log!(
+                            Debug,
+                            &self.log_target,
                             "Sync => WarpSyncRuntimeBuild(success=true, duration={:?})",
                             elapsed
                         );
                     }
                     Err(err) => {
                         // TODO: should disconnect peer
-                        log::debug!(target: &self.log_target, "Sync => WarpSyncRuntimeBuild(error={})", err);
+                        util::log!(
+                            Debug,
+                            &self.log_target,
+                            "Sync => WarpSyncRuntimeBuild(error={})",
+                            err
+                        );
                         if !matches!(err, all::WarpSyncBuildRuntimeError::SourceMisbehavior(_)) {
-                            log::warn!(target: &self.log_target, "Failed to compile runtime during warp syncing process: {}", err);
+                            util::log!(
+                                Warn,
+                                &self.log_target,
+                                "Failed to compile runtime during warp syncing process: {}",
+                                err
+                            );
                         }
                     }
                 };
@@ -699,16 +902,25 @@ impl<TPlat: PlatformRef> Task<TPlat> {
                 let (new_sync, error) = req.build();
                 match error {
                     Ok(()) => {
-                        log::debug!(target: &self.log_target, "Sync => WarpSyncBuildChainInformation(success=true)")
+                        util::log!(
+                            Debug,
+                            &self.log_target,
+                            "Sync => WarpSyncBuildChainInformation(success=true)"
+                        )
                     }
                     Err(err) => {
                         // TODO: should disconnect peer
-                        log::debug!(target: &self.log_target, "Sync => WarpSyncBuildChainInformation(error={})", err);
+                        util::log!(
+                            Debug,
+                            &self.log_target,
+                            "Sync => WarpSyncBuildChainInformation(error={})",
+                            err
+                        );
                         if !matches!(
                             err,
                             all::WarpSyncBuildChainInformationError::SourceMisbehavior(_)
                         ) {
-                            log::warn!(target: &self.log_target, "Failed to build the chain information during warp syncing process: {}", err);
+                            util::log!(Warn, &self.log_target, "Failed to build the chain information during warp syncing process: {}", err);
                         }
                     }
                 };
@@ -726,8 +938,9 @@ impl<TPlat: PlatformRef> Task<TPlat> {
                 self.sync = sync;
 
                 let finalized_header = self.sync.finalized_block_header();
-                log::info!(
-                    target: &self.log_target,
+                util::log!(
+                    Info,
+                    &self.log_target,
                     "GrandPa warp sync finished to #{} ({})",
                     finalized_header.number,
                     HashDisplay(&finalized_header.hash(self.sync.block_number_bytes()))
@@ -736,6 +949,23 @@ impl<TPlat: PlatformRef> Task<TPlat> {
                 self.warp_sync_taking_long_time_warning =
                     future::Either::Right(future::pending()).fuse();
 
+                if let Some(gap_sync) = &mut self.gap_sync {
+                    gap_sync.warp_sync_block_number = Some(finalized_header.number);
+                    if finalized_header.number > gap_sync.checkpoint_block_number + 1 {
+                        util::log!(
+                            Info,
+                            &self.log_target,
+                            "Downloading in the background the headers between block #{} and \
+                            block #{}",
+                            gap_sync.checkpoint_block_number,
+                            finalized_header.number
+                        );
+                        gap_sync.next_download =
+                            Some((finalized_header.number - 1, *finalized_header.parent_hash));
+                        gap_sync.skip_known_blocks();
+                    }
+                }
+
                 debug_assert!(self.known_finalized_runtime.is_none());
                 self.known_finalized_runtime = Some(FinalizedBlockRuntime {
                     virtual_machine: finalized_block_runtime,
@@ -769,8 +999,8 @@ impl<TPlat: PlatformRef> Task<TPlat> {
                 match result {
                     Ok((fragment_hash, fragment_number)) => {
                         // TODO: must call `set_local_grandpa_state` and `set_local_best_block` so that other peers notify us of neighbor packets
-                        log::debug!(
-                            target: &self.log_target,
+                        util::log!(Debug,
+                            &self.log_target,
                             "Sync => WarpSyncFragmentVerified(sender={}, verified_hash={}, verified_height={fragment_number})",
                             sender_peer_id,
                             HashDisplay(&fragment_hash)
@@ -780,8 +1010,9 @@ impl<TPlat: PlatformRef> Task<TPlat> {
                         // TODO: should disconnect peer
                         let maybe_forced_change =
                             matches!(err, all::VerifyFragmentError::JustificationVerify(_));
-                        log::warn!(
-                            target: &self.log_target,
+                        util::log!(
+                            Warn,
+                            &self.log_target,
                             "Failed to verify warp sync fragment from {}: {}{}",
                             sender_peer_id,
                             err,
@@ -789,7 +1020,9 @@ impl<TPlat: PlatformRef> Task<TPlat> {
                                 ". This might be caused by a forced GrandPa authorities change having \
                                 been enacted on the chain. If this is the case, please update the \
                                 chain specification with a checkpoint past this forced change."
-                            } else { "" }
+                            } else {
+                                ""
+                            }
                         );
                     }
                 }
@@ -798,6 +1031,11 @@ impl<TPlat: PlatformRef> Task<TPlat> {
             all::ProcessOne::VerifyBlock(verify) => {
                 // Header to verify.
                 let verified_hash = verify.hash();
+                // The peer that has sent the header must be obtained before the verification,
+                // as the block is discarded if it turns out to be invalid.
+                let sender = verify
+                    .header_sender()
+                    .map(|(_, (peer_id, _))| peer_id.clone());
                 match verify.verify_header(self.platform.now_from_unix_epoch()) {
                     all::HeaderVerifyOutcome::Success {
                         success,
@@ -807,8 +1045,9 @@ impl<TPlat: PlatformRef> Task<TPlat> {
                         let verified_height = success.height();
                         self.sync = success.finish(());
 
-                        log::debug!(
-                            target: &self.log_target,
+                        util::log!(
+                            Debug,
+                            &self.log_target,
                             "Sync => HeaderVerified(hash={}, new_best={})",
                             HashDisplay(&verified_hash),
                             if is_new_best { "yes" } else { "no" }
@@ -855,7 +1094,7 @@ impl<TPlat: PlatformRef> Task<TPlat> {
                             let mut all_sources = self
                                 .sync
                                 .sources()
-                                .filter(|s| matches!(self.sync[*s].1, protocol::Role::Light))
+                                .filter(|s| matches!(self.sync[*s].1, codec::Role::Light))
                                 .collect::<HashSet<_, fnv::FnvBuildHasher>>();
                             for knows in self
                                 .sync
@@ -871,7 +1110,7 @@ impl<TPlat: PlatformRef> Task<TPlat> {
                             // stay borrowed accross an `await`, which isn't possible because it
                             // doesn't implement `Sync`.
                             let (source_peer_id, _source_role) = &self.sync[source_id].clone();
-                            debug_assert!(matches!(_source_role, protocol::Role::Light));
+                            debug_assert!(matches!(_source_role, codec::Role::Light));
 
                             if self
                                 .network_service
@@ -885,8 +1124,9 @@ impl<TPlat: PlatformRef> Task<TPlat> {
                                 .await
                                 .is_ok()
                             {
-                                log::debug!(
-                                    target: &self.log_target,
+                                util::log!(
+                                    Debug,
+                                    &self.log_target,
                                     "Network <= BlockAnnounce(peer_id={}, hash={})",
                                     source_peer_id,
                                     HashDisplay(&verified_hash)
@@ -919,31 +1159,52 @@ impl<TPlat: PlatformRef> Task<TPlat> {
                                 parent_hash,
                             })
                         });
+
+                        if is_new_best {
+                            self.apply_pseudo_finality();
+                        }
                     }
 
                     all::HeaderVerifyOutcome::Error { sync, error, .. } => {
                         self.sync = sync;
 
-                        // TODO: print which peer sent the header
-                        log::debug!(
-                            target: &self.log_target,
-                            "Sync => HeaderVerifyError(hash={}, error={:?})",
+                        util::log!(
+                            Debug,
+                            &self.log_target,
+                            "Sync => HeaderVerifyError(hash={}, error={:?}, peer={})",
                             HashDisplay(&verified_hash),
-                            error
+                            error,
+                            sender
+                                .as_ref()
+                                .map_or("unknown".to_owned(), |p| p.to_string()),
                         );
 
-                        log::warn!(
-                            target: &self.log_target,
+                        util::log!(
+                            Warn,
+                            &self.log_target,
                             "Error while verifying header {}: {}",
                             HashDisplay(&verified_hash),
                             error
                         );
+
+                        // Errors that aren't caused by the content of the header, such as the
+                        // local clock being wrong, aren't the fault of the sender.
+                        if let (Some(peer_id), true) = (sender, error.is_bad_block()) {
+                            self.report_verification_failure(VerificationFailure {
+                                peer_id,
+                                block_hash: Some(verified_hash),
+                                kind: VerificationFailureKind::Header,
+                                error: error.to_string(),
+                            })
+                            .await;
+                        }
                     }
                 }
             }
 
             all::ProcessOne::VerifyFinalityProof(verify) => {
                 // Finality proof to verify.
+                let sender = verify.sender().map(|(_, (peer_id, _))| peer_id.clone());
                 match verify.perform({
                     let mut seed = [0; 32];
                     self.platform.fill_random_bytes(&mut seed);
@@ -959,73 +1220,132 @@ impl<TPlat: PlatformRef> Task<TPlat> {
                     ) => {
                         self.sync = sync;
 
-                        log::debug!(
-                            target: &self.log_target,
+                        util::log!(
+                            Debug,
+                            &self.log_target,
                             "Sync => FinalityProofVerified(finalized_blocks={})",
                             finalized_blocks_newest_to_oldest.len(),
                         );
 
-                        if updates_best_block {
-                            self.network_up_to_date_best = false;
-                        }
-                        self.network_up_to_date_finalized = false;
-                        // Invalidate the cache of the runtime of the finalized blocks if any
-                        // of the finalized blocks indicates that a runtime update happened.
-                        if finalized_blocks_newest_to_oldest
-                            .iter()
-                            .any(|b| b.header.digest.has_runtime_environment_updated())
-                        {
-                            self.known_finalized_runtime = None;
-                        }
-                        self.dispatch_all_subscribers(Notification::Finalized {
-                            hash: self
-                                .sync
-                                .finalized_block_header()
-                                .hash(self.sync.block_number_bytes()),
-                            best_block_hash: self.sync.best_block_hash(),
-                        });
+                        self.on_new_finalized(
+                            updates_best_block,
+                            &finalized_blocks_newest_to_oldest,
+                        );
                     }
 
                     (
                         sync,
                         all::FinalityProofVerifyOutcome::AlreadyFinalized
-                        | all::FinalityProofVerifyOutcome::GrandpaCommitPending,
+                        | all::FinalityProofVerifyOutcome::GrandpaCommitPending
+                        | all::FinalityProofVerifyOutcome::FinalityFrozen,
                     ) => {
                         self.sync = sync;
                     }
 
+                    (sync, all::FinalityProofVerifyOutcome::FinalityEquivocation(equivocation)) => {
+                        self.sync = sync;
+
+                        util::log!(
+                            Error,
+                            &self.log_target,
+                            "Finality equivocation detected: block #{} is finalized as both {} and \
+                            {}. The finality of the chain can no longer be trusted and will no \
+                            longer advance.",
+                            equivocation.block_number,
+                            HashDisplay(&equivocation.finalized_block_hash),
+                            HashDisplay(&equivocation.conflicting_block_hash),
+                        );
+
+                        let equivocation = FinalityEquivocation {
+                            peer_id: sender,
+                            block_number: equivocation.block_number,
+                            finalized_block_hash: equivocation.finalized_block_hash,
+                            conflicting_block_hash: equivocation.conflicting_block_hash,
+                            proof: match equivocation.proof {
+                                chain::blocks_tree::FinalityEquivocationProof::Justification(
+                                    proof,
+                                ) => FinalityEquivocationProof::Justification(proof),
+                                chain::blocks_tree::FinalityEquivocationProof::GrandpaCommit(
+                                    proof,
+                                ) => FinalityEquivocationProof::GrandpaCommit(proof),
+                            },
+                        };
+
+                        // Subscribers whose channel is full are kept, and the equivocation is
+                        // simply not delivered to them.
+                        for index in (0..self.finality_equivocations_subscribers.len()).rev() {
+                            let subscription =
+                                self.finality_equivocations_subscribers.swap_remove(index);
+                            if let Err(async_channel::TrySendError::Closed(_)) =
+                                subscription.try_send(equivocation.clone())
+                            {
+                                continue;
+                            }
+
+                            self.finality_equivocations_subscribers.push(subscription);
+                        }
+                    }
+
                     (sync, all::FinalityProofVerifyOutcome::JustificationError(error)) => {
                         self.sync = sync;
 
-                        // TODO: print which peer sent the proof
-                        log::debug!(
-                            target: &self.log_target,
-                            "Sync => JustificationVerificationError(error={:?})",
+                        util::log!(
+                            Debug,
+                            &self.log_target,
+                            "Sync => JustificationVerificationError(error={:?}, peer={})",
                             error,
+                            sender
+                                .as_ref()
+                                .map_or("unknown".to_owned(), |p| p.to_string()),
                         );
 
-                        log::warn!(
-                            target: &self.log_target,
+                        util::log!(
+                            Warn,
+                            &self.log_target,
                             "Error while verifying justification: {}",
                             error
                         );
+
+                        if let Some(peer_id) = sender {
+                            self.report_verification_failure(VerificationFailure {
+                                peer_id,
+                                block_hash: None,
+                                kind: VerificationFailureKind::Justification,
+                                error: error.to_string(),
+                            })
+                            .await;
+                        }
                     }
 
                     (sync, all::FinalityProofVerifyOutcome::GrandpaCommitError(error)) => {
                         self.sync = sync;
 
-                        // TODO: print which peer sent the proof
-                        log::debug!(
-                            target: &self.log_target,
-                            "Sync => GrandpaCommitVerificationError(error={:?})",
+                        util::log!(
+                            Debug,
+                            &self.log_target,
+                            "Sync => GrandpaCommitVerificationError(error={:?}, peer={})",
                             error,
+                            sender
+                                .as_ref()
+                                .map_or("unknown".to_owned(), |p| p.to_string()),
                         );
 
-                        log::warn!(
-                            target: &self.log_target,
+                        util::log!(
+                            Warn,
+                            &self.log_target,
                             "Error while verifying GrandPa commit: {}",
                             error
                         );
+
+                        if let Some(peer_id) = sender {
+                            self.report_verification_failure(VerificationFailure {
+                                peer_id,
+                                block_hash: None,
+                                kind: VerificationFailureKind::GrandpaCommit,
+                                error: error.to_string(),
+                            })
+                            .await;
+                        }
                     }
                 }
             }
@@ -1034,6 +1354,294 @@ impl<TPlat: PlatformRef> Task<TPlat> {
         (self, true)
     }
 
+    /// Updates the state of the task after some blocks have been finalized, and notifies the
+    /// subscribers.
+    fn on_new_finalized(
+        &mut self,
+        updates_best_block: bool,
+        finalized_blocks_newest_to_oldest: &[all::Block<()>],
+    ) {
+        if updates_best_block {
+            self.network_up_to_date_best = false;
+        }
+        self.network_up_to_date_finalized = false;
+        // Invalidate the cache of the runtime of the finalized blocks if any
+        // of the finalized blocks indicates that a runtime update happened.
+        if finalized_blocks_newest_to_oldest
+            .iter()
+            .any(|b| b.header.digest.has_runtime_environment_updated())
+        {
+            self.known_finalized_runtime = None;
+        }
+        self.dispatch_all_subscribers(Notification::Finalized {
+            hash: self
+                .sync
+                .finalized_block_header()
+                .hash(self.sync.block_number_bytes()),
+            best_block_hash: self.sync.best_block_hash(),
+        });
+    }
+
+    /// If [`Task::pseudo_finality_depth`] is `Some` and the chain doesn't have any finality
+    /// mechanism, finalizes the block of the best chain that is this number of blocks below the
+    /// best block.
+    fn apply_pseudo_finality(&mut self) {
+        let Some(depth) = self.pseudo_finality_depth else {
+            return;
+        };
+
+        if !matches!(
+            self.sync.as_chain_information().as_ref().finality,
+            chain::chain_information::ChainInformationFinalityRef::Outsourced
+        ) {
+            return;
+        }
+
+        let Some(target_number) = self.sync.best_block_number().checked_sub(depth.get()) else {
+            return;
+        };
+        if target_number <= self.sync.finalized_block_header().number {
+            return;
+        }
+
+        // Walk the best chain backwards until the target is reached.
+        let block_number_bytes = self.sync.block_number_bytes();
+        let parents = self
+            .sync
+            .non_finalized_blocks_unordered()
+            .map(|h| (h.hash(block_number_bytes), (h.number, *h.parent_hash)))
+            .collect::<HashMap<_, _, fnv::FnvBuildHasher>>();
+        let mut target_hash = self.sync.best_block_hash();
+        loop {
+            let (number, parent_hash) = parents[&target_hash];
+            if number == target_number {
+                break;
+            }
+            target_hash = parent_hash;
+        }
+
+        match self.sync.force_finalize(&target_hash) {
+            Ok(all::FinalityProofVerifyOutcome::NewFinalized {
+                updates_best_block,
+                finalized_blocks_newest_to_oldest,
+                ..
+            }) => {
+                util::log!(
+                    Debug,
+                    &self.log_target,
+                    "Sync => PseudoFinalized(hash={}, finalized_blocks={})",
+                    HashDisplay(&target_hash),
+                    finalized_blocks_newest_to_oldest.len(),
+                );
+
+                self.on_new_finalized(updates_best_block, &finalized_blocks_newest_to_oldest);
+            }
+            Ok(_) => unreachable!(),
+            Err(error) => {
+                util::log!(
+                    Debug,
+                    &self.log_target,
+                    "Sync => PseudoFinalityError(hash={}, error={:?})",
+                    HashDisplay(&target_hash),
+                    error
+                );
+            }
+        }
+    }
+
+    /// Starts a request towards a peer in order to download headers for [`Task::gap_sync`], if
+    /// necessary.
+    fn start_gap_sync_request(&mut self) {
+        if self.gap_sync_request.is_some() {
+            return;
+        }
+
+        let Some(gap_sync) = &self.gap_sync else {
+            return;
+        };
+        let Some((next_number, next_hash)) = gap_sync.next_download else {
+            return;
+        };
+
+        // Choose a random source among the ones that are idle and that are assumed to know the
+        // block.
+        let candidates = self
+            .sync
+            .sources()
+            .filter(|source_id| {
+                self.sync.source_num_ongoing_requests(*source_id) == 0
+                    && self.sync.source_best_block(*source_id).0 >= next_number
+            })
+            .collect::<Vec<_>>();
+        if candidates.is_empty() {
+            return;
+        }
+        let source_id = candidates[{
+            let mut index = [0; 4];
+            self.platform.fill_random_bytes(&mut index);
+            usize::try_from(u32::from_ne_bytes(index)).unwrap_or(0) % candidates.len()
+        }];
+
+        // A new planner is created for each request, as `next_download` might jump over blocks
+        // that are already known between two requests.
+        // `next_download` is guaranteed to be strictly above the checkpoint.
+        let planner = codec::BlocksRequestPlanner::new(codec::BlocksRequestPlannerConfig {
+            first_block_number: next_number,
+            first_block_hash: Some(next_hash),
+            num_blocks: NonZeroU64::new(next_number - gap_sync.checkpoint_block_number).unwrap(),
+            direction: codec::BlocksRequestDirection::Descending,
+            fields: codec::BlocksRequestFields {
+                header: true,
+                body: false,
+                justifications: false,
+            },
+            max_blocks_per_request: gap_sync.max_blocks_per_request,
+        });
+        let request_config = planner.next_request().unwrap();
+
+        util::log!(
+            Debug,
+            &self.log_target,
+            "GapSync => Request(start=#{}, num_blocks={})",
+            next_number,
+            request_config.desired_count
+        );
+
+        let peer_id = self.sync[source_id].0.clone();
+        let request = self.network_service.clone().blocks_request(
+            peer_id.clone(),
+            self.network_chain_id,
+            request_config,
+            Duration::from_secs(10),
+        );
+        self.gap_sync.as_mut().unwrap().request_planner = Some(planner);
+
+        // In case of failure, wait a bit before reporting the failure, in order to avoid
+        // immediately starting a new request that is likely to fail as well.
+        let platform = self.platform.clone();
+        self.gap_sync_request = Some(Box::pin(async move {
+            let result = request.await;
+            if result.is_err() {
+                platform.sleep(Duration::from_secs(5)).await;
+            }
+            (peer_id, result)
+        }));
+    }
+
+    /// Injects the response to a request started with [`Task::start_gap_sync_request`].
+    async fn gap_sync_request_response(
+        &mut self,
+        peer_id: libp2p::PeerId,
+        result: Result<Vec<codec::BlockData>, network_service::BlocksRequestError>,
+    ) {
+        let Some(gap_sync) = &mut self.gap_sync else {
+            return;
+        };
+        let Some(mut planner) = gap_sync.request_planner.take() else {
+            return;
+        };
+
+        let blocks = match result {
+            Ok(blocks) => blocks,
+            Err(err) => {
+                util::log!(Debug, &self.log_target, "GapSync => Error({})", err);
+                return;
+            }
+        };
+
+        let num_received = u32::try_from(blocks.len()).unwrap_or(u32::MAX);
+        let mut num_inserted = 0;
+        for block in blocks {
+            let Some((expected_number, expected_hash)) = gap_sync.next_download else {
+                break;
+            };
+
+            let Some(scale_encoded_header) = block.header else {
+                break;
+            };
+
+            // Because the headers are downloaded from the highest to the lowest, each header is
+            // verified by comparing its hash with the parent hash of the header above it.
+            let parent_hash =
+                match header::decode(&scale_encoded_header, self.sync.block_number_bytes()) {
+                    Ok(h)
+                        if h.number == expected_number
+                            && h.hash(self.sync.block_number_bytes()) == expected_hash =>
+                    {
+                        *h.parent_hash
+                    }
+                    _ => {
+                        util::log!(
+                            Debug,
+                            &self.log_target,
+                            "GapSync => InvalidHeader(expected=#{}, peer={})",
+                            expected_number,
+                            peer_id
+                        );
+                        self.network_service
+                            .report_peer(
+                                peer_id,
+                                self.network_chain_id,
+                                VERIFICATION_FAILURE_REPUTATION_CHANGE,
+                                "invalid-gap-sync-header",
+                            )
+                            .await;
+                        return;
+                    }
+                };
+
+            if expected_number == gap_sync.checkpoint_block_number + 1
+                && parent_hash != gap_sync.checkpoint_block_hash
+            {
+                // The warp sync has led to a chain that doesn't include the checkpoint.
+                util::log!(
+                    Warn,
+                    &self.log_target,
+                    "Block #{} (0x{}) downloaded during the headers backfill isn't a child of \
+                    the checkpoint (0x{})",
+                    expected_number,
+                    HashDisplay(&expected_hash),
+                    HashDisplay(&gap_sync.checkpoint_block_hash)
+                );
+                gap_sync.next_download = None;
+                return;
+            }
+
+            gap_sync
+                .headers
+                .insert(expected_hash, (parent_hash, scale_encoded_header));
+            gap_sync.next_download = Some((expected_number - 1, parent_hash));
+            gap_sync.skip_known_blocks();
+            num_inserted += 1;
+        }
+
+        // All the headers of the response are valid. If the peer has sent back fewer blocks
+        // than requested, request fewer blocks at once from now on.
+        if planner.inject_response(num_received, None).is_ok() {
+            gap_sync.max_blocks_per_request = planner.max_blocks_per_request();
+        }
+
+        util::log!(
+            Debug,
+            &self.log_target,
+            "GapSync => Response(num_inserted={}, next={:?})",
+            num_inserted,
+            gap_sync.next_download.map(|(n, _)| n)
+        );
+
+        if gap_sync.next_download.is_none() && num_inserted != 0 {
+            util::log!(
+                Info,
+                &self.log_target,
+                "Finished downloading the headers between block #{} and block #{}",
+                gap_sync.checkpoint_block_number,
+                gap_sync
+                    .warp_sync_block_number
+                    .unwrap_or(gap_sync.checkpoint_block_number)
+            );
+        }
+    }
+
     /// Process a request coming from the foreground service.
     fn process_foreground_message(&mut self, message: ToBackground) {
         match message {
@@ -1041,6 +1649,18 @@ impl<TPlat: PlatformRef> Task<TPlat> {
                 let _ = send_back.send(self.sync.is_near_head_of_chain_heuristic());
             }
 
+            ToBackground::SyncPhase { send_back } => {
+                let phase = match self.sync.status() {
+                    all::Status::Sync if self.sync.is_near_head_of_chain_heuristic() => {
+                        SyncPhase::NearHead
+                    }
+                    all::Status::Sync => SyncPhase::CatchingUp,
+                    all::Status::WarpSyncFragments { .. }
+                    | all::Status::WarpSyncChainInformation { .. } => SyncPhase::WarpSync,
+                };
+                let _ = send_back.send(phase);
+            }
+
             ToBackground::SubscribeAll {
                 send_back,
                 buffer_size,
@@ -1125,6 +1745,68 @@ impl<TPlat: PlatformRef> Task<TPlat> {
             ToBackground::SerializeChainInformation { send_back } => {
                 let _ = send_back.send(Some(self.sync.as_chain_information().into()));
             }
+
+            ToBackground::SubscribeVerificationFailures {
+                send_back,
+                buffer_size,
+            } => {
+                let (tx, rx) = async_channel::bounded(buffer_size.saturating_sub(1));
+                self.verification_failures_subscribers.push(tx);
+                let _ = send_back.send(rx);
+            }
+
+            ToBackground::SubscribeFinalityEquivocations {
+                send_back,
+                buffer_size,
+            } => {
+                let (tx, rx) = async_channel::bounded(buffer_size.saturating_sub(1));
+                self.finality_equivocations_subscribers.push(tx);
+                let _ = send_back.send(rx);
+            }
+            ToBackground::GapSyncHeader { send_back, hash } => {
+                let _ = send_back.send(
+                    self.gap_sync
+                        .as_ref()
+                        .and_then(|gap_sync| gap_sync.headers.get(&hash))
+                        .map(|(_, header)| header)
+                        .or_else(|| self.recent_headers.get(&hash))
+                        .cloned(),
+                );
+            }
+
+            ToBackground::GapSyncProgress { send_back } => {
+                let _ = send_back.send(self.gap_sync.as_ref().map(|gap_sync| GapSyncProgress {
+                    checkpoint_block_number: gap_sync.checkpoint_block_number,
+                    warp_sync_block_number: gap_sync.warp_sync_block_number,
+                    next_download_block_number: gap_sync.next_download.map(|(n, _)| n),
+                    num_downloaded_headers: gap_sync.headers.len(),
+                }));
+            }
+
+            ToBackground::RecentHeaders { send_back, max } => {
+                let mut headers = self
+                    .gap_sync
+                    .iter()
+                    .flat_map(|gap_sync| gap_sync.headers.values().map(|(_, header)| header))
+                    .chain(self.recent_headers.values())
+                    .filter_map(|scale_encoded_header| {
+                        let number =
+                            header::decode(scale_encoded_header, self.sync.block_number_bytes())
+                                .ok()?
+                                .number;
+                        Some((number, scale_encoded_header))
+                    })
+                    .collect::<Vec<_>>();
+                headers.sort_unstable_by(|(a, _), (b, _)| b.cmp(a));
+                headers.dedup_by_key(|(number, _)| *number);
+                let _ = send_back.send(
+                    headers
+                        .into_iter()
+                        .take(max)
+                        .map(|(_, header)| header.clone())
+                        .collect(),
+                );
+            }
         }
     }
 
@@ -1164,40 +1846,45 @@ impl<TPlat: PlatformRef> Task<TPlat> {
                 chain_id,
                 peer_id,
                 announce,
+                header: decoded_header,
             } if chain_id == self.network_chain_id => {
                 let sync_source_id = *self.peers_source_id_map.get(&peer_id).unwrap();
                 let decoded = announce.decode();
 
-                match header::decode(decoded.scale_encoded_header, self.sync.block_number_bytes()) {
+                match decoded_header {
                     Ok(decoded_header) => {
-                        log::debug!(
-                            target: &self.log_target,
+                        util::log!(
+                            Debug,
+                            &self.log_target,
                             "Sync <= BlockAnnounce(sender={}, hash={}, is_best={}, parent_hash={})",
                             peer_id,
-                            HashDisplay(&header::hash_from_scale_encoded_header(decoded.scale_encoded_header)),
+                            HashDisplay(&decoded_header.hash),
                             decoded.is_best,
-                            HashDisplay(decoded_header.parent_hash)
+                            HashDisplay(&decoded_header.parent_hash)
                         );
                     }
                     Err(error) => {
-                        log::debug!(
-                            target: &self.log_target,
+                        util::log!(Debug,
+                            &self.log_target,
                             "Sync <= BlockAnnounce(sender={}, hash={}, is_best={}, parent_hash=<unknown>)",
                             peer_id,
                             HashDisplay(&header::hash_from_scale_encoded_header(decoded.scale_encoded_header)),
                             decoded.is_best,
                         );
 
-                        log::debug!(
-                            target: &self.log_target,
+                        util::log!(
+                            Debug,
+                            &self.log_target,
                             "Sync => InvalidBlockHeader(error={})",
                             error
                         );
 
-                        log::warn!(
-                            target: &self.log_target,
+                        util::log!(
+                            Warn,
+                            &self.log_target,
                             "Failed to decode header in block announce received from {}. Error: {}",
-                            peer_id, error,
+                            peer_id,
+                            error,
                         )
                     }
                 }
@@ -1209,47 +1896,34 @@ impl<TPlat: PlatformRef> Task<TPlat> {
                 ) {
                     all::BlockAnnounceOutcome::HeaderVerify
                     | all::BlockAnnounceOutcome::AlreadyInChain => {
-                        log::debug!(
-                            target: &self.log_target,
-                            "Sync => Ok"
-                        );
+                        util::log!(Debug, &self.log_target, "Sync => Ok");
                     }
                     all::BlockAnnounceOutcome::Discarded => {
-                        log::debug!(
-                            target: &self.log_target,
-                            "Sync => Discarded"
-                        );
+                        util::log!(Debug, &self.log_target, "Sync => Discarded");
                     }
                     all::BlockAnnounceOutcome::StoredForLater {} => {
-                        log::debug!(
-                            target: &self.log_target,
-                            "Sync => StoredForLater"
-                        );
+                        util::log!(Debug, &self.log_target, "Sync => StoredForLater");
                     }
                     all::BlockAnnounceOutcome::TooOld {
                         announce_block_height,
                         ..
                     } => {
-                        log::debug!(
-                            target: &self.log_target,
-                            "Sync => TooOld"
-                        );
+                        util::log!(Debug, &self.log_target, "Sync => TooOld");
 
-                        log::warn!(
-                            target: &self.log_target,
+                        util::log!(
+                            Warn,
+                            &self.log_target,
                             "Block announce header height (#{}) from {} is below finalized block",
                             announce_block_height,
                             peer_id
                         );
                     }
                     all::BlockAnnounceOutcome::NotFinalizedChain => {
-                        log::debug!(
-                            target: &self.log_target,
-                            "Sync => NotFinalized"
-                        );
+                        util::log!(Debug, &self.log_target, "Sync => NotFinalized");
 
-                        log::warn!(
-                            target: &self.log_target,
+                        util::log!(
+                            Warn,
+                            &self.log_target,
                             "Block announce from {} isn't part of finalized chain",
                             peer_id
                         );
@@ -1257,6 +1931,17 @@ impl<TPlat: PlatformRef> Task<TPlat> {
                     all::BlockAnnounceOutcome::InvalidHeader(_) => {
                         // Log messages are already printed above.
                     }
+                    all::BlockAnnounceOutcome::BadBlock => {
+                        util::log!(Debug, &self.log_target, "Sync => BadBlock");
+
+                        util::log!(
+                            Warn,
+                            &self.log_target,
+                            "Block announce from {} concerns a block marked as bad in the chain \
+                            specification",
+                            peer_id
+                        );
+                    }
                 }
             }
 
@@ -1282,16 +1967,10 @@ impl<TPlat: PlatformRef> Task<TPlat> {
                 {
                     all::GrandpaCommitMessageOutcome::Queued => {
                         // TODO: print more details?
-                        log::debug!(
-                            target: &self.log_target,
-                            "Sync <= QueuedGrandpaCommit"
-                        );
+                        util::log!(Debug, &self.log_target, "Sync <= QueuedGrandpaCommit");
                     }
                     all::GrandpaCommitMessageOutcome::Discarded => {
-                        log::debug!(
-                            target: &self.log_target,
-                            "Sync <= IgnoredGrandpaCommit"
-                        );
+                        util::log!(Debug, &self.log_target, "Sync <= IgnoredGrandpaCommit");
                     }
                 }
             }
@@ -1302,6 +1981,37 @@ impl<TPlat: PlatformRef> Task<TPlat> {
         }
     }
 
+    /// Notifies the subscribers of a verification failure, then lowers the reputation of the
+    /// peer that has sent the invalid data. The networking service bans the peer if its
+    /// reputation falls too low.
+    async fn report_verification_failure(&mut self, failure: VerificationFailure) {
+        // Contrary to `all_notifications`, subscribers whose channel is full are kept, and the
+        // failure is simply not delivered to them.
+        for index in (0..self.verification_failures_subscribers.len()).rev() {
+            let subscription = self.verification_failures_subscribers.swap_remove(index);
+            if let Err(async_channel::TrySendError::Closed(_)) =
+                subscription.try_send(failure.clone())
+            {
+                continue;
+            }
+
+            self.verification_failures_subscribers.push(subscription);
+        }
+
+        self.network_service
+            .report_peer(
+                failure.peer_id,
+                self.network_chain_id,
+                VERIFICATION_FAILURE_REPUTATION_CHANGE,
+                match failure.kind {
+                    VerificationFailureKind::Header => "invalid-header",
+                    VerificationFailureKind::Justification => "invalid-justification",
+                    VerificationFailureKind::GrandpaCommit => "invalid-grandpa-commit",
+                },
+            )
+            .await;
+    }
+
     /// Sends a notification to all the notification receivers.
     fn dispatch_all_subscribers(&mut self, notification: Notification) {
         // Elements in `all_notifications` are removed one by one and inserted back if the
@@ -67,7 +67,7 @@
 //! transaction.
 //!
 
-use crate::{network_service, platform::PlatformRef, runtime_service, sync_service};
+use crate::{network_service, platform::PlatformRef, runtime_service, sync_service, util};
 
 use alloc::{
     borrow::ToOwned as _,
@@ -91,7 +91,7 @@ use smoldot::{
     header,
     informant::HashDisplay,
     libp2p::peer_id::PeerId,
-    network::protocol,
+    network::codec,
     transactions::{light_pool, validate},
 };
 
@@ -103,6 +103,9 @@ pub struct Config<TPlat: PlatformRef> {
     /// >           have been filtered out from this name.
     pub log_name: String,
 
+    /// Maximum level of the logs emitted by this service.
+    pub log_max_level: log::LevelFilter,
+
     /// Access to the platform's capabilities.
     pub platform: TPlat,
 
@@ -145,7 +148,10 @@ pub struct TransactionsService<TPlat> {
 impl<TPlat: PlatformRef> TransactionsService<TPlat> {
     /// Builds a new service.
     pub async fn new(config: Config<TPlat>) -> Self {
-        let log_target = format!("tx-service-{}", config.log_name);
+        let log_target = util::LogTarget::new(
+            format!("tx-service-{}", config.log_name),
+            config.log_max_level,
+        );
         let (to_background, from_foreground) = async_channel::bounded(8);
 
         let task = Box::pin(background_task::<TPlat>(BackgroundTaskConfig {
@@ -166,9 +172,9 @@ impl<TPlat: PlatformRef> TransactionsService<TPlat> {
 
         config
             .platform
-            .spawn_task(log_target.clone().into(), async move {
+            .spawn_task(log_target.to_string().into(), async move {
                 task.await;
-                log::debug!(target: &log_target, "Shutdown");
+                util::log!(Debug, &log_target, "Shutdown");
             });
 
         TransactionsService {
@@ -188,12 +194,15 @@ impl<TPlat: PlatformRef> TransactionsService<TPlat> {
     ///
     /// > **Note**: Dropping the value returned does not cancel sending out the transaction.
     ///
+    /// The `propagation` parameter indicates to which peers the transaction is gossiped.
+    ///
     /// If this exact same transaction has already been submitted before, the transaction isn't
     /// added a second time. Instead, a second channel is created pointing to the already-existing
-    /// transaction.
+    /// transaction. The [`PropagationPolicy`] of the already-existing transaction is kept.
     pub async fn submit_and_watch_transaction(
         &self,
         transaction_bytes: Vec<u8>,
+        propagation: PropagationPolicy,
         channel_size: usize,
     ) -> async_channel::Receiver<TransactionStatus> {
         let (updates_report, rx) = async_channel::bounded(channel_size);
@@ -201,6 +210,7 @@ impl<TPlat: PlatformRef> TransactionsService<TPlat> {
         self.to_background
             .send(ToBackground::SubmitTransaction {
                 transaction_bytes,
+                propagation,
                 updates_report: Some(updates_report),
             })
             .await
@@ -211,10 +221,15 @@ impl<TPlat: PlatformRef> TransactionsService<TPlat> {
 
     /// Similar to [`TransactionsService::submit_and_watch_transaction`], but doesn't return any
     /// channel.
-    pub async fn submit_transaction(&self, transaction_bytes: Vec<u8>) {
+    pub async fn submit_transaction(
+        &self,
+        transaction_bytes: Vec<u8>,
+        propagation: PropagationPolicy,
+    ) {
         self.to_background
             .send(ToBackground::SubmitTransaction {
                 transaction_bytes,
+                propagation,
                 updates_report: None,
             })
             .await
@@ -222,6 +237,32 @@ impl<TPlat: PlatformRef> TransactionsService<TPlat> {
     }
 }
 
+/// How a transaction submitted to the service is gossiped to the peers of the chain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PropagationPolicy {
+    /// The transaction is never gossiped. It is only validated and watched for inclusion in
+    /// blocks, for example because it is sent to block authors through a different channel.
+    None,
+    /// The transaction is gossiped to all the peers we are connected to.
+    AllPeers,
+    /// Every time the transaction is gossiped, it is only sent to at most the given number of
+    /// peers. These peers are chosen randomly every time, so that re-announcing the transaction
+    /// reaches other peers.
+    Subset(NonZeroUsize),
+}
+
+impl PropagationPolicy {
+    /// Returns the maximum number of peers to send the transaction to every time it is gossiped,
+    /// or `None` if it must never be gossiped.
+    fn max_peers(&self) -> Option<usize> {
+        match self {
+            PropagationPolicy::None => None,
+            PropagationPolicy::AllPeers => Some(usize::MAX),
+            PropagationPolicy::Subset(max_peers) => Some(max_peers.get()),
+        }
+    }
+}
+
 /// Update on the state of a transaction in the service.
 ///
 /// > **Note**: Because this code isn't an *actual* transactions pool that leverages the runtime,
@@ -240,10 +281,22 @@ pub enum TransactionStatus {
 
     /// The block in which a block is included has changed.
     IncludedBlockUpdate {
-        /// If `Some`, the transaction is included in the block of the best chain with the given
-        /// hash and at the given index. If `None`, the transaction isn't present in the best
-        /// chain.
-        block_hash: Option<([u8; 32], u32)>,
+        /// The transaction is included in the block of the best chain with the given hash and at
+        /// the given index.
+        block_hash: ([u8; 32], u32),
+    },
+
+    /// The block in which the transaction was included, as reported by the latest
+    /// [`TransactionStatus::IncludedBlockUpdate`], is no longer part of the best chain, either
+    /// because of a re-org or because it has been pruned following the finalization of one of
+    /// its siblings or uncles.
+    ///
+    /// The transaction is broadcasted again to peers, and a new
+    /// [`TransactionStatus::IncludedBlockUpdate`] is generated if it gets included in a block of
+    /// the best chain.
+    Retracted {
+        /// Hash of the block the transaction was included in.
+        block_hash: [u8; 32],
     },
 
     /// Transaction has been removed from the pool.
@@ -302,13 +355,14 @@ enum ValidationError {
 enum ToBackground {
     SubmitTransaction {
         transaction_bytes: Vec<u8>,
+        propagation: PropagationPolicy,
         updates_report: Option<async_channel::Sender<TransactionStatus>>,
     },
 }
 
 /// Configuration for [`background_task`̀].
 struct BackgroundTaskConfig<TPlat: PlatformRef> {
-    log_target: String,
+    log_target: util::LogTarget,
     platform: TPlat,
     sync_service: Arc<sync_service::SyncService<TPlat>>,
     runtime_service: Arc<runtime_service::RuntimeService<TPlat>>,
@@ -440,8 +494,9 @@ async fn background_task<TPlat: PlatformRef>(mut config: BackgroundTaskConfig<TP
         worker.validations_in_progress.clear();
         worker.next_reannounce.clear();
 
-        log::debug!(
-            target: &config.log_target,
+        util::log!(
+            Debug,
+            &config.log_target,
             "Reset(new_finalized={}. dropped-transactions={{{}}})",
             HashDisplay(&initial_finalized_block_hash),
             dropped_transactions
@@ -549,8 +604,9 @@ async fn background_task<TPlat: PlatformRef>(mut config: BackgroundTaskConfig<TP
                 let (tx_body, mut transaction) =
                     worker.pending_transactions.remove_transaction(tx_id);
 
-                log::debug!(
-                    target: &config.log_target,
+                util::log!(
+                    Debug,
+                    &config.log_target,
                     "Discarded(tx_hash={}, error={:?})",
                     HashDisplay(&blake2_hash(&tx_body)),
                     error,
@@ -602,7 +658,7 @@ async fn background_task<TPlat: PlatformRef>(mut config: BackgroundTaskConfig<TP
                     let download_future = worker.sync_service.clone().block_query(
                         block_number,
                         block_hash,
-                        protocol::BlocksRequestFields {
+                        codec::BlocksRequestFields {
                             body: true,
                             header: true, // TODO: must be true in order to avoid an error being generated, fix this in sync service
                             justifications: false,
@@ -626,8 +682,9 @@ async fn background_task<TPlat: PlatformRef>(mut config: BackgroundTaskConfig<TP
                     .unwrap()
                     .downloading = true;
 
-                log::debug!(
-                    target: &config.log_target,
+                util::log!(
+                    Debug,
+                    &config.log_target,
                     "BlockDownloads <= Start(block={})",
                     HashDisplay(&block_hash)
                 );
@@ -642,8 +699,9 @@ async fn background_task<TPlat: PlatformRef>(mut config: BackgroundTaskConfig<TP
                     .unpin_block(&block.block_hash)
                     .await;
 
-                log::debug!(
-                    target: &config.log_target,
+                util::log!(
+                    Debug,
+                    &config.log_target,
                     "Finalized(block={}, body-transactions={{{}}})",
                     HashDisplay(&block.block_hash),
                     block
@@ -684,8 +742,9 @@ async fn background_task<TPlat: PlatformRef>(mut config: BackgroundTaskConfig<TP
                                 worker.set_best_block(&config.log_target, &hash);
                             }
                         },
-                        Some(runtime_service::Notification::Finalized { hash, best_block_hash, .. }) => {
+                        Some(runtime_service::Notification::Finalized { hash, best_block_hash, pruned_blocks }) => {
                             worker.set_best_block(&config.log_target, &best_block_hash);
+                            worker.retract_pruned_blocks(&config.log_target, &pruned_blocks);
                             for pruned in worker
                                 .pending_transactions
                                 .set_finalized_block(&hash)
@@ -737,8 +796,8 @@ async fn background_task<TPlat: PlatformRef>(mut config: BackgroundTaskConfig<TP
                             .set_block_body(&block_hash, block_body.into_iter())
                             .collect::<Vec<_>>();
 
-                        log::debug!(
-                            target: &config.log_target,
+                        util::log!(Debug,
+                            &config.log_target,
                             "BlockDownloads => Success(block={}, included-transactions={{{}}})",
                             HashDisplay(&block_hash),
                             included_transactions.iter()
@@ -751,13 +810,13 @@ async fn background_task<TPlat: PlatformRef>(mut config: BackgroundTaskConfig<TP
                             let tx = worker.pending_transactions.transaction_user_data_mut(tx_id).unwrap();
                             // We assume that there's no more than 2<<32 transactions per block.
                             let body_index = u32::try_from(body_index).unwrap();
-                            tx.update_status(TransactionStatus::IncludedBlockUpdate { block_hash: Some((block_hash, body_index)) });
+                            tx.update_status(TransactionStatus::IncludedBlockUpdate { block_hash: (block_hash, body_index) });
                         }
 
                     } else {
                         block.failed_downloads = block.failed_downloads.saturating_add(1);
-                        log::debug!(
-                            target: &config.log_target,
+                        util::log!(Debug,
+                            &config.log_target,
                             "BlockDownloads => Failed(block={})",
                             HashDisplay(&block_hash)
                         );
@@ -791,7 +850,9 @@ async fn background_task<TPlat: PlatformRef>(mut config: BackgroundTaskConfig<TP
                         continue;
                     }
 
-                    // TODO: only announce if propagate is true
+                    let Some(max_peers) = tx.propagation.max_peers() else {
+                        continue;
+                    };
 
                     // Update transaction state for the next re-announce.
                     tx.when_reannounce = now + Duration::from_secs(5);
@@ -808,11 +869,12 @@ async fn background_task<TPlat: PlatformRef>(mut config: BackgroundTaskConfig<TP
                         .clone()
                         .announce_transaction(
                             worker.network_chain_id,
-                            worker.pending_transactions.scale_encoding(maybe_reannounce_tx_id).unwrap()
+                            worker.pending_transactions.scale_encoding(maybe_reannounce_tx_id).unwrap(),
+                            max_peers
                         )
                         .await;
-                    log::debug!(
-                        target: &config.log_target,
+                    util::log!(Debug,
+                        &config.log_target,
                         "NetworkService <= Announced(tx={}, peers={{{}}})",
                         HashDisplay(&blake2_hash(worker.pending_transactions.scale_encoding(maybe_reannounce_tx_id).unwrap())),
                         peers_sent.iter().join(", ")
@@ -852,8 +914,8 @@ async fn background_task<TPlat: PlatformRef>(mut config: BackgroundTaskConfig<TP
                     // possible for the validation to have been performed against a block
                     // that has already been finalized and removed from the pool.
                     if !worker.pending_transactions.has_block(&block_hash) {
-                        log::debug!(
-                            target: &config.log_target,
+                        util::log!(Debug,
+                            &config.log_target,
                             "TxValidations => ObsoleteBlock(tx={}, block={})",
                             HashDisplay(&tx_hash),
                             HashDisplay(&block_hash)
@@ -863,8 +925,8 @@ async fn background_task<TPlat: PlatformRef>(mut config: BackgroundTaskConfig<TP
 
                     let validation_result = match validation_result {
                         Ok(result) => {
-                            log::debug!(
-                                target: &config.log_target,
+                            util::log!(Debug,
+                                &config.log_target,
                                 "TxValidations => Success(tx={}, block={}, priority={}, longevity={}, propagate={:?})",
                                 HashDisplay(&tx_hash),
                                 HashDisplay(&block_hash),
@@ -873,8 +935,8 @@ async fn background_task<TPlat: PlatformRef>(mut config: BackgroundTaskConfig<TP
                                 result.propagate,
                             );
 
-                            log::info!(
-                                target: &config.log_target,
+                            util::log!(Info,
+                                &config.log_target,
                                 "Successfully validated transaction {}",
                                 HashDisplay(&tx_hash)
                             );
@@ -897,16 +959,16 @@ async fn background_task<TPlat: PlatformRef>(mut config: BackgroundTaskConfig<TP
                             continue 'channels_rebuild
                         }
                         Err(ValidationError::InvalidOrError(InvalidOrError::Invalid(error))) => {
-                            log::debug!(
-                                target: &config.log_target,
+                            util::log!(Debug,
+                                &config.log_target,
                                 "TxValidations => Invalid(tx={}, block={}, error={:?})",
                                 HashDisplay(&tx_hash),
                                 HashDisplay(&block_hash),
                                 error,
                             );
 
-                            log::warn!(
-                                target: &config.log_target,
+                            util::log!(Warn,
+                                &config.log_target,
                                 "Transaction {} invalid against block {}: {}",
                                 HashDisplay(&tx_hash),
                                 HashDisplay(&block_hash),
@@ -916,16 +978,16 @@ async fn background_task<TPlat: PlatformRef>(mut config: BackgroundTaskConfig<TP
                             Err(InvalidOrError::Invalid(error))
                         }
                         Err(ValidationError::InvalidOrError(InvalidOrError::ValidateError(error))) => {
-                            log::debug!(
-                                target: &config.log_target,
+                            util::log!(Debug,
+                                &config.log_target,
                                 "TxValidations => Error(tx={}, block={}, error={:?})",
                                 HashDisplay(&tx_hash),
                                 HashDisplay(&block_hash),
                                 error,
                             );
 
-                            log::warn!(
-                                target: &config.log_target,
+                            util::log!(Warn,
+                                &config.log_target,
                                 "Failed to validate transaction {}: {}",
                                 HashDisplay(&tx_hash),
                                 error
@@ -951,6 +1013,7 @@ async fn background_task<TPlat: PlatformRef>(mut config: BackgroundTaskConfig<TP
                     match message {
                         ToBackground::SubmitTransaction {
                             transaction_bytes,
+                            propagation,
                             updates_report,
                         } => {
                             // Handle the situation where the same transaction has already been
@@ -982,6 +1045,7 @@ async fn background_task<TPlat: PlatformRef>(mut config: BackgroundTaskConfig<TP
                                 .pending_transactions
                                 .add_unvalidated(transaction_bytes, PendingTransaction {
                                     when_reannounce: worker.platform.now(),
+                                    propagation,
                                     status_update: {
                                         let mut vec = Vec::with_capacity(1);
                                         if let Some(updates_report) = updates_report {
@@ -1064,7 +1128,7 @@ struct Worker<TPlat: PlatformRef> {
 impl<TPlat: PlatformRef> Worker<TPlat> {
     /// Update the best block. Must have been previously inserted with
     /// [`light_pool::LightPool::add_block`].
-    fn set_best_block(&mut self, log_target: &str, new_best_block_hash: &[u8; 32]) {
+    fn set_best_block(&mut self, log_target: &util::LogTarget, new_best_block_hash: &[u8; 32]) {
         let updates = self
             .pending_transactions
             .set_best_block(new_best_block_hash);
@@ -1075,8 +1139,8 @@ impl<TPlat: PlatformRef> Worker<TPlat> {
         // In that situation we need to first signal `Retracted`, then only `InBlock`.
         // Consequently, process `retracted_transactions` first.
 
-        log::debug!(
-            target: log_target,
+        util::log!(Debug,
+            log_target,
             "BestChainUpdate(new-best-block={}, included-transactions={{{}}}, retracted-transactions={{{}}})",
             HashDisplay(new_best_block_hash),
             updates.included_transactions.iter()
@@ -1087,12 +1151,8 @@ impl<TPlat: PlatformRef> Worker<TPlat> {
                 .join(", ")
         );
 
-        for (tx_id, _, _) in updates.retracted_transactions {
-            let tx = self
-                .pending_transactions
-                .transaction_user_data_mut(tx_id)
-                .unwrap();
-            tx.update_status(TransactionStatus::IncludedBlockUpdate { block_hash: None });
+        for (tx_id, block_hash, _) in updates.retracted_transactions {
+            self.retract_transaction(tx_id, block_hash);
         }
 
         for (tx_id, block_hash, block_body_index) in updates.included_transactions {
@@ -1103,10 +1163,65 @@ impl<TPlat: PlatformRef> Worker<TPlat> {
             // We assume that there's no more than 2<<32 transactions per block.
             let block_body_index = u32::try_from(block_body_index).unwrap();
             tx.update_status(TransactionStatus::IncludedBlockUpdate {
-                block_hash: Some((block_hash, block_body_index)),
+                block_hash: (block_hash, block_body_index),
             });
         }
     }
+
+    /// Retracts the transactions whose latest reported inclusion is in one of the given blocks.
+    /// Must be passed the blocks that have been pruned following a finalization.
+    ///
+    /// Transactions that are included in a block of the best chain are normally retracted by
+    /// [`Worker::set_best_block`] when this block leaves the best chain. This function guarantees
+    /// that no transaction is stuck in a block that no longer exists.
+    fn retract_pruned_blocks(&mut self, log_target: &util::LogTarget, pruned_blocks: &[[u8; 32]]) {
+        let to_retract = self
+            .pending_transactions
+            .transactions_iter()
+            .filter_map(|(tx_id, tx)| match &tx.latest_status {
+                Some(TransactionStatus::IncludedBlockUpdate {
+                    block_hash: (block_hash, _),
+                }) if pruned_blocks.contains(block_hash) => Some((tx_id, *block_hash)),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+
+        if to_retract.is_empty() {
+            return;
+        }
+
+        util::log!(
+            Debug,
+            log_target,
+            "PrunedBlocksRetraction(retracted-transactions={{{}}})",
+            to_retract
+                .iter()
+                .map(|(id, _)| HashDisplay(&blake2_hash(
+                    self.pending_transactions.scale_encoding(*id).unwrap()
+                ))
+                .to_string())
+                .join(", ")
+        );
+
+        for (tx_id, block_hash) in to_retract {
+            self.retract_transaction(tx_id, block_hash);
+        }
+    }
+
+    /// Reports the given transaction as no longer included in the given block, and schedules it
+    /// to be announced again to the network.
+    fn retract_transaction(&mut self, tx_id: light_pool::TransactionId, block_hash: [u8; 32]) {
+        let tx = self
+            .pending_transactions
+            .transaction_user_data_mut(tx_id)
+            .unwrap();
+        tx.update_status(TransactionStatus::Retracted { block_hash });
+
+        // The re-announce future of the transaction has been dropped when the transaction got
+        // included, and must thus be created again.
+        tx.when_reannounce = self.platform.now();
+        self.next_reannounce.push(Box::pin(async move { tx_id }));
+    }
 }
 
 struct Block {
@@ -1130,6 +1245,9 @@ struct PendingTransaction<TPlat: PlatformRef> {
     /// that is not validated.
     when_reannounce: TPlat::Instant,
 
+    /// Value passed when the transaction was submitted.
+    propagation: PropagationPolicy,
+
     /// List of channels that should receive changes to the transaction status.
     status_update: Vec<async_channel::Sender<TransactionStatus>>,
 
@@ -1174,7 +1292,7 @@ impl<TPlat: PlatformRef> PendingTransaction<TPlat> {
 ///
 /// Returns the result of the validation, and the hash of the block it was validated against.
 async fn validate_transaction<TPlat: PlatformRef>(
-    log_target: &str,
+    log_target: &util::LogTarget,
     relay_chain_sync: &Arc<runtime_service::RuntimeService<TPlat>>,
     relay_chain_sync_subscription_id: runtime_service::SubscriptionId,
     block_hash: [u8; 32],
@@ -1192,8 +1310,9 @@ async fn validate_transaction<TPlat: PlatformRef>(
         }
     };
 
-    log::debug!(
-        target: log_target,
+    util::log!(
+        Debug,
+        log_target,
         "TxValidations <= Start(tx={}, block={}, block_height={})",
         HashDisplay(&blake2_hash(scale_encoded_transaction.as_ref())),
         HashDisplay(runtime_lock.block_hash()),
@@ -1219,6 +1338,7 @@ async fn validate_transaction<TPlat: PlatformRef>(
             1,
             Duration::from_secs(8),
             NonZeroU32::new(1).unwrap(),
+            None,
         )
         .await
         .map_err(ValidateTransactionError::Call)
@@ -1330,3 +1450,19 @@ async fn validate_transaction<TPlat: PlatformRef>(
 fn blake2_hash(bytes: &[u8]) -> [u8; 32] {
     <[u8; 32]>::try_from(blake2_rfc::blake2b::blake2b(32, &[], bytes).as_bytes()).unwrap()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::PropagationPolicy;
+    use core::num::NonZeroUsize;
+
+    #[test]
+    fn propagation_policy_max_peers() {
+        assert_eq!(PropagationPolicy::None.max_peers(), None);
+        assert_eq!(PropagationPolicy::AllPeers.max_peers(), Some(usize::MAX));
+        assert_eq!(
+            PropagationPolicy::Subset(NonZeroUsize::new(3).unwrap()).max_peers(),
+            Some(3)
+        );
+    }
+}
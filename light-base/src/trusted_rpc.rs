@@ -0,0 +1,415 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Client for a trusted JSON-RPC server.
+//!
+//! When the peer-to-peer network is unable to answer a query, for example because all the
+//! bootnodes are down, the JSON-RPC service can forward this query to a JSON-RPC server
+//! configured by the API user. The responses of this server are trusted blindly, and the
+//! JSON-RPC service is responsible for marking them as unverified.
+//!
+//! The connection to the server is opened lazily, when the first request is sent, and is
+//! re-opened automatically if it gets closed. A TCP connection is opened using the
+//! [`PlatformRef::connect_stream`] function, and the WebSocket protocol is implemented on top of
+//! it using the [`websocket`] module. Each request is sent as one WebSocket text message, and
+//! each message sent back by the server is parsed as one JSON-RPC response.
+//!
+//! > **Note**: Because the WebSocket protocol is implemented by this module rather than by the
+//! >           platform, secure WebSocket (`/wss`) addresses aren't supported.
+
+use crate::{
+    platform::{address_parse, Address, IpAddr, PlatformRef},
+    util,
+};
+
+use alloc::{
+    borrow::ToOwned as _,
+    boxed::Box,
+    format,
+    string::{String, ToString as _},
+};
+use core::{pin::Pin, str, time::Duration};
+use futures_channel::oneshot;
+use futures_lite::FutureExt as _;
+use futures_util::StreamExt as _;
+use smoldot::{
+    json_rpc::parse,
+    libp2p::{connection::websocket, Multiaddr},
+};
+
+/// Maximum size, in bytes, of a message sent by the server. Responses can be large, for example
+/// when the runtime code is queried.
+const MAX_RESPONSE_SIZE: usize = 32 * 1024 * 1024;
+
+/// Configuration for a [`TrustedRpcClient`].
+pub struct Config<TPlat> {
+    /// Access to the platform's capabilities.
+    pub platform: TPlat,
+
+    /// Name of the chain, for logging purposes.
+    ///
+    /// > **Note**: This name will be directly printed out. Any special character should already
+    /// >           have been filtered out from this name.
+    pub log_name: String,
+
+    /// Maximum level of the logs emitted by this service.
+    pub log_max_level: log::LevelFilter,
+
+    /// Address of the JSON-RPC server. Must be a non-secure WebSocket address, for example
+    /// `/dns/example.com/tcp/9944/ws`.
+    pub address: Multiaddr,
+
+    /// Maximum duration to wait for a response before a request fails.
+    pub request_timeout: Duration,
+}
+
+/// See [the module-level documentation](..).
+pub struct TrustedRpcClient<TPlat> {
+    /// See [`Config::platform`].
+    platform: TPlat,
+
+    /// See [`Config::address`]. Used only for logging purposes.
+    address_string: String,
+
+    /// See [`Config::request_timeout`].
+    request_timeout: Duration,
+
+    /// Sending requests to the background task.
+    to_background: async_channel::Sender<ToBackground>,
+}
+
+/// Request sent to the background task.
+struct ToBackground {
+    /// Name of the JSON-RPC method to call.
+    method: String,
+    /// JSON-formatted parameters of the call.
+    params_json: String,
+    /// Sender for the outcome of the request.
+    result_tx: oneshot::Sender<Result<String, RequestError>>,
+}
+
+/// Checks whether the given address can be passed as [`Config::address`].
+///
+/// Returns an error if the address isn't a non-secure WebSocket address, or if the platform
+/// doesn't support opening TCP connections to it.
+pub fn check_address<TPlat: PlatformRef>(
+    platform: &TPlat,
+    address: &Multiaddr,
+) -> Result<(), InvalidAddressError> {
+    match tcp_address(address) {
+        Some((tcp_address, _)) if platform.supports_connection_type((&tcp_address).into()) => {
+            Ok(())
+        }
+        _ => Err(InvalidAddressError),
+    }
+}
+
+/// Turns a non-secure WebSocket multiaddress into the address of the TCP connection to open and
+/// the value of the `Host` header to pass during the WebSocket handshake.
+///
+/// Returns `None` if the multiaddress isn't a non-secure WebSocket address.
+fn tcp_address(address: &Multiaddr) -> Option<(Address<'_>, String)> {
+    match address_parse::multiaddr_to_address(address) {
+        Ok(address_parse::AddressOrMultiStreamAddress::Address(Address::WebSocketIp {
+            ip: IpAddr::V4(ip),
+            port,
+        })) => Some((
+            Address::TcpIp {
+                ip: IpAddr::V4(ip),
+                port,
+            },
+            format!("{}:{port}", no_std_net::Ipv4Addr::from(ip)),
+        )),
+        Ok(address_parse::AddressOrMultiStreamAddress::Address(Address::WebSocketIp {
+            ip: IpAddr::V6(ip),
+            port,
+        })) => Some((
+            Address::TcpIp {
+                ip: IpAddr::V6(ip),
+                port,
+            },
+            format!("[{}]:{port}", no_std_net::Ipv6Addr::from(ip)),
+        )),
+        Ok(address_parse::AddressOrMultiStreamAddress::Address(Address::WebSocketDns {
+            hostname,
+            port,
+            secure: false,
+        })) => Some((
+            Address::TcpDns { hostname, port },
+            format!("{hostname}:{port}"),
+        )),
+        _ => None,
+    }
+}
+
+impl<TPlat: PlatformRef> TrustedRpcClient<TPlat> {
+    /// Initializes a new client and spawns a background task that manages the connection.
+    ///
+    /// # Panic
+    ///
+    /// Panics if [`check_address`] returns an error for [`Config::address`].
+    ///
+    pub fn new(config: Config<TPlat>) -> Self {
+        assert!(check_address(&config.platform, &config.address).is_ok());
+
+        let log_target = util::LogTarget::new(
+            format!("trusted-rpc-{}", config.log_name),
+            config.log_max_level,
+        );
+        let address_string = config.address.to_string();
+        let (to_background, from_foreground) = async_channel::bounded(16);
+
+        config.platform.spawn_task(log_target.to_string().into(), {
+            let platform = config.platform.clone();
+            let address = config.address;
+            async move {
+                background_task(platform, log_target.clone(), address, from_foreground).await;
+                util::log!(Debug, &log_target, "Shutdown");
+            }
+        });
+
+        TrustedRpcClient {
+            platform: config.platform,
+            address_string,
+            request_timeout: config.request_timeout,
+            to_background,
+        }
+    }
+
+    /// Returns the address of the server, for logging purposes.
+    pub fn address(&self) -> &str {
+        &self.address_string
+    }
+
+    /// Sends a request to the server and waits for the response.
+    ///
+    /// `params_json` must be the JSON-formatted array or object containing the parameters of
+    /// the call. On success, returns the JSON-formatted content of the `result` field of the
+    /// response.
+    pub async fn request(&self, method: &str, params_json: &str) -> Result<String, RequestError> {
+        let (result_tx, result_rx) = oneshot::channel();
+
+        self.to_background
+            .send(ToBackground {
+                method: method.to_owned(),
+                params_json: params_json.to_owned(),
+                result_tx,
+            })
+            .await
+            .unwrap();
+
+        let timeout = self.platform.sleep(self.request_timeout);
+        async {
+            result_rx
+                .await
+                .unwrap_or(Err(RequestError::ConnectionClosed))
+        }
+        .or(async {
+            timeout.await;
+            Err(RequestError::Timeout)
+        })
+        .await
+    }
+}
+
+/// Error returned by [`check_address`].
+#[derive(Debug, derive_more::Display, Clone)]
+#[display(fmt = "Address must be a non-secure WebSocket address supported by the platform")]
+pub struct InvalidAddressError;
+
+/// Error returned by [`TrustedRpcClient::request`].
+#[derive(Debug, derive_more::Display, Clone)]
+pub enum RequestError {
+    /// Failed to connect to the server.
+    #[display(fmt = "Failed to connect to trusted JSON-RPC server: {_0}")]
+    ConnectionFailed(String),
+    /// Connection to the server has been closed before the response was received.
+    #[display(fmt = "Connection to trusted JSON-RPC server closed")]
+    ConnectionClosed,
+    /// No response has been received in time.
+    #[display(fmt = "Timeout waiting for trusted JSON-RPC server")]
+    Timeout,
+    /// Server has answered with an error.
+    #[display(fmt = "Trusted JSON-RPC server returned an error: {_0}")]
+    ServerError(String),
+}
+
+async fn background_task<TPlat: PlatformRef>(
+    platform: TPlat,
+    log_target: util::LogTarget,
+    address: Multiaddr,
+    from_foreground: async_channel::Receiver<ToBackground>,
+) {
+    let mut from_foreground = Box::pin(from_foreground);
+
+    // Identifier to assign to the next request sent to the server.
+    let mut next_request_id: u64 = 0;
+
+    // Outer loop. Opens the connection after a request has been received, then processes
+    // requests until the connection closes.
+    loop {
+        let Some(first_request) = from_foreground.next().await else {
+            return;
+        };
+
+        let Some((tcp_address, host)) = tcp_address(&address) else {
+            // Checked when creating the client.
+            unreachable!()
+        };
+
+        util::log!(Debug, &log_target, "Connecting to {}", address);
+        let mut stream: Pin<Box<TPlat::Stream>> = match platform.connect_stream(tcp_address).await {
+            Ok(stream) => Box::pin(stream),
+            Err(err) => {
+                util::log!(
+                    Debug,
+                    &log_target,
+                    "Failed to connect to {}: {}",
+                    address,
+                    err.message
+                );
+                let _ = first_request
+                    .result_tx
+                    .send(Err(RequestError::ConnectionFailed(err.message)));
+                continue;
+            }
+        };
+
+        let mut websocket = websocket::WebSocket::new(websocket::Config {
+            host: &host,
+            url: "/",
+            randomness_seed: {
+                let mut seed = [0; 32];
+                platform.fill_random_bytes(&mut seed);
+                seed
+            },
+        });
+
+        // Requests that have been sent and that are waiting for a response, indexed by their
+        // JSON-formatted identifier.
+        let mut pending_requests = hashbrown::HashMap::<
+            String,
+            oneshot::Sender<Result<String, RequestError>>,
+            fnv::FnvBuildHasher,
+        >::default();
+
+        let mut queue_request = |request: ToBackground, websocket: &mut websocket::WebSocket| {
+            let id_json = next_request_id.to_string();
+            next_request_id += 1;
+            websocket.queue_text_message(parse::build_request(&parse::Request {
+                id_json: Some(&id_json),
+                method: &request.method,
+                params_json: Some(&request.params_json),
+            }));
+            (id_json, request.result_tx)
+        };
+
+        let (id_json, result_tx) = queue_request(first_request, &mut websocket);
+        pending_requests.insert(id_json, result_tx);
+
+        // Inner loop. Processes the connection.
+        let connection_error = loop {
+            // Note that `read_write` is dropped at the end of this block.
+            let is_closed = {
+                let Ok(mut read_write) = platform.read_write_access(stream.as_mut()) else {
+                    break RequestError::ConnectionClosed;
+                };
+
+                if let Err(err) = websocket.read_write_messages(&mut read_write, MAX_RESPONSE_SIZE)
+                {
+                    util::log!(
+                        Debug,
+                        &log_target,
+                        "WebSocket error with {}: {}",
+                        address,
+                        err
+                    );
+                    break RequestError::ConnectionClosed;
+                }
+
+                read_write.expected_incoming_bytes.is_none()
+            };
+
+            // Each message sent by the server is a JSON-RPC response or notification.
+            while let Some(message) = websocket.pull_message() {
+                let Ok(message) = str::from_utf8(&message) else {
+                    util::log!(Debug, &log_target, "Ignoring non-UTF-8 message");
+                    continue;
+                };
+
+                match parse::parse_response(message) {
+                    Ok(parse::Response::Success {
+                        id_json,
+                        result_json,
+                    }) => {
+                        if let Some(result_tx) = pending_requests.remove(id_json) {
+                            let _ = result_tx.send(Ok(result_json.to_owned()));
+                        }
+                    }
+                    Ok(parse::Response::Error {
+                        id_json,
+                        error_message,
+                        ..
+                    }) => {
+                        if let Some(result_tx) = pending_requests.remove(id_json) {
+                            let _ = result_tx
+                                .send(Err(RequestError::ServerError(error_message.to_owned())));
+                        }
+                    }
+                    Ok(parse::Response::ParseError { .. }) | Err(_) => {
+                        // Notifications and invalid messages are ignored.
+                        util::log!(Debug, &log_target, "Ignoring message: {}", message);
+                    }
+                }
+            }
+
+            if is_closed {
+                break RequestError::ConnectionClosed;
+            }
+
+            // Wait for either the stream to be ready or a new request.
+            let next_request = {
+                let stream_ready = async {
+                    platform.wait_read_write_again(stream.as_mut()).await;
+                    None
+                };
+                let new_request = async { Some(from_foreground.next().await) };
+                stream_ready.or(new_request).await
+            };
+
+            match next_request {
+                None => {}
+                Some(None) => return,
+                Some(Some(request)) => {
+                    let (id_json, result_tx) = queue_request(request, &mut websocket);
+                    pending_requests.insert(id_json, result_tx);
+                }
+            }
+
+            // Clean up the requests whose sender has given up on waiting.
+            pending_requests.retain(|_, result_tx| !result_tx.is_canceled());
+        };
+
+        util::log!(Debug, &log_target, "Connection to {} closed", address);
+        for (_, result_tx) in pending_requests {
+            let _ = result_tx.send(Err(connection_error.clone()));
+        }
+
+        // Make sure to not reconnect in a tight loop.
+        platform.sleep(Duration::from_secs(1)).await;
+    }
+}
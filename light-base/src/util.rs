@@ -15,8 +15,75 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <http://www.gnu.org/licenses/>.
 
+use alloc::string::String;
 use core::fmt::{self, Write as _};
 
+/// Emits a log message, unless the given [`LogTarget`] is configured to discard messages of
+/// this level.
+///
+/// Usage is similar to the macros of the `log` crate, except that the level and the
+/// [`LogTarget`] are passed explicitly: `util::log!(Debug, &log_target, "message {}", arg)`.
+macro_rules! log {
+    ($level:ident, $target:expr, $($arg:tt)+) => {{
+        let target: &$crate::util::LogTarget = $target;
+        if ::log::Level::$level <= target.max_level() {
+            ::log::log!(target: target.name(), ::log::Level::$level, $($arg)+);
+        }
+    }};
+}
+
+pub(crate) use log;
+
+/// Target of the logs of a service, alongside with the maximum level of these logs.
+///
+/// Each chain can configure the verbosity of its services independently. See
+/// [`crate::AddChainConfig::logs`].
+#[derive(Debug, Clone)]
+pub struct LogTarget {
+    name: String,
+    max_level: ::log::LevelFilter,
+}
+
+impl LogTarget {
+    pub fn new(name: String, max_level: ::log::LevelFilter) -> Self {
+        LogTarget { name, max_level }
+    }
+
+    /// Returns the name of the target, as passed to the `log` crate.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the maximum level of the logs emitted with this target.
+    pub fn max_level(&self) -> ::log::LevelFilter {
+        self.max_level
+    }
+
+    /// Returns the value to pass as the maximum log level of the runtime when performing a
+    /// runtime call on behalf of this target.
+    ///
+    /// The logs generated by the runtime are meant to be re-emitted at the `Debug` level. `0`,
+    /// meaning that the runtime doesn't generate any log, is returned if this level is disabled.
+    pub fn runtime_max_log_level(&self) -> u32 {
+        if ::log::Level::Debug > self.max_level
+            || !::log::log_enabled!(target: self.name(), ::log::Level::Debug)
+        {
+            return 0;
+        }
+
+        match self.max_level {
+            ::log::LevelFilter::Trace => 5,
+            _ => 4,
+        }
+    }
+}
+
+impl fmt::Display for LogTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.name, f)
+    }
+}
+
 /// Returns an opaque object implementing the `fmt::Display` trait. Truncates the given `char`
 /// yielding iterator to the given number of elements, and if the limit is reached adds a `…` at
 /// the end.
@@ -98,6 +98,7 @@ fn add_chain(
                 .unwrap_or_else(|_| panic!("non-utf8 chain spec")),
             database_content: str::from_utf8(&database_content)
                 .unwrap_or_else(|_| panic!("non-utf8 database content")),
+            snapshot: None,
             json_rpc: if let Some(json_rpc_max_pending_requests) =
                 NonZeroU32::new(json_rpc_max_pending_requests)
             {
@@ -105,11 +106,20 @@ fn add_chain(
                     max_pending_requests: json_rpc_max_pending_requests,
                     // Note: the PolkadotJS UI is very heavy in terms of subscriptions.
                     max_subscriptions: json_rpc_max_subscriptions,
+                    network_requests: Default::default(),
                 }
             } else {
                 smoldot_light::AddChainConfigJsonRpc::Disabled
             },
             potential_relay_chains: potential_relay_chains.into_iter(),
+            gap_sync: false,
+            pseudo_finality_depth: None,
+            nonce_tracking: false,
+            track_peers_quality: false,
+            trusted_rpc_fallback: None,
+            ip_family_policy: smoldot_light::IpFamilyPolicy::NoPreference,
+            best_block_policy: smoldot_light::BestBlockPolicy::FollowInput,
+            logs: Default::default(),
         }) {
         Ok(c) => c,
         Err(error) => {